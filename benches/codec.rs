@@ -0,0 +1,96 @@
+use paxos_classic::alpha::{Id, Round, Value};
+use paxos_classic::bytes::BytesValue;
+use paxos_classic::codec::{from_bytes, to_bytes};
+use paxos_classic::transport::Codec;
+use std::time::{Duration, Instant};
+
+const SIZES: [usize; 5] = [64, 256, 1 << 10, 4 << 10, 64 << 10];
+const BYTES_PER_RUN: usize = 32 << 20;
+// A gigabit link, in bytes per second.
+const LINK: f64 = 125e6;
+// How much worse than the best codec the default may be at any size before
+// the run fails.
+const TOLERANCE: f64 = 1.25;
+
+fn main() {
+    let codecs = [
+        ("plain", Codec::Plain),
+        ("lz", Codec::Lz { above: 0 }),
+        ("default", Codec::default()),
+    ];
+    let mut regressions = Vec::new();
+    for (kind, payload) in [("text", text as fn(usize) -> Vec<u8>), ("noise", noise)] {
+        for size in SIZES {
+            let value = Value::new(BytesValue::from(payload(size)), Round::new(Id::default()));
+            let costs: Vec<_> = codecs
+                .iter()
+                .map(|(name, codec)| {
+                    let (elapsed, wire) = measure(*codec, &value);
+                    let cost = elapsed.as_secs_f64() + wire as f64 / LINK;
+                    println!(
+                        "{kind} {size:>6} B {name:>7}: {:>9.2?} per message, {wire:>6} B on the wire",
+                        elapsed
+                    );
+                    cost
+                })
+                .collect();
+            let best = costs.iter().copied().fold(f64::INFINITY, f64::min);
+            if costs[2] > best * TOLERANCE {
+                regressions.push(format!("{kind} messages of {size} bytes"));
+            }
+        }
+    }
+    if !regressions.is_empty() {
+        eprintln!(
+            "the default codec fell behind on {}",
+            regressions.join(", ")
+        );
+        std::process::exit(1);
+    }
+}
+
+/// Encodes, packs, unpacks and decodes `value` until a run's worth of bytes
+/// went through, returning the time per message and the bytes on the wire.
+fn measure(codec: Codec, value: &Value<BytesValue>) -> (Duration, usize) {
+    let iterations = (BYTES_PER_RUN / value.value().len()).max(1);
+    let mut wire = 0;
+    let started = Instant::now();
+    for _ in 0..iterations {
+        let frame = to_bytes(value);
+        let decoded: Value<BytesValue> = match codec.pack(&frame) {
+            Some(packed) => {
+                wire = packed.len();
+                from_bytes(&Codec::unpack(&packed, usize::MAX).unwrap()).unwrap()
+            }
+            None => {
+                wire = frame.len();
+                from_bytes(&frame).unwrap()
+            }
+        };
+        assert_eq!(decoded.value().len(), value.value().len());
+    }
+    (started.elapsed() / iterations as u32, wire)
+}
+
+fn text(size: usize) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(size);
+    let mut key = 0u64;
+    while bytes.len() < size {
+        bytes.extend_from_slice(format!("key-{key}=value-{};", key * 7).as_bytes());
+        key += 1;
+    }
+    bytes.truncate(size);
+    bytes
+}
+
+fn noise(size: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    (0..size)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
@@ -0,0 +1,31 @@
+use paxos_classic::sim::{Faults, Simulation};
+use std::time::{Duration, Instant};
+
+const ACCEPTORS: usize = 5;
+const DECISIONS: u64 = 2000;
+
+fn main() {
+    for proposers in [1, 3, 5] {
+        let mut latencies = Vec::with_capacity(DECISIONS as usize);
+        let started = Instant::now();
+        for seed in 0..DECISIONS {
+            let simulation = Simulation::new(ACCEPTORS, seed, Faults::default());
+            let values = (0..proposers).collect();
+            let decision = Instant::now();
+            simulation.propose_all(values);
+            latencies.push(decision.elapsed());
+        }
+        let elapsed = started.elapsed();
+        latencies.sort();
+        println!(
+            "{proposers} proposers: {:.0} decisions/s, p50 {:?}, p99 {:?}",
+            DECISIONS as f64 / elapsed.as_secs_f64(),
+            percentile(&latencies, 0.50),
+            percentile(&latencies, 0.99),
+        );
+    }
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    sorted[((sorted.len() - 1) as f64 * p).round() as usize]
+}
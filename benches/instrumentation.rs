@@ -0,0 +1,65 @@
+use futures::executor::block_on;
+use paxos_classic::alpha::Id;
+use paxos_classic::metrics::{Observer, PrometheusObserver};
+use paxos_classic::proposer::{FailureDetector, Proposer};
+use paxos_classic::sim::{Faults, SimPeers, Simulation};
+use std::future::{self, Future};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const ACCEPTORS: usize = 5;
+const DECISIONS: u64 = 20_000;
+
+struct Leader;
+
+impl FailureDetector for Leader {
+    fn leader(&self) -> Id {
+        Id::default()
+    }
+
+    fn changed(&self) -> impl Future<Output = ()> {
+        future::pending()
+    }
+}
+
+fn main() {
+    measure("no observer", |peers| {
+        Proposer::builder(Id::default(), peers, Leader).build()
+    });
+    let observer = Arc::new(PrometheusObserver::default());
+    measure("prometheus observer", |peers| {
+        Proposer::builder(Id::default(), peers, Leader)
+            .observer(observer.clone())
+            .build()
+    });
+    if cfg!(feature = "tracing") {
+        println!("(built with tracing spans)");
+    }
+}
+
+fn measure<O: Observer>(
+    label: &str,
+    build: impl Fn(SimPeers<u64>) -> Proposer<u64, SimPeers<u64>, Leader, O>,
+) {
+    let mut latencies = Vec::with_capacity(DECISIONS as usize);
+    let started = Instant::now();
+    for seed in 0..DECISIONS {
+        let simulation = Simulation::new(ACCEPTORS, seed, Faults::default());
+        let mut proposer = build(simulation.peers());
+        let decision = Instant::now();
+        block_on(proposer.propose(seed)).expect("a lone proposer decides");
+        latencies.push(decision.elapsed());
+    }
+    let elapsed = started.elapsed();
+    latencies.sort();
+    println!(
+        "{label}: {:.0} decisions/s, p50 {:?}, p99 {:?}",
+        DECISIONS as f64 / elapsed.as_secs_f64(),
+        percentile(&latencies, 0.50),
+        percentile(&latencies, 0.99),
+    );
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    sorted[((sorted.len() - 1) as f64 * p).round() as usize]
+}
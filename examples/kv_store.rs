@@ -0,0 +1,101 @@
+use futures::executor::block_on;
+use paxos_classic::alpha::Id;
+use paxos_classic::kv::{KvClient, KvError, Request};
+use paxos_classic::local::{LocalCluster, LocalPeers};
+use paxos_classic::log::{LogIndex, ReplicatedLog, SlotPeers};
+use paxos_classic::proposer::FailureDetector;
+use paxos_classic::session::ClientId;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::future::{self, Future};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+const ACCEPTORS: usize = 3;
+
+#[derive(Clone, Default)]
+struct Slots {
+    clusters: Rc<RefCell<BTreeMap<LogIndex, LocalCluster<Request>>>>,
+}
+
+impl SlotPeers<Request> for Slots {
+    type Peers = LocalPeers<Request>;
+
+    fn slot(&self, index: LogIndex) -> Self::Peers {
+        self.clusters
+            .borrow_mut()
+            .entry(index)
+            .or_insert_with(|| LocalCluster::new(ACCEPTORS))
+            .peers()
+    }
+}
+
+#[derive(Clone, Default)]
+struct Leader(Arc<AtomicU64>);
+
+impl Leader {
+    fn elect(&self, id: Id) {
+        self.0.store(id.get(), Ordering::Release);
+    }
+}
+
+impl FailureDetector for Leader {
+    fn leader(&self) -> Id {
+        Id::new(self.0.load(Ordering::Acquire))
+    }
+
+    fn changed(&self) -> impl Future<Output = ()> {
+        future::pending()
+    }
+}
+
+fn replica(id: u64, slots: &Slots, leader: &Leader) -> KvClient<Slots, Leader> {
+    let log = ReplicatedLog::new(Id::new(id), slots.clone(), leader.clone());
+    KvClient::new(log, ClientId(id))
+}
+
+fn main() -> Result<(), KvError> {
+    block_on(async {
+        let slots = Slots::default();
+        let leader = Leader::default();
+
+        leader.elect(Id::new(1));
+        let mut first = replica(1, &slots, &leader);
+        first.put("language", "rust").await?;
+        first.put("protocol", "paxos").await?;
+        first.put("editor", "vim").await?;
+        let previous = first.put("editor", "helix").await?;
+        assert_eq!(previous.as_deref(), Some("vim"));
+        first.delete("language").await?;
+        println!("replica 1 wrote {} keys", first.store().len());
+
+        let snapshot = first.snapshot()?;
+        println!(
+            "replica 1 snapshotted through log index {}",
+            snapshot.last_included.get()
+        );
+        first.put("shell", "fish").await?;
+
+        leader.elect(Id::new(2));
+        let mut second = replica(2, &slots, &leader);
+        second.restore(snapshot);
+        second.recover().await?;
+        assert_eq!(second.store().get("editor"), Some("helix"));
+        assert_eq!(second.store().get("shell"), Some("fish"));
+        assert_eq!(second.store().get("language"), None);
+        println!("replica 2 recovered from the snapshot and the log tail");
+
+        second.put("protocol", "multi-paxos").await?;
+
+        leader.elect(Id::new(1));
+        let protocol = first.get("protocol").await?;
+        assert_eq!(protocol.as_deref(), Some("multi-paxos"));
+        println!(
+            "replica 1 reads protocol = {}",
+            protocol.unwrap_or_default()
+        );
+
+        Ok(())
+    })
+}
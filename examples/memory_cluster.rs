@@ -0,0 +1,124 @@
+use futures::executor::block_on;
+use paxos_classic::alpha::{Error, Id};
+use paxos_classic::chaos::{FaultyPeers, PeerFaults};
+use paxos_classic::local::{LocalCluster, LocalPeers};
+use paxos_classic::log::{LogIndex, ReplicatedLog, SlotPeers};
+use paxos_classic::proposer::FailureDetector;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+use std::future::{self, Future};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+const ACCEPTORS: usize = 3;
+
+/// One in-memory acceptor group per log slot. Acceptors listed in `cut_off`
+/// stay up but every reply they send is lost, as if they had crashed.
+#[derive(Clone, Default)]
+struct Slots {
+    clusters: Rc<RefCell<BTreeMap<LogIndex, LocalCluster<String>>>>,
+    cut_off: Rc<RefCell<HashSet<Id>>>,
+}
+
+impl Slots {
+    fn crash(&self, acceptor: Id) {
+        self.cut_off.borrow_mut().insert(acceptor);
+    }
+
+    fn recover(&self, acceptor: Id) {
+        self.cut_off.borrow_mut().remove(&acceptor);
+    }
+}
+
+impl SlotPeers<String> for Slots {
+    type Peers = FaultyPeers<LocalPeers<String>>;
+
+    fn slot(&self, index: LogIndex) -> Self::Peers {
+        let peers = self
+            .clusters
+            .borrow_mut()
+            .entry(index)
+            .or_insert_with(|| LocalCluster::new(ACCEPTORS))
+            .peers();
+        let peers = FaultyPeers::new(peers, PeerFaults::default());
+        for acceptor in self.cut_off.borrow().iter() {
+            peers.partition(*acceptor);
+        }
+        peers
+    }
+}
+
+#[derive(Clone, Default)]
+struct Leader(Arc<AtomicU64>);
+
+impl Leader {
+    fn elect(&self, id: Id) {
+        self.0.store(id.get(), Ordering::Release);
+    }
+}
+
+impl FailureDetector for Leader {
+    fn leader(&self) -> Id {
+        Id::new(self.0.load(Ordering::Acquire))
+    }
+
+    fn changed(&self) -> impl Future<Output = ()> {
+        future::pending()
+    }
+}
+
+type Replica = ReplicatedLog<String, Slots, Leader>;
+
+fn replica(id: u64, slots: &Slots, leader: &Leader) -> Replica {
+    ReplicatedLog::new(Id::new(id), slots.clone(), leader.clone())
+}
+
+fn entries(log: &Replica) -> Vec<String> {
+    (log.first_index().get()..log.next_index().get())
+        .filter_map(|index| log.read(LogIndex::new(index)).cloned())
+        .collect()
+}
+
+fn main() -> Result<(), Error> {
+    block_on(async {
+        let slots = Slots::default();
+        let leader = Leader::default();
+
+        leader.elect(Id::new(1));
+        let mut first = replica(1, &slots, &leader);
+        first.append("x = 1".to_string()).await?;
+        first.append("y = 2".to_string()).await?;
+        println!("replica 1 leads and committed {:?}", entries(&first));
+
+        // Replica 1 goes away; replica 2 takes over and must first learn
+        // everything its predecessor committed.
+        drop(first);
+        leader.elect(Id::new(2));
+        let mut second = replica(2, &slots, &leader);
+        second.catch_up().await?;
+        println!(
+            "replica 2 took over and caught up to {:?}",
+            entries(&second)
+        );
+        second.append("z = 3".to_string()).await?;
+
+        // A majority is still two of three acceptors.
+        slots.crash(Id::new(2));
+        second.append("w = 4".to_string()).await?;
+        println!("acceptor 2 is down; replica 2 still committed `w = 4`");
+        slots.recover(Id::new(2));
+
+        // Replica 1 restarts empty and rebuilds the log from the acceptors.
+        leader.elect(Id::new(1));
+        let mut restarted = replica(1, &slots, &leader);
+        restarted.catch_up().await?;
+        assert_eq!(entries(&restarted), entries(&second));
+        println!(
+            "replica 1 restarted and recovered {:?}",
+            entries(&restarted)
+        );
+
+        Ok(())
+    })
+}
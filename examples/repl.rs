@@ -0,0 +1,104 @@
+use paxos_classic::sim::{Faults, NodeView, Simulation};
+use std::io::{self, BufRead, Write};
+
+const NODES: usize = 5;
+const ATTEMPTS: usize = 5;
+
+const HELP: &str = "commands:
+  propose <node> <value>        propose <value> as <node>
+  kill <node>                   crash <node>; it keeps what it persisted
+  restart <node>                bring <node> back from its storage
+  partition <nodes> <nodes>...  split into sides, e.g. `partition 0,1 2,3,4`
+  heal                          reconnect every side
+  status                        show each node's promise, acceptance and decision
+  help                          show this again
+  quit                          leave";
+
+/// A five-node simulated cluster to poke at from the keyboard: propose from
+/// any node, crash and restart nodes, and split the network to see which
+/// side can still decide.
+fn main() -> io::Result<()> {
+    let simulation = Simulation::<String>::new(NODES, 0, Faults::default());
+    println!("{NODES} nodes, numbered 0 to {}\n{HELP}", NODES - 1);
+    let mut stdout = io::stdout();
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("> ");
+        stdout.flush()?;
+        let Some(line) = lines.next() else {
+            return Ok(());
+        };
+        let line = line?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match execute(&simulation, &words) {
+            Ok(Some(output)) => println!("{output}"),
+            Ok(None) => return Ok(()),
+            Err(message) => println!("error: {message}"),
+        }
+    }
+}
+
+fn execute(simulation: &Simulation<String>, words: &[&str]) -> Result<Option<String>, String> {
+    let output = match words {
+        [] => String::new(),
+        ["propose", node, value @ ..] if !value.is_empty() => {
+            match simulation.propose(node_id(node)?, value.join(" "), ATTEMPTS) {
+                Ok(decided) => format!("decided {decided}"),
+                Err(error) => format!("no decision: {error}"),
+            }
+        }
+        ["kill", node] => {
+            simulation.crash(node_id(node)?);
+            format!("node {node} is down")
+        }
+        ["restart", node] => {
+            simulation.restart(node_id(node)?);
+            format!("node {node} is up")
+        }
+        ["partition", sides @ ..] if !sides.is_empty() => {
+            let sides = sides
+                .iter()
+                .map(|side| side.split(',').map(node_id).collect())
+                .collect::<Result<Vec<Vec<usize>>, String>>()?;
+            simulation.partition(&sides);
+            "partitioned".to_string()
+        }
+        ["heal"] => {
+            simulation.heal();
+            "healed".to_string()
+        }
+        ["status"] => simulation
+            .nodes()
+            .iter()
+            .map(describe)
+            .collect::<Vec<_>>()
+            .join("\n"),
+        ["help"] => HELP.to_string(),
+        ["quit" | "exit"] => return Ok(None),
+        _ => return Err(format!("unknown command `{}`; try `help`", words.join(" "))),
+    };
+    Ok(Some(output))
+}
+
+fn node_id(word: &str) -> Result<usize, String> {
+    word.parse()
+        .ok()
+        .filter(|node| *node < NODES)
+        .ok_or(format!("no node `{word}`"))
+}
+
+fn describe(node: &NodeView<String>) -> String {
+    format!(
+        "node {} {:<4} side={} promised={:?} accepted={} learned={}",
+        node.id.get(),
+        if node.up { "up" } else { "down" },
+        node.side
+            .map_or_else(|| "-".to_string(), |side| side.to_string()),
+        node.promised,
+        node.accepted.as_ref().map_or_else(
+            || "-".to_string(),
+            |(round, value)| format!("{value} in {round:?}")
+        ),
+        node.learned.as_deref().unwrap_or("-"),
+    )
+}
@@ -0,0 +1,167 @@
+use futures::executor::block_on;
+use futures::StreamExt;
+use paxos_classic::alpha::Id;
+use paxos_classic::bytes::BytesValue;
+use paxos_classic::config::ClusterConfig;
+use paxos_classic::node::Node;
+use std::error::Error;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::Duration;
+use std::{env, fs, thread};
+
+const MEMBERS: u64 = 3;
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Runs three nodes as separate processes that talk over TCP. Without
+/// arguments this process is the driver: it writes a cluster config, starts
+/// one child per member (`tcp_cluster node <cluster.toml> <id>`) and steers
+/// them over their standard input.
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.as_slice() {
+        [] => drive(),
+        [mode, config, id] if mode == "node" => serve(Path::new(config), Id::new(id.parse()?)),
+        _ => Err("usage: tcp_cluster [node <cluster.toml> <id>]".into()),
+    }
+}
+
+fn serve(config: &Path, id: Id) -> Result<(), Box<dyn Error>> {
+    let node = Node::<BytesValue>::start(ClusterConfig::load(config)?.node(id)?)?;
+    let changes = node.leadership_changes();
+    thread::spawn(move || {
+        block_on(changes.for_each(|leader| {
+            println!("leader {}", leader.get());
+            async {}
+        }))
+    });
+    println!("listening on {}", node.local_addr());
+
+    for line in io::stdin().lock().lines() {
+        if let Some(value) = line?.strip_prefix("propose ") {
+            let decided = block_on(node.propose(BytesValue::from(value.as_bytes().to_vec())))?;
+            println!("decided {}", String::from_utf8_lossy(&decided));
+        }
+    }
+    block_on(node.shutdown())?;
+    Ok(())
+}
+
+struct Member {
+    id: u64,
+    process: Child,
+    stdin: ChildStdin,
+}
+
+impl Member {
+    fn start(config: &Path, id: u64, output: &Sender<(u64, String)>) -> io::Result<Self> {
+        let mut process = Command::new(env::current_exe()?)
+            .arg("node")
+            .arg(config)
+            .arg(id.to_string())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = process.stdin.take().expect("stdin is piped");
+        let stdout = process.stdout.take().expect("stdout is piped");
+        let output = output.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                println!("  [node {id}] {line}");
+                if output.send((id, line)).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self { id, process, stdin })
+    }
+
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{command}")
+    }
+
+    fn crash(mut self) -> io::Result<()> {
+        self.process.kill()?;
+        self.process.wait()?;
+        Ok(())
+    }
+
+    fn stop(self) -> io::Result<()> {
+        let Self {
+            mut process, stdin, ..
+        } = self;
+        drop(stdin);
+        process.wait()?;
+        Ok(())
+    }
+}
+
+/// Waits for `id` to print a line starting with `prefix` and returns the
+/// rest of it.
+fn expect(
+    output: &Receiver<(u64, String)>,
+    id: u64,
+    prefix: &str,
+) -> Result<String, Box<dyn Error>> {
+    loop {
+        let (from, line) = output.recv_timeout(REPLY_TIMEOUT)?;
+        if from == id {
+            if let Some(rest) = line.strip_prefix(prefix) {
+                return Ok(rest.to_string());
+            }
+        }
+    }
+}
+
+fn drive() -> Result<(), Box<dyn Error>> {
+    let dir = env::temp_dir().join(format!("toy-paxos-tcp-cluster-{}", std::process::id()));
+    fs::create_dir_all(&dir)?;
+    let config = dir.join("cluster.toml");
+    let mut text = String::new();
+    for id in 1..=MEMBERS {
+        let addr = TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+        let storage = dir.join(format!("node-{id}"));
+        text += &format!(
+            "[[members]]\nid = {id}\naddr = \"{addr}\"\nstorage_path = {:?}\n\n",
+            storage.display().to_string()
+        );
+    }
+    fs::write(&config, text)?;
+
+    let (sender, output) = channel();
+    let mut members = (1..=MEMBERS)
+        .map(|id| Member::start(&config, id, &sender))
+        .collect::<io::Result<Vec<_>>>()?;
+    for member in &members {
+        expect(&output, member.id, "listening on ")?;
+    }
+
+    println!("node 1 proposes `first`");
+    members[0].send("propose first")?;
+    let decided = expect(&output, 1, "decided ")?;
+    println!("cluster decided `{decided}`");
+
+    println!("node 1 crashes; node 2 proposes `second`");
+    members.remove(0).crash()?;
+    members[0].send("propose second")?;
+    let after_failover = expect(&output, 2, "decided ")?;
+    assert_eq!(after_failover, decided);
+    println!("node 2 took over and still decides `{after_failover}`");
+
+    println!("node 1 restarts from its storage and proposes `third`");
+    members.insert(0, Member::start(&config, 1, &sender)?);
+    expect(&output, 1, "listening on ")?;
+    members[0].send("propose third")?;
+    let recovered = expect(&output, 1, "decided ")?;
+    assert_eq!(recovered, decided);
+    println!("node 1 recovered `{recovered}`");
+
+    for member in members {
+        member.stop()?;
+    }
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
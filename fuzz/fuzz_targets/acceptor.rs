@@ -0,0 +1,41 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use paxos_classic::acceptor::Acceptor;
+use paxos_classic::alpha::{Id, Round, Status, Value};
+use paxos_classic::storage::MemoryStorage;
+
+fuzz_target!(|ops: Vec<(bool, u8, u8, u8)>| {
+    let mut acceptor =
+        Acceptor::new(Id::new(1), MemoryStorage::<u8>::default()).expect("acceptor");
+    let mut promised = Round::default();
+    let mut accepted: Option<(Round, u8)> = None;
+    for (write, tick, process, value) in ops {
+        let round = Round::resume(Id::new(u64::from(process % 4)), u64::from(tick));
+        if write {
+            let response = acceptor
+                .handle_write(Value::new(value, round))
+                .expect("in-memory write");
+            let takes = round >= promised && accepted.is_none_or(|(last, _)| round > last);
+            // A same-round write is only acknowledged again for an equal value.
+            let repeat = round >= promised && accepted == Some((round, value));
+            if takes {
+                promised = round;
+                accepted = Some((round, value));
+            }
+            assert_eq!(response.last_round_entered, promised);
+            assert_eq!(matches!(response.status, Status::Accepted), takes || repeat);
+        } else {
+            let response = acceptor.handle_read(round).expect("in-memory read");
+            let fresh = round >= promised;
+            promised = promised.max(round);
+            assert_eq!(response.state.last_round_entered(), promised);
+            assert_eq!(matches!(response.status, Status::Accepted), fresh);
+            let state = response
+                .state
+                .accepted_round()
+                .zip(response.state.accepted_value().copied());
+            assert_eq!(state, accepted);
+        }
+    }
+});
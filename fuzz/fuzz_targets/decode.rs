@@ -0,0 +1,39 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use paxos_classic::alpha::{Alpha, ReadResponse, Value, WriteResponse};
+use paxos_classic::audit::AuditEvent;
+use paxos_classic::bytes::BytesValue;
+use paxos_classic::codec::{from_bytes, to_bytes, Decode, Encode};
+use paxos_classic::learner::DecisionBroadcast;
+use paxos_classic::lock::LeaseOp;
+use paxos_classic::membership::Membership;
+use paxos_classic::session::SessionRequest;
+use paxos_classic::smr::Snapshot;
+
+fn round_trip<T: Encode + Decode>(bytes: &[u8]) {
+    let Ok(decoded) = from_bytes::<T>(bytes) else {
+        return;
+    };
+    let encoded = to_bytes(&decoded);
+    let decoded = from_bytes::<T>(&encoded).expect("re-encoded message decodes");
+    assert_eq!(to_bytes(&decoded), encoded);
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((kind, bytes)) = data.split_first() else {
+        return;
+    };
+    match kind % 10 {
+        0 => round_trip::<Value<BytesValue>>(bytes),
+        1 => round_trip::<Alpha<BytesValue>>(bytes),
+        2 => round_trip::<ReadResponse<BytesValue>>(bytes),
+        3 => round_trip::<WriteResponse>(bytes),
+        4 => round_trip::<DecisionBroadcast<BytesValue>>(bytes),
+        5 => round_trip::<Membership>(bytes),
+        6 => round_trip::<Snapshot<BytesValue>>(bytes),
+        7 => round_trip::<SessionRequest<BytesValue>>(bytes),
+        8 => round_trip::<LeaseOp>(bytes),
+        _ => round_trip::<AuditEvent>(bytes),
+    }
+});
@@ -0,0 +1,55 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use paxos_classic::acceptor::Acceptor;
+use paxos_classic::alpha::Id;
+use paxos_classic::bytes::BytesValue;
+use paxos_classic::storage::MemoryStorage;
+use paxos_classic::transport::tcp::{Server, PROTOCOL_VERSION};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+
+fn server() -> SocketAddr {
+    static ADDR: OnceLock<SocketAddr> = OnceLock::new();
+    *ADDR.get_or_init(|| {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let addr = listener.local_addr().expect("local address");
+        let storage = MemoryStorage::<BytesValue>::default();
+        let acceptor = Acceptor::new(Id::new(1), storage).expect("acceptor");
+        let server = Server::new(Arc::new(Mutex::new(acceptor)));
+        thread::spawn(move || server.serve(listener));
+        addr
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(mut stream) = TcpStream::connect(server()) else {
+        return;
+    };
+    let payload = match data.split_first() {
+        // Wrap the rest in deeply nested instance requests behind a valid version,
+        // which the server must reject without recursing.
+        Some((&depth, rest)) if depth & 1 == 1 => nested(usize::from(depth) * 64, rest),
+        _ => data.to_vec(),
+    };
+    let mut frame = (payload.len() as u32).to_be_bytes().to_vec();
+    frame.extend_from_slice(&payload);
+    if stream.write_all(&frame).is_err() {
+        return;
+    }
+    let _ = stream.shutdown(std::net::Shutdown::Write);
+    let _ = stream.read_to_end(&mut Vec::new());
+});
+
+fn nested(depth: usize, request: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(4 + depth * 9 + request.len());
+    payload.extend_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+    for instance in 0..depth as u64 {
+        payload.push(6);
+        payload.extend_from_slice(&instance.to_be_bytes());
+    }
+    payload.extend_from_slice(request);
+    payload
+}
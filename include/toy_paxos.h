@@ -0,0 +1,90 @@
+#ifndef TOY_PAXOS_H
+#define TOY_PAXOS_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum ToyPaxosStatus {
+  TOY_PAXOS_STATUS_OK = 0,
+  TOY_PAXOS_STATUS_INVALID_ARGUMENT = 1,
+  TOY_PAXOS_STATUS_INVALID_CONFIG = 2,
+  TOY_PAXOS_STATUS_IO = 3,
+  TOY_PAXOS_STATUS_NOT_DECIDED = 4,
+  TOY_PAXOS_STATUS_PROPOSAL_FAILED = 5,
+  TOY_PAXOS_STATUS_STOPPED = 6,
+} ToyPaxosStatus;
+
+/**
+ * An opaque handle to a running node.
+ */
+typedef struct ToyPaxosNode ToyPaxosNode;
+
+/**
+ * Called with the decided value, which is only valid for the duration of
+ * the call, or with a failed status and no value.
+ */
+typedef void (*ToyPaxosCallback)(void *user_data,
+                                 enum ToyPaxosStatus status,
+                                 const uint8_t *data,
+                                 size_t len);
+
+/**
+ * Starts a node from the text of a node config file and stores its handle
+ * in `out`.
+ *
+ * # Safety
+ *
+ * `config` must be a NUL-terminated string and `out` must be valid for a
+ * write.
+ */
+enum ToyPaxosStatus toy_paxos_node_start(const char *config, struct ToyPaxosNode **out);
+
+/**
+ * Proposes `len` bytes at `data` and returns at once; `callback` runs on
+ * another thread once the node decides.
+ *
+ * # Safety
+ *
+ * `node` must come from [`toy_paxos_node_start`] and not be stopped yet,
+ * `data` must be valid for `len` bytes, and `user_data` must be safe to use
+ * from another thread.
+ */
+enum ToyPaxosStatus toy_paxos_node_submit(const struct ToyPaxosNode *node,
+                                          const uint8_t *data,
+                                          size_t len,
+                                          ToyPaxosCallback callback,
+                                          void *user_data);
+
+/**
+ * Calls `callback` before returning with what this node has learned was
+ * decided, or with [`ToyPaxosStatus::NotDecided`].
+ *
+ * # Safety
+ *
+ * `node` must come from [`toy_paxos_node_start`] and not be stopped yet.
+ */
+enum ToyPaxosStatus toy_paxos_node_read(const struct ToyPaxosNode *node,
+                                        ToyPaxosCallback callback,
+                                        void *user_data);
+
+/**
+ * Shuts the node down and frees its handle, which must not be used again.
+ * Proposals still running fail with [`ToyPaxosStatus::Stopped`].
+ *
+ * # Safety
+ *
+ * `node` must come from [`toy_paxos_node_start`] and not be stopped yet.
+ */
+enum ToyPaxosStatus toy_paxos_node_stop(struct ToyPaxosNode *node);
+
+/**
+ * A static, NUL-terminated description of `status`.
+ */
+const char *toy_paxos_status_message(enum ToyPaxosStatus status);
+
+#endif /* TOY_PAXOS_H */
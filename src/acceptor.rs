@@ -0,0 +1,322 @@
+use crate::alpha::{Alpha, Error, Id, ReadResponse, Round, Status, Tick, Value, WriteResponse};
+use crate::audit::{Audit, AuditSink, EventKind};
+use crate::storage::Storage;
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::Stream;
+use std::hash::Hash;
+use std::hint;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+use std::sync::Arc;
+
+pub trait ValueValidator<V> {
+    fn validate(&self, value: &V) -> bool;
+}
+
+impl<V, F: Fn(&V) -> bool> ValueValidator<V> for F {
+    fn validate(&self, value: &V) -> bool {
+        self(value)
+    }
+}
+
+/// The round an acceptor has promised, readable without locking the
+/// acceptor. It is only published once persisted, and rounds only grow, so a
+/// write below it can be refused from here alone.
+pub struct Promised {
+    id: Id,
+    // A seqlock: odd while the round is being replaced.
+    sequence: AtomicU64,
+    tick: AtomicU64,
+    process_id: AtomicU64,
+}
+
+impl Promised {
+    fn new(id: Id, round: Round) -> Self {
+        Self {
+            id,
+            sequence: AtomicU64::new(0),
+            tick: AtomicU64::new(round.tick.0),
+            process_id: AtomicU64::new(round.process_id.0),
+        }
+    }
+
+    // Only the owning acceptor stores, so there is a single writer.
+    fn store(&self, round: Round) {
+        let sequence = self.sequence.load(Ordering::Relaxed);
+        self.sequence.store(sequence + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        self.tick.store(round.tick.0, Ordering::Relaxed);
+        self.process_id.store(round.process_id.0, Ordering::Relaxed);
+        self.sequence.store(sequence + 2, Ordering::Release);
+    }
+
+    pub fn load(&self) -> Round {
+        loop {
+            let before = self.sequence.load(Ordering::Acquire);
+            let round = Round {
+                tick: Tick(self.tick.load(Ordering::Relaxed)),
+                process_id: Id(self.process_id.load(Ordering::Relaxed)),
+            };
+            fence(Ordering::Acquire);
+            if before.is_multiple_of(2) && self.sequence.load(Ordering::Relaxed) == before {
+                return round;
+            }
+            hint::spin_loop();
+        }
+    }
+
+    /// The refusal the acceptor would send a write in `round`, if `round` is
+    /// below the promise.
+    pub fn refuse(&self, round: Round) -> Option<WriteResponse> {
+        let promised = self.load();
+        (round < promised).then_some(WriteResponse {
+            acceptor: self.id,
+            round,
+            status: Status::Rejected(promised),
+            last_round_entered: promised,
+            signature: None,
+        })
+    }
+}
+
+pub struct Acceptor<V, S> {
+    id: Id,
+    state: Alpha<V>,
+    promised: Arc<Promised>,
+    storage: S,
+    validator: Option<Box<dyn ValueValidator<V> + Send>>,
+    audit: Option<Audit<V>>,
+    watchers: Vec<UnboundedSender<(Round, Option<V>)>>,
+}
+
+impl<V, S> Acceptor<V, S>
+where
+    V: Clone,
+    S: Storage<V>,
+{
+    pub fn new(id: Id, mut storage: S) -> Result<Self, Error> {
+        let state = storage
+            .load()
+            .map_err(|error| Error::Storage(Box::new(error)))?
+            .unwrap_or_default();
+        Ok(Self {
+            id,
+            promised: Arc::new(Promised::new(id, state.last_round_entered)),
+            state,
+            storage,
+            validator: None,
+            audit: None,
+            watchers: Vec::new(),
+        })
+    }
+
+    pub fn with_validator(mut self, validator: impl ValueValidator<V> + Send + 'static) -> Self {
+        self.validator = Some(Box::new(validator));
+        self
+    }
+
+    pub fn with_audit(mut self, sink: impl AuditSink + Send + 'static) -> Self
+    where
+        V: Hash,
+    {
+        self.audit = Some(Audit::new(self.id, sink));
+        self
+    }
+
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    pub fn state(&self) -> &Alpha<V> {
+        &self.state
+    }
+
+    /// A handle that refuses stale writes without locking this acceptor.
+    pub fn promised(&self) -> Arc<Promised> {
+        self.promised.clone()
+    }
+
+    pub fn discard(mut self) -> Result<(), Error> {
+        self.storage
+            .discard()
+            .map_err(|error| Error::Storage(Box::new(error)))
+    }
+
+    pub fn watch(&mut self) -> impl Stream<Item = (Round, Option<V>)> {
+        let (sender, receiver) = unbounded();
+        let _ = sender.unbounded_send(self.snapshot());
+        self.watchers.push(sender);
+        receiver
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(acceptor = ?self.id, ?round)))]
+    pub fn handle_read(&mut self, round: Round) -> Result<ReadResponse<V>, Error> {
+        let mut state = self.state.clone();
+        let response = state.read(self.id, round);
+        #[cfg(feature = "tracing")]
+        tracing::trace!(status = ?response.status, "read");
+        self.commit(state)?;
+        if let (Some(audit), Status::Accepted) = (&mut self.audit, response.status) {
+            let value = response
+                .state
+                .value
+                .as_ref()
+                .map(|value| audit.hash(&value.value));
+            audit
+                .record(EventKind::Promise, round, value)
+                .map_err(|error| Error::Storage(Box::new(error)))?;
+        }
+        Ok(response)
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(acceptor = ?self.id, round = ?value.last_round_with_write))
+    )]
+    pub fn handle_write(&mut self, value: Value<V>) -> Result<WriteResponse, Error>
+    where
+        V: PartialEq,
+    {
+        if let Some(validator) = &self.validator {
+            if !validator.validate(&value.value) {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("invalid value");
+                return Ok(WriteResponse {
+                    acceptor: self.id,
+                    round: value.last_round_with_write,
+                    status: Status::Invalid,
+                    last_round_entered: self.state.last_round_entered,
+                    signature: None,
+                });
+            }
+        }
+        let hash = self.audit.as_ref().map(|audit| audit.hash(&value.value));
+        let mut state = self.state.clone();
+        let response = state.write(self.id, value);
+        #[cfg(feature = "tracing")]
+        tracing::trace!(status = ?response.status, "write");
+        self.commit(state)?;
+        if let (Some(audit), Status::Accepted) = (&mut self.audit, response.status) {
+            audit
+                .record(EventKind::Accept, response.round, hash)
+                .map_err(|error| Error::Storage(Box::new(error)))?;
+        }
+        Ok(response)
+    }
+
+    fn commit(&mut self, state: Alpha<V>) -> Result<(), Error> {
+        if state.last_round_entered != self.state.last_round_entered
+            || state.value.as_ref().map(|v| v.last_round_with_write)
+                != self.state.value.as_ref().map(|v| v.last_round_with_write)
+        {
+            self.storage
+                .persist(&state)
+                .map_err(|error| Error::Storage(Box::new(error)))?;
+            self.state = state;
+            self.promised.store(self.state.last_round_entered);
+            let update = self.snapshot();
+            self.watchers
+                .retain(|watcher| watcher.unbounded_send(update.clone()).is_ok());
+        }
+        Ok(())
+    }
+
+    fn snapshot(&self) -> (Round, Option<V>) {
+        (
+            self.state.last_round_entered,
+            self.state.value.as_ref().map(|v| (*v.value).clone()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use futures::{FutureExt, StreamExt};
+
+    fn acceptor() -> Acceptor<u64, MemoryStorage<u64>> {
+        Acceptor::new(Id(0), MemoryStorage::default()).unwrap()
+    }
+
+    #[test]
+    fn refuses_values_the_validator_rejects() {
+        let mut acceptor = acceptor().with_validator(|value: &u64| value.is_multiple_of(2));
+        let round = Round::new(Id(1));
+        acceptor.handle_read(round).unwrap();
+
+        let refused = acceptor.handle_write(Value::new(7, round)).unwrap();
+        assert!(matches!(refused.status, Status::Invalid));
+        assert_eq!(refused.last_round_entered, round);
+        assert_eq!(acceptor.state().accepted_value(), None);
+
+        let accepted = acceptor.handle_write(Value::new(8, round)).unwrap();
+        assert!(matches!(accepted.status, Status::Accepted));
+        assert_eq!(acceptor.state().accepted_value(), Some(&8));
+    }
+
+    #[test]
+    fn watchers_see_the_current_state_then_each_change() {
+        let mut acceptor = acceptor();
+        let mut updates = acceptor.watch();
+        let mut next = || updates.next().now_or_never().flatten();
+        assert_eq!(next(), Some((Round::default(), None)));
+
+        let round = Round::new(Id(1));
+        acceptor.handle_read(round).unwrap();
+        acceptor.handle_read(round).unwrap();
+        acceptor.handle_write(Value::new(5, round)).unwrap();
+        assert_eq!(next(), Some((round, None)));
+        assert_eq!(next(), Some((round, Some(5))));
+        assert_eq!(next(), None);
+    }
+
+    #[test]
+    fn dropped_watchers_are_forgotten() {
+        let mut acceptor = acceptor();
+        drop(acceptor.watch());
+        acceptor.handle_read(Round::new(Id(1))).unwrap();
+        assert!(acceptor.watchers.is_empty());
+    }
+
+    #[test]
+    fn refuses_writes_below_the_promise_without_the_acceptor() {
+        let mut acceptor = acceptor();
+        let promised = acceptor.promised();
+        let (old, new) = (Round::new(Id(1)), Round::new(Id(1)).next());
+        assert!(promised.refuse(old).is_none());
+
+        acceptor.handle_read(new).unwrap();
+        assert_eq!(promised.load(), new);
+        let refused = promised.refuse(old).unwrap();
+        assert!(matches!(refused.status, Status::Rejected(round) if round == new));
+        assert!(promised.refuse(new).is_none());
+
+        let answered = acceptor.handle_write(Value::new(1, old)).unwrap();
+        assert_eq!(answered.last_round_entered, refused.last_round_entered);
+        assert_eq!(answered.acceptor, refused.acceptor);
+    }
+
+    #[test]
+    fn promised_rounds_are_never_read_torn() {
+        let promised = Arc::new(Promised::new(Id(0), Round::default()));
+        let reader = {
+            let promised = promised.clone();
+            std::thread::spawn(move || {
+                let mut last = Round::default();
+                for _ in 0..100_000 {
+                    let round = promised.load();
+                    assert_eq!(round.tick.0, round.process_id.0);
+                    assert!(round >= last);
+                    last = round;
+                }
+            })
+        };
+        for i in 1..=100_000 {
+            promised.store(Round {
+                tick: Tick(i),
+                process_id: Id(i),
+            });
+        }
+        reader.join().unwrap();
+    }
+}
@@ -1,129 +1,372 @@
+use crate::bootstrap::BootstrapError;
+use crate::certificate::Signature;
+use crate::data_dir::LayoutError;
+use crate::fence::Fence;
+use crate::identity::IdentityError;
+use crate::time::{timeout_with, Clock};
 use futures::Stream;
 use futures::StreamExt;
 use std::cmp::max;
+use std::collections::HashSet;
 use std::fmt::Debug;
-use std::future::ready;
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Alpha<V> {
-    last_round_entered: Round,
-    value: Option<Value<V>>,
+    pub(crate) last_round_entered: Round,
+    pub(crate) value: Option<Value<V>>,
+}
+
+impl<V> Default for Alpha<V> {
+    fn default() -> Self {
+        Self {
+            last_round_entered: Round::default(),
+            value: None,
+        }
+    }
+}
+
+impl<V> Alpha<V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_state(last_round_entered: Round, value: Option<Value<V>>) -> Self {
+        Self {
+            last_round_entered,
+            value,
+        }
+    }
+
+    pub fn last_round_entered(&self) -> Round {
+        self.last_round_entered
+    }
+
+    pub fn accepted_value(&self) -> Option<&V> {
+        self.value.as_ref().map(Value::value)
+    }
+
+    pub fn accepted_round(&self) -> Option<Round> {
+        self.value.as_ref().map(Value::round)
+    }
 }
 
 impl<V> Alpha<V>
 where
     V: Clone,
 {
-    pub async fn alpha<P>(&mut self, peers: &P, round: Round, value: V) -> Result<Option<V>, Error>
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "round", skip_all, fields(?round)))]
+    pub async fn alpha<P>(
+        &mut self,
+        peers: &P,
+        round: Round,
+        value: V,
+        clock: &dyn Clock,
+        stage_timeout: Option<Duration>,
+    ) -> Result<Option<V>, Error>
     where
-        P: WriteClient<V> + ReadClient<V> + Quorum,
+        P: WritePeers<V> + ReadPeers<V> + Quorum,
     {
-        let value = match self.read_stage(peers, round, value).await? {
-            None => return Ok(None),
-            Some(v) => v,
-        };
+        match self.prepare(peers, round, clock, stage_timeout).await? {
+            None => Ok(None),
+            Some(promise) => {
+                self.accept(peers, promise, value, clock, stage_timeout)
+                    .await
+            }
+        }
+    }
 
-        self.write_stage(peers, round, value).await
+    pub async fn prepare<P>(
+        &self,
+        peers: &P,
+        round: Round,
+        clock: &dyn Clock,
+        stage_timeout: Option<Duration>,
+    ) -> Result<Option<Promise<V>>, Error>
+    where
+        P: ReadPeers<V> + Quorum,
+    {
+        let mut responses = pin!(peers.read(round));
+        self.read_stage(peers, round, &mut responses, clock, stage_timeout)
+            .await
     }
 
-    async fn read_stage<P>(&self, peers: &P, round: Round, value: V) -> Result<Option<V>, Error>
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(?round)))]
+    pub(crate) async fn read_stage<P, S>(
+        &self,
+        peers: &P,
+        round: Round,
+        responses: &mut S,
+        clock: &dyn Clock,
+        stage_timeout: Option<Duration>,
+    ) -> Result<Option<Promise<V>>, Error>
     where
-        P: WriteClient<V> + ReadClient<V> + Quorum,
+        P: Quorum,
+        S: Stream<Item = Result<ReadResponse<V>, Error>> + Unpin,
     {
-        let responses = peers
-            .broadcast_read(round)
-            .filter_map(|result| ready(result.ok()))
-            .take(peers.majority())
-            .collect::<Vec<ReadResponse<V>>>()
-            .await;
+        let responses = within(
+            clock,
+            stage_timeout,
+            collect_quorum(
+                responses,
+                |response: &ReadResponse<V>| (response.acceptor, response.status),
+                |acceptors| peers.is_read_quorum(acceptors),
+                peers.max_failures(),
+            ),
+        )
+        .await??;
 
-        if responses.iter().any(|response| response.round > round) {
-            return Ok(None);
-        }
+        let responses = match responses {
+            Ok(responses) => responses,
+            Err(responses) if responses.is_empty() => return Err(Error::EmptyReadResponse),
+            Err(_) => return Ok(None),
+        };
 
-        let value = responses
+        let highest = responses
+            .iter()
+            .filter_map(|response| response.state.value.as_ref())
+            .map(|v| v.last_round_with_write)
+            .max();
+        let stale = responses
+            .iter()
+            .filter(|response| {
+                response
+                    .state
+                    .value
+                    .as_ref()
+                    .map(|v| v.last_round_with_write)
+                    < highest
+            })
+            .map(|response| response.acceptor)
+            .collect();
+        let accepted = responses
             .into_iter()
-            .max_by_key(|response| response.state.last_round_entered)
-            .ok_or(Error::EmptyReadResponse)?
-            .state
-            .value
-            .map(|v| v.value)
-            .unwrap_or(value);
+            .filter_map(|response| response.state.value)
+            .max_by_key(|v| v.last_round_with_write)
+            .map(|v| v.value);
 
-        Ok(Some(value))
+        Ok(Some(Promise {
+            round,
+            accepted,
+            stale,
+        }))
     }
 
-    async fn write_stage<P>(
+    pub async fn accept<P>(
         &mut self,
         peers: &P,
-        round: Round,
+        promise: Promise<V>,
         value: V,
+        clock: &dyn Clock,
+        stage_timeout: Option<Duration>,
     ) -> Result<Option<V>, Error>
     where
-        P: WriteClient<V> + ReadClient<V> + Quorum,
+        P: WritePeers<V> + Quorum,
     {
-        self.last_round_entered = round;
-        let new_value = Value {
-            value,
-            last_round_with_write: round,
-        };
-        self.value = Some(new_value.clone());
+        let proposal = promise.proposal(value);
+        let mut responses = pin!(peers.write(proposal.clone()));
+        let decided = self
+            .write_stage(peers, proposal, &mut responses, clock, stage_timeout)
+            .await?;
+        Ok(decided.map(|(value, _)| value))
+    }
 
-        let responses = peers
-            .broadcast_write(new_value.clone())
-            .filter_map(|result| ready(result.ok()))
-            .take(peers.majority())
-            .collect::<Vec<WriteResponse>>()
-            .await;
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(round = ?proposal.last_round_with_write)))]
+    pub(crate) async fn write_stage<P, S>(
+        &mut self,
+        peers: &P,
+        proposal: Value<V>,
+        responses: &mut S,
+        clock: &dyn Clock,
+        stage_timeout: Option<Duration>,
+    ) -> Result<Option<(V, Vec<WriteResponse>)>, Error>
+    where
+        P: Quorum,
+        S: Stream<Item = Result<WriteResponse, Error>> + Unpin,
+    {
+        let responses = within(
+            clock,
+            stage_timeout,
+            collect_quorum(
+                responses,
+                |response: &WriteResponse| (response.acceptor, response.status),
+                |acceptors| peers.is_write_quorum(acceptors),
+                peers.max_failures(),
+            ),
+        )
+        .await??;
 
-        if responses
-            .iter()
-            .any(|response| response.last_round_entered > round)
-        {
+        let Ok(responses) = responses else {
             return Ok(None);
-        }
+        };
 
-        Ok(Some(new_value.value))
+        self.last_round_entered = proposal.last_round_with_write;
+        self.value = Some(proposal.clone());
+        Ok(Some((Arc::unwrap_or_clone(proposal.value), responses)))
     }
 
-    fn read(&mut self, round: Round) -> ReadResponse<V> {
+    pub(crate) fn read(&mut self, acceptor: Id, round: Round) -> ReadResponse<V> {
         self.last_round_entered = max(self.last_round_entered, round);
         ReadResponse {
+            acceptor,
             round,
+            status: Status::check(round, self.last_round_entered),
             state: self.clone(),
         }
     }
+}
 
-    fn write(&mut self, value: Value<V>) -> WriteResponse {
+impl<V> Alpha<V>
+where
+    V: Clone + PartialEq,
+{
+    /// A same-round write is accepted again only for an equal value, so a
+    /// decoded retransmit is acknowledged while a conflicting one is not.
+    pub(crate) fn write(&mut self, acceptor: Id, value: Value<V>) -> WriteResponse {
         let round = value.last_round_with_write;
-
-        if round >= self.last_round_entered
-            && self
-                .value
-                .as_ref()
-                .map_or(true, |v| round > v.last_round_with_write)
-        {
-            self.last_round_entered = round;
-            self.value = Some(value);
-        }
+        let stored = match &self.value {
+            _ if round < self.last_round_entered => false,
+            Some(accepted) if round <= accepted.last_round_with_write => {
+                round == accepted.last_round_with_write && accepted.value == value.value
+            }
+            _ => {
+                self.last_round_entered = round;
+                self.value = Some(value);
+                true
+            }
+        };
         WriteResponse {
+            acceptor,
             round,
+            status: if stored {
+                Status::Accepted
+            } else {
+                Status::Rejected(self.last_round_entered)
+            },
             last_round_entered: self.last_round_entered,
+            signature: None,
         }
     }
 }
 
+pub(crate) async fn collect_quorum<S, T, E>(
+    responses: &mut S,
+    inspect: impl Fn(&T) -> (Id, Status),
+    is_quorum: impl Fn(&HashSet<Id>) -> bool,
+    max_failures: usize,
+) -> Result<Result<Vec<T>, Vec<T>>, Error>
+where
+    S: Stream<Item = Result<T, E>> + Unpin,
+    E: Debug,
+    Error: From<E>,
+{
+    let mut acceptors = HashSet::new();
+    let mut quorum = Vec::new();
+    let mut errors = Vec::new();
+    while !is_quorum(&acceptors) {
+        match responses.next().await {
+            Some(Ok(response)) => match inspect(&response) {
+                (_id, Status::Rejected(conflict)) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(peer = ?_id, ?conflict, "rejected");
+                    return Err(Error::Preempted(conflict));
+                }
+                (_id, Status::Invalid) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(peer = ?_id, "invalid value");
+                    return Err(Error::InvalidValue);
+                }
+                (id, Status::Accepted) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::trace!(peer = ?id, "accepted");
+                    if acceptors.insert(id) {
+                        quorum.push(response);
+                    }
+                }
+            },
+            Some(Err(error)) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(?error, "peer error");
+                let error = Error::from(error);
+                if let Error::ValueTooLarge { .. } = error {
+                    return Err(error);
+                }
+                errors.push(error);
+                if errors.len() > max_failures {
+                    return Err(Error::QuorumUnreachable { errors });
+                }
+            }
+            None if errors.is_empty() => return Ok(Err(quorum)),
+            None => return Err(Error::QuorumUnreachable { errors }),
+        }
+    }
+    Ok(Ok(quorum))
+}
+
+async fn within<F: Future>(
+    clock: &dyn Clock,
+    stage_timeout: Option<Duration>,
+    future: F,
+) -> Result<F::Output, Error> {
+    match stage_timeout {
+        Some(duration) => timeout_with(clock, duration, future)
+            .await
+            .map_err(|_| Error::QuorumTimeout),
+        None => Ok(future.await),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Value<V> {
+    pub(crate) value: Arc<V>,
+    pub(crate) last_round_with_write: Round,
+}
+
+impl<V> Value<V> {
+    pub fn new(value: V, round: Round) -> Self {
+        Self {
+            value: Arc::new(value),
+            last_round_with_write: round,
+        }
+    }
+
+    pub fn value(&self) -> &V {
+        &self.value
+    }
+
+    pub fn round(&self) -> Round {
+        self.last_round_with_write
+    }
+}
+
 #[derive(Clone, Debug)]
-struct Value<V> {
-    value: V,
-    last_round_with_write: Round,
+pub struct Promise<V> {
+    pub(crate) round: Round,
+    pub(crate) accepted: Option<Arc<V>>,
+    pub(crate) stale: Vec<Id>,
 }
 
-#[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
+impl<V> Promise<V> {
+    pub(crate) fn needs_repair(&self) -> bool {
+        self.accepted.is_some() && !self.stale.is_empty()
+    }
+
+    pub(crate) fn proposal(self, value: V) -> Value<V> {
+        Value {
+            value: self.accepted.unwrap_or_else(|| Arc::new(value)),
+            last_round_with_write: self.round,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Default)]
 pub struct Round {
-    tick: Tick,
-    process_id: Id,
+    pub(crate) tick: Tick,
+    pub(crate) process_id: Id,
 }
 
 impl Round {
@@ -134,19 +377,67 @@ impl Round {
         }
     }
 
+    pub fn resume(process_id: Id, last_tick: u64) -> Self {
+        Self {
+            tick: Tick(last_tick).next(),
+            process_id,
+        }
+    }
+
     pub fn next(self) -> Self {
         Self {
             tick: self.tick.next(),
             process_id: self.process_id,
         }
     }
+
+    pub fn greater_than(self, other: Round) -> Self {
+        let tick = if self.process_id > other.process_id {
+            other.tick
+        } else {
+            other.tick.next()
+        };
+        Self {
+            tick,
+            process_id: self.process_id,
+        }
+    }
 }
 
-#[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
-pub struct Id(u64);
+#[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash, Default)]
+pub struct Id(pub(crate) u64);
+
+impl Id {
+    pub fn new(id: u64) -> Self {
+        Self(id)
+    }
+
+    pub fn get(self) -> u64 {
+        self.0
+    }
+
+    pub fn from_name(name: &str) -> Self {
+        let hash = name.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        Self(hash)
+    }
+}
+
+impl From<u64> for Id {
+    fn from(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+impl From<u128> for Id {
+    fn from(uuid: u128) -> Self {
+        Self((uuid >> 64) as u64 ^ uuid as u64)
+    }
+}
 
 #[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Default)]
-struct Tick(u64);
+pub(crate) struct Tick(pub(crate) u64);
 
 impl Tick {
     fn next(self) -> Self {
@@ -158,16 +449,134 @@ impl Tick {
 pub enum Error {
     #[error("no read response")]
     EmptyReadResponse,
+    #[error("transport error")]
+    Transport(#[from] std::io::Error),
+    #[error("timed out waiting for a quorum")]
+    QuorumTimeout,
+    #[error("preempted by round {0:?}")]
+    Preempted(Round),
+    #[error("quorum unreachable after {} peer errors", errors.len())]
+    QuorumUnreachable { errors: Vec<Error> },
+    #[error("gave up after {attempts} attempts")]
+    RetriesExhausted {
+        attempts: usize,
+        #[source]
+        last: Option<Box<Error>>,
+    },
+    #[error("batch proposal failed")]
+    BatchFailed(#[source] std::sync::Arc<Error>),
+    #[error("message of {size} bytes exceeds the {limit} byte limit")]
+    ValueTooLarge { size: usize, limit: usize },
+    #[error("value rejected by an acceptor's validator")]
+    InvalidValue,
+    #[error("not the leader")]
+    NotLeader,
+    #[error("over the client's quota; it fits again after {retry_after:?}")]
+    Throttled { retry_after: Duration },
+    #[error("an observer neither votes nor proposes")]
+    Observing,
+    #[error("proposal cancelled")]
+    Cancelled,
+    #[error("proposer stopped")]
+    ProposerStopped,
+    #[error("log entry {0} is not committed")]
+    NotCommitted(u64),
+    #[error("instance {0} was garbage collected")]
+    Collected(u64),
+    #[error("membership changed concurrently")]
+    MembershipConflict,
+    #[error("member {0:?} has not caught up with the cluster")]
+    NotCaughtUp(Id),
+    #[error("removing member {0:?} would leave too few live voters for a quorum")]
+    QuorumAtRisk(Id),
+    #[error("force recovery refused: {0}")]
+    RecoveryRefused(&'static str),
+    #[error("the storage of {id:?} is older than fence {seen:?} it announced before")]
+    StaleStorage { id: Id, seen: Fence },
+    #[error("storage error")]
+    Storage(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("peer error")]
+    Peer(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("protocol version {remote} is incompatible with local version {local}")]
+    IncompatibleVersion { local: u32, remote: u32 },
+    #[error("bootstrap failed")]
+    Bootstrap(#[from] BootstrapError),
+    #[error("node identity")]
+    Identity(#[from] IdentityError),
+    #[error("data directory")]
+    Layout(#[from] LayoutError),
 }
 
-struct ReadResponse<V> {
-    round: Round,
-    state: Alpha<V>,
+impl Error {
+    pub fn peer(error: impl std::error::Error + Send + Sync + 'static) -> Self {
+        Error::Peer(Box::new(error))
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::EmptyReadResponse
+            | Error::Transport(_)
+            | Error::QuorumTimeout
+            | Error::Preempted(_)
+            | Error::QuorumUnreachable { .. }
+            | Error::NotLeader
+            | Error::Throttled { .. }
+            | Error::MembershipConflict
+            | Error::NotCaughtUp(_)
+            | Error::Peer(_) => true,
+            Error::BatchFailed(error) => error.is_retryable(),
+            Error::RetriesExhausted { .. }
+            | Error::ValueTooLarge { .. }
+            | Error::InvalidValue
+            | Error::Observing
+            | Error::Cancelled
+            | Error::ProposerStopped
+            | Error::NotCommitted(_)
+            | Error::Collected(_)
+            | Error::QuorumAtRisk(_)
+            | Error::RecoveryRefused(_)
+            | Error::StaleStorage { .. }
+            | Error::Storage(_)
+            | Error::IncompatibleVersion { .. }
+            | Error::Bootstrap(_)
+            | Error::Identity(_)
+            | Error::Layout(_) => false,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Status {
+    Accepted,
+    Rejected(Round),
+    Invalid,
 }
 
-struct WriteResponse {
-    round: Round,
-    last_round_entered: Round,
+impl Status {
+    fn check(round: Round, last_round_entered: Round) -> Self {
+        if round < last_round_entered {
+            Status::Rejected(last_round_entered)
+        } else {
+            Status::Accepted
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ReadResponse<V> {
+    pub acceptor: Id,
+    pub round: Round,
+    pub status: Status,
+    pub state: Alpha<V>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct WriteResponse {
+    pub acceptor: Id,
+    pub round: Round,
+    pub status: Status,
+    pub last_round_entered: Round,
+    pub signature: Option<Signature>,
 }
 
 pub trait ReadClient<V> {
@@ -182,6 +591,123 @@ pub trait WriteClient<V> {
     fn broadcast_write(&self, value: Value<V>) -> Self::Stream;
 }
 
+pub trait ReadPeers<V> {
+    fn read(&self, round: Round) -> impl Stream<Item = Result<ReadResponse<V>, Error>>;
+}
+
+pub trait WritePeers<V> {
+    fn write(&self, value: Value<V>) -> impl Stream<Item = Result<WriteResponse, Error>>;
+
+    /// Sends `value` to at least `acceptors`. The stream may be dropped
+    /// unpolled, so transports should dispatch on the call. Those that cannot
+    /// address a single acceptor keep the default, which broadcasts.
+    fn write_to(
+        &self,
+        acceptors: &[Id],
+        value: Value<V>,
+    ) -> impl Stream<Item = Result<WriteResponse, Error>> {
+        let _ = acceptors;
+        self.write(value)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Compat<P>(pub P);
+
+impl<V, P: ReadClient<V>> ReadPeers<V> for Compat<P> {
+    fn read(&self, round: Round) -> impl Stream<Item = Result<ReadResponse<V>, Error>> {
+        self.0
+            .broadcast_read(round)
+            .map(|response| response.map_err(Into::into))
+    }
+}
+
+impl<V, P: WriteClient<V>> WritePeers<V> for Compat<P> {
+    fn write(&self, value: Value<V>) -> impl Stream<Item = Result<WriteResponse, Error>> {
+        self.0
+            .broadcast_write(value)
+            .map(|response| response.map_err(Into::into))
+    }
+}
+
+impl<P: Quorum> Quorum for Compat<P> {
+    fn majority(&self) -> usize {
+        self.0.majority()
+    }
+
+    fn max_failures(&self) -> usize {
+        self.0.max_failures()
+    }
+
+    fn is_read_quorum(&self, acceptors: &HashSet<Id>) -> bool {
+        self.0.is_read_quorum(acceptors)
+    }
+
+    fn is_write_quorum(&self, acceptors: &HashSet<Id>) -> bool {
+        self.0.is_write_quorum(acceptors)
+    }
+}
+
 pub trait Quorum {
     fn majority(&self) -> usize;
+
+    /// How many acceptors may fail before no quorum can answer. This depends
+    /// on the member count, not just the majority, so it has no default.
+    fn max_failures(&self) -> usize;
+
+    fn is_read_quorum(&self, acceptors: &HashSet<Id>) -> bool {
+        acceptors.len() >= self.majority()
+    }
+
+    fn is_write_quorum(&self, acceptors: &HashSet<Id>) -> bool {
+        acceptors.len() >= self.majority()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_rejects_a_different_value_in_the_same_round() {
+        let round = Round::new(Id(1));
+        let mut alpha = Alpha::new();
+        let first = Value::new("a", round);
+        assert_eq!(alpha.write(Id(0), first.clone()).status, Status::Accepted);
+        assert_eq!(alpha.write(Id(0), first).status, Status::Accepted);
+        assert_eq!(
+            alpha.write(Id(0), Value::new("b", round)).status,
+            Status::Rejected(round)
+        );
+        assert_eq!(alpha.accepted_value(), Some(&"a"));
+    }
+
+    #[test]
+    fn write_accepts_a_decoded_retransmit_of_the_same_value() {
+        let round = Round::new(Id(1));
+        let mut alpha = Alpha::new();
+        assert_eq!(
+            alpha
+                .write(Id(0), Value::new(String::from("a"), round))
+                .status,
+            Status::Accepted
+        );
+        assert_eq!(
+            alpha
+                .write(Id(0), Value::new(String::from("a"), round))
+                .status,
+            Status::Accepted
+        );
+        assert_eq!(alpha.accepted_value().map(String::as_str), Some("a"));
+    }
+
+    #[test]
+    fn write_rejects_an_older_round() {
+        let mut alpha = Alpha::new();
+        let newer = Round::new(Id(1)).next();
+        alpha.read(Id(0), newer);
+        let response = alpha.write(Id(0), Value::new("a", Round::new(Id(1))));
+        assert_eq!(response.status, Status::Rejected(newer));
+        assert_eq!(alpha.accepted_value(), None);
+    }
 }
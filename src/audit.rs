@@ -0,0 +1,458 @@
+use crate::alpha::{Id, Quorum, Round, WriteResponse};
+#[cfg(feature = "auth")]
+use crate::auth::Keyring;
+#[cfg(feature = "auth")]
+use crate::certificate::signature_valid;
+use crate::certificate::{CertificateError, DecisionCertificate};
+use crate::codec::{to_bytes, Decode, Encode};
+use crate::digest::sha256;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Promise,
+    Accept,
+    Decision,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AuditEvent {
+    pub kind: EventKind,
+    pub round: Round,
+    pub peer: Id,
+    pub timestamp: u64,
+    pub value: Option<u64>,
+}
+
+pub trait AuditSink {
+    fn record(&mut self, event: &AuditEvent) -> io::Result<()>;
+}
+
+impl<F: FnMut(&AuditEvent) -> io::Result<()>> AuditSink for F {
+    fn record(&mut self, event: &AuditEvent) -> io::Result<()> {
+        self(event)
+    }
+}
+
+pub struct AuditFile {
+    file: File,
+}
+
+impl AuditFile {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file })
+    }
+}
+
+impl AuditSink for AuditFile {
+    fn record(&mut self, event: &AuditEvent) -> io::Result<()> {
+        self.file.write_all(&to_bytes(event))?;
+        self.file.sync_data()
+    }
+}
+
+pub fn replay(path: impl AsRef<Path>) -> io::Result<Vec<AuditEvent>> {
+    let bytes = fs::read(path)?;
+    let mut buf = bytes.as_slice();
+    let mut events = Vec::new();
+    while !buf.is_empty() {
+        events.push(AuditEvent::decode(&mut buf)?);
+    }
+    Ok(events)
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum Violation {
+    #[error("{peer:?} entered {round:?} after promising {promised:?}")]
+    RoundRegressed {
+        peer: Id,
+        round: Round,
+        promised: Round,
+    },
+    #[error("two different values were accepted in {0:?}")]
+    ConflictingAccepts(Round),
+    #[error("two different values were decided")]
+    ConflictingDecisions,
+    #[error("{peer:?} accepted a different value in {round:?} after a decision in {decided:?}")]
+    AcceptedAfterDecision {
+        peer: Id,
+        round: Round,
+        decided: Round,
+    },
+}
+
+pub fn verify<'a>(events: impl IntoIterator<Item = &'a AuditEvent>) -> Result<(), Violation> {
+    let mut promised: HashMap<Id, Round> = HashMap::new();
+    let mut accepted: BTreeMap<Round, Option<u64>> = BTreeMap::new();
+    let mut accepts = Vec::new();
+    let mut decision: Option<(Round, Option<u64>)> = None;
+    for event in events {
+        match event.kind {
+            EventKind::Promise | EventKind::Accept => {
+                let last = promised.entry(event.peer).or_default();
+                if event.round < *last {
+                    return Err(Violation::RoundRegressed {
+                        peer: event.peer,
+                        round: event.round,
+                        promised: *last,
+                    });
+                }
+                *last = event.round;
+                if event.kind == EventKind::Accept {
+                    if *accepted.entry(event.round).or_insert(event.value) != event.value {
+                        return Err(Violation::ConflictingAccepts(event.round));
+                    }
+                    accepts.push(event);
+                }
+            }
+            EventKind::Decision => match decision {
+                Some((_, value)) if value != event.value => {
+                    return Err(Violation::ConflictingDecisions)
+                }
+                Some((round, _)) if round <= event.round => {}
+                _ => decision = Some((event.round, event.value)),
+            },
+        }
+    }
+    let Some((decided, value)) = decision else {
+        return Ok(());
+    };
+    match accepts
+        .into_iter()
+        .find(|accept| accept.round >= decided && accept.value != value)
+    {
+        Some(accept) => Err(Violation::AcceptedAfterDecision {
+            peer: accept.peer,
+            round: accept.round,
+            decided,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// What one instance decided: the round, a SHA-256 digest of the value and
+/// the write acknowledgements that made it a decision. Each record carries
+/// the digest of the one before it, so editing, dropping or reordering a
+/// record breaks the chain from there on.
+#[derive(Clone, Debug)]
+pub struct DecisionRecord {
+    pub instance: u64,
+    pub proposer: Id,
+    pub round: Round,
+    pub value: [u8; 32],
+    pub acks: Vec<WriteResponse>,
+    pub previous: [u8; 32],
+}
+
+impl DecisionRecord {
+    pub fn digest(&self) -> [u8; 32] {
+        sha256(&to_bytes(self))
+    }
+}
+
+/// An append-only file of [`DecisionRecord`]s.
+pub struct DecisionTrail {
+    file: File,
+    last: [u8; 32],
+    instances: HashSet<u64>,
+}
+
+impl DecisionTrail {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let records = match export(path) {
+            Ok(records) => records,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(error) => return Err(error),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file,
+            last: records.last().map_or([0; 32], DecisionRecord::digest),
+            instances: records.iter().map(|record| record.instance).collect(),
+        })
+    }
+
+    /// Appends the record for `instance` unless it already has one, so every
+    /// proposer that saw the decision can call this.
+    pub fn record<V: Encode>(
+        &mut self,
+        instance: u64,
+        certificate: &DecisionCertificate<V>,
+    ) -> io::Result<()> {
+        if self.instances.contains(&instance) {
+            return Ok(());
+        }
+        let record = DecisionRecord {
+            instance,
+            proposer: certificate.proposer,
+            round: certificate.round,
+            value: sha256(&to_bytes(&certificate.value)),
+            acks: certificate.responses.clone(),
+            previous: self.last,
+        };
+        self.file.write_all(&to_bytes(&record))?;
+        self.file.sync_data()?;
+        self.last = record.digest();
+        self.instances.insert(instance);
+        Ok(())
+    }
+}
+
+pub fn export(path: impl AsRef<Path>) -> io::Result<Vec<DecisionRecord>> {
+    let bytes = fs::read(path)?;
+    let mut buf = bytes.as_slice();
+    let mut records = Vec::new();
+    while !buf.is_empty() {
+        records.push(DecisionRecord::decode(&mut buf)?);
+    }
+    Ok(records)
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum TrailError {
+    #[error("record for instance {0} does not follow the one before it")]
+    BrokenChain(u64),
+    #[error("instance {0} is recorded more than once")]
+    DuplicateInstance(u64),
+    #[error("acknowledgements for instance {instance} are not a decision")]
+    Acks {
+        instance: u64,
+        #[source]
+        error: CertificateError,
+    },
+}
+
+/// Checks the chain and that every record's acknowledgements form a write
+/// quorum for its round.
+pub fn verify_trail<'a>(
+    records: impl IntoIterator<Item = &'a DecisionRecord>,
+    quorum: &impl Quorum,
+) -> Result<(), TrailError> {
+    let mut previous = [0; 32];
+    let mut instances = HashSet::new();
+    for record in records {
+        if record.previous != previous {
+            return Err(TrailError::BrokenChain(record.instance));
+        }
+        if !instances.insert(record.instance) {
+            return Err(TrailError::DuplicateInstance(record.instance));
+        }
+        let certificate = DecisionCertificate {
+            proposer: record.proposer,
+            round: record.round,
+            value: (),
+            responses: record.acks.clone(),
+        };
+        certificate
+            .verify(quorum)
+            .map_err(|error| TrailError::Acks {
+                instance: record.instance,
+                error,
+            })?;
+        previous = record.digest();
+    }
+    Ok(())
+}
+
+/// Checks each acknowledgement's signature over the recorded value digest.
+/// Like [`DecisionCertificate::verify_signatures`], this needs the keys the
+/// acceptors share with each record's proposer.
+#[cfg(feature = "auth")]
+pub fn verify_trail_signatures<'a>(
+    records: impl IntoIterator<Item = &'a DecisionRecord>,
+    keyring: &Keyring,
+) -> Result<(), TrailError> {
+    for record in records {
+        if let Some(ack) = record
+            .acks
+            .iter()
+            .find(|ack| !signature_valid(keyring, record.proposer, ack, &record.value))
+        {
+            return Err(TrailError::Acks {
+                instance: record.instance,
+                error: CertificateError::BadSignature(ack.acceptor),
+            });
+        }
+    }
+    Ok(())
+}
+
+pub(crate) struct Audit<V> {
+    peer: Id,
+    sink: Box<dyn AuditSink + Send>,
+    hash: fn(&V) -> u64,
+}
+
+impl<V> Audit<V> {
+    pub(crate) fn new(peer: Id, sink: impl AuditSink + Send + 'static) -> Self
+    where
+        V: Hash,
+    {
+        Self {
+            peer,
+            sink: Box::new(sink),
+            hash: hash_value,
+        }
+    }
+
+    pub(crate) fn hash(&self, value: &V) -> u64 {
+        (self.hash)(value)
+    }
+
+    pub(crate) fn record(
+        &mut self,
+        kind: EventKind,
+        round: Round,
+        value: Option<u64>,
+    ) -> io::Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        self.sink.record(&AuditEvent {
+            kind,
+            round,
+            peer: self.peer,
+            timestamp,
+            value,
+        })
+    }
+}
+
+fn hash_value<V: Hash>(value: &V) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alpha::Status;
+    use crate::quorum::QuorumSpec;
+    use std::path::PathBuf;
+
+    fn scratch(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("paxos-audit-{}-{name}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn certificate(value: u64, acceptors: &[u64]) -> DecisionCertificate<u64> {
+        let round = Round::new(Id(1));
+        DecisionCertificate {
+            proposer: Id(1),
+            round,
+            value,
+            responses: acceptors
+                .iter()
+                .map(|acceptor| WriteResponse {
+                    acceptor: Id(*acceptor),
+                    round,
+                    status: Status::Accepted,
+                    last_round_entered: round,
+                    signature: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn quorum() -> QuorumSpec {
+        QuorumSpec::Majority {
+            members: vec![Id(1), Id(2), Id(3)],
+        }
+    }
+
+    #[test]
+    fn exports_a_verifiable_chain() {
+        let path = scratch("chain");
+        let mut trail = DecisionTrail::open(&path).unwrap();
+        trail.record(0, &certificate(10, &[1, 2])).unwrap();
+        trail.record(0, &certificate(10, &[1, 2, 3])).unwrap();
+        drop(trail);
+
+        let mut reopened = DecisionTrail::open(&path).unwrap();
+        reopened.record(1, &certificate(11, &[2, 3])).unwrap();
+        let records = export(&path).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].value, sha256(&to_bytes(&11u64)));
+        verify_trail(&records, &quorum()).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn detects_tampering() {
+        let path = scratch("tamper");
+        let mut trail = DecisionTrail::open(&path).unwrap();
+        trail.record(0, &certificate(10, &[1, 2])).unwrap();
+        trail.record(1, &certificate(11, &[1, 3])).unwrap();
+        let records = export(&path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let mut edited = records.clone();
+        edited[0].value = sha256(&to_bytes(&99u64));
+        assert_eq!(
+            verify_trail(&edited, &quorum()),
+            Err(TrailError::BrokenChain(1))
+        );
+        assert_eq!(
+            verify_trail(&records[1..], &quorum()),
+            Err(TrailError::BrokenChain(1))
+        );
+
+        let mut thin = records.clone();
+        thin[1].acks.pop();
+        assert_eq!(
+            verify_trail(&thin, &quorum()),
+            Err(TrailError::Acks {
+                instance: 1,
+                error: CertificateError::NoQuorum
+            })
+        );
+    }
+
+    #[cfg(feature = "auth")]
+    #[test]
+    fn signatures_cover_the_recorded_value() {
+        let proposer = Keyring::new(Id(1))
+            .with_key(Id(2), *b"two")
+            .with_key(Id(3), *b"three");
+        let mut certificate = certificate(10, &[2, 3]);
+        for response in &mut certificate.responses {
+            let acceptor = Keyring::new(response.acceptor).with_key(
+                Id(1),
+                match response.acceptor {
+                    Id(2) => &b"two"[..],
+                    _ => &b"three"[..],
+                },
+            );
+            *response = crate::certificate::sign(*response, &10u64, &acceptor, Id(1)).unwrap();
+        }
+        let path = scratch("signed");
+        DecisionTrail::open(&path)
+            .unwrap()
+            .record(0, &certificate)
+            .unwrap();
+        let mut records = export(&path).unwrap();
+        fs::remove_file(path).unwrap();
+        verify_trail_signatures(&records, &proposer).unwrap();
+
+        records[0].value = sha256(&to_bytes(&11u64));
+        assert_eq!(
+            verify_trail_signatures(&records, &proposer),
+            Err(TrailError::Acks {
+                instance: 0,
+                error: CertificateError::BadSignature(Id(2))
+            })
+        );
+    }
+}
@@ -0,0 +1,370 @@
+use crate::alpha::Id;
+use crate::codec::invalid_data;
+use crate::digest::hmac_sha256;
+use crate::rng::XorShift;
+use crate::roles::Permissions;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::io;
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError, RwLock, RwLockReadGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const TAG: usize = 32;
+/// The sender's id, the time it sealed the frame and a nonce.
+pub(crate) const HEAD: usize = 24;
+
+/// The keys a node authenticates its messages with. A keyring shared with
+/// a server and its peers can be [rotated](Self::rotate) while they run.
+///
+/// Every frame carries when it was sealed and a nonce, so one captured on
+/// the wire is refused once it is older than the freshness window, and
+/// refused as a replay before then.
+pub struct Keyring {
+    id: Id,
+    keys: RwLock<Keys>,
+    freshness: Duration,
+    nonces: AtomicU64,
+    /// The frames opened within the freshness window, by when they were
+    /// sealed.
+    seen: Mutex<BTreeSet<(u64, Id, u64)>>,
+}
+
+#[derive(Clone, Default)]
+struct Keys {
+    current: HashMap<Id, Vec<u8>>,
+    /// What the last rotation replaced, still accepted from peers that have
+    /// not rotated yet.
+    previous: HashMap<Id, Vec<u8>>,
+    roles: HashMap<Id, Permissions>,
+}
+
+impl Keyring {
+    pub fn new(id: Id) -> Self {
+        let seed = now_millis() ^ u64::from(std::process::id()) << 32 ^ id.0;
+        Self {
+            id,
+            keys: RwLock::default(),
+            freshness: Duration::from_secs(30),
+            nonces: AtomicU64::new(XorShift::new(seed).next_u64()),
+            seen: Mutex::default(),
+        }
+    }
+
+    /// How far apart the clocks of a frame's sender and receiver, plus its
+    /// time in flight, may be before it is refused as stale; 30 seconds by
+    /// default.
+    pub fn with_freshness(mut self, freshness: Duration) -> Self {
+        self.freshness = freshness;
+        self
+    }
+
+    pub fn with_key(mut self, peer: Id, key: impl Into<Vec<u8>>) -> Self {
+        self.keys_mut().current.insert(peer, key.into());
+        self
+    }
+
+    /// Keys an operator, such as `paxos-admin`, that may send admin requests
+    /// like leadership transfers and snapshots.
+    pub fn with_operator(self, operator: Id, key: impl Into<Vec<u8>>) -> Self {
+        self.with_role(operator, key, Permissions::ALL)
+    }
+
+    /// Keys an identity that is not a member and may only ask for what
+    /// `permissions` grant.
+    pub fn with_role(mut self, id: Id, key: impl Into<Vec<u8>>, permissions: Permissions) -> Self {
+        self.keys_mut().roles.insert(id, permissions);
+        self.with_key(id, key)
+    }
+
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    /// Seals from now on with the keys and roles of `next`. Messages sealed
+    /// with the keys it replaces are still opened until the next rotation,
+    /// so members can rotate one at a time; what a rotated member sends one
+    /// that has not rotated yet is refused, and retried, until that one
+    /// rotates too.
+    pub fn rotate(&self, next: &Keyring) {
+        let next = next.keys().clone();
+        let mut keys = self.keys.write().unwrap_or_else(PoisonError::into_inner);
+        keys.previous = mem::replace(&mut keys.current, next.current);
+        keys.roles = next.roles;
+    }
+
+    /// What `id` was given, or `None` for the members.
+    pub(crate) fn role(&self, id: Id) -> Option<Permissions> {
+        self.keys().roles.get(&id).copied()
+    }
+
+    pub(crate) fn seal(&self, to: Id, payload: &[u8]) -> io::Result<Vec<u8>> {
+        self.seal_at(to, payload, now_millis())
+    }
+
+    fn seal_at(&self, to: Id, payload: &[u8], now: u64) -> io::Result<Vec<u8>> {
+        let (head, tail) = self.seal_around_at(to, payload, now)?;
+        let mut frame = Vec::with_capacity(HEAD + payload.len() + TAG);
+        frame.extend_from_slice(&head);
+        frame.extend_from_slice(payload);
+        frame.extend_from_slice(&tail);
+        Ok(frame)
+    }
+
+    /// What [`seal`](Self::seal) puts before and after `payload`, for
+    /// callers that send the payload without copying it.
+    pub(crate) fn seal_around(
+        &self,
+        to: Id,
+        payload: &[u8],
+    ) -> io::Result<([u8; HEAD], [u8; TAG])> {
+        self.seal_around_at(to, payload, now_millis())
+    }
+
+    fn seal_around_at(
+        &self,
+        to: Id,
+        payload: &[u8],
+        now: u64,
+    ) -> io::Result<([u8; HEAD], [u8; TAG])> {
+        let mut head = [0; HEAD];
+        head[..8].copy_from_slice(&self.id.0.to_be_bytes());
+        head[8..16].copy_from_slice(&now.to_be_bytes());
+        let nonce = self.nonces.fetch_add(1, Ordering::Relaxed);
+        head[16..].copy_from_slice(&nonce.to_be_bytes());
+        let keys = self.keys();
+        let key = keys
+            .current
+            .get(&to)
+            .ok_or_else(|| invalid_data(&format!("no key for {to:?}")))?;
+        Ok((head, tag(key, self.id, to, &[&head[8..], payload])))
+    }
+
+    pub(crate) fn open<'a>(&self, frame: &'a [u8]) -> io::Result<(Id, &'a [u8])> {
+        self.open_at(frame, now_millis())
+    }
+
+    fn open_at<'a>(&self, frame: &'a [u8], now: u64) -> io::Result<(Id, &'a [u8])> {
+        if frame.len() < HEAD + TAG {
+            return Err(invalid_data("unauthenticated message"));
+        }
+        let (head, rest) = frame.split_at(HEAD);
+        let (payload, received) = rest.split_at(rest.len() - TAG);
+        let word = |at: usize| u64::from_be_bytes(head[at..at + 8].try_into().unwrap());
+        let (from, sealed_at, nonce) = (Id(word(0)), word(8), word(16));
+        if !self.authentic(from, self.id, &[&head[8..], payload], received) {
+            return Err(invalid_data("unauthenticated message"));
+        }
+        let freshness = self.freshness.as_millis() as u64;
+        if sealed_at.abs_diff(now) > freshness {
+            return Err(invalid_data("stale message"));
+        }
+        let mut seen = self.seen.lock().unwrap_or_else(PoisonError::into_inner);
+        // Anything sealed before the window opened is refused as stale, so
+        // need not be remembered.
+        *seen = seen.split_off(&(now.saturating_sub(freshness), Id(0), 0));
+        match seen.insert((sealed_at, from, nonce)) {
+            true => Ok((from, payload)),
+            false => Err(invalid_data("replayed message")),
+        }
+    }
+
+    pub(crate) fn sign(&self, to: Id, message: &[u8]) -> io::Result<[u8; TAG]> {
+        let keys = self.keys();
+        let key = keys
+            .current
+            .get(&to)
+            .ok_or_else(|| invalid_data(&format!("no key for {to:?}")))?;
+        Ok(tag(key, self.id, to, &[message]))
+    }
+
+    pub(crate) fn verify(&self, from: Id, to: Id, message: &[u8], signature: &[u8]) -> bool {
+        self.authentic(from, to, &[message], signature)
+    }
+
+    fn authentic(&self, from: Id, to: Id, parts: &[&[u8]], received: &[u8]) -> bool {
+        let keys = self.keys();
+        let authentic = [&keys.current, &keys.previous]
+            .into_iter()
+            .filter_map(|keys| keys.get(&from))
+            .any(|key| constant_time_eq(&tag(key, from, to, parts), received));
+        authentic
+    }
+
+    fn keys(&self) -> RwLockReadGuard<'_, Keys> {
+        self.keys.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn keys_mut(&mut self) -> &mut Keys {
+        self.keys.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Clone for Keyring {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            keys: RwLock::new(self.keys().clone()),
+            freshness: self.freshness,
+            nonces: AtomicU64::new(self.nonces.load(Ordering::Relaxed)),
+            seen: Mutex::new(
+                self.seen
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .clone(),
+            ),
+        }
+    }
+}
+
+impl fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys = self.keys();
+        f.debug_struct("Keyring")
+            .field("id", &self.id)
+            .field("peers", &keys.current.keys().collect::<Vec<_>>())
+            .field("roles", &keys.roles)
+            .finish()
+    }
+}
+
+fn tag(key: &[u8], from: Id, to: Id, parts: &[&[u8]]) -> [u8; TAG] {
+    let len = parts.iter().map(|part| part.len()).sum::<usize>();
+    let mut message = Vec::with_capacity(16 + len);
+    message.extend_from_slice(&from.0.to_be_bytes());
+    message.extend_from_slice(&to.0.to_be_bytes());
+    for part in parts {
+        message.extend_from_slice(part);
+    }
+    hmac_sha256(key, &message)
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    #[test]
+    fn matches_rfc_4231_vectors() {
+        let long_key = [0xaa; 131];
+        for (key, message, tag) in [
+            (
+                &[0x0b; 20][..],
+                &b"Hi There"[..],
+                "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+            ),
+            (
+                b"Jefe",
+                b"what do ya want for nothing?",
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            ),
+            (
+                &[0xaa; 20],
+                &[0xdd; 50],
+                "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe",
+            ),
+            (
+                &(1..=25).collect::<Vec<u8>>(),
+                &[0xcd; 50],
+                "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b",
+            ),
+            (
+                &long_key,
+                b"Test Using Larger Than Block-Size Key - Hash Key First",
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+            ),
+            (
+                &long_key,
+                b"This is a test using a larger than block-size key and a larger than \
+                  block-size data. The key needs to be hashed before being used by the \
+                  HMAC algorithm.",
+                "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+            ),
+        ] {
+            assert_eq!(hex(&hmac_sha256(key, message)), tag);
+        }
+    }
+
+    #[test]
+    fn opens_only_untampered_frames_from_keyed_peers() {
+        let alice = Keyring::new(Id(1)).with_key(Id(2), "secret");
+        let bob = Keyring::new(Id(2)).with_key(Id(1), "secret");
+        let frame = alice.seal(Id(2), b"payload").unwrap();
+        assert_eq!(bob.open(&frame).unwrap(), (Id(1), &b"payload"[..]));
+
+        let mut tampered = frame.clone();
+        tampered[9] ^= 1;
+        assert!(bob.open(&tampered).is_err());
+        assert!(alice.open(&frame).is_err());
+        assert!(Keyring::new(Id(2)).open(&frame).is_err());
+        assert!(alice.seal(Id(3), b"payload").is_err());
+    }
+
+    #[test]
+    fn refuses_replayed_and_stale_frames() {
+        let alice = Keyring::new(Id(1)).with_key(Id(2), "secret");
+        let bob = Keyring::new(Id(2))
+            .with_key(Id(1), "secret")
+            .with_freshness(Duration::from_secs(1));
+        let now = 1_000_000;
+        let first = alice.seal_at(Id(2), b"accept", now).unwrap();
+        let second = alice.seal_at(Id(2), b"accept", now).unwrap();
+        assert_ne!(first, second);
+        assert!(bob.open_at(&first, now).is_ok());
+        let replayed = bob.open_at(&first, now + 10).unwrap_err();
+        assert_eq!(replayed.to_string(), "replayed message");
+        assert!(bob.open_at(&second, now + 10).is_ok());
+
+        let late = alice.seal_at(Id(2), b"accept", now).unwrap();
+        for at in [now + 1_001, now - 1_001] {
+            assert_eq!(
+                bob.open_at(&late, at).unwrap_err().to_string(),
+                "stale message"
+            );
+        }
+        assert!(bob.open_at(&late, now + 1_000).is_ok());
+        assert_eq!(bob.seen.lock().unwrap().len(), 3);
+
+        // What fell out of the window is forgotten, as it is refused anyway.
+        let later = alice.seal_at(Id(2), b"accept", now + 1_001).unwrap();
+        assert!(bob.open_at(&later, now + 1_001).is_ok());
+        assert_eq!(bob.seen.lock().unwrap().len(), 1);
+        assert!(bob.open_at(&first, now + 1_001).is_err());
+        assert!(bob.open(&alice.seal(Id(2), b"accept").unwrap()).is_ok());
+    }
+
+    #[test]
+    fn still_opens_what_the_replaced_keys_sealed_until_the_next_rotation() {
+        let alice = Keyring::new(Id(1)).with_key(Id(2), "old");
+        let bob = Keyring::new(Id(2)).with_key(Id(1), "old");
+        let before = bob.seal(Id(1), b"payload").unwrap();
+
+        alice.rotate(&Keyring::new(Id(1)).with_role(Id(2), "new", Permissions::READ));
+        assert_eq!(alice.role(Id(2)), Some(Permissions::READ));
+        assert_eq!(alice.open(&before).unwrap(), (Id(2), &b"payload"[..]));
+        // Bob has not rotated, so refuses what Alice seals with the new key.
+        let after = alice.seal(Id(2), b"payload").unwrap();
+        assert!(bob.open(&after).is_err());
+        bob.rotate(&Keyring::new(Id(2)).with_key(Id(1), "new"));
+        assert_eq!(bob.open(&after).unwrap(), (Id(1), &b"payload"[..]));
+
+        alice.rotate(&Keyring::new(Id(1)).with_key(Id(2), "newer"));
+        assert!(alice.open(&before).is_err());
+        assert!(alice.open(&bob.seal(Id(1), b"payload").unwrap()).is_ok());
+    }
+}
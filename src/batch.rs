@@ -0,0 +1,394 @@
+use crate::alpha::Error;
+use crate::budget::{Accounted, MemoryBudget, Reservation};
+use crate::log::{LogIndex, ReplicatedLog, SlotPeers};
+use crate::proposer::FailureDetector;
+use crate::time::{Clock, SystemClock};
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::channel::oneshot;
+use futures::future::{select, Either};
+use futures::StreamExt;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const DEFAULT_MAX_BATCH_SIZE: usize = 64;
+
+#[derive(Copy, Clone, Debug, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+}
+
+pub struct BatchingProposer<V, S, D> {
+    log: ReplicatedLog<Vec<V>, S, D>,
+    max_batch_size: usize,
+    window: Duration,
+    clock: Arc<dyn Clock>,
+    depths: Arc<[AtomicUsize; 3]>,
+    budget: Option<Accounted<V>>,
+    sender: UnboundedSender<Submission<V>>,
+    receiver: UnboundedReceiver<Submission<V>>,
+}
+
+pub struct BatchHandle<V> {
+    sender: UnboundedSender<Submission<V>>,
+    depths: Arc<[AtomicUsize; 3]>,
+    budget: Option<Accounted<V>>,
+}
+
+struct Submission<V> {
+    value: V,
+    priority: Priority,
+    reply: oneshot::Sender<Result<(LogIndex, V), Error>>,
+    /// Held until the reply is sent, covering the value both while it is
+    /// queued and while its response waits.
+    reservation: Option<Reservation>,
+}
+
+impl<V> Clone for BatchHandle<V> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            depths: self.depths.clone(),
+            budget: self.budget.clone(),
+        }
+    }
+}
+
+impl<V> BatchHandle<V> {
+    pub async fn submit(&self, value: V) -> Result<(LogIndex, V), Error> {
+        self.submit_with_priority(Priority::default(), value).await
+    }
+
+    pub async fn submit_with_priority(
+        &self,
+        priority: Priority,
+        value: V,
+    ) -> Result<(LogIndex, V), Error> {
+        let reservation = match &self.budget {
+            Some(budget) => Some(budget.reserve(&value).await),
+            None => None,
+        };
+        let (reply, receiver) = oneshot::channel();
+        let depth = &self.depths[priority as usize];
+        depth.fetch_add(1, Ordering::Relaxed);
+        let submission = Submission {
+            value,
+            priority,
+            reply,
+            reservation,
+        };
+        if self.sender.unbounded_send(submission).is_err() {
+            depth.fetch_sub(1, Ordering::Relaxed);
+            return Err(Error::ProposerStopped);
+        }
+        receiver.await.map_err(|_| Error::ProposerStopped)?
+    }
+
+    pub fn queue_depth(&self, priority: Priority) -> usize {
+        self.depths[priority as usize].load(Ordering::Relaxed)
+    }
+}
+
+// A class that waits this many batches without being served goes first in the
+// next one, so sustained high-priority load cannot starve the lower classes.
+const STARVATION_LIMIT: u32 = 8;
+
+struct Queues<V> {
+    classes: [VecDeque<Submission<V>>; 3],
+    depths: Arc<[AtomicUsize; 3]>,
+    skipped: [u32; 3],
+}
+
+impl<V> Queues<V> {
+    fn push(&mut self, submission: Submission<V>) {
+        self.classes[submission.priority as usize].push_back(submission);
+    }
+
+    fn len(&self) -> usize {
+        self.classes.iter().map(VecDeque::len).sum()
+    }
+
+    fn take(&mut self, max: usize) -> Vec<Submission<V>> {
+        let mut batch = Vec::with_capacity(max.min(self.len()));
+        let (starved, rest): (Vec<_>, Vec<_>) =
+            (0..self.classes.len()).partition(|&class| self.skipped[class] >= STARVATION_LIMIT);
+        for class in starved.into_iter().chain(rest) {
+            let queue = &mut self.classes[class];
+            let count = queue.len().min(max - batch.len());
+            self.depths[class].fetch_sub(count, Ordering::Relaxed);
+            batch.extend(queue.drain(..count));
+            if queue.is_empty() || count > 0 {
+                self.skipped[class] = 0;
+            } else {
+                self.skipped[class] += 1;
+            }
+        }
+        batch
+    }
+}
+
+impl<V, S, D> BatchingProposer<V, S, D>
+where
+    V: Clone + PartialEq,
+    S: SlotPeers<Vec<V>>,
+    D: FailureDetector + Clone,
+{
+    pub fn new(log: ReplicatedLog<Vec<V>, S, D>) -> Self {
+        let (sender, receiver) = unbounded();
+        Self {
+            log,
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            window: Duration::from_millis(1),
+            clock: Arc::new(SystemClock),
+            depths: Arc::default(),
+            budget: None,
+            sender,
+            receiver,
+        }
+    }
+
+    /// Submitters wait while `budget` is exhausted; each value counts for
+    /// `size` bytes from submission until its reply.
+    pub fn memory_budget(mut self, budget: Arc<MemoryBudget>, size: fn(&V) -> usize) -> Self {
+        self.budget = Some(Accounted::new(budget, size));
+        self
+    }
+
+    pub fn max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = max_batch_size.max(1);
+        self
+    }
+
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn handle(&self) -> BatchHandle<V> {
+        BatchHandle {
+            sender: self.sender.clone(),
+            depths: self.depths.clone(),
+            budget: self.budget.clone(),
+        }
+    }
+
+    pub async fn run(self) -> ReplicatedLog<Vec<V>, S, D> {
+        let Self {
+            mut log,
+            max_batch_size,
+            window,
+            clock,
+            depths,
+            sender,
+            mut receiver,
+            ..
+        } = self;
+        drop(sender);
+
+        let mut queues = Queues {
+            classes: Default::default(),
+            depths,
+            skipped: [0; 3],
+        };
+        let mut open = true;
+        loop {
+            if queues.len() == 0 {
+                match receiver.next().await {
+                    Some(submission) => queues.push(submission),
+                    None => break,
+                }
+            }
+            let mut deadline = clock.sleep(window);
+            while open && queues.len() < max_batch_size {
+                match select(receiver.next(), &mut deadline).await {
+                    Either::Left((Some(submission), _)) => queues.push(submission),
+                    Either::Left((None, _)) => open = false,
+                    Either::Right(_) => break,
+                }
+            }
+            while let Ok(Some(submission)) = receiver.try_next() {
+                queues.push(submission);
+            }
+
+            let batch = queues.take(max_batch_size);
+            let (values, replies): (Vec<V>, Vec<_>) = batch
+                .into_iter()
+                .map(|submission| (submission.value, (submission.reply, submission.reservation)))
+                .unzip();
+            match log.append(values.clone()).await {
+                Ok(index) => {
+                    for (value, (reply, _reservation)) in values.into_iter().zip(replies) {
+                        let _ = reply.send(Ok((index, value)));
+                    }
+                }
+                Err(error) => {
+                    let error = Arc::new(error);
+                    for (reply, _reservation) in replies {
+                        let _ = reply.send(Err(Error::BatchFailed(error.clone())));
+                    }
+                }
+            }
+        }
+        log
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "threads")]
+    use crate::alpha::Id;
+    #[cfg(feature = "threads")]
+    use crate::local::{LocalCluster, LocalPeers};
+    #[cfg(feature = "threads")]
+    use crate::time::MockClock;
+    #[cfg(feature = "threads")]
+    use std::future::{self, Future};
+
+    fn submission(value: u64, priority: Priority) -> Submission<u64> {
+        Submission {
+            value,
+            priority,
+            reply: oneshot::channel().0,
+            reservation: None,
+        }
+    }
+
+    #[test]
+    fn sustained_high_priority_load_still_lets_low_through() {
+        let mut queues = Queues {
+            classes: Default::default(),
+            depths: Arc::default(),
+            skipped: [0; 3],
+        };
+        queues.push(submission(0, Priority::Low));
+        let mut served = None;
+        for batch in 1..=2 * STARVATION_LIMIT {
+            queues.push(submission(1, Priority::High));
+            queues.push(submission(1, Priority::High));
+            let taken = queues.take(2);
+            if taken
+                .iter()
+                .any(|submission| submission.priority == Priority::Low)
+            {
+                served = Some(batch);
+                break;
+            }
+            assert!(taken
+                .iter()
+                .all(|submission| submission.priority == Priority::High));
+        }
+        assert_eq!(served, Some(STARVATION_LIMIT + 1));
+        assert_eq!(queues.len(), 1);
+    }
+
+    #[cfg(feature = "threads")]
+    struct Slots(LocalCluster<Vec<u64>>, LocalCluster<Vec<u64>>);
+
+    #[cfg(feature = "threads")]
+    impl SlotPeers<Vec<u64>> for &Slots {
+        type Peers = LocalPeers<Vec<u64>>;
+
+        fn slot(&self, index: LogIndex) -> Self::Peers {
+            match index.get() {
+                0 => self.0.peers(),
+                _ => self.1.peers(),
+            }
+        }
+    }
+
+    #[cfg(feature = "threads")]
+    #[derive(Clone)]
+    struct Leader;
+
+    #[cfg(feature = "threads")]
+    impl FailureDetector for Leader {
+        fn leader(&self) -> Id {
+            Id(0)
+        }
+
+        fn changed(&self) -> impl Future<Output = ()> {
+            future::pending()
+        }
+    }
+
+    #[cfg(feature = "threads")]
+    #[test]
+    fn batches_what_is_queued_highest_priority_first() {
+        use futures::executor::block_on;
+        use futures::future::{join, join_all};
+
+        // A zero window never waits, so each batch is whatever is already
+        // queued, up to `max_batch_size`.
+        let slots = Slots(LocalCluster::new(3), LocalCluster::new(3));
+        let proposer = BatchingProposer::new(ReplicatedLog::new(Id(0), &slots, Leader))
+            .max_batch_size(3)
+            .window(Duration::ZERO)
+            .clock(Arc::new(MockClock::new()));
+        let handle = proposer.handle();
+        let submissions = join_all(
+            [
+                (Priority::Low, 1),
+                (Priority::Normal, 2),
+                (Priority::High, 3),
+                (Priority::Normal, 4),
+            ]
+            .map(|(priority, value)| {
+                let handle = handle.clone();
+                async move { handle.submit_with_priority(priority, value).await }
+            }),
+        );
+        let submitted = async move {
+            let results = submissions.await;
+            let depths = Priority::ALL.map(|priority| handle.queue_depth(priority));
+            (results, depths)
+        };
+
+        let (log, (results, depths)) = block_on(join(proposer.run(), submitted));
+        let indices: Vec<_> = results
+            .into_iter()
+            .map(|result| result.unwrap().0.get())
+            .collect();
+        assert_eq!(indices, [1, 0, 0, 0]);
+        assert_eq!(log.read(LogIndex::new(0)), Some(&vec![3, 2, 4]));
+        assert_eq!(log.read(LogIndex::new(1)), Some(&vec![1]));
+        assert_eq!(depths, [0; 3]);
+    }
+
+    #[cfg(feature = "threads")]
+    #[test]
+    fn submitters_wait_while_the_budget_is_spent() {
+        use futures::FutureExt;
+
+        let budget = MemoryBudget::new(8);
+        let slots = Slots(LocalCluster::new(3), LocalCluster::new(3));
+        let proposer = BatchingProposer::new(ReplicatedLog::new(Id(0), &slots, Leader))
+            .memory_budget(budget.clone(), |_| 8);
+        let handle = proposer.handle();
+        let held = budget.charge(1);
+        let mut submitted = Box::pin(handle.submit(1));
+        assert!((&mut submitted).now_or_never().is_none());
+        assert_eq!(handle.queue_depth(Priority::Normal), 0);
+
+        drop(held);
+        assert!((&mut submitted).now_or_never().is_none());
+        assert_eq!(handle.queue_depth(Priority::Normal), 1);
+        assert_eq!(budget.used(), 8);
+        drop(submitted);
+        drop(proposer);
+        assert_eq!(budget.used(), 0);
+    }
+}
@@ -0,0 +1,360 @@
+use futures::executor::block_on;
+use futures::{Stream, StreamExt};
+use paxos_classic::alpha::{Error, Id};
+#[cfg(feature = "auth")]
+use paxos_classic::auth::Keyring;
+use paxos_classic::bytes::BytesValue;
+use paxos_classic::config::NodeConfig;
+use paxos_classic::dashboard::Dashboard;
+use paxos_classic::membership::Membership;
+use paxos_classic::transport::tcp::{Features, NodeStatus, Proposed, TcpPeers};
+use std::env;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::process::ExitCode;
+#[cfg(feature = "auth")]
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const USAGE: &str =
+    "usage: paxos-admin (--config <node.toml> | --peers <addr>[,<addr>...]) <command>
+
+commands:
+  status          dump acceptor state, leader, commit index and membership
+  leader          show which node each member considers the leader
+  transfer <id>   ask every member to prefer <id> as leader
+  snapshot        ask every member to take a snapshot
+  backup <dir>    have every member copy its state into <dir>/node-<id> on its own host
+  log-filter <filter>
+                  have every member log what <filter> lets through, e.g. info,paxos_classic::node=debug
+  reload-credentials
+                  have every member reread its keys and client tokens, as SIGHUP does
+  add <id> <addr> have the leader add <id>, listening at <addr>, as a voter once it has caught up
+  remove <id>     have the leader remove <id> as a voter, unless that leaves no live quorum
+  join-token <id> [<secs>]
+                  have the leader issue a token <id> can join with once in the next <secs>
+                  (default 600), given to it as `toy-paxos --join <token>`
+  watch [<secs>]  redraw leader, commit index, latencies and elections every <secs> (default 1)";
+
+enum Command {
+    Status,
+    Leader,
+    Transfer(Id),
+    Snapshot,
+    Backup(String),
+    LogFilter(String),
+    ReloadCredentials,
+    Add(Id, SocketAddr),
+    Remove(Id),
+    JoinToken(Id, Duration),
+    Watch(Duration),
+}
+
+struct Args {
+    members: Vec<(Option<Id>, SocketAddr)>,
+    command: Command,
+    #[cfg(feature = "auth")]
+    keyring: Option<Keyring>,
+}
+
+fn main() -> ExitCode {
+    let args = match parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{message}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match args.command {
+        Command::Watch(interval) => return watch(&args, interval),
+        Command::Add(..) | Command::Remove(_) | Command::JoinToken(..) => {
+            return change_membership(&args)
+        }
+        _ => {}
+    }
+    let mut failed = false;
+    for (id, addr) in &args.members {
+        let peers = connect(&args, *id, *addr);
+        let outcome = match args.command {
+            Command::Status => first(peers.status()).map(|status| describe(&status)),
+            Command::Leader => first(peers.status()).map(|status| match status.leader {
+                Some(leader) => format!("leader={}", leader.get()),
+                None => "leader=unknown".to_string(),
+            }),
+            Command::Transfer(to) => {
+                first(peers.transfer_leadership(to)).map(|()| format!("prefers {}", to.get()))
+            }
+            Command::Snapshot => {
+                let peers = peers.with_features(Features::SNAPSHOTS);
+                block_on(peers.handshake())
+                    .and_then(|_| first(peers.snapshot()))
+                    .map(|()| "snapshot taken".to_string())
+            }
+            Command::Backup(ref dir) => {
+                let to = match id {
+                    Some(id) => format!("{dir}/node-{}", id.get()),
+                    None => format!("{dir}/{}", addr.port()),
+                };
+                first(peers.backup(&to)).map(|()| format!("backed up to {to}"))
+            }
+            Command::LogFilter(ref filter) => {
+                first(peers.set_log_filter(filter)).map(|()| format!("logging {filter}"))
+            }
+            Command::ReloadCredentials => {
+                first(peers.reload_credentials()).map(|()| "reloaded credentials".to_string())
+            }
+            Command::Add(..) | Command::Remove(_) | Command::JoinToken(..) | Command::Watch(_) => {
+                unreachable!("handled above")
+            }
+        };
+        match outcome {
+            Ok(line) => println!("{addr}\t{line}"),
+            Err(error) => {
+                failed = true;
+                println!("{addr}\terror: {}", report(&error));
+            }
+        }
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut members = Vec::new();
+    let mut command = None;
+    #[cfg(feature = "auth")]
+    let (mut id, mut key) = (None, None);
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| args.next().ok_or(format!("{flag} needs a value"));
+        match arg.as_str() {
+            "--config" => {
+                let config = NodeConfig::load(value("--config")?)
+                    .map_err(|error| format!("invalid config: {error}"))?;
+                members.extend(config.members.iter().map(|(id, addr)| (Some(*id), *addr)));
+            }
+            "--peers" => {
+                for addr in value("--peers")?.split(',') {
+                    let addr = addr
+                        .parse()
+                        .map_err(|_| format!("invalid address `{addr}`"))?;
+                    members.push((None, addr));
+                }
+            }
+            #[cfg(feature = "auth")]
+            "--id" => id = Some(Id::new(parse_id(&value("--id")?)?)),
+            #[cfg(feature = "auth")]
+            "--key" => key = Some(value("--key")?),
+            "status" => command = Some(Command::Status),
+            "leader" => command = Some(Command::Leader),
+            "transfer" => {
+                command = Some(Command::Transfer(Id::new(parse_id(&value("transfer")?)?)))
+            }
+            "snapshot" => command = Some(Command::Snapshot),
+            "backup" => command = Some(Command::Backup(value("backup")?)),
+            "log-filter" => command = Some(Command::LogFilter(value("log-filter")?)),
+            "reload-credentials" => command = Some(Command::ReloadCredentials),
+            "add" => {
+                let id = Id::new(parse_id(&value("add")?)?);
+                let addr = value("add")?;
+                let addr = addr
+                    .parse()
+                    .map_err(|_| format!("invalid address `{addr}`"))?;
+                command = Some(Command::Add(id, addr))
+            }
+            "remove" => command = Some(Command::Remove(Id::new(parse_id(&value("remove")?)?))),
+            "join-token" => {
+                let id = Id::new(parse_id(&value("join-token")?)?);
+                command = Some(Command::JoinToken(id, Duration::from_secs(600)))
+            }
+            "watch" => command = Some(Command::Watch(Duration::from_secs(1))),
+            secs if matches!(command, Some(Command::JoinToken(..))) => {
+                let secs = secs
+                    .parse()
+                    .ok()
+                    .filter(|secs| *secs > 0)
+                    .ok_or(format!("invalid lifetime `{secs}`"))?;
+                if let Some(Command::JoinToken(_, ttl)) = &mut command {
+                    *ttl = Duration::from_secs(secs);
+                }
+            }
+            secs if matches!(command, Some(Command::Watch(_))) => {
+                let secs = secs
+                    .parse()
+                    .ok()
+                    .filter(|secs| *secs > 0)
+                    .ok_or(format!("invalid interval `{secs}`"))?;
+                command = Some(Command::Watch(Duration::from_secs(secs)))
+            }
+            other => return Err(format!("unexpected argument `{other}`")),
+        }
+    }
+    if members.is_empty() {
+        return Err("no members given".to_string());
+    }
+    #[cfg(feature = "auth")]
+    let keyring = match (id, key) {
+        (Some(id), Some(key)) => {
+            if members.iter().any(|(member, _)| member.is_none()) {
+                return Err("authenticated requests need member ids from --config".to_string());
+            }
+            Some(
+                members
+                    .iter()
+                    .filter_map(|(member, _)| *member)
+                    .fold(Keyring::new(id), |keyring, member| {
+                        keyring.with_key(member, key.as_bytes())
+                    }),
+            )
+        }
+        (None, None) => None,
+        _ => return Err("--id and --key must be given together".to_string()),
+    };
+    Ok(Args {
+        members,
+        command: command.ok_or("no command given")?,
+        #[cfg(feature = "auth")]
+        keyring,
+    })
+}
+
+/// Sends the change to each member in turn until the leader makes it.
+fn change_membership(args: &Args) -> ExitCode {
+    let described = |membership: Membership| format!("voters=[{}]", voters(&membership));
+    for (id, addr) in &args.members {
+        let peers = connect(args, *id, *addr);
+        let changed = match args.command {
+            Command::Add(id, addr) => {
+                first(peers.add_member(id, addr)).map(|changed| decided(changed, described))
+            }
+            Command::Remove(id) => {
+                first(peers.remove_member(id)).map(|changed| decided(changed, described))
+            }
+            Command::JoinToken(id, ttl) => first(peers.issue_join_token(id, ttl))
+                .map(|issued| decided(issued, |token| format!("join token {token}"))),
+            _ => unreachable!("only membership changes"),
+        };
+        match changed {
+            Ok(Proposed::Decided(line)) => {
+                println!("{addr}	{line}");
+                return ExitCode::SUCCESS;
+            }
+            Ok(Proposed::NotLeader(leader)) => println!(
+                "{addr}	not the leader; leader={}",
+                leader.map_or_else(|| "unknown".to_string(), |leader| leader.get().to_string())
+            ),
+            Ok(Proposed::Throttled(retry_after)) => {
+                println!("{addr}	throttled; retry after {retry_after:?}")
+            }
+            Err(error) => println!("{addr}	error: {}", report(&error)),
+        }
+    }
+    ExitCode::FAILURE
+}
+
+fn decided<T>(proposed: Proposed<T>, describe: impl FnOnce(T) -> String) -> Proposed<String> {
+    match proposed {
+        Proposed::Decided(decided) => Proposed::Decided(describe(decided)),
+        Proposed::NotLeader(leader) => Proposed::NotLeader(leader),
+        Proposed::Throttled(retry_after) => Proposed::Throttled(retry_after),
+    }
+}
+
+fn voters(membership: &Membership) -> String {
+    membership
+        .voters()
+        .iter()
+        .map(|id| id.get().to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Asks every member for its status each `interval` and redraws the
+/// dashboard until interrupted.
+fn watch(args: &Args, interval: Duration) -> ExitCode {
+    let peers: Vec<_> = args
+        .members
+        .iter()
+        .map(|(id, addr)| (*addr, connect(args, *id, *addr)))
+        .collect();
+    let mut dashboard = Dashboard::new(peers.iter().map(|(addr, _)| *addr));
+    let mut stdout = io::stdout();
+    loop {
+        let round = Instant::now();
+        for (addr, peers) in &peers {
+            let sent = Instant::now();
+            let status = first(peers.status()).map_err(|error| report(&error));
+            dashboard.observe(*addr, status, sent.elapsed());
+        }
+        let now = Instant::now();
+        dashboard.settle(now);
+        // Clears the screen and moves to the top left before redrawing.
+        let drawn =
+            write!(stdout, "\x1b[2J\x1b[H{}", dashboard.render(now)).and_then(|()| stdout.flush());
+        if drawn.is_err() {
+            return ExitCode::FAILURE;
+        }
+        thread::sleep(interval.saturating_sub(round.elapsed()));
+    }
+}
+
+fn parse_id(id: &str) -> Result<u64, String> {
+    id.parse().map_err(|_| format!("invalid node id `{id}`"))
+}
+
+#[cfg(not(feature = "auth"))]
+fn connect(_args: &Args, _id: Option<Id>, addr: SocketAddr) -> TcpPeers<BytesValue> {
+    TcpPeers::new(vec![addr])
+}
+
+#[cfg(feature = "auth")]
+fn connect(args: &Args, id: Option<Id>, addr: SocketAddr) -> TcpPeers<BytesValue> {
+    let peers = TcpPeers::new(vec![addr]);
+    match (&args.keyring, id) {
+        (Some(keyring), Some(id)) => peers.with_auth(Arc::new(keyring.clone()), vec![id]),
+        _ => peers,
+    }
+}
+
+fn first<T>(responses: impl Stream<Item = Result<T, Error>>) -> Result<T, Error> {
+    let mut responses = Box::pin(responses);
+    block_on(responses.next()).expect("every member responds once")
+}
+
+fn describe(status: &NodeStatus) -> String {
+    let ids = |ids: &[Id]| {
+        ids.iter()
+            .map(|id| id.get().to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    format!(
+        "id={} leader={} members=[{}] suspected=[{}] promised={:?} accepted={:?} decided={} commit_index={} epoch={}",
+        status.id.get(),
+        status
+            .leader
+            .map_or_else(|| "unknown".to_string(), |leader| leader.get().to_string()),
+        ids(&status.members),
+        ids(&status.suspected),
+        status.last_round_entered,
+        status.accepted_round,
+        status.decided,
+        status
+            .commit_index
+            .map_or_else(|| "-".to_string(), |index| index.get().to_string()),
+        status.epoch,
+    )
+}
+
+fn report(error: &Error) -> String {
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(error);
+    while let Some(error) = source {
+        message = format!("{message}: {error}");
+        source = error.source();
+    }
+    message
+}
@@ -0,0 +1,277 @@
+use futures::executor::block_on;
+use paxos_classic::alpha::{Error, Id};
+#[cfg(feature = "auth")]
+use paxos_classic::auth::Keyring;
+use paxos_classic::bytes::BytesValue;
+use paxos_classic::config::{ClusterConfig, NodeConfig};
+use paxos_classic::retry::RetryPolicy;
+use paxos_classic::transport::client::Client;
+use std::env;
+use std::net::SocketAddr;
+use std::process::ExitCode;
+#[cfg(feature = "auth")]
+use std::sync::Arc;
+
+const USAGE: &str = "usage: toy-paxos-cli (--config <node.toml> | --cluster <cluster.toml> | --peers <addr>[,<addr>...]) [--attempts <n>] [--token <token>] <command>
+
+commands:
+  propose <value>   propose <value> through the leader and print the decision
+  get               print the decided value, if any
+  status            print every member's view of the cluster
+  health            print whether each member is alive, recovering, ready or the leader
+  members           list each member's role, epoch and commit index, and the leader
+
+options:
+  --token <token>   present <token> with proposals and reads, for clusters that set
+                    `client_tokens`";
+
+#[cfg(feature = "auth")]
+const AUTH_USAGE: &str = "
+authentication:
+  --id <id> --key <secret>   sign requests as <id> with the cluster's shared <secret>";
+
+const DEFAULT_ATTEMPTS: usize = 5;
+
+enum Command {
+    Propose(BytesValue),
+    Get,
+    Status,
+    Health,
+    Members,
+}
+
+struct Args {
+    members: Vec<(Option<Id>, SocketAddr)>,
+    attempts: usize,
+    token: Option<String>,
+    command: Command,
+    #[cfg(feature = "auth")]
+    keyring: Option<Keyring>,
+}
+
+fn main() -> ExitCode {
+    let args = match parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{message}\n\n{}", usage());
+            return ExitCode::from(2);
+        }
+    };
+    let client = connect(&args);
+    let outcome = match args.command {
+        Command::Propose(value) => propose(client, value),
+        Command::Get => get(&client),
+        Command::Status => status(&client),
+        Command::Health => health(&client),
+        Command::Members => members(&client),
+    };
+    match outcome {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", report(&error));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn propose(mut client: Client<BytesValue>, value: BytesValue) -> Result<(), Error> {
+    let decided = block_on(client.propose(value))?;
+    println!("decided {}", String::from_utf8_lossy(&decided));
+    Ok(())
+}
+
+fn get(client: &Client<BytesValue>) -> Result<(), Error> {
+    match block_on(client.decision())? {
+        Some(decided) => println!("decided {}", String::from_utf8_lossy(&decided)),
+        None => println!("undecided"),
+    }
+    Ok(())
+}
+
+fn status(client: &Client<BytesValue>) -> Result<(), Error> {
+    let mut failed = None;
+    for (addr, status) in block_on(client.status()) {
+        match status {
+            Ok(status) => println!(
+                "{addr}\tid={} leader={} decided={} members={}",
+                status.id.get(),
+                status
+                    .leader
+                    .map_or_else(|| "unknown".to_string(), |leader| leader.get().to_string()),
+                status.decided,
+                status.members.len(),
+            ),
+            Err(error) => {
+                println!("{addr}\terror: {}", report(&error));
+                failed = Some(error);
+            }
+        }
+    }
+    failed.map_or(Ok(()), Err)
+}
+
+fn health(client: &Client<BytesValue>) -> Result<(), Error> {
+    let mut failed = None;
+    for (addr, health) in block_on(client.health()) {
+        match health {
+            Ok(health) => println!("{addr}\t{health}"),
+            Err(error) => {
+                println!("{addr}\terror: {}", report(&error));
+                failed = Some(error);
+            }
+        }
+    }
+    failed.map_or(Ok(()), Err)
+}
+
+/// Lists the members as the first member that answers knows them.
+fn members(client: &Client<BytesValue>) -> Result<(), Error> {
+    for member in block_on(client.topology())? {
+        let mut flags = Vec::new();
+        if member.leader {
+            flags.push("leader");
+        }
+        if !member.reachable {
+            flags.push("unreachable");
+        }
+        println!(
+            "{}\t{}\t{}\tepoch={}\tcommit_index={}\t{}",
+            member.id.get(),
+            member.addr,
+            member.role,
+            member
+                .epoch
+                .map_or_else(|| "-".to_string(), |epoch| epoch.to_string()),
+            member
+                .commit_index
+                .map_or_else(|| "-".to_string(), |index| index.get().to_string()),
+            flags.join(","),
+        );
+    }
+    Ok(())
+}
+
+fn usage() -> String {
+    #[cfg(feature = "auth")]
+    return format!("{USAGE}\n{AUTH_USAGE}");
+    #[cfg(not(feature = "auth"))]
+    USAGE.to_string()
+}
+
+fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut members = Vec::new();
+    let mut attempts = DEFAULT_ATTEMPTS;
+    let mut token = None;
+    let mut command = None;
+    #[cfg(feature = "auth")]
+    let (mut id, mut key) = (None, None);
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| args.next().ok_or(format!("{flag} needs a value"));
+        match arg.as_str() {
+            "--config" => {
+                let config = NodeConfig::load(value("--config")?)
+                    .map_err(|error| format!("invalid config: {error}"))?;
+                members.extend(config.members.iter().map(|(id, addr)| (Some(*id), *addr)));
+            }
+            "--cluster" => {
+                let cluster = ClusterConfig::load(value("--cluster")?)
+                    .map_err(|error| format!("invalid cluster config: {error}"))?;
+                members.extend(
+                    cluster
+                        .members
+                        .iter()
+                        .map(|member| (Some(member.id), member.addr)),
+                );
+            }
+            "--peers" => {
+                for addr in value("--peers")?.split(',') {
+                    let addr = addr
+                        .parse()
+                        .map_err(|_| format!("invalid address `{addr}`"))?;
+                    members.push((None, addr));
+                }
+            }
+            "--attempts" => {
+                let count = value("--attempts")?;
+                attempts = count
+                    .parse()
+                    .ok()
+                    .filter(|attempts| *attempts > 0)
+                    .ok_or(format!("invalid attempt count `{count}`"))?;
+            }
+            "--token" => token = Some(value("--token")?),
+            #[cfg(feature = "auth")]
+            "--id" => id = Some(Id::new(parse_id(&value("--id")?)?)),
+            #[cfg(feature = "auth")]
+            "--key" => key = Some(value("--key")?),
+            "propose" => {
+                let value = value("propose")?;
+                command = Some(Command::Propose(BytesValue::from(value.into_bytes())))
+            }
+            "get" => command = Some(Command::Get),
+            "status" => command = Some(Command::Status),
+            "health" => command = Some(Command::Health),
+            "members" => command = Some(Command::Members),
+            other => return Err(format!("unexpected argument `{other}`")),
+        }
+    }
+    if members.is_empty() {
+        return Err("no members given".to_string());
+    }
+    #[cfg(feature = "auth")]
+    let keyring = match (id, key) {
+        (Some(id), Some(key)) => {
+            if members.iter().any(|(member, _)| member.is_none()) {
+                return Err("authenticated requests need member ids from a config".to_string());
+            }
+            Some(
+                members
+                    .iter()
+                    .filter_map(|(member, _)| *member)
+                    .fold(Keyring::new(id), |keyring, member| {
+                        keyring.with_key(member, key.as_bytes())
+                    }),
+            )
+        }
+        (None, None) => None,
+        _ => return Err("--id and --key must be given together".to_string()),
+    };
+    Ok(Args {
+        members,
+        attempts,
+        token,
+        command: command.ok_or("no command given")?,
+        #[cfg(feature = "auth")]
+        keyring,
+    })
+}
+
+#[cfg(feature = "auth")]
+fn parse_id(id: &str) -> Result<u64, String> {
+    id.parse().map_err(|_| format!("invalid node id `{id}`"))
+}
+
+fn connect(args: &Args) -> Client<BytesValue> {
+    let mut client = Client::new(args.members.iter().copied()).with_retry_policy(RetryPolicy {
+        max_attempts: Some(args.attempts),
+        ..RetryPolicy::default()
+    });
+    if let Some(token) = &args.token {
+        client = client.with_token(token);
+    }
+    #[cfg(feature = "auth")]
+    if let Some(keyring) = &args.keyring {
+        return client.with_auth(Arc::new(keyring.clone()));
+    }
+    client
+}
+
+fn report(error: &Error) -> String {
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(error);
+    while let Some(error) = source {
+        message = format!("{message}: {error}");
+        source = error.source();
+    }
+    message
+}
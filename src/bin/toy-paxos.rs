@@ -0,0 +1,494 @@
+use futures::executor::block_on;
+use futures::StreamExt;
+use paxos_classic::alpha::Id;
+#[cfg(feature = "auth")]
+use paxos_classic::auth::Keyring;
+use paxos_classic::bytes::BytesValue;
+use paxos_classic::config::{
+    ClusterConfig, ConfigError, CredentialSource, Credentials, NodeConfig,
+};
+use paxos_classic::inspect::inspect;
+#[cfg(feature = "tracing")]
+use paxos_classic::log_filter::{self, Filter};
+use paxos_classic::node::Node;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::{env, fmt, thread};
+
+const USAGE: &str =
+    "usage: toy-paxos (--config <node.toml> | --cluster <cluster.toml> --id <id>) [--propose <value>]
+       toy-paxos --init --config <node.toml>
+       toy-paxos inspect <data_dir> [--values utf8|hex]
+
+Starts the node described by the config file and serves until standard input
+is closed. `inspect` prints what a stopped node persisted in its data_dir, or
+storage path, without changing it: the acceptor's promise and accepted value,
+the membership log and snapshots. Values show as text by default.
+
+options:
+  --config <path>     node config: id, listen address, members, storage, timeouts
+  --cluster <path>    cluster config shared by every member; pick one with --id
+  --id <id>           which member of the cluster config to run
+  --propose <value>   propose <value> once the node is up and print the decision
+  --init              create the config's data_dir, or migrate an older one, store
+                      the node id and the config in it, and exit
+  --unsafe-force-recover <id>[,<id>...]
+                      before starting, rewrite the membership to these surviving
+                      members and enter a new epoch; only when the rest is lost for
+                      good, as values they decided may be forgotten
+  --restore <backup>  before starting, rebuild the node's empty data_dir or storage
+                      from a backup taken with `paxos-admin backup`, entering the
+                      epoch after the one it was taken in
+  --join <token>      join the cluster of the config's other members as a voter,
+                      with a token from `paxos-admin join-token`
+
+SIGHUP, or `paxos-admin reload-credentials`, rereads the config's `client_tokens`
+and `roles` and, with --key-file, the shared secret, without a restart.";
+
+#[cfg(feature = "auth")]
+const AUTH_USAGE: &str = "  --key <secret>      authenticate every member with the shared <secret>
+  --key-file <path>   read the shared <secret> from <path>, rereading it on reload
+  --operator <id>     let <id>, keyed with the same <secret>, send admin requests;
+                      the config's `roles` key others with it for what they name";
+
+#[cfg(feature = "tracing")]
+const TRACING_USAGE: &str = "  --log-filter <filter>
+                      log what <filter> lets through, e.g. info,paxos_classic::node=debug,
+                      instead of the config's `log_filter`; `paxos-admin log-filter`
+                      changes it while the node runs, and SIGHUP rereads the config's";
+
+/// Reads the node's config file again.
+type Reread = Arc<dyn Fn() -> Result<NodeConfig, ConfigError> + Send + Sync>;
+
+struct Args {
+    config: NodeConfig,
+    init: bool,
+    propose: Option<BytesValue>,
+    recover: Option<Vec<Id>>,
+    restore: Option<PathBuf>,
+    #[cfg(feature = "tracing")]
+    reread: Reread,
+}
+
+#[cfg(feature = "auth")]
+#[derive(Clone, Debug)]
+enum Key {
+    Secret(String),
+    File(PathBuf),
+}
+
+/// What the node reloads its credentials from: its config and, when the
+/// secret came from a file, that file.
+struct Rereads {
+    reread: Reread,
+    #[cfg(feature = "auth")]
+    key: Option<Key>,
+    #[cfg(feature = "auth")]
+    operators: Vec<Id>,
+}
+
+impl fmt::Debug for Rereads {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Rereads")
+    }
+}
+
+impl CredentialSource for Rereads {
+    fn load(&self) -> Result<Credentials, ConfigError> {
+        let config = (self.reread)()?;
+        Ok(Credentials {
+            #[cfg(feature = "auth")]
+            keyring: match &self.key {
+                Some(key) => Some(keyring(&config, &key.secret()?, &self.operators)),
+                None => None,
+            },
+            client_tokens: config.client_tokens,
+        })
+    }
+}
+
+#[cfg(feature = "auth")]
+impl Key {
+    fn secret(&self) -> io::Result<String> {
+        match self {
+            Key::Secret(secret) => Ok(secret.clone()),
+            Key::File(path) => Ok(std::fs::read_to_string(path)?.trim_end().to_string()),
+        }
+    }
+}
+
+/// Keys every member, operator and identity with a role with `key`.
+#[cfg(feature = "auth")]
+fn keyring(config: &NodeConfig, key: &str, operators: &[Id]) -> Keyring {
+    let keyring = config
+        .members
+        .iter()
+        .fold(Keyring::new(config.id), |keyring, (member, _)| {
+            keyring.with_key(*member, key.as_bytes())
+        });
+    let keyring = operators.iter().fold(keyring, |keyring, operator| {
+        keyring.with_operator(*operator, key.as_bytes())
+    });
+    config
+        .roles
+        .iter()
+        .fold(keyring, |keyring, (id, permissions)| {
+            keyring.with_role(*id, key.as_bytes(), *permissions)
+        })
+}
+
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("inspect") {
+        return run_inspect(args.skip(1));
+    }
+    let args = match parse(args) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{message}\n\n{}", usage());
+            return ExitCode::from(2);
+        }
+    };
+    #[cfg(feature = "tracing")]
+    {
+        let filter = args.config.log_filter.clone().unwrap_or_default();
+        if let Err(error) = log_filter::install(filter) {
+            eprintln!("not logging: {error}");
+        }
+    }
+    if args.init {
+        println!(
+            "initialised {} for node {}",
+            args.config
+                .data_dir
+                .as_ref()
+                .map_or_else(String::new, |dir| dir.display().to_string()),
+            args.config.id.get()
+        );
+        return ExitCode::SUCCESS;
+    }
+    if let Some(backup) = &args.restore {
+        match Node::<BytesValue>::restore(&args.config, backup) {
+            Ok(epoch) => println!("restored {}: now in epoch {epoch}", backup.display()),
+            Err(error) => {
+                eprintln!("failed to restore: {error}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    if let Some(survivors) = &args.recover {
+        let members = args
+            .config
+            .members
+            .iter()
+            .filter(|(id, _)| survivors.contains(id))
+            .copied()
+            .collect();
+        match Node::<BytesValue>::force_recover(&args.config, members) {
+            Ok(epoch) => println!("forced recovery: now in epoch {epoch}"),
+            Err(error) => {
+                eprintln!("failed to force recovery: {error}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let started = match args.config.join_token {
+        Some(_) => {
+            let existing = args
+                .config
+                .members
+                .iter()
+                .filter(|(id, _)| *id != args.config.id)
+                .copied()
+                .collect();
+            block_on(Node::<BytesValue>::join(args.config, existing))
+        }
+        None => Node::<BytesValue>::start(args.config),
+    };
+    let node = match started {
+        Ok(node) => node,
+        Err(error) => {
+            eprintln!("failed to start node: {error}");
+            return ExitCode::FAILURE;
+        }
+    };
+    println!(
+        "node {} listening on {}",
+        node.id().get(),
+        node.local_addr()
+    );
+    #[cfg(unix)]
+    {
+        let node = Arc::downgrade(&node);
+        #[cfg(feature = "tracing")]
+        let reread = args.reread;
+        hangup::watch(Box::new(move || {
+            #[cfg(feature = "tracing")]
+            match reread()
+                .map_err(|error| error.to_string())
+                .and_then(|config| {
+                    let filter = config.log_filter.unwrap_or_default().to_string();
+                    log_filter::set(&filter)
+                        .map(|()| filter)
+                        .map_err(|error| error.to_string())
+                }) {
+                Ok(filter) => eprintln!("logging {filter}"),
+                Err(error) => eprintln!("kept the log filter: {error}"),
+            }
+            if let Some(node) = node.upgrade() {
+                match node.reload_credentials() {
+                    Ok(()) => eprintln!("reloaded credentials"),
+                    Err(error) => eprintln!("kept the credentials: {error}"),
+                }
+            }
+        }));
+    }
+
+    let changes = node.leadership_changes();
+    thread::spawn(move || {
+        block_on(changes.for_each(|leader| {
+            println!("leader is now {}", leader.get());
+            async {}
+        }))
+    });
+
+    let mut failed = false;
+    if let Some(value) = args.propose {
+        match block_on(node.propose(value)) {
+            Ok(decided) => println!("decided {}", String::from_utf8_lossy(&decided)),
+            Err(error) => {
+                failed = true;
+                eprintln!("proposal failed: {error}");
+            }
+        }
+    } else if let Some(decided) = node.decision() {
+        println!("decided {}", String::from_utf8_lossy(&decided));
+    }
+
+    let _ = io::stdin().read_to_end(&mut Vec::new());
+    if let Err(error) = block_on(node.shutdown()) {
+        eprintln!("shutdown failed: {error}");
+        failed = true;
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn run_inspect(mut args: impl Iterator<Item = String>) -> ExitCode {
+    let (dir, hex) = match (args.next(), args.next().as_deref(), args.next()) {
+        (Some(dir), None, None) => (dir, false),
+        (Some(dir), Some("--values"), Some(codec)) if codec == "utf8" || codec == "hex" => {
+            (dir, codec == "hex")
+        }
+        _ => {
+            eprintln!(
+                "inspect needs a data_dir and at most --values utf8|hex\n\n{}",
+                usage()
+            );
+            return ExitCode::from(2);
+        }
+    };
+    match inspect::<BytesValue>(dir.as_ref()) {
+        Ok(inspection) => {
+            print!(
+                "{}",
+                inspection.render(|value| match hex {
+                    true => value.iter().map(|byte| format!("{byte:02x}")).collect(),
+                    false => format!("{:?}", String::from_utf8_lossy(value)),
+                })
+            );
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("failed to inspect {dir}: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn usage() -> String {
+    #[cfg_attr(not(any(feature = "auth", feature = "tracing")), allow(unused_mut))]
+    let mut usage = USAGE.to_string();
+    #[cfg(feature = "auth")]
+    usage.push_str(&format!("\n{AUTH_USAGE}"));
+    #[cfg(feature = "tracing")]
+    usage.push_str(&format!("\n{TRACING_USAGE}"));
+    usage
+}
+
+/// Rereads the config's `log_filter` and credentials whenever the process
+/// gets SIGHUP.
+#[cfg(unix)]
+mod hangup {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    const SIGHUP: i32 = 1;
+
+    static HUNG_UP: AtomicBool = AtomicBool::new(false);
+
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    }
+
+    extern "C" fn on_hangup(_: i32) {
+        HUNG_UP.store(true, Ordering::SeqCst);
+    }
+
+    pub(super) fn watch(reload: Box<dyn Fn() + Send>) {
+        // SAFETY: the handler only stores to an atomic, which is
+        // async-signal-safe.
+        unsafe {
+            signal(SIGHUP, on_hangup);
+        }
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_millis(200));
+            if !HUNG_UP.swap(false, Ordering::SeqCst) {
+                continue;
+            }
+            reload();
+        });
+    }
+}
+
+fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut config = None;
+    let mut init = false;
+    let mut cluster = None;
+    let mut id = None;
+    let mut propose = None;
+    let mut recover = None;
+    let mut restore = None;
+    let mut join_token = None;
+    #[cfg(feature = "tracing")]
+    let mut filter = None;
+    #[cfg(feature = "auth")]
+    let mut key: Option<Key> = None;
+    #[cfg(feature = "auth")]
+    let mut operators = Vec::new();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| args.next().ok_or(format!("{flag} needs a value"));
+        match arg.as_str() {
+            "--config" => config = Some(value("--config")?),
+            "--init" => init = true,
+            "--cluster" => {
+                let path = value("--cluster")?;
+                let config = ClusterConfig::load(&path)
+                    .map_err(|error| format!("invalid cluster config: {error}"))?;
+                cluster = Some((path, config));
+            }
+            "--id" => id = Some(Id::new(parse_id(&value("--id")?)?)),
+            "--propose" => propose = Some(BytesValue::from(value("--propose")?.into_bytes())),
+            "--unsafe-force-recover" => {
+                recover = Some(
+                    value("--unsafe-force-recover")?
+                        .split(',')
+                        .map(|id| parse_id(id).map(Id::new))
+                        .collect::<Result<Vec<_>, _>>()?,
+                )
+            }
+            "--restore" => restore = Some(PathBuf::from(value("--restore")?)),
+            "--join" => join_token = Some(value("--join")?),
+            #[cfg(feature = "auth")]
+            "--key" | "--key-file" if key.is_some() => {
+                return Err("give either --key or --key-file".to_string())
+            }
+            #[cfg(feature = "auth")]
+            "--key" => key = Some(Key::Secret(value("--key")?)),
+            #[cfg(feature = "auth")]
+            "--key-file" => key = Some(Key::File(PathBuf::from(value("--key-file")?))),
+            #[cfg(feature = "tracing")]
+            "--log-filter" => {
+                filter = Some(
+                    value("--log-filter")?
+                        .parse::<Filter>()
+                        .map_err(|error| error.to_string())?,
+                )
+            }
+            #[cfg(feature = "auth")]
+            "--operator" => operators.push(Id::new(parse_id(&value("--operator")?)?)),
+            other => return Err(format!("unexpected argument `{other}`")),
+        }
+    }
+    let reread: Reread = match (config.clone(), &cluster, id) {
+        (Some(path), _, _) => Arc::new(move || NodeConfig::load(&path)),
+        (None, Some((path, _)), Some(id)) => {
+            let path = path.clone();
+            Arc::new(move || ClusterConfig::load(&path)?.node(id))
+        }
+        _ => Arc::new(|| Err(ConfigError::Missing("config".to_string()))),
+    };
+    let config = match config {
+        Some(path) if init => {
+            Some(NodeConfig::init(path).map_err(|error| format!("failed to initialise: {error}"))?)
+        }
+        Some(path) => {
+            Some(NodeConfig::load(path).map_err(|error| format!("invalid config: {error}"))?)
+        }
+        None if init => return Err("--init needs --config".to_string()),
+        None => None,
+    };
+    let mut config = match (config, cluster, id) {
+        (Some(config), None, None) => config,
+        (None, Some((_, cluster)), Some(id)) => cluster
+            .node(id)
+            .map_err(|error| format!("invalid cluster config: {error}"))?,
+        (None, Some(_), None) => return Err("--cluster needs --id".to_string()),
+        (None, None, _) => return Err("no config given".to_string()),
+        _ => return Err("give either --config or --cluster with --id".to_string()),
+    };
+    #[cfg(feature = "auth")]
+    match &key {
+        Some(key) => {
+            let secret = key
+                .secret()
+                .map_err(|error| format!("failed to read the key: {error}"))?;
+            config.keyring = Some(Arc::new(keyring(&config, &secret, &operators)));
+        }
+        None if !operators.is_empty() => return Err("--operator needs --key".to_string()),
+        None if !config.roles.is_empty() => return Err("`roles` need --key".to_string()),
+        None => {}
+    }
+    #[cfg(not(feature = "auth"))]
+    if !config.roles.is_empty() {
+        return Err("`roles` need a build with the `auth` feature".to_string());
+    }
+    #[cfg(feature = "tracing")]
+    if filter.is_some() {
+        config.log_filter = filter;
+    }
+    config.join_token = join_token;
+    if let Some(survivor) = recover
+        .iter()
+        .flatten()
+        .find(|id| !config.members.iter().any(|(member, _)| member == *id))
+    {
+        return Err(format!("member {} is not in the config", survivor.get()));
+    }
+    config.credentials = Some(Arc::new(Rereads {
+        reread: reread.clone(),
+        #[cfg(feature = "auth")]
+        key,
+        #[cfg(feature = "auth")]
+        operators,
+    }));
+    Ok(Args {
+        config,
+        init,
+        propose,
+        recover,
+        restore,
+        #[cfg(feature = "tracing")]
+        reread,
+    })
+}
+
+fn parse_id(id: &str) -> Result<u64, String> {
+    id.parse().map_err(|_| format!("invalid node id `{id}`"))
+}
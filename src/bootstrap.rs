@@ -0,0 +1,156 @@
+use crate::alpha::Id;
+use crate::codec::{from_bytes, to_bytes};
+use crate::digest::sha256;
+use crate::storage::RecordFile;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// The members a cluster was first formed from. Every one of them persists
+/// it before the cluster serves, and a member never adopts a second one, so
+/// two bootstraps that share a member cannot both complete.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bootstrap {
+    members: Vec<(Id, SocketAddr)>,
+}
+
+/// Names the cluster a [`Bootstrap`] formed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ClusterId(pub u64);
+
+impl fmt::Display for ClusterId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum BootstrapError {
+    #[error("already part of cluster {ours}, not {theirs}")]
+    Conflict { ours: ClusterId, theirs: ClusterId },
+    #[error("member {member:?} already belongs to cluster {cluster}")]
+    Claimed { member: Id, cluster: ClusterId },
+    #[error("no member of the existing cluster has been bootstrapped")]
+    NotBootstrapped,
+    #[error("members disagree on which cluster they belong to")]
+    Split,
+    #[error("failed to persist the bootstrap")]
+    Io(#[from] io::Error),
+}
+
+impl Bootstrap {
+    /// The order `members` are given in does not matter.
+    pub fn new(members: impl IntoIterator<Item = (Id, SocketAddr)>) -> Self {
+        let mut members: Vec<_> = members.into_iter().collect();
+        members.sort();
+        members.dedup();
+        Self { members }
+    }
+
+    pub fn members(&self) -> &[(Id, SocketAddr)] {
+        &self.members
+    }
+
+    pub fn cluster(&self) -> ClusterId {
+        let digest = sha256(&to_bytes(self));
+        ClusterId(u64::from_be_bytes(digest[..8].try_into().unwrap()))
+    }
+}
+
+/// Where a member keeps the [`Bootstrap`] it adopted: next to its acceptor
+/// state, or only in memory for nodes that keep no state.
+#[cfg_attr(not(feature = "threads"), allow(dead_code))]
+pub(crate) struct BootstrapFile {
+    file: Option<RecordFile>,
+    adopted: Option<Bootstrap>,
+}
+
+#[cfg_attr(not(feature = "threads"), allow(dead_code))]
+impl BootstrapFile {
+    pub(crate) fn open(path: Option<PathBuf>) -> io::Result<Self> {
+        let mut file = path.map(RecordFile::new);
+        let adopted = match &mut file {
+            Some(file) => file
+                .recover()?
+                .map(|bytes| from_bytes(&bytes))
+                .transpose()?,
+            None => None,
+        };
+        Ok(Self { file, adopted })
+    }
+
+    pub(crate) fn adopted(&self) -> Option<&Bootstrap> {
+        self.adopted.as_ref()
+    }
+
+    /// Persists `bootstrap` unless one was adopted before, and returns
+    /// whichever this member now holds.
+    pub(crate) fn adopt(&mut self, bootstrap: Bootstrap) -> io::Result<&Bootstrap> {
+        if self.adopted.is_none() {
+            if let Some(file) = &mut self.file {
+                file.append(&to_bytes(&bootstrap))?;
+            }
+            self.adopted = Some(bootstrap);
+        }
+        Ok(self.adopted.as_ref().expect("adopted above"))
+    }
+
+    /// Like [`adopt`](Self::adopt), but fails if a different bootstrap was
+    /// adopted before.
+    pub(crate) fn claim(&mut self, bootstrap: Bootstrap) -> Result<(), BootstrapError> {
+        let theirs = bootstrap.cluster();
+        let held = self.adopt(bootstrap)?;
+        match held.cluster() {
+            ours if ours == theirs => Ok(()),
+            ours => Err(BootstrapError::Conflict { ours, theirs }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn members(ids: &[u64]) -> Bootstrap {
+        Bootstrap::new(ids.iter().map(|id| {
+            (
+                Id(*id),
+                SocketAddr::from(([127, 0, 0, 1], 7000 + *id as u16)),
+            )
+        }))
+    }
+
+    #[test]
+    fn the_cluster_id_ignores_member_order() {
+        let forward = members(&[1, 2, 3]);
+        let backward = Bootstrap::new(forward.members().iter().rev().copied());
+        assert_eq!(forward, backward);
+        assert_eq!(forward.cluster(), backward.cluster());
+        assert_ne!(forward.cluster(), members(&[1, 2, 4]).cluster());
+    }
+
+    #[test]
+    fn a_member_keeps_the_first_bootstrap_across_restarts() {
+        let path = std::env::temp_dir().join(format!("paxos-bootstrap-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut file = BootstrapFile::open(Some(path.clone())).unwrap();
+        assert!(file.adopted().is_none());
+        file.claim(members(&[1, 2, 3])).unwrap();
+        file.claim(members(&[1, 2, 3])).unwrap();
+        drop(file);
+
+        let mut reopened = BootstrapFile::open(Some(path.clone())).unwrap();
+        assert_eq!(reopened.adopted(), Some(&members(&[1, 2, 3])));
+        assert!(matches!(
+            reopened.claim(members(&[3, 4, 5])),
+            Err(BootstrapError::Conflict { ours, .. }) if ours == members(&[1, 2, 3]).cluster()
+        ));
+        assert_eq!(
+            reopened.adopt(members(&[3, 4, 5])).unwrap(),
+            &members(&[1, 2, 3])
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+/// Bytes held by queued values, out-of-order decisions and responses nobody
+/// has collected yet, shared by everything that holds them. Once `limit` is
+/// reached, [`reserve`](Self::reserve) waits until enough is released, so
+/// submitters slow down instead of the process running out of memory.
+#[derive(Debug)]
+pub struct MemoryBudget {
+    limit: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    used: usize,
+    next_ticket: u64,
+    waiters: VecDeque<Waiter>,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    ticket: u64,
+    bytes: usize,
+    waker: Option<Waker>,
+}
+
+impl State {
+    /// Anything fits into an empty budget, so a value larger than the whole
+    /// limit still goes through on its own.
+    fn fits(&self, limit: usize, bytes: usize) -> bool {
+        self.used == 0 || self.used.saturating_add(bytes) <= limit
+    }
+
+    fn wake_next(&mut self) {
+        if let Some(waker) = self.waiters.front_mut().and_then(|next| next.waker.take()) {
+            waker.wake();
+        }
+    }
+}
+
+impl MemoryBudget {
+    pub fn new(limit: usize) -> Arc<Self> {
+        Arc::new(Self {
+            limit,
+            state: Mutex::default(),
+        })
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn used(&self) -> usize {
+        self.lock().used
+    }
+
+    /// Waits until `bytes` fit. Reservations are granted in the order they
+    /// were asked for, so a large one is not starved by a stream of small
+    /// ones.
+    pub fn reserve(self: &Arc<Self>, bytes: usize) -> Reserve {
+        Reserve {
+            budget: self.clone(),
+            bytes,
+            ticket: None,
+        }
+    }
+
+    /// Takes `bytes` now if they fit and nobody is waiting ahead.
+    pub fn try_reserve(self: &Arc<Self>, bytes: usize) -> Option<Reservation> {
+        let mut state = self.lock();
+        if !state.waiters.is_empty() || !state.fits(self.limit, bytes) {
+            return None;
+        }
+        state.used += bytes;
+        Some(self.reservation(bytes))
+    }
+
+    /// Accounts for `bytes` that are already held and cannot wait, even past
+    /// the limit. Later reservations wait until they are released.
+    pub fn charge(self: &Arc<Self>, bytes: usize) -> Reservation {
+        self.lock().used += bytes;
+        self.reservation(bytes)
+    }
+
+    fn reservation(self: &Arc<Self>, bytes: usize) -> Reservation {
+        Reservation {
+            budget: self.clone(),
+            bytes,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A [`MemoryBudget`] together with how many bytes a value counts for.
+pub(crate) struct Accounted<V> {
+    budget: Arc<MemoryBudget>,
+    size: fn(&V) -> usize,
+}
+
+impl<V> Clone for Accounted<V> {
+    fn clone(&self) -> Self {
+        Self {
+            budget: self.budget.clone(),
+            size: self.size,
+        }
+    }
+}
+
+impl<V> Accounted<V> {
+    pub(crate) fn new(budget: Arc<MemoryBudget>, size: fn(&V) -> usize) -> Self {
+        Self { budget, size }
+    }
+
+    pub(crate) fn reserve(&self, value: &V) -> Reserve {
+        self.budget.reserve((self.size)(value))
+    }
+
+    pub(crate) fn charge(&self, value: &V) -> Reservation {
+        self.budget.charge((self.size)(value))
+    }
+}
+
+/// Resolves to a [`Reservation`] once the bytes fit.
+#[derive(Debug)]
+pub struct Reserve {
+    budget: Arc<MemoryBudget>,
+    bytes: usize,
+    ticket: Option<u64>,
+}
+
+impl Future for Reserve {
+    type Output = Reservation;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Reservation> {
+        let this = &mut *self;
+        let mut state = this.budget.lock();
+        let first = match (this.ticket, state.waiters.front()) {
+            (_, None) => true,
+            (Some(ticket), Some(front)) => front.ticket == ticket,
+            (None, Some(_)) => false,
+        };
+        if first && state.fits(this.budget.limit, this.bytes) {
+            if this.ticket.take().is_some() {
+                state.waiters.pop_front();
+            }
+            state.used += this.bytes;
+            // The next waiter may fit into what is left as well.
+            state.wake_next();
+            drop(state);
+            return Poll::Ready(this.budget.reservation(this.bytes));
+        }
+        let waker = Some(cx.waker().clone());
+        match this.ticket {
+            Some(ticket) => {
+                if let Some(waiter) = state.waiters.iter_mut().find(|w| w.ticket == ticket) {
+                    waiter.waker = waker;
+                }
+            }
+            None => {
+                state.next_ticket += 1;
+                let ticket = state.next_ticket;
+                state.waiters.push_back(Waiter {
+                    ticket,
+                    bytes: this.bytes,
+                    waker,
+                });
+                this.ticket = Some(ticket);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Reserve {
+    fn drop(&mut self) {
+        let Some(ticket) = self.ticket else {
+            return;
+        };
+        let mut state = self.budget.lock();
+        let first = state.waiters.front().is_some_and(|w| w.ticket == ticket);
+        state.waiters.retain(|waiter| waiter.ticket != ticket);
+        if first
+            && state
+                .waiters
+                .front()
+                .is_some_and(|next| state.fits(self.budget.limit, next.bytes))
+        {
+            state.wake_next();
+        }
+    }
+}
+
+/// Bytes taken from a [`MemoryBudget`], given back when dropped.
+#[derive(Debug)]
+pub struct Reservation {
+    budget: Arc<MemoryBudget>,
+    bytes: usize,
+}
+
+impl Reservation {
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let mut state = self.budget.lock();
+        state.used -= self.bytes;
+        state.wake_next();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::noop_waker;
+    use futures::FutureExt;
+
+    fn poll(reserve: &mut Reserve) -> Option<Reservation> {
+        let waker = noop_waker();
+        match Pin::new(reserve).poll(&mut Context::from_waker(&waker)) {
+            Poll::Ready(reservation) => Some(reservation),
+            Poll::Pending => None,
+        }
+    }
+
+    #[test]
+    fn reservations_wait_until_enough_is_released() {
+        let budget = MemoryBudget::new(10);
+        let first = budget.reserve(6).now_or_never().unwrap();
+        let mut second = budget.reserve(6);
+        assert!(poll(&mut second).is_none());
+        assert!(budget.try_reserve(1).is_none());
+
+        drop(first);
+        let second = poll(&mut second).unwrap();
+        assert_eq!(budget.used(), second.bytes());
+        drop(second);
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[test]
+    fn waiters_are_served_in_order() {
+        let budget = MemoryBudget::new(10);
+        let held = budget.charge(9);
+        let (mut large, mut small) = (budget.reserve(8), budget.reserve(1));
+        assert!(poll(&mut large).is_none());
+        // There is room for the small one, but the large one asked first.
+        assert!(poll(&mut small).is_none());
+
+        drop(held);
+        let large = poll(&mut large).unwrap();
+        assert!(poll(&mut small).is_some());
+        drop(large);
+
+        let mut cancelled = budget.reserve(10);
+        let held = budget.charge(1);
+        assert!(poll(&mut cancelled).is_none());
+        drop(cancelled);
+        assert!(budget.try_reserve(1).is_some());
+        drop(held);
+    }
+
+    #[test]
+    fn oversized_values_go_through_alone() {
+        let budget = MemoryBudget::new(10);
+        let huge = budget.try_reserve(100).unwrap();
+        assert_eq!(budget.used(), 100);
+        assert!(budget.try_reserve(1).is_none());
+        drop(huge);
+        assert!(budget.try_reserve(1).is_some());
+    }
+}
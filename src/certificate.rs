@@ -0,0 +1,149 @@
+use crate::alpha::{Id, Quorum, Round, Status, WriteResponse};
+#[cfg(feature = "auth")]
+use crate::auth::Keyring;
+#[cfg(feature = "auth")]
+use crate::codec::{to_bytes, Encode};
+#[cfg(feature = "auth")]
+use crate::digest::sha256;
+use std::collections::HashSet;
+#[cfg(feature = "auth")]
+use std::io;
+use thiserror::Error;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Signature(pub [u8; 32]);
+
+/// The write quorum that accepted a decided value.
+///
+/// [`verify`](Self::verify) only checks that the responses form a quorum for
+/// the round; it does not tie them to `value`. Under the `auth` feature each
+/// response also carries an HMAC over the response and a SHA-256 digest of the
+/// value. Those keys are pairwise, so the signatures can only be checked by a
+/// holder of the key each acceptor shares with `proposer`.
+#[derive(Clone, Debug)]
+pub struct DecisionCertificate<V> {
+    pub proposer: Id,
+    pub round: Round,
+    pub value: V,
+    pub responses: Vec<WriteResponse>,
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum CertificateError {
+    #[error("{0:?} did not accept the value")]
+    NotAccepted(Id),
+    #[error("{0:?} responded to a different round")]
+    WrongRound(Id),
+    #[error("{0:?} responded more than once")]
+    Duplicate(Id),
+    #[error("responses do not form a write quorum")]
+    NoQuorum,
+    #[cfg(feature = "auth")]
+    #[error("{0:?} has a missing or invalid signature")]
+    BadSignature(Id),
+}
+
+impl<V> DecisionCertificate<V> {
+    pub fn verify(&self, quorum: &impl Quorum) -> Result<(), CertificateError> {
+        let mut acceptors = HashSet::new();
+        for response in &self.responses {
+            if response.status != Status::Accepted {
+                return Err(CertificateError::NotAccepted(response.acceptor));
+            }
+            if response.round != self.round {
+                return Err(CertificateError::WrongRound(response.acceptor));
+            }
+            if !acceptors.insert(response.acceptor) {
+                return Err(CertificateError::Duplicate(response.acceptor));
+            }
+        }
+        if !quorum.is_write_quorum(&acceptors) {
+            return Err(CertificateError::NoQuorum);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "auth")]
+    pub fn verify_signatures(&self, keyring: &Keyring) -> Result<(), CertificateError>
+    where
+        V: Encode,
+    {
+        let digest = sha256(&to_bytes(&self.value));
+        for response in &self.responses {
+            if !signature_valid(keyring, self.proposer, response, &digest) {
+                return Err(CertificateError::BadSignature(response.acceptor));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "auth")]
+pub(crate) fn sign<V: Encode>(
+    mut response: WriteResponse,
+    value: &V,
+    keyring: &Keyring,
+    proposer: Id,
+) -> io::Result<WriteResponse> {
+    let digest = sha256(&to_bytes(value));
+    let signature = keyring.sign(proposer, &signed_message(&response, &digest))?;
+    response.signature = Some(Signature(signature));
+    Ok(response)
+}
+
+/// Whether `response` carries the signature its acceptor would have made for
+/// a value with SHA-256 `digest`, on behalf of `proposer`.
+#[cfg(feature = "auth")]
+pub(crate) fn signature_valid(
+    keyring: &Keyring,
+    proposer: Id,
+    response: &WriteResponse,
+    digest: &[u8; 32],
+) -> bool {
+    let message = signed_message(response, digest);
+    response.signature.is_some_and(|signature| {
+        keyring.verify(response.acceptor, proposer, &message, &signature.0)
+    })
+}
+
+#[cfg(feature = "auth")]
+fn signed_message(response: &WriteResponse, digest: &[u8; 32]) -> Vec<u8> {
+    let mut message = to_bytes(&WriteResponse {
+        signature: None,
+        ..*response
+    });
+    message.extend_from_slice(digest);
+    message
+}
+
+#[cfg(all(test, feature = "auth"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures_cover_the_value() {
+        let acceptor = Keyring::new(Id(2)).with_key(Id(1), *b"shared");
+        let proposer = Keyring::new(Id(1)).with_key(Id(2), *b"shared");
+        let round = Round::new(Id(1));
+        let response = WriteResponse {
+            acceptor: Id(2),
+            round,
+            status: Status::Accepted,
+            last_round_entered: round,
+            signature: None,
+        };
+        let mut certificate = DecisionCertificate {
+            proposer: Id(1),
+            round,
+            value: 7u64,
+            responses: vec![sign(response, &7u64, &acceptor, Id(1)).unwrap()],
+        };
+        assert_eq!(certificate.verify_signatures(&proposer), Ok(()));
+
+        certificate.value = 8;
+        assert_eq!(
+            certificate.verify_signatures(&proposer),
+            Err(CertificateError::BadSignature(Id(2)))
+        );
+    }
+}
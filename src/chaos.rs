@@ -0,0 +1,237 @@
+use crate::alpha::{
+    Error, Id, Quorum, ReadPeers, ReadResponse, Round, Value, WritePeers, WriteResponse,
+};
+use crate::learner::{DecisionBroadcast, DecisionPeers};
+use crate::rng::XorShift;
+use crate::time::{Clock, SystemClock};
+use futures::stream::{self, Stream, StreamExt};
+use std::collections::{HashMap, HashSet};
+use std::iter;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PeerFaults {
+    pub drop: f64,
+    pub duplicate: f64,
+    pub max_delay: Duration,
+}
+
+pub struct FaultyPeers<P> {
+    peers: P,
+    faults: PeerFaults,
+    overrides: HashMap<Id, PeerFaults>,
+    partitioned: Mutex<HashSet<Id>>,
+    rng: Mutex<XorShift>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<P> FaultyPeers<P> {
+    pub fn new(peers: P, faults: PeerFaults) -> Self {
+        Self {
+            peers,
+            faults,
+            overrides: HashMap::new(),
+            partitioned: Mutex::new(HashSet::new()),
+            rng: Mutex::new(XorShift::new(0)),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Mutex::new(XorShift::new(seed));
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_peer(mut self, id: Id, faults: PeerFaults) -> Self {
+        self.overrides.insert(id, faults);
+        self
+    }
+
+    pub fn partition(&self, id: Id) {
+        lock(&self.partitioned).insert(id);
+    }
+
+    pub fn heal(&self, id: Id) {
+        lock(&self.partitioned).remove(&id);
+    }
+
+    fn plan(&self, acceptor: Option<Id>) -> (usize, Duration) {
+        if acceptor.is_some_and(|id| lock(&self.partitioned).contains(&id)) {
+            return (0, Duration::ZERO);
+        }
+        let faults = acceptor
+            .and_then(|id| self.overrides.get(&id))
+            .unwrap_or(&self.faults);
+        let mut rng = lock(&self.rng);
+        let copies = if rng.chance(faults.drop) {
+            0
+        } else if rng.chance(faults.duplicate) {
+            2
+        } else {
+            1
+        };
+        (copies, faults.max_delay.mul_f64(rng.unit()))
+    }
+
+    fn inject<'a, T, S, F>(
+        &'a self,
+        responses: S,
+        acceptor: F,
+    ) -> impl Stream<Item = Result<T, Error>> + use<'a, P, T, S, F>
+    where
+        T: Clone,
+        S: Stream<Item = Result<T, Error>>,
+        F: Fn(&T) -> Option<Id>,
+    {
+        responses
+            .map(move |response| {
+                let (copies, delay) = match &response {
+                    Ok(response) => self.plan(acceptor(response)),
+                    Err(_) => (1, Duration::ZERO),
+                };
+                let delay = (!delay.is_zero()).then(|| self.clock.sleep(delay));
+                async move {
+                    if let Some(delay) = delay {
+                        delay.await;
+                    }
+                    match response {
+                        Ok(response) => iter::repeat_n(response, copies).map(Ok).collect(),
+                        Err(error) => vec![Err(error)],
+                    }
+                }
+            })
+            .buffer_unordered(usize::MAX)
+            .flat_map(stream::iter)
+    }
+}
+
+impl<V: Clone, P: ReadPeers<V>> ReadPeers<V> for FaultyPeers<P> {
+    fn read(&self, round: Round) -> impl Stream<Item = Result<ReadResponse<V>, Error>> {
+        self.inject(self.peers.read(round), |response| Some(response.acceptor))
+    }
+}
+
+impl<V, P: WritePeers<V>> WritePeers<V> for FaultyPeers<P> {
+    fn write(&self, value: Value<V>) -> impl Stream<Item = Result<WriteResponse, Error>> {
+        self.inject(self.peers.write(value), |response| Some(response.acceptor))
+    }
+
+    fn write_to(
+        &self,
+        acceptors: &[Id],
+        value: Value<V>,
+    ) -> impl Stream<Item = Result<WriteResponse, Error>> {
+        self.inject(self.peers.write_to(acceptors, value), |response| {
+            Some(response.acceptor)
+        })
+    }
+}
+
+impl<V, P: DecisionPeers<V>> DecisionPeers<V> for FaultyPeers<P> {
+    fn decide(&self, decision: DecisionBroadcast<V>) -> impl Stream<Item = Result<(), Error>> {
+        self.inject(self.peers.decide(decision), |_| None)
+    }
+}
+
+impl<P: Quorum> Quorum for FaultyPeers<P> {
+    fn majority(&self) -> usize {
+        self.peers.majority()
+    }
+
+    fn max_failures(&self) -> usize {
+        self.peers.max_failures()
+    }
+
+    fn is_read_quorum(&self, acceptors: &HashSet<Id>) -> bool {
+        self.peers.is_read_quorum(acceptors)
+    }
+
+    fn is_write_quorum(&self, acceptors: &HashSet<Id>) -> bool {
+        self.peers.is_write_quorum(acceptors)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alpha::Alpha;
+    use crate::time::MockClock;
+    use futures::executor::block_on;
+    use futures::FutureExt;
+
+    struct Acceptors;
+
+    impl ReadPeers<u64> for Acceptors {
+        fn read(&self, round: Round) -> impl Stream<Item = Result<ReadResponse<u64>, Error>> {
+            stream::iter((0..3).map(move |id| Ok(Alpha::new().read(Id(id), round))))
+        }
+    }
+
+    fn answered(peers: &FaultyPeers<Acceptors>) -> Vec<Id> {
+        let responses = block_on(peers.read(Round::new(Id(0))).collect::<Vec<_>>());
+        let mut acceptors: Vec<_> = responses
+            .into_iter()
+            .map(|response| response.unwrap().acceptor)
+            .collect();
+        acceptors.sort();
+        acceptors
+    }
+
+    #[test]
+    fn drops_responses_unless_the_peer_is_spared() {
+        let faults = PeerFaults {
+            drop: 1.0,
+            ..PeerFaults::default()
+        };
+        let peers = FaultyPeers::new(Acceptors, faults);
+        assert_eq!(answered(&peers), []);
+        let peers = peers.with_peer(Id(1), PeerFaults::default());
+        assert_eq!(answered(&peers), [Id(1)]);
+    }
+
+    #[test]
+    fn duplicates_responses() {
+        let faults = PeerFaults {
+            duplicate: 1.0,
+            ..PeerFaults::default()
+        };
+        let peers = FaultyPeers::new(Acceptors, faults);
+        assert_eq!(answered(&peers), [Id(0), Id(0), Id(1), Id(1), Id(2), Id(2)]);
+    }
+
+    #[test]
+    fn partitioned_peers_are_silent_until_healed() {
+        let peers = FaultyPeers::new(Acceptors, PeerFaults::default());
+        peers.partition(Id(2));
+        assert_eq!(answered(&peers), [Id(0), Id(1)]);
+        peers.heal(Id(2));
+        assert_eq!(answered(&peers), [Id(0), Id(1), Id(2)]);
+    }
+
+    #[test]
+    fn delays_responses_on_the_clock() {
+        let clock = MockClock::new();
+        let faults = PeerFaults {
+            max_delay: Duration::from_secs(1),
+            ..PeerFaults::default()
+        };
+        let peers = FaultyPeers::new(Acceptors, faults)
+            .with_seed(7)
+            .with_clock(Arc::new(clock.clone()));
+        let mut responses = Box::pin(peers.read(Round::new(Id(0))));
+        assert!(responses.next().now_or_never().is_none());
+        clock.advance(Duration::from_secs(1));
+        let delivered = block_on(responses.collect::<Vec<_>>());
+        assert_eq!(delivered.len(), 3);
+    }
+}
@@ -0,0 +1,409 @@
+//! Values too large for one log entry, split across several and put back
+//! together when they are applied. A [`Replica`] over [`Chunked`] values
+//! proposes a value larger than its [`ChunkPolicy`]'s chunk size as parts
+//! that are pipelined like any other entries, so one huge value neither
+//! exceeds the transport's message limit nor holds up the slots behind it;
+//! [`Reassembly`] applies it to the inner state machine once the last of its
+//! parts is applied, at that part's index.
+
+use crate::alpha::{Error, Id};
+use crate::bytes::BytesValue;
+use crate::codec::{from_bytes, to_bytes, Decode, Encode};
+use crate::log::{LogIndex, SlotPeers};
+use crate::proposer::FailureDetector;
+use crate::smr::{Replica, Snapshotting, StateMachine};
+use std::collections::BTreeMap;
+use std::io;
+use std::mem;
+
+/// How large a value goes into one entry, and how large one may be at all.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ChunkPolicy {
+    pub chunk_size: usize,
+    pub max_value_size: Option<usize>,
+}
+
+impl Default for ChunkPolicy {
+    fn default() -> Self {
+        Self {
+            chunk_size: 1 << 20,
+            max_value_size: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Chunked<V> {
+    Whole(V),
+    Part(Part),
+}
+
+/// One of `count` parts of the encoded value a writer proposed as its
+/// `sequence`th.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Part {
+    pub writer: Id,
+    pub sequence: u64,
+    pub index: u32,
+    pub count: u32,
+    pub bytes: BytesValue,
+}
+
+/// Splits `value` into the entries `policy` allows, or refuses it when it
+/// is larger than any value may be.
+pub fn split<V: Encode>(
+    value: V,
+    writer: Id,
+    sequence: u64,
+    policy: ChunkPolicy,
+) -> Result<Vec<Chunked<V>>, Error> {
+    let bytes = to_bytes(&value);
+    let chunk_size = policy.chunk_size.max(1);
+    let too_large = |limit| Error::ValueTooLarge {
+        size: bytes.len(),
+        limit,
+    };
+    if let Some(limit) = policy.max_value_size.filter(|limit| bytes.len() > *limit) {
+        return Err(too_large(limit));
+    }
+    if bytes.len() <= chunk_size {
+        return Ok(vec![Chunked::Whole(value)]);
+    }
+    let count = u32::try_from(bytes.len().div_ceil(chunk_size))
+        .map_err(|_| too_large(chunk_size * u32::MAX as usize))?;
+    Ok((0..count)
+        .zip(bytes.chunks(chunk_size))
+        .map(|(index, chunk)| {
+            Chunked::Part(Part {
+                writer,
+                sequence,
+                index,
+                count,
+                bytes: chunk.into(),
+            })
+        })
+        .collect())
+}
+
+/// Applies whole values to `M` as they come and parts once all of a
+/// value's have; its output is `None` for every other part, and for a value
+/// whose parts do not decode.
+///
+/// A writer finishes one value before it starts the next, so parts of an
+/// earlier value it never finished are dropped once a later one's arrive,
+/// and any that arrive after are ignored.
+#[derive(Debug, Default)]
+pub struct Reassembly<M> {
+    state_machine: M,
+    writers: BTreeMap<Id, Assembling>,
+}
+
+/// The parts of a writer's latest value applied so far; none once it is
+/// whole.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Assembling {
+    pub sequence: u64,
+    pub parts: Vec<Option<BytesValue>>,
+}
+
+impl<M> Reassembly<M> {
+    pub fn new(state_machine: M) -> Self {
+        Self {
+            state_machine,
+            writers: BTreeMap::new(),
+        }
+    }
+
+    pub fn state_machine(&self) -> &M {
+        &self.state_machine
+    }
+
+    /// How many values are partly applied.
+    pub fn pending(&self) -> usize {
+        self.writers
+            .values()
+            .filter(|assembling| !assembling.parts.is_empty())
+            .count()
+    }
+
+    fn insert(&mut self, part: &Part) -> Option<Vec<u8>> {
+        let count = part.count as usize;
+        let fresh = || Assembling {
+            sequence: part.sequence,
+            parts: vec![None; count],
+        };
+        let assembling = self.writers.entry(part.writer).or_insert_with(fresh);
+        if part.sequence < assembling.sequence {
+            return None;
+        }
+        if part.sequence > assembling.sequence {
+            *assembling = fresh();
+        }
+        if assembling.parts.len() != count {
+            return None;
+        }
+        *assembling.parts.get_mut(part.index as usize)? = Some(part.bytes.clone());
+        if assembling.parts.iter().any(Option::is_none) {
+            return None;
+        }
+        let parts = mem::take(&mut assembling.parts);
+        Some(
+            parts
+                .into_iter()
+                .flatten()
+                .flat_map(|bytes| bytes.to_vec())
+                .collect(),
+        )
+    }
+}
+
+impl<M, V> StateMachine<Chunked<V>> for Reassembly<M>
+where
+    M: StateMachine<V>,
+    V: Decode,
+{
+    type Output = Option<M::Output>;
+
+    fn apply(&mut self, index: LogIndex, value: &Chunked<V>) -> Self::Output {
+        match value {
+            Chunked::Whole(value) => Some(self.state_machine.apply(index, value)),
+            Chunked::Part(part) => {
+                let value = from_bytes(&self.insert(part)?).ok()?;
+                Some(self.state_machine.apply(index, &value))
+            }
+        }
+    }
+}
+
+impl<M, V> Snapshotting<Chunked<V>> for Reassembly<M>
+where
+    M: Snapshotting<V>,
+    V: Decode,
+{
+    type State = (M::State, BTreeMap<Id, Assembling>);
+
+    fn snapshot(&self) -> Self::State {
+        (self.state_machine.snapshot(), self.writers.clone())
+    }
+
+    fn restore(&mut self, (state, writers): Self::State) {
+        self.state_machine.restore(state);
+        self.writers = writers;
+    }
+}
+
+impl<M, V, S, D> Replica<Reassembly<M>, Chunked<V>, S, D>
+where
+    M: StateMachine<V>,
+    V: Clone + PartialEq + Encode + Decode,
+    S: SlotPeers<Chunked<V>>,
+    D: FailureDetector + Clone,
+{
+    /// Proposes `value` as one entry or, when it is larger than a chunk, as
+    /// its parts, and returns what applying it output.
+    pub async fn propose_chunked(
+        &mut self,
+        value: V,
+        policy: ChunkPolicy,
+    ) -> Result<M::Output, Error> {
+        // Hybrid timestamps only grow, so a writer that restarts mid-value
+        // still numbers its next value after the one it left unfinished.
+        let sequence = self.log_mut().now().to_bits();
+        let entries = split(value, self.log().id(), sequence, policy)?;
+        let outputs = self.propose_all_and_wait(entries).await?;
+        outputs.into_iter().flatten().next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the value's parts do not decode",
+            )
+            .into()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct Applied(Vec<(LogIndex, String)>);
+
+    impl StateMachine<String> for Applied {
+        type Output = usize;
+
+        fn apply(&mut self, index: LogIndex, value: &String) -> usize {
+            self.0.push((index, value.clone()));
+            value.len()
+        }
+    }
+
+    impl Snapshotting<String> for Applied {
+        type State = Vec<(LogIndex, String)>;
+
+        fn snapshot(&self) -> Self::State {
+            self.0.clone()
+        }
+
+        fn restore(&mut self, state: Self::State) {
+            self.0 = state;
+        }
+    }
+
+    fn policy(chunk_size: usize) -> ChunkPolicy {
+        ChunkPolicy {
+            chunk_size,
+            max_value_size: Some(64),
+        }
+    }
+
+    fn apply_all(
+        machine: &mut Reassembly<Applied>,
+        entries: &[Chunked<String>],
+    ) -> Vec<Option<usize>> {
+        (0..)
+            .zip(entries)
+            .map(|(index, entry)| machine.apply(LogIndex::new(index), entry))
+            .collect()
+    }
+
+    #[test]
+    fn splits_only_what_exceeds_a_chunk_and_refuses_what_exceeds_the_limit() {
+        let small = split("tiny".to_string(), Id(1), 0, policy(16)).unwrap();
+        assert_eq!(small, [Chunked::Whole("tiny".to_string())]);
+
+        let value = "x".repeat(40);
+        let parts = split(value.clone(), Id(1), 7, policy(16)).unwrap();
+        assert_eq!(parts.len(), 3);
+        assert!(parts.iter().all(|part| to_bytes(part).len() < 64));
+        for entry in &parts {
+            assert_eq!(
+                &from_bytes::<Chunked<String>>(&to_bytes(entry)).unwrap(),
+                entry
+            );
+        }
+
+        assert!(matches!(
+            split("y".repeat(80), Id(1), 0, policy(16)),
+            Err(Error::ValueTooLarge {
+                size: 88,
+                limit: 64
+            })
+        ));
+    }
+
+    #[test]
+    fn applies_a_value_once_all_its_parts_are_applied_in_any_order() {
+        let first = "a".repeat(40);
+        let second = "b".repeat(20);
+        let mut parts = split(first.clone(), Id(1), 1, policy(16)).unwrap();
+        parts.swap(0, 2);
+        let other = split(second.clone(), Id(2), 1, policy(16)).unwrap();
+        let entries = [
+            parts[0].clone(),
+            other[0].clone(),
+            parts[1].clone(),
+            Chunked::Whole("whole".to_string()),
+            other[1].clone(),
+            parts[2].clone(),
+        ];
+        let mut machine = Reassembly::new(Applied::default());
+        let outputs = apply_all(&mut machine, &entries);
+        assert_eq!(outputs, [None, None, None, Some(5), Some(20), Some(40)]);
+        assert_eq!(
+            machine.state_machine().0,
+            [
+                (LogIndex::new(3), "whole".to_string()),
+                (LogIndex::new(4), second),
+                (LogIndex::new(5), first),
+            ]
+        );
+        assert_eq!(machine.pending(), 0);
+    }
+
+    #[test]
+    fn a_writers_later_value_drops_the_one_it_left_unfinished() {
+        let mut abandoned = split("a".repeat(40), Id(1), 1, policy(16)).unwrap();
+        let next = split("b".repeat(40), Id(1), 2, policy(16)).unwrap();
+        let mut machine = Reassembly::new(Applied::default());
+        apply_all(&mut machine, &abandoned[..2]);
+        assert_eq!(machine.pending(), 1);
+
+        // A snapshot taken mid-value restores the parts applied so far.
+        let snapshot = machine.snapshot();
+        let mut restored = Reassembly::new(Applied::default());
+        restored.restore(snapshot);
+        assert_eq!(restored.pending(), 1);
+
+        let mut entries = next.clone();
+        entries.push(abandoned.pop().unwrap());
+        let outputs = apply_all(&mut restored, &entries);
+        assert_eq!(outputs, [None, None, Some(40), None]);
+        assert_eq!(restored.pending(), 0);
+        assert_eq!(restored.state_machine().0.len(), 1);
+    }
+
+    #[cfg(feature = "threads")]
+    #[test]
+    fn replicas_propose_large_values_as_pipelined_parts() {
+        use crate::local::{LocalCluster, LocalPeers};
+        use futures::executor::block_on;
+        use std::cell::RefCell;
+        use std::future::{self, Future};
+        use std::rc::Rc;
+
+        #[derive(Clone, Default)]
+        struct Slots(Rc<RefCell<BTreeMap<LogIndex, LocalCluster<Chunked<String>>>>>);
+
+        impl SlotPeers<Chunked<String>> for Slots {
+            type Peers = LocalPeers<Chunked<String>>;
+
+            fn slot(&self, index: LogIndex) -> Self::Peers {
+                self.0
+                    .borrow_mut()
+                    .entry(index)
+                    .or_insert_with(|| LocalCluster::new(3))
+                    .peers()
+            }
+        }
+
+        #[derive(Clone)]
+        struct Leader;
+
+        impl FailureDetector for Leader {
+            fn leader(&self) -> Id {
+                Id(1)
+            }
+
+            fn changed(&self) -> impl Future<Output = ()> {
+                future::pending()
+            }
+        }
+
+        let slots = Slots::default();
+        let mut replica = Replica::new(
+            crate::log::ReplicatedLog::new(Id(1), slots.clone(), Leader),
+            Reassembly::new(Applied::default()),
+        );
+        assert_eq!(
+            block_on(replica.propose_chunked("small".to_string(), policy(16))).unwrap(),
+            5
+        );
+        let large = "z".repeat(50);
+        assert_eq!(
+            block_on(replica.propose_chunked(large.clone(), policy(16))).unwrap(),
+            50
+        );
+        assert_eq!(replica.log().next_index(), LogIndex::new(5));
+        assert_eq!(
+            replica.state_machine().state_machine().0,
+            [
+                (LogIndex::new(0), "small".to_string()),
+                (LogIndex::new(4), large),
+            ]
+        );
+        assert!(matches!(
+            block_on(replica.propose_chunked("y".repeat(80), policy(16))),
+            Err(Error::ValueTooLarge { .. })
+        ));
+    }
+}
@@ -0,0 +1,955 @@
+use crate::alpha::{Alpha, Id, ReadResponse, Round, Status, Tick, Value, WriteResponse};
+use crate::audit::{AuditEvent, DecisionRecord, EventKind};
+use crate::bootstrap::Bootstrap;
+use crate::bytes::BytesValue;
+use crate::certificate::{DecisionCertificate, Signature};
+use crate::chunk::{Chunked, Part};
+use crate::fence::{Announced, Fence};
+use crate::instance::InstanceId;
+use crate::lock::{FencingToken, LeaseOp};
+use crate::log::LogIndex;
+use crate::membership::{Applied, Configuration, Member, Membership, Role};
+use crate::sealed::{self, Sealed};
+use crate::session::{ClientId, SessionRequest};
+use crate::smr::{SessionToken, Snapshot};
+use crate::time::Timestamp;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+pub trait Encode {
+    fn encode(&self, buf: &mut Vec<u8>);
+}
+
+pub trait Decode: Sized {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self>;
+}
+
+pub fn to_bytes<T: Encode>(value: &T) -> Vec<u8> {
+    let mut buf = Vec::new();
+    value.encode(&mut buf);
+    buf
+}
+
+pub fn from_bytes<T: Decode>(mut buf: &[u8]) -> io::Result<T> {
+    let value = T::decode(&mut buf)?;
+    if !buf.is_empty() {
+        return Err(invalid_data("trailing bytes"));
+    }
+    Ok(value)
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    if buf.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let (head, tail) = buf.split_at(len);
+    *buf = tail;
+    Ok(head)
+}
+
+impl Encode for u8 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(*self);
+    }
+}
+
+impl Decode for u8 {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(take(buf, 1)?[0])
+    }
+}
+
+impl Encode for u32 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl Decode for u32 {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(take(buf, 4)?);
+        Ok(Self::from_be_bytes(bytes))
+    }
+}
+
+impl Encode for u64 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl Decode for u64 {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(take(buf, 8)?);
+        Ok(Self::from_be_bytes(bytes))
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        u8::from(*self).encode(buf);
+    }
+}
+
+impl Decode for bool {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        match u8::decode(buf)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid_data("invalid bool")),
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.len() as u64).encode(buf);
+        for item in self {
+            item.encode(buf);
+        }
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        let len = u64::decode(buf)?;
+        let mut items = Vec::with_capacity(len.min(buf.len() as u64) as usize);
+        for _ in 0..len {
+            items.push(T::decode(buf)?);
+        }
+        Ok(items)
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.len() as u64).encode(buf);
+        buf.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Decode for String {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        let len = u64::decode(buf)?;
+        let len = usize::try_from(len).map_err(|_| invalid_data("string too long"))?;
+        let bytes = take(buf, len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid_data("invalid utf-8"))
+    }
+}
+
+impl Encode for BytesValue {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.len() as u64).encode(buf);
+        buf.extend_from_slice(self);
+    }
+}
+
+impl Decode for BytesValue {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        let len = u64::decode(buf)?;
+        let len = usize::try_from(len).map_err(|_| invalid_data("bytes too long"))?;
+        Ok(BytesValue::from(take(buf, len)?))
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            None => 0u8.encode(buf),
+            Some(value) => {
+                1u8.encode(buf);
+                value.encode(buf);
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        match u8::decode(buf)? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode(buf)?)),
+            _ => Err(invalid_data("invalid option tag")),
+        }
+    }
+}
+
+impl Encode for Id {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+    }
+}
+
+impl Decode for Id {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self(u64::decode(buf)?))
+    }
+}
+
+impl Encode for InstanceId {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+    }
+}
+
+impl Decode for InstanceId {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self(u64::decode(buf)?))
+    }
+}
+
+impl Encode for Tick {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+    }
+}
+
+impl Decode for Tick {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self(u64::decode(buf)?))
+    }
+}
+
+impl Encode for Round {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.tick.encode(buf);
+        self.process_id.encode(buf);
+    }
+}
+
+impl Decode for Round {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            tick: Tick::decode(buf)?,
+            process_id: Id::decode(buf)?,
+        })
+    }
+}
+
+impl<V: Encode> Encode for Value<V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.value.encode(buf);
+        self.last_round_with_write.encode(buf);
+    }
+}
+
+impl<V: Decode> Decode for Value<V> {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            value: Arc::new(V::decode(buf)?),
+            last_round_with_write: Round::decode(buf)?,
+        })
+    }
+}
+
+impl<V: Encode> Encode for Alpha<V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.last_round_entered.encode(buf);
+        self.value.encode(buf);
+    }
+}
+
+impl<V: Decode> Decode for Alpha<V> {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            last_round_entered: Round::decode(buf)?,
+            value: Option::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for Status {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Status::Accepted => 0u8.encode(buf),
+            Status::Rejected(conflict) => {
+                1u8.encode(buf);
+                conflict.encode(buf);
+            }
+            Status::Invalid => 2u8.encode(buf),
+        }
+    }
+}
+
+impl Decode for Status {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        match u8::decode(buf)? {
+            0 => Ok(Status::Accepted),
+            1 => Ok(Status::Rejected(Round::decode(buf)?)),
+            2 => Ok(Status::Invalid),
+            _ => Err(invalid_data("invalid status tag")),
+        }
+    }
+}
+
+impl Encode for AuditEvent {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let kind: u8 = match self.kind {
+            EventKind::Promise => 0,
+            EventKind::Accept => 1,
+            EventKind::Decision => 2,
+        };
+        kind.encode(buf);
+        self.round.encode(buf);
+        self.peer.encode(buf);
+        self.timestamp.encode(buf);
+        self.value.encode(buf);
+    }
+}
+
+impl Decode for AuditEvent {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        let kind = match u8::decode(buf)? {
+            0 => EventKind::Promise,
+            1 => EventKind::Accept,
+            2 => EventKind::Decision,
+            _ => return Err(invalid_data("invalid audit event tag")),
+        };
+        Ok(Self {
+            kind,
+            round: Round::decode(buf)?,
+            peer: Id::decode(buf)?,
+            timestamp: u64::decode(buf)?,
+            value: Option::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for DecisionRecord {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.instance.encode(buf);
+        self.proposer.encode(buf);
+        self.round.encode(buf);
+        buf.extend_from_slice(&self.value);
+        self.acks.encode(buf);
+        buf.extend_from_slice(&self.previous);
+    }
+}
+
+impl Decode for DecisionRecord {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            instance: u64::decode(buf)?,
+            proposer: Id::decode(buf)?,
+            round: Round::decode(buf)?,
+            value: take(buf, 32)?.try_into().unwrap(),
+            acks: Vec::decode(buf)?,
+            previous: take(buf, 32)?.try_into().unwrap(),
+        })
+    }
+}
+
+impl<V: Encode> Encode for ReadResponse<V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.acceptor.encode(buf);
+        self.round.encode(buf);
+        self.status.encode(buf);
+        self.state.encode(buf);
+    }
+}
+
+impl<V: Decode> Decode for ReadResponse<V> {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            acceptor: Id::decode(buf)?,
+            round: Round::decode(buf)?,
+            status: Status::decode(buf)?,
+            state: Alpha::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for WriteResponse {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.acceptor.encode(buf);
+        self.round.encode(buf);
+        self.status.encode(buf);
+        self.last_round_entered.encode(buf);
+        self.signature.encode(buf);
+    }
+}
+
+impl Decode for WriteResponse {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            acceptor: Id::decode(buf)?,
+            round: Round::decode(buf)?,
+            status: Status::decode(buf)?,
+            last_round_entered: Round::decode(buf)?,
+            signature: Option::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for Signature {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.0);
+    }
+}
+
+impl Decode for Signature {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self(take(buf, 32)?.try_into().unwrap()))
+    }
+}
+
+impl<V: Encode> Encode for DecisionCertificate<V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.proposer.encode(buf);
+        self.round.encode(buf);
+        self.value.encode(buf);
+        self.responses.encode(buf);
+    }
+}
+
+impl<V: Decode> Decode for DecisionCertificate<V> {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            proposer: Id::decode(buf)?,
+            round: Round::decode(buf)?,
+            value: V::decode(buf)?,
+            responses: Vec::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for Configuration {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.voters()
+            .iter()
+            .copied()
+            .collect::<Vec<_>>()
+            .encode(buf);
+    }
+}
+
+impl Decode for Configuration {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Configuration::new(Vec::<Id>::decode(buf)?))
+    }
+}
+
+impl Encode for Membership {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Membership::Stable(configuration) => {
+                0u8.encode(buf);
+                configuration.encode(buf);
+            }
+            Membership::Joint { old, new } => {
+                1u8.encode(buf);
+                old.encode(buf);
+                new.encode(buf);
+            }
+        }
+    }
+}
+
+impl Decode for Membership {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        match u8::decode(buf)? {
+            0 => Ok(Membership::Stable(Configuration::decode(buf)?)),
+            1 => Ok(Membership::Joint {
+                old: Configuration::decode(buf)?,
+                new: Configuration::decode(buf)?,
+            }),
+            _ => Err(invalid_data("invalid membership tag")),
+        }
+    }
+}
+
+impl Encode for Timestamp {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.to_bits().encode(buf);
+    }
+}
+
+impl Decode for Timestamp {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Timestamp::from_bits(u64::decode(buf)?))
+    }
+}
+
+impl Encode for LogIndex {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.get().encode(buf);
+    }
+}
+
+impl Decode for LogIndex {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(LogIndex::new(u64::decode(buf)?))
+    }
+}
+
+impl<T: Encode> Encode for Snapshot<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.last_included.encode(buf);
+        self.state.encode(buf);
+    }
+}
+
+impl<T: Decode> Decode for Snapshot<T> {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            last_included: LogIndex::decode(buf)?,
+            state: T::decode(buf)?,
+        })
+    }
+}
+
+impl<V: Encode> Encode for Chunked<V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Chunked::Whole(value) => {
+                0u8.encode(buf);
+                value.encode(buf);
+            }
+            Chunked::Part(part) => {
+                1u8.encode(buf);
+                part.encode(buf);
+            }
+        }
+    }
+}
+
+impl<V: Decode> Decode for Chunked<V> {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        match u8::decode(buf)? {
+            0 => Ok(Chunked::Whole(V::decode(buf)?)),
+            1 => Ok(Chunked::Part(Part::decode(buf)?)),
+            _ => Err(invalid_data("invalid chunk tag")),
+        }
+    }
+}
+
+impl Encode for Part {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.writer.encode(buf);
+        self.sequence.encode(buf);
+        self.index.encode(buf);
+        self.count.encode(buf);
+        self.bytes.encode(buf);
+    }
+}
+
+impl Decode for Part {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            writer: Id::decode(buf)?,
+            sequence: u64::decode(buf)?,
+            index: u32::decode(buf)?,
+            count: u32::decode(buf)?,
+            bytes: BytesValue::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for SessionToken {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.applied.encode(buf);
+        self.timestamp.encode(buf);
+    }
+}
+
+impl Decode for SessionToken {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            applied: LogIndex::decode(buf)?,
+            timestamp: Timestamp::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for Fence {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.epoch.encode(buf);
+        self.count.encode(buf);
+    }
+}
+
+impl Decode for Fence {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            epoch: u64::decode(buf)?,
+            count: u64::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for Announced {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.id.encode(buf);
+        self.fence.encode(buf);
+    }
+}
+
+impl Decode for Announced {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            id: Id::decode(buf)?,
+            fence: Fence::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for Sealed {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.key.encode(buf);
+        buf.extend_from_slice(&self.tag);
+        self.ciphertext.encode(buf);
+    }
+}
+
+impl Decode for Sealed {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        let key = u32::decode(buf)?;
+        Ok(Self {
+            key,
+            tag: take(buf, sealed::TAG)?.try_into().unwrap(),
+            ciphertext: BytesValue::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for ClientId {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+    }
+}
+
+impl Decode for ClientId {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self(u64::decode(buf)?))
+    }
+}
+
+impl<C: Encode> Encode for SessionRequest<C> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.client.encode(buf);
+        self.sequence.encode(buf);
+        self.command.encode(buf);
+    }
+}
+
+impl<C: Decode> Decode for SessionRequest<C> {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            client: ClientId::decode(buf)?,
+            sequence: u64::decode(buf)?,
+            command: C::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for LeaseOp {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            LeaseOp::Acquire { owner, at, ttl } => {
+                0u8.encode(buf);
+                owner.encode(buf);
+                at.encode(buf);
+                ttl.encode(buf);
+            }
+            LeaseOp::Renew {
+                owner,
+                token,
+                at,
+                ttl,
+            } => {
+                1u8.encode(buf);
+                owner.encode(buf);
+                token.encode(buf);
+                at.encode(buf);
+                ttl.encode(buf);
+            }
+            LeaseOp::Release { owner, token } => {
+                2u8.encode(buf);
+                owner.encode(buf);
+                token.encode(buf);
+            }
+        }
+    }
+}
+
+impl Encode for FencingToken {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.instance.encode(buf);
+        self.round.encode(buf);
+    }
+}
+
+impl Decode for FencingToken {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            instance: InstanceId::decode(buf)?,
+            round: Round::decode(buf)?,
+        })
+    }
+}
+
+impl Decode for LeaseOp {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        match u8::decode(buf)? {
+            0 => Ok(LeaseOp::Acquire {
+                owner: String::decode(buf)?,
+                at: u64::decode(buf)?,
+                ttl: u64::decode(buf)?,
+            }),
+            1 => Ok(LeaseOp::Renew {
+                owner: String::decode(buf)?,
+                token: FencingToken::decode(buf)?,
+                at: u64::decode(buf)?,
+                ttl: u64::decode(buf)?,
+            }),
+            2 => Ok(LeaseOp::Release {
+                owner: String::decode(buf)?,
+                token: FencingToken::decode(buf)?,
+            }),
+            _ => Err(invalid_data("invalid lease op tag")),
+        }
+    }
+}
+
+impl Encode for SocketAddr {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.to_string().encode(buf);
+    }
+}
+
+impl Decode for SocketAddr {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        String::decode(buf)?
+            .parse()
+            .map_err(|_| invalid_data("invalid socket address"))
+    }
+}
+
+impl Encode for Bootstrap {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.members().len() as u64).encode(buf);
+        for (id, addr) in self.members() {
+            id.encode(buf);
+            addr.encode(buf);
+        }
+    }
+}
+
+impl Decode for Bootstrap {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        let len = u64::decode(buf)?;
+        let mut members = Vec::with_capacity(len.min(buf.len() as u64) as usize);
+        for _ in 0..len {
+            members.push((Id::decode(buf)?, SocketAddr::decode(buf)?));
+        }
+        Ok(Bootstrap::new(members))
+    }
+}
+
+impl Encode for Applied {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.next.encode(buf);
+        self.membership.encode(buf);
+        (self.members.len() as u64).encode(buf);
+        for (id, addr) in &self.members {
+            id.encode(buf);
+            addr.encode(buf);
+        }
+        self.epoch.encode(buf);
+    }
+}
+
+impl Decode for Applied {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        let next = LogIndex::decode(buf)?;
+        let membership = Membership::decode(buf)?;
+        let len = u64::decode(buf)?;
+        let mut members = Vec::with_capacity(len.min(buf.len() as u64) as usize);
+        for _ in 0..len {
+            members.push((Id::decode(buf)?, SocketAddr::decode(buf)?));
+        }
+        Ok(Applied {
+            next,
+            membership,
+            members,
+            epoch: u64::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for Member {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.id.encode(buf);
+        self.addr.encode(buf);
+        (self.role == Role::Voter).encode(buf);
+        self.leader.encode(buf);
+        self.reachable.encode(buf);
+        self.epoch.encode(buf);
+        self.commit_index.encode(buf);
+    }
+}
+
+impl Decode for Member {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Member {
+            id: Id::decode(buf)?,
+            addr: SocketAddr::decode(buf)?,
+            role: match bool::decode(buf)? {
+                true => Role::Voter,
+                false => Role::Learner,
+            },
+            leader: bool::decode(buf)?,
+            reachable: bool::decode(buf)?,
+            epoch: Option::decode(buf)?,
+            commit_index: Option::decode(buf)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T: Encode + Decode>(value: &T) -> T {
+        let bytes = to_bytes(value);
+        let decoded: T = from_bytes(&bytes).unwrap();
+        assert_eq!(to_bytes(&decoded), bytes);
+        decoded
+    }
+
+    #[test]
+    fn primitives_round_trip() {
+        assert_eq!(round_trip(&0xfe_u8), 0xfe);
+        assert_eq!(round_trip(&u32::MAX), u32::MAX);
+        assert_eq!(
+            round_trip(&0x0102_0304_0506_0708_u64),
+            0x0102_0304_0506_0708
+        );
+        assert!(round_trip(&true));
+        assert_eq!(round_trip(&"héllo".to_string()), "héllo");
+        assert_eq!(round_trip(&vec![Some(Id(1)), None]), [Some(Id(1)), None]);
+        assert_eq!(to_bytes(&7u32), [0, 0, 0, 7]);
+    }
+
+    #[test]
+    fn protocol_messages_round_trip() {
+        let round = Round::new(Id(3)).next();
+        let response = ReadResponse {
+            acceptor: Id(2),
+            round,
+            status: Status::Rejected(round.next()),
+            state: Alpha {
+                last_round_entered: round,
+                value: Some(Value::new(42u64, round)),
+            },
+        };
+        let decoded = round_trip(&response);
+        assert_eq!(decoded.status, Status::Rejected(round.next()));
+        assert_eq!(decoded.state.value.unwrap().value(), &42);
+
+        let membership = Membership::Joint {
+            old: Configuration::new([Id(1), Id(2)]),
+            new: Configuration::new([Id(2), Id(3)]),
+        };
+        assert_eq!(round_trip(&membership), membership);
+
+        let token = FencingToken {
+            instance: InstanceId(3),
+            round: Round::new(Id(2)),
+        };
+        let op = LeaseOp::Renew {
+            owner: "worker".to_string(),
+            token,
+            at: 10,
+            ttl: 5,
+        };
+        assert_eq!(round_trip(&op), op);
+        let op = LeaseOp::Release {
+            owner: "worker".to_string(),
+            token,
+        };
+        assert_eq!(round_trip(&op), op);
+
+        let session = SessionToken {
+            applied: LogIndex::new(7),
+            timestamp: Timestamp::new(1_000, 3),
+        };
+        assert_eq!(round_trip(&session), session);
+    }
+
+    #[test]
+    fn bootstraps_round_trip() {
+        let bootstrap = Bootstrap::new([
+            (Id(2), "[::1]:7002".parse().unwrap()),
+            (Id(1), "127.0.0.1:7001".parse().unwrap()),
+        ]);
+        assert_eq!(round_trip(&bootstrap), bootstrap);
+        assert!(from_bytes::<SocketAddr>(&to_bytes(&"nowhere".to_string())).is_err());
+    }
+
+    #[test]
+    fn applied_memberships_round_trip() {
+        let applied = Applied {
+            next: LogIndex::new(4),
+            membership: Membership::Joint {
+                old: Configuration::new([Id(1), Id(2)]),
+                new: Configuration::new([Id(1), Id(2), Id(3)]),
+            },
+            members: vec![
+                (Id(1), "127.0.0.1:7001".parse().unwrap()),
+                (Id(3), "[::1]:7003".parse().unwrap()),
+            ],
+            epoch: 2,
+        };
+        assert_eq!(round_trip(&applied), applied);
+    }
+
+    #[test]
+    fn members_round_trip() {
+        let members = vec![
+            Member {
+                id: Id(1),
+                addr: "127.0.0.1:7001".parse().unwrap(),
+                role: Role::Voter,
+                leader: true,
+                reachable: true,
+                epoch: Some(2),
+                commit_index: Some(LogIndex::new(5)),
+            },
+            Member {
+                id: Id(4),
+                addr: "[::1]:7004".parse().unwrap(),
+                role: Role::Learner,
+                leader: false,
+                reachable: false,
+                epoch: None,
+                commit_index: None,
+            },
+        ];
+        assert_eq!(round_trip(&members), members);
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert!(from_bytes::<u64>(&[0; 4]).is_err());
+        assert!(from_bytes::<u32>(&[0; 5]).is_err());
+        assert!(from_bytes::<bool>(&[2]).is_err());
+        assert!(from_bytes::<Option<u8>>(&[9, 0]).is_err());
+        assert!(from_bytes::<Membership>(&[5]).is_err());
+        assert!(from_bytes::<String>(&[0, 0, 0, 0, 0, 0, 0, 2, 0xff, 0xfe]).is_err());
+    }
+
+    #[test]
+    fn untrusted_lengths_do_not_preallocate() {
+        let error = from_bytes::<Vec<u64>>(&u64::MAX.to_be_bytes()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(from_bytes::<String>(&u64::MAX.to_be_bytes()).is_err());
+    }
+}
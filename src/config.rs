@@ -0,0 +1,1262 @@
+use crate::alpha::Id;
+#[cfg(feature = "auth")]
+use crate::auth::Keyring;
+use crate::batch::DEFAULT_MAX_BATCH_SIZE;
+use crate::data_dir::{DataDir, LayoutError};
+use crate::identity::{self, IdentityError};
+use crate::instance::InstanceId;
+use crate::log_filter::{Filter, FilterError};
+use crate::metrics::AdaptiveTimeout;
+use crate::proposer::TickSource;
+use crate::quorum::{QuorumError, QuorumSpec};
+use crate::quota::{QuotaError, Quotas};
+use crate::retry::RetryPolicy;
+use crate::roles::{parse_roles, Permissions};
+use crate::token::{StaticTokens, TokenVerifier};
+use crate::transport::DEFAULT_MAX_MESSAGE_SIZE;
+use derive_new::new;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use std::{fmt, fs, io};
+use thiserror::Error;
+
+#[derive(new, Clone, Debug)]
+pub struct NodeConfig {
+    pub id: Id,
+    pub listen: SocketAddr,
+    pub members: Vec<(Id, SocketAddr)>,
+    #[new(default)]
+    pub storage_path: Option<PathBuf>,
+    /// A [`DataDir`] to keep the node's state in instead of the files
+    /// beside `storage_path`.
+    #[new(default)]
+    pub data_dir: Option<PathBuf>,
+    /// Where to keep the [`DecisionTrail`](crate::audit::DecisionTrail) of
+    /// instances this node decided.
+    #[new(default)]
+    pub decision_trail: Option<PathBuf>,
+    /// The instance this cluster decides, which its decision is filed under
+    /// in the trail.
+    #[new(default)]
+    pub instance: InstanceId,
+    #[new(default)]
+    pub quorum: Option<QuorumSpec>,
+    /// The most values a [`BatchingProposer`](crate::batch::BatchingProposer)
+    /// puts in one entry.
+    #[new(value = "DEFAULT_MAX_BATCH_SIZE")]
+    pub max_batch_size: usize,
+    /// How many applied entries [`Replica::snapshot_every`](crate::smr::Replica::snapshot_every)
+    /// is given; without it snapshots are only taken when asked for.
+    #[new(default)]
+    pub snapshot_every: Option<u64>,
+    /// The consensus groups the node runs besides its own, each with the
+    /// settings above unless it overrides them; see [`group`](Self::group).
+    #[new(default)]
+    pub groups: Vec<GroupConfig>,
+    #[new(value = "Duration::from_millis(100)")]
+    pub heartbeat_interval: Duration,
+    #[new(value = "Duration::from_millis(500)")]
+    pub failure_timeout: Duration,
+    #[new(default)]
+    pub retry_policy: RetryPolicy,
+    #[new(default)]
+    pub stage_timeout: Option<Duration>,
+    #[new(value = "DEFAULT_MAX_MESSAGE_SIZE")]
+    pub max_message_size: usize,
+    #[new(default)]
+    pub tick_source: TickSource,
+    #[new(default)]
+    pub adaptive_timeout: Option<AdaptiveTimeout>,
+    /// Send requests to the fastest majority first, as the adaptive timeouts
+    /// measured them.
+    #[new(default)]
+    pub fastest_quorum: bool,
+    /// What the node logs when it installs the crate's
+    /// [logger](crate::log_filter); changeable while it runs.
+    #[new(default)]
+    pub log_filter: Option<Filter>,
+    /// Checks the tokens clients present with proposals and reads; without
+    /// one every client is served.
+    #[new(default)]
+    pub client_tokens: Option<Arc<dyn TokenVerifier>>,
+    /// How much each client may propose; without them clients are not
+    /// throttled.
+    #[new(default)]
+    pub client_quotas: Option<Quotas>,
+    /// What authenticated identities other than the members may ask for.
+    #[new(default)]
+    pub roles: Vec<(Id, Permissions)>,
+    /// The token the leader issued for this node to
+    /// [join](crate::node::Node::join) with as a voter.
+    #[new(default)]
+    pub join_token: Option<String>,
+    #[cfg(feature = "auth")]
+    #[new(default)]
+    pub keyring: Option<Arc<Keyring>>,
+    /// Where the running node rereads its keyring and client tokens from
+    /// when an operator asks it to.
+    #[new(default)]
+    pub credentials: Option<Arc<dyn CredentialSource>>,
+}
+
+/// The keys and client tokens a running node takes on when it reloads
+/// them; what is `None` is kept.
+#[derive(Clone, Debug, Default)]
+pub struct Credentials {
+    #[cfg(feature = "auth")]
+    pub keyring: Option<Keyring>,
+    pub client_tokens: Option<Arc<dyn TokenVerifier>>,
+}
+
+pub trait CredentialSource: fmt::Debug + Send + Sync {
+    fn load(&self) -> Result<Credentials, ConfigError>;
+}
+
+/// The settings every node of a cluster shares, plus where each member
+/// listens and keeps its state. [`ClusterConfig::node`] derives the
+/// [`NodeConfig`] a single member starts with.
+#[derive(new, Clone, Debug)]
+pub struct ClusterConfig {
+    pub members: Vec<MemberConfig>,
+    #[new(default)]
+    pub instance: InstanceId,
+    #[new(default)]
+    pub quorum: Option<QuorumSpec>,
+    #[new(value = "DEFAULT_MAX_BATCH_SIZE")]
+    pub max_batch_size: usize,
+    #[new(default)]
+    pub snapshot_every: Option<u64>,
+    /// Parsed from the `[[groups]]` tables.
+    #[new(default)]
+    pub groups: Vec<GroupConfig>,
+    #[new(value = "Duration::from_millis(100)")]
+    pub heartbeat_interval: Duration,
+    #[new(value = "Duration::from_millis(500)")]
+    pub failure_timeout: Duration,
+    #[new(default)]
+    pub retry_policy: RetryPolicy,
+    #[new(default)]
+    pub stage_timeout: Option<Duration>,
+    #[new(value = "DEFAULT_MAX_MESSAGE_SIZE")]
+    pub max_message_size: usize,
+    #[new(default)]
+    pub tick_source: TickSource,
+    #[new(default)]
+    pub adaptive_timeout: Option<AdaptiveTimeout>,
+    #[new(default)]
+    pub fastest_quorum: bool,
+    #[new(default)]
+    pub log_filter: Option<Filter>,
+    /// Parsed from `client_tokens` as [`StaticTokens`].
+    #[new(default)]
+    pub client_tokens: Option<Arc<dyn TokenVerifier>>,
+    /// Parsed from `client_quotas`, e.g. `proposals:100,9=bytes:4096`.
+    #[new(default)]
+    pub client_quotas: Option<Quotas>,
+    /// Parsed from `roles`, e.g. `9=admin+watch,10=read`.
+    #[new(default)]
+    pub roles: Vec<(Id, Permissions)>,
+}
+
+#[derive(new, Clone, Debug)]
+pub struct MemberConfig {
+    pub id: Id,
+    pub addr: SocketAddr,
+    /// Where the member binds, when that differs from the address its peers
+    /// dial (for example `0.0.0.0:7000`).
+    #[new(default)]
+    pub listen: Option<SocketAddr>,
+    #[new(default)]
+    pub storage_path: Option<PathBuf>,
+    #[new(default)]
+    pub data_dir: Option<PathBuf>,
+    #[new(default)]
+    pub decision_trail: Option<PathBuf>,
+}
+
+/// One more consensus group the members run, such as a small metadata
+/// group beside a bulk-data one; what it leaves `None` it takes from the
+/// node.
+#[derive(new, Clone, Debug)]
+pub struct GroupConfig {
+    pub name: String,
+    #[new(default)]
+    pub quorum: Option<QuorumSpec>,
+    #[new(default)]
+    pub max_batch_size: Option<usize>,
+    #[new(default)]
+    pub snapshot_every: Option<u64>,
+    #[new(default)]
+    pub storage_path: Option<PathBuf>,
+}
+
+/// What a group runs with, as [`NodeConfig::group`] resolves it.
+#[derive(Clone, Debug)]
+pub struct GroupSettings {
+    pub name: String,
+    pub quorum: QuorumSpec,
+    pub max_batch_size: usize,
+    pub snapshot_every: Option<u64>,
+    /// `None` for a node that keeps no state.
+    pub storage_path: Option<PathBuf>,
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("failed to read config")]
+    Io(#[from] io::Error),
+    #[error("line {line}: {message}")]
+    Syntax { line: usize, message: String },
+    #[error("missing key `{0}`")]
+    Missing(String),
+    #[error("invalid value for `{key}`: {message}")]
+    Invalid { key: String, message: String },
+    #[error("node {0:?} is not one of the members")]
+    NotAMember(Id),
+    #[error("no group named `{0}`")]
+    UnknownGroup(String),
+    #[error("`heartbeat_interval_ms` must be shorter than `failure_timeout_ms`")]
+    HeartbeatTooSlow,
+    #[error("invalid `quorum`")]
+    Quorum(#[from] QuorumError),
+    #[error("node identity")]
+    Identity(#[from] IdentityError),
+    #[error("data directory")]
+    Layout(#[from] LayoutError),
+}
+
+impl NodeConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        Self::from_toml(&fs::read_to_string(path)?)
+    }
+
+    /// Like [`load`](Self::load), but first creates the file's `data_dir`,
+    /// or migrates an older one, and caches the file in it.
+    pub fn init(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path)?;
+        let (mut root, _, _) = parse(&text)?;
+        let dir = match root.optional_string("data_dir")? {
+            Some(dir) => DataDir::init(dir, Some(&text))?,
+            None => return Err(ConfigError::Missing("data_dir".to_string())),
+        };
+        let config = Self::from_toml(&text)?;
+        dir.identity(Some(config.id))?;
+        Ok(config)
+    }
+
+    /// Parses a node file: a cluster file whose root also names this node's
+    /// `id`, `listen` address, `storage_path` or `data_dir`, and
+    /// `decision_trail`. A node that keeps state may leave out its `id`: it
+    /// is the one stored there, or a random one that is stored on first
+    /// start.
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        let (mut root, members, groups) = parse(text)?;
+        let storage_path = root.optional_string("storage_path")?.map(PathBuf::from);
+        let data_dir = root.optional_string("data_dir")?.map(PathBuf::from);
+        let id = match (root.optional_id("id")?, &storage_path, &data_dir) {
+            (Some(id), _, _) => id,
+            (None, _, Some(dir)) => DataDir::open(dir)?.identity(None)?,
+            (None, Some(path), None) => identity::assign(identity::beside(path), None)?,
+            (None, None, None) => return Err(ConfigError::Missing("id".to_string())),
+        };
+        let listen = root.addr("listen")?;
+        let decision_trail = root.optional_string("decision_trail")?.map(PathBuf::from);
+        let cluster = ClusterConfig::from_tables(root, members, groups)?;
+        let mut config = cluster.node(id)?;
+        config.listen = listen;
+        if storage_path.is_some() || data_dir.is_some() {
+            config.storage_path = storage_path;
+            config.data_dir = data_dir;
+        }
+        if decision_trail.is_some() {
+            config.decision_trail = decision_trail;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.quorum_spec().validate()?;
+        if !self.members.iter().any(|(id, _)| *id == self.id) {
+            return Err(ConfigError::NotAMember(self.id));
+        }
+        for (index, (_, addr)) in self.members.iter().enumerate() {
+            if self.members[..index].iter().any(|(_, other)| other == addr) {
+                return Err(invalid("members", "duplicate member address"));
+            }
+        }
+        if self.storage_path.is_some() && self.data_dir.is_some() {
+            return Err(invalid(
+                "data_dir",
+                "give either `storage_path` or `data_dir`",
+            ));
+        }
+        if self.heartbeat_interval >= self.failure_timeout {
+            return Err(ConfigError::HeartbeatTooSlow);
+        }
+        if self.fastest_quorum && self.adaptive_timeout.is_none() {
+            return Err(invalid(
+                "targets",
+                "the fastest quorum needs adaptive timeouts",
+            ));
+        }
+        validate_groups(self.max_batch_size, self.snapshot_every, &self.groups)?;
+        if let Some(index) = self.groups.iter().position(|group| {
+            group.storage_path.is_some() && group.storage_path == self.storage_path
+        }) {
+            return Err(invalid(
+                &format!("groups[{index}].storage_path"),
+                "the node keeps its own state there",
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn quorum_spec(&self) -> QuorumSpec {
+        self.quorum.clone().unwrap_or_else(|| QuorumSpec::Majority {
+            members: self.members.iter().map(|(id, _)| *id).collect(),
+        })
+    }
+
+    /// The settings group `name` runs with. Unless it gives its own, it
+    /// keeps its state beside `storage_path` with a `.group-<name>` suffix,
+    /// or in `groups/<name>` of the `data_dir`, so no two groups share a
+    /// log.
+    pub fn group(&self, name: &str) -> Result<GroupSettings, ConfigError> {
+        let group = self
+            .groups
+            .iter()
+            .find(|group| group.name == name)
+            .ok_or_else(|| ConfigError::UnknownGroup(name.to_string()))?;
+        let storage_path = match (&group.storage_path, &self.storage_path, &self.data_dir) {
+            (Some(path), _, _) => Some(path.clone()),
+            (None, Some(path), _) => {
+                let mut path = path.clone().into_os_string();
+                path.push(format!(".group-{name}"));
+                Some(PathBuf::from(path))
+            }
+            (None, None, Some(dir)) => Some(dir.join("groups").join(name)),
+            (None, None, None) => None,
+        };
+        Ok(GroupSettings {
+            name: group.name.clone(),
+            quorum: group.quorum.clone().unwrap_or_else(|| self.quorum_spec()),
+            max_batch_size: group.max_batch_size.unwrap_or(self.max_batch_size),
+            snapshot_every: group.snapshot_every.or(self.snapshot_every),
+            storage_path,
+        })
+    }
+}
+
+impl ClusterConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        Self::from_toml(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        let (root, members, groups) = parse(text)?;
+        Self::from_tables(root, members, groups)
+    }
+
+    fn from_tables(
+        mut root: Table,
+        members: Vec<Table>,
+        groups: Vec<Table>,
+    ) -> Result<Self, ConfigError> {
+        let members = members
+            .into_iter()
+            .map(|mut member| {
+                let mut config = MemberConfig::new(member.id("id")?, member.addr("addr")?);
+                config.listen = member.optional_addr("listen")?;
+                config.storage_path = member.optional_string("storage_path")?.map(PathBuf::from);
+                config.data_dir = member.optional_string("data_dir")?.map(PathBuf::from);
+                config.decision_trail =
+                    member.optional_string("decision_trail")?.map(PathBuf::from);
+                member.finish()?;
+                Ok(config)
+            })
+            .collect::<Result<Vec<_>, ConfigError>>()?;
+
+        let mut config = ClusterConfig::new(members);
+        if let Some(instance) = root.optional_integer("instance")? {
+            config.instance = InstanceId(instance);
+        }
+        if let Some(ms) = root.optional_integer("heartbeat_interval_ms")? {
+            config.heartbeat_interval = Duration::from_millis(ms);
+        }
+        if let Some(ms) = root.optional_integer("failure_timeout_ms")? {
+            config.failure_timeout = Duration::from_millis(ms);
+        }
+        config.stage_timeout = root
+            .optional_integer("stage_timeout_ms")?
+            .map(Duration::from_millis);
+        config.retry_policy.attempt_timeout = root
+            .optional_integer("attempt_timeout_ms")?
+            .map(Duration::from_millis);
+        if let Some(size) = root.optional_integer("max_message_size")? {
+            config.max_message_size = size as usize;
+        }
+        if let Some(size) = root.optional_integer("max_batch_size")? {
+            config.max_batch_size = size as usize;
+        }
+        config.snapshot_every = root.optional_integer("snapshot_every")?;
+        config.retry_policy.max_attempts = root
+            .optional_integer("max_attempts")?
+            .map(|attempts| attempts as usize);
+
+        config.tick_source = match root.optional_string("tick_source")?.as_deref() {
+            None | Some("counter") => TickSource::Counter,
+            Some("hybrid") => TickSource::Hybrid,
+            Some(other) => {
+                return Err(ConfigError::Invalid {
+                    key: "tick_source".to_string(),
+                    message: format!("unknown tick source `{other}`"),
+                })
+            }
+        };
+
+        config.adaptive_timeout = match root.optional_string("timeouts")?.as_deref() {
+            None | Some("static") => None,
+            Some("adaptive") => {
+                let mut policy = AdaptiveTimeout::default();
+                if let Some(ms) = root.optional_integer("timeout_margin_ms")? {
+                    policy.margin = Duration::from_millis(ms);
+                }
+                Some(policy)
+            }
+            Some(other) => {
+                return Err(ConfigError::Invalid {
+                    key: "timeouts".to_string(),
+                    message: format!("unknown timeout mode `{other}`"),
+                })
+            }
+        };
+
+        config.fastest_quorum = match root.optional_string("targets")?.as_deref() {
+            None | Some("everyone") => false,
+            Some("fastest_quorum") => true,
+            Some(other) => {
+                return Err(ConfigError::Invalid {
+                    key: "targets".to_string(),
+                    message: format!("unknown targets `{other}`"),
+                })
+            }
+        };
+
+        let ids: Vec<Id> = config.members.iter().map(|member| member.id).collect();
+        config.quorum = quorum(&mut root, &ids)?
+            .filter(|quorum| !matches!(quorum, QuorumSpec::Majority { .. }));
+        config.groups = groups
+            .into_iter()
+            .map(|mut group| {
+                let mut config = GroupConfig::new(group.string("name")?);
+                config.quorum = quorum(&mut group, &ids)?;
+                config.max_batch_size = group
+                    .optional_integer("max_batch_size")?
+                    .map(|size| size as usize);
+                config.snapshot_every = group.optional_integer("snapshot_every")?;
+                config.storage_path = group.optional_string("storage_path")?.map(PathBuf::from);
+                group.finish()?;
+                Ok(config)
+            })
+            .collect::<Result<Vec<_>, ConfigError>>()?;
+        config.log_filter = root
+            .optional_string("log_filter")?
+            .map(|filter| {
+                filter
+                    .parse()
+                    .map_err(|error: FilterError| invalid("log_filter", &error.to_string()))
+            })
+            .transpose()?;
+        config.client_tokens = root
+            .optional_string("client_tokens")?
+            .map(|tokens| {
+                tokens
+                    .parse::<StaticTokens>()
+                    .map(|tokens| Arc::new(tokens) as Arc<dyn TokenVerifier>)
+                    .map_err(|error| invalid("client_tokens", &error.to_string()))
+            })
+            .transpose()?;
+        config.client_quotas = root
+            .optional_string("client_quotas")?
+            .map(|quotas| {
+                quotas
+                    .parse()
+                    .map_err(|error: QuotaError| invalid("client_quotas", &error.to_string()))
+            })
+            .transpose()?;
+        if let Some(roles) = root.optional_string("roles")? {
+            config.roles =
+                parse_roles(&roles).map_err(|error| invalid("roles", &error.to_string()))?;
+        }
+        root.finish()?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.members.is_empty() {
+            return Err(ConfigError::Missing("members".to_string()));
+        }
+        for (index, member) in self.members.iter().enumerate() {
+            if self.members[..index]
+                .iter()
+                .any(|other| other.id == member.id)
+            {
+                return Err(invalid(
+                    &format!("members[{index}].id"),
+                    "duplicate member id",
+                ));
+            }
+            if self.members[..index]
+                .iter()
+                .any(|other| other.addr == member.addr)
+            {
+                return Err(invalid(
+                    &format!("members[{index}].addr"),
+                    "duplicate member address",
+                ));
+            }
+        }
+        if let Some((id, _)) = self
+            .roles
+            .iter()
+            .find(|(id, _)| self.members.iter().any(|member| member.id == *id))
+        {
+            return Err(invalid(
+                "roles",
+                &format!(
+                    "member {} takes part in consensus, so has no role",
+                    id.get()
+                ),
+            ));
+        }
+        if self.heartbeat_interval >= self.failure_timeout {
+            return Err(ConfigError::HeartbeatTooSlow);
+        }
+        self.quorum_spec().validate()?;
+        validate_groups(self.max_batch_size, self.snapshot_every, &self.groups)
+    }
+
+    pub fn quorum_spec(&self) -> QuorumSpec {
+        self.quorum.clone().unwrap_or_else(|| QuorumSpec::Majority {
+            members: self.members.iter().map(|member| member.id).collect(),
+        })
+    }
+
+    /// The configuration member `id` starts with: it listens on its own
+    /// address and keeps its own storage.
+    pub fn node(&self, id: Id) -> Result<NodeConfig, ConfigError> {
+        let member = self
+            .members
+            .iter()
+            .find(|member| member.id == id)
+            .ok_or(ConfigError::NotAMember(id))?;
+        let members = self
+            .members
+            .iter()
+            .map(|member| (member.id, member.addr))
+            .collect();
+        let mut config = NodeConfig::new(id, member.listen.unwrap_or(member.addr), members);
+        config.storage_path = member.storage_path.clone();
+        config.data_dir = member.data_dir.clone();
+        config.decision_trail = member.decision_trail.clone();
+        config.instance = self.instance;
+        config.quorum = self.quorum.clone();
+        config.max_batch_size = self.max_batch_size;
+        config.snapshot_every = self.snapshot_every;
+        config.groups = self.groups.clone();
+        config.heartbeat_interval = self.heartbeat_interval;
+        config.failure_timeout = self.failure_timeout;
+        config.retry_policy = self.retry_policy.clone();
+        config.stage_timeout = self.stage_timeout;
+        config.max_message_size = self.max_message_size;
+        config.tick_source = self.tick_source;
+        config.adaptive_timeout = self.adaptive_timeout;
+        config.fastest_quorum = self.fastest_quorum;
+        config.log_filter = self.log_filter.clone();
+        config.client_tokens = self.client_tokens.clone();
+        config.client_quotas = self.client_quotas.clone();
+        config.roles = self.roles.clone();
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+enum Scalar {
+    Integer(u64),
+    String(String),
+}
+
+/// Keys in a `[[members]]` or `[[groups]]` table are reported with their
+/// position, e.g. `members[1].addr`.
+#[derive(Default)]
+struct Table {
+    prefix: String,
+    entries: BTreeMap<String, Scalar>,
+}
+
+impl Table {
+    fn element(array: &str, index: usize) -> Self {
+        Self {
+            prefix: format!("{array}[{index}]."),
+            entries: BTreeMap::new(),
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{key}", self.prefix)
+    }
+
+    fn optional_integer(&mut self, key: &str) -> Result<Option<u64>, ConfigError> {
+        match self.entries.remove(key) {
+            None => Ok(None),
+            Some(Scalar::Integer(value)) => Ok(Some(value)),
+            Some(Scalar::String(_)) => Err(invalid(&self.key(key), "expected an integer")),
+        }
+    }
+
+    fn optional_string(&mut self, key: &str) -> Result<Option<String>, ConfigError> {
+        match self.entries.remove(key) {
+            None => Ok(None),
+            Some(Scalar::String(value)) => Ok(Some(value)),
+            Some(Scalar::Integer(_)) => Err(invalid(&self.key(key), "expected a string")),
+        }
+    }
+
+    fn integer(&mut self, key: &str) -> Result<u64, ConfigError> {
+        self.optional_integer(key)?
+            .ok_or_else(|| ConfigError::Missing(self.key(key)))
+    }
+
+    fn string(&mut self, key: &str) -> Result<String, ConfigError> {
+        self.optional_string(key)?
+            .ok_or_else(|| ConfigError::Missing(self.key(key)))
+    }
+
+    fn id(&mut self, key: &str) -> Result<Id, ConfigError> {
+        self.optional_id(key)?
+            .ok_or_else(|| ConfigError::Missing(self.key(key)))
+    }
+
+    fn optional_id(&mut self, key: &str) -> Result<Option<Id>, ConfigError> {
+        match self.entries.remove(key) {
+            None => Ok(None),
+            Some(Scalar::Integer(id)) => Ok(Some(Id::new(id))),
+            Some(Scalar::String(name)) => Ok(Some(Id::from_name(&name))),
+        }
+    }
+
+    fn optional_addr(&mut self, key: &str) -> Result<Option<SocketAddr>, ConfigError> {
+        self.optional_string(key)?
+            .map(|addr| {
+                addr.parse()
+                    .map_err(|_| invalid(&self.key(key), "expected a socket address"))
+            })
+            .transpose()
+    }
+
+    fn addr(&mut self, key: &str) -> Result<SocketAddr, ConfigError> {
+        self.optional_addr(key)?
+            .ok_or_else(|| ConfigError::Missing(self.key(key)))
+    }
+
+    fn finish(self) -> Result<(), ConfigError> {
+        match self.entries.keys().next() {
+            None => Ok(()),
+            Some(key) => Err(invalid(&self.key(key), "unknown key")),
+        }
+    }
+}
+
+fn invalid(key: &str, message: &str) -> ConfigError {
+    ConfigError::Invalid {
+        key: key.to_string(),
+        message: message.to_string(),
+    }
+}
+
+/// The quorum `table` names over the members `ids`. An explicit
+/// `majority` is kept, so a group can name it over a root quorum.
+fn quorum(table: &mut Table, ids: &[Id]) -> Result<Option<QuorumSpec>, ConfigError> {
+    let members = ids.to_vec();
+    Ok(match table.optional_string("quorum")?.as_deref() {
+        None => None,
+        Some("majority") => Some(QuorumSpec::Majority { members }),
+        Some("flexible") => Some(QuorumSpec::Flexible {
+            members,
+            read: table.integer("read_quorum")? as usize,
+            write: table.integer("write_quorum")? as usize,
+        }),
+        Some("overlap") => Some(QuorumSpec::Overlap {
+            members,
+            faults: table.integer("overlap_faults")? as usize,
+        }),
+        Some(other) => {
+            return Err(invalid(
+                &table.key("quorum"),
+                &format!("unknown policy `{other}`"),
+            ))
+        }
+    })
+}
+
+fn validate_groups(
+    max_batch_size: usize,
+    snapshot_every: Option<u64>,
+    groups: &[GroupConfig],
+) -> Result<(), ConfigError> {
+    if max_batch_size == 0 {
+        return Err(invalid("max_batch_size", "must be at least 1"));
+    }
+    if snapshot_every == Some(0) {
+        return Err(invalid("snapshot_every", "must be at least 1"));
+    }
+    for (index, group) in groups.iter().enumerate() {
+        let key = |key: &str| format!("groups[{index}].{key}");
+        if group.name.is_empty()
+            || !group
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(invalid(
+                &key("name"),
+                "expected letters, digits, `_` and `-`",
+            ));
+        }
+        if groups[..index].iter().any(|other| other.name == group.name) {
+            return Err(invalid(&key("name"), "duplicate group name"));
+        }
+        if let Some(quorum) = &group.quorum {
+            quorum
+                .validate()
+                .map_err(|error| invalid(&key("quorum"), &error.to_string()))?;
+        }
+        if group.max_batch_size == Some(0) {
+            return Err(invalid(&key("max_batch_size"), "must be at least 1"));
+        }
+        if group.snapshot_every == Some(0) {
+            return Err(invalid(&key("snapshot_every"), "must be at least 1"));
+        }
+        if group.storage_path.is_some()
+            && groups[..index]
+                .iter()
+                .any(|other| other.storage_path == group.storage_path)
+        {
+            return Err(invalid(
+                &key("storage_path"),
+                "duplicate group storage path",
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn parse(text: &str) -> Result<(Table, Vec<Table>, Vec<Table>), ConfigError> {
+    let mut root = Table::default();
+    let mut members: Vec<Table> = Vec::new();
+    let mut groups: Vec<Table> = Vec::new();
+    let mut in_groups = false;
+    for (number, line) in text.lines().enumerate() {
+        let line_number = number + 1;
+        let syntax = |message: &str| ConfigError::Syntax {
+            line: line_number,
+            message: message.to_string(),
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            in_groups = match strip_comment(line) {
+                "[[members]]" => false,
+                "[[groups]]" => true,
+                _ => {
+                    return Err(syntax(
+                        "only [[members]] and [[groups]] tables are supported",
+                    ))
+                }
+            };
+            match in_groups {
+                true => groups.push(Table::element("groups", groups.len())),
+                false => members.push(Table::element("members", members.len())),
+            }
+            continue;
+        }
+
+        let (key, rest) = line
+            .split_once('=')
+            .ok_or_else(|| syntax("expected `key = value`"))?;
+        let key = key.trim();
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(syntax("invalid key"));
+        }
+        let (value, rest) = scalar(rest.trim()).ok_or_else(|| syntax("invalid value"))?;
+        if !strip_comment(rest).is_empty() {
+            return Err(syntax("unexpected characters after value"));
+        }
+        let table = match in_groups {
+            true => groups.last_mut(),
+            false => members.last_mut(),
+        };
+        if table
+            .unwrap_or(&mut root)
+            .entries
+            .insert(key.to_string(), value)
+            .is_some()
+        {
+            return Err(syntax("duplicate key"));
+        }
+    }
+    Ok((root, members, groups))
+}
+
+fn scalar(text: &str) -> Option<(Scalar, &str)> {
+    if let Some(quoted) = text.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = quoted.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Some((Scalar::String(value), &quoted[i + 1..])),
+                '\\' => match chars.next()?.1 {
+                    '"' => value.push('"'),
+                    '\\' => value.push('\\'),
+                    'n' => value.push('\n'),
+                    't' => value.push('\t'),
+                    _ => return None,
+                },
+                c => value.push(c),
+            }
+        }
+        return None;
+    }
+    let end = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '_'))
+        .unwrap_or(text.len());
+    let digits: String = text[..end].chars().filter(|c| *c != '_').collect();
+    Some((Scalar::Integer(digits.parse().ok()?), &text[end..]))
+}
+
+fn strip_comment(text: &str) -> &str {
+    text.split_once('#')
+        .map_or(text, |(before, _)| before)
+        .trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::Access;
+    use std::num::NonZeroU64;
+
+    const CLUSTER: &str = r#"
+heartbeat_interval_ms = 50
+instance = 3
+
+[[members]]
+id = 1
+addr = "10.0.0.1:7000"
+listen = "0.0.0.0:7000"
+storage_path = "/var/lib/paxos/1"
+
+[[members]]
+id = 2
+addr = "10.0.0.2:7000"
+"#;
+
+    #[test]
+    fn derives_each_members_node_config() {
+        let cluster = ClusterConfig::from_toml(CLUSTER).unwrap();
+        let first = cluster.node(Id(1)).unwrap();
+        assert_eq!(first.listen, "0.0.0.0:7000".parse().unwrap());
+        assert_eq!(first.storage_path, Some(PathBuf::from("/var/lib/paxos/1")));
+        assert_eq!(first.heartbeat_interval, Duration::from_millis(50));
+        assert_eq!(first.instance, InstanceId(3));
+        assert_eq!(first.members.len(), 2);
+
+        let second = cluster.node(Id(2)).unwrap();
+        assert_eq!(second.listen, "10.0.0.2:7000".parse().unwrap());
+        assert_eq!(second.storage_path, None);
+        assert!(matches!(
+            cluster.node(Id(3)),
+            Err(ConfigError::NotAMember(Id(3)))
+        ));
+    }
+
+    #[test]
+    fn errors_name_the_member_field() {
+        let bad_addr = CLUSTER.replace("10.0.0.2:7000", "nowhere");
+        match ClusterConfig::from_toml(&bad_addr) {
+            Err(ConfigError::Invalid { key, .. }) => assert_eq!(key, "members[1].addr"),
+            other => panic!("unexpected {other:?}"),
+        }
+
+        let missing_id = CLUSTER.replace("id = 2\n", "");
+        match ClusterConfig::from_toml(&missing_id) {
+            Err(ConfigError::Missing(key)) => assert_eq!(key, "members[1].id"),
+            other => panic!("unexpected {other:?}"),
+        }
+
+        let duplicate = CLUSTER.replace("id = 2", "id = 1");
+        match ClusterConfig::from_toml(&duplicate) {
+            Err(ConfigError::Invalid { key, .. }) => assert_eq!(key, "members[1].id"),
+            other => panic!("unexpected {other:?}"),
+        }
+
+        let unknown = CLUSTER.replace("id = 2", "id = 2\nport = 1");
+        match ClusterConfig::from_toml(&unknown) {
+            Err(ConfigError::Invalid { key, .. }) => assert_eq!(key, "members[1].port"),
+            other => panic!("unexpected {other:?}"),
+        }
+
+        let same_addr = CLUSTER.replace("10.0.0.2:7000", "10.0.0.1:7000");
+        match ClusterConfig::from_toml(&same_addr) {
+            Err(ConfigError::Invalid { key, .. }) => assert_eq!(key, "members[1].addr"),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn fastest_quorum_needs_adaptive_timeouts() {
+        let fastest = format!("targets = \"fastest_quorum\"\n{CLUSTER}");
+        match ClusterConfig::from_toml(&fastest).unwrap().node(Id(1)) {
+            Err(ConfigError::Invalid { key, .. }) => assert_eq!(key, "targets"),
+            other => panic!("unexpected {other:?}"),
+        }
+        let adaptive = format!("timeouts = \"adaptive\"\n{fastest}");
+        let node = ClusterConfig::from_toml(&adaptive).unwrap().node(Id(1));
+        assert!(node.unwrap().fastest_quorum);
+    }
+
+    #[test]
+    fn parses_the_roles_of_identities_other_than_the_members() {
+        let roles = format!("roles = \"9=admin+watch,10=read\"\n{CLUSTER}");
+        let node = ClusterConfig::from_toml(&roles)
+            .unwrap()
+            .node(Id(1))
+            .unwrap();
+        assert_eq!(
+            node.roles,
+            [
+                (Id(9), Permissions::ADMIN.union(Permissions::WATCH)),
+                (Id(10), Permissions::READ),
+            ]
+        );
+        for invalid in ["roles = \"9=root\"", "roles = \"1=read\""] {
+            match ClusterConfig::from_toml(&format!("{invalid}\n{CLUSTER}")) {
+                Err(ConfigError::Invalid { key, .. }) => assert_eq!(key, "roles"),
+                other => panic!("unexpected {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn parses_the_client_tokens() {
+        let tokens = format!("client_tokens = \"write:w-secret,read:r-secret\"\n{CLUSTER}");
+        let node = ClusterConfig::from_toml(&tokens)
+            .unwrap()
+            .node(Id(1))
+            .unwrap();
+        let verifier = node.client_tokens.unwrap();
+        assert_eq!(verifier.verify("w-secret", Access::Write), Ok(()));
+        assert!(verifier.verify("r-secret", Access::Write).is_err());
+        assert!(ClusterConfig::from_toml(CLUSTER)
+            .unwrap()
+            .client_tokens
+            .is_none());
+
+        let invalid = format!("client_tokens = \"admin:secret\"\n{CLUSTER}");
+        match ClusterConfig::from_toml(&invalid) {
+            Err(ConfigError::Invalid { key, .. }) => assert_eq!(key, "client_tokens"),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_the_client_quotas() {
+        let quotas = format!("client_quotas = \"proposals:100,9=bytes:4096\"\n{CLUSTER}");
+        let node = ClusterConfig::from_toml(&quotas)
+            .unwrap()
+            .node(Id(1))
+            .unwrap();
+        let quotas = node.client_quotas.unwrap();
+        assert_eq!(quotas.default.proposals, NonZeroU64::new(100));
+        assert_eq!(quotas.identities[0].0, Id(9));
+        assert_eq!(quotas.identities[0].1.bytes, NonZeroU64::new(4096));
+
+        let invalid = format!("client_quotas = \"proposals:many\"\n{CLUSTER}");
+        match ClusterConfig::from_toml(&invalid) {
+            Err(ConfigError::Invalid { key, .. }) => assert_eq!(key, "client_quotas"),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_the_log_filter() {
+        let filtered = format!("log_filter = \"warn,paxos_classic::node=debug\"\n{CLUSTER}");
+        let node = ClusterConfig::from_toml(&filtered)
+            .unwrap()
+            .node(Id(2))
+            .unwrap();
+        assert_eq!(
+            node.log_filter.map(|filter| filter.to_string()).as_deref(),
+            Some("warn,paxos_classic::node=debug")
+        );
+        let loud = format!("log_filter = \"loud\"\n{CLUSTER}");
+        match ClusterConfig::from_toml(&loud) {
+            Err(ConfigError::Invalid { key, .. }) => assert_eq!(key, "log_filter"),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    fn grouped() -> String {
+        format!(
+            r#"max_batch_size = 16
+snapshot_every = 1000
+quorum = "flexible"
+read_quorum = 2
+write_quorum = 1
+{CLUSTER}
+[[groups]]
+name = "metadata"
+quorum = "majority"
+max_batch_size = 1
+
+[[groups]]
+name = "bulk"
+snapshot_every = 100_000
+storage_path = "/mnt/bulk/log"
+"#
+        )
+    }
+
+    #[test]
+    fn groups_override_what_they_name_and_inherit_the_rest() {
+        let cluster = ClusterConfig::from_toml(&grouped()).unwrap();
+        let first = cluster.node(Id(1)).unwrap();
+        assert_eq!(first.max_batch_size, 16);
+        let metadata = first.group("metadata").unwrap();
+        assert!(matches!(
+            &metadata.quorum,
+            QuorumSpec::Majority { members } if *members == [Id(1), Id(2)]
+        ));
+        assert_eq!(metadata.max_batch_size, 1);
+        assert_eq!(metadata.snapshot_every, Some(1000));
+        assert_eq!(
+            metadata.storage_path,
+            Some(PathBuf::from("/var/lib/paxos/1.group-metadata"))
+        );
+
+        let bulk = first.group("bulk").unwrap();
+        assert!(matches!(
+            bulk.quorum,
+            QuorumSpec::Flexible {
+                read: 2,
+                write: 1,
+                ..
+            }
+        ));
+        assert_eq!(bulk.max_batch_size, 16);
+        assert_eq!(bulk.snapshot_every, Some(100_000));
+        assert_eq!(bulk.storage_path, Some(PathBuf::from("/mnt/bulk/log")));
+        assert!(matches!(
+            first.group("audit"),
+            Err(ConfigError::UnknownGroup(name)) if name == "audit"
+        ));
+
+        let mut second = cluster.node(Id(2)).unwrap();
+        assert_eq!(second.group("metadata").unwrap().storage_path, None);
+        second.data_dir = Some(PathBuf::from("/data"));
+        assert_eq!(
+            second.group("metadata").unwrap().storage_path,
+            Some(PathBuf::from("/data/groups/metadata"))
+        );
+
+        let plain = ClusterConfig::from_toml(CLUSTER).unwrap();
+        assert_eq!(plain.max_batch_size, DEFAULT_MAX_BATCH_SIZE);
+        assert_eq!(plain.snapshot_every, None);
+        assert!(plain.groups.is_empty());
+    }
+
+    #[test]
+    fn group_errors_name_the_group_field() {
+        let text = grouped();
+        for (from, to, expected) in [
+            (
+                "snapshot_every = 100_000",
+                "quorum = \"grid\"",
+                "groups[1].quorum",
+            ),
+            (
+                "snapshot_every = 100_000",
+                "quorum = \"flexible\"\nread_quorum = 1\nwrite_quorum = 1",
+                "groups[1].quorum",
+            ),
+            ("\"bulk\"", "\"metadata\"", "groups[1].name"),
+            ("\"bulk\"", "\"a/b\"", "groups[1].name"),
+            (
+                "snapshot_every = 100_000",
+                "replicas = 3",
+                "groups[1].replicas",
+            ),
+            (
+                "max_batch_size = 1\n",
+                "max_batch_size = 0\n",
+                "groups[0].max_batch_size",
+            ),
+            (
+                "snapshot_every = 1000",
+                "snapshot_every = 0",
+                "snapshot_every",
+            ),
+            (
+                "\"/mnt/bulk/log\"",
+                "\"/var/lib/paxos/1\"",
+                "groups[1].storage_path",
+            ),
+        ] {
+            let changed = text.replacen(from, to, 1);
+            match ClusterConfig::from_toml(&changed).and_then(|cluster| cluster.node(Id(1))) {
+                Err(ConfigError::Invalid { key, .. }) => assert_eq!(key, expected, "{to}"),
+                other => panic!("{to}: unexpected {other:?}"),
+            }
+        }
+        match ClusterConfig::from_toml(&text.replace("name = \"bulk\"\n", "")) {
+            Err(ConfigError::Missing(key)) => assert_eq!(key, "groups[1].name"),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn node_config_rejects_duplicate_addresses() {
+        let addr: SocketAddr = "10.0.0.1:7000".parse().unwrap();
+        let config = NodeConfig::new(Id(1), addr, vec![(Id(1), addr), (Id(2), addr)]);
+        match config.validate() {
+            Err(ConfigError::Invalid { key, .. }) => assert_eq!(key, "members"),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_scalars_and_comments() {
+        let (mut root, members, _) = parse(
+            r#"
+# a comment
+name = "a \"quoted\" \\ path # not a comment" # trailing
+size = 1_048_576
+
+[[members]] # first
+id = "alpha"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            root.optional_string("name").unwrap().as_deref(),
+            Some("a \"quoted\" \\ path # not a comment")
+        );
+        assert_eq!(root.integer("size").unwrap(), 1_048_576);
+        assert!(root.finish().is_ok());
+        let mut member = members.into_iter().next().unwrap();
+        assert_eq!(member.id("id").unwrap(), Id::from_name("alpha"));
+        assert!(matches!(
+            member.integer("missing"),
+            Err(ConfigError::Missing(key)) if key == "members[0].missing"
+        ));
+    }
+
+    #[test]
+    fn syntax_errors_report_the_line() {
+        for (text, expected) in [
+            ("a = 1\nb\n", 2),
+            ("a = 1\na = 2\n", 2),
+            ("[server]\n", 1),
+            ("a = \"unterminated\n", 1),
+            ("a = \"bad \\q escape\"\n", 1),
+            ("\n\na = 1 2\n", 3),
+            ("a b = 1\n", 1),
+            ("a = 99999999999999999999\n", 1),
+        ] {
+            match parse(text) {
+                Err(ConfigError::Syntax { line, .. }) => assert_eq!(line, expected, "{text:?}"),
+                other => panic!("{text:?}: unexpected {:?}", other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn node_file_overrides_its_member_entry() {
+        let text =
+            format!("id = 2\nlisten = \"127.0.0.1:9000\"\nstorage_path = \"/tmp/two\"\n{CLUSTER}");
+        let config = NodeConfig::from_toml(&text).unwrap();
+        assert_eq!(config.id, Id(2));
+        assert_eq!(config.listen, "127.0.0.1:9000".parse().unwrap());
+        assert_eq!(config.storage_path, Some(PathBuf::from("/tmp/two")));
+        assert!(matches!(
+            NodeConfig::from_toml(&CLUSTER.replace("heartbeat_interval_ms = 50", "id = 1")),
+            Err(ConfigError::Missing(key)) if key == "listen"
+        ));
+    }
+
+    #[test]
+    fn a_node_file_without_an_id_uses_the_stored_one() {
+        let storage = std::env::temp_dir().join(format!("paxos-config-id-{}", std::process::id()));
+        let path = identity::beside(&storage);
+        let _ = fs::remove_file(&path);
+        identity::assign(&path, Some(Id(2))).unwrap();
+        let text = format!(
+            "listen = \"127.0.0.1:9000\"\nstorage_path = \"{}\"\n{CLUSTER}",
+            storage.display()
+        );
+        assert_eq!(NodeConfig::from_toml(&text).unwrap().id, Id(2));
+        fs::remove_file(path).unwrap();
+        assert!(matches!(
+            NodeConfig::from_toml(&format!("listen = \"127.0.0.1:9000\"\n{CLUSTER}")),
+            Err(ConfigError::Missing(key)) if key == "id"
+        ));
+    }
+
+    #[test]
+    fn init_creates_the_data_dir_and_stores_the_id() {
+        let scratch =
+            std::env::temp_dir().join(format!("paxos-config-init-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&scratch);
+        fs::create_dir_all(&scratch).unwrap();
+        let dir = scratch.join("data");
+        let file = scratch.join("node.toml");
+        let body = format!(
+            "listen = \"127.0.0.1:9000\"\ndata_dir = \"{}\"\n{CLUSTER}",
+            dir.display()
+        );
+        fs::write(&file, &body).unwrap();
+        assert!(matches!(
+            NodeConfig::load(&file),
+            Err(ConfigError::Layout(LayoutError::NotInitialized(_)))
+        ));
+
+        fs::write(&file, format!("id = 2\n{body}")).unwrap();
+        let config = NodeConfig::init(&file).unwrap();
+        assert_eq!(config.data_dir.as_ref(), Some(&dir));
+        assert_eq!(
+            DataDir::open(&dir).unwrap().cached_config().unwrap(),
+            Some(format!("id = 2\n{body}"))
+        );
+        fs::write(&file, &body).unwrap();
+        assert_eq!(NodeConfig::load(&file).unwrap().id, Id(2));
+
+        let both = format!("storage_path = \"/tmp/two\"\nid = 2\n{body}");
+        assert!(matches!(
+            NodeConfig::from_toml(&both),
+            Err(ConfigError::Invalid { key, .. }) if key == "data_dir"
+        ));
+        fs::remove_dir_all(scratch).unwrap();
+    }
+}
@@ -0,0 +1,249 @@
+use crate::alpha::Id;
+use crate::transport::tcp::NodeStatus;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+const ELECTIONS: usize = 8;
+
+/// What a cluster looked like the last time every member was asked for its
+/// status, for `paxos-admin watch` to redraw in place.
+#[derive(Debug)]
+pub struct Dashboard {
+    members: Vec<Member>,
+    leader: Option<Id>,
+    elections: VecDeque<Election>,
+}
+
+#[derive(Debug)]
+struct Member {
+    addr: SocketAddr,
+    status: Result<NodeStatus, String>,
+    latency: Option<Duration>,
+}
+
+/// A change of the leader most answering members agree on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Election {
+    pub at: Instant,
+    pub previous: Option<Id>,
+    pub leader: Id,
+}
+
+impl Dashboard {
+    pub fn new(addrs: impl IntoIterator<Item = SocketAddr>) -> Self {
+        Self {
+            members: addrs
+                .into_iter()
+                .map(|addr| Member {
+                    addr,
+                    status: Err("not asked yet".to_string()),
+                    latency: None,
+                })
+                .collect(),
+            leader: None,
+            elections: VecDeque::new(),
+        }
+    }
+
+    /// Records how the member at `addr` answered and how long it took.
+    pub fn observe(&mut self, addr: SocketAddr, status: Result<NodeStatus, String>, rtt: Duration) {
+        if let Some(member) = self.members.iter_mut().find(|member| member.addr == addr) {
+            member.latency = status.is_ok().then_some(rtt);
+            member.status = status;
+        }
+    }
+
+    /// Call once every member has been observed: notes an election when the
+    /// members that answered agree on a new leader.
+    pub fn settle(&mut self, at: Instant) {
+        let Some(leader) = self.agreed_leader() else {
+            return;
+        };
+        if self.leader == Some(leader) {
+            return;
+        }
+        if self.elections.len() == ELECTIONS {
+            self.elections.pop_back();
+        }
+        self.elections.push_front(Election {
+            at,
+            previous: self.leader.replace(leader),
+            leader,
+        });
+    }
+
+    pub fn leader(&self) -> Option<Id> {
+        self.leader
+    }
+
+    /// The most recent first.
+    pub fn elections(&self) -> impl Iterator<Item = &Election> {
+        self.elections.iter()
+    }
+
+    /// The leader named by more than half of the members that answered.
+    fn agreed_leader(&self) -> Option<Id> {
+        let mut votes = HashMap::new();
+        let mut answered = 0;
+        for status in self
+            .members
+            .iter()
+            .filter_map(|member| member.status.as_ref().ok())
+        {
+            answered += 1;
+            if let Some(leader) = status.leader {
+                *votes.entry(leader).or_insert(0) += 1;
+            }
+        }
+        votes
+            .into_iter()
+            .find(|(_, votes)| votes * 2 > answered)
+            .map(|(leader, _)| leader)
+    }
+
+    pub fn render(&self, now: Instant) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "leader: {}", id(self.leader));
+        let _ = writeln!(
+            out,
+            "\n{:<22} {:>4} {:>6} {:>7} {:>12} {:>9}  suspected",
+            "member", "id", "leader", "decided", "commit_index", "rtt"
+        );
+        for member in &self.members {
+            let status = match &member.status {
+                Ok(status) => status,
+                Err(error) => {
+                    let _ = writeln!(out, "{:<22} error: {error}", member.addr.to_string());
+                    continue;
+                }
+            };
+            let _ = writeln!(
+                out,
+                "{:<22} {:>4} {:>6} {:>7} {:>12} {:>9}  {}",
+                member.addr.to_string(),
+                status.id.get(),
+                id(status.leader),
+                status.decided,
+                status
+                    .commit_index
+                    .map_or_else(|| "-".to_string(), |index| index.get().to_string()),
+                member
+                    .latency
+                    .map_or_else(|| "-".to_string(), |rtt| format!("{rtt:.1?}")),
+                status
+                    .suspected
+                    .iter()
+                    .map(|id| id.get().to_string())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            );
+        }
+        let _ = writeln!(out, "\nrecent elections:");
+        if self.elections.is_empty() {
+            let _ = writeln!(out, "  none seen");
+        }
+        for election in &self.elections {
+            let _ = writeln!(
+                out,
+                "  {:>6}s ago  {} -> {}",
+                now.saturating_duration_since(election.at).as_secs(),
+                id(election.previous),
+                election.leader.get(),
+            );
+        }
+        out
+    }
+}
+
+fn id(id: Option<Id>) -> String {
+    id.map_or_else(|| "?".to_string(), |id| id.get().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alpha::Round;
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    fn status(id: u64, leader: Option<u64>) -> Result<NodeStatus, String> {
+        Ok(NodeStatus {
+            id: Id(id),
+            leader: leader.map(Id),
+            members: vec![Id(1), Id(2), Id(3)],
+            suspected: Vec::new(),
+            last_round_entered: Round::default(),
+            accepted_round: None,
+            decided: false,
+            commit_index: None,
+            epoch: 0,
+        })
+    }
+
+    #[test]
+    fn elections_follow_the_leader_most_members_agree_on() {
+        let mut dashboard = Dashboard::new((1..=3).map(addr));
+        let start = Instant::now();
+        let rtt = Duration::from_millis(1);
+        dashboard.observe(addr(1), status(1, Some(1)), rtt);
+        dashboard.observe(addr(2), status(2, Some(2)), rtt);
+        dashboard.observe(addr(3), Err("connection refused".to_string()), rtt);
+        dashboard.settle(start);
+        assert_eq!(dashboard.leader(), None);
+
+        dashboard.observe(addr(2), status(2, Some(1)), rtt);
+        dashboard.settle(start);
+        dashboard.settle(start + Duration::from_secs(1));
+        assert_eq!(dashboard.leader(), Some(Id(1)));
+
+        dashboard.observe(addr(1), Err("timed out".to_string()), rtt);
+        dashboard.observe(addr(2), status(2, Some(3)), rtt);
+        dashboard.observe(addr(3), status(3, Some(3)), rtt);
+        dashboard.settle(start + Duration::from_secs(5));
+        let elections: Vec<_> = dashboard.elections().cloned().collect();
+        assert_eq!(
+            elections,
+            [
+                Election {
+                    at: start + Duration::from_secs(5),
+                    previous: Some(Id(1)),
+                    leader: Id(3),
+                },
+                Election {
+                    at: start,
+                    previous: None,
+                    leader: Id(1),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn renders_members_errors_and_elections() {
+        let mut dashboard = Dashboard::new([addr(1), addr(2)]);
+        let start = Instant::now();
+        dashboard.observe(addr(1), status(1, Some(1)), Duration::from_millis(2));
+        dashboard.observe(
+            addr(2),
+            Err("connection refused".to_string()),
+            Duration::ZERO,
+        );
+        dashboard.settle(start);
+        let rendered = dashboard.render(start + Duration::from_secs(3));
+        assert!(rendered.starts_with("leader: 1\n"));
+        assert!(rendered.contains("127.0.0.1:2            error: connection refused"));
+        assert!(rendered.contains("2.0ms"));
+        assert!(rendered.contains("3s ago  ? -> 1"));
+
+        let mut elections = Dashboard::new([addr(1)]);
+        for leader in 0..ELECTIONS as u64 + 2 {
+            elections.observe(addr(1), status(1, Some(leader)), Duration::ZERO);
+            elections.settle(start);
+        }
+        assert_eq!(elections.elections().count(), ELECTIONS);
+    }
+}
@@ -0,0 +1,309 @@
+//! The directory a node keeps everything it persists in:
+//!
+//! - `layout`: the version of this layout;
+//! - `acceptor` and `acceptor.tick`: the acceptor's promise, acceptance and
+//!   round counter;
+//! - `acceptor.id`: the node's [identity](crate::identity);
+//! - `acceptor.cluster`, `acceptor.members` and `acceptor.membership`: the
+//!   bootstrap it adopted, the membership it applied and the membership log;
+//! - `snapshots/`: state machine snapshots;
+//! - `groups/<name>`: the storage of each consensus group the node runs
+//!   besides its own;
+//! - `config.toml`: the config the directory was initialised with.
+//!
+//! A backup is a data directory too, so restoring one is copying it back.
+//!
+//! Nodes used to keep the same files side by side next to a
+//! `storage_path`; [`DataDir::open`] moves such a layout into a directory
+//! at that same path.
+
+use crate::alpha::Id;
+use crate::codec::{from_bytes, to_bytes};
+use crate::identity::{self, IdentityError};
+use crate::storage::{sync_parent, write_atomic, RecordFile};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// The layout this build writes. The flat files of a `storage_path` count
+/// as version 0.
+pub const LAYOUT_VERSION: u32 = 1;
+
+/// What nodes keep next to their `storage_path`, by extension. The round
+/// counter comes last so a copy taken while the node runs never holds a
+/// round it has not counted past.
+const FLAT_FILES: [&str; 7] = [
+    "id",
+    "cluster",
+    "members",
+    "membership",
+    "fence",
+    "fences",
+    "tick",
+];
+
+#[derive(Error, Debug)]
+pub enum LayoutError {
+    #[error("{0} is not an initialised data directory")]
+    NotInitialized(PathBuf),
+    #[error("data directory layout {found} is newer than {LAYOUT_VERSION}")]
+    Unsupported { found: u32 },
+    #[error("{0} already holds node state")]
+    NotEmpty(PathBuf),
+    #[error("failed to set up the data directory")]
+    Io(#[from] io::Error),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DataDir {
+    root: PathBuf,
+}
+
+impl DataDir {
+    /// Creates the directory unless it exists, migrating older layouts, and
+    /// caches `config` in it.
+    pub fn init(root: impl Into<PathBuf>, config: Option<&str>) -> Result<Self, LayoutError> {
+        let root = root.into();
+        let dir = match Self::open(&root) {
+            Ok(dir) => dir,
+            Err(LayoutError::NotInitialized(_)) => {
+                fs::create_dir_all(&root)?;
+                let dir = Self { root };
+                dir.finish()?;
+                dir
+            }
+            Err(error) => return Err(error),
+        };
+        if let Some(config) = config {
+            write_atomic(&dir.config_path(), config.as_bytes())?;
+        }
+        Ok(dir)
+    }
+
+    /// Opens an initialised directory, migrating it to the current layout
+    /// if it is older. Refuses layouts newer than this build understands.
+    pub fn open(root: impl Into<PathBuf>) -> Result<Self, LayoutError> {
+        let dir = Self { root: root.into() };
+        if dir.migrating().exists() || dir.root.is_file() {
+            dir.migrate_flat()?;
+        }
+        Self::peek(dir.root)
+    }
+
+    /// Like [`open`](Self::open), but never writes: a directory in an older
+    /// layout counts as not initialised.
+    pub fn peek(root: impl Into<PathBuf>) -> Result<Self, LayoutError> {
+        let dir = Self { root: root.into() };
+        match dir.version()? {
+            Some(LAYOUT_VERSION) => Ok(dir),
+            Some(found) => Err(LayoutError::Unsupported { found }),
+            None => Err(LayoutError::NotInitialized(dir.root)),
+        }
+    }
+
+    /// Copies the state kept at `storage_path`, and whatever sits beside it,
+    /// into a new data directory at `to`. Every file is replaced whole and
+    /// records are only ever appended, so a copy taken while the node writes
+    /// holds a state it passed through, as if it had crashed then.
+    pub fn copy(storage_path: &Path, to: impl Into<PathBuf>) -> Result<Self, LayoutError> {
+        let dir = Self::init(to, None)?;
+        copy_state(storage_path, &dir.storage_path())?;
+        Ok(dir)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Where the acceptor keeps its state; everything else the node
+    /// persists sits beside it.
+    pub fn storage_path(&self) -> PathBuf {
+        self.root.join("acceptor")
+    }
+
+    /// The id of the node this directory belongs to; see
+    /// [`identity::assign`].
+    pub fn identity(&self, configured: Option<Id>) -> Result<Id, IdentityError> {
+        identity::assign(identity::beside(&self.storage_path()), configured)
+    }
+
+    pub fn snapshots(&self) -> PathBuf {
+        self.root.join("snapshots")
+    }
+    /// The config the directory was last initialised with, if any.
+    pub fn cached_config(&self) -> io::Result<Option<String>> {
+        match fs::read_to_string(self.config_path()) {
+            Ok(config) => Ok(Some(config)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn config_path(&self) -> PathBuf {
+        self.root.join("config.toml")
+    }
+
+    fn version(&self) -> io::Result<Option<u32>> {
+        RecordFile::new(self.root.join("layout"))
+            .recover()?
+            .map(|bytes| from_bytes(&bytes))
+            .transpose()
+    }
+
+    /// Where a flat layout is gathered before it takes the root's place.
+    fn migrating(&self) -> PathBuf {
+        self.root.with_extension("migrating")
+    }
+
+    /// Moves the acceptor file at the root, and the files beside it, into a
+    /// directory that then replaces it. Every step can be repeated, so a
+    /// migration interrupted by a crash resumes on the next open.
+    fn migrate_flat(&self) -> io::Result<()> {
+        let staging = Self {
+            root: self.migrating(),
+        };
+        fs::create_dir_all(&staging.root)?;
+        if self.root.is_file() {
+            fs::rename(&self.root, staging.storage_path())?;
+        }
+        for extension in FLAT_FILES {
+            let flat = self.root.with_extension(extension);
+            if flat.exists() {
+                fs::rename(flat, staging.storage_path().with_extension(extension))?;
+            }
+        }
+        staging.finish()?;
+        fs::rename(&staging.root, &self.root)?;
+        sync_parent(&self.root)
+    }
+
+    fn finish(&self) -> io::Result<()> {
+        fs::create_dir_all(self.snapshots())?;
+        write_layout(&self.root, LAYOUT_VERSION)
+    }
+}
+
+/// Copies the state kept at `from`, and the files beside it, to `to`,
+/// refusing to overwrite any state already there.
+pub fn copy_state(from: &Path, to: &Path) -> Result<(), LayoutError> {
+    if to.exists() {
+        return Err(LayoutError::NotEmpty(to.to_path_buf()));
+    }
+    copy_if_present(from, to)?;
+    for extension in FLAT_FILES {
+        copy_if_present(
+            &from.with_extension(extension),
+            &to.with_extension(extension),
+        )?;
+    }
+    Ok(())
+}
+
+fn copy_if_present(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::read(from) {
+        Ok(bytes) => write_atomic(to, &bytes),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+fn write_layout(root: &Path, version: u32) -> io::Result<()> {
+    RecordFile::new(root.join("layout")).rewrite([to_bytes(&version).as_slice()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("paxos-data-dir-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn init_creates_the_layout_once() {
+        let root = scratch("init");
+        assert!(matches!(
+            DataDir::open(&root),
+            Err(LayoutError::NotInitialized(_))
+        ));
+        let dir = DataDir::init(&root, Some("id = 1\n")).unwrap();
+        assert!(dir.snapshots().is_dir());
+        assert_eq!(DataDir::open(&root).unwrap(), dir);
+        assert_eq!(DataDir::peek(&root).unwrap(), dir);
+        assert_eq!(DataDir::init(&root, None).unwrap(), dir);
+        assert_eq!(dir.cached_config().unwrap().as_deref(), Some("id = 1\n"));
+        assert_eq!(dir.identity(Some(Id(1))).unwrap(), Id(1));
+        assert!(matches!(
+            dir.identity(Some(Id(2))),
+            Err(IdentityError::Conflict { .. })
+        ));
+
+        write_layout(&root, LAYOUT_VERSION + 1).unwrap();
+        assert!(matches!(
+            DataDir::open(&root),
+            Err(LayoutError::Unsupported { found }) if found == LAYOUT_VERSION + 1
+        ));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn copies_state_into_a_fresh_directory_only() {
+        let (from, to) = (scratch("copy-from"), scratch("copy-to"));
+        let source = DataDir::init(&from, None).unwrap();
+        fs::write(source.storage_path(), b"acceptor").unwrap();
+        fs::write(source.storage_path().with_extension("tick"), b"tick").unwrap();
+
+        let copy = DataDir::copy(&source.storage_path(), &to).unwrap();
+        assert_eq!(fs::read(copy.storage_path()).unwrap(), b"acceptor");
+        assert_eq!(
+            fs::read(copy.storage_path().with_extension("tick")).unwrap(),
+            b"tick"
+        );
+        assert!(!copy.storage_path().with_extension("members").exists());
+        assert!(matches!(
+            DataDir::copy(&source.storage_path(), &to),
+            Err(LayoutError::NotEmpty(_))
+        ));
+        fs::remove_dir_all(from).unwrap();
+        fs::remove_dir_all(to).unwrap();
+    }
+
+    #[test]
+    fn migrates_the_flat_files_of_a_storage_path() {
+        let root = scratch("flat");
+        fs::write(&root, b"acceptor").unwrap();
+        fs::write(root.with_extension("tick"), b"tick").unwrap();
+        fs::write(root.with_extension("members"), b"members").unwrap();
+
+        let dir = DataDir::open(&root).unwrap();
+        assert_eq!(fs::read(dir.storage_path()).unwrap(), b"acceptor");
+        assert_eq!(
+            fs::read(dir.storage_path().with_extension("tick")).unwrap(),
+            b"tick"
+        );
+        assert_eq!(
+            fs::read(dir.storage_path().with_extension("members")).unwrap(),
+            b"members"
+        );
+        assert!(!root.with_extension("tick").exists());
+        assert!(!dir.migrating().exists());
+
+        // A migration cut short after moving the acceptor file resumes.
+        fs::remove_dir_all(&root).unwrap();
+        fs::create_dir_all(root.with_extension("migrating")).unwrap();
+        fs::write(root.with_extension("migrating").join("acceptor"), b"moved").unwrap();
+        fs::write(root.with_extension("id"), b"id").unwrap();
+        let dir = DataDir::open(&root).unwrap();
+        assert_eq!(fs::read(dir.storage_path()).unwrap(), b"moved");
+        assert_eq!(
+            fs::read(dir.storage_path().with_extension("id")).unwrap(),
+            b"id"
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,351 @@
+use crate::alpha::Id;
+use crate::metrics::{AdaptiveTimeout, ResponseTimes};
+use crate::proposer::FailureDetector;
+use crate::time::{Clock, SystemClock};
+use futures::channel::oneshot;
+#[cfg(feature = "threads")]
+use futures::executor::block_on;
+use futures::future::{select, BoxFuture, FutureExt, Shared};
+use futures::{stream, Stream};
+use std::collections::HashMap;
+use std::future::Future;
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+#[cfg(feature = "threads")]
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub trait HeartbeatClient {
+    fn broadcast_heartbeat(&self, from: Id);
+    /// Resolves once every member has answered or failed, so a leaving node
+    /// can await it from inside the heartbeat task.
+    fn broadcast_leave(&self, from: Id) -> impl Future<Output = ()> + Send;
+}
+
+pub struct OmegaDetector {
+    id: Id,
+    timeout: Duration,
+    intervals: Option<ResponseTimes<Id>>,
+    clock: Arc<dyn Clock>,
+    last_heard: Mutex<HashMap<Id, Instant>>,
+    preferred: Mutex<Option<Id>>,
+    announced: Mutex<Option<Id>>,
+    watchers: Mutex<Vec<oneshot::Sender<()>>>,
+    expiry: Mutex<Option<Shared<BoxFuture<'static, ()>>>>,
+    stopped: AtomicBool,
+}
+
+impl OmegaDetector {
+    pub fn new(id: Id, members: impl IntoIterator<Item = Id>, timeout: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            id,
+            timeout,
+            intervals: None,
+            clock: Arc::new(SystemClock),
+            last_heard: Mutex::new(members.into_iter().map(|member| (member, now)).collect()),
+            preferred: Mutex::new(None),
+            announced: Mutex::new(None),
+            watchers: Mutex::new(Vec::new()),
+            expiry: Mutex::new(None),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        let now = clock.now();
+        for heard in self
+            .last_heard
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values_mut()
+        {
+            *heard = now;
+        }
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_adaptive_timeout(mut self, policy: AdaptiveTimeout) -> Self {
+        self.intervals = Some(ResponseTimes::new(policy));
+        self
+    }
+
+    /// Heartbeats from nodes that are not members are ignored, so they can
+    /// never be elected.
+    pub fn heartbeat(&self, from: Id) {
+        let now = self.clock.now();
+        let previous = match lock(&self.last_heard).get_mut(&from) {
+            Some(heard) => mem::replace(heard, now),
+            None => return,
+        };
+        if let Some(intervals) = &self.intervals {
+            intervals.record(from, now - previous);
+        }
+        self.leader();
+    }
+
+    pub fn timeout(&self, member: Id) -> Duration {
+        self.intervals
+            .as_ref()
+            .and_then(|intervals| intervals.timeout(member))
+            .unwrap_or(self.timeout)
+    }
+
+    pub fn leave(&self, member: Id) {
+        self.last_heard
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&member);
+        self.leader();
+    }
+
+    pub fn transfer(&self, to: Id) {
+        *self
+            .preferred
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(to);
+        self.leader();
+    }
+
+    /// Starts watching a new set of members; ones that stay keep their last
+    /// heartbeat, new ones count as just heard from.
+    pub fn set_members(&self, members: impl IntoIterator<Item = Id>) {
+        let now = self.clock.now();
+        {
+            let mut last_heard = self
+                .last_heard
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            *last_heard = members
+                .into_iter()
+                .map(|member| (member, last_heard.get(&member).copied().unwrap_or(now)))
+                .collect();
+        }
+        self.leader();
+    }
+
+    pub fn members(&self) -> Vec<Id> {
+        let mut members: Vec<Id> = self
+            .last_heard
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .keys()
+            .copied()
+            .collect();
+        members.sort();
+        members
+    }
+
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Release);
+        self.notify();
+    }
+
+    pub fn leadership_changes(self: &Arc<Self>) -> impl Stream<Item = Id> {
+        let detector = Arc::downgrade(self);
+        stream::unfold(None, move |last| {
+            let detector = detector.clone();
+            async move {
+                loop {
+                    let detector = detector.upgrade()?;
+                    if detector.stopped.load(Ordering::Acquire) {
+                        return None;
+                    }
+                    let changed = OmegaDetector::changed(&detector);
+                    let leader = detector.leader();
+                    if last != Some(leader) {
+                        return Some((leader, Some(leader)));
+                    }
+                    drop(detector);
+                    changed.await;
+                }
+            }
+        })
+    }
+
+    // Resolves once `leader()` may return something new: on an announced
+    // change, or when the current leader's lease runs out. The expiry sleep is
+    // shared by every waiter and only replaced after it fires.
+    fn changed(&self) -> impl Future<Output = ()> {
+        let expiry = self.expiry();
+        let (sender, receiver) = oneshot::channel();
+        lock(&self.watchers).push(sender);
+        async move {
+            match expiry {
+                Some(expiry) => {
+                    select(receiver, expiry).await;
+                }
+                None => {
+                    let _ = receiver.await;
+                }
+            }
+        }
+    }
+
+    fn expiry(&self) -> Option<Shared<BoxFuture<'static, ()>>> {
+        let leader = self.leader();
+        if leader == self.id {
+            // Only a heartbeat from a lower id can take over, and that is
+            // announced.
+            return None;
+        }
+        let mut expiry = lock(&self.expiry);
+        if let Some(pending) = expiry.as_ref().filter(|sleep| sleep.peek().is_none()) {
+            return Some(pending.clone());
+        }
+        let heard = *lock(&self.last_heard).get(&leader)?;
+        let remaining = (heard + self.timeout(leader)).saturating_duration_since(self.clock.now());
+        let sleep = self.clock.sleep(remaining).shared();
+        *expiry = Some(sleep.clone());
+        Some(sleep)
+    }
+
+    fn announce(&self, leader: Id) {
+        let mut announced = lock(&self.announced);
+        if *announced != Some(leader) {
+            *announced = Some(leader);
+            drop(announced);
+            *lock(&self.expiry) = None;
+            self.notify();
+        }
+    }
+
+    fn notify(&self) {
+        for watcher in lock(&self.watchers).drain(..) {
+            let _ = watcher.send(());
+        }
+    }
+
+    pub fn suspected(&self) -> Vec<Id> {
+        let now = self.clock.now();
+        let mut suspected: Vec<Id> = self
+            .last_heard
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|(member, heard)| **member != self.id && now - **heard > self.timeout(**member))
+            .map(|(member, _)| *member)
+            .collect();
+        suspected.sort();
+        suspected
+    }
+
+    pub fn heartbeats<C>(
+        self: &Arc<Self>,
+        client: C,
+        interval: Duration,
+    ) -> impl Future<Output = ()>
+    where
+        C: HeartbeatClient,
+    {
+        let detector = Arc::downgrade(self);
+        async move {
+            while let Some(detector) = detector.upgrade() {
+                if detector.stopped.load(Ordering::Acquire) {
+                    client.broadcast_leave(detector.id).await;
+                    return;
+                }
+                client.broadcast_heartbeat(detector.id);
+                let sleep = detector.clock.sleep(interval);
+                drop(detector);
+                sleep.await;
+            }
+        }
+    }
+
+    #[cfg(feature = "threads")]
+    pub fn spawn_heartbeats<C>(self: &Arc<Self>, client: C, interval: Duration) -> JoinHandle<()>
+    where
+        C: HeartbeatClient + Send + 'static,
+    {
+        let heartbeats = self.heartbeats(client, interval);
+        thread::spawn(move || block_on(heartbeats))
+    }
+}
+
+impl FailureDetector for OmegaDetector {
+    fn leader(&self) -> Id {
+        let now = self.clock.now();
+        let live: Vec<Id> = self
+            .last_heard
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|(member, heard)| now - **heard <= self.timeout(**member))
+            .map(|(member, _)| *member)
+            .chain([self.id])
+            .collect();
+        let preferred = *self
+            .preferred
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let leader = match preferred {
+            Some(preferred) if live.contains(&preferred) => preferred,
+            _ => live.into_iter().min().unwrap_or(self.id),
+        };
+        self.announce(leader);
+        leader
+    }
+
+    fn changed(&self) -> impl Future<Output = ()> {
+        OmegaDetector::changed(self)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::MockClock;
+    use std::pin::pin;
+
+    fn detector(clock: &MockClock) -> OmegaDetector {
+        OmegaDetector::new(Id(2), [Id(1), Id(2), Id(3)], Duration::from_millis(500))
+            .with_clock(Arc::new(clock.clone()))
+    }
+
+    #[test]
+    fn heartbeats_only_wake_waiters_when_the_leader_changes() {
+        let clock = MockClock::new();
+        let detector = detector(&clock);
+        assert_eq!(detector.leader(), Id(1));
+
+        let mut changed = pin!(detector.changed());
+        detector.heartbeat(Id(1));
+        detector.heartbeat(Id(3));
+        assert!(changed.as_mut().now_or_never().is_none());
+
+        detector.leave(Id(1));
+        assert!(changed.now_or_never().is_some());
+        assert_eq!(detector.leader(), Id(2));
+    }
+
+    #[test]
+    fn ignores_heartbeats_from_non_members() {
+        let clock = MockClock::new();
+        let detector = detector(&clock);
+        detector.heartbeat(Id(0));
+        assert_eq!(detector.members(), [Id(1), Id(2), Id(3)]);
+        assert_eq!(detector.leader(), Id(1));
+    }
+
+    #[test]
+    fn waiters_wake_when_the_leader_expires() {
+        let clock = MockClock::new();
+        let detector = detector(&clock);
+        let mut first = pin!(detector.changed());
+        let second = pin!(detector.changed());
+
+        clock.advance(Duration::from_millis(400));
+        assert!(first.as_mut().now_or_never().is_none());
+        clock.advance(Duration::from_millis(200));
+        assert!(first.now_or_never().is_some());
+        assert!(second.now_or_never().is_some());
+        assert_eq!(detector.leader(), Id(2));
+    }
+}
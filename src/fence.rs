@@ -0,0 +1,267 @@
+//! Storage fences, which catch a node started on a copy of its storage
+//! older than what it ran with since, such as a data directory put back
+//! from an old backup by hand: its acceptor forgot the promises it made
+//! after the copy was taken.
+//!
+//! A node counts each start and each backup, after either of which an
+//! older copy of its storage may exist, and tells the other members its new
+//! [`Fence`] before it serves. They keep the highest fence they heard of
+//! from each member and refuse one that is not above it. The epoch leads
+//! the count, so a node rebuilt through
+//! [`Node::restore`](crate::node::Node::restore), which enters the next
+//! epoch to be fenced off by it instead, is let through.
+
+use crate::alpha::Id;
+use crate::codec::{from_bytes, to_bytes, Decode, Encode};
+use crate::storage::RecordFile;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use thiserror::Error;
+
+/// Once this many announcements are kept, only the highest of each member
+/// is rewritten.
+const COMPACT_AFTER: usize = 1024;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fence {
+    pub epoch: u64,
+    pub count: u64,
+}
+
+#[derive(Error, Debug)]
+pub enum FenceError {
+    #[error("the storage is older than fence {seen:?} it ran with before")]
+    Stale { seen: Fence },
+    #[error("failed to keep the fence")]
+    Io(#[from] io::Error),
+}
+
+/// What a node counted of its own storage, kept beside it so copies of the
+/// storage hold the count they were taken at.
+pub struct StorageFence {
+    count: u64,
+    refused: Option<Fence>,
+    file: Option<RecordFile>,
+}
+
+impl StorageFence {
+    pub fn in_memory() -> Self {
+        Self {
+            count: 0,
+            refused: None,
+            file: None,
+        }
+    }
+
+    pub fn open(path: PathBuf) -> io::Result<Self> {
+        let mut file = RecordFile::new(path);
+        let (count, refused) = match file.recover()? {
+            Some(bytes) => {
+                let buf = &mut bytes.as_slice();
+                (u64::decode(buf)?, Option::decode(buf)?)
+            }
+            None => (0, None),
+        };
+        Ok(Self {
+            count,
+            refused,
+            file: Some(file),
+        })
+    }
+
+    /// Counts one more start or backup, kept before it returns so the count
+    /// is never announced twice; a copy found stale stays refused.
+    pub fn advance(&mut self) -> Result<u64, FenceError> {
+        if let Some(seen) = self.refused {
+            return Err(FenceError::Stale { seen });
+        }
+        self.count += 1;
+        self.write()?;
+        Ok(self.count)
+    }
+
+    /// Remembers that a member heard of `seen` before, so starting this
+    /// copy again does not count past it.
+    pub fn refuse(&mut self, seen: Fence) -> io::Result<()> {
+        self.refused = Some(seen);
+        self.write()
+    }
+
+    /// Lets a copy found stale start again, as one that enters the next
+    /// epoch may.
+    pub fn reinstate(&mut self) -> io::Result<()> {
+        self.refused = None;
+        self.write()
+    }
+
+    fn write(&mut self) -> io::Result<()> {
+        if let Some(file) = &mut self.file {
+            let mut bytes = to_bytes(&self.count);
+            self.refused.encode(&mut bytes);
+            file.append(&bytes)?;
+        }
+        Ok(())
+    }
+}
+
+/// The highest fence each member announced, as the node hearing them keeps
+/// them.
+pub struct FenceLedger {
+    seen: Mutex<BTreeMap<Id, Fence>>,
+    file: Mutex<Option<RecordFile>>,
+}
+
+impl FenceLedger {
+    pub fn in_memory() -> Self {
+        Self {
+            seen: Mutex::default(),
+            file: Mutex::new(None),
+        }
+    }
+
+    pub fn open(path: PathBuf) -> io::Result<Self> {
+        let mut file = RecordFile::log(path);
+        let mut seen = BTreeMap::new();
+        for bytes in file.recover_all()? {
+            let Announced { id, fence } = from_bytes(&bytes)?;
+            let highest = seen.entry(id).or_insert(fence);
+            *highest = fence.max(*highest);
+        }
+        Ok(Self {
+            seen: Mutex::new(seen),
+            file: Mutex::new(Some(file)),
+        })
+    }
+
+    pub fn seen(&self, id: Id) -> Option<Fence> {
+        lock(&self.seen).get(&id).copied()
+    }
+
+    /// Keeps `fence` as the highest `id` announced, unless it announced as
+    /// high a one before.
+    pub fn announce(&self, id: Id, fence: Fence) -> Result<(), FenceError> {
+        let mut seen = lock(&self.seen);
+        if let Some(&highest) = seen.get(&id).filter(|highest| **highest >= fence) {
+            return Err(FenceError::Stale { seen: highest });
+        }
+        if let Some(file) = lock(&self.file).as_mut() {
+            match file.records() >= COMPACT_AFTER {
+                true => {
+                    let records: Vec<_> = seen
+                        .iter()
+                        .filter(|(known, _)| **known != id)
+                        .map(|(&id, &fence)| to_bytes(&Announced { id, fence }))
+                        .chain([to_bytes(&Announced { id, fence })])
+                        .collect();
+                    file.rewrite(records.iter().map(Vec::as_slice))?;
+                }
+                false => file.append(&to_bytes(&Announced { id, fence }))?,
+            }
+        }
+        seen.insert(id, fence);
+        Ok(())
+    }
+}
+
+/// One announcement as the ledger keeps it.
+pub(crate) struct Announced {
+    pub(crate) id: Id,
+    pub(crate) fence: Fence,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("paxos-fence-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn members_refuse_fences_no_higher_than_one_announced_before() {
+        let path = scratch("ledger");
+        let ledger = FenceLedger::open(path.clone()).unwrap();
+        let fence = |epoch, count| Fence { epoch, count };
+        ledger.announce(Id(2), fence(0, 3)).unwrap();
+        ledger.announce(Id(3), fence(0, 1)).unwrap();
+        for stale in [fence(0, 3), fence(0, 2)] {
+            assert!(matches!(
+                ledger.announce(Id(2), stale),
+                Err(FenceError::Stale { seen }) if seen == fence(0, 3)
+            ));
+        }
+        // A node restored into the next epoch starts counting again.
+        ledger.announce(Id(2), fence(1, 1)).unwrap();
+        assert_eq!(ledger.seen(Id(2)), Some(fence(1, 1)));
+
+        let reopened = FenceLedger::open(path.clone()).unwrap();
+        assert_eq!(reopened.seen(Id(2)), Some(fence(1, 1)));
+        assert_eq!(reopened.seen(Id(3)), Some(fence(0, 1)));
+        assert_eq!(reopened.seen(Id(4)), None);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn compacts_the_ledger_to_the_highest_fences() {
+        let path = scratch("compact");
+        let ledger = FenceLedger::open(path.clone()).unwrap();
+        for count in 1..=COMPACT_AFTER as u64 + 2 {
+            ledger.announce(Id(2), Fence { epoch: 0, count }).unwrap();
+        }
+        ledger
+            .announce(Id(3), Fence { epoch: 2, count: 1 })
+            .unwrap();
+        let mut file = RecordFile::log(path.clone());
+        assert!(file.recover_all().unwrap().len() < COMPACT_AFTER);
+        let reopened = FenceLedger::open(path.clone()).unwrap();
+        assert_eq!(
+            reopened.seen(Id(2)),
+            Some(Fence {
+                epoch: 0,
+                count: COMPACT_AFTER as u64 + 2
+            })
+        );
+        assert_eq!(reopened.seen(Id(3)), Some(Fence { epoch: 2, count: 1 }));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn a_copy_found_stale_stays_refused() {
+        let path = scratch("storage");
+        let mut fence = StorageFence::open(path.clone()).unwrap();
+        assert_eq!(fence.advance().unwrap(), 1);
+        assert_eq!(fence.advance().unwrap(), 2);
+        let copy = std::fs::read(&path).unwrap();
+        assert_eq!(StorageFence::open(path.clone()).unwrap().count, 2);
+
+        let seen = Fence { epoch: 0, count: 4 };
+        fence.refuse(seen).unwrap();
+        let mut reopened = StorageFence::open(path.clone()).unwrap();
+        assert!(matches!(
+            reopened.advance(),
+            Err(FenceError::Stale { seen: refused }) if refused == seen
+        ));
+        reopened.reinstate().unwrap();
+        assert_eq!(
+            StorageFence::open(path.clone()).unwrap().advance().unwrap(),
+            3
+        );
+        std::fs::write(&path, copy).unwrap();
+        assert_eq!(
+            StorageFence::open(path.clone()).unwrap().advance().unwrap(),
+            3
+        );
+
+        let mut memory = StorageFence::in_memory();
+        assert_eq!(memory.advance().unwrap(), 1);
+        std::fs::remove_file(path).unwrap();
+    }
+}
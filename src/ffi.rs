@@ -0,0 +1,290 @@
+//! A C interface to a [`Node`] deciding raw bytes, for services that embed
+//! consensus without being written in Rust. `include/toy_paxos.h` declares
+//! it; regenerate it with `cbindgen --config cbindgen.toml --output
+//! include/toy_paxos.h` after changing anything here.
+
+use crate::alpha::Error;
+use crate::bytes::BytesValue;
+use crate::config::NodeConfig;
+use crate::node::Node;
+use futures::executor::block_on;
+use std::ffi::{c_char, c_void, CStr};
+use std::sync::Arc;
+use std::{ptr, slice, thread};
+
+/// An opaque handle to a running node.
+pub struct ToyPaxosNode {
+    node: Arc<Node<BytesValue>>,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ToyPaxosStatus {
+    Ok = 0,
+    InvalidArgument = 1,
+    InvalidConfig = 2,
+    Io = 3,
+    NotDecided = 4,
+    ProposalFailed = 5,
+    Stopped = 6,
+}
+
+impl From<&Error> for ToyPaxosStatus {
+    fn from(error: &Error) -> Self {
+        match error {
+            Error::Transport(_) | Error::Storage(_) => ToyPaxosStatus::Io,
+            Error::ProposerStopped | Error::Cancelled => ToyPaxosStatus::Stopped,
+            _ => ToyPaxosStatus::ProposalFailed,
+        }
+    }
+}
+
+/// Called with the decided value, which is only valid for the duration of
+/// the call, or with a failed status and no value.
+pub type ToyPaxosCallback =
+    extern "C" fn(user_data: *mut c_void, status: ToyPaxosStatus, data: *const u8, len: usize);
+
+/// The `user_data` a caller hands us, passed back untouched from whichever
+/// thread finishes the request.
+struct UserData(*mut c_void);
+
+// SAFETY: the caller promises `user_data` may be used from another thread
+// when it submits asynchronously.
+unsafe impl Send for UserData {}
+
+/// Starts a node from the text of a node config file and stores its handle
+/// in `out`.
+///
+/// # Safety
+///
+/// `config` must be a NUL-terminated string and `out` must be valid for a
+/// write.
+#[no_mangle]
+pub unsafe extern "C" fn toy_paxos_node_start(
+    config: *const c_char,
+    out: *mut *mut ToyPaxosNode,
+) -> ToyPaxosStatus {
+    if config.is_null() || out.is_null() {
+        return ToyPaxosStatus::InvalidArgument;
+    }
+    let Ok(config) = CStr::from_ptr(config).to_str() else {
+        return ToyPaxosStatus::InvalidArgument;
+    };
+    let config = match NodeConfig::from_toml(config) {
+        Ok(config) => config,
+        Err(_) => return ToyPaxosStatus::InvalidConfig,
+    };
+    match Node::start(config) {
+        Ok(node) => {
+            *out = Box::into_raw(Box::new(ToyPaxosNode { node }));
+            ToyPaxosStatus::Ok
+        }
+        Err(error) => (&error).into(),
+    }
+}
+
+/// Proposes `len` bytes at `data` and returns at once; `callback` runs on
+/// another thread once the node decides.
+///
+/// # Safety
+///
+/// `node` must come from [`toy_paxos_node_start`] and not be stopped yet,
+/// `data` must be valid for `len` bytes, and `user_data` must be safe to use
+/// from another thread.
+#[no_mangle]
+pub unsafe extern "C" fn toy_paxos_node_submit(
+    node: *const ToyPaxosNode,
+    data: *const u8,
+    len: usize,
+    callback: ToyPaxosCallback,
+    user_data: *mut c_void,
+) -> ToyPaxosStatus {
+    let Some(node) = node.as_ref() else {
+        return ToyPaxosStatus::InvalidArgument;
+    };
+    if data.is_null() && len > 0 {
+        return ToyPaxosStatus::InvalidArgument;
+    }
+    let value = match len {
+        0 => Vec::new(),
+        len => slice::from_raw_parts(data, len).to_vec(),
+    };
+    let (node, user_data) = (node.node.clone(), UserData(user_data));
+    thread::spawn(move || {
+        let user_data = user_data;
+        match block_on(node.propose(BytesValue::from(value))) {
+            Ok(decided) => callback(
+                user_data.0,
+                ToyPaxosStatus::Ok,
+                decided.as_ptr(),
+                decided.len(),
+            ),
+            Err(error) => callback(user_data.0, (&error).into(), ptr::null(), 0),
+        }
+    });
+    ToyPaxosStatus::Ok
+}
+
+/// Calls `callback` before returning with what this node has learned was
+/// decided, or with [`ToyPaxosStatus::NotDecided`].
+///
+/// # Safety
+///
+/// `node` must come from [`toy_paxos_node_start`] and not be stopped yet.
+#[no_mangle]
+pub unsafe extern "C" fn toy_paxos_node_read(
+    node: *const ToyPaxosNode,
+    callback: ToyPaxosCallback,
+    user_data: *mut c_void,
+) -> ToyPaxosStatus {
+    let Some(node) = node.as_ref() else {
+        return ToyPaxosStatus::InvalidArgument;
+    };
+    match node.node.decision() {
+        Some(decided) => {
+            callback(
+                user_data,
+                ToyPaxosStatus::Ok,
+                decided.as_ptr(),
+                decided.len(),
+            );
+            ToyPaxosStatus::Ok
+        }
+        None => {
+            callback(user_data, ToyPaxosStatus::NotDecided, ptr::null(), 0);
+            ToyPaxosStatus::NotDecided
+        }
+    }
+}
+
+/// Shuts the node down and frees its handle, which must not be used again.
+/// Proposals still running fail with [`ToyPaxosStatus::Stopped`].
+///
+/// # Safety
+///
+/// `node` must come from [`toy_paxos_node_start`] and not be stopped yet.
+#[no_mangle]
+pub unsafe extern "C" fn toy_paxos_node_stop(node: *mut ToyPaxosNode) -> ToyPaxosStatus {
+    if node.is_null() {
+        return ToyPaxosStatus::InvalidArgument;
+    }
+    let node = Box::from_raw(node);
+    match block_on(node.node.shutdown()) {
+        Ok(()) => ToyPaxosStatus::Ok,
+        Err(error) => (&error).into(),
+    }
+}
+
+/// A static, NUL-terminated description of `status`.
+#[no_mangle]
+pub extern "C" fn toy_paxos_status_message(status: ToyPaxosStatus) -> *const c_char {
+    let message: &'static CStr = match status {
+        ToyPaxosStatus::Ok => c"ok",
+        ToyPaxosStatus::InvalidArgument => c"invalid argument",
+        ToyPaxosStatus::InvalidConfig => c"invalid node config",
+        ToyPaxosStatus::Io => c"transport or storage error",
+        ToyPaxosStatus::NotDecided => c"nothing decided yet",
+        ToyPaxosStatus::ProposalFailed => c"proposal failed",
+        ToyPaxosStatus::Stopped => c"node stopped",
+    };
+    message.as_ptr()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+    use std::net::TcpListener;
+    use std::sync::mpsc::{channel, Sender};
+
+    type Outcome = (ToyPaxosStatus, Vec<u8>);
+
+    extern "C" fn record(
+        user_data: *mut c_void,
+        status: ToyPaxosStatus,
+        data: *const u8,
+        len: usize,
+    ) {
+        // SAFETY: every test passes a `Sender` it keeps alive until the
+        // callback has run.
+        let sender = unsafe { &*(user_data as *const Sender<Outcome>) };
+        let data = match data.is_null() {
+            true => Vec::new(),
+            false => unsafe { slice::from_raw_parts(data, len) }.to_vec(),
+        };
+        let _ = sender.send((status, data));
+    }
+
+    fn config() -> CString {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .unwrap();
+        CString::new(format!(
+            "id = 1\nlisten = \"{addr}\"\n[[members]]\nid = 1\naddr = \"{addr}\"\n"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn submits_and_reads_through_the_c_interface() {
+        let config = config();
+        let mut node = ptr::null_mut();
+        let (sender, outcomes) = channel::<Outcome>();
+        let user_data = &sender as *const Sender<Outcome> as *mut c_void;
+        unsafe {
+            assert_eq!(
+                toy_paxos_node_start(config.as_ptr(), &mut node),
+                ToyPaxosStatus::Ok
+            );
+            assert_eq!(
+                toy_paxos_node_read(node, record, user_data),
+                ToyPaxosStatus::NotDecided
+            );
+            assert_eq!(
+                outcomes.recv().unwrap(),
+                (ToyPaxosStatus::NotDecided, Vec::new())
+            );
+
+            let value = b"hello";
+            let status =
+                toy_paxos_node_submit(node, value.as_ptr(), value.len(), record, user_data);
+            assert_eq!(status, ToyPaxosStatus::Ok);
+            assert_eq!(
+                outcomes.recv().unwrap(),
+                (ToyPaxosStatus::Ok, value.to_vec())
+            );
+            assert_eq!(
+                toy_paxos_node_read(node, record, user_data),
+                ToyPaxosStatus::Ok
+            );
+            assert_eq!(outcomes.recv().unwrap().1, value);
+            assert_eq!(toy_paxos_node_stop(node), ToyPaxosStatus::Ok);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let mut node = ptr::null_mut();
+        let bad = CString::new("id = 1").unwrap();
+        unsafe {
+            assert_eq!(
+                toy_paxos_node_start(ptr::null(), &mut node),
+                ToyPaxosStatus::InvalidArgument
+            );
+            assert_eq!(
+                toy_paxos_node_start(bad.as_ptr(), &mut node),
+                ToyPaxosStatus::InvalidConfig
+            );
+            assert_eq!(
+                toy_paxos_node_submit(ptr::null(), ptr::null(), 0, record, ptr::null_mut()),
+                ToyPaxosStatus::InvalidArgument
+            );
+            assert_eq!(
+                toy_paxos_node_stop(ptr::null_mut()),
+                ToyPaxosStatus::InvalidArgument
+            );
+        }
+        let message = unsafe { CStr::from_ptr(toy_paxos_status_message(ToyPaxosStatus::Stopped)) };
+        assert_eq!(message.to_str().unwrap(), "node stopped");
+    }
+}
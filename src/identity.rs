@@ -0,0 +1,86 @@
+use crate::alpha::Id;
+use crate::codec::{from_bytes, to_bytes};
+use crate::rng::XorShift;
+use crate::storage::RecordFile;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum IdentityError {
+    #[error("the state here belongs to node {stored:?}, not {configured:?}")]
+    Conflict { stored: Id, configured: Id },
+    #[error("failed to persist the node id")]
+    Io(#[from] io::Error),
+}
+
+/// Where a node keeps its id, next to its acceptor state.
+pub fn beside(storage_path: &Path) -> PathBuf {
+    storage_path.with_extension("id")
+}
+
+/// The id of the node whose state is kept at `path`. The first start
+/// persists `configured`, or a random id when none is given; every later
+/// start must give the same id or none, so a data directory reused under a
+/// new id is refused instead of mixing two members' promises.
+pub fn assign(path: impl Into<PathBuf>, configured: Option<Id>) -> Result<Id, IdentityError> {
+    let mut file = RecordFile::new(path.into());
+    let stored = read(&mut file)?;
+    match (stored, configured) {
+        (Some(stored), Some(configured)) if stored != configured => {
+            Err(IdentityError::Conflict { stored, configured })
+        }
+        (Some(stored), _) => Ok(stored),
+        (None, configured) => {
+            let id = configured.unwrap_or_else(random);
+            file.append(&to_bytes(&id))?;
+            Ok(id)
+        }
+    }
+}
+
+/// The id stored at `path`, if one was, without storing one otherwise.
+pub fn stored(path: impl Into<PathBuf>) -> io::Result<Option<Id>> {
+    read(&mut RecordFile::new(path.into()))
+}
+
+fn read(file: &mut RecordFile) -> io::Result<Option<Id>> {
+    file.recover()?.map(|bytes| from_bytes(&bytes)).transpose()
+}
+
+fn random() -> Id {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+    let seed = now ^ u64::from(std::process::id()) << 32;
+    Id::new(XorShift::new(seed).next_u64().max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_first_id_and_refuses_another() {
+        let path = std::env::temp_dir().join(format!("paxos-identity-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(assign(&path, Some(Id(3))).unwrap(), Id(3));
+        assert_eq!(assign(&path, Some(Id(3))).unwrap(), Id(3));
+        assert_eq!(assign(&path, None).unwrap(), Id(3));
+        assert!(matches!(
+            assign(&path, Some(Id(4))),
+            Err(IdentityError::Conflict { stored, configured })
+                if stored == Id(3) && configured == Id(4)
+        ));
+        assert_eq!(stored(&path).unwrap(), Some(Id(3)));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(stored(&path).unwrap(), None);
+        assert!(!path.exists());
+
+        let generated = assign(&path, None).unwrap();
+        assert_eq!(assign(&path, None).unwrap(), generated);
+        std::fs::remove_file(path).unwrap();
+    }
+}
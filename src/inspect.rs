@@ -0,0 +1,351 @@
+//! Reads what a stopped node left in its [data directory](crate::data_dir)
+//! without writing to it, for postmortems.
+
+use crate::alpha::{Alpha, Id};
+use crate::bootstrap::{BootstrapFile, ClusterId};
+use crate::codec::{from_bytes, Decode, Encode};
+use crate::data_dir::{DataDir, LayoutError};
+use crate::identity;
+use crate::instance::{InstanceId, InstanceStore};
+use crate::log::LogIndex;
+use crate::membership::{Applied, Configuration, Membership};
+use crate::storage::{FileStorage, RecordFile, Storage};
+use std::fmt::Write;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+/// Everything a node persisted, with the values it accepted decoded as `V`.
+#[derive(Debug)]
+pub struct Inspection<V> {
+    pub storage_path: PathBuf,
+    pub id: Option<Id>,
+    pub cluster: Option<ClusterId>,
+    pub acceptor: Option<Alpha<V>>,
+    pub tick: Option<u64>,
+    pub applied: Option<AppliedMembership>,
+    pub membership_log: Vec<Slot>,
+    pub snapshots: Vec<SnapshotFile>,
+}
+
+/// The membership the node last applied from its membership log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AppliedMembership {
+    /// The first slot of the membership log not applied yet.
+    pub next: LogIndex,
+    pub membership: Membership,
+    pub members: Vec<(Id, SocketAddr)>,
+    pub epoch: u64,
+}
+
+/// What the node's acceptor holds for one slot of the membership log.
+#[derive(Clone, Debug)]
+pub struct Slot {
+    pub instance: InstanceId,
+    pub state: Option<Alpha<Membership>>,
+    pub tick: Option<u64>,
+    /// Whether the node applied the slot, so its value was decided.
+    pub committed: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotFile {
+    pub name: String,
+    pub len: u64,
+    /// The last log entry the snapshot covers, unless the file is too short
+    /// to say.
+    pub last_included: Option<LogIndex>,
+}
+
+/// Reads the data directory at `root`, or the flat files of a
+/// `storage_path` from before data directories, decoding accepted values
+/// with `V`'s codec.
+pub fn inspect<V: Encode + Decode>(root: &Path) -> Result<Inspection<V>, LayoutError> {
+    let (storage_path, snapshots) = match root.is_file() {
+        true => (root.to_path_buf(), None),
+        false => {
+            let dir = DataDir::peek(root)?;
+            (dir.storage_path(), Some(dir.snapshots()))
+        }
+    };
+    let mut storage = FileStorage::<V>::new(&storage_path);
+    let applied = RecordFile::new(storage_path.with_extension("members"))
+        .recover()?
+        .map(|bytes| from_bytes::<Applied>(&bytes))
+        .transpose()?
+        .map(|applied| AppliedMembership {
+            next: applied.next,
+            membership: applied.membership,
+            members: applied.members,
+            epoch: applied.epoch,
+        });
+    Ok(Inspection {
+        id: identity::stored(identity::beside(&storage_path))?,
+        cluster: BootstrapFile::open(Some(storage_path.with_extension("cluster")))?
+            .adopted()
+            .map(|bootstrap| bootstrap.cluster()),
+        acceptor: storage.load()?,
+        tick: storage.load_tick()?,
+        membership_log: membership_log(&storage_path, applied.as_ref())?,
+        applied,
+        snapshots: match snapshots {
+            Some(dir) => snapshot_files(&dir)?,
+            None => Vec::new(),
+        },
+        storage_path,
+    })
+}
+
+impl<V> Inspection<V> {
+    /// One line per fact, showing accepted values with `value`.
+    pub fn render(&self, value: impl Fn(&V) -> String) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "storage: {}", self.storage_path.display());
+        let _ = writeln!(out, "id: {}", self.id.map_or("-".to_string(), id));
+        let _ = writeln!(
+            out,
+            "cluster: {}",
+            self.cluster
+                .map_or("-".to_string(), |cluster| cluster.to_string())
+        );
+        let _ = writeln!(out, "tick: {}", optional(self.tick));
+        match &self.acceptor {
+            Some(acceptor) => {
+                let _ = writeln!(out, "{}", state("acceptor:", acceptor, &value));
+            }
+            None => {
+                let _ = writeln!(out, "acceptor: nothing persisted");
+            }
+        }
+        match &self.applied {
+            Some(applied) => {
+                let _ = writeln!(
+                    out,
+                    "applied: next={} epoch={} membership={} members=[{}]",
+                    applied.next.get(),
+                    applied.epoch,
+                    membership(&applied.membership),
+                    applied
+                        .members
+                        .iter()
+                        .map(|(member, addr)| format!("{}@{addr}", id(*member)))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                );
+            }
+            None => {
+                let _ = writeln!(out, "applied: the bootstrap membership");
+            }
+        }
+        let _ = writeln!(out, "membership log: {} slots", self.membership_log.len());
+        for slot in &self.membership_log {
+            let label = format!(
+                "  {} {} tick={}",
+                slot.instance.0,
+                if slot.committed {
+                    "committed"
+                } else {
+                    "pending"
+                },
+                optional(slot.tick)
+            );
+            let _ = match &slot.state {
+                Some(alpha) => writeln!(out, "{}", state(&label, alpha, membership)),
+                None => writeln!(out, "{label}"),
+            };
+        }
+        let _ = writeln!(out, "snapshots: {}", self.snapshots.len());
+        for snapshot in &self.snapshots {
+            let _ = writeln!(
+                out,
+                "  {} bytes={} last_included={}",
+                snapshot.name,
+                snapshot.len,
+                optional(snapshot.last_included.map(LogIndex::get))
+            );
+        }
+        out
+    }
+}
+
+fn membership_log(
+    storage_path: &Path,
+    applied: Option<&AppliedMembership>,
+) -> io::Result<Vec<Slot>> {
+    let path = storage_path.with_extension("membership");
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let store = InstanceStore::<Membership>::open(path)?;
+    let next = applied.map_or(0, |applied| applied.next.get());
+    store
+        .instances()
+        .into_iter()
+        .map(|instance| {
+            let mut storage = store.storage(instance);
+            Ok(Slot {
+                instance,
+                state: storage.load()?,
+                tick: storage.load_tick()?,
+                committed: instance.0 < next,
+            })
+        })
+        .collect()
+}
+
+fn snapshot_files(dir: &Path) -> io::Result<Vec<SnapshotFile>> {
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let mut index = [0; 8];
+        let last_included = match File::open(entry.path())?.read_exact(&mut index) {
+            Ok(()) => Some(LogIndex::decode(&mut index.as_slice())?),
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => None,
+            Err(error) => return Err(error),
+        };
+        snapshots.push(SnapshotFile {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            len: entry.metadata()?.len(),
+            last_included,
+        });
+    }
+    snapshots.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(snapshots)
+}
+
+fn state<V>(label: &str, alpha: &Alpha<V>, value: impl Fn(&V) -> String) -> String {
+    match (alpha.accepted_round(), alpha.accepted_value()) {
+        (Some(round), Some(accepted)) => format!(
+            "{label} promised={:?} accepted={round:?} value={}",
+            alpha.last_round_entered(),
+            value(accepted)
+        ),
+        _ => format!(
+            "{label} promised={:?} accepted=-",
+            alpha.last_round_entered()
+        ),
+    }
+}
+
+fn membership(membership: &Membership) -> String {
+    let voters = |configuration: &Configuration| {
+        configuration
+            .voters()
+            .iter()
+            .map(|voter| id(*voter))
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    match membership {
+        Membership::Stable(voters_now) => format!("[{}]", voters(voters_now)),
+        Membership::Joint { old, new } => format!("[{}]->[{}]", voters(old), voters(new)),
+    }
+}
+
+fn id(id: Id) -> String {
+    id.get().to_string()
+}
+
+fn optional(value: Option<u64>) -> String {
+    value.map_or("-".to_string(), |value| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alpha::{Round, Value};
+    use crate::codec::to_bytes;
+    use crate::smr::Snapshot;
+    use crate::storage::write_atomic;
+
+    #[test]
+    fn reads_every_file_of_a_data_dir_without_writing() {
+        let root = std::env::temp_dir().join(format!("paxos-inspect-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        assert!(matches!(
+            inspect::<u64>(&root),
+            Err(LayoutError::NotInitialized(_))
+        ));
+        let dir = DataDir::init(&root, None).unwrap();
+        let path = dir.storage_path();
+        let mut storage = FileStorage::<u64>::new(&path);
+        let round = Round::new(Id(1)).next();
+        storage
+            .persist(&Alpha::from_state(round, Some(Value::new(7, round))))
+            .unwrap();
+        storage.persist_tick(2).unwrap();
+        dir.identity(Some(Id(1))).unwrap();
+
+        let store = InstanceStore::<Membership>::open(path.with_extension("membership")).unwrap();
+        let voters = Membership::Stable(Configuration::new([Id(1), Id(2)]));
+        for slot in 0..2 {
+            store
+                .storage(InstanceId(slot))
+                .persist(&Alpha::from_state(
+                    round,
+                    Some(Value::new(voters.clone(), round)),
+                ))
+                .unwrap();
+        }
+        let applied = Applied {
+            next: LogIndex::new(1),
+            membership: voters,
+            members: vec![(Id(1), "127.0.0.1:7001".parse().unwrap())],
+            epoch: 3,
+        };
+        RecordFile::new(path.with_extension("members"))
+            .append(&to_bytes(&applied))
+            .unwrap();
+        let snapshot = Snapshot {
+            last_included: LogIndex::new(4),
+            state: 11u64,
+        };
+        write_atomic(&dir.snapshots().join("4"), &to_bytes(&snapshot)).unwrap();
+
+        let inspection = inspect::<u64>(&root).unwrap();
+        assert_eq!(inspection.id, Some(Id(1)));
+        assert_eq!(inspection.tick, Some(2));
+        assert_eq!(inspection.cluster, None);
+        assert_eq!(
+            inspection.acceptor.as_ref().and_then(Alpha::accepted_value),
+            Some(&7)
+        );
+        assert_eq!(
+            inspection.applied.as_ref().map(|applied| applied.epoch),
+            Some(3)
+        );
+        let committed: Vec<_> = inspection
+            .membership_log
+            .iter()
+            .map(|slot| (slot.instance, slot.committed))
+            .collect();
+        assert_eq!(committed, [(InstanceId(0), true), (InstanceId(1), false)]);
+        assert_eq!(
+            inspection.snapshots,
+            [SnapshotFile {
+                name: "4".to_string(),
+                len: 16,
+                last_included: Some(LogIndex::new(4)),
+            }]
+        );
+
+        let rendered = inspection.render(|value| format!("#{value}"));
+        assert!(rendered.contains("id: 1\n"));
+        assert!(rendered.contains("value=#7"));
+        assert!(rendered
+            .contains("applied: next=1 epoch=3 membership=[1,2] members=[1@127.0.0.1:7001]"));
+        assert!(rendered.contains("  0 committed tick=- promised="));
+        assert!(rendered.contains("  1 pending"));
+        assert!(rendered.contains("  4 bytes=16 last_included=4"));
+
+        // A flat storage path reads the same way, and nothing was migrated.
+        let flat = inspect::<u64>(&path).unwrap();
+        assert_eq!(flat.id, Some(Id(1)));
+        assert!(flat.snapshots.is_empty());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
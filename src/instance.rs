@@ -0,0 +1,825 @@
+use crate::acceptor::Acceptor;
+use crate::alpha::{
+    Alpha, Error, Id, Quorum, ReadPeers, ReadResponse, Round, Value, WritePeers, WriteResponse,
+};
+use crate::codec::{from_bytes, invalid_data, to_bytes, Decode, Encode};
+use crate::learner::{DecisionBroadcast, DecisionPeers};
+use crate::proposer::{FailureDetector, Proposer, TickSource, Ticks};
+use crate::retry::RetryPolicy;
+use crate::storage::{RecordFile, Storage};
+use crate::time::{Clock, SystemClock};
+use std::collections::hash_map::Entry as HashEntry;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::mem;
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::SystemTime;
+
+#[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash, Default)]
+pub struct InstanceId(pub u64);
+
+pub trait InstancePeers<V> {
+    type Peers: WritePeers<V> + ReadPeers<V> + DecisionPeers<V> + Quorum;
+    fn instance(&self, instance: InstanceId) -> Self::Peers;
+
+    fn acknowledge(&self, _learner: Id, _below: InstanceId) {}
+}
+
+pub struct InstanceManager<V, P, D> {
+    id: Id,
+    peers: P,
+    failure_detector: D,
+    retry_policy: RetryPolicy,
+    clock: Arc<dyn Clock>,
+    ticks: Ticks,
+    store: Option<InstanceStore<V>>,
+    decided: Mutex<HashMap<InstanceId, (V, Round)>>,
+}
+
+impl<V, P, D> InstanceManager<V, P, D>
+where
+    V: Clone + Encode + Decode + Send + Sync + 'static,
+    P: InstancePeers<V>,
+    D: FailureDetector + Clone,
+{
+    pub fn new(id: Id, peers: P, failure_detector: D) -> Self {
+        Self {
+            id,
+            peers,
+            failure_detector,
+            retry_policy: RetryPolicy::default(),
+            clock: Arc::new(SystemClock),
+            ticks: Ticks::new(TickSource::Counter),
+            store: None,
+            decided: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Rounds for instances without a [`store`](Self::store) are drawn from
+    /// `ticks`, so a retried decision never reuses one.
+    pub fn ticks(mut self, ticks: Ticks) -> Self {
+        self.ticks = ticks;
+        self
+    }
+
+    pub fn store(mut self, store: InstanceStore<V>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    pub(crate) fn system_time(&self) -> SystemTime {
+        self.clock.system_time()
+    }
+
+    pub async fn propose(&self, instance: InstanceId, value: V) -> Result<V, Error> {
+        let (decided, _) = self.decide(instance, value).await?;
+        Ok(decided)
+    }
+
+    pub async fn decide(&self, instance: InstanceId, value: V) -> Result<(V, Round), Error> {
+        if let Some(decided) = lock(&self.decided).get(&instance) {
+            return Ok(decided.clone());
+        }
+        let mut builder = Proposer::builder(
+            self.id,
+            self.peers.instance(instance),
+            self.failure_detector.clone(),
+        )
+        .retry_policy(self.retry_policy.clone())
+        .clock(self.clock.clone());
+        builder = match &self.store {
+            Some(store) => builder.storage(store.storage(instance))?,
+            None => builder.ticks(self.ticks.clone()),
+        };
+        let mut proposer = builder.build();
+        let chosen = proposer.propose(value).await?;
+        Ok(lock(&self.decided)
+            .entry(instance)
+            .or_insert((chosen, proposer.round()))
+            .clone())
+    }
+
+    pub fn decision(&self, instance: InstanceId) -> Option<V> {
+        lock(&self.decided)
+            .get(&instance)
+            .map(|(value, _)| value.clone())
+    }
+
+    /// Reports that every instance below `below` has been applied here, so
+    /// acceptors can free them once all members have done the same.
+    pub fn acknowledge(&self, below: InstanceId) {
+        lock(&self.decided).retain(|instance, _| *instance >= below);
+        self.peers.acknowledge(self.id, below);
+    }
+}
+
+type InstanceAcceptor<V> = Acceptor<V, InstanceStorage<V>>;
+
+pub struct InstanceAcceptors<V> {
+    id: Id,
+    store: InstanceStore<V>,
+    acceptors: Mutex<HashMap<InstanceId, Arc<Mutex<InstanceAcceptor<V>>>>>,
+    decisions: Mutex<HashMap<InstanceId, V>>,
+    applied: Mutex<HashMap<Id, InstanceId>>,
+    first: Mutex<InstanceId>,
+}
+
+impl<V> InstanceAcceptors<V>
+where
+    V: Clone + PartialEq + Encode + Decode,
+{
+    pub fn new(id: Id, store: InstanceStore<V>) -> Self {
+        Self {
+            id,
+            store,
+            acceptors: Mutex::new(HashMap::new()),
+            decisions: Mutex::new(HashMap::new()),
+            applied: Mutex::new(HashMap::new()),
+            first: Mutex::new(InstanceId::default()),
+        }
+    }
+
+    pub fn learners(self, learners: impl IntoIterator<Item = Id>) -> Self {
+        self.set_learners(learners);
+        self
+    }
+
+    pub fn set_learners(&self, learners: impl IntoIterator<Item = Id>) {
+        let mut applied = lock(&self.applied);
+        *applied = learners
+            .into_iter()
+            .map(|learner| (learner, applied.get(&learner).copied().unwrap_or_default()))
+            .collect();
+    }
+
+    pub fn first_instance(&self) -> InstanceId {
+        *lock(&self.first)
+    }
+
+    pub fn handle_read(
+        &self,
+        instance: InstanceId,
+        round: Round,
+    ) -> Result<ReadResponse<V>, Error> {
+        self.with_acceptor(instance, |acceptor| acceptor.handle_read(round))
+    }
+
+    pub fn handle_write(
+        &self,
+        instance: InstanceId,
+        value: Value<V>,
+    ) -> Result<WriteResponse, Error> {
+        self.with_acceptor(instance, |acceptor| acceptor.handle_write(value))
+    }
+
+    pub fn handle_decision(&self, instance: InstanceId, decision: DecisionBroadcast<V>) {
+        if instance < self.first_instance() {
+            return;
+        }
+        lock(&self.decisions)
+            .entry(instance)
+            .or_insert(decision.value);
+    }
+
+    pub fn acknowledge(&self, learner: Id, below: InstanceId) -> Result<InstanceId, Error> {
+        let watermark = {
+            let mut applied = lock(&self.applied);
+            if let Some(applied) = applied.get_mut(&learner) {
+                *applied = (*applied).max(below);
+            }
+            applied.values().min().copied().unwrap_or_default()
+        };
+        {
+            let mut acceptors = lock(&self.acceptors);
+            let mut first = lock(&self.first);
+            if watermark <= *first {
+                return Ok(*first);
+            }
+            *first = watermark;
+            acceptors.retain(|instance, _| *instance >= watermark);
+            lock(&self.decisions).retain(|instance, _| *instance >= watermark);
+        }
+        self.store
+            .discard_below(watermark)
+            .map_err(|error| Error::Storage(Box::new(error)))?;
+        Ok(watermark)
+    }
+
+    pub fn decision(&self, instance: InstanceId) -> Option<V> {
+        lock(&self.decisions).get(&instance).cloned()
+    }
+
+    fn with_acceptor<R>(
+        &self,
+        instance: InstanceId,
+        handle: impl FnOnce(&mut InstanceAcceptor<V>) -> Result<R, Error>,
+    ) -> Result<R, Error> {
+        // Only the lookup holds the map; each instance persists under its own
+        // lock, so a slow fsync does not stall the other instances.
+        let acceptor = {
+            let mut acceptors = lock(&self.acceptors);
+            if instance < self.first_instance() {
+                return Err(Error::Collected(instance.0));
+            }
+            match acceptors.entry(instance) {
+                HashEntry::Occupied(entry) => entry.get().clone(),
+                HashEntry::Vacant(entry) => {
+                    let acceptor = Acceptor::new(self.id, self.store.storage(instance))?;
+                    entry.insert(Arc::new(Mutex::new(acceptor))).clone()
+                }
+            }
+        };
+        let mut acceptor = lock(&acceptor);
+        handle(&mut acceptor)
+    }
+}
+
+const COMPACT_AFTER: usize = 1024;
+
+/// Acceptor state for every instance, kept in one append-only file of
+/// per-instance changes that are folded on [`open`](Self::open).
+pub struct InstanceStore<V> {
+    inner: Arc<StoreInner<V>>,
+}
+
+struct StoreInner<V> {
+    // Changes are queued under `entries`, so the file sees them in the order
+    // they were made in; `entries` alone serves reads.
+    file: Mutex<Option<RecordFile>>,
+    durable: bool,
+    entries: Mutex<Entries<V>>,
+    queue: Mutex<Queue>,
+    flushed: Condvar,
+}
+
+/// Changes waiting for the file. Whoever finds no flush in progress writes
+/// everything queued so far with one sync, while the rest wait to hear that
+/// their change is durable.
+#[derive(Default)]
+struct Queue {
+    pending: Vec<Vec<u8>>,
+    queued: u64,
+    durable: u64,
+    flushing: bool,
+    syncs: u64,
+    // What reached the disk after a failed write is unknown, so the store
+    // refuses every later change rather than risk both outcomes.
+    failed: Option<(io::ErrorKind, String)>,
+}
+
+impl Queue {
+    fn failure(&self) -> Option<io::Error> {
+        let (kind, message) = self.failed.as_ref()?;
+        Some(io::Error::new(*kind, message.clone()))
+    }
+}
+
+struct Entries<V> {
+    records: BTreeMap<InstanceId, Record<V>>,
+    below: InstanceId,
+}
+
+#[derive(Clone)]
+struct Record<V> {
+    instance: InstanceId,
+    state: Option<Alpha<V>>,
+    tick: Option<u64>,
+}
+
+enum Change<V> {
+    Put(Record<V>),
+    Remove(InstanceId),
+    Below(InstanceId),
+}
+
+impl<V> Clone for InstanceStore<V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<V> Entries<V> {
+    fn apply(&mut self, change: Change<V>) {
+        match change {
+            Change::Put(record) if record.instance >= self.below => {
+                self.records.insert(record.instance, record);
+            }
+            Change::Put(_) => {}
+            Change::Remove(instance) => {
+                self.records.remove(&instance);
+            }
+            Change::Below(below) => {
+                self.below = self.below.max(below);
+                self.records = self.records.split_off(&self.below);
+            }
+        }
+    }
+}
+
+impl<V> InstanceStore<V>
+where
+    V: Clone + Encode + Decode,
+{
+    pub fn in_memory() -> Self {
+        Self::with_entries(None, Entries::default())
+    }
+
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let mut file = RecordFile::log(path.into());
+        let mut entries = Entries::default();
+        for payload in file.recover_all()? {
+            entries.apply(from_bytes(&payload)?);
+        }
+        Ok(Self::with_entries(Some(file), entries))
+    }
+
+    pub fn storage(&self, instance: InstanceId) -> InstanceStorage<V> {
+        InstanceStorage {
+            store: self.clone(),
+            instance,
+        }
+    }
+
+    pub fn instances(&self) -> Vec<InstanceId> {
+        lock(&self.inner.entries).records.keys().copied().collect()
+    }
+
+    pub fn discard_below(&self, below: InstanceId) -> io::Result<()> {
+        self.change(|_| Some(Change::Below(below)))
+    }
+
+    /// How many times the store has synced its file; fewer than the number
+    /// of changes when concurrent changes were written together.
+    pub fn syncs(&self) -> u64 {
+        lock(&self.inner.queue).syncs
+    }
+
+    fn with_entries(file: Option<RecordFile>, entries: Entries<V>) -> Self {
+        Self {
+            inner: Arc::new(StoreInner {
+                durable: file.is_some(),
+                file: Mutex::new(file),
+                entries: Mutex::new(entries),
+                queue: Mutex::new(Queue::default()),
+                flushed: Condvar::new(),
+            }),
+        }
+    }
+
+    fn get<T>(&self, instance: InstanceId, field: impl FnOnce(&Record<V>) -> T) -> Option<T> {
+        lock(&self.inner.entries).records.get(&instance).map(field)
+    }
+
+    fn update(&self, instance: InstanceId, update: impl FnOnce(&mut Record<V>)) -> io::Result<()> {
+        self.change(|entries| {
+            // A straggler for a collected instance must not bring it back.
+            if instance < entries.below {
+                return None;
+            }
+            let record = entries.records.entry(instance).or_insert(Record {
+                instance,
+                state: None,
+                tick: None,
+            });
+            update(record);
+            Some(Change::Put(record.clone()))
+        })
+    }
+
+    fn change(&self, change: impl FnOnce(&mut Entries<V>) -> Option<Change<V>>) -> io::Result<()> {
+        let durable = self.inner.durable;
+        let sequence = {
+            let mut entries = lock(&self.inner.entries);
+            let mut queue = lock(&self.inner.queue);
+            if let Some(error) = queue.failure() {
+                return Err(error);
+            }
+            let Some(change) = change(&mut entries) else {
+                return Ok(());
+            };
+            if durable {
+                queue.pending.push(to_bytes(&change));
+                queue.queued += 1;
+            }
+            entries.apply(change);
+            queue.queued
+        };
+        if !durable {
+            return Ok(());
+        }
+
+        let mut queue = lock(&self.inner.queue);
+        loop {
+            if queue.durable >= sequence {
+                return queue.failure().map_or(Ok(()), Err);
+            }
+            if !queue.flushing {
+                break;
+            }
+            queue = self
+                .inner
+                .flushed
+                .wait(queue)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        queue.flushing = true;
+        drop(queue);
+
+        let result = self.flush();
+        lock(&self.inner.queue).flushing = false;
+        self.inner.flushed.notify_all();
+        result
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        let mut file = lock(&self.inner.file);
+        let Some(file) = &mut *file else {
+            return Ok(());
+        };
+        let (batch, through) = {
+            let mut queue = lock(&self.inner.queue);
+            (mem::take(&mut queue.pending), queue.queued)
+        };
+        let result = file.append_all(batch.iter().map(Vec::as_slice));
+        let mut queue = lock(&self.inner.queue);
+        queue.syncs += 1;
+        if let Err(error) = &result {
+            queue.failed = Some((error.kind(), error.to_string()));
+        }
+        // Whatever queued while the file was busy went out with this write.
+        queue.durable = through;
+        drop(queue);
+        result?;
+        let live = lock(&self.inner.entries).records.len();
+        if file.records() >= COMPACT_AFTER.max(2 * live) {
+            let snapshot = self.snapshot();
+            file.rewrite(snapshot.iter().map(Vec::as_slice))?;
+        }
+        Ok(())
+    }
+
+    fn snapshot(&self) -> Vec<Vec<u8>> {
+        let entries = lock(&self.inner.entries);
+        std::iter::once(to_bytes(&Change::<V>::Below(entries.below)))
+            .chain(
+                entries
+                    .records
+                    .values()
+                    .map(|record| to_bytes(&Change::Put(record.clone()))),
+            )
+            .collect()
+    }
+}
+
+impl<V> Default for Entries<V> {
+    fn default() -> Self {
+        Self {
+            records: BTreeMap::new(),
+            below: InstanceId::default(),
+        }
+    }
+}
+
+pub struct InstanceStorage<V> {
+    store: InstanceStore<V>,
+    instance: InstanceId,
+}
+
+impl<V> Storage<V> for InstanceStorage<V>
+where
+    V: Clone + Encode + Decode,
+{
+    type Error = io::Error;
+
+    fn persist(&mut self, state: &Alpha<V>) -> Result<(), Self::Error> {
+        self.store
+            .update(self.instance, |record| record.state = Some(state.clone()))
+    }
+
+    fn load(&mut self) -> Result<Option<Alpha<V>>, Self::Error> {
+        Ok(self
+            .store
+            .get(self.instance, |record| record.state.clone())
+            .flatten())
+    }
+
+    fn persist_tick(&mut self, tick: u64) -> Result<(), Self::Error> {
+        self.store
+            .update(self.instance, |record| record.tick = Some(tick))
+    }
+
+    fn load_tick(&mut self) -> Result<Option<u64>, Self::Error> {
+        Ok(self
+            .store
+            .get(self.instance, |record| record.tick)
+            .flatten())
+    }
+
+    fn discard(&mut self) -> Result<(), Self::Error> {
+        let instance = self.instance;
+        self.store.change(|_| Some(Change::Remove(instance)))
+    }
+}
+
+impl<V: Encode> Encode for Record<V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.instance.encode(buf);
+        self.state.encode(buf);
+        self.tick.encode(buf);
+    }
+}
+
+impl<V: Decode> Decode for Record<V> {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            instance: InstanceId::decode(buf)?,
+            state: Option::decode(buf)?,
+            tick: Option::decode(buf)?,
+        })
+    }
+}
+
+impl<V: Encode> Encode for Change<V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Change::Put(record) => {
+                0u8.encode(buf);
+                record.encode(buf);
+            }
+            Change::Remove(instance) => {
+                1u8.encode(buf);
+                instance.encode(buf);
+            }
+            Change::Below(below) => {
+                2u8.encode(buf);
+                below.encode(buf);
+            }
+        }
+    }
+}
+
+impl<V: Decode> Decode for Change<V> {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        match u8::decode(buf)? {
+            0 => Ok(Change::Put(Record::decode(buf)?)),
+            1 => Ok(Change::Remove(InstanceId::decode(buf)?)),
+            2 => Ok(Change::Below(InstanceId::decode(buf)?)),
+            _ => Err(invalid_data("invalid instance change tag")),
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alpha::Status;
+    use futures::executor::block_on;
+    use futures::stream::{self, Stream};
+    use std::future::{self, Future};
+
+    #[derive(Clone)]
+    struct Peers(Arc<Vec<InstanceAcceptors<u64>>>);
+
+    impl Peers {
+        fn new() -> Self {
+            let acceptors = (1..=3)
+                .map(|id| InstanceAcceptors::new(Id(id), InstanceStore::in_memory()))
+                .collect();
+            Self(Arc::new(acceptors))
+        }
+    }
+
+    struct Instance(Peers, InstanceId);
+
+    impl Instance {
+        fn each<T>(
+            &self,
+            handle: impl Fn(&InstanceAcceptors<u64>) -> Result<T, Error>,
+        ) -> stream::Iter<std::vec::IntoIter<Result<T, Error>>> {
+            stream::iter(self.0 .0.iter().map(handle).collect::<Vec<_>>())
+        }
+    }
+
+    impl ReadPeers<u64> for Instance {
+        fn read(&self, round: Round) -> impl Stream<Item = Result<ReadResponse<u64>, Error>> {
+            self.each(|acceptors| acceptors.handle_read(self.1, round))
+        }
+    }
+
+    impl WritePeers<u64> for Instance {
+        fn write(&self, value: Value<u64>) -> impl Stream<Item = Result<WriteResponse, Error>> {
+            self.each(|acceptors| acceptors.handle_write(self.1, value.clone()))
+        }
+    }
+
+    impl DecisionPeers<u64> for Instance {
+        fn decide(
+            &self,
+            decision: DecisionBroadcast<u64>,
+        ) -> impl Stream<Item = Result<(), Error>> {
+            self.each(|acceptors| {
+                acceptors.handle_decision(self.1, decision.clone());
+                Ok(())
+            })
+        }
+    }
+
+    impl Quorum for Instance {
+        fn majority(&self) -> usize {
+            2
+        }
+
+        fn max_failures(&self) -> usize {
+            1
+        }
+    }
+
+    impl InstancePeers<u64> for Peers {
+        type Peers = Instance;
+
+        fn instance(&self, instance: InstanceId) -> Self::Peers {
+            Instance(self.clone(), instance)
+        }
+    }
+
+    #[derive(Clone)]
+    struct Leader(Id);
+
+    impl FailureDetector for Leader {
+        fn leader(&self) -> Id {
+            self.0
+        }
+
+        fn changed(&self) -> impl Future<Output = ()> {
+            future::pending()
+        }
+    }
+
+    #[test]
+    fn instances_decide_independently() {
+        let peers = Peers::new();
+        let first = InstanceManager::new(Id(1), peers.clone(), Leader(Id(1)));
+        assert_eq!(block_on(first.propose(InstanceId(0), 10)).unwrap(), 10);
+        assert_eq!(block_on(first.propose(InstanceId(1), 11)).unwrap(), 11);
+        assert_eq!(block_on(first.propose(InstanceId(0), 99)).unwrap(), 10);
+        assert_eq!(first.decision(InstanceId(1)), Some(11));
+        assert_eq!(peers.0[2].decision(InstanceId(0)), Some(10));
+
+        let second = InstanceManager::new(Id(2), peers, Leader(Id(2)));
+        assert_eq!(block_on(second.propose(InstanceId(1), 21)).unwrap(), 11);
+        assert_eq!(block_on(second.propose(InstanceId(2), 22)).unwrap(), 22);
+    }
+
+    #[test]
+    fn store_recovers_accepted_instances() {
+        let path = std::env::temp_dir().join(format!("paxos-instances-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let round = Round::new(Id(1));
+        {
+            let acceptors = InstanceAcceptors::new(Id(1), InstanceStore::open(&path).unwrap());
+            acceptors
+                .handle_write(InstanceId(4), Value::new(40u64, round))
+                .unwrap();
+        }
+
+        let store = InstanceStore::<u64>::open(&path).unwrap();
+        assert_eq!(store.instances(), [InstanceId(4)]);
+        let acceptors = InstanceAcceptors::new(Id(1), store);
+        let read = acceptors.handle_read(InstanceId(4), round.next()).unwrap();
+        assert_eq!(read.state.value.map(|value| *value.value()), Some(40));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn frees_instances_every_learner_has_applied() {
+        let store = InstanceStore::<u64>::in_memory();
+        let acceptors = InstanceAcceptors::new(Id(1), store.clone()).learners([Id(1), Id(2)]);
+        let round = Round::new(Id(1));
+        for instance in 0..4 {
+            acceptors
+                .handle_write(InstanceId(instance), Value::new(instance, round))
+                .unwrap();
+        }
+
+        assert_eq!(
+            acceptors.acknowledge(Id(1), InstanceId(3)).unwrap(),
+            InstanceId(0)
+        );
+        assert_eq!(
+            acceptors.acknowledge(Id(2), InstanceId(2)).unwrap(),
+            InstanceId(2)
+        );
+        assert_eq!(store.instances(), [InstanceId(2), InstanceId(3)]);
+        assert!(matches!(
+            acceptors.handle_read(InstanceId(1), round.next()),
+            Err(Error::Collected(1))
+        ));
+        assert!(acceptors.handle_read(InstanceId(2), round.next()).is_ok());
+
+        acceptors.set_learners([Id(1), Id(2), Id(3)]);
+        assert_eq!(
+            acceptors.acknowledge(Id(2), InstanceId(4)).unwrap(),
+            InstanceId(2)
+        );
+    }
+
+    #[test]
+    fn store_appends_one_record_per_change() {
+        let path = std::env::temp_dir().join(format!("paxos-changes-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let round = Round::new(Id(1));
+        {
+            let acceptors = InstanceAcceptors::new(Id(1), InstanceStore::open(&path).unwrap());
+            for instance in 0..3 {
+                acceptors
+                    .handle_write(InstanceId(instance), Value::new(instance, round))
+                    .unwrap();
+            }
+            acceptors.handle_read(InstanceId(1), round.next()).unwrap();
+            let mut file = RecordFile::log(path.clone());
+            assert_eq!(file.recover_all().unwrap().len(), 4);
+
+            let store = InstanceStore::<u64>::open(&path).unwrap();
+            store.discard_below(InstanceId(1)).unwrap();
+            store.storage(InstanceId(0)).persist(&Alpha::new()).unwrap();
+        }
+
+        let store = InstanceStore::<u64>::open(&path).unwrap();
+        assert_eq!(store.instances(), [InstanceId(1), InstanceId(2)]);
+        let state = store.storage(InstanceId(1)).load().unwrap().unwrap();
+        assert_eq!(state.last_round_entered(), round.next());
+        assert_eq!(state.accepted_value(), Some(&1));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn instances_do_not_wait_on_each_other() {
+        let acceptors = InstanceAcceptors::new(Id(1), InstanceStore::<u64>::in_memory());
+        let round = Round::new(Id(1));
+        // Holding one instance's acceptor must not block another's.
+        let inner = acceptors
+            .with_acceptor(InstanceId(1), |_| {
+                acceptors.handle_write(InstanceId(2), Value::new(2, round))
+            })
+            .unwrap();
+        assert_eq!(inner.status, Status::Accepted);
+    }
+
+    #[test]
+    fn changes_queued_behind_a_sync_share_the_next_one() {
+        let path = std::env::temp_dir().join(format!("paxos-group-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let store = InstanceStore::<u64>::open(&path).unwrap();
+        let file = lock(&store.inner.file);
+        let writers: Vec<_> = (0..4)
+            .map(|instance| {
+                let mut storage = store.storage(InstanceId(instance));
+                std::thread::spawn(move || storage.persist_tick(instance))
+            })
+            .collect();
+        while lock(&store.inner.queue).queued < 4 {
+            std::thread::yield_now();
+        }
+        drop(file);
+        for writer in writers {
+            writer.join().unwrap().unwrap();
+        }
+        assert_eq!(store.syncs(), 1);
+
+        let store = InstanceStore::<u64>::open(&path).unwrap();
+        assert_eq!(store.instances().len(), 4);
+        assert_eq!(store.storage(InstanceId(3)).load_tick().unwrap(), Some(3));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn refuses_changes_after_a_failed_write() {
+        let path = std::env::temp_dir()
+            .join(format!("paxos-missing-{}", std::process::id()))
+            .join("instances");
+        let store = InstanceStore::<u64>::open(&path).unwrap();
+        let mut storage = store.storage(InstanceId(1));
+        let error = storage.persist_tick(1).unwrap_err();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(storage.persist_tick(2).unwrap_err().kind(), error.kind());
+        assert!(!path.exists());
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,829 @@
+use crate::alpha;
+use crate::log::{LogIndex, ReplicatedLog, SlotPeers};
+use crate::proposer::FailureDetector;
+use crate::session::{
+    ClientId, ClientSession, SessionError, SessionRequest, SessionState, Sessions,
+};
+use crate::smr::{Replica, SessionToken, Snapshot, Snapshotting, StateMachine};
+use crate::time::Timestamp;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct LeaseId(pub u64);
+
+/// Expiry is only ever decided by `Expire` entries proposed by the leader, so
+/// every replica drops the same keys at the same log index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Put {
+        key: String,
+        value: String,
+    },
+    Delete {
+        key: String,
+    },
+    PutExpiring {
+        key: String,
+        value: String,
+        expires: Timestamp,
+    },
+    /// Granting a lease that is already granted renews it.
+    Grant {
+        lease: LeaseId,
+        expires: Timestamp,
+    },
+    PutLeased {
+        key: String,
+        value: String,
+        lease: LeaseId,
+    },
+    Revoke {
+        lease: LeaseId,
+    },
+    Expire {
+        now: Timestamp,
+    },
+    /// Writes `value`, or deletes the key when it is `None`, only if the key
+    /// meets `expected` when the entry is applied.
+    CompareAndSwap {
+        key: String,
+        expected: Precondition,
+        value: Option<String>,
+    },
+}
+
+/// What a conditional write expects of its key, `None` meaning absent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Precondition {
+    Value(Option<String>),
+    /// The log index of the entry that last wrote the key.
+    Revision(Option<LogIndex>),
+}
+
+pub type Request = SessionRequest<Command>;
+
+pub type Output = Result<Option<String>, Refused>;
+
+pub type KvSnapshot = Snapshot<SessionState<KvState, Output>>;
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("lease {0:?} is not granted")]
+pub struct UnknownLease(pub LeaseId);
+
+/// What a conditional write found instead of what it expected.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("`{key}` does not meet the write's precondition")]
+pub struct Conflict {
+    pub key: String,
+    pub current: Option<String>,
+    pub revision: Option<LogIndex>,
+}
+
+/// Why an entry changed nothing.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum Refused {
+    #[error(transparent)]
+    UnknownLease(#[from] UnknownLease),
+    #[error(transparent)]
+    Conflict(#[from] Conflict),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lease {
+    pub expires: Timestamp,
+    pub keys: BTreeSet<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KvState {
+    pub entries: BTreeMap<String, String>,
+    pub expiries: BTreeMap<String, Timestamp>,
+    pub leases: BTreeMap<LeaseId, Lease>,
+    pub revisions: BTreeMap<String, LogIndex>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct KvStore {
+    state: KvState,
+}
+
+impl KvStore {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.state.entries.get(key).map(String::as_str)
+    }
+
+    pub fn expires(&self, key: &str) -> Option<Timestamp> {
+        let leased = self
+            .state
+            .leases
+            .values()
+            .find(|lease| lease.keys.contains(key));
+        leased
+            .map(|lease| lease.expires)
+            .or_else(|| self.state.expiries.get(key).copied())
+    }
+
+    /// The log index of the entry that last wrote `key`.
+    pub fn revision(&self, key: &str) -> Option<LogIndex> {
+        self.state.revisions.get(key).copied()
+    }
+
+    pub fn lease(&self, lease: LeaseId) -> Option<&Lease> {
+        self.state.leases.get(&lease)
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state.entries.is_empty()
+    }
+
+    fn insert(&mut self, index: LogIndex, key: &str, value: &str) -> Option<String> {
+        let previous = self.remove(key);
+        self.state
+            .entries
+            .insert(key.to_string(), value.to_string());
+        self.state.revisions.insert(key.to_string(), index);
+        previous
+    }
+
+    // Drops the key's expiry and lease along with it, so a later put starts
+    // from a key that never expires.
+    fn remove(&mut self, key: &str) -> Option<String> {
+        self.state.expiries.remove(key);
+        for lease in self.state.leases.values_mut() {
+            lease.keys.remove(key);
+        }
+        self.state.revisions.remove(key);
+        self.state.entries.remove(key)
+    }
+
+    fn revoke(&mut self, lease: LeaseId) {
+        if let Some(lease) = self.state.leases.remove(&lease) {
+            for key in lease.keys {
+                self.state.revisions.remove(&key);
+                self.state.entries.remove(&key);
+            }
+        }
+    }
+
+    fn check(&self, key: &str, expected: &Precondition) -> Result<(), Conflict> {
+        let met = match expected {
+            Precondition::Value(value) => value.as_deref() == self.get(key),
+            Precondition::Revision(revision) => *revision == self.revision(key),
+        };
+        match met {
+            true => Ok(()),
+            false => Err(Conflict {
+                key: key.to_string(),
+                current: self.get(key).map(str::to_string),
+                revision: self.revision(key),
+            }),
+        }
+    }
+
+    fn expire(&mut self, now: Timestamp) {
+        let expired: Vec<_> = self
+            .state
+            .expiries
+            .iter()
+            .filter(|(_, expires)| **expires <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            self.remove(&key);
+        }
+        let expired: Vec<_> = self
+            .state
+            .leases
+            .iter()
+            .filter(|(_, lease)| lease.expires <= now)
+            .map(|(lease, _)| *lease)
+            .collect();
+        for lease in expired {
+            self.revoke(lease);
+        }
+    }
+}
+
+impl StateMachine<Command> for KvStore {
+    type Output = Output;
+
+    fn apply(&mut self, index: LogIndex, command: &Command) -> Self::Output {
+        let previous = match command {
+            Command::Put { key, value } => self.insert(index, key, value),
+            Command::Delete { key } => self.remove(key),
+            Command::PutExpiring {
+                key,
+                value,
+                expires,
+            } => {
+                let previous = self.insert(index, key, value);
+                self.state.expiries.insert(key.clone(), *expires);
+                previous
+            }
+            Command::Grant { lease, expires } => {
+                self.state
+                    .leases
+                    .entry(*lease)
+                    .or_insert_with(|| Lease {
+                        expires: *expires,
+                        keys: BTreeSet::new(),
+                    })
+                    .expires = *expires;
+                None
+            }
+            Command::PutLeased { key, value, lease } => {
+                if !self.state.leases.contains_key(lease) {
+                    return Err(UnknownLease(*lease).into());
+                }
+                let previous = self.insert(index, key, value);
+                if let Some(lease) = self.state.leases.get_mut(lease) {
+                    lease.keys.insert(key.clone());
+                }
+                previous
+            }
+            Command::Revoke { lease } => {
+                self.revoke(*lease);
+                None
+            }
+            Command::Expire { now } => {
+                self.expire(*now);
+                None
+            }
+            Command::CompareAndSwap {
+                key,
+                expected,
+                value,
+            } => {
+                self.check(key, expected)?;
+                match value {
+                    Some(value) => self.insert(index, key, value),
+                    None => self.remove(key),
+                }
+            }
+        };
+        Ok(previous)
+    }
+}
+
+impl Snapshotting<Command> for KvStore {
+    type State = KvState;
+
+    fn snapshot(&self) -> Self::State {
+        self.state.clone()
+    }
+
+    fn restore(&mut self, state: Self::State) {
+        self.state = state;
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum KvError {
+    #[error(transparent)]
+    Paxos(#[from] alpha::Error),
+    #[error(transparent)]
+    Session(#[from] SessionError),
+    #[error(transparent)]
+    UnknownLease(#[from] UnknownLease),
+    #[error(transparent)]
+    Conflict(#[from] Conflict),
+}
+
+impl From<Refused> for KvError {
+    fn from(refused: Refused) -> Self {
+        match refused {
+            Refused::UnknownLease(unknown) => unknown.into(),
+            Refused::Conflict(conflict) => conflict.into(),
+        }
+    }
+}
+
+pub struct KvClient<S, D> {
+    replica: Replica<Sessions<KvStore, Output>, Request, S, D>,
+    session: ClientSession,
+    pending: Option<Request>,
+    written: SessionToken,
+}
+
+impl<S, D> KvClient<S, D>
+where
+    S: SlotPeers<Request>,
+    D: FailureDetector + Clone,
+{
+    pub fn new(log: ReplicatedLog<Request, S, D>, client: ClientId) -> Self {
+        Self {
+            replica: Replica::new(log, Sessions::new(KvStore::default())),
+            session: ClientSession::new(client),
+            pending: None,
+            written: SessionToken::default(),
+        }
+    }
+
+    /// Also reads what was written under `token`, e.g. through another
+    /// handle of the same client.
+    pub fn with_session_token(mut self, token: SessionToken) -> Self {
+        self.written = self.written.merge(token);
+        self
+    }
+
+    /// What this client has written; see [`get_own`](Self::get_own).
+    pub fn session_token(&self) -> SessionToken {
+        self.written
+    }
+
+    pub async fn put(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<Option<String>, KvError> {
+        self.submit(Command::Put {
+            key: key.into(),
+            value: value.into(),
+        })
+        .await
+    }
+
+    pub async fn delete(&mut self, key: impl Into<String>) -> Result<Option<String>, KvError> {
+        self.submit(Command::Delete { key: key.into() }).await
+    }
+
+    pub async fn put_with_ttl(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+        ttl: Duration,
+    ) -> Result<Option<String>, KvError> {
+        let expires = self.replica.log_mut().now().after(ttl);
+        self.submit(Command::PutExpiring {
+            key: key.into(),
+            value: value.into(),
+            expires,
+        })
+        .await
+    }
+
+    pub async fn grant(&mut self, lease: LeaseId, ttl: Duration) -> Result<(), KvError> {
+        let expires = self.replica.log_mut().now().after(ttl);
+        self.submit(Command::Grant { lease, expires }).await?;
+        Ok(())
+    }
+
+    pub async fn put_leased(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+        lease: LeaseId,
+    ) -> Result<Option<String>, KvError> {
+        self.submit(Command::PutLeased {
+            key: key.into(),
+            value: value.into(),
+            lease,
+        })
+        .await
+    }
+
+    /// Writes `value` to `key`, or deletes it when `value` is `None`, only
+    /// if the key still meets `expected` when the write is applied; fails
+    /// with [`KvError::Conflict`] and what the key holds instead.
+    pub async fn compare_and_swap(
+        &mut self,
+        key: impl Into<String>,
+        expected: Precondition,
+        value: Option<String>,
+    ) -> Result<Option<String>, KvError> {
+        self.submit(Command::CompareAndSwap {
+            key: key.into(),
+            expected,
+            value,
+        })
+        .await
+    }
+
+    pub async fn revoke(&mut self, lease: LeaseId) -> Result<(), KvError> {
+        self.submit(Command::Revoke { lease }).await?;
+        Ok(())
+    }
+
+    /// Proposes an expiration entry at the leader's current time; the leader
+    /// calls this periodically so replicas never consult their own clocks.
+    pub async fn expire(&mut self) -> Result<(), KvError> {
+        let now = self.replica.log_mut().now();
+        self.submit(Command::Expire { now }).await?;
+        Ok(())
+    }
+
+    pub async fn get(&mut self, key: &str) -> Result<Option<String>, KvError> {
+        let value = self
+            .replica
+            .read_quorum(|sessions| sessions.state_machine().get(key).map(str::to_string))
+            .await?;
+        Ok(value)
+    }
+
+    /// Reads `key` from this handle's replica once it has applied
+    /// everything this client wrote, learning what it is missing, so the
+    /// read sees the client's own writes without a round to the leader.
+    /// Only a replica still behind after that reads like [`get`](Self::get).
+    pub async fn get_own(&mut self, key: &str) -> Result<Option<String>, KvError> {
+        let read = |sessions: &Sessions<KvStore, Output>| {
+            sessions.state_machine().get(key).map(str::to_string)
+        };
+        match self.replica.read_at(self.written, read).await {
+            Err(alpha::Error::NotCommitted(_)) => self.get(key).await,
+            read => Ok(read?),
+        }
+    }
+
+    pub fn store(&self) -> &KvStore {
+        self.replica.state_machine().state_machine()
+    }
+
+    pub fn snapshot(&mut self) -> Result<KvSnapshot, KvError> {
+        let next = self.replica.log().next_index().get();
+        let last = next
+            .checked_sub(1)
+            .ok_or(alpha::Error::NotCommitted(next))?;
+        Ok(self.replica.take_snapshot(LogIndex::new(last))?)
+    }
+
+    pub fn restore(&mut self, snapshot: KvSnapshot) {
+        self.replica.install_snapshot(snapshot);
+    }
+
+    pub async fn recover(&mut self) -> Result<(), KvError> {
+        self.replica.catch_up().await?;
+        Ok(())
+    }
+
+    // A failed proposal may still have been chosen, so retrying the same
+    // command resends the pending request and its sequence number; the
+    // sessions layer then applies it at most once.
+    async fn submit(&mut self, command: Command) -> Result<Option<String>, KvError> {
+        let request = match self.pending.take() {
+            Some(pending) if pending.command == command => pending,
+            _ => self.session.request(command),
+        };
+        self.pending = Some(request.clone());
+        let (output, written) = self.replica.propose_with_token(request).await?;
+        self.pending = None;
+        self.written = self.written.merge(written);
+        Ok(output??)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply_all(store: &mut KvStore, commands: &[Command]) -> Vec<Output> {
+        commands
+            .iter()
+            .enumerate()
+            .map(|(index, command)| store.apply(LogIndex::new(index as u64), command))
+            .collect()
+    }
+
+    fn put_expiring(key: &str, expires: u64) -> Command {
+        Command::PutExpiring {
+            key: key.to_string(),
+            value: "v".to_string(),
+            expires: Timestamp::new(expires, 0),
+        }
+    }
+
+    fn expire(now: u64) -> Command {
+        Command::Expire {
+            now: Timestamp::new(now, 0),
+        }
+    }
+
+    #[test]
+    fn keys_expire_only_at_expiration_entries() {
+        let mut store = KvStore::default();
+        apply_all(
+            &mut store,
+            &[put_expiring("a", 10), put_expiring("b", 20), expire(9)],
+        );
+        assert_eq!(store.len(), 2);
+
+        apply_all(&mut store, &[expire(10)]);
+        assert_eq!(store.get("a"), None);
+        assert_eq!(store.expires("b"), Some(Timestamp::new(20, 0)));
+
+        let outputs = apply_all(
+            &mut store,
+            &[
+                Command::Put {
+                    key: "b".to_string(),
+                    value: "kept".to_string(),
+                },
+                expire(30),
+            ],
+        );
+        assert_eq!(outputs[0], Ok(Some("v".to_string())));
+        assert_eq!(store.get("b"), Some("kept"));
+        assert_eq!(store.expires("b"), None);
+    }
+
+    #[test]
+    fn leases_drop_their_keys_when_they_expire_or_are_revoked() {
+        let (first, second) = (LeaseId(1), LeaseId(2));
+        let leased = |key: &str, lease| Command::PutLeased {
+            key: key.to_string(),
+            value: "v".to_string(),
+            lease,
+        };
+        let mut store = KvStore::default();
+        let outputs = apply_all(
+            &mut store,
+            &[
+                leased("orphan", first),
+                Command::Grant {
+                    lease: first,
+                    expires: Timestamp::new(10, 0),
+                },
+                Command::Grant {
+                    lease: second,
+                    expires: Timestamp::new(10, 0),
+                },
+                leased("a", first),
+                leased("b", second),
+                Command::Grant {
+                    lease: second,
+                    expires: Timestamp::new(50, 0),
+                },
+                expire(20),
+            ],
+        );
+        assert_eq!(outputs[0], Err(UnknownLease(first).into()));
+        assert_eq!(store.get("a"), None);
+        assert!(store.lease(first).is_none());
+        assert_eq!(store.expires("b"), Some(Timestamp::new(50, 0)));
+
+        let replay = store.snapshot();
+        apply_all(&mut store, &[Command::Revoke { lease: second }]);
+        assert!(store.is_empty());
+
+        let mut restored = KvStore::default();
+        restored.restore(replay);
+        assert_eq!(restored.get("b"), Some("v"));
+    }
+
+    #[test]
+    fn conditional_writes_apply_only_while_their_precondition_holds() {
+        let cas = |key: &str, expected, value: Option<&str>| Command::CompareAndSwap {
+            key: key.to_string(),
+            expected,
+            value: value.map(str::to_string),
+        };
+        let conflict = |current: Option<&str>, revision: Option<u64>| {
+            Err(Refused::Conflict(Conflict {
+                key: "a".to_string(),
+                current: current.map(str::to_string),
+                revision: revision.map(LogIndex::new),
+            }))
+        };
+        let mut store = KvStore::default();
+        let outputs = apply_all(
+            &mut store,
+            &[
+                cas("a", Precondition::Value(None), Some("1")),
+                cas("a", Precondition::Value(None), Some("2")),
+                cas("a", Precondition::Value(Some("1".into())), Some("2")),
+                cas(
+                    "a",
+                    Precondition::Revision(Some(LogIndex::new(0))),
+                    Some("3"),
+                ),
+                cas("a", Precondition::Revision(Some(LogIndex::new(2))), None),
+                cas("a", Precondition::Revision(None), Some("4")),
+            ],
+        );
+        assert_eq!(
+            outputs,
+            [
+                Ok(None),
+                conflict(Some("1"), Some(0)),
+                Ok(Some("1".into())),
+                conflict(Some("2"), Some(2)),
+                Ok(Some("2".into())),
+                Ok(None),
+            ]
+        );
+        assert_eq!(store.get("a"), Some("4"));
+        assert_eq!(store.revision("a"), Some(LogIndex::new(5)));
+    }
+
+    #[cfg(feature = "threads")]
+    mod client {
+        use super::*;
+        use crate::alpha::{
+            Error, Id, Quorum, ReadPeers, ReadResponse, Round, Value, WritePeers, WriteResponse,
+        };
+        use crate::learner::{DecisionBroadcast, DecisionPeers};
+        use crate::local::{LocalCluster, LocalPeers};
+        use crate::retry::RetryPolicy;
+        use futures::executor::block_on;
+        use futures::future::ready;
+        use futures::{Stream, StreamExt};
+        use std::cell::{Cell, RefCell};
+        use std::future::{self, Future};
+        use std::rc::Rc;
+
+        #[derive(Clone, Default)]
+        struct Slots {
+            clusters: Rc<RefCell<BTreeMap<LogIndex, LocalCluster<Request>>>>,
+            lose_write_acks: Rc<Cell<bool>>,
+        }
+
+        /// Delivers writes to the acceptors but loses every acknowledgement while
+        /// `lose` is set, so the proposer fails even though its value was chosen.
+        struct LostAcks {
+            peers: LocalPeers<Request>,
+            lose: bool,
+        }
+
+        impl SlotPeers<Request> for Slots {
+            type Peers = LostAcks;
+
+            fn slot(&self, index: LogIndex) -> Self::Peers {
+                let peers = self
+                    .clusters
+                    .borrow_mut()
+                    .entry(index)
+                    .or_insert_with(|| LocalCluster::new(3))
+                    .peers();
+                LostAcks {
+                    peers,
+                    lose: self.lose_write_acks.get(),
+                }
+            }
+
+            fn learned(
+                &self,
+                index: LogIndex,
+            ) -> Option<impl Stream<Item = Result<Option<Request>, Error>>> {
+                self.clusters
+                    .borrow()
+                    .get(&index)
+                    .map(LocalCluster::learned)
+            }
+        }
+
+        impl ReadPeers<Request> for LostAcks {
+            fn read(
+                &self,
+                round: Round,
+            ) -> impl Stream<Item = Result<ReadResponse<Request>, Error>> {
+                self.peers.read(round)
+            }
+        }
+
+        impl WritePeers<Request> for LostAcks {
+            fn write(
+                &self,
+                value: Value<Request>,
+            ) -> impl Stream<Item = Result<WriteResponse, Error>> {
+                let lose = self.lose;
+                self.peers.write(value).filter(move |_| ready(!lose))
+            }
+        }
+
+        impl DecisionPeers<Request> for LostAcks {
+            fn decide(
+                &self,
+                decision: DecisionBroadcast<Request>,
+            ) -> impl Stream<Item = Result<(), Error>> {
+                self.peers.decide(decision)
+            }
+        }
+
+        impl Quorum for LostAcks {
+            fn majority(&self) -> usize {
+                self.peers.majority()
+            }
+
+            fn max_failures(&self) -> usize {
+                self.peers.max_failures()
+            }
+        }
+
+        #[derive(Clone)]
+        struct Leader;
+
+        impl FailureDetector for Leader {
+            fn leader(&self) -> Id {
+                Id(1)
+            }
+
+            fn changed(&self) -> impl Future<Output = ()> {
+                future::pending()
+            }
+        }
+
+        #[test]
+        fn retried_submissions_apply_once() {
+            let slots = Slots::default();
+            let log = ReplicatedLog::new(Id(1), slots.clone(), Leader).retry_policy(RetryPolicy {
+                max_attempts: Some(1),
+                ..RetryPolicy::immediate()
+            });
+            let mut client = KvClient::new(log, ClientId(1));
+
+            slots.lose_write_acks.set(true);
+            assert!(block_on(client.put("counter", "1")).is_err());
+            slots.lose_write_acks.set(false);
+            assert_eq!(block_on(client.put("counter", "1")).unwrap(), None);
+            assert_eq!(
+                block_on(client.put("counter", "2")).unwrap(),
+                Some("1".into())
+            );
+            assert_eq!(client.replica.log().next_index(), LogIndex::new(2));
+        }
+
+        #[test]
+        fn replicas_recover_and_restore_what_clients_wrote() {
+            let slots = Slots::default();
+            let mut writer = KvClient::new(
+                ReplicatedLog::new(Id(1), slots.clone(), Leader),
+                ClientId(1),
+            );
+            block_on(writer.put("a", "1")).unwrap();
+            block_on(writer.put("b", "2")).unwrap();
+            assert_eq!(block_on(writer.delete("a")).unwrap(), Some("1".into()));
+            assert_eq!(block_on(writer.delete("a")).unwrap(), None);
+            assert_eq!(block_on(writer.get("b")).unwrap(), Some("2".into()));
+
+            let mut reader = KvClient::new(ReplicatedLog::new(Id(1), slots, Leader), ClientId(2));
+            block_on(reader.recover()).unwrap();
+            assert_eq!(reader.store().get("a"), None);
+            assert_eq!(reader.store().get("b"), Some("2"));
+
+            let snapshot = writer.snapshot().unwrap();
+            assert_eq!(snapshot.last_included, LogIndex::new(3));
+            let mut restored = KvClient::new(
+                ReplicatedLog::new(Id(1), Slots::default(), Leader),
+                ClientId(3),
+            );
+            restored.restore(snapshot);
+            assert_eq!(restored.store().len(), 1);
+            assert_eq!(restored.store().get("b"), Some("2"));
+        }
+
+        #[test]
+        fn clients_read_their_own_writes_from_any_replica() {
+            let slots = Slots::default();
+            let mut writer = KvClient::new(
+                ReplicatedLog::new(Id(1), slots.clone(), Leader),
+                ClientId(1),
+            );
+            assert_eq!(writer.session_token(), SessionToken::default());
+            block_on(writer.put("a", "1")).unwrap();
+            block_on(writer.put("a", "2")).unwrap();
+            assert_eq!(writer.session_token().applied, LogIndex::new(2));
+            assert_eq!(block_on(writer.get_own("a")).unwrap(), Some("2".into()));
+
+            // An observer cannot read through the leader, so it serves the
+            // read from what it learned.
+            let observer = || ReplicatedLog::new(Id(2), slots.clone(), Leader).observer();
+            let mut reader =
+                KvClient::new(observer(), ClientId(1)).with_session_token(writer.session_token());
+            assert_eq!(block_on(reader.get_own("a")).unwrap(), Some("2".into()));
+            assert!(matches!(
+                block_on(reader.get("a")),
+                Err(KvError::Paxos(Error::Observing))
+            ));
+
+            // One still behind after learning reads like `get`.
+            let ahead = SessionToken {
+                applied: LogIndex::new(9),
+                ..writer.session_token()
+            };
+            let mut behind = KvClient::new(observer(), ClientId(1)).with_session_token(ahead);
+            assert!(matches!(
+                block_on(behind.get_own("a")),
+                Err(KvError::Paxos(Error::Observing))
+            ));
+
+            assert!(matches!(
+                block_on(writer.compare_and_swap("a", Precondition::Value(None), None)),
+                Err(KvError::Conflict(Conflict { current: Some(current), .. })) if current == "2"
+            ));
+            assert_eq!(
+                block_on(writer.compare_and_swap(
+                    "a",
+                    Precondition::Value(Some("2".into())),
+                    Some("2".into())
+                ))
+                .unwrap(),
+                Some("2".into())
+            );
+        }
+    }
+}
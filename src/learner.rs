@@ -0,0 +1,133 @@
+use crate::alpha::{Compat, Error, Id};
+use crate::audit::{Audit, AuditSink, EventKind};
+use crate::certificate::DecisionCertificate;
+use futures::channel::oneshot;
+use futures::{Stream, StreamExt};
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Decisions travel with the write quorum that accepted them, so a receiver
+/// can [`verify`](DecisionCertificate::verify) one before adopting it.
+pub type DecisionBroadcast<V> = DecisionCertificate<V>;
+
+pub trait DecisionClient<V> {
+    type Error: Into<Error> + Debug;
+    type Stream: Stream<Item = Result<(), Self::Error>>;
+    fn broadcast_decision(&self, decision: DecisionBroadcast<V>) -> Self::Stream;
+}
+
+/// Sends a decision to every learner as soon as `decide` is called; the
+/// returned stream only reports acknowledgements and may be dropped unpolled.
+pub trait DecisionPeers<V> {
+    fn decide(&self, decision: DecisionBroadcast<V>) -> impl Stream<Item = Result<(), Error>>;
+}
+
+impl<V, P: DecisionClient<V>> DecisionPeers<V> for Compat<P> {
+    fn decide(&self, decision: DecisionBroadcast<V>) -> impl Stream<Item = Result<(), Error>> {
+        self.0
+            .broadcast_decision(decision)
+            .map(|response| response.map_err(Into::into))
+    }
+}
+
+pub struct Learner<V> {
+    inner: Arc<Mutex<Inner<V>>>,
+}
+
+struct Inner<V> {
+    decision: Option<V>,
+    certificate: Option<DecisionCertificate<V>>,
+    waiters: Vec<oneshot::Sender<V>>,
+    audit: Option<Audit<V>>,
+}
+
+impl<V> Clone for Learner<V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<V> Default for Learner<V> {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                decision: None,
+                certificate: None,
+                waiters: Vec::new(),
+                audit: None,
+            })),
+        }
+    }
+}
+
+impl<V> Learner<V>
+where
+    V: Clone,
+{
+    pub fn with_audit(self, id: Id, sink: impl AuditSink + Send + 'static) -> Self
+    where
+        V: Hash,
+    {
+        self.inner
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .audit = Some(Audit::new(id, sink));
+        self
+    }
+
+    pub fn handle_decision(&self, decision: DecisionBroadcast<V>) {
+        let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        if inner.decision.is_some() {
+            return;
+        }
+        #[cfg(feature = "tracing")]
+        tracing::info!(round = ?decision.round, "learned");
+        if let Some(audit) = &mut inner.audit {
+            let value = audit.hash(&decision.value);
+            let _recorded = audit.record(EventKind::Decision, decision.round, Some(value));
+            #[cfg(feature = "tracing")]
+            if let Err(error) = _recorded {
+                tracing::warn!(%error, "failed to record decision");
+            }
+        }
+        for waiter in inner.waiters.drain(..) {
+            let _ = waiter.send(decision.value.clone());
+        }
+        inner.decision = Some(decision.value.clone());
+        inner.certificate = Some(decision);
+    }
+
+    pub fn certificate(&self) -> Option<DecisionCertificate<V>> {
+        self.inner
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .certificate
+            .clone()
+    }
+
+    pub fn decision(&self) -> Option<V> {
+        self.inner
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .decision
+            .clone()
+    }
+
+    pub async fn await_decision(&self) -> V {
+        let receiver = {
+            let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(decision) = &inner.decision {
+                return decision.clone();
+            }
+            let (sender, receiver) = oneshot::channel();
+            inner.waiters.push(sender);
+            receiver
+        };
+        receiver
+            .await
+            .expect("waiters are only dropped after a decision is sent")
+    }
+}
@@ -1,2 +1,53 @@
+pub mod acceptor;
 pub mod alpha;
-mod proposer;
+pub mod audit;
+#[cfg(feature = "auth")]
+pub mod auth;
+pub mod batch;
+pub mod bootstrap;
+pub mod budget;
+pub mod bytes;
+pub mod certificate;
+pub mod chaos;
+pub mod chunk;
+pub mod codec;
+pub mod config;
+#[cfg(feature = "admin")]
+pub mod dashboard;
+pub mod data_dir;
+mod digest;
+pub mod failure_detector;
+pub mod fence;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod identity;
+pub mod inspect;
+pub mod instance;
+pub mod kv;
+pub mod learner;
+#[cfg(feature = "threads")]
+pub mod local;
+pub mod lock;
+pub mod log;
+pub mod log_filter;
+pub mod membership;
+pub mod metrics;
+#[cfg(feature = "threads")]
+pub mod node;
+pub mod proposer;
+pub mod quorum;
+pub mod quota;
+pub mod registry;
+pub mod retry;
+mod rng;
+pub mod roles;
+pub mod sealed;
+pub mod session;
+pub mod sim;
+pub mod smr;
+pub mod storage;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+pub mod time;
+pub mod token;
+pub mod transport;
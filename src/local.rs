@@ -0,0 +1,143 @@
+use crate::acceptor::Acceptor;
+use crate::alpha::{Error, Id, ReadResponse, Round, Value, WriteResponse};
+use crate::learner::{DecisionBroadcast, Learner};
+use crate::storage::MemoryStorage;
+use crate::transport::broadcast::{Broadcast, Peer};
+use futures::channel::oneshot;
+use futures::stream::{FuturesOrdered, Stream};
+use std::future::Future;
+use std::io;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+pub struct LocalCluster<V> {
+    peers: LocalPeers<V>,
+    learners: Vec<Learner<V>>,
+}
+
+impl<V> LocalCluster<V>
+where
+    V: Clone + PartialEq + Send + Sync + 'static,
+{
+    pub fn new(n: usize) -> Self {
+        let mut inboxes = Vec::with_capacity(n);
+        let mut learners = Vec::with_capacity(n);
+        for i in 0..n {
+            let acceptor = Acceptor::new(Id(i as u64), MemoryStorage::default())
+                .expect("memory storage cannot fail");
+            let learner = Learner::default();
+            let (sender, receiver) = channel();
+            let node = learner.clone();
+            thread::spawn(move || run(acceptor, node, receiver));
+            inboxes.push(LocalPeer {
+                id: Id(i as u64),
+                inbox: sender,
+            });
+            learners.push(learner);
+        }
+        Self {
+            peers: Broadcast::new(inboxes),
+            learners,
+        }
+    }
+
+    pub fn peers(&self) -> LocalPeers<V> {
+        self.peers.clone()
+    }
+
+    pub fn learners(&self) -> &[Learner<V>] {
+        &self.learners
+    }
+
+    /// What each acceptor's learner holds, asked behind the requests
+    /// already sent to it, so decisions sent before are counted.
+    pub fn learned(&self) -> impl Stream<Item = Result<Option<V>, Error>> {
+        self.peers
+            .peers()
+            .iter()
+            .map(|peer| peer.send(Request::Learned))
+            .collect::<FuturesOrdered<_>>()
+    }
+}
+
+pub type LocalPeers<V> = Broadcast<LocalPeer<V>>;
+
+/// One acceptor thread of a [`LocalCluster`].
+pub struct LocalPeer<V> {
+    id: Id,
+    inbox: Sender<Request<V>>,
+}
+
+impl<V> Clone for LocalPeer<V> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            inbox: self.inbox.clone(),
+        }
+    }
+}
+
+impl<V> LocalPeer<V> {
+    fn send<T>(
+        &self,
+        request: impl FnOnce(oneshot::Sender<Result<T, Error>>) -> Request<V>,
+    ) -> impl Future<Output = Result<T, Error>> {
+        let (reply, response) = oneshot::channel();
+        let sent = self.inbox.send(request(reply));
+        async move {
+            sent.map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+            response
+                .await
+                .unwrap_or_else(|_| Err(io::Error::from(io::ErrorKind::BrokenPipe).into()))
+        }
+    }
+}
+
+impl<V> Peer<V> for LocalPeer<V> {
+    fn read(&self, round: Round) -> impl Future<Output = Result<ReadResponse<V>, Error>> {
+        self.send(move |reply| Request::Read(round, reply))
+    }
+
+    fn write(&self, value: Value<V>) -> impl Future<Output = Result<WriteResponse, Error>> {
+        self.send(move |reply| Request::Write(value, reply))
+    }
+
+    fn decide(&self, decision: DecisionBroadcast<V>) -> impl Future<Output = Result<(), Error>> {
+        self.send(move |reply| Request::Decision(decision, reply))
+    }
+
+    fn id(&self) -> Option<Id> {
+        Some(self.id)
+    }
+}
+
+enum Request<V> {
+    Read(Round, oneshot::Sender<Result<ReadResponse<V>, Error>>),
+    Write(Value<V>, oneshot::Sender<Result<WriteResponse, Error>>),
+    Decision(DecisionBroadcast<V>, oneshot::Sender<Result<(), Error>>),
+    Learned(oneshot::Sender<Result<Option<V>, Error>>),
+}
+
+fn run<V: Clone + PartialEq>(
+    mut acceptor: Acceptor<V, MemoryStorage<V>>,
+    learner: Learner<V>,
+    requests: Receiver<Request<V>>,
+) {
+    for request in requests {
+        match request {
+            Request::Read(round, reply) => {
+                let _ = reply.send(acceptor.handle_read(round));
+            }
+            Request::Write(value, reply) => {
+                let _ = reply.send(acceptor.handle_write(value));
+            }
+            Request::Decision(decision, reply) => {
+                learner.handle_decision(decision);
+                let _ = reply.send(Ok(()));
+            }
+            Request::Learned(reply) => {
+                let _ = reply.send(Ok(learner.decision()));
+            }
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use crate::alpha::{self, Round};
+use crate::instance::{InstanceId, InstanceManager, InstancePeers};
+use crate::proposer::FailureDetector;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LeaseOp {
+    Acquire {
+        owner: String,
+        at: u64,
+        ttl: u64,
+    },
+    Renew {
+        owner: String,
+        token: FencingToken,
+        at: u64,
+        ttl: u64,
+    },
+    Release {
+        owner: String,
+        token: FencingToken,
+    },
+}
+
+#[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
+pub struct FencingToken {
+    pub instance: InstanceId,
+    pub round: Round,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lease {
+    pub owner: String,
+    pub expires_at: SystemTime,
+    pub token: FencingToken,
+}
+
+impl Lease {
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        now >= self.expires_at
+    }
+
+    fn expired_at(&self, at: u64) -> bool {
+        UNIX_EPOCH + Duration::from_millis(at) >= self.expires_at
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum LockError {
+    #[error(transparent)]
+    Paxos(#[from] alpha::Error),
+    #[error("lease is held by {owner}")]
+    Held { owner: String },
+    #[error("lease is not held by {owner}")]
+    NotHeld { owner: String },
+}
+
+pub struct DistributedLock<P, D> {
+    instances: InstanceManager<LeaseOp, P, D>,
+    next: InstanceId,
+    lease: Option<Lease>,
+}
+
+impl<P, D> DistributedLock<P, D>
+where
+    P: InstancePeers<LeaseOp>,
+    D: FailureDetector + Clone,
+{
+    pub fn new(instances: InstanceManager<LeaseOp, P, D>) -> Self {
+        Self {
+            instances,
+            next: InstanceId::default(),
+            lease: None,
+        }
+    }
+
+    pub fn lease(&self) -> Option<&Lease> {
+        let now = self.instances.system_time();
+        self.lease.as_ref().filter(|lease| !lease.is_expired(now))
+    }
+
+    pub async fn acquire(&mut self, owner: &str, ttl: Duration) -> Result<Lease, LockError> {
+        let op = LeaseOp::Acquire {
+            owner: owner.to_string(),
+            at: self.now(),
+            ttl: ttl.as_millis() as u64,
+        };
+        match (self.submit(op).await?, &self.lease) {
+            (true, Some(lease)) => Ok(lease.clone()),
+            (_, lease) => Err(LockError::Held {
+                owner: lease
+                    .as_ref()
+                    .map_or_else(String::new, |lease| lease.owner.clone()),
+            }),
+        }
+    }
+
+    pub async fn renew(&mut self, lease: &Lease, ttl: Duration) -> Result<Lease, LockError> {
+        let op = LeaseOp::Renew {
+            owner: lease.owner.clone(),
+            token: lease.token,
+            at: self.now(),
+            ttl: ttl.as_millis() as u64,
+        };
+        match (self.submit(op).await?, &self.lease) {
+            (true, Some(current)) if current.token == lease.token => Ok(current.clone()),
+            _ => Err(LockError::NotHeld {
+                owner: lease.owner.clone(),
+            }),
+        }
+    }
+
+    pub async fn release(&mut self, lease: &Lease) -> Result<(), LockError> {
+        let op = LeaseOp::Release {
+            owner: lease.owner.clone(),
+            token: lease.token,
+        };
+        if !self.submit(op).await? {
+            return Err(LockError::NotHeld {
+                owner: lease.owner.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    fn now(&self) -> u64 {
+        self.instances
+            .system_time()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64
+    }
+
+    async fn submit(&mut self, op: LeaseOp) -> Result<bool, LockError> {
+        loop {
+            let instance = self.next;
+            let (chosen, round) = self.instances.decide(instance, op.clone()).await?;
+            self.next = InstanceId(instance.0 + 1);
+            let applied = self.apply(&chosen, FencingToken { instance, round });
+            self.instances.acknowledge(self.next);
+            if chosen == op {
+                return Ok(applied);
+            }
+        }
+    }
+
+    fn apply(&mut self, op: &LeaseOp, granted: FencingToken) -> bool {
+        match op {
+            LeaseOp::Acquire { owner, at, ttl } => {
+                let free = self
+                    .lease
+                    .as_ref()
+                    .is_none_or(|lease| lease.expired_at(*at));
+                if free {
+                    self.lease = Some(Lease {
+                        owner: owner.clone(),
+                        expires_at: UNIX_EPOCH + Duration::from_millis(at + ttl),
+                        token: granted,
+                    });
+                }
+                free
+            }
+            // Renewals and releases name the token they were granted, so a
+            // handle from an earlier acquisition by the same owner is fenced.
+            LeaseOp::Renew {
+                owner,
+                token,
+                at,
+                ttl,
+            } => match &mut self.lease {
+                Some(lease)
+                    if lease.owner == *owner && lease.token == *token && !lease.expired_at(*at) =>
+                {
+                    lease.expires_at = UNIX_EPOCH + Duration::from_millis(at + ttl);
+                    true
+                }
+                _ => false,
+            },
+            LeaseOp::Release { owner, token } => {
+                let held = self
+                    .lease
+                    .as_ref()
+                    .is_some_and(|lease| lease.owner == *owner && lease.token == *token);
+                if held {
+                    self.lease = None;
+                }
+                held
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "threads"))]
+mod tests {
+    use super::*;
+    use crate::alpha::Id;
+    use crate::local::{LocalCluster, LocalPeers};
+    use crate::time::MockClock;
+    use futures::executor::block_on;
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
+    use std::future::{self, Future};
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct Instances(Rc<RefCell<BTreeMap<InstanceId, LocalCluster<LeaseOp>>>>);
+
+    impl InstancePeers<LeaseOp> for Instances {
+        type Peers = LocalPeers<LeaseOp>;
+
+        fn instance(&self, instance: InstanceId) -> Self::Peers {
+            self.0
+                .borrow_mut()
+                .entry(instance)
+                .or_insert_with(|| LocalCluster::new(3))
+                .peers()
+        }
+    }
+
+    #[derive(Clone)]
+    struct Leader;
+
+    impl FailureDetector for Leader {
+        fn leader(&self) -> Id {
+            Id(1)
+        }
+
+        fn changed(&self) -> impl Future<Output = ()> {
+            future::pending()
+        }
+    }
+
+    #[test]
+    fn leases_expire_and_fence_the_previous_owner() {
+        let clock = MockClock::new();
+        let instances = InstanceManager::new(Id(1), Instances::default(), Leader)
+            .clock(Arc::new(clock.clone()));
+        let mut lock = DistributedLock::new(instances);
+        let ttl = Duration::from_secs(10);
+
+        let first = block_on(lock.acquire("a", ttl)).unwrap();
+        assert!(matches!(
+            block_on(lock.acquire("b", ttl)),
+            Err(LockError::Held { owner }) if owner == "a"
+        ));
+        clock.advance(Duration::from_secs(5));
+        let renewed = block_on(lock.renew(&first, ttl)).unwrap();
+        assert_eq!(renewed.token, first.token);
+        assert!(renewed.expires_at > first.expires_at);
+
+        clock.advance(Duration::from_secs(11));
+        assert_eq!(lock.lease(), None);
+        let second = block_on(lock.acquire("b", ttl)).unwrap();
+        assert!(second.token > first.token);
+        assert!(matches!(
+            block_on(lock.renew(&first, ttl)),
+            Err(LockError::NotHeld { .. })
+        ));
+        assert!(matches!(
+            block_on(lock.release(&first)),
+            Err(LockError::NotHeld { .. })
+        ));
+
+        block_on(lock.release(&second)).unwrap();
+        assert_eq!(lock.lease(), None);
+        block_on(lock.acquire("a", ttl)).unwrap();
+    }
+
+    #[test]
+    fn a_stale_handle_cannot_renew_or_release_a_reacquired_lease() {
+        let clock = MockClock::new();
+        let instances = InstanceManager::new(Id(1), Instances::default(), Leader)
+            .clock(Arc::new(clock.clone()));
+        let mut lock = DistributedLock::new(instances);
+        let ttl = Duration::from_secs(10);
+
+        let stale = block_on(lock.acquire("a", ttl)).unwrap();
+        clock.advance(Duration::from_secs(11));
+        let current = block_on(lock.acquire("a", ttl)).unwrap();
+        assert!(current.token > stale.token);
+
+        assert!(matches!(
+            block_on(lock.renew(&stale, ttl)),
+            Err(LockError::NotHeld { .. })
+        ));
+        assert!(matches!(
+            block_on(lock.release(&stale)),
+            Err(LockError::NotHeld { .. })
+        ));
+        assert_eq!(lock.lease(), Some(&current));
+
+        let renewed = block_on(lock.renew(&current, ttl)).unwrap();
+        assert_eq!(renewed.token, current.token);
+        block_on(lock.release(&renewed)).unwrap();
+        assert_eq!(lock.lease(), None);
+    }
+}
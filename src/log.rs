@@ -0,0 +1,905 @@
+use crate::acceptor::Acceptor;
+use crate::alpha::{
+    collect_quorum, Error, Id, Promise, Quorum, ReadPeers, Round, Status, Value, WritePeers,
+};
+use crate::budget::{Accounted, MemoryBudget};
+use crate::learner::DecisionPeers;
+use crate::proposer::{Delivery, FailureDetector, Proposer, ProposerBuilder, TickSource, Ticks};
+use crate::retry::RetryPolicy;
+use crate::storage::Storage;
+use crate::time::{Clock, HybridClock, SystemClock, Timestamp};
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::future::join;
+use futures::stream::{self, FuturesUnordered};
+use futures::{Stream, StreamExt};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::mem;
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash, Default)]
+pub struct LogIndex(u64);
+
+impl LogIndex {
+    pub fn new(index: u64) -> Self {
+        Self(index)
+    }
+
+    pub fn get(self) -> u64 {
+        self.0
+    }
+
+    pub fn next(self) -> Self {
+        Self(self.0 + 1)
+    }
+}
+
+pub trait SlotPeers<V> {
+    type Peers: WritePeers<V> + ReadPeers<V> + DecisionPeers<V> + Quorum;
+    fn slot(&self, index: LogIndex) -> Self::Peers;
+
+    fn read_from(
+        &self,
+        _from: LogIndex,
+        _round: Round,
+    ) -> Option<impl Stream<Item = Result<RangeResponse<V>, Error>>> {
+        None::<stream::Empty<_>>
+    }
+
+    fn acknowledge(&self, _learner: Id, _next_to_apply: LogIndex) {}
+
+    /// What each acceptor of the slot learned was decided there, asked
+    /// without taking part in any round. Peers that cannot say teach an
+    /// observer nothing.
+    fn learned(&self, _index: LogIndex) -> Option<impl Stream<Item = Result<Option<V>, Error>>> {
+        None::<stream::Empty<_>>
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RangeResponse<V> {
+    pub acceptor: Id,
+    pub status: Status,
+    pub slots: BTreeMap<LogIndex, Option<Value<V>>>,
+}
+
+pub fn read_range<'a, V, S>(
+    acceptor: Id,
+    round: Round,
+    slots: impl IntoIterator<Item = (LogIndex, &'a mut Acceptor<V, S>)>,
+) -> Result<RangeResponse<V>, Error>
+where
+    V: Clone + 'a,
+    S: Storage<V> + 'a,
+{
+    let mut response = RangeResponse {
+        acceptor,
+        status: Status::Accepted,
+        slots: BTreeMap::new(),
+    };
+    for (index, slot) in slots {
+        let read = slot.handle_read(round)?;
+        response.status = match (response.status, read.status) {
+            (Status::Rejected(seen), Status::Rejected(conflict)) => {
+                Status::Rejected(seen.max(conflict))
+            }
+            (Status::Accepted, status) | (status, _) => status,
+        };
+        response.slots.insert(index, read.state.value);
+    }
+    Ok(response)
+}
+
+type StorageFactory<S> = Box<dyn FnMut(LogIndex) -> S + Send>;
+
+pub struct SlotAcceptors<V, S> {
+    id: Id,
+    storage: StorageFactory<S>,
+    slots: BTreeMap<LogIndex, Acceptor<V, S>>,
+    applied: HashMap<Id, LogIndex>,
+    first: LogIndex,
+}
+
+impl<V, S> SlotAcceptors<V, S>
+where
+    V: Clone,
+    S: Storage<V>,
+{
+    pub fn new(
+        id: Id,
+        learners: impl IntoIterator<Item = Id>,
+        storage: impl FnMut(LogIndex) -> S + Send + 'static,
+    ) -> Self {
+        Self {
+            id,
+            storage: Box::new(storage),
+            slots: BTreeMap::new(),
+            applied: learners
+                .into_iter()
+                .map(|learner| (learner, LogIndex::default()))
+                .collect(),
+            first: LogIndex::default(),
+        }
+    }
+
+    pub fn first_index(&self) -> LogIndex {
+        self.first
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn slot(&mut self, index: LogIndex) -> Result<Option<&mut Acceptor<V, S>>, Error> {
+        if index < self.first {
+            return Ok(None);
+        }
+        let acceptor = match self.slots.entry(index) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(Acceptor::new(self.id, (self.storage)(index))?),
+        };
+        Ok(Some(acceptor))
+    }
+
+    pub fn read_from(&mut self, from: LogIndex, round: Round) -> Result<RangeResponse<V>, Error> {
+        let slots = self
+            .slots
+            .range_mut(from.max(self.first)..)
+            .map(|(index, acceptor)| (*index, acceptor));
+        read_range(self.id, round, slots)
+    }
+
+    pub fn acknowledge(&mut self, learner: Id, next_to_apply: LogIndex) -> Result<LogIndex, Error> {
+        if let Some(applied) = self.applied.get_mut(&learner) {
+            *applied = (*applied).max(next_to_apply);
+        }
+        let watermark = self.applied.values().min().copied().unwrap_or_default();
+        if watermark > self.first {
+            let retained = self.slots.split_off(&watermark);
+            self.first = watermark;
+            for acceptor in mem::replace(&mut self.slots, retained).into_values() {
+                acceptor.discard()?;
+            }
+        }
+        Ok(self.first)
+    }
+}
+
+pub struct ReplicatedLog<V, S, D> {
+    id: Id,
+    peers: S,
+    failure_detector: D,
+    retry_policy: RetryPolicy,
+    delivery: Delivery,
+    read_repair: bool,
+    pipeline_window: usize,
+    leader_lease: Option<Duration>,
+    observer: bool,
+    clock: Arc<dyn Clock>,
+    ticks: Ticks,
+    budget: Option<Accounted<V>>,
+    hlc: HybridClock,
+    promises: BTreeMap<LogIndex, (Promise<V>, Instant)>,
+    entries: BTreeMap<LogIndex, (Timestamp, V)>,
+    first: LogIndex,
+    next: LogIndex,
+    subscribers: Vec<UnboundedSender<(LogIndex, Timestamp, V)>>,
+}
+
+impl<V, S, D> ReplicatedLog<V, S, D>
+where
+    V: Clone + PartialEq,
+    S: SlotPeers<V>,
+    D: FailureDetector + Clone,
+{
+    pub fn new(id: Id, peers: S, failure_detector: D) -> Self {
+        Self {
+            id,
+            peers,
+            failure_detector,
+            retry_policy: RetryPolicy::default(),
+            delivery: Delivery::default(),
+            read_repair: false,
+            pipeline_window: 1,
+            leader_lease: None,
+            observer: false,
+            clock: Arc::new(SystemClock),
+            ticks: Ticks::new(TickSource::Counter),
+            budget: None,
+            hlc: HybridClock::new(Arc::new(SystemClock)),
+            promises: BTreeMap::new(),
+            entries: BTreeMap::new(),
+            first: LogIndex::default(),
+            next: LogIndex::default(),
+            subscribers: Vec::new(),
+        }
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn delivery(mut self, delivery: Delivery) -> Self {
+        self.delivery = delivery;
+        self
+    }
+
+    pub fn read_repair(mut self, read_repair: bool) -> Self {
+        self.read_repair = read_repair;
+        self
+    }
+
+    pub fn pipeline_window(mut self, pipeline_window: usize) -> Self {
+        self.pipeline_window = pipeline_window.max(1);
+        self
+    }
+
+    pub fn leader_lease(mut self, leader_lease: Duration) -> Self {
+        self.leader_lease = Some(leader_lease);
+        self
+    }
+
+    /// An observer follows the log through [`learn`](Self::learn) and
+    /// refuses to propose, or to prepare a read, until it is
+    /// [promoted](Self::promote).
+    pub fn observer(mut self) -> Self {
+        self.observer = true;
+        self
+    }
+
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.hlc = HybridClock::new(clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    /// Rounds for every slot are drawn from `ticks`, so a retried or
+    /// post-restart proposal never reuses a round; back it with storage to
+    /// survive restarts.
+    pub fn ticks(mut self, ticks: Ticks) -> Self {
+        self.ticks = ticks;
+        self
+    }
+
+    /// Decisions that arrive ahead of an undecided slot wait in a reorder
+    /// buffer; each counts for `size` bytes against `budget` until it
+    /// commits.
+    pub fn memory_budget(mut self, budget: Arc<MemoryBudget>, size: fn(&V) -> usize) -> Self {
+        self.budget = Some(Accounted::new(budget, size));
+        self
+    }
+
+    pub async fn append(&mut self, value: V) -> Result<LogIndex, Error> {
+        let indices = self.append_all([value]).await?;
+        Ok(indices[0])
+    }
+
+    pub async fn append_all(
+        &mut self,
+        values: impl IntoIterator<Item = V>,
+    ) -> Result<Vec<LogIndex>, Error> {
+        self.proposing()?;
+        let mut queue: VecDeque<(usize, V)> = values.into_iter().enumerate().collect();
+        let mut indices = vec![None; queue.len()];
+        let mut decided = BTreeMap::new();
+        let mut in_flight = FuturesUnordered::new();
+        let mut next_slot = self.next;
+
+        loop {
+            while in_flight.len() < self.pipeline_window {
+                let Some((position, value)) = queue.pop_front() else {
+                    break;
+                };
+                let index = next_slot;
+                next_slot = next_slot.next();
+                let mut builder = self.proposer(index);
+                if let Some(promise) = self.take_promise(index) {
+                    builder = builder.promise(promise);
+                }
+                let mut proposer = builder.build();
+                let mut prefetch = self.leader_lease.map(|_| {
+                    let index = LogIndex(index.0 + self.pipeline_window as u64);
+                    let proposer = self.proposer(index).build();
+                    (index, proposer)
+                });
+                in_flight.push(async move {
+                    let prepare = async {
+                        let (index, proposer) = prefetch.as_mut()?;
+                        let promise = proposer.prepare().await.ok()??;
+                        Some((*index, promise))
+                    };
+                    let (result, promise) = join(proposer.propose(value.clone()), prepare).await;
+                    (index, position, value, result, promise)
+                });
+            }
+
+            let Some((index, position, value, result, promise)) = in_flight.next().await else {
+                break;
+            };
+            let consensus = result?;
+            if consensus == value {
+                indices[position] = Some(index);
+                if let (Some((index, promise)), Some(lease)) = (promise, self.leader_lease) {
+                    self.promises
+                        .insert(index, (promise, self.clock.deadline(lease)));
+                }
+            } else {
+                self.promises.clear();
+                queue.push_back((position, value));
+            }
+            let reservation = self.budget.as_ref().map(|budget| budget.charge(&consensus));
+            decided.insert(index, (consensus, reservation));
+            while let Some((consensus, _reservation)) = decided.remove(&self.next) {
+                self.commit(self.next, consensus);
+            }
+        }
+
+        Ok(indices
+            .into_iter()
+            .map(|index| index.expect("every value is eventually decided"))
+            .collect())
+    }
+
+    pub async fn read_index(&mut self) -> Result<LogIndex, Error> {
+        self.proposing()?;
+        loop {
+            let index = self.next;
+            let mut proposer = self.proposer(index).build();
+            let promise = proposer.prepare().await?.ok_or(Error::NotLeader)?;
+            let Some(accepted) = promise.accepted.clone() else {
+                return Ok(index);
+            };
+
+            let mut proposer = self.proposer(index).promise(promise).build();
+            let consensus = proposer.propose(Arc::unwrap_or_clone(accepted)).await?;
+            self.commit(index, consensus);
+        }
+    }
+
+    pub async fn catch_up(&mut self) -> Result<LogIndex, Error> {
+        self.proposing()?;
+        if self.failure_detector.leader() != self.id {
+            return Err(Error::NotLeader);
+        }
+        let quorum = self.peers.slot(self.next);
+        let mut round = self.ticks.first_round(self.id, &*self.clock);
+        let mut attempts = 0;
+        let responses = loop {
+            round = self.ticks.claim(round, &*self.clock)?;
+            let Some(responses) = self.peers.read_from(self.next, round) else {
+                return self.read_index().await;
+            };
+            let collected = collect_quorum(
+                &mut pin!(responses),
+                |response: &RangeResponse<V>| (response.acceptor, response.status),
+                |acceptors| quorum.is_read_quorum(acceptors),
+                quorum.max_failures(),
+            )
+            .await;
+            let conflict = match collected {
+                Ok(Ok(responses)) => break responses,
+                Ok(Err(_)) => None,
+                Err(Error::Preempted(conflict)) => {
+                    self.observe_round(conflict);
+                    Some(conflict)
+                }
+                Err(error) => return Err(error),
+            };
+            attempts += 1;
+            if self.retry_policy.exhausted(attempts) {
+                return Err(Error::RetriesExhausted {
+                    attempts,
+                    last: conflict.map(|conflict| Box::new(Error::Preempted(conflict))),
+                });
+            }
+            let next = conflict.map_or(round.next(), |conflict| {
+                round.next().max(round.greater_than(conflict))
+            });
+            round = self.ticks.advance(next, &*self.clock);
+        };
+
+        let mut slots: BTreeMap<LogIndex, (HashMap<_, _>, Option<Value<V>>)> = BTreeMap::new();
+        for response in responses {
+            for (index, value) in response.slots {
+                let (promised, accepted) = slots.entry(index).or_default();
+                let written = value.as_ref().map(|v| v.last_round_with_write);
+                if let Some(written) = written {
+                    self.observe_round(written);
+                }
+                promised.insert(response.acceptor, written);
+                if written > accepted.as_ref().map(|v| v.last_round_with_write) {
+                    *accepted = value;
+                }
+            }
+        }
+        let from = self.next;
+        let mut promises = slots
+            .into_iter()
+            .filter(|(index, (promised, _))| {
+                *index >= from && quorum.is_read_quorum(&promised.keys().copied().collect())
+            })
+            .map(|(index, (promised, accepted))| {
+                let highest = accepted.as_ref().map(|v| v.last_round_with_write);
+                let stale = promised
+                    .into_iter()
+                    .filter(|(_, written)| *written < highest)
+                    .map(|(acceptor, _)| acceptor)
+                    .collect();
+                let accepted = accepted.map(|v| v.value);
+                (
+                    index,
+                    Promise {
+                        round,
+                        accepted,
+                        stale,
+                    },
+                )
+            })
+            .peekable();
+
+        while let Some((index, promise)) = promises.next_if(|(index, _)| *index == self.next) {
+            let Some(accepted) = promise.accepted.clone() else {
+                if let Some(lease) = self.leader_lease {
+                    let expires = self.clock.deadline(lease);
+                    self.promises.insert(index, (promise, expires));
+                    self.promises
+                        .extend(promises.map(|(index, promise)| (index, (promise, expires))));
+                }
+                return Ok(index);
+            };
+            let mut proposer = self.proposer(index).promise(promise).build();
+            let consensus = proposer.propose(Arc::unwrap_or_clone(accepted)).await?;
+            self.commit(index, consensus);
+        }
+        self.read_index().await
+    }
+
+    /// Commits, from the next index on, every entry some acceptor learned
+    /// was decided, and stops at the first slot none has; returns that
+    /// slot. Neither reads nor writes a single acceptor, so it is how an
+    /// observer follows the log.
+    pub async fn learn(&mut self) -> Result<LogIndex, Error> {
+        loop {
+            let learned = {
+                let Some(responses) = self.peers.learned(self.next) else {
+                    return Ok(self.next);
+                };
+                let mut responses = pin!(responses);
+                let (mut answered, mut errors) = (false, Vec::new());
+                loop {
+                    match responses.next().await {
+                        Some(Ok(Some(value))) => break Some(value),
+                        Some(Ok(None)) => answered = true,
+                        Some(Err(error)) => errors.push(error),
+                        None if answered || errors.is_empty() => break None,
+                        None => return Err(Error::QuorumUnreachable { errors }),
+                    }
+                }
+            };
+            match learned {
+                Some(value) => self.commit(self.next, value),
+                None => return Ok(self.next),
+            }
+        }
+    }
+
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    pub fn is_observer(&self) -> bool {
+        self.observer
+    }
+
+    /// Lets an observer propose from now on. Everything it learned stays
+    /// committed, so a warm spare only has to catch up on the entries
+    /// decided since it last learned.
+    pub fn promote(&mut self) {
+        self.observer = false;
+    }
+
+    pub fn read(&self, index: LogIndex) -> Option<&V> {
+        self.read_stamped(index).map(|(_, value)| value)
+    }
+
+    pub fn read_stamped(&self, index: LogIndex) -> Option<(Timestamp, &V)> {
+        self.entries
+            .get(&index)
+            .map(|(timestamp, value)| (*timestamp, value))
+    }
+
+    pub fn now(&mut self) -> Timestamp {
+        self.hlc.now()
+    }
+
+    /// Merges a timestamp seen outside this log, e.g. from another consensus
+    /// group, so entries committed afterwards are stamped after it.
+    pub fn observe(&mut self, timestamp: Timestamp) {
+        self.hlc.observe(timestamp);
+    }
+
+    pub fn first_index(&self) -> LogIndex {
+        self.first
+    }
+
+    pub fn next_index(&self) -> LogIndex {
+        self.next
+    }
+
+    pub fn acknowledge(&self, next_to_apply: LogIndex) {
+        self.peers.acknowledge(self.id, next_to_apply);
+    }
+
+    pub fn truncate(&mut self, up_to: LogIndex) {
+        self.entries = self.entries.split_off(&up_to.next());
+        self.first = self.first.max(up_to.next());
+    }
+
+    pub fn install(&mut self, last_included: LogIndex) {
+        self.truncate(last_included);
+        self.next = self.next.max(last_included.next());
+    }
+
+    pub fn committed(&mut self) -> impl Stream<Item = (LogIndex, Timestamp, V)> {
+        let (sender, receiver) = unbounded();
+        for (index, (timestamp, value)) in &self.entries {
+            let _ = sender.unbounded_send((*index, *timestamp, value.clone()));
+        }
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    fn proposer(&self, index: LogIndex) -> ProposerBuilder<V, S::Peers, D> {
+        Proposer::builder(
+            self.id,
+            self.peers.slot(index),
+            self.failure_detector.clone(),
+        )
+        .retry_policy(self.retry_policy.clone())
+        .delivery(self.delivery)
+        .read_repair(self.read_repair)
+        .clock(self.clock.clone())
+        .ticks(self.ticks.clone())
+    }
+
+    fn proposing(&self) -> Result<(), Error> {
+        match self.observer {
+            true => Err(Error::Observing),
+            false => Ok(()),
+        }
+    }
+
+    fn take_promise(&mut self, index: LogIndex) -> Option<Promise<V>> {
+        self.promises = self.promises.split_off(&index);
+        let (promise, expires) = self.promises.remove(&index)?;
+        (self.clock.now() < expires).then_some(promise)
+    }
+
+    // Rounds only carry wall-clock time under `TickSource::Hybrid`; counter
+    // ticks are far below any physical timestamp and merge as no-ops.
+    fn observe_round(&mut self, round: Round) {
+        self.hlc.observe(Timestamp::from_bits(round.tick.0));
+    }
+
+    fn commit(&mut self, index: LogIndex, value: V) {
+        let timestamp = self.hlc.now();
+        self.subscribers.retain(|subscriber| {
+            subscriber
+                .unbounded_send((index, timestamp, value.clone()))
+                .is_ok()
+        });
+        self.entries.insert(index, (timestamp, value));
+        self.next = index.next();
+    }
+}
+
+#[cfg(all(test, feature = "threads"))]
+mod tests {
+    use super::*;
+    use crate::alpha::{ReadResponse, WriteResponse};
+    use crate::learner::DecisionBroadcast;
+    use crate::local::{LocalCluster, LocalPeers};
+    use crate::storage::MemoryStorage;
+    use crate::time::MockClock;
+    use futures::executor::block_on;
+    use std::cell::RefCell;
+    use std::future::{self, Future};
+    use std::io;
+    use std::rc::Rc;
+
+    #[derive(Clone, Default)]
+    struct Slots(Rc<RefCell<BTreeMap<LogIndex, LocalCluster<u64>>>>);
+
+    impl SlotPeers<u64> for Slots {
+        type Peers = LocalPeers<u64>;
+
+        fn slot(&self, index: LogIndex) -> Self::Peers {
+            self.0
+                .borrow_mut()
+                .entry(index)
+                .or_insert_with(|| LocalCluster::new(3))
+                .peers()
+        }
+
+        fn learned(
+            &self,
+            index: LogIndex,
+        ) -> Option<impl Stream<Item = Result<Option<u64>, Error>>> {
+            self.0.borrow().get(&index).map(LocalCluster::learned)
+        }
+    }
+
+    type Node = SlotAcceptors<u64, MemoryStorage<u64>>;
+
+    /// Three [`SlotAcceptors`] answering every slot, and whole ranges, in
+    /// place. Acceptors listed in `lost_reads` or `lost_writes` fail those
+    /// requests instead.
+    #[derive(Clone)]
+    struct Acceptors {
+        nodes: Rc<RefCell<Vec<Node>>>,
+        lost_reads: Rc<RefCell<Vec<usize>>>,
+        lost_writes: Rc<RefCell<Vec<usize>>>,
+    }
+
+    impl Acceptors {
+        fn new() -> Self {
+            let learners = [Id(1), Id(2)];
+            let nodes = (1..=3)
+                .map(|id| SlotAcceptors::new(Id(id), learners, |_| MemoryStorage::default()))
+                .collect();
+            Self {
+                nodes: Rc::new(RefCell::new(nodes)),
+                lost_reads: Rc::default(),
+                lost_writes: Rc::default(),
+            }
+        }
+
+        fn each<T>(
+            &self,
+            index: LogIndex,
+            lost: &RefCell<Vec<usize>>,
+            mut handle: impl FnMut(&mut Acceptor<u64, MemoryStorage<u64>>) -> Result<T, Error>,
+        ) -> stream::Iter<std::vec::IntoIter<Result<T, Error>>> {
+            let lost = lost.borrow();
+            let responses: Vec<_> = self
+                .nodes
+                .borrow_mut()
+                .iter_mut()
+                .enumerate()
+                .map(|(position, acceptors)| match acceptors.slot(index)? {
+                    Some(_) if lost.contains(&position) => {
+                        Err(io::Error::from(io::ErrorKind::TimedOut).into())
+                    }
+                    Some(acceptor) => handle(acceptor),
+                    None => Err(io::Error::from(io::ErrorKind::NotFound).into()),
+                })
+                .collect();
+            stream::iter(responses)
+        }
+    }
+
+    struct Slot(Acceptors, LogIndex);
+
+    impl ReadPeers<u64> for Slot {
+        fn read(&self, round: Round) -> impl Stream<Item = Result<ReadResponse<u64>, Error>> {
+            self.0.each(self.1, &self.0.lost_reads, |acceptor| {
+                acceptor.handle_read(round)
+            })
+        }
+    }
+
+    impl WritePeers<u64> for Slot {
+        fn write(&self, value: Value<u64>) -> impl Stream<Item = Result<WriteResponse, Error>> {
+            self.0.each(self.1, &self.0.lost_writes, |acceptor| {
+                acceptor.handle_write(value.clone())
+            })
+        }
+    }
+
+    impl DecisionPeers<u64> for Slot {
+        fn decide(&self, _: DecisionBroadcast<u64>) -> impl Stream<Item = Result<(), Error>> {
+            self.0.each(self.1, &RefCell::default(), |_| Ok(()))
+        }
+    }
+
+    impl Quorum for Slot {
+        fn majority(&self) -> usize {
+            2
+        }
+
+        fn max_failures(&self) -> usize {
+            1
+        }
+    }
+
+    impl SlotPeers<u64> for Acceptors {
+        type Peers = Slot;
+
+        fn slot(&self, index: LogIndex) -> Self::Peers {
+            Slot(self.clone(), index)
+        }
+
+        fn read_from(
+            &self,
+            from: LogIndex,
+            round: Round,
+        ) -> Option<impl Stream<Item = Result<RangeResponse<u64>, Error>>> {
+            let responses: Vec<_> = self
+                .nodes
+                .borrow_mut()
+                .iter_mut()
+                .map(|acceptors| acceptors.read_from(from, round))
+                .collect();
+            Some(stream::iter(responses))
+        }
+
+        fn acknowledge(&self, learner: Id, next_to_apply: LogIndex) {
+            for acceptors in self.nodes.borrow_mut().iter_mut() {
+                acceptors.acknowledge(learner, next_to_apply).unwrap();
+            }
+        }
+    }
+
+    #[derive(Clone)]
+    struct Leader(Id);
+
+    impl FailureDetector for Leader {
+        fn leader(&self) -> Id {
+            self.0
+        }
+
+        fn changed(&self) -> impl Future<Output = ()> {
+            future::pending()
+        }
+    }
+
+    #[test]
+    fn observers_learn_the_log_without_proposing() {
+        let slots = Slots::default();
+        let mut leader = ReplicatedLog::new(Id(1), slots.clone(), Leader(Id(1)));
+        let mut observer = ReplicatedLog::new(Id(2), slots, Leader(Id(2))).observer();
+        assert_eq!(block_on(observer.learn()).unwrap(), LogIndex::new(0));
+        block_on(leader.append_all([1, 2, 3])).unwrap();
+
+        assert_eq!(block_on(observer.learn()).unwrap(), LogIndex::new(3));
+        let learned: Vec<_> = (0..3)
+            .map(|index| observer.read(LogIndex::new(index)).copied())
+            .collect();
+        assert_eq!(learned, [Some(1), Some(2), Some(3)]);
+        assert!(matches!(
+            block_on(observer.append(4)),
+            Err(Error::Observing)
+        ));
+        assert!(matches!(
+            block_on(observer.read_index()),
+            Err(Error::Observing)
+        ));
+        assert!(matches!(
+            block_on(observer.catch_up()),
+            Err(Error::Observing)
+        ));
+        assert!(!Error::Observing.is_retryable());
+
+        observer.promote();
+        assert!(!observer.is_observer());
+        assert_eq!(block_on(observer.append(4)).unwrap(), LogIndex::new(3));
+        assert_eq!(block_on(leader.learn()).unwrap(), LogIndex::new(4));
+        assert_eq!(leader.read(LogIndex::new(3)), Some(&4));
+    }
+
+    #[test]
+    fn stamps_committed_entries_with_hybrid_timestamps() {
+        let clock = MockClock::new();
+        let mut log = ReplicatedLog::new(Id(1), Slots::default(), Leader(Id(1)))
+            .clock(Arc::new(clock.clone()));
+        let mut committed = log.committed();
+
+        block_on(log.append_all([1, 2])).unwrap();
+        let remote = Timestamp::new(Timestamp::physical(&clock).millis() + 60_000, 0);
+        log.observe(remote);
+        block_on(log.append(3)).unwrap();
+
+        let entries: Vec<_> = block_on(committed.by_ref().take(3).collect());
+        let stamps: Vec<_> = entries.iter().map(|(_, timestamp, _)| *timestamp).collect();
+        assert_eq!(stamps[0], Timestamp::physical(&clock));
+        assert!(stamps[0] < stamps[1] && stamps[1] < remote && remote < stamps[2]);
+        for (index, timestamp, value) in entries {
+            assert_eq!(log.read_stamped(index), Some((timestamp, &value)));
+        }
+    }
+
+    #[test]
+    fn committed_decisions_give_their_budget_back() {
+        let budget = MemoryBudget::new(64);
+        let mut log = ReplicatedLog::new(Id(1), Slots::default(), Leader(Id(1)))
+            .pipeline_window(4)
+            .memory_budget(budget.clone(), |_| 8);
+        block_on(log.append_all(0..16)).unwrap();
+        assert_eq!(log.next_index(), LogIndex::new(16));
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[test]
+    fn catch_up_commits_what_a_previous_leader_left_accepted() {
+        let acceptors = Acceptors::new();
+        let mut old = ReplicatedLog::new(Id(2), acceptors.clone(), Leader(Id(2)));
+        block_on(old.append_all([10, 20])).unwrap();
+
+        let mut new = ReplicatedLog::new(Id(1), acceptors, Leader(Id(1)));
+        assert_eq!(block_on(new.catch_up()).unwrap(), LogIndex(2));
+        assert_eq!(
+            (new.read(LogIndex(0)), new.read(LogIndex(1))),
+            (Some(&10), Some(&20))
+        );
+        assert_eq!(block_on(new.append(30)).unwrap(), LogIndex(2));
+
+        let mut follower = ReplicatedLog::new(Id(2), new.peers.clone(), Leader(Id(1)));
+        assert!(matches!(
+            block_on(follower.catch_up()),
+            Err(Error::NotLeader)
+        ));
+    }
+
+    #[test]
+    fn collects_slots_every_learner_has_applied() {
+        let acceptors = Acceptors::new();
+        let mut log = ReplicatedLog::new(Id(1), acceptors.clone(), Leader(Id(1)));
+        block_on(log.append_all([1, 2, 3])).unwrap();
+
+        log.acknowledge(LogIndex(2));
+        let firsts = |acceptors: &Acceptors| {
+            let acceptors = acceptors.nodes.borrow();
+            acceptors
+                .iter()
+                .map(|a| (a.first_index(), a.len()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(firsts(&acceptors), [(LogIndex(0), 3); 3]);
+
+        acceptors.acknowledge(Id(2), LogIndex(3));
+        acceptors.acknowledge(Id(9), LogIndex(3));
+        assert_eq!(firsts(&acceptors), [(LogIndex(2), 1); 3]);
+
+        let mut first = acceptors.nodes.borrow_mut().remove(0);
+        assert!(first.slot(LogIndex(1)).unwrap().is_none());
+        let range = first
+            .read_from(LogIndex(0), Round::new(Id(1)).next())
+            .unwrap();
+        assert_eq!(
+            range.slots.keys().copied().collect::<Vec<_>>(),
+            [LogIndex(2)]
+        );
+        assert_eq!(first.acknowledge(Id(1), LogIndex(1)).unwrap(), LogIndex(2));
+    }
+
+    #[test]
+    fn retried_appends_never_reuse_a_round() {
+        let acceptors = Acceptors::new();
+        let mut log =
+            ReplicatedLog::new(Id(1), acceptors.clone(), Leader(Id(1))).retry_policy(RetryPolicy {
+                max_attempts: Some(1),
+                ..RetryPolicy::immediate()
+            });
+
+        // The first value reaches a single acceptor, and the retry's read
+        // quorum misses it.
+        *acceptors.lost_writes.borrow_mut() = vec![1, 2];
+        assert!(block_on(log.append(1)).is_err());
+        *acceptors.lost_writes.borrow_mut() = vec![];
+        *acceptors.lost_reads.borrow_mut() = vec![0];
+        assert_eq!(block_on(log.append(2)).unwrap(), LogIndex(0));
+
+        for acceptors in acceptors.nodes.borrow_mut().iter_mut() {
+            let slot = acceptors.slot(LogIndex(0)).unwrap().unwrap();
+            let accepted = slot.state().value.as_ref().unwrap();
+            assert_eq!(*accepted.value(), 2);
+        }
+    }
+}
@@ -0,0 +1,318 @@
+//! Which tracing events a node logs, written like
+//! `info,paxos_classic::transport=debug`: a bare level applies to every
+//! target, and `target=level` to that module and those inside it, the
+//! longest matching target winning. With the `tracing` feature, [`install`]
+//! logs to standard error through a filter [`set`] swaps while the node
+//! runs, so turning on debug logging does not take a restart.
+
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+const LEVELS: [(Level, &str); 6] = [
+    (Level::Off, "off"),
+    (Level::Error, "error"),
+    (Level::Warn, "warn"),
+    (Level::Info, "info"),
+    (Level::Debug, "debug"),
+    (Level::Trace, "trace"),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Filter {
+    default: Level,
+    targets: Vec<(String, Level)>,
+}
+
+#[derive(Error, Debug)]
+pub enum FilterError {
+    #[error("invalid filter directive `{0}`")]
+    Invalid(String),
+    #[error("no reloadable logger is installed")]
+    NotInstalled,
+    #[error("another logger is already installed")]
+    Installed,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self {
+            default: Level::Info,
+            targets: Vec::new(),
+        }
+    }
+}
+
+impl Filter {
+    /// The most verbose level logged for events from `target`.
+    pub fn level(&self, target: &str) -> Level {
+        self.targets
+            .iter()
+            .filter(|(prefix, _)| {
+                target
+                    .strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+}
+
+impl FromStr for Level {
+    type Err = FilterError;
+
+    fn from_str(level: &str) -> Result<Self, Self::Err> {
+        LEVELS
+            .iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(level))
+            .map(|(level, _)| *level)
+            .ok_or_else(|| FilterError::Invalid(level.to_string()))
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (_, name) = LEVELS[*self as usize];
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = FilterError;
+
+    fn from_str(filter: &str) -> Result<Self, Self::Err> {
+        let mut parsed = Filter::default();
+        for directive in filter.split(',').map(str::trim) {
+            match directive.split_once('=') {
+                None if directive.is_empty() => {}
+                None => parsed.default = directive.parse()?,
+                Some((target, level)) if !target.is_empty() => {
+                    let level = level
+                        .parse()
+                        .map_err(|_| FilterError::Invalid(directive.to_string()))?;
+                    parsed.targets.retain(|(known, _)| known != target);
+                    parsed.targets.push((target.to_string(), level));
+                }
+                Some(_) => return Err(FilterError::Invalid(directive.to_string())),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.default)?;
+        for (target, level) in &self.targets {
+            write!(f, ",{target}={level}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tracing")]
+pub use logger::{install, set, Logger};
+
+#[cfg(feature = "tracing")]
+mod logger {
+    use super::{Filter, FilterError, Level};
+    use std::fmt::{self, Write as _};
+    use std::io::{self, Write};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex, OnceLock, PoisonError, RwLock};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::subscriber::Interest;
+    use tracing::{Event, Metadata, Subscriber};
+
+    static INSTALLED: OnceLock<Arc<Logger>> = OnceLock::new();
+
+    type Output = Box<dyn Write + Send>;
+
+    /// Writes one line per event its filter lets through.
+    pub struct Logger {
+        filter: RwLock<Filter>,
+        out: Mutex<Output>,
+        spans: AtomicU64,
+    }
+
+    impl Logger {
+        pub fn new(filter: Filter, out: impl Write + Send + 'static) -> Self {
+            Self {
+                filter: RwLock::new(filter),
+                out: Mutex::new(Box::new(out)),
+                spans: AtomicU64::new(0),
+            }
+        }
+
+        pub fn filter(&self) -> Filter {
+            self.filter
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone()
+        }
+
+        pub fn set_filter(&self, filter: Filter) {
+            *self.filter.write().unwrap_or_else(PoisonError::into_inner) = filter;
+            tracing::callsite::rebuild_interest_cache();
+        }
+    }
+
+    /// Makes a [`Logger`] writing to standard error the global subscriber.
+    pub fn install(filter: Filter) -> Result<(), FilterError> {
+        let logger = Arc::new(Logger::new(filter, io::stderr()));
+        tracing::subscriber::set_global_default(logger.clone())
+            .map_err(|_| FilterError::Installed)?;
+        INSTALLED.set(logger).map_err(|_| FilterError::Installed)
+    }
+
+    /// Replaces the filter of the logger [`install`] made.
+    pub fn set(filter: &str) -> Result<(), FilterError> {
+        let filter = filter.parse()?;
+        INSTALLED
+            .get()
+            .ok_or(FilterError::NotInstalled)?
+            .set_filter(filter);
+        Ok(())
+    }
+
+    fn level(level: &tracing::Level) -> Level {
+        match *level {
+            tracing::Level::ERROR => Level::Error,
+            tracing::Level::WARN => Level::Warn,
+            tracing::Level::INFO => Level::Info,
+            tracing::Level::DEBUG => Level::Debug,
+            tracing::Level::TRACE => Level::Trace,
+        }
+    }
+
+    struct Line(String);
+
+    impl Visit for Line {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            let _ = match field.name() {
+                "message" => write!(self.0, " {value:?}"),
+                name => write!(self.0, " {name}={value:?}"),
+            };
+        }
+    }
+
+    impl Subscriber for Logger {
+        // Asked again for every event, so a new filter applies at once.
+        fn register_callsite(&self, _: &'static Metadata<'static>) -> Interest {
+            Interest::sometimes()
+        }
+
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+            level(metadata.level())
+                <= self
+                    .filter
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .level(metadata.target())
+        }
+
+        fn new_span(&self, _: &Attributes<'_>) -> Id {
+            Id::from_u64(self.spans.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let metadata = event.metadata();
+            let mut line = Line(format!(
+                "{} {}:",
+                level(metadata.level()),
+                metadata.target()
+            ));
+            event.record(&mut line);
+            let mut out = self.out.lock().unwrap_or_else(PoisonError::into_inner);
+            let _ = writeln!(out, "{}", line.0);
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_longest_matching_target_wins() {
+        let filter: Filter = "warn, paxos_classic=info,paxos_classic::transport=debug"
+            .parse()
+            .unwrap();
+        assert_eq!(filter.level("other"), Level::Warn);
+        assert_eq!(filter.level("paxos_classic::node"), Level::Info);
+        assert_eq!(filter.level("paxos_classic::transport::tcp"), Level::Debug);
+        assert_eq!(filter.level("paxos_classic_fuzz"), Level::Warn);
+        assert_eq!(
+            filter.to_string(),
+            "warn,paxos_classic=info,paxos_classic::transport=debug"
+        );
+        assert_eq!(filter.to_string().parse::<Filter>().unwrap(), filter);
+        assert_eq!("".parse::<Filter>().unwrap(), Filter::default());
+        for invalid in ["loud", "node=loud", "=debug"] {
+            assert!(matches!(
+                invalid.parse::<Filter>(),
+                Err(FilterError::Invalid(_))
+            ));
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn a_new_filter_applies_to_the_next_event() {
+        use std::io;
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Clone, Default)]
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+
+        impl io::Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let out = Shared::default();
+        let logger = Arc::new(Logger::new(Filter::default(), out.clone()));
+        let log = || {
+            tracing::subscriber::with_default(logger.clone(), || {
+                tracing::info!(target: "paxos_classic::node", round = 3, "entered");
+                tracing::debug!(target: "paxos_classic::node", "promised");
+            })
+        };
+        log();
+        logger.set_filter("info,paxos_classic::node=debug".parse().unwrap());
+        log();
+        let lines = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            lines,
+            "info paxos_classic::node: entered round=3\n\
+             info paxos_classic::node: entered round=3\n\
+             debug paxos_classic::node: promised\n"
+        );
+        assert!(matches!(set("debug"), Err(FilterError::NotInstalled)));
+    }
+}
@@ -0,0 +1,316 @@
+use crate::alpha::{Error, Id, Quorum};
+use crate::log::{LogIndex, SlotPeers};
+use crate::proposer::{FailureDetector, Proposer, TickSource, Ticks};
+use crate::quorum::WithQuorum;
+use crate::retry::RetryPolicy;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::net::SocketAddr;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Configuration {
+    voters: BTreeSet<Id>,
+}
+
+impl Configuration {
+    pub fn new(voters: impl IntoIterator<Item = Id>) -> Self {
+        Self {
+            voters: voters.into_iter().collect(),
+        }
+    }
+
+    pub fn voters(&self) -> &BTreeSet<Id> {
+        &self.voters
+    }
+
+    fn is_quorum(&self, acceptors: &HashSet<Id>) -> bool {
+        let votes = self
+            .voters
+            .iter()
+            .filter(|voter| acceptors.contains(voter))
+            .count();
+        votes > self.voters.len() / 2
+    }
+
+    fn max_failures(&self) -> usize {
+        self.voters.len().saturating_sub(self.voters.len() / 2 + 1)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Membership {
+    Stable(Configuration),
+    Joint {
+        old: Configuration,
+        new: Configuration,
+    },
+}
+
+impl Membership {
+    pub fn voters(&self) -> BTreeSet<Id> {
+        match self {
+            Membership::Stable(configuration) => configuration.voters.clone(),
+            Membership::Joint { old, new } => old.voters.union(&new.voters).copied().collect(),
+        }
+    }
+
+    fn is_quorum(&self, acceptors: &HashSet<Id>) -> bool {
+        match self {
+            Membership::Stable(configuration) => configuration.is_quorum(acceptors),
+            Membership::Joint { old, new } => old.is_quorum(acceptors) && new.is_quorum(acceptors),
+        }
+    }
+}
+
+impl Quorum for Membership {
+    fn majority(&self) -> usize {
+        match self {
+            Membership::Stable(configuration)
+            | Membership::Joint {
+                new: configuration, ..
+            } => configuration.voters.len() / 2 + 1,
+        }
+    }
+
+    fn max_failures(&self) -> usize {
+        match self {
+            Membership::Stable(configuration) => configuration.max_failures(),
+            Membership::Joint { old, new } => old.max_failures() + new.max_failures(),
+        }
+    }
+
+    fn is_read_quorum(&self, acceptors: &HashSet<Id>) -> bool {
+        self.is_quorum(acceptors)
+    }
+
+    fn is_write_quorum(&self, acceptors: &HashSet<Id>) -> bool {
+        self.is_quorum(acceptors)
+    }
+}
+
+/// Whether a member counts towards quorums or only follows the decisions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Role {
+    Voter,
+    Learner,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Role::Voter => "voter",
+            Role::Learner => "learner",
+        })
+    }
+}
+
+/// A member of the cluster as the node asked last heard from it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Member {
+    pub id: Id,
+    pub addr: SocketAddr,
+    pub role: Role,
+    pub leader: bool,
+    /// Whether it answered this time; if not, `epoch` and `commit_index`
+    /// are what it said when it last did.
+    pub reachable: bool,
+    /// `None` if it never answered.
+    pub epoch: Option<u64>,
+    pub commit_index: Option<LogIndex>,
+}
+
+/// The last membership a node applied from the log, where each of its
+/// voters listens, and the epoch the node is in, as it keeps them across
+/// restarts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Applied {
+    /// The first slot of the membership log not applied.
+    pub next: LogIndex,
+    pub membership: Membership,
+    pub members: Vec<(Id, SocketAddr)>,
+    pub epoch: u64,
+}
+
+type OnCommit = Box<dyn FnMut(&Membership) + Send>;
+
+pub struct Cluster<S, D> {
+    id: Id,
+    peers: S,
+    failure_detector: D,
+    retry_policy: RetryPolicy,
+    ticks: Ticks,
+    membership: Membership,
+    next: LogIndex,
+    on_commit: Option<OnCommit>,
+}
+
+impl<S, D> Cluster<S, D>
+where
+    S: SlotPeers<Membership>,
+    D: FailureDetector + Clone,
+{
+    pub fn new(id: Id, peers: S, failure_detector: D, initial: Configuration) -> Self {
+        Self {
+            id,
+            peers,
+            failure_detector,
+            retry_policy: RetryPolicy::default(),
+            ticks: Ticks::new(TickSource::Counter),
+            membership: Membership::Stable(initial),
+            next: LogIndex::default(),
+            on_commit: None,
+        }
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Shares rounds across every configuration change, so a retried change
+    /// never reuses one; back it with storage to survive restarts.
+    pub fn ticks(mut self, ticks: Ticks) -> Self {
+        self.ticks = ticks;
+        self
+    }
+
+    /// Called with every configuration this cluster commits, so the transport
+    /// can start reaching added voters and stop counting removed ones.
+    pub fn on_commit(mut self, on_commit: impl FnMut(&Membership) + Send + 'static) -> Self {
+        self.on_commit = Some(Box::new(on_commit));
+        self
+    }
+
+    /// Picks up after the memberships decided below `next`, the last of
+    /// which was `membership`.
+    pub fn resume(mut self, next: LogIndex, membership: Membership) -> Self {
+        self.next = next;
+        self.membership = membership;
+        self
+    }
+
+    pub fn membership(&self) -> &Membership {
+        &self.membership
+    }
+
+    pub async fn add_node(&mut self, id: Id) -> Result<&Membership, Error> {
+        let old = self.settle().await?.clone();
+        let mut new = old.clone();
+        new.voters.insert(id);
+        self.reconfigure(old, new).await
+    }
+
+    pub async fn remove_node(&mut self, id: Id) -> Result<&Membership, Error> {
+        let old = self.settle().await?.clone();
+        let mut new = old.clone();
+        new.voters.remove(&id);
+        self.reconfigure(old, new).await
+    }
+
+    async fn reconfigure(
+        &mut self,
+        old: Configuration,
+        new: Configuration,
+    ) -> Result<&Membership, Error> {
+        if old == new {
+            return Ok(&self.membership);
+        }
+        self.decide(Membership::Joint {
+            old,
+            new: new.clone(),
+        })
+        .await?;
+        self.decide(Membership::Stable(new)).await?;
+        Ok(&self.membership)
+    }
+
+    async fn settle(&mut self) -> Result<&Configuration, Error> {
+        if let Membership::Joint { new, .. } = &self.membership {
+            self.decide(Membership::Stable(new.clone())).await?;
+        }
+        match &self.membership {
+            Membership::Stable(configuration) => Ok(configuration),
+            Membership::Joint { .. } => Err(Error::MembershipConflict),
+        }
+    }
+
+    async fn decide(&mut self, proposal: Membership) -> Result<(), Error> {
+        let peers = WithQuorum::from_quorum(self.peers.slot(self.next), self.membership.clone());
+        let mut proposer = Proposer::builder(self.id, peers, self.failure_detector.clone())
+            .retry_policy(self.retry_policy.clone())
+            .ticks(self.ticks.clone())
+            .build();
+        let decided = proposer.propose(proposal.clone()).await?;
+        let ours = decided == proposal;
+        self.membership = decided;
+        self.next = self.next.next();
+        if let Some(on_commit) = &mut self.on_commit {
+            on_commit(&self.membership);
+        }
+        if ours {
+            Ok(())
+        } else {
+            Err(Error::MembershipConflict)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "threads"))]
+mod tests {
+    use super::*;
+    use crate::local::{LocalCluster, LocalPeers};
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
+    use std::future::{self, Future};
+    use std::rc::Rc;
+    use std::sync::{Arc, Mutex};
+
+    /// Acceptors are `Id(0)..Id(5)`, so every voter the test names is reachable.
+    #[derive(Clone, Default)]
+    struct Slots(Rc<RefCell<BTreeMap<LogIndex, LocalCluster<Membership>>>>);
+
+    impl SlotPeers<Membership> for Slots {
+        type Peers = LocalPeers<Membership>;
+
+        fn slot(&self, index: LogIndex) -> Self::Peers {
+            self.0
+                .borrow_mut()
+                .entry(index)
+                .or_insert_with(|| LocalCluster::new(5))
+                .peers()
+        }
+    }
+
+    #[derive(Clone)]
+    struct Leader;
+
+    impl FailureDetector for Leader {
+        fn leader(&self) -> Id {
+            Id(1)
+        }
+
+        fn changed(&self) -> impl Future<Output = ()> {
+            future::pending()
+        }
+    }
+
+    #[test]
+    fn reports_every_committed_configuration() {
+        let committed = Arc::new(Mutex::new(Vec::new()));
+        let initial = Configuration::new([Id(1), Id(2), Id(3)]);
+        let mut cluster = Cluster::new(Id(1), Slots::default(), Leader, initial).on_commit({
+            let committed = committed.clone();
+            move |membership| committed.lock().unwrap().push(membership.voters())
+        });
+
+        futures::executor::block_on(cluster.add_node(Id(4))).unwrap();
+        let voters = |ids: &[u64]| ids.iter().copied().map(Id).collect::<BTreeSet<_>>();
+        assert_eq!(
+            *committed.lock().unwrap(),
+            [voters(&[1, 2, 3, 4]), voters(&[1, 2, 3, 4])]
+        );
+
+        futures::executor::block_on(cluster.remove_node(Id(2))).unwrap();
+        assert_eq!(committed.lock().unwrap()[3], voters(&[1, 3, 4]));
+    }
+}
@@ -0,0 +1,412 @@
+use crate::alpha::Round;
+use crate::time::Timeouts;
+use std::collections::HashMap;
+use std::fmt::{Display, Write};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+const BUCKETS_PER_DOUBLING: f64 = 4.0;
+const BUCKETS: usize = 160;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Read,
+    Write,
+}
+
+pub trait Observer {
+    /// Lets the proposer skip work, such as reading the clock for latencies,
+    /// that only an observer would use.
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    fn on_round_started(&self, _round: Round) {}
+    fn on_quorum_reached(&self, _round: Round, _stage: Stage) {}
+    fn on_conflict(&self, _round: Round, _conflict: Round) {}
+    fn on_decided(&self, _round: Round, _latency: Duration) {}
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NoopObserver;
+
+impl Observer for NoopObserver {
+    fn enabled(&self) -> bool {
+        false
+    }
+}
+
+impl<O> Observer for Arc<O>
+where
+    O: Observer + ?Sized,
+{
+    fn enabled(&self) -> bool {
+        (**self).enabled()
+    }
+
+    fn on_round_started(&self, round: Round) {
+        (**self).on_round_started(round)
+    }
+
+    fn on_quorum_reached(&self, round: Round, stage: Stage) {
+        (**self).on_quorum_reached(round, stage)
+    }
+
+    fn on_conflict(&self, round: Round, conflict: Round) {
+        (**self).on_conflict(round, conflict)
+    }
+
+    fn on_decided(&self, round: Round, latency: Duration) {
+        (**self).on_decided(round, latency)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PrometheusObserver {
+    rounds: AtomicU64,
+    read_quorums: AtomicU64,
+    write_quorums: AtomicU64,
+    conflicts: AtomicU64,
+    decisions: AtomicU64,
+    latency_micros: AtomicU64,
+}
+
+impl PrometheusObserver {
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let counters = [
+            ("paxos_rounds_started_total", &self.rounds, None),
+            (
+                "paxos_quorums_reached_total",
+                &self.read_quorums,
+                Some("read"),
+            ),
+            (
+                "paxos_quorums_reached_total",
+                &self.write_quorums,
+                Some("write"),
+            ),
+            ("paxos_conflicts_total", &self.conflicts, None),
+        ];
+        let mut last = "";
+        for (name, counter, stage) in counters {
+            if name != last {
+                let _ = writeln!(out, "# TYPE {name} counter");
+                last = name;
+            }
+            let value = counter.load(Ordering::Relaxed);
+            match stage {
+                Some(stage) => {
+                    let _ = writeln!(out, "{name}{{stage=\"{stage}\"}} {value}");
+                }
+                None => {
+                    let _ = writeln!(out, "{name} {value}");
+                }
+            }
+        }
+        let latency = Duration::from_micros(self.latency_micros.load(Ordering::Relaxed));
+        let _ = writeln!(out, "# TYPE paxos_decision_latency_seconds summary");
+        let _ = writeln!(
+            out,
+            "paxos_decision_latency_seconds_sum {}",
+            latency.as_secs_f64()
+        );
+        let _ = writeln!(
+            out,
+            "paxos_decision_latency_seconds_count {}",
+            self.decisions.load(Ordering::Relaxed)
+        );
+        out
+    }
+}
+
+impl Observer for PrometheusObserver {
+    fn on_round_started(&self, _round: Round) {
+        self.rounds.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_quorum_reached(&self, _round: Round, stage: Stage) {
+        match stage {
+            Stage::Read => &self.read_quorums,
+            Stage::Write => &self.write_quorums,
+        }
+        .fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_conflict(&self, _round: Round, _conflict: Round) {
+        self.conflicts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_decided(&self, _round: Round, latency: Duration) {
+        self.decisions.fetch_add(1, Ordering::Relaxed);
+        self.latency_micros
+            .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug)]
+pub struct Histogram {
+    buckets: [AtomicU64; BUCKETS],
+    count: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            count: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Histogram {
+    pub fn record(&self, elapsed: Duration) {
+        let micros = elapsed.as_micros().max(1) as f64;
+        let bucket = ((micros.log2() * BUCKETS_PER_DOUBLING).ceil() as usize).min(BUCKETS - 1);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn quantile(&self, quantile: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let target = ((quantile.clamp(0.0, 1.0) * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        let bucket = self
+            .buckets
+            .iter()
+            .position(|bucket| {
+                seen += bucket.load(Ordering::Relaxed);
+                seen >= target
+            })
+            .unwrap_or(BUCKETS - 1);
+        Some(Duration::from_secs_f64(
+            2f64.powf(bucket as f64 / BUCKETS_PER_DOUBLING) / 1e6,
+        ))
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct AdaptiveTimeout {
+    pub quantile: f64,
+    pub margin: Duration,
+    pub min: Duration,
+    pub max: Duration,
+    pub min_samples: u64,
+}
+
+impl Default for AdaptiveTimeout {
+    fn default() -> Self {
+        Self {
+            quantile: 0.99,
+            margin: Duration::from_millis(5),
+            min: Duration::from_millis(1),
+            max: Duration::from_secs(10),
+            min_samples: 16,
+        }
+    }
+}
+
+impl AdaptiveTimeout {
+    pub fn derive(&self, histogram: &Histogram) -> Option<Duration> {
+        if histogram.count() < self.min_samples {
+            return None;
+        }
+        let observed = histogram.quantile(self.quantile)?;
+        Some((observed + self.margin).clamp(self.min, self.max))
+    }
+}
+
+#[derive(Debug)]
+pub struct ResponseTimes<K> {
+    policy: AdaptiveTimeout,
+    peers: Mutex<HashMap<K, Arc<Histogram>>>,
+}
+
+impl<K: Copy + Eq + Hash> ResponseTimes<K> {
+    pub fn new(policy: AdaptiveTimeout) -> Self {
+        Self {
+            policy,
+            peers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn record(&self, peer: K, elapsed: Duration) {
+        self.peers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(peer)
+            .or_default()
+            .record(elapsed);
+    }
+
+    pub fn histogram(&self, peer: K) -> Option<Arc<Histogram>> {
+        self.peers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&peer)
+            .cloned()
+    }
+
+    pub fn timeout(&self, peer: K) -> Option<Duration> {
+        self.policy.derive(&*self.histogram(peer)?)
+    }
+
+    pub fn quorum_timeout(&self, quorum: usize) -> Option<Duration> {
+        let mut timeouts: Vec<Duration> = self
+            .peers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .filter_map(|histogram| self.policy.derive(histogram))
+            .collect();
+        timeouts.sort();
+        timeouts.get(quorum.checked_sub(1)?).copied()
+    }
+
+    pub fn render(&self) -> String
+    where
+        K: Display,
+    {
+        let mut out = String::new();
+        let _ = writeln!(out, "# TYPE paxos_peer_response_seconds summary");
+        let peers = self.peers.lock().unwrap_or_else(PoisonError::into_inner);
+        for (peer, histogram) in peers.iter() {
+            for quantile in [0.5, 0.9, 0.99] {
+                if let Some(value) = histogram.quantile(quantile) {
+                    let _ = writeln!(
+                        out,
+                        "paxos_peer_response_seconds{{peer=\"{peer}\",quantile=\"{quantile}\"}} {}",
+                        value.as_secs_f64()
+                    );
+                }
+            }
+            let _ = writeln!(
+                out,
+                "paxos_peer_response_seconds_count{{peer=\"{peer}\"}} {}",
+                histogram.count()
+            );
+        }
+        out
+    }
+}
+
+impl<K: Copy + Eq + Hash + Send> Timeouts for ResponseTimes<K> {
+    fn stage_timeout(&self, quorum: usize) -> Option<Duration> {
+        self.quorum_timeout(quorum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alpha::Id;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    fn within_a_bucket(observed: Duration, sample: Duration) -> bool {
+        observed >= sample && observed.as_secs_f64() <= sample.as_secs_f64() * 1.2
+    }
+
+    #[test]
+    fn histogram_quantiles_round_up_to_their_bucket() {
+        let histogram = Histogram::default();
+        assert_eq!(histogram.quantile(0.5), None);
+        for _ in 0..90 {
+            histogram.record(MS);
+        }
+        for _ in 0..10 {
+            histogram.record(100 * MS);
+        }
+        assert_eq!(histogram.count(), 100);
+        assert!(within_a_bucket(histogram.quantile(0.5).unwrap(), MS));
+        assert!(within_a_bucket(histogram.quantile(0.9).unwrap(), MS));
+        assert!(within_a_bucket(histogram.quantile(0.99).unwrap(), 100 * MS));
+        assert!(within_a_bucket(histogram.quantile(7.0).unwrap(), 100 * MS));
+    }
+
+    #[test]
+    fn adaptive_timeouts_wait_for_samples_then_clamp() {
+        let policy = AdaptiveTimeout {
+            quantile: 0.5,
+            margin: 2 * MS,
+            min: 5 * MS,
+            max: 50 * MS,
+            min_samples: 3,
+        };
+        let histogram = Histogram::default();
+        histogram.record(MS);
+        histogram.record(MS);
+        assert_eq!(policy.derive(&histogram), None);
+        histogram.record(MS);
+        assert_eq!(policy.derive(&histogram), Some(5 * MS));
+
+        let slow = Histogram::default();
+        for _ in 0..3 {
+            slow.record(20 * MS);
+        }
+        let derived = policy.derive(&slow).unwrap();
+        assert!(within_a_bucket(derived - 2 * MS, 20 * MS));
+
+        for _ in 0..10 {
+            slow.record(Duration::from_secs(1));
+        }
+        assert_eq!(policy.derive(&slow), Some(50 * MS));
+    }
+
+    #[test]
+    fn quorum_timeouts_wait_for_the_quorum_th_fastest_peer() {
+        let times = ResponseTimes::new(AdaptiveTimeout {
+            min_samples: 1,
+            margin: Duration::ZERO,
+            ..AdaptiveTimeout::default()
+        });
+        for (peer, latency) in [(1, 40), (2, 10), (3, 20)] {
+            times.record(Id(peer), latency * MS);
+        }
+        assert!(within_a_bucket(times.quorum_timeout(2).unwrap(), 20 * MS));
+        assert!(within_a_bucket(times.timeout(Id(1)).unwrap(), 40 * MS));
+        assert_eq!(times.quorum_timeout(4), None);
+        assert_eq!(times.quorum_timeout(0), None);
+        assert_eq!(times.timeout(Id(9)), None);
+    }
+
+    #[test]
+    fn the_prometheus_observer_counts_every_hook() {
+        let observer = Arc::new(PrometheusObserver::default());
+        let hooks: &dyn Observer = &observer;
+        let round = Round::new(Id(1));
+        hooks.on_round_started(round);
+        hooks.on_round_started(round);
+        hooks.on_quorum_reached(round, Stage::Read);
+        hooks.on_quorum_reached(round, Stage::Write);
+        hooks.on_quorum_reached(round, Stage::Write);
+        hooks.on_conflict(round, round.next());
+        hooks.on_decided(round, Duration::from_micros(1500));
+
+        let metrics = observer.render();
+        for line in [
+            "paxos_rounds_started_total 2",
+            "paxos_quorums_reached_total{stage=\"read\"} 1",
+            "paxos_quorums_reached_total{stage=\"write\"} 2",
+            "paxos_conflicts_total 1",
+            "paxos_decision_latency_seconds_sum 0.0015",
+            "paxos_decision_latency_seconds_count 1",
+        ] {
+            assert!(
+                metrics.lines().any(|rendered| rendered == line),
+                "{metrics}"
+            );
+        }
+        assert!(hooks.enabled());
+        assert!(!NoopObserver.enabled());
+    }
+}
@@ -0,0 +1,2217 @@
+use crate::acceptor::Acceptor;
+use crate::alpha::{Error, Id, Quorum, ReadPeers, Round};
+use crate::audit::DecisionTrail;
+use crate::bootstrap::{Bootstrap, BootstrapError, BootstrapFile, ClusterId};
+use crate::certificate::DecisionCertificate;
+use crate::codec::{from_bytes, to_bytes, Decode, Encode};
+use crate::config::NodeConfig;
+use crate::data_dir::{self, DataDir};
+use crate::failure_detector::OmegaDetector;
+use crate::fence::{Fence, FenceError, FenceLedger, StorageFence};
+use crate::identity;
+use crate::instance::{InstanceAcceptors, InstanceId, InstancePeers, InstanceStore};
+use crate::learner::{DecisionPeers, Learner};
+use crate::log::{LogIndex, SlotPeers};
+use crate::membership::{Applied, Cluster, Configuration, Member, Membership, Role};
+use crate::metrics::{AdaptiveTimeout, ResponseTimes};
+use crate::proposer::{FailureDetector, ProposeHandle, Proposer, Ticks};
+use crate::quorum::{QuorumSpec, SharedQuorum, WithQuorum};
+use crate::quota::RateLimiter;
+use crate::rng::XorShift;
+use crate::storage::{FileStorage, MemoryStorage, RecordFile, Storage};
+use crate::time::{Clock, SystemClock};
+use crate::token::{JoinTokens, ReloadableTokens, TokenVerifier};
+use crate::transport::tcp::{
+    Admin, Features, Loopback, Proposals, Proposed, Reconfigure, Server, TcpPeers,
+};
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::channel::oneshot;
+use futures::executor::block_on;
+use futures::{Stream, StreamExt};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+pub struct Node<V> {
+    config: NodeConfig,
+    members: Mutex<Vec<(Id, SocketAddr)>>,
+    detector: Arc<OmegaDetector>,
+    learner: Learner<V>,
+    peers: TcpPeers<V>,
+    quorum: SharedQuorum,
+    bootstrap: Arc<Mutex<BootstrapFile>>,
+    membership_log: TcpPeers<Membership>,
+    applied: Mutex<Applied>,
+    applied_file: Mutex<Option<RecordFile>>,
+    introduced: Mutex<HashMap<Id, SocketAddr>>,
+    last_heard: Mutex<HashMap<Id, (u64, Option<LogIndex>)>>,
+    changing: futures::lock::Mutex<()>,
+    epoch: Arc<AtomicU64>,
+    ticks: Ticks,
+    trail: Option<Mutex<DecisionTrail>>,
+    response_times: Option<Arc<ResponseTimes<SocketAddr>>>,
+    addr: SocketAddr,
+    stopped: Arc<AtomicBool>,
+    in_flight: Mutex<InFlight>,
+    idle: Mutex<Option<UnboundedReceiver<()>>>,
+    server: Mutex<Option<JoinHandle<io::Result<()>>>>,
+    heartbeats: Mutex<Option<JoinHandle<()>>>,
+    negotiation: Mutex<Option<(Sender<()>, JoinHandle<()>)>>,
+    ready: Arc<AtomicBool>,
+    recovery: Mutex<Option<(Sender<()>, JoinHandle<()>)>>,
+    client_tokens: Option<Arc<ReloadableTokens>>,
+    transfer: Mutex<TransferProgress>,
+    fence: Mutex<StorageFence>,
+}
+
+/// How far a joining node has come in taking on the state of the member
+/// it [transfers](Node::transfer_state) it from.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransferProgress {
+    /// The first slot of the membership log this node has not applied.
+    pub applied: LogIndex,
+    /// The first slot the member knew no decision for, when last asked.
+    pub end: LogIndex,
+    pub pages: u64,
+}
+
+impl TransferProgress {
+    pub fn done(&self) -> bool {
+        self.applied >= self.end
+    }
+}
+
+/// Everything this build can speak beyond the base protocol. Each feature is
+/// only used once every member has advertised it, so a cluster can be
+/// upgraded one node at a time.
+const FEATURES: Features = Features::COMPRESSION;
+
+/// Hands what clients and operators send the server to the node, once it
+/// is up.
+struct Handle<V>(OnceLock<Weak<Node<V>>>);
+
+impl<V> Handle<V> {
+    fn node(&self) -> Result<Arc<Node<V>>, Error> {
+        self.0
+            .get()
+            .and_then(Weak::upgrade)
+            .ok_or(Error::ProposerStopped)
+    }
+}
+
+impl<V> Proposals<V> for Handle<V>
+where
+    V: Clone + PartialEq + Encode + Decode + Send + Sync + 'static,
+{
+    fn propose(&self, value: V) -> Result<V, Error> {
+        block_on(self.node()?.propose(value))
+    }
+}
+
+impl<V> Reconfigure for Handle<V>
+where
+    V: Clone + PartialEq + Encode + Decode + Send + Sync + 'static,
+{
+    fn add_member(&self, id: Id, addr: SocketAddr) -> Result<Membership, Error> {
+        block_on(self.node()?.add_member(id, addr))
+    }
+
+    fn remove_member(&self, id: Id) -> Result<Membership, Error> {
+        block_on(self.node()?.remove_member(id))
+    }
+
+    fn introduce(&self, id: Id, addr: SocketAddr) {
+        if let Ok(node) = self.node() {
+            node.introduce(id, addr);
+        }
+    }
+
+    fn learned(&self, instance: InstanceId, membership: Membership) -> Result<(), Error> {
+        match self.node() {
+            Ok(node) => node.learn(LogIndex::new(instance.0), membership),
+            Err(_) => Ok(()),
+        }
+    }
+
+    fn members(&self) -> Result<Vec<Member>, Error> {
+        Ok(block_on(self.node()?.members()))
+    }
+
+    fn applied(&self) -> Result<Applied, Error> {
+        Ok(lock(&self.node()?.applied).clone())
+    }
+}
+
+/// A node's log is its membership log, so that is what its commit index
+/// counts.
+impl<V> Admin for Handle<V>
+where
+    V: Clone + PartialEq + Encode + Decode + Send + Sync + 'static,
+{
+    fn commit_index(&self) -> Option<LogIndex> {
+        self.node().ok()?.commit_index()
+    }
+
+    fn snapshot(&self) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "a node keeps no snapshots",
+        ))
+    }
+
+    fn backup(&self, to: &Path) -> io::Result<()> {
+        self.node()
+            .and_then(|node| node.backup(to))
+            .map_err(|error| io::Error::other(error.to_string()))
+    }
+
+    fn set_log_filter(&self, filter: &str) -> io::Result<()> {
+        #[cfg(feature = "tracing")]
+        return crate::log_filter::set(filter)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error));
+        #[cfg(not(feature = "tracing"))]
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("built without tracing, so `{filter}` cannot apply"),
+        ))
+    }
+
+    fn reload_credentials(&self) -> io::Result<()> {
+        self.node()
+            .map_err(|error| io::Error::other(error.to_string()))?
+            .reload_credentials()
+    }
+}
+
+/// Each slot of the membership log is an instance of its own.
+#[derive(Clone)]
+struct MembershipSlots(TcpPeers<Membership>);
+
+impl SlotPeers<Membership> for MembershipSlots {
+    type Peers = TcpPeers<Membership>;
+
+    fn slot(&self, index: LogIndex) -> Self::Peers {
+        self.0.instance(InstanceId(index.get()))
+    }
+
+    fn learned(
+        &self,
+        index: LogIndex,
+    ) -> Option<impl Stream<Item = Result<Option<Membership>, Error>>> {
+        Some(self.slot(index).decision())
+    }
+}
+
+enum Change {
+    Add(Id),
+    Remove(Id),
+}
+
+/// What a node shares with the server answering its peers.
+struct Shared<V> {
+    detector: Arc<OmegaDetector>,
+    learner: Learner<V>,
+    quorum: SharedQuorum,
+    stopped: Arc<AtomicBool>,
+    bootstrap: Arc<Mutex<BootstrapFile>>,
+    membership_log: Arc<InstanceAcceptors<Membership>>,
+    epoch: Arc<AtomicU64>,
+    ready: Arc<AtomicBool>,
+    fences: Arc<FenceLedger>,
+}
+
+struct Serving<V> {
+    server: JoinHandle<io::Result<()>>,
+    loopback: Option<Loopback>,
+    handle: Arc<Handle<V>>,
+    /// Whether the acceptor took part in a round before it restarted.
+    restored: bool,
+}
+
+struct InFlight {
+    tasks: Option<UnboundedSender<()>>,
+    handles: HashMap<u64, ProposeHandle>,
+    next: u64,
+}
+
+struct Task<'a> {
+    in_flight: &'a Mutex<InFlight>,
+    id: u64,
+    _tasks: UnboundedSender<()>,
+}
+
+impl Drop for Task<'_> {
+    fn drop(&mut self) {
+        lock(self.in_flight).handles.remove(&self.id);
+    }
+}
+
+impl<V> Node<V>
+where
+    V: Clone + PartialEq + Encode + Decode + Send + Sync + 'static,
+{
+    /// Starts serving at once; clients may propose through the server as
+    /// soon as this returns. A node it bootstrapped or joined before keeps
+    /// the cluster it belongs to.
+    pub fn start(config: NodeConfig) -> Result<Arc<Self>, Error> {
+        let config = open_state(config)?;
+        let bootstrap = BootstrapFile::open(bootstrap_path(&config))?;
+        Self::launch(config, bootstrap)
+    }
+
+    /// Forms a new cluster out of `config.members`, each of which must be
+    /// started this way or with [`start`](Self::start). Returns once every
+    /// member has persisted the same member set, retrying the ones that are
+    /// not up yet as the retry policy allows. Fails if this node, or any
+    /// member, already belongs to a different cluster.
+    pub async fn bootstrap(config: NodeConfig) -> Result<Arc<Self>, Error> {
+        let config = open_state(config)?;
+        let bootstrap = Bootstrap::new(config.members.iter().copied());
+        let mut file = BootstrapFile::open(bootstrap_path(&config))?;
+        file.claim(bootstrap.clone())?;
+        let node = Self::launch(config, file)?;
+        if let Err(error) = node.agree(bootstrap).await {
+            let _ = node.shutdown().await;
+            return Err(error);
+        }
+        Ok(node)
+    }
+
+    /// Starts a node that joins the cluster `existing` already formed,
+    /// adopting its bootstrap so this node can never bootstrap or join
+    /// another one. The node reaches `existing` but does not count towards
+    /// their quorum until the cluster adds it as a voter: with the config's
+    /// `join_token`, the leader that issued it does so before this returns;
+    /// without one, an operator has to.
+    pub async fn join(
+        config: NodeConfig,
+        existing: Vec<(Id, SocketAddr)>,
+    ) -> Result<Arc<Self>, Error> {
+        let mut config = open_state(config)?;
+        let peers = tcp_peers::<V>(&NodeConfig {
+            members: existing.clone(),
+            ..config.clone()
+        });
+        let epoch = peers
+            .status()
+            .filter_map(|status| async move { status.ok() })
+            .fold(0, |epoch, status| async move { epoch.max(status.epoch) })
+            .await;
+        let mut held: Option<Bootstrap> = None;
+        let mut errors = Vec::new();
+        let mut answers = peers.bootstrap(None);
+        while let Some(answer) = answers.next().await {
+            match answer {
+                Ok((_, Some(bootstrap))) => match &held {
+                    Some(held) if held.cluster() != bootstrap.cluster() => {
+                        return Err(BootstrapError::Split.into());
+                    }
+                    _ => held = Some(bootstrap),
+                },
+                Ok((_, None)) => {}
+                Err(error) => errors.push(error),
+            }
+        }
+        let bootstrap = match held {
+            Some(bootstrap) => bootstrap,
+            None if !existing.is_empty() && errors.len() == existing.len() => {
+                return Err(Error::QuorumUnreachable { errors });
+            }
+            None => return Err(BootstrapError::NotBootstrapped.into()),
+        };
+        let mut file = BootstrapFile::open(bootstrap_path(&config))?;
+        file.claim(bootstrap)?;
+        config.quorum.get_or_insert_with(|| QuorumSpec::Majority {
+            members: existing.iter().map(|(id, _)| *id).collect(),
+        });
+        config.members = existing;
+        if !config.members.iter().any(|(id, _)| *id == config.id) {
+            config.members.push((config.id, config.listen));
+        }
+        let node = Self::launch(config, file)?;
+        if epoch > node.epoch() {
+            node.enter(epoch)?;
+        }
+        if let Err(error) = node.transfer_state(&peers).await {
+            let _ = node.shutdown().await;
+            return Err(error);
+        }
+        if let Some(token) = node.config.join_token.clone() {
+            if let Err(error) = node.admit(&token, &peers).await {
+                let _ = node.shutdown().await;
+                return Err(error);
+            }
+        }
+        Ok(node)
+    }
+
+    /// Takes on the state a member of `existing` holds, so this node votes
+    /// knowing what the cluster decided: the membership the member applied
+    /// and its membership log decided since, the highest round its acceptor
+    /// entered, and the decision it learned. The log comes a page at a time,
+    /// each applied and kept before the next is asked for from where this
+    /// node got to, so a transfer cut short resumes there.
+    pub async fn transfer_state(&self, existing: &TcpPeers<V>) -> Result<TransferProgress, Error> {
+        let mut round = Round::default();
+        let progress = loop {
+            let from = lock(&self.applied).next;
+            let mut errors = Vec::new();
+            let mut pages = existing.state_transfer(from);
+            let page = loop {
+                match pages.next().await {
+                    Some(Ok(page)) => break page,
+                    Some(Err(error)) => errors.push(error),
+                    None => return Err(Error::QuorumUnreachable { errors }),
+                }
+            };
+            // Checked against the members this node joined through, which
+            // certified it, before the membership it takes on replaces them.
+            if let Some(certificate) = page.certificate {
+                let verified = certificate.verify(&self.quorum);
+                #[cfg(feature = "auth")]
+                let verified = verified.and_then(|()| match &self.config.keyring {
+                    Some(keyring) => certificate.verify_signatures(keyring),
+                    None => Ok(()),
+                });
+                if verified.is_ok() {
+                    self.learner.handle_decision(certificate);
+                }
+            }
+            if let Some(applied) = page.applied {
+                self.install(applied)?;
+            }
+            for (index, membership) in page.entries {
+                self.learn(index, membership)?;
+            }
+            round = round.max(page.last_round_entered);
+            let progress = {
+                let mut progress = lock(&self.transfer);
+                progress.applied = lock(&self.applied).next;
+                progress.end = page.end;
+                progress.pages += 1;
+                *progress
+            };
+            if progress.done() || progress.applied == from {
+                break progress;
+            }
+        };
+        // Entering the round refuses what proposers below it would still ask
+        // of this acceptor, as the member it took the round from does.
+        if round != Round::default() {
+            let own = tcp_peers::<V>(&NodeConfig {
+                members: vec![(self.config.id, self.addr)],
+                ..self.config.clone()
+            })
+            .with_epoch(self.epoch.clone());
+            own.read(round)
+                .next()
+                .await
+                .ok_or(Error::NotCaughtUp(self.config.id))??;
+        }
+        Ok(progress)
+    }
+
+    /// How far [`transfer_state`](Self::transfer_state) got.
+    pub fn transfer_progress(&self) -> TransferProgress {
+        *lock(&self.transfer)
+    }
+
+    /// Has the leader among `existing` add this node as a voter with the
+    /// join token it issued.
+    async fn admit(&self, token: &str, existing: &TcpPeers<V>) -> Result<Membership, Error> {
+        let addr = self
+            .addresses()
+            .into_iter()
+            .find(|(id, _)| *id == self.config.id)
+            .map_or(self.addr, |(_, addr)| addr);
+        let mut answers = existing.join(token, self.config.id, addr);
+        let mut errors = Vec::new();
+        while let Some(answer) = answers.next().await {
+            match answer {
+                Ok(Proposed::Decided(membership)) => return Ok(membership),
+                Ok(Proposed::NotLeader(_) | Proposed::Throttled(_)) => {}
+                Err(error) => errors.push(error),
+            }
+        }
+        Err(errors.pop().unwrap_or(Error::NotLeader))
+    }
+
+    /// Rewrites the membership this stopped node starts with to `members`,
+    /// for when most of the cluster is lost for good and no quorum can ever
+    /// form again. **This is unsafe**: whatever the lost members decided
+    /// without this node may be forgotten and decided differently.
+    ///
+    /// Run it on every survivor with the same `members`, then start them.
+    /// Each enters the next epoch, which this returns, and the survivors
+    /// refuse consensus requests from members of any other epoch, so a lost
+    /// member that comes back cannot take part until it rejoins.
+    pub fn force_recover(
+        config: &NodeConfig,
+        members: Vec<(Id, SocketAddr)>,
+    ) -> Result<u64, Error> {
+        let config = &open_state(config.clone())?;
+        if !members.iter().any(|(id, _)| *id == config.id) {
+            return Err(Error::RecoveryRefused(
+                "this node must be one of the new members",
+            ));
+        }
+        let Some(mut file) = membership_path(config, "members").map(RecordFile::new) else {
+            return Err(Error::RecoveryRefused(
+                "nothing survives without a storage path",
+            ));
+        };
+        let epoch = match file.recover()? {
+            Some(bytes) => from_bytes::<Applied>(&bytes)?.epoch,
+            None => 0,
+        } + 1;
+        // The membership log restarts with the new epoch; what it holds was
+        // decided by members that are gone.
+        if let Some(log) = membership_path(config, "membership") {
+            match std::fs::remove_file(log) {
+                Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error.into()),
+                _ => {}
+            }
+        }
+        let recovered = Applied {
+            next: LogIndex::default(),
+            membership: Membership::Stable(Configuration::new(members.iter().map(|(id, _)| *id))),
+            members,
+            epoch,
+        };
+        file.append(&to_bytes(&recovered))?;
+        if let Some(path) = membership_path(config, "fence") {
+            StorageFence::open(path)?.reinstate()?;
+        }
+        Ok(epoch)
+    }
+
+    /// Rebuilds the state of this stopped node from a [`backup`](Self::backup)
+    /// taken of it, into a data directory or storage path that holds none.
+    /// The node enters the epoch after the one it was backed up in, which
+    /// this returns: nodes restored from backups taken in the same epoch
+    /// form a cluster again, while one restored on its own is fenced off
+    /// from members that kept running until it rejoins, since the promises
+    /// it made after the backup are lost.
+    pub fn restore(config: &NodeConfig, from: &Path) -> Result<u64, Error> {
+        let backup = DataDir::open(from)?;
+        let mut config = config.clone();
+        if let Some(dir) = config.data_dir.take() {
+            config.storage_path = Some(DataDir::init(dir, None)?.storage_path());
+        }
+        let Some(target) = config.storage_path.clone() else {
+            return Err(Error::RecoveryRefused(
+                "nothing can be restored without a storage path",
+            ));
+        };
+        if identity::beside(&backup.storage_path()).exists() {
+            backup.identity(Some(config.id))?;
+        }
+        data_dir::copy_state(&backup.storage_path(), &target)?;
+        identity::assign(identity::beside(&target), Some(config.id))?;
+
+        let mut file = RecordFile::new(target.with_extension("members"));
+        let fenced = match file.recover()? {
+            Some(bytes) => {
+                let applied = from_bytes::<Applied>(&bytes)?;
+                Applied {
+                    epoch: applied.epoch + 1,
+                    ..applied
+                }
+            }
+            None => {
+                let bootstrap = BootstrapFile::open(bootstrap_path(&config))?;
+                let members = match bootstrap.adopted() {
+                    Some(bootstrap) => bootstrap.members().to_vec(),
+                    None => config.members.clone(),
+                };
+                Applied {
+                    next: LogIndex::default(),
+                    membership: Membership::Stable(Configuration::new(
+                        members.iter().map(|(id, _)| *id),
+                    )),
+                    members,
+                    epoch: 1,
+                }
+            }
+        };
+        file.append(&to_bytes(&fenced))?;
+        StorageFence::open(target.with_extension("fence"))?.reinstate()?;
+        Ok(fenced.epoch)
+    }
+
+    /// Copies this running node's state into a new data directory at `to`,
+    /// for [`restore`](Self::restore) to rebuild it from.
+    pub fn backup(&self, to: &Path) -> Result<(), Error> {
+        let Some(path) = &self.config.storage_path else {
+            return Err(Error::RecoveryRefused(
+                "a node without a storage path keeps nothing to back up",
+            ));
+        };
+        {
+            // Holding the applied membership keeps it from moving on mid-copy.
+            let _applied = lock(&self.applied_file);
+            DataDir::copy(path, to)?;
+        }
+        // The copy keeps the fence it was taken at, which members hear this
+        // node move past, so a node started on the copy by hand is refused.
+        announce_fence(
+            &self.config,
+            self.peers.clone(),
+            &mut lock(&self.fence),
+            self.epoch(),
+        )
+    }
+
+    /// Takes on the keyring and client tokens the config's
+    /// [`credentials`](NodeConfig::credentials) now hold. Connections stay
+    /// up: every message is authenticated on its own, so the next one is
+    /// sealed and checked with the new keys.
+    pub fn reload_credentials(&self) -> io::Result<()> {
+        let unsupported =
+            |message: &str| io::Error::new(io::ErrorKind::Unsupported, message.to_string());
+        let source =
+            self.config.credentials.as_ref().ok_or_else(|| {
+                unsupported("the node was given nowhere to reload credentials from")
+            })?;
+        let credentials = source
+            .load()
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+        #[cfg(feature = "auth")]
+        let keyring = match (&credentials.keyring, &self.config.keyring) {
+            (Some(next), Some(keyring)) => Some((next, keyring)),
+            (Some(_), None) => {
+                return Err(unsupported(
+                    "a node started without a keyring takes one only on restart",
+                ))
+            }
+            (None, _) => None,
+        };
+        let client_tokens = match (credentials.client_tokens, &self.client_tokens) {
+            (Some(next), Some(tokens)) => Some((next, tokens)),
+            (Some(_), None) => {
+                return Err(unsupported(
+                    "a node started without client tokens takes them only on restart",
+                ))
+            }
+            (None, _) => None,
+        };
+        #[cfg(feature = "auth")]
+        if let Some((next, keyring)) = keyring {
+            keyring.rotate(next);
+        }
+        if let Some((next, tokens)) = client_tokens {
+            tokens.reload(next);
+        }
+        Ok(())
+    }
+
+    fn launch(mut config: NodeConfig, bootstrap: BootstrapFile) -> Result<Arc<Self>, Error> {
+        let client_tokens = config
+            .client_tokens
+            .take()
+            .map(|tokens| Arc::new(ReloadableTokens::new(tokens)));
+        config.client_tokens = client_tokens
+            .clone()
+            .map(|tokens| tokens as Arc<dyn TokenVerifier>);
+        // The membership log starts from the members the cluster was formed
+        // of, and a node that applied changes from it before keeps them.
+        let genesis = match bootstrap.adopted() {
+            Some(bootstrap) => Configuration::new(bootstrap.members().iter().map(|(id, _)| *id)),
+            None => Configuration::new(config.members.iter().map(|(id, _)| *id)),
+        };
+        let mut applied_file = membership_path(&config, "members").map(RecordFile::new);
+        let stored: Option<Applied> = match &mut applied_file {
+            Some(file) => file
+                .recover()?
+                .map(|bytes| from_bytes(&bytes))
+                .transpose()?,
+            None => None,
+        };
+        let changed = stored.is_some();
+        let applied = match stored {
+            Some(applied) => {
+                config.members = applied.members.clone();
+                applied
+            }
+            None => Applied {
+                next: LogIndex::default(),
+                membership: Membership::Stable(genesis),
+                members: config.members.clone(),
+                epoch: 0,
+            },
+        };
+        let log_store = match membership_path(&config, "membership") {
+            Some(path) => InstanceStore::open(path)?,
+            None => InstanceStore::in_memory(),
+        };
+        let fences = match membership_path(&config, "fences") {
+            Some(path) => FenceLedger::open(path)?,
+            None => FenceLedger::in_memory(),
+        };
+        // Only storage kept across starts can be put back from a copy. The
+        // members are told before this node listens, so it votes only once
+        // its storage is known to be current.
+        let fence = match membership_path(&config, "fence") {
+            Some(path) => {
+                let mut fence = StorageFence::open(path)?;
+                let others = NodeConfig {
+                    members: config
+                        .members
+                        .iter()
+                        .filter(|(id, _)| *id != config.id)
+                        .copied()
+                        .collect(),
+                    ..config.clone()
+                };
+                announce_fence(&config, tcp_peers::<V>(&others), &mut fence, applied.epoch)?;
+                fence
+            }
+            None => StorageFence::in_memory(),
+        };
+
+        let listener = TcpListener::bind(config.listen)?;
+        let addr = listener.local_addr()?;
+        let mut detector = OmegaDetector::new(
+            config.id,
+            config.members.iter().map(|(id, _)| *id),
+            config.failure_timeout,
+        );
+        if let Some(policy) = config.adaptive_timeout {
+            detector = detector.with_adaptive_timeout(AdaptiveTimeout {
+                min: policy.min.max(config.heartbeat_interval * 2),
+                ..policy
+            });
+        }
+        let shared = Shared {
+            detector: Arc::new(detector),
+            learner: Learner::default(),
+            quorum: SharedQuorum::new(config.quorum_spec()),
+            stopped: Arc::new(AtomicBool::new(false)),
+            bootstrap: Arc::new(Mutex::new(bootstrap)),
+            membership_log: Arc::new(InstanceAcceptors::new(config.id, log_store)),
+            epoch: Arc::new(AtomicU64::new(applied.epoch)),
+            ready: Arc::new(AtomicBool::new(false)),
+            fences: Arc::new(fences),
+        };
+        if changed {
+            shared.quorum.set(applied.membership.clone());
+        }
+
+        let Serving {
+            server,
+            loopback,
+            handle,
+            restored,
+        } = match &config.storage_path {
+            Some(path) => serve(&config, FileStorage::new(path), listener, &shared)?,
+            None => serve(&config, MemoryStorage::default(), listener, &shared)?,
+        };
+        let response_times = config
+            .adaptive_timeout
+            .map(|policy| Arc::new(ResponseTimes::new(policy)));
+        let mut peers = tcp_peers::<V>(&config).with_epoch(shared.epoch.clone());
+        if let Some(loopback) = loopback {
+            peers = peers.with_loopback(loopback);
+        }
+        if let Some(response_times) = &response_times {
+            peers = peers.with_response_times(response_times.clone());
+        }
+        if config.fastest_quorum {
+            peers = peers.with_fastest_quorum();
+        }
+        let mut ticks = Ticks::new(config.tick_source);
+        if let Some(path) = &config.storage_path {
+            ticks = ticks.storage(FileStorage::<V>::new(path))?;
+        }
+        let trail = match &config.decision_trail {
+            Some(path) => Some(Mutex::new(DecisionTrail::open(path)?)),
+            None => None,
+        };
+        let Shared {
+            detector,
+            learner,
+            quorum,
+            stopped,
+            bootstrap,
+            membership_log: _,
+            epoch,
+            ready,
+            fences: _,
+        } = shared;
+        let heartbeats = detector.spawn_heartbeats(peers.clone(), config.heartbeat_interval);
+        let negotiation = negotiate(peers.clone(), config.failure_timeout);
+        let recovery = restored.then(|| {
+            recover(
+                &config,
+                peers.clone(),
+                learner.clone(),
+                quorum.clone(),
+                ready.clone(),
+            )
+        });
+        let membership_log = peers.membership_log();
+        let (tasks, idle) = unbounded();
+
+        let node = Arc::new(Self {
+            members: Mutex::new(config.members.clone()),
+            config,
+            detector,
+            learner,
+            peers,
+            quorum,
+            bootstrap,
+            membership_log,
+            applied: Mutex::new(applied),
+            applied_file: Mutex::new(applied_file),
+            introduced: Mutex::new(HashMap::new()),
+            last_heard: Mutex::new(HashMap::new()),
+            changing: futures::lock::Mutex::new(()),
+            epoch,
+            ticks,
+            trail,
+            response_times,
+            addr,
+            stopped,
+            in_flight: Mutex::new(InFlight {
+                tasks: Some(tasks),
+                handles: HashMap::new(),
+                next: 0,
+            }),
+            idle: Mutex::new(Some(idle)),
+            server: Mutex::new(Some(server)),
+            heartbeats: Mutex::new(Some(heartbeats)),
+            negotiation: Mutex::new(Some(negotiation)),
+            ready,
+            recovery: Mutex::new(recovery),
+            client_tokens,
+            transfer: Mutex::default(),
+            fence: Mutex::new(fence),
+        });
+        let _ = handle.0.set(Arc::downgrade(&node));
+        Ok(node)
+    }
+
+    pub fn id(&self) -> Id {
+        self.config.id
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The cluster this node was bootstrapped into or joined, if any.
+    pub fn cluster(&self) -> Option<ClusterId> {
+        lock(&self.bootstrap).adopted().map(Bootstrap::cluster)
+    }
+
+    pub fn response_times(&self) -> Option<&ResponseTimes<SocketAddr>> {
+        self.response_times.as_deref()
+    }
+
+    pub fn decision(&self) -> Option<V> {
+        self.learner.decision()
+    }
+
+    pub async fn await_decision(&self) -> V {
+        self.learner.await_decision().await
+    }
+
+    pub fn certificate(&self) -> Option<DecisionCertificate<V>> {
+        self.learner.certificate()
+    }
+
+    /// Whether this node serves clients yet. A node whose acceptor recovered
+    /// state from before a restart holds off until it has learned what was
+    /// decided while it was down, or until a read quorum confirms nothing
+    /// was, so it never answers with a stale decision.
+    pub fn ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    /// The features every member currently agrees on.
+    pub fn negotiated_features(&self) -> Features {
+        self.peers.negotiated()
+    }
+
+    pub fn leadership_changes(&self) -> impl Stream<Item = Id> {
+        self.detector.leadership_changes()
+    }
+
+    /// Where each member this node knows of listens.
+    pub fn addresses(&self) -> Vec<(Id, SocketAddr)> {
+        lock(&self.members).clone()
+    }
+
+    /// Every member this node knows of, whether it votes, and the epoch and
+    /// commit index it reports. Members that do not answer are listed with
+    /// what they last reported.
+    pub async fn members(&self) -> Vec<Member> {
+        let mut answered = HashSet::new();
+        let mut statuses = self.peers.status();
+        while let Some(status) = statuses.next().await {
+            if let Ok(status) = status {
+                answered.insert(status.id);
+                lock(&self.last_heard).insert(status.id, (status.epoch, status.commit_index));
+            }
+        }
+        let voters = self.membership().voters();
+        let leader = self.detector.leader();
+        let last_heard = lock(&self.last_heard);
+        self.addresses()
+            .into_iter()
+            .map(|(id, addr)| {
+                let heard = last_heard.get(&id);
+                Member {
+                    id,
+                    addr,
+                    role: match voters.contains(&id) {
+                        true => Role::Voter,
+                        false => Role::Learner,
+                    },
+                    leader: id == leader,
+                    reachable: answered.contains(&id),
+                    epoch: heard.map(|(epoch, _)| *epoch),
+                    commit_index: heard.and_then(|(_, commit_index)| *commit_index),
+                }
+            })
+            .collect()
+    }
+
+    /// The last slot of the membership log this node applied.
+    fn commit_index(&self) -> Option<LogIndex> {
+        let next = lock(&self.applied).next.get();
+        next.checked_sub(1).map(LogIndex::new)
+    }
+
+    /// Bumped by [`force_recover`](Self::force_recover); only members of
+    /// the same epoch take part in consensus with each other.
+    pub fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::Acquire)
+    }
+
+    /// The membership this node last applied from the membership log.
+    pub fn membership(&self) -> Membership {
+        lock(&self.applied).membership.clone()
+    }
+
+    /// Adds `id`, listening at `addr`, as a voter, through a joint
+    /// configuration decided on the membership log. Only the leader changes
+    /// the membership. The joiner must already have joined this cluster and
+    /// must have learned what this node has decided, so it is handed this
+    /// node's decision first; otherwise fails with [`Error::NotCaughtUp`].
+    pub async fn add_member(
+        self: &Arc<Self>,
+        id: Id,
+        addr: SocketAddr,
+    ) -> Result<Membership, Error> {
+        let _changing = self.changing.lock().await;
+        self.catch_up(id, addr).await?;
+        self.introduce(id, addr);
+        self.peers.introduce(id, addr).count().await;
+        let mut members = self.addresses();
+        if !members.iter().any(|(member, _)| *member == id) {
+            members.push((id, addr));
+            self.peers.reconfigure(members);
+        }
+        self.change(Change::Add(id)).await
+    }
+
+    /// Removes `id` as a voter, unless fewer than a majority of the voters
+    /// before or after would be left that this node still hears from; that
+    /// fails with [`Error::QuorumAtRisk`].
+    pub async fn remove_member(self: &Arc<Self>, id: Id) -> Result<Membership, Error> {
+        let _changing = self.changing.lock().await;
+        let current = match self.membership() {
+            Membership::Stable(configuration)
+            | Membership::Joint {
+                new: configuration, ..
+            } => configuration.voters().clone(),
+        };
+        if current.contains(&id) {
+            // Members that left are no longer watched at all.
+            let (watched, suspected) = (self.detector.members(), self.detector.suspected());
+            let mut remaining = current.clone();
+            remaining.remove(&id);
+            let quorate = |voters: &BTreeSet<Id>| {
+                let live = voters
+                    .iter()
+                    .filter(|voter| watched.contains(voter) && !suspected.contains(voter))
+                    .count();
+                live > voters.len() / 2
+            };
+            if remaining.is_empty() || !quorate(&current) || !quorate(&remaining) {
+                return Err(Error::QuorumAtRisk(id));
+            }
+        }
+        self.change(Change::Remove(id)).await
+    }
+
+    /// Remembers where a member about to be added listens, so this node can
+    /// reach it once the membership log adds it.
+    fn introduce(&self, id: Id, addr: SocketAddr) {
+        lock(&self.introduced).insert(id, addr);
+    }
+
+    /// Applies the membership the log decided at `index`, unless this node
+    /// applied a later one already.
+    fn learn(&self, index: LogIndex, membership: Membership) -> Result<(), Error> {
+        let mut applied = lock(&self.applied);
+        if index < applied.next {
+            return Ok(());
+        }
+        let known: HashMap<Id, SocketAddr> = {
+            let introduced = lock(&self.introduced);
+            let members = lock(&self.members);
+            introduced
+                .iter()
+                .chain(members.iter().map(|(id, addr)| (id, addr)))
+                .map(|(id, addr)| (*id, *addr))
+                .collect()
+        };
+        let members: Vec<_> = membership
+            .voters()
+            .into_iter()
+            .filter_map(|voter| known.get(&voter).map(|addr| (voter, *addr)))
+            .collect();
+        let next = Applied {
+            next: index.next(),
+            membership,
+            members,
+            epoch: self.epoch(),
+        };
+        if let Some(file) = lock(&self.applied_file).as_mut() {
+            file.append(&to_bytes(&next))?;
+        }
+        self.reconfigure(&next.membership, next.members.clone());
+        *applied = next;
+        Ok(())
+    }
+
+    /// Applies the membership a member applied, with every address it knows,
+    /// unless this node applied as far already.
+    fn install(&self, snapshot: Applied) -> Result<(), Error> {
+        let mut applied = lock(&self.applied);
+        if snapshot.next <= applied.next {
+            return Ok(());
+        }
+        let installed = Applied {
+            epoch: snapshot.epoch.max(self.epoch()),
+            ..snapshot
+        };
+        if let Some(file) = lock(&self.applied_file).as_mut() {
+            file.append(&to_bytes(&installed))?;
+        }
+        self.epoch.store(installed.epoch, Ordering::Release);
+        self.reconfigure(&installed.membership, installed.members.clone());
+        *applied = installed;
+        Ok(())
+    }
+
+    /// Moves a node that just joined into the epoch its cluster is in.
+    fn enter(&self, epoch: u64) -> Result<(), Error> {
+        let mut applied = lock(&self.applied);
+        let entered = Applied {
+            epoch,
+            ..applied.clone()
+        };
+        if let Some(file) = lock(&self.applied_file).as_mut() {
+            file.append(&to_bytes(&entered))?;
+        }
+        self.epoch.store(epoch, Ordering::Release);
+        *applied = entered;
+        Ok(())
+    }
+
+    /// Hands a would-be voter this node's decision, and fails unless it
+    /// belongs to the same cluster and has learned it.
+    async fn catch_up(&self, id: Id, addr: SocketAddr) -> Result<(), Error> {
+        let joiner = tcp_peers::<V>(&NodeConfig {
+            members: vec![(id, addr)],
+            ..self.config.clone()
+        })
+        .with_epoch(self.epoch.clone());
+        let (member, held) = joiner
+            .bootstrap(None)
+            .next()
+            .await
+            .ok_or(Error::NotCaughtUp(id))??;
+        let theirs = held.as_ref().map(Bootstrap::cluster);
+        match (self.cluster(), theirs) {
+            _ if member != id => return Err(Error::NotCaughtUp(id)),
+            (Some(ours), Some(theirs)) if ours != theirs => {
+                let cluster = theirs;
+                return Err(BootstrapError::Claimed { member, cluster }.into());
+            }
+            (Some(_), None) => return Err(Error::NotCaughtUp(id)),
+            _ => {}
+        }
+        if let Some(certificate) = self.learner.certificate() {
+            let _ = joiner.decide(certificate).next().await;
+        }
+        let status = joiner
+            .status()
+            .next()
+            .await
+            .ok_or(Error::NotCaughtUp(id))??;
+        if status.epoch != self.epoch() || self.learner.decision().is_some() && !status.decided {
+            return Err(Error::NotCaughtUp(id));
+        }
+        Ok(())
+    }
+
+    /// Decides `change` on the membership log, catching up with whatever
+    /// other leaders decided there in the meantime.
+    async fn change(self: &Arc<Self>, change: Change) -> Result<Membership, Error> {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let mut cluster = self.cluster_log();
+            let changed = match change {
+                Change::Add(id) => cluster.add_node(id).await,
+                Change::Remove(id) => cluster.remove_node(id).await,
+            };
+            match changed.cloned() {
+                Err(Error::MembershipConflict) if !self.config.retry_policy.exhausted(attempts) => {
+                }
+                changed => return changed,
+            }
+        }
+    }
+
+    fn cluster_log(self: &Arc<Self>) -> Cluster<MembershipSlots, Arc<OmegaDetector>> {
+        let Applied {
+            next, membership, ..
+        } = lock(&self.applied).clone();
+        let node = Arc::downgrade(self);
+        let mut index = next;
+        let slots = MembershipSlots(self.membership_log.clone());
+        Cluster::new(
+            self.config.id,
+            slots,
+            self.detector.clone(),
+            Configuration::default(),
+        )
+        .resume(next, membership)
+        .retry_policy(self.config.retry_policy.clone())
+        .ticks(self.ticks.clone())
+        .on_commit(move |membership| {
+            if let Some(node) = node.upgrade() {
+                let _ = node.learn(index, membership.clone());
+            }
+            index = index.next();
+        })
+    }
+
+    /// Points the transport, failure detector and quorum at a newly
+    /// committed configuration, e.g. from [`Cluster::on_commit`]. `members`
+    /// holds the address of every voter on either side; while `membership` is
+    /// joint, proposals and decisions need a majority of each side. This
+    /// replaces any quorum the node was started with.
+    ///
+    /// [`Cluster::on_commit`]: crate::membership::Cluster::on_commit
+    pub fn reconfigure(&self, membership: &Membership, members: Vec<(Id, SocketAddr)>) {
+        self.peers.reconfigure(members.iter().copied());
+        self.detector.set_members(members.iter().map(|(id, _)| *id));
+        self.quorum.set(membership.clone());
+        *lock(&self.members) = members;
+    }
+
+    pub async fn propose(&self, value: V) -> Result<V, Error> {
+        if let Some(decision) = self.learner.decision() {
+            return Ok(decision);
+        }
+        let peers = WithQuorum::from_quorum(self.peers.clone(), self.quorum.clone());
+        let mut builder = Proposer::builder(self.config.id, peers, self.detector.clone())
+            .retry_policy(self.config.retry_policy.clone())
+            .ticks(self.ticks.clone());
+        if let Some(stage_timeout) = self.config.stage_timeout {
+            builder = builder.stage_timeout(stage_timeout);
+        }
+        if let Some(response_times) = &self.response_times {
+            builder = builder.timeouts(response_times.clone());
+        }
+        let mut proposer = builder.build();
+        let (handle, proposal) = proposer.propose_cancellable(value);
+        let _task = self.track(handle)?;
+        let decided = proposal.await?;
+        if let Some(certificate) = proposer.certificate() {
+            if let Some(trail) = &self.trail {
+                lock(trail).record(self.config.instance.0, certificate)?;
+            }
+            self.learner.handle_decision(certificate.clone());
+        }
+        Ok(decided)
+    }
+
+    pub async fn shutdown(&self) -> Result<(), Error> {
+        let handles: Vec<_> = {
+            let mut in_flight = lock(&self.in_flight);
+            in_flight.tasks = None;
+            in_flight
+                .handles
+                .drain()
+                .map(|(_, handle)| handle)
+                .collect()
+        };
+        for handle in handles {
+            handle.cancel();
+        }
+        let idle = lock(&self.idle).take();
+        if let Some(mut idle) = idle {
+            idle.next().await;
+        }
+
+        self.detector.stop();
+        let heartbeats = lock(&self.heartbeats).take();
+        if let Some(heartbeats) = heartbeats {
+            join(heartbeats).await;
+        }
+        let negotiation = lock(&self.negotiation).take();
+        if let Some((stop, negotiation)) = negotiation {
+            drop(stop);
+            join(negotiation).await;
+        }
+        let recovery = lock(&self.recovery).take();
+        if let Some((stop, recovery)) = recovery {
+            drop(stop);
+            join(recovery).await;
+        }
+
+        self.stopped.store(true, Ordering::Release);
+        let _ = TcpStream::connect(self.addr);
+        let server = lock(&self.server).take();
+        if let Some(server) = server {
+            if let Some(served) = join(server).await {
+                served?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Offers `bootstrap` to every member until each has persisted it.
+    async fn agree(&self, bootstrap: Bootstrap) -> Result<(), Error> {
+        let cluster = bootstrap.cluster();
+        let mut pending: BTreeSet<Id> = bootstrap.members().iter().map(|(id, _)| *id).collect();
+        let mut rng = XorShift::new(self.config.id.get());
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let mut last = None;
+            let mut answers = self.peers.bootstrap(Some(bootstrap.clone()));
+            while let Some(answer) = answers.next().await {
+                match answer {
+                    Ok((member, Some(held))) if held.cluster() == cluster => {
+                        pending.remove(&member);
+                    }
+                    Ok((member, Some(held))) => {
+                        let cluster = held.cluster();
+                        return Err(BootstrapError::Claimed { member, cluster }.into());
+                    }
+                    Ok((_, None)) => {}
+                    Err(error) => last = Some(error),
+                }
+            }
+            if pending.is_empty() {
+                return Ok(());
+            }
+            if self.config.retry_policy.exhausted(attempts) {
+                return Err(Error::RetriesExhausted {
+                    attempts,
+                    last: last.map(Box::new),
+                });
+            }
+            let backoff = self.config.retry_policy.backoff(attempts, &mut rng);
+            SystemClock.sleep(backoff).await;
+        }
+    }
+
+    fn track(&self, handle: ProposeHandle) -> Result<Task<'_>, Error> {
+        let mut in_flight = lock(&self.in_flight);
+        let tasks = in_flight.tasks.clone().ok_or(Error::ProposerStopped)?;
+        let id = in_flight.next;
+        in_flight.next += 1;
+        in_flight.handles.insert(id, handle);
+        Ok(Task {
+            in_flight: &self.in_flight,
+            id,
+            _tasks: tasks,
+        })
+    }
+}
+
+async fn join<T: Send + 'static>(handle: JoinHandle<T>) -> Option<T> {
+    let (sender, receiver) = oneshot::channel();
+    thread::spawn(move || {
+        let _ = sender.send(handle.join());
+    });
+    receiver.await.ok()?.ok()
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Re-runs the feature handshake every `interval`, so features turn on once
+/// the last member is upgraded and off again if one is rolled back.
+fn negotiate<V>(peers: TcpPeers<V>, interval: Duration) -> (Sender<()>, JoinHandle<()>)
+where
+    V: Encode + Decode + Send + Sync + 'static,
+{
+    let (stop, stopped) = mpsc::channel();
+    let negotiation = thread::spawn(move || loop {
+        let _ = block_on(peers.handshake());
+        if stopped.recv_timeout(interval) != Err(RecvTimeoutError::Timeout) {
+            return;
+        }
+    });
+    (stop, negotiation)
+}
+
+/// Asks the members what they learned until this node catches up, backing
+/// off as the retry policy says but never giving up, then marks it ready.
+fn recover<V>(
+    config: &NodeConfig,
+    peers: TcpPeers<V>,
+    learner: Learner<V>,
+    quorum: SharedQuorum,
+    ready: Arc<AtomicBool>,
+) -> (Sender<()>, JoinHandle<()>)
+where
+    V: Clone + Encode + Decode + Send + Sync + 'static,
+{
+    let policy = config.retry_policy.clone();
+    let mut rng = XorShift::new(config.id.get());
+    #[cfg(feature = "auth")]
+    let keyring = config.keyring.clone();
+    let (stop, stopped) = mpsc::channel();
+    let recovery = thread::spawn(move || {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let caught_up = block_on(async {
+                let mut answered = HashSet::new();
+                let mut answers = peers.certificate();
+                while let Some(answer) = answers.next().await {
+                    let Ok((id, certificate)) = answer else {
+                        continue;
+                    };
+                    answered.insert(id);
+                    let Some(certificate) = certificate else {
+                        continue;
+                    };
+                    let verified = certificate.verify(&quorum);
+                    #[cfg(feature = "auth")]
+                    let verified = verified.and_then(|()| match &keyring {
+                        Some(keyring) => certificate.verify_signatures(keyring),
+                        None => Ok(()),
+                    });
+                    if verified.is_ok() {
+                        learner.handle_decision(certificate);
+                        return true;
+                    }
+                }
+                learner.decision().is_some() || quorum.is_read_quorum(&answered)
+            });
+            if caught_up {
+                ready.store(true, Ordering::Release);
+                return;
+            }
+            let backoff = policy.backoff(attempts, &mut rng);
+            if stopped.recv_timeout(backoff) != Err(RecvTimeoutError::Timeout) {
+                return;
+            }
+        }
+    });
+    (stop, recovery)
+}
+
+fn tcp_peers<V>(config: &NodeConfig) -> TcpPeers<V>
+where
+    V: Encode + Decode + Send + Sync + 'static,
+{
+    #[cfg_attr(not(feature = "auth"), allow(unused_mut))]
+    let mut peers = TcpPeers::new(config.members.iter().map(|(_, addr)| *addr).collect())
+        .with_max_message_size(config.max_message_size)
+        .with_features(FEATURES);
+    #[cfg(feature = "auth")]
+    if let Some(keyring) = &config.keyring {
+        let ids = config.members.iter().map(|(id, _)| *id).collect();
+        peers = peers.with_auth(keyring.clone(), ids);
+    }
+    peers
+}
+
+/// Keeps the node's state in its data directory, if it has one, and fails
+/// if that state belongs to a node with another id; the first start stores
+/// this one.
+fn open_state(mut config: NodeConfig) -> Result<NodeConfig, Error> {
+    if let Some(dir) = config.data_dir.take() {
+        config.storage_path = Some(DataDir::open(dir)?.storage_path());
+    }
+    if let Some(path) = &config.storage_path {
+        identity::assign(identity::beside(path), Some(config.id))?;
+    }
+    Ok(config)
+}
+
+/// Where a node keeps the bootstrap it adopted, next to its acceptor state.
+fn bootstrap_path(config: &NodeConfig) -> Option<PathBuf> {
+    membership_path(config, "cluster")
+}
+
+/// Counts a start or backup on the node's storage fence and tells `peers`
+/// the new fence. Fails if one heard of as high a fence from this node
+/// before, since its storage is then a copy older than what it ran with,
+/// and keeps the copy refused. Members that cannot be reached are not
+/// waited for.
+fn announce_fence<V>(
+    config: &NodeConfig,
+    peers: TcpPeers<V>,
+    fence: &mut StorageFence,
+    epoch: u64,
+) -> Result<(), Error>
+where
+    V: Encode + Decode + Send + Sync + 'static,
+{
+    let id = config.id;
+    let stale = |seen| Error::StaleStorage { id, seen };
+    let count = fence.advance().map_err(|error| match error {
+        FenceError::Stale { seen } => stale(seen),
+        FenceError::Io(error) => error.into(),
+    })?;
+    let announced = Fence { epoch, count };
+    // Announced from a thread of its own, as callers may be running in an
+    // executor already.
+    let seen = thread::spawn(move || {
+        block_on(
+            Box::pin(
+                peers
+                    .announce_fence(id, announced)
+                    .filter_map(|answer| async move { answer.ok().flatten() }),
+            )
+            .next(),
+        )
+    })
+    .join()
+    .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+    match seen {
+        Some(seen) => {
+            fence.refuse(seen)?;
+            Err(stale(seen))
+        }
+        None => Ok(()),
+    }
+}
+
+fn membership_path(config: &NodeConfig, extension: &str) -> Option<PathBuf> {
+    config
+        .storage_path
+        .as_ref()
+        .map(|path| path.with_extension(extension))
+}
+
+fn serve<V, S>(
+    config: &NodeConfig,
+    storage: S,
+    listener: TcpListener,
+    shared: &Shared<V>,
+) -> Result<Serving<V>, Error>
+where
+    V: Clone + PartialEq + Encode + Decode + Send + Sync + 'static,
+    S: Storage<V> + Send + 'static,
+{
+    let acceptor = Acceptor::new(config.id, storage)?;
+    let restored = {
+        let state = acceptor.state();
+        state.last_round_entered != Round::default() || state.value.is_some()
+    };
+    shared.ready.store(!restored, Ordering::Release);
+    let handle = Arc::new(Handle(OnceLock::new()));
+    let mut server = Server::new(Arc::new(Mutex::new(acceptor)))
+        .with_detector(shared.detector.clone())
+        .with_learner(shared.learner.clone())
+        .with_quorum(shared.quorum.clone())
+        .with_proposals(handle.clone())
+        .with_membership(shared.membership_log.clone(), handle.clone())
+        .with_admin(handle.clone())
+        .with_bootstrap(shared.bootstrap.clone())
+        .with_epoch(shared.epoch.clone())
+        .with_readiness(shared.ready.clone())
+        .with_fences(shared.fences.clone())
+        .with_shutdown(shared.stopped.clone())
+        .with_max_message_size(config.max_message_size)
+        .with_features(FEATURES);
+    if let Some(tokens) = &config.client_tokens {
+        server = server.with_tokens(tokens.clone());
+    }
+    server = server.with_join_tokens(Arc::new(JoinTokens::new(Arc::new(SystemClock))));
+    if let Some(quotas) = &config.client_quotas {
+        server = server.with_rate_limiter(Arc::new(RateLimiter::new(
+            quotas.clone(),
+            Arc::new(SystemClock),
+        )));
+    }
+    #[cfg(feature = "auth")]
+    if let Some(keyring) = &config.keyring {
+        server = server.with_auth(keyring.clone());
+    }
+    // Our own acceptor is reached in process, so its fsync overlaps with the
+    // sends to the other members.
+    let loopback = config
+        .members
+        .iter()
+        .find(|(id, _)| *id == config.id)
+        .map(|(_, addr)| server.loopback(*addr));
+    Ok(Serving {
+        server: thread::spawn(move || server.serve(listener)),
+        loopback,
+        handle,
+        restored,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alpha::ReadPeers;
+    use crate::audit::{export, verify_trail};
+    use crate::config::{ConfigError, CredentialSource, Credentials};
+    use crate::data_dir::LayoutError;
+    use crate::identity::IdentityError;
+    use crate::instance::InstanceId;
+    use crate::membership::Configuration;
+    use crate::retry::RetryPolicy;
+    use crate::token::{Access, StaticTokens};
+    use crate::transport::client::Client;
+    use crate::transport::tcp::Health;
+    use futures::future::join;
+    use std::time::Instant;
+
+    #[test]
+    fn concurrent_proposals_agree() {
+        let addr = free_addr();
+        let node = Node::<u64>::start(NodeConfig::new(Id(1), addr, vec![(Id(1), addr)])).unwrap();
+        let (a, b) = block_on(join(node.propose(1), node.propose(2)));
+        let (a, b) = (a.unwrap(), b.unwrap());
+        assert_eq!(a, b);
+        assert_eq!(node.decision(), Some(a));
+        block_on(node.shutdown()).unwrap();
+    }
+
+    #[test]
+    fn records_decisions_in_the_trail() {
+        let addr = free_addr();
+        let trail = std::env::temp_dir().join(format!("paxos-node-trail-{}", std::process::id()));
+        let _ = std::fs::remove_file(&trail);
+        let mut config = NodeConfig::new(Id(1), addr, vec![(Id(1), addr)]);
+        config.decision_trail = Some(trail.clone());
+        config.instance = InstanceId(4);
+        let node = Node::<u64>::start(config).unwrap();
+        let (a, b) = block_on(join(node.propose(1), node.propose(2)));
+        assert_eq!(a.unwrap(), b.unwrap());
+        block_on(node.shutdown()).unwrap();
+
+        let records = export(&trail).unwrap();
+        std::fs::remove_file(trail).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].instance, 4);
+        verify_trail(&records, &node.config.quorum_spec()).unwrap();
+    }
+
+    #[test]
+    fn joint_configurations_need_a_majority_of_each_side() {
+        let addr = free_addr();
+        let node = Node::<u64>::start(NodeConfig::new(Id(1), addr, vec![(Id(1), addr)])).unwrap();
+        let voters = |ids: &[u64]| Configuration::new(ids.iter().copied().map(Id));
+        let joint = Membership::Joint {
+            old: voters(&[1, 2, 3]),
+            new: voters(&[3, 4, 5]),
+        };
+        let members = (1..=5).map(|id| (Id(id), addr)).collect();
+        node.reconfigure(&joint, members);
+
+        let acked = |ids: &[u64]| ids.iter().copied().map(Id).collect::<HashSet<_>>();
+        assert!(!node.quorum.is_write_quorum(&acked(&[1, 2, 3])));
+        assert!(!node.quorum.is_read_quorum(&acked(&[1, 2, 3])));
+        assert!(node.quorum.is_write_quorum(&acked(&[1, 3, 4])));
+        assert_eq!(node.addresses().len(), 5);
+        block_on(node.shutdown()).unwrap();
+    }
+
+    #[test]
+    fn shutdown_hands_over_leadership() {
+        let addrs: Vec<SocketAddr> = (0..2).map(|_| free_addr()).collect();
+        let members = vec![(Id(1), addrs[0]), (Id(2), addrs[1])];
+        let start = |index: usize| {
+            let mut config = NodeConfig::new(Id(index as u64 + 1), addrs[index], members.clone());
+            config.failure_timeout = Duration::from_secs(60);
+            Node::<u64>::start(config).unwrap()
+        };
+        let (first, second) = (start(0), start(1));
+        block_on(second.detector.wait_until_leader(Id(1)));
+
+        block_on(first.shutdown()).unwrap();
+        assert_eq!(second.detector.leader(), Id(2));
+        block_on(second.shutdown()).unwrap();
+    }
+
+    #[test]
+    fn enables_features_once_every_member_supports_them() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (old, addr) = (listener.local_addr().unwrap(), free_addr());
+        let members = vec![(Id(1), addr), (Id(2), old)];
+        let start = |id: u64, listen: SocketAddr| {
+            let mut config = NodeConfig::new(Id(id), listen, members.clone());
+            config.heartbeat_interval = Duration::from_millis(20);
+            config.failure_timeout = Duration::from_millis(100);
+            Node::<u64>::start(config).unwrap()
+        };
+        let wait_for = |node: &Node<u64>, features: Features| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while node.negotiated_features() != features {
+                assert!(
+                    Instant::now() < deadline,
+                    "still {:?}",
+                    node.negotiated_features()
+                );
+                thread::sleep(Duration::from_millis(10));
+            }
+        };
+
+        // Member 2 still runs a build that advertises nothing.
+        let stopped = Arc::new(AtomicBool::new(false));
+        let acceptor = Acceptor::new(Id(2), MemoryStorage::<u64>::default()).unwrap();
+        let server = Server::new(Arc::new(Mutex::new(acceptor))).with_shutdown(stopped.clone());
+        let server = thread::spawn(move || server.serve(listener));
+        let upgraded = start(1, addr);
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(upgraded.negotiated_features(), Features::empty());
+
+        stopped.store(true, Ordering::Release);
+        let _ = TcpStream::connect(old);
+        server.join().unwrap().unwrap();
+        let second = start(2, old);
+        wait_for(&upgraded, FEATURES);
+
+        block_on(second.shutdown()).unwrap();
+        wait_for(&upgraded, Features::empty());
+        block_on(upgraded.shutdown()).unwrap();
+    }
+
+    #[test]
+    fn bootstrap_waits_until_every_member_persisted_the_same_members() {
+        let members: Vec<_> = (1..=3).map(|id| (Id(id), free_addr())).collect();
+        let starting: Vec<_> = members
+            .iter()
+            .map(|&(id, addr)| {
+                let config = NodeConfig::new(id, addr, members.clone());
+                thread::spawn(move || match id.get() {
+                    // A member started plainly adopts what the others offer.
+                    3 => Node::<u64>::start(config),
+                    _ => block_on(Node::<u64>::bootstrap(config)),
+                })
+            })
+            .collect();
+        let nodes: Vec<_> = starting
+            .into_iter()
+            .map(|node| node.join().unwrap().unwrap())
+            .collect();
+        let cluster = Bootstrap::new(members).cluster();
+        for node in &nodes {
+            assert_eq!(node.cluster(), Some(cluster));
+        }
+        assert_eq!(block_on(nodes[0].propose(5)).unwrap(), 5);
+        for node in nodes {
+            block_on(node.shutdown()).unwrap();
+        }
+    }
+
+    #[test]
+    fn refuses_to_bootstrap_over_another_cluster() {
+        let dir = std::env::temp_dir().join(format!("paxos-node-bootstrap-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let (first, second) = ((Id(1), free_addr()), (Id(2), free_addr()));
+        let existing = block_on(Node::<u64>::bootstrap(NodeConfig::new(
+            first.0,
+            first.1,
+            vec![first],
+        )))
+        .unwrap();
+
+        // Member 1 already formed a cluster of its own.
+        let mut config = NodeConfig::new(second.0, second.1, vec![first, second]);
+        config.retry_policy = RetryPolicy {
+            max_attempts: Some(3),
+            ..RetryPolicy::immediate()
+        };
+        assert!(matches!(
+            block_on(Node::<u64>::bootstrap(config.clone())),
+            Err(Error::Bootstrap(BootstrapError::Claimed { member, .. })) if member == first.0
+        ));
+
+        // Joining adopts member 1's cluster instead, for good.
+        config.members = vec![second];
+        config.storage_path = Some(dir.join("acceptor"));
+        let joined = block_on(Node::<u64>::join(config.clone(), vec![first])).unwrap();
+        assert_eq!(joined.addresses(), vec![first, second]);
+        assert_eq!(joined.cluster(), existing.cluster());
+        assert!(!joined.quorum.is_write_quorum(&HashSet::from([second.0])));
+        block_on(joined.shutdown()).unwrap();
+        assert!(matches!(
+            block_on(Node::<u64>::bootstrap(config)),
+            Err(Error::Bootstrap(BootstrapError::Conflict { .. }))
+        ));
+        block_on(existing.shutdown()).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn joining_needs_a_bootstrapped_cluster() {
+        let (first, second) = ((Id(1), free_addr()), (Id(2), free_addr()));
+        let unformed = Node::<u64>::start(NodeConfig::new(first.0, first.1, vec![first])).unwrap();
+        let config = NodeConfig::new(second.0, second.1, vec![second]);
+        assert!(matches!(
+            block_on(Node::<u64>::join(config, vec![first])),
+            Err(Error::Bootstrap(BootstrapError::NotBootstrapped))
+        ));
+        block_on(unformed.shutdown()).unwrap();
+    }
+
+    #[test]
+    fn adds_a_caught_up_member_through_the_membership_log() {
+        let dir = std::env::temp_dir().join(format!("paxos-node-add-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let (first, second) = ((Id(1), free_addr()), (Id(2), free_addr()));
+        let mut config = NodeConfig::new(first.0, first.1, vec![first]);
+        config.storage_path = Some(dir.join("acceptor"));
+        let leader = block_on(Node::<u64>::bootstrap(config.clone())).unwrap();
+        assert_eq!(block_on(leader.propose(7)).unwrap(), 7);
+
+        // A node that never joined the cluster gets no vote.
+        let stranger =
+            Node::<u64>::start(NodeConfig::new(second.0, second.1, vec![second])).unwrap();
+        assert!(matches!(
+            block_on(leader.add_member(second.0, second.1)),
+            Err(Error::NotCaughtUp(id)) if id == second.0
+        ));
+        block_on(stranger.shutdown()).unwrap();
+
+        let joiner = NodeConfig::new(second.0, second.1, vec![second]);
+        let joined = block_on(Node::<u64>::join(joiner, vec![first])).unwrap();
+        let both = Membership::Stable(Configuration::new([first.0, second.0]));
+        assert_eq!(
+            block_on(leader.add_member(second.0, second.1)).unwrap(),
+            both
+        );
+        assert_eq!(joined.decision(), Some(7));
+        assert_eq!(leader.addresses(), vec![first, second]);
+        assert!(!leader.quorum.is_write_quorum(&HashSet::from([first.0])));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while joined.membership() != both {
+            assert!(Instant::now() < deadline, "still {:?}", joined.membership());
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        block_on(joined.shutdown()).unwrap();
+        block_on(leader.shutdown()).unwrap();
+        let restarted = Node::<u64>::start(config).unwrap();
+        assert_eq!(restarted.membership(), both);
+        assert_eq!(restarted.addresses(), vec![first, second]);
+        block_on(restarted.shutdown()).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn joiners_take_on_a_members_state_before_they_vote() {
+        let (first, second, third) = (
+            (Id(1), free_addr()),
+            (Id(2), free_addr()),
+            (Id(3), free_addr()),
+        );
+        let leader = block_on(Node::<u64>::bootstrap(NodeConfig::new(
+            first.0,
+            first.1,
+            vec![first],
+        )))
+        .unwrap();
+        assert_eq!(block_on(leader.propose(7)).unwrap(), 7);
+        let joined = block_on(Node::<u64>::join(
+            NodeConfig::new(second.0, second.1, vec![second]),
+            vec![first],
+        ))
+        .unwrap();
+        block_on(leader.add_member(second.0, second.1)).unwrap();
+        let both = Membership::Stable(Configuration::new([first.0, second.0]));
+        assert!(joined.transfer_progress().done());
+
+        let late = block_on(Node::<u64>::join(
+            NodeConfig::new(third.0, third.1, vec![third]),
+            vec![first],
+        ))
+        .unwrap();
+        let progress = late.transfer_progress();
+        assert!(progress.done());
+        assert_eq!(progress.applied, lock(&leader.applied).next);
+        assert_eq!(late.membership(), both);
+        assert_eq!(late.addresses(), vec![first, second]);
+        assert_eq!(late.decision(), Some(7));
+        let round = |addr| {
+            let page = block_on(
+                TcpPeers::<u64>::new(vec![addr])
+                    .state_transfer(LogIndex::default())
+                    .next(),
+            );
+            page.unwrap().unwrap().last_round_entered
+        };
+        assert!(round(third.1) >= round(first.1));
+
+        // A transfer resumed where the last one stopped has nothing to add.
+        let peers = TcpPeers::<u64>::new(vec![first.1]);
+        let resumed = block_on(late.transfer_state(&peers)).unwrap();
+        assert_eq!(resumed.applied, progress.applied);
+        assert_eq!(resumed.pages, progress.pages + 1);
+
+        block_on(late.shutdown()).unwrap();
+        block_on(joined.shutdown()).unwrap();
+        block_on(leader.shutdown()).unwrap();
+    }
+
+    #[test]
+    fn joins_as_a_voter_with_a_token_the_leader_issued() {
+        let (first, second) = ((Id(1), free_addr()), (Id(2), free_addr()));
+        let leader = block_on(Node::<u64>::bootstrap(NodeConfig::new(
+            first.0,
+            first.1,
+            vec![first],
+        )))
+        .unwrap();
+        let operator = TcpPeers::<u64>::new(vec![first.1]);
+        let issue = || match block_on(
+            operator
+                .issue_join_token(second.0, Duration::from_secs(60))
+                .next(),
+        ) {
+            Some(Ok(Proposed::Decided(token))) => token,
+            other => panic!("unexpected {other:?}"),
+        };
+
+        let mut joiner = NodeConfig::new(second.0, second.1, vec![second]);
+        joiner.join_token = Some("guess".to_string());
+        match block_on(Node::<u64>::join(joiner.clone(), vec![first])) {
+            Err(Error::Transport(error)) => assert!(error.to_string().contains("not valid")),
+            other => panic!("unexpected {:?}", other.map(|node| node.id())),
+        }
+        assert_eq!(leader.membership().voters(), BTreeSet::from([first.0]));
+
+        let token = issue();
+        joiner.join_token = Some(token.clone());
+        let joined = block_on(Node::<u64>::join(joiner.clone(), vec![first])).unwrap();
+        let both = Membership::Stable(Configuration::new([first.0, second.0]));
+        assert_eq!(leader.membership(), both);
+        block_on(joined.shutdown()).unwrap();
+
+        // Each token adds its node once.
+        let spent = block_on(operator.join(&token, second.0, second.1).next());
+        assert!(
+            matches!(spent, Some(Err(Error::Transport(error))) if error.to_string().contains("not valid"))
+        );
+        block_on(leader.shutdown()).unwrap();
+    }
+
+    #[test]
+    fn refuses_removals_that_leave_no_live_quorum() {
+        let (first, second) = ((Id(1), free_addr()), (Id(2), free_addr()));
+        let mut config = NodeConfig::new(first.0, first.1, vec![first]);
+        config.heartbeat_interval = Duration::from_millis(20);
+        config.failure_timeout = Duration::from_millis(100);
+        let leader = block_on(Node::<u64>::bootstrap(config.clone())).unwrap();
+        assert!(matches!(
+            block_on(leader.remove_member(first.0)),
+            Err(Error::QuorumAtRisk(id)) if id == first.0
+        ));
+
+        let joiner = NodeConfig {
+            id: second.0,
+            listen: second.1,
+            members: vec![second],
+            ..config
+        };
+        let joined = block_on(Node::<u64>::join(joiner, vec![first])).unwrap();
+        block_on(leader.add_member(second.0, second.1)).unwrap();
+        block_on(joined.shutdown()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while leader.detector.members().contains(&second.0)
+            && !leader.detector.suspected().contains(&second.0)
+        {
+            assert!(Instant::now() < deadline, "member 2 still looks live");
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // Both sides of the joint configuration would need member 2.
+        assert!(matches!(
+            block_on(leader.remove_member(second.0)),
+            Err(Error::QuorumAtRisk(id)) if id == second.0
+        ));
+        assert_eq!(
+            leader.membership(),
+            Membership::Stable(Configuration::new([first.0, second.0]))
+        );
+        block_on(leader.shutdown()).unwrap();
+    }
+
+    #[test]
+    fn force_recovery_fences_off_the_lost_members() {
+        let dir = std::env::temp_dir().join(format!("paxos-node-recover-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let members: Vec<_> = (1..=2).map(|id| (Id(id), free_addr())).collect();
+        let configs: Vec<_> = members
+            .iter()
+            .map(|&(id, addr)| {
+                let mut config = NodeConfig::new(id, addr, members.clone());
+                config.storage_path = Some(dir.join(format!("acceptor-{}", id.get())));
+                config
+            })
+            .collect();
+        let nodes: Vec<_> = configs
+            .iter()
+            .map(|config| Node::<u64>::start(config.clone()).unwrap())
+            .collect();
+        assert_eq!(block_on(nodes[0].propose(7)).unwrap(), 7);
+        for node in nodes {
+            block_on(node.shutdown()).unwrap();
+        }
+
+        // Member 2 is gone for good, so member 1 alone can never be a quorum.
+        let survivor = vec![members[0]];
+        assert!(matches!(
+            Node::<u64>::force_recover(&configs[1], survivor.clone()),
+            Err(Error::RecoveryRefused(_))
+        ));
+        assert!(matches!(
+            Node::<u64>::force_recover(
+                &NodeConfig::new(Id(1), members[0].1, members.clone()),
+                survivor.clone()
+            ),
+            Err(Error::RecoveryRefused(_))
+        ));
+        assert_eq!(
+            Node::<u64>::force_recover(&configs[0], survivor.clone()).unwrap(),
+            1
+        );
+
+        let recovered = Node::<u64>::start(configs[0].clone()).unwrap();
+        assert_eq!(recovered.epoch(), 1);
+        assert_eq!(recovered.addresses(), survivor);
+        assert_eq!(
+            recovered.membership(),
+            Membership::Stable(Configuration::new([Id(1)]))
+        );
+        assert_eq!(block_on(recovered.propose(9)).unwrap(), 7);
+
+        // A member still in the old epoch is refused.
+        let stale = TcpPeers::<u64>::new(vec![members[0].1]);
+        let read = block_on(Box::pin(stale.read(Round::new(Id(2)))).next());
+        assert!(matches!(read, Some(Err(_))));
+        let current = stale.with_epoch(Arc::new(AtomicU64::new(1)));
+        let read = block_on(Box::pin(current.read(Round::new(Id(2)))).next());
+        assert!(matches!(read, Some(Ok(_))));
+        block_on(recovered.shutdown()).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_restarted_node_serves_once_it_has_caught_up() {
+        let dir = std::env::temp_dir().join(format!("paxos-node-ready-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let members: Vec<_> = (1..=2).map(|id| (Id(id), free_addr())).collect();
+        let configs: Vec<_> = members
+            .iter()
+            .map(|&(id, addr)| {
+                let mut config = NodeConfig::new(id, addr, members.clone());
+                config.storage_path = Some(dir.join(format!("acceptor-{}", id.get())));
+                config
+            })
+            .collect();
+        let nodes: Vec<_> = configs
+            .iter()
+            .map(|config| Node::<u64>::start(config.clone()).unwrap())
+            .collect();
+        assert!(nodes.iter().all(|node| node.ready()));
+        assert_eq!(block_on(nodes[0].propose(7)).unwrap(), 7);
+        for node in nodes {
+            block_on(node.shutdown()).unwrap();
+        }
+
+        // Alone, member 2 cannot tell whether it missed anything.
+        let second = Node::<u64>::start(configs[1].clone()).unwrap();
+        let client = TcpPeers::<u64>::new(vec![members[1].1]);
+        let health = block_on(Box::pin(client.health()).next()).unwrap().unwrap();
+        assert!(health < Health::Ready);
+        let read = block_on(Box::pin(client.decision()).next());
+        assert!(matches!(read, Some(Err(_))));
+        assert!(!second.ready());
+
+        let first = Node::<u64>::start(configs[0].clone()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !(first.ready() && second.ready()) {
+            assert!(Instant::now() < deadline, "never caught up");
+            thread::sleep(Duration::from_millis(10));
+        }
+        let health = block_on(Box::pin(client.health()).next()).unwrap().unwrap();
+        assert!(health >= Health::Ready);
+        assert_eq!(block_on(first.propose(9)).unwrap(), 7);
+        block_on(second.shutdown()).unwrap();
+        block_on(first.shutdown()).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn lists_voters_learners_and_what_they_last_reported() {
+        let (first, second) = ((Id(1), free_addr()), (Id(2), free_addr()));
+        let existing = block_on(Node::<u64>::bootstrap(NodeConfig::new(
+            first.0,
+            first.1,
+            vec![first],
+        )))
+        .unwrap();
+        let joined = block_on(Node::<u64>::join(
+            NodeConfig::new(second.0, second.1, vec![second]),
+            vec![first],
+        ))
+        .unwrap();
+
+        let members = block_on(joined.members());
+        assert_eq!(
+            members
+                .iter()
+                .map(|member| (member.id, member.addr, member.role, member.reachable))
+                .collect::<Vec<_>>(),
+            vec![
+                (first.0, first.1, Role::Voter, true),
+                (second.0, second.1, Role::Learner, true),
+            ]
+        );
+        assert!(members.iter().all(|member| member.epoch == Some(0)));
+        assert!(members.iter().all(|member| member.commit_index.is_none()));
+        let client = TcpPeers::<u64>::new(vec![second.1]);
+        let served = block_on(Box::pin(client.topology()).next())
+            .unwrap()
+            .unwrap();
+        assert_eq!(served.len(), 2);
+
+        block_on(existing.shutdown()).unwrap();
+        let members = block_on(joined.members());
+        assert!(!members[0].reachable);
+        assert_eq!(members[0].epoch, Some(0));
+        block_on(joined.shutdown()).unwrap();
+    }
+
+    #[test]
+    fn refuses_state_left_by_a_node_with_another_id() {
+        let dir = std::env::temp_dir().join(format!("paxos-node-identity-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let addr = free_addr();
+        let mut config = NodeConfig::new(Id(1), addr, vec![(Id(1), addr)]);
+        config.storage_path = Some(dir.join("acceptor"));
+        let node = Node::<u64>::start(config.clone()).unwrap();
+        block_on(node.shutdown()).unwrap();
+
+        let reused = NodeConfig {
+            id: Id(2),
+            members: vec![(Id(2), addr)],
+            ..config.clone()
+        };
+        assert!(matches!(
+            Node::<u64>::start(reused),
+            Err(Error::Identity(IdentityError::Conflict { stored, .. })) if stored == Id(1)
+        ));
+        let node = Node::<u64>::start(config).unwrap();
+        block_on(node.shutdown()).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_its_state_in_an_initialised_data_dir() {
+        let root = std::env::temp_dir().join(format!("paxos-node-data-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let addr = free_addr();
+        let mut config = NodeConfig::new(Id(1), addr, vec![(Id(1), addr)]);
+        config.data_dir = Some(root.clone());
+        assert!(matches!(
+            Node::<u64>::start(config.clone()),
+            Err(Error::Layout(LayoutError::NotInitialized(_)))
+        ));
+
+        let dir = DataDir::init(&root, None).unwrap();
+        let node = Node::<u64>::start(config.clone()).unwrap();
+        assert_eq!(block_on(node.propose(3)).unwrap(), 3);
+        block_on(node.shutdown()).unwrap();
+        assert!(dir.storage_path().is_file());
+        assert_eq!(dir.identity(None).unwrap(), Id(1));
+
+        let node = Node::<u64>::start(config).unwrap();
+        assert_eq!(block_on(node.propose(4)).unwrap(), 3);
+        block_on(node.shutdown()).unwrap();
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn restores_a_backup_taken_while_running_into_the_next_epoch() {
+        let root = std::env::temp_dir().join(format!("paxos-node-backup-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let addr = free_addr();
+        let mut config = NodeConfig::new(Id(1), addr, vec![(Id(1), addr)]);
+        config.data_dir = Some(root.join("live"));
+        DataDir::init(root.join("live"), None).unwrap();
+        let node = Node::<u64>::start(config.clone()).unwrap();
+        assert_eq!(block_on(node.propose(7)).unwrap(), 7);
+        let backup = root.join("backup");
+        let peers = TcpPeers::<u64>::new(vec![addr]);
+        let backed_up = block_on(Box::pin(peers.backup(backup.to_str().unwrap())).next());
+        assert!(matches!(backed_up, Some(Ok(()))));
+        assert!(matches!(
+            node.backup(&backup),
+            Err(Error::Layout(LayoutError::NotEmpty(_)))
+        ));
+        block_on(node.shutdown()).unwrap();
+
+        assert!(matches!(
+            Node::<u64>::restore(&config, &backup),
+            Err(Error::Layout(LayoutError::NotEmpty(_)))
+        ));
+        let mut other = config.clone();
+        other.id = Id(2);
+        other.data_dir = Some(root.join("other"));
+        assert!(matches!(
+            Node::<u64>::restore(&other, &backup),
+            Err(Error::Identity(_))
+        ));
+
+        config.data_dir = Some(root.join("restored"));
+        assert_eq!(Node::<u64>::restore(&config, &backup).unwrap(), 1);
+        let restored = Node::<u64>::start(config).unwrap();
+        assert_eq!(restored.epoch(), 1);
+        assert_eq!(block_on(restored.propose(9)).unwrap(), 7);
+        block_on(restored.shutdown()).unwrap();
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn refuses_to_start_on_storage_older_than_its_members_heard_of() {
+        let root = std::env::temp_dir().join(format!("paxos-node-fence-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let addrs: Vec<SocketAddr> = (0..2).map(|_| free_addr()).collect();
+        let members = vec![(Id(1), addrs[0]), (Id(2), addrs[1])];
+        let config = |id: u64, dir: &str| {
+            let mut config = NodeConfig::new(Id(id), addrs[id as usize - 1], members.clone());
+            config.data_dir = Some(root.join(dir));
+            config
+        };
+        for dir in ["first", "second"] {
+            DataDir::init(root.join(dir), None).unwrap();
+        }
+        let peer = Node::<u64>::start(config(2, "second")).unwrap();
+        let node = Node::<u64>::start(config(1, "first")).unwrap();
+        block_on(node.shutdown()).unwrap();
+
+        // A copy taken by hand while the node was stopped, and started on
+        // after the node ran again.
+        let storage = DataDir::open(root.join("first")).unwrap().storage_path();
+        DataDir::copy(&storage, root.join("copy")).unwrap();
+        let node = Node::<u64>::start(config(1, "first")).unwrap();
+        block_on(node.shutdown()).unwrap();
+        let stale = |dir| match Node::<u64>::start(config(1, dir)) {
+            Err(Error::StaleStorage { id: Id(1), seen }) => seen,
+            other => panic!("unexpected {:?}", other.map(|node| node.id())),
+        };
+        assert_eq!(stale("copy"), Fence { epoch: 0, count: 2 });
+        assert_eq!(stale("copy"), Fence { epoch: 0, count: 2 });
+
+        // The live node moves past the fence a backup holds.
+        let node = Node::<u64>::start(config(1, "first")).unwrap();
+        node.backup(&root.join("backup")).unwrap();
+        block_on(node.shutdown()).unwrap();
+        assert_eq!(stale("backup"), Fence { epoch: 0, count: 4 });
+
+        // Restoring it properly moves the node into the next epoch instead.
+        let restored = config(1, "restored");
+        assert_eq!(
+            Node::<u64>::restore(&restored, &root.join("backup")).unwrap(),
+            1
+        );
+        let node = Node::<u64>::start(restored).unwrap();
+        assert_eq!(node.epoch(), 1);
+        block_on(node.shutdown()).unwrap();
+        block_on(peer.shutdown()).unwrap();
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[derive(Debug)]
+    struct Rotating(Mutex<Credentials>);
+
+    impl CredentialSource for Rotating {
+        fn load(&self) -> Result<Credentials, ConfigError> {
+            Ok(lock(&self.0).clone())
+        }
+    }
+
+    #[test]
+    fn reloads_client_tokens_while_serving() {
+        let tokens = |token: &str| -> Arc<dyn TokenVerifier> {
+            Arc::new(StaticTokens::new().with_token(token, Access::Write))
+        };
+        let addr = free_addr();
+        let source = Arc::new(Rotating(Mutex::new(Credentials::default())));
+        let mut config = NodeConfig::new(Id(1), addr, vec![(Id(1), addr)]);
+        config.client_tokens = Some(tokens("old"));
+        config.credentials = Some(source.clone());
+        let node = Node::<u64>::start(config).unwrap();
+        let client = |token| {
+            Client::<u64>::new([(Some(Id(1)), addr)])
+                .with_retry_policy(RetryPolicy::immediate())
+                .with_token(token)
+        };
+        assert_eq!(block_on(client("old").propose(7)).unwrap(), 7);
+
+        lock(&source.0).client_tokens = Some(tokens("new"));
+        let peers = TcpPeers::<u64>::new(vec![addr]);
+        let reloaded = block_on(Box::pin(peers.reload_credentials()).next());
+        assert!(matches!(reloaded, Some(Ok(()))));
+        assert!(block_on(client("old").decision()).is_err());
+        assert_eq!(block_on(client("new").decision()).unwrap(), Some(7));
+        block_on(node.shutdown()).unwrap();
+
+        let addr = free_addr();
+        let mut config = NodeConfig::new(Id(1), addr, vec![(Id(1), addr)]);
+        let node = Node::<u64>::start(config.clone()).unwrap();
+        assert_eq!(
+            node.reload_credentials().unwrap_err().kind(),
+            io::ErrorKind::Unsupported
+        );
+        block_on(node.shutdown()).unwrap();
+        config.listen = free_addr();
+        config.members = vec![(Id(1), config.listen)];
+        config.credentials = Some(source);
+        let node = Node::<u64>::start(config).unwrap();
+        let refused = node.reload_credentials().unwrap_err();
+        assert!(refused.to_string().contains("without client tokens"));
+        block_on(node.shutdown()).unwrap();
+    }
+
+    fn free_addr() -> SocketAddr {
+        TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .unwrap()
+    }
+}
@@ -1,34 +1,926 @@
-use crate::alpha::{Alpha, Id, Quorum, ReadClient, Round, WriteClient};
+use crate::alpha::{Alpha, Error, Id, Promise, Quorum, ReadPeers, Round, WritePeers};
+use crate::certificate::DecisionCertificate;
+use crate::learner::DecisionPeers;
+use crate::metrics::{NoopObserver, Observer, Stage};
+use crate::retry::RetryPolicy;
+use crate::rng::XorShift;
+use crate::storage::Storage;
+use crate::time::{timeout_with, Clock, SystemClock, Timeouts, Timestamp};
+use derive_new::new;
+use futures::future::Either;
+use futures::stream::FuturesUnordered;
+use futures::task::AtomicWaker;
+use futures::{Stream, StreamExt};
+use std::collections::VecDeque;
+use std::future::{poll_fn, Future};
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::Poll;
+use std::time::Duration;
 
-struct Proposer<V, P, D> {
+pub struct Proposer<V, P, D, O = NoopObserver> {
     id: Id,
     alpha: Alpha<V>,
     peers: P,
     failure_detector: D,
+    retry_policy: RetryPolicy,
+    stage_timeout: Option<Duration>,
+    timeouts: Option<Arc<dyn Timeouts>>,
+    delivery: Delivery,
+    read_repair: bool,
+    promise: Option<Promise<V>>,
+    observer: O,
+    clock: Arc<dyn Clock>,
+    ticks: Ticks,
+    rng: XorShift,
+    pending: VecDeque<V>,
+    certificate: Option<DecisionCertificate<V>>,
 }
 
-impl<V, P, D> Proposer<V, P, D>
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome<V> {
+    Chosen(V),
+    Superseded(V),
+    Preempted(Round),
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Delivery {
+    #[default]
+    Quorum,
+    /// Keep draining responses beyond the quorum while later stages run.
+    /// They are abandoned once the value is decided.
+    All,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TickSource {
+    #[default]
+    Counter,
+    Hybrid,
+}
+
+type TickStore = Box<dyn FnMut(u64) -> Result<(), Error> + Send>;
+
+#[derive(Clone)]
+pub struct Ticks {
+    inner: Arc<Mutex<TickState>>,
+}
+
+struct TickState {
+    source: TickSource,
+    last: Option<u64>,
+    store: Option<TickStore>,
+}
+
+impl<V, P, D> Proposer<V, P, D> {
+    pub fn builder(id: Id, peers: P, failure_detector: D) -> ProposerBuilder<V, P, D> {
+        ProposerBuilder::new(id, peers, failure_detector, NoopObserver)
+    }
+}
+
+#[derive(new)]
+pub struct ProposerBuilder<V, P, D, O = NoopObserver> {
+    id: Id,
+    peers: P,
+    failure_detector: D,
+    observer: O,
+    #[new(default)]
+    alpha: Option<Alpha<V>>,
+    #[new(default)]
+    retry_policy: RetryPolicy,
+    #[new(default)]
+    stage_timeout: Option<Duration>,
+    #[new(default)]
+    timeouts: Option<Arc<dyn Timeouts>>,
+    #[new(default)]
+    delivery: Delivery,
+    #[new(default)]
+    read_repair: bool,
+    #[new(default)]
+    promise: Option<Promise<V>>,
+    #[new(value = "Arc::new(SystemClock)")]
+    clock: Arc<dyn Clock>,
+    #[new(default)]
+    last_tick: Option<u64>,
+    #[new(default)]
+    tick_store: Option<TickStore>,
+    #[new(default)]
+    tick_source: TickSource,
+    #[new(default)]
+    ticks: Option<Ticks>,
+}
+
+impl<V, P, D, O> ProposerBuilder<V, P, D, O> {
+    pub fn alpha(mut self, alpha: Alpha<V>) -> Self {
+        self.alpha = Some(alpha);
+        self
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn stage_timeout(mut self, stage_timeout: Duration) -> Self {
+        self.stage_timeout = Some(stage_timeout);
+        self
+    }
+
+    pub fn timeouts(mut self, timeouts: Arc<dyn Timeouts>) -> Self {
+        self.timeouts = Some(timeouts);
+        self
+    }
+
+    pub fn delivery(mut self, delivery: Delivery) -> Self {
+        self.delivery = delivery;
+        self
+    }
+
+    /// Once a round is decided, writes the value again to the acceptors the
+    /// read stage found stale. The decision never waits for their acks.
+    pub fn read_repair(mut self, read_repair: bool) -> Self {
+        self.read_repair = read_repair;
+        self
+    }
+
+    pub fn promise(mut self, promise: Promise<V>) -> Self {
+        self.promise = Some(promise);
+        self
+    }
+
+    pub fn observer<T: Observer>(self, observer: T) -> ProposerBuilder<V, P, D, T> {
+        ProposerBuilder {
+            id: self.id,
+            peers: self.peers,
+            failure_detector: self.failure_detector,
+            observer,
+            alpha: self.alpha,
+            retry_policy: self.retry_policy,
+            stage_timeout: self.stage_timeout,
+            timeouts: self.timeouts,
+            delivery: self.delivery,
+            read_repair: self.read_repair,
+            promise: self.promise,
+            clock: self.clock,
+            last_tick: self.last_tick,
+            tick_store: self.tick_store,
+            tick_source: self.tick_source,
+            ticks: self.ticks,
+        }
+    }
+
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn tick_source(mut self, tick_source: TickSource) -> Self {
+        self.tick_source = tick_source;
+        self
+    }
+
+    pub fn storage<S>(mut self, storage: S) -> Result<Self, Error>
+    where
+        V: 'static,
+        S: Storage<V> + Send + 'static,
+    {
+        let (last_tick, tick_store) = tick_store(storage)?;
+        self.last_tick = last_tick;
+        self.tick_store = Some(tick_store);
+        Ok(self)
+    }
+
+    pub fn ticks(mut self, ticks: Ticks) -> Self {
+        self.ticks = Some(ticks);
+        self
+    }
+
+    pub fn build(self) -> Proposer<V, P, D, O> {
+        Proposer {
+            id: self.id,
+            alpha: self.alpha.unwrap_or_default(),
+            peers: self.peers,
+            failure_detector: self.failure_detector,
+            retry_policy: self.retry_policy,
+            stage_timeout: self.stage_timeout,
+            timeouts: self.timeouts,
+            delivery: self.delivery,
+            read_repair: self.read_repair,
+            promise: self.promise,
+            observer: self.observer,
+            clock: self.clock,
+            ticks: self.ticks.unwrap_or_else(|| {
+                Ticks::with_state(TickState {
+                    source: self.tick_source,
+                    last: self.last_tick,
+                    store: self.tick_store,
+                })
+            }),
+            rng: XorShift::new(self.id.0),
+            pending: VecDeque::new(),
+            certificate: None,
+        }
+    }
+}
+
+impl<V, P, D, O> Proposer<V, P, D, O>
 where
     V: Clone,
     D: FailureDetector,
-    P: WriteClient<V> + ReadClient<V> + Quorum,
+    P: WritePeers<V> + ReadPeers<V> + DecisionPeers<V> + Quorum,
+    O: Observer,
 {
-    async fn propose(&mut self, value: V) -> V {
-        let mut round = Round::new(self.id);
+    pub async fn propose(&mut self, value: V) -> Result<V, Error> {
+        self.propose_certified(value)
+            .await
+            .map(|certificate| certificate.value)
+    }
 
-        loop {
+    pub async fn propose_certified(&mut self, value: V) -> Result<DecisionCertificate<V>, Error> {
+        self.propose_until(value, &ProposeHandle::default(), false)
+            .await
+    }
+
+    pub async fn try_propose(&mut self, value: V) -> Result<Outcome<V>, Error>
+    where
+        V: PartialEq,
+    {
+        match self
+            .propose_until(value.clone(), &ProposeHandle::default(), true)
+            .await
+        {
+            Ok(certificate) if certificate.value == value => Ok(Outcome::Chosen(certificate.value)),
+            Ok(certificate) => Ok(Outcome::Superseded(certificate.value)),
+            Err(Error::Preempted(conflict)) => {
+                self.pending.push_back(value);
+                Ok(Outcome::Preempted(conflict))
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    pub async fn resubmit(&mut self) -> Result<Option<Outcome<V>>, Error>
+    where
+        V: PartialEq,
+    {
+        if self.failure_detector.leader() != self.id {
+            return Ok(None);
+        }
+        match self.pending.pop_front() {
+            Some(value) => self.try_propose(value).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn round(&self) -> Round {
+        self.alpha.last_round_entered
+    }
+
+    pub fn certificate(&self) -> Option<&DecisionCertificate<V>> {
+        self.certificate.as_ref()
+    }
+
+    pub fn propose_cancellable(
+        &mut self,
+        value: V,
+    ) -> (ProposeHandle, impl Future<Output = Result<V, Error>> + '_) {
+        let handle = ProposeHandle::default();
+        let cancellation = handle.clone();
+        (handle, async move {
+            self.propose_until(value, &cancellation, false)
+                .await
+                .map(|certificate| certificate.value)
+        })
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = ?self.id)))]
+    async fn propose_until(
+        &mut self,
+        value: V,
+        handle: &ProposeHandle,
+        once: bool,
+    ) -> Result<DecisionCertificate<V>, Error> {
+        let mut round = self
+            .promise
+            .as_ref()
+            .map_or(self.ticks.first_round(self.id, &*self.clock), |promise| {
+                promise.round
+            });
+        let mut attempts = 0;
+        let started = self.observer.enabled().then(|| self.clock.now());
+        let stragglers = Mutex::new(FuturesUnordered::new());
+
+        let (consensus, responses) = loop {
+            if handle.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
             if self.failure_detector.leader() == self.id {
-                if let Ok(Some(consensus)) =
-                    self.alpha.alpha(&self.peers, round, value.clone()).await
-                {
-                    break consensus;
+                let promise = self.promise.take();
+                round = match promise {
+                    Some(_) => self.ticks.record(round)?,
+                    None => self.ticks.claim(round, &*self.clock)?,
+                };
+                self.observer.on_round_started(round);
+                let (stage_timeout, delivery) = (self.stage_timeout(), self.delivery);
+                let read_repair = self.read_repair;
+                let (alpha, peers, observer) = (&mut self.alpha, &self.peers, &self.observer);
+                let clock = &*self.clock;
+                let stragglers = &stragglers;
+                let value = value.clone();
+                let attempt = async move {
+                    let promise = match promise {
+                        Some(promise) => promise,
+                        None => {
+                            let mut responses = Box::pin(peers.read(round));
+                            let prepared = alpha
+                                .read_stage(peers, round, &mut responses, clock, stage_timeout)
+                                .await;
+                            if delivery == Delivery::All {
+                                lock(stragglers).push(Either::Left(responses.count()));
+                            }
+                            match prepared? {
+                                Some(promise) => {
+                                    observer.on_quorum_reached(round, Stage::Read);
+                                    promise
+                                }
+                                None => return Ok(None),
+                            }
+                        }
+                    };
+                    let repair = read_repair && promise.needs_repair();
+                    #[cfg(feature = "tracing")]
+                    if repair {
+                        tracing::debug!(stale = ?promise.stale, "read repair");
+                    }
+                    let stale = repair.then(|| promise.stale.clone());
+                    let proposal = promise.proposal(value);
+                    let mut responses = Box::pin(peers.write(proposal.clone()));
+                    let decided = alpha
+                        .write_stage(
+                            peers,
+                            proposal.clone(),
+                            &mut responses,
+                            clock,
+                            stage_timeout,
+                        )
+                        .await;
+                    if delivery == Delivery::All {
+                        lock(stragglers).push(Either::Right(responses.count()));
+                    }
+                    let decided = decided?;
+                    if decided.is_some() {
+                        observer.on_quorum_reached(round, Stage::Write);
+                        if let Some(stale) = stale {
+                            // Peers dispatch on the call, so the repair goes
+                            // out without the decision waiting on its acks.
+                            drop(peers.write_to(&stale, proposal));
+                        }
+                    }
+                    Ok(decided)
+                };
+                let outcome = match self.retry_policy.attempt_timeout {
+                    Some(duration) => handle
+                        .guard(stragglers, timeout_with(clock, duration, attempt))
+                        .await?
+                        .ok(),
+                    None => Some(handle.guard(stragglers, attempt).await?),
+                };
+                let (conflict, last) = match outcome {
+                    Some(Ok(Some(consensus))) => break consensus,
+                    Some(Err(Error::Preempted(conflict))) => {
+                        self.observer.on_conflict(round, conflict);
+                        (Some(conflict), Some(Error::Preempted(conflict)))
+                    }
+                    Some(Err(error)) if !error.is_retryable() => return Err(error),
+                    Some(Err(error)) => (None, Some(error)),
+                    Some(Ok(None)) => (None, None),
+                    None => (None, Some(Error::QuorumTimeout)),
+                };
+
+                attempts += 1;
+                if once {
+                    if let Some(conflict) = conflict {
+                        self.ticks.record(round.greater_than(conflict))?;
+                    }
+                    return Err(last.unwrap_or(Error::Preempted(round)));
+                }
+                if self.retry_policy.exhausted(attempts) {
+                    return Err(Error::RetriesExhausted {
+                        attempts,
+                        last: last.map(Box::new),
+                    });
                 }
-                round = round.next();
+                let backoff = clock.sleep(self.retry_policy.backoff(attempts, &mut self.rng));
+                handle.guard(stragglers, backoff).await?;
+                let next = conflict.map_or(round.next(), |conflict| {
+                    round.next().max(round.greater_than(conflict))
+                });
+                round = self.ticks.advance(next, clock);
+            } else if once {
+                return Err(Error::NotLeader);
+            } else {
+                let elected = self.failure_detector.wait_until_leader(self.id);
+                handle.guard(&stragglers, elected).await?;
+            }
+        };
+
+        if let Some(started) = started {
+            self.observer
+                .on_decided(round, self.clock.now().duration_since(started));
+        }
+        #[cfg(feature = "tracing")]
+        tracing::info!(?round, attempts, "decided");
+        // Peers dispatch eagerly, so neither the decision acks nor the
+        // responses still outstanding from earlier stages need to hold up the
+        // caller once a quorum has accepted.
+        let certificate = DecisionCertificate {
+            proposer: self.id,
+            round,
+            value: consensus,
+            responses,
+        };
+        drop(self.peers.decide(certificate.clone()));
+        drop(stragglers);
+        self.certificate = Some(certificate.clone());
+        Ok(certificate)
+    }
+
+    pub async fn prepare(&mut self) -> Result<Option<Promise<V>>, Error> {
+        let mut round = self.ticks.first_round(self.id, &*self.clock);
+        let mut attempts = 0;
+        loop {
+            if self.failure_detector.leader() != self.id {
+                return Ok(None);
+            }
+            round = self.ticks.claim(round, &*self.clock)?;
+            let mut responses = pin!(self.peers.read(round));
+            let prepared = self
+                .alpha
+                .read_stage(
+                    &self.peers,
+                    round,
+                    &mut responses,
+                    &*self.clock,
+                    self.stage_timeout(),
+                )
+                .await;
+            let Err(Error::Preempted(conflict)) = prepared else {
+                return prepared;
+            };
+            self.observer.on_conflict(round, conflict);
+            attempts += 1;
+            if self.retry_policy.exhausted(attempts) {
+                return Err(Error::RetriesExhausted {
+                    attempts,
+                    last: Some(Box::new(Error::Preempted(conflict))),
+                });
+            }
+            let backoff = self.retry_policy.backoff(attempts, &mut self.rng);
+            self.clock.sleep(backoff).await;
+            round = self
+                .ticks
+                .advance(round.next().max(round.greater_than(conflict)), &*self.clock);
+        }
+    }
+
+    fn stage_timeout(&self) -> Option<Duration> {
+        self.timeouts
+            .as_ref()
+            .and_then(|timeouts| timeouts.stage_timeout(self.peers.majority()))
+            .or(self.stage_timeout)
+    }
+}
+
+impl Ticks {
+    pub fn new(source: TickSource) -> Self {
+        Self::with_state(TickState {
+            source,
+            last: None,
+            store: None,
+        })
+    }
+
+    pub fn storage<V, S>(self, storage: S) -> Result<Self, Error>
+    where
+        S: Storage<V> + Send + 'static,
+    {
+        let (last, store) = tick_store(storage)?;
+        {
+            let mut state = lock(&self.inner);
+            state.last = state.last.max(last);
+            state.store = Some(store);
+        }
+        Ok(self)
+    }
+
+    fn with_state(state: TickState) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    pub(crate) fn first_round(&self, id: Id, clock: &dyn Clock) -> Round {
+        let state = lock(&self.inner);
+        let round = state
+            .last
+            .map_or(Round::new(id), |tick| Round::resume(id, tick));
+        state.advance(round, clock)
+    }
+
+    pub(crate) fn advance(&self, round: Round, clock: &dyn Clock) -> Round {
+        lock(&self.inner).advance(round, clock)
+    }
+
+    pub(crate) fn claim(&self, round: Round, clock: &dyn Clock) -> Result<Round, Error> {
+        let mut state = lock(&self.inner);
+        let mut round = state.advance(round, clock);
+        if let Some(last) = state.last.filter(|last| round.tick.0 <= *last) {
+            round = Round::resume(round.process_id, last);
+        }
+        state.record(round)
+    }
+
+    fn record(&self, round: Round) -> Result<Round, Error> {
+        lock(&self.inner).record(round)
+    }
+}
+
+fn tick_store<V, S>(mut storage: S) -> Result<(Option<u64>, TickStore), Error>
+where
+    S: Storage<V> + Send + 'static,
+{
+    let last = storage
+        .load_tick()
+        .map_err(|error| Error::Storage(Box::new(error)))?;
+    let store = Box::new(move |tick| {
+        storage
+            .persist_tick(tick)
+            .map_err(|error| Error::Storage(Box::new(error)))
+    });
+    Ok((last, store))
+}
+
+impl TickState {
+    fn advance(&self, round: Round, clock: &dyn Clock) -> Round {
+        match self.source {
+            TickSource::Counter => round,
+            TickSource::Hybrid => {
+                let physical = Timestamp::physical(clock).to_bits();
+                round.max(Round::resume(round.process_id, physical))
             }
         }
     }
+
+    fn record(&mut self, round: Round) -> Result<Round, Error> {
+        if self.last.is_some_and(|tick| round.tick.0 <= tick) {
+            return Ok(round);
+        }
+        if let Some(store) = &mut self.store {
+            store(round.tick.0)?;
+        }
+        self.last = Some(round.tick.0);
+        Ok(round)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct ProposeHandle {
+    inner: Arc<Cancellation>,
+}
+
+#[derive(Default)]
+struct Cancellation {
+    cancelled: AtomicBool,
+    waker: AtomicWaker,
 }
 
-trait FailureDetector {
+impl ProposeHandle {
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::Release);
+        self.inner.waker.wake();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+
+    async fn guard<F, S>(&self, stragglers: &Mutex<S>, future: F) -> Result<F::Output, Error>
+    where
+        F: Future,
+        S: Stream + Unpin,
+    {
+        let mut future = pin!(future);
+        poll_fn(|cx| {
+            self.inner.waker.register(cx.waker());
+            if self.is_cancelled() {
+                return Poll::Ready(Err(Error::Cancelled));
+            }
+            while let Poll::Ready(Some(_)) = lock(stragglers).poll_next_unpin(cx) {}
+            future.as_mut().poll(cx).map(Ok)
+        })
+        .await
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub trait FailureDetector {
     fn leader(&self) -> Id;
+
+    /// Resolves once `leader()` may return something new. Detectors whose
+    /// leader never changes can return [`pending`](futures::future::pending).
+    fn changed(&self) -> impl Future<Output = ()>;
+
+    fn wait_until_leader(&self, id: Id) -> impl Future<Output = ()> {
+        async move {
+            loop {
+                let changed = self.changed();
+                if self.leader() == id {
+                    return;
+                }
+                changed.await;
+            }
+        }
+    }
+}
+
+impl<D> FailureDetector for Arc<D>
+where
+    D: FailureDetector + ?Sized,
+{
+    fn leader(&self) -> Id {
+        (**self).leader()
+    }
+
+    fn changed(&self) -> impl Future<Output = ()> {
+        (**self).changed()
+    }
+
+    fn wait_until_leader(&self, id: Id) -> impl Future<Output = ()> {
+        (**self).wait_until_leader(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alpha::{ReadResponse, Value, WriteResponse};
+    use crate::learner::DecisionBroadcast;
+    use crate::metrics::PrometheusObserver;
+    use crate::storage::MemoryStorage;
+    use crate::time::MockClock;
+    use futures::executor::block_on;
+    use futures::stream;
+    use std::future;
+
+    /// Three acceptors of which only the first two ever answer; decision
+    /// acknowledgements never arrive.
+    struct Stalled(Mutex<Vec<Alpha<u64>>>);
+
+    impl Stalled {
+        fn new() -> Self {
+            Self(Mutex::new((0..3).map(|_| Alpha::new()).collect()))
+        }
+
+        fn respond<T>(&self, mut f: impl FnMut(Id, &mut Alpha<u64>) -> T) -> impl Stream<Item = T> {
+            let responses: Vec<_> = lock(&self.0)
+                .iter_mut()
+                .enumerate()
+                .take(2)
+                .map(|(index, alpha)| f(Id(index as u64), alpha))
+                .collect();
+            stream::iter(responses).chain(stream::pending())
+        }
+    }
+
+    impl ReadPeers<u64> for Stalled {
+        fn read(&self, round: Round) -> impl Stream<Item = Result<ReadResponse<u64>, Error>> {
+            self.respond(move |id, alpha| Ok(alpha.read(id, round)))
+        }
+    }
+
+    impl WritePeers<u64> for Stalled {
+        fn write(&self, value: Value<u64>) -> impl Stream<Item = Result<WriteResponse, Error>> {
+            self.respond(move |id, alpha| Ok(alpha.write(id, value.clone())))
+        }
+    }
+
+    impl DecisionPeers<u64> for Stalled {
+        fn decide(&self, _: DecisionBroadcast<u64>) -> impl Stream<Item = Result<(), Error>> {
+            stream::pending()
+        }
+    }
+
+    impl Quorum for Stalled {
+        fn majority(&self) -> usize {
+            2
+        }
+
+        fn max_failures(&self) -> usize {
+            1
+        }
+    }
+
+    /// Acceptor 2 answers reads but misses every broadcast write.
+    #[derive(Clone)]
+    struct Lagging(Arc<Mutex<Vec<Alpha<u64>>>>);
+
+    impl Lagging {
+        fn respond<T>(
+            &self,
+            acceptors: &[usize],
+            mut f: impl FnMut(Id, &mut Alpha<u64>) -> T,
+        ) -> impl Stream<Item = T> {
+            let mut alphas = lock(&self.0);
+            let responses: Vec<_> = acceptors
+                .iter()
+                .map(|&index| f(Id(index as u64), &mut alphas[index]))
+                .collect();
+            stream::iter(responses)
+        }
+    }
+
+    impl ReadPeers<u64> for Lagging {
+        fn read(&self, round: Round) -> impl Stream<Item = Result<ReadResponse<u64>, Error>> {
+            self.respond(&[0, 2], move |id, alpha| Ok(alpha.read(id, round)))
+        }
+    }
+
+    impl WritePeers<u64> for Lagging {
+        fn write(&self, value: Value<u64>) -> impl Stream<Item = Result<WriteResponse, Error>> {
+            self.respond(&[0, 1], move |id, alpha| Ok(alpha.write(id, value.clone())))
+        }
+
+        fn write_to(
+            &self,
+            acceptors: &[Id],
+            value: Value<u64>,
+        ) -> impl Stream<Item = Result<WriteResponse, Error>> {
+            let acceptors: Vec<_> = acceptors.iter().map(|id| id.0 as usize).collect();
+            self.respond(&acceptors, move |id, alpha| {
+                Ok(alpha.write(id, value.clone()))
+            })
+        }
+    }
+
+    impl DecisionPeers<u64> for Lagging {
+        fn decide(&self, _: DecisionBroadcast<u64>) -> impl Stream<Item = Result<(), Error>> {
+            stream::empty()
+        }
+    }
+
+    impl Quorum for Lagging {
+        fn majority(&self) -> usize {
+            2
+        }
+
+        fn max_failures(&self) -> usize {
+            1
+        }
+    }
+
+    struct Leader;
+
+    impl FailureDetector for Leader {
+        fn leader(&self) -> Id {
+            Id(1)
+        }
+
+        fn changed(&self) -> impl Future<Output = ()> {
+            future::pending()
+        }
+    }
+
+    #[test]
+    fn hybrid_ticks_follow_the_clock() {
+        let ticks = Ticks::new(TickSource::Hybrid);
+        let clock = MockClock::new();
+        let first = ticks
+            .claim(ticks.first_round(Id(1), &clock), &clock)
+            .unwrap();
+        clock.advance(Duration::from_secs(1));
+        let second = ticks.claim(first.next(), &clock).unwrap();
+        assert!(second.tick.0 - first.tick.0 >= 1000 << 16);
+    }
+
+    #[test]
+    fn returns_without_waiting_for_decision_acks() {
+        let mut proposer = Proposer::builder(Id(1), Stalled::new(), Leader).build();
+        assert_eq!(block_on(proposer.propose(7)).unwrap(), 7);
+    }
+
+    #[test]
+    fn waits_for_leadership_until_told_it_changed() {
+        use futures::channel::oneshot;
+        use futures::FutureExt;
+
+        struct Handover(Mutex<(Id, Vec<oneshot::Sender<()>>)>);
+
+        impl FailureDetector for Handover {
+            fn leader(&self) -> Id {
+                lock(&self.0).0
+            }
+
+            fn changed(&self) -> impl Future<Output = ()> {
+                let (sender, receiver) = oneshot::channel();
+                lock(&self.0).1.push(sender);
+                receiver.map(drop)
+            }
+        }
+
+        let detector = Handover(Mutex::new((Id(0), Vec::new())));
+        let mut elected = pin!(detector.wait_until_leader(Id(1)));
+        assert!(elected.as_mut().now_or_never().is_none());
+        assert!(elected.as_mut().now_or_never().is_none());
+        assert_eq!(lock(&detector.0).1.len(), 1);
+
+        let mut state = lock(&detector.0);
+        state.0 = Id(1);
+        for watcher in state.1.drain(..) {
+            let _ = watcher.send(());
+        }
+        drop(state);
+        assert!(elected.now_or_never().is_some());
+    }
+
+    #[test]
+    fn returns_without_waiting_for_read_repair() {
+        let peers = Stalled::new();
+        lock(&peers.0)[0].write(Id(0), Value::new(5, Round::new(Id(0))));
+        let mut proposer = Proposer::builder(Id(1), peers, Leader)
+            .read_repair(true)
+            .build();
+        assert_eq!(block_on(proposer.propose(7)).unwrap(), 5);
+    }
+
+    #[test]
+    fn read_repair_reaches_acceptors_the_write_missed() {
+        let peers = Lagging(Arc::new(Mutex::new((0..3).map(|_| Alpha::new()).collect())));
+        lock(&peers.0)[0].write(Id(0), Value::new(5, Round::new(Id(0))));
+        let mut proposer = Proposer::builder(Id(1), peers.clone(), Leader)
+            .read_repair(true)
+            .build();
+        assert_eq!(block_on(proposer.propose(7)).unwrap(), 5);
+        let alphas = lock(&peers.0);
+        assert_eq!(alphas[2].accepted_value(), Some(&5));
+        assert_eq!(alphas[2].accepted_round(), alphas[0].accepted_round());
+    }
+
+    #[test]
+    fn stale_acceptors_stay_stale_without_read_repair() {
+        let peers = Lagging(Arc::new(Mutex::new((0..3).map(|_| Alpha::new()).collect())));
+        lock(&peers.0)[0].write(Id(0), Value::new(5, Round::new(Id(0))));
+        let mut proposer = Proposer::builder(Id(1), peers.clone(), Leader).build();
+        assert_eq!(block_on(proposer.propose(7)).unwrap(), 5);
+        assert_eq!(lock(&peers.0)[2].accepted_value(), None);
+    }
+
+    #[test]
+    fn reports_to_the_observer() {
+        let observer = Arc::new(PrometheusObserver::default());
+        let mut proposer = Proposer::builder(Id(1), Stalled::new(), Leader)
+            .observer(observer.clone())
+            .build();
+        block_on(proposer.propose(7)).unwrap();
+        let metrics = observer.render();
+        assert!(
+            metrics.contains("paxos_rounds_started_total 1"),
+            "{metrics}"
+        );
+        assert!(
+            metrics.contains("paxos_decision_latency_seconds_count 1"),
+            "{metrics}"
+        );
+    }
+
+    #[test]
+    fn returns_without_waiting_for_every_acceptor() {
+        let mut proposer = Proposer::builder(Id(1), Stalled::new(), Leader)
+            .delivery(Delivery::All)
+            .build();
+        assert_eq!(block_on(proposer.propose(7)).unwrap(), 7);
+    }
+
+    #[test]
+    fn shared_ticks_never_reuse_a_round() {
+        let ticks = Ticks::new(TickSource::Counter)
+            .storage::<u64, _>(MemoryStorage::default())
+            .unwrap();
+        let other = ticks.clone();
+        let id = Id(1);
+        let clock = SystemClock;
+        let first = ticks.claim(ticks.first_round(id, &clock), &clock).unwrap();
+        let second = other.claim(other.first_round(id, &clock), &clock).unwrap();
+        let third = ticks.claim(first, &clock).unwrap();
+        assert!(first < second);
+        assert!(second < third);
+    }
 }
@@ -0,0 +1,418 @@
+use crate::alpha::{
+    Error, Id, Quorum, ReadPeers, ReadResponse, Round, Value, WritePeers, WriteResponse,
+};
+use crate::learner::{DecisionBroadcast, DecisionPeers};
+use futures::Stream;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, PoisonError};
+use thiserror::Error;
+
+#[derive(Clone, Debug)]
+pub enum QuorumSpec {
+    Majority {
+        members: Vec<Id>,
+    },
+    Flexible {
+        members: Vec<Id>,
+        read: usize,
+        write: usize,
+    },
+    Grid {
+        rows: Vec<Vec<Id>>,
+    },
+    Weighted {
+        weights: Vec<(Id, u64)>,
+        read: u64,
+        write: u64,
+    },
+    /// Quorums of `(members + faults) / 2 + 1`, so any two share more than
+    /// `faults` members. Only the sizes change: acceptors are still trusted
+    /// to report what they accepted, so this does not tolerate Byzantine
+    /// faults, and `auth` only rules out forged messages, not lying members.
+    Overlap {
+        members: Vec<Id>,
+        faults: usize,
+    },
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum QuorumError {
+    #[error("quorum has no members")]
+    Empty,
+    #[error("member {0:?} appears more than once")]
+    DuplicateMember(Id),
+    #[error("quorum size {size} is outside 1..={total}")]
+    OutOfRange { size: u64, total: u64 },
+    #[error("read quorum {read} and write quorum {write} do not intersect within {total}")]
+    NoIntersection { read: u64, write: u64, total: u64 },
+    #[error("{members} members cannot overlap every quorum by more than {faults}")]
+    TooManyFaults { members: usize, faults: usize },
+}
+
+impl QuorumSpec {
+    pub fn validate(&self) -> Result<(), QuorumError> {
+        let members = self.members();
+        if members.is_empty() {
+            return Err(QuorumError::Empty);
+        }
+        let mut seen = HashSet::new();
+        if let Some(duplicate) = members.iter().find(|member| !seen.insert(**member)) {
+            return Err(QuorumError::DuplicateMember(*duplicate));
+        }
+
+        match self {
+            QuorumSpec::Majority { .. } => Ok(()),
+            QuorumSpec::Flexible {
+                members,
+                read,
+                write,
+            } => check_sizes(*read as u64, *write as u64, members.len() as u64),
+            QuorumSpec::Grid { rows } => {
+                if rows.iter().any(Vec::is_empty) {
+                    return Err(QuorumError::Empty);
+                }
+                Ok(())
+            }
+            QuorumSpec::Weighted {
+                weights,
+                read,
+                write,
+            } => check_sizes(*read, *write, weights.iter().map(|(_, w)| w).sum()),
+            QuorumSpec::Overlap { members, faults } => {
+                if members.len() <= 3 * faults {
+                    return Err(QuorumError::TooManyFaults {
+                        members: members.len(),
+                        faults: *faults,
+                    });
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn members(&self) -> Vec<Id> {
+        match self {
+            QuorumSpec::Majority { members }
+            | QuorumSpec::Flexible { members, .. }
+            | QuorumSpec::Overlap { members, .. } => members.clone(),
+            QuorumSpec::Grid { rows } => rows.iter().flatten().copied().collect(),
+            QuorumSpec::Weighted { weights, .. } => weights.iter().map(|(id, _)| *id).collect(),
+        }
+    }
+
+    fn smallest_quorum(&self) -> usize {
+        match self {
+            QuorumSpec::Majority { members } => members.len() / 2 + 1,
+            QuorumSpec::Overlap { members, faults } => (members.len() + faults) / 2 + 1,
+            QuorumSpec::Flexible { read, write, .. } => *read.min(write),
+            QuorumSpec::Grid { rows } => {
+                rows.iter().map(Vec::len).min().unwrap_or(0).min(rows.len())
+            }
+            QuorumSpec::Weighted {
+                weights,
+                read,
+                write,
+            } => {
+                let mut heaviest: Vec<_> = weights.iter().map(|(_, weight)| *weight).collect();
+                heaviest.sort_unstable_by(|a, b| b.cmp(a));
+                let mut total = 0;
+                heaviest
+                    .into_iter()
+                    .take_while(|weight| {
+                        let reached = total >= *read.min(write);
+                        total += weight;
+                        !reached
+                    })
+                    .count()
+            }
+        }
+    }
+
+    fn count(&self, acceptors: &HashSet<Id>) -> u64 {
+        match self {
+            QuorumSpec::Weighted { weights, .. } => weights
+                .iter()
+                .filter(|(id, _)| acceptors.contains(id))
+                .map(|(_, weight)| weight)
+                .sum(),
+            _ => self
+                .members()
+                .iter()
+                .filter(|id| acceptors.contains(id))
+                .count() as u64,
+        }
+    }
+}
+
+fn check_sizes(read: u64, write: u64, total: u64) -> Result<(), QuorumError> {
+    for size in [read, write] {
+        if size == 0 || size > total {
+            return Err(QuorumError::OutOfRange { size, total });
+        }
+    }
+    if read + write <= total {
+        return Err(QuorumError::NoIntersection { read, write, total });
+    }
+    Ok(())
+}
+
+impl Quorum for QuorumSpec {
+    fn majority(&self) -> usize {
+        self.members().len() / 2 + 1
+    }
+
+    fn max_failures(&self) -> usize {
+        self.members().len().saturating_sub(self.smallest_quorum())
+    }
+
+    fn is_read_quorum(&self, acceptors: &HashSet<Id>) -> bool {
+        match self {
+            QuorumSpec::Majority { .. } => self.count(acceptors) >= self.majority() as u64,
+            QuorumSpec::Flexible { read, .. } => self.count(acceptors) >= *read as u64,
+            QuorumSpec::Grid { rows } => rows
+                .iter()
+                .any(|row| row.iter().all(|id| acceptors.contains(id))),
+            QuorumSpec::Weighted { read, .. } => self.count(acceptors) >= *read,
+            QuorumSpec::Overlap { .. } => self.count(acceptors) >= self.smallest_quorum() as u64,
+        }
+    }
+
+    fn is_write_quorum(&self, acceptors: &HashSet<Id>) -> bool {
+        match self {
+            QuorumSpec::Majority { .. } => self.count(acceptors) >= self.majority() as u64,
+            QuorumSpec::Flexible { write, .. } => self.count(acceptors) >= *write as u64,
+            QuorumSpec::Grid { rows } => rows
+                .iter()
+                .all(|row| row.iter().any(|id| acceptors.contains(id))),
+            QuorumSpec::Weighted { write, .. } => self.count(acceptors) >= *write,
+            QuorumSpec::Overlap { .. } => self.count(acceptors) >= self.smallest_quorum() as u64,
+        }
+    }
+}
+
+pub struct WithQuorum<P, Q = QuorumSpec> {
+    peers: P,
+    quorum: Q,
+}
+
+impl<P> WithQuorum<P> {
+    pub fn new(peers: P, spec: QuorumSpec) -> Result<Self, QuorumError> {
+        spec.validate()?;
+        Ok(Self::from_quorum(peers, spec))
+    }
+}
+
+impl<P, Q> WithQuorum<P, Q> {
+    pub fn from_quorum(peers: P, quorum: Q) -> Self {
+        Self { peers, quorum }
+    }
+}
+
+impl<V, P: ReadPeers<V>, Q> ReadPeers<V> for WithQuorum<P, Q> {
+    fn read(&self, round: Round) -> impl Stream<Item = Result<ReadResponse<V>, Error>> {
+        self.peers.read(round)
+    }
+}
+
+impl<V, P: WritePeers<V>, Q> WritePeers<V> for WithQuorum<P, Q> {
+    fn write(&self, value: Value<V>) -> impl Stream<Item = Result<WriteResponse, Error>> {
+        self.peers.write(value)
+    }
+
+    fn write_to(
+        &self,
+        acceptors: &[Id],
+        value: Value<V>,
+    ) -> impl Stream<Item = Result<WriteResponse, Error>> {
+        self.peers.write_to(acceptors, value)
+    }
+}
+
+impl<V, P: DecisionPeers<V>, Q> DecisionPeers<V> for WithQuorum<P, Q> {
+    fn decide(&self, decision: DecisionBroadcast<V>) -> impl Stream<Item = Result<(), Error>> {
+        self.peers.decide(decision)
+    }
+}
+
+impl<P, Q: Quorum> Quorum for WithQuorum<P, Q> {
+    fn majority(&self) -> usize {
+        self.quorum.majority()
+    }
+
+    fn max_failures(&self) -> usize {
+        self.quorum.max_failures()
+    }
+
+    fn is_read_quorum(&self, acceptors: &HashSet<Id>) -> bool {
+        self.quorum.is_read_quorum(acceptors)
+    }
+
+    fn is_write_quorum(&self, acceptors: &HashSet<Id>) -> bool {
+        self.quorum.is_write_quorum(acceptors)
+    }
+}
+
+/// A quorum that can be swapped for a newly committed configuration while
+/// clones of it are held elsewhere, such as by a server checking decisions.
+#[derive(Clone)]
+pub struct SharedQuorum(Arc<Mutex<Arc<dyn Quorum + Send + Sync>>>);
+
+impl SharedQuorum {
+    pub fn new(quorum: impl Quorum + Send + Sync + 'static) -> Self {
+        Self(Arc::new(Mutex::new(Arc::new(quorum))))
+    }
+
+    pub fn set(&self, quorum: impl Quorum + Send + Sync + 'static) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = Arc::new(quorum);
+    }
+
+    fn current(&self) -> Arc<dyn Quorum + Send + Sync> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+impl Quorum for SharedQuorum {
+    fn majority(&self) -> usize {
+        self.current().majority()
+    }
+
+    fn max_failures(&self) -> usize {
+        self.current().max_failures()
+    }
+
+    fn is_read_quorum(&self, acceptors: &HashSet<Id>) -> bool {
+        self.current().is_read_quorum(acceptors)
+    }
+
+    fn is_write_quorum(&self, acceptors: &HashSet<Id>) -> bool {
+        self.current().is_write_quorum(acceptors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(range: std::ops::Range<u64>) -> Vec<Id> {
+        range.map(Id).collect()
+    }
+
+    fn set(range: std::ops::Range<u64>) -> HashSet<Id> {
+        range.map(Id).collect()
+    }
+
+    #[test]
+    fn validate_rejects_malformed_specs() {
+        let empty = QuorumSpec::Majority { members: vec![] };
+        assert_eq!(empty.validate(), Err(QuorumError::Empty));
+        let duplicate = QuorumSpec::Majority {
+            members: vec![Id(1), Id(2), Id(1)],
+        };
+        assert_eq!(
+            duplicate.validate(),
+            Err(QuorumError::DuplicateMember(Id(1)))
+        );
+        let empty_row = QuorumSpec::Grid {
+            rows: vec![ids(0..2), vec![]],
+        };
+        assert_eq!(empty_row.validate(), Err(QuorumError::Empty));
+        let oversized = QuorumSpec::Flexible {
+            members: ids(0..3),
+            read: 4,
+            write: 2,
+        };
+        assert_eq!(
+            oversized.validate(),
+            Err(QuorumError::OutOfRange { size: 4, total: 3 })
+        );
+        let disjoint = QuorumSpec::Weighted {
+            weights: vec![(Id(0), 3), (Id(1), 1), (Id(2), 1)],
+            read: 2,
+            write: 3,
+        };
+        assert_eq!(
+            disjoint.validate(),
+            Err(QuorumError::NoIntersection {
+                read: 2,
+                write: 3,
+                total: 5
+            })
+        );
+        let faulty = QuorumSpec::Overlap {
+            members: ids(0..6),
+            faults: 2,
+        };
+        assert_eq!(
+            faulty.validate(),
+            Err(QuorumError::TooManyFaults {
+                members: 6,
+                faults: 2
+            })
+        );
+    }
+
+    #[test]
+    fn flexible_reads_and_writes_intersect() {
+        let spec = QuorumSpec::Flexible {
+            members: ids(0..5),
+            read: 4,
+            write: 2,
+        };
+        spec.validate().unwrap();
+        assert!(spec.is_write_quorum(&set(3..5)));
+        assert!(!spec.is_read_quorum(&set(3..5)));
+        assert!(spec.is_read_quorum(&set(0..4)));
+        assert!(!spec.is_write_quorum(&set(4..5)));
+        assert_eq!(spec.max_failures(), 3);
+
+        let too_small = QuorumSpec::Flexible {
+            members: ids(0..5),
+            read: 3,
+            write: 2,
+        };
+        assert!(matches!(
+            too_small.validate(),
+            Err(QuorumError::NoIntersection { .. })
+        ));
+    }
+
+    #[test]
+    fn overlap_quorums_share_more_than_the_faults() {
+        let spec = QuorumSpec::Overlap {
+            members: ids(0..7),
+            faults: 2,
+        };
+        spec.validate().unwrap();
+        assert!(spec.is_read_quorum(&set(0..5)));
+        assert!(!spec.is_read_quorum(&set(0..4)));
+        assert!(spec.is_write_quorum(&set(2..7)));
+        assert_eq!(spec.max_failures(), 2);
+        let shared = set(0..5).intersection(&set(2..7)).count();
+        assert!(shared > 2);
+    }
+
+    #[test]
+    fn grid_reads_need_a_row_and_writes_a_column() {
+        let spec = QuorumSpec::Grid {
+            rows: vec![ids(0..3), ids(3..6)],
+        };
+        spec.validate().unwrap();
+        assert!(spec.is_read_quorum(&set(3..6)));
+        assert!(!spec.is_read_quorum(&set(2..5)));
+        assert!(spec.is_write_quorum(&[Id(0), Id(4)].into()));
+        assert!(!spec.is_write_quorum(&set(0..3)));
+    }
+
+    #[test]
+    fn shared_quorums_follow_the_latest_configuration() {
+        let shared = SharedQuorum::new(QuorumSpec::Majority { members: ids(0..3) });
+        let held = shared.clone();
+        assert!(held.is_write_quorum(&set(0..2)));
+        shared.set(QuorumSpec::Majority { members: ids(0..5) });
+        assert!(!held.is_write_quorum(&set(0..2)));
+        assert!(held.is_write_quorum(&set(0..3)));
+    }
+}
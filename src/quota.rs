@@ -0,0 +1,276 @@
+//! How much each client may propose, so one tenant cannot monopolize the
+//! log. A [`RateLimiter`] keeps a bucket per client: the authenticated
+//! identity that sent the proposal, else the token it presented, else one
+//! bucket every anonymous client shares. A bucket holds one second of the
+//! client's [`Quota`], so a quiet client may burst that much at once; a
+//! proposal that would overdraw it is refused at once with how long until
+//! it fits, and nothing waits.
+//!
+//! Quotas are written `proposals:100+bytes:1048576`, per second, and a
+//! config lists the default first and then any identity's own:
+//! `proposals:100,9=proposals:10+bytes:4096`.
+
+use crate::alpha::Id;
+use crate::digest::sha256;
+use crate::time::Clock;
+use std::collections::HashMap;
+use std::num::NonZeroU64;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Once this many clients have buckets, those that have refilled are
+/// forgotten, which changes nothing.
+const SWEEP_AFTER: usize = 1024;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum QuotaError {
+    #[error("invalid quota `{0}`, expected `proposals:<n>[+bytes:<n>]`")]
+    Invalid(String),
+}
+
+/// Proposals and bytes one client may send per second; what is `None` is
+/// not limited.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Quota {
+    pub proposals: Option<NonZeroU64>,
+    pub bytes: Option<NonZeroU64>,
+}
+
+/// The quota every client gets, and the identities given their own.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Quotas {
+    pub default: Quota,
+    pub identities: Vec<(Id, Quota)>,
+}
+
+/// Whose bucket a proposal is counted against. Tokens are kept only as
+/// their digest.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Tenant {
+    Identity(Id),
+    Token([u8; 32]),
+    Anonymous,
+}
+
+impl Tenant {
+    pub fn new(from: Option<Id>, token: Option<&str>) -> Self {
+        match (from, token) {
+            (Some(id), _) => Tenant::Identity(id),
+            (None, Some(token)) => Tenant::Token(sha256(token.as_bytes())),
+            (None, None) => Tenant::Anonymous,
+        }
+    }
+}
+
+pub struct RateLimiter {
+    quotas: Quotas,
+    clock: Arc<dyn Clock>,
+    buckets: Mutex<HashMap<Tenant, Bucket>>,
+}
+
+struct Bucket {
+    proposals: f64,
+    bytes: f64,
+    at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(quotas: Quotas, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            quotas,
+            clock,
+            buckets: Mutex::default(),
+        }
+    }
+
+    pub fn quota(&self, tenant: &Tenant) -> Quota {
+        match tenant {
+            Tenant::Identity(id) => self
+                .quotas
+                .identities
+                .iter()
+                .find(|(known, _)| known == id)
+                .map_or(self.quotas.default, |(_, quota)| *quota),
+            Tenant::Token(_) | Tenant::Anonymous => self.quotas.default,
+        }
+    }
+
+    /// Counts a proposal of `bytes` against `tenant`'s bucket, or says how
+    /// long until it fits. A value larger than a whole second of bytes goes
+    /// through once the bucket is full, leaving it in debt.
+    pub fn admit(&self, tenant: &Tenant, bytes: usize) -> Result<(), Duration> {
+        let quota = self.quota(tenant);
+        let now = self.clock.now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        if buckets.len() >= SWEEP_AFTER {
+            buckets.retain(|tenant, bucket| !bucket.full(self.quota(tenant), now));
+        }
+        let bucket = buckets.entry(tenant.clone()).or_insert(Bucket {
+            proposals: capacity(quota.proposals),
+            bytes: capacity(quota.bytes),
+            at: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.at).as_secs_f64();
+        bucket.at = now;
+        let wait = refill(&mut bucket.proposals, quota.proposals, elapsed, 1.0).max(refill(
+            &mut bucket.bytes,
+            quota.bytes,
+            elapsed,
+            bytes as f64,
+        ));
+        if wait > 0.0 {
+            // Rounded up, so a client that waits this long always fits.
+            return Err(Duration::from_millis((wait * 1000.0).ceil() as u64));
+        }
+        bucket.proposals -= 1.0;
+        bucket.bytes -= bytes as f64;
+        Ok(())
+    }
+}
+
+impl Bucket {
+    fn full(&self, quota: Quota, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.at).as_secs_f64();
+        let full = |level: f64, rate: Option<NonZeroU64>| {
+            rate.is_none_or(|rate| level + elapsed * rate.get() as f64 >= rate.get() as f64)
+        };
+        full(self.proposals, quota.proposals) && full(self.bytes, quota.bytes)
+    }
+}
+
+fn capacity(rate: Option<NonZeroU64>) -> f64 {
+    rate.map_or(f64::INFINITY, |rate| rate.get() as f64)
+}
+
+/// Refills `level` for `elapsed` seconds at `rate`, returning the seconds
+/// until it holds `cost`, or as much as it can hold.
+fn refill(level: &mut f64, rate: Option<NonZeroU64>, elapsed: f64, cost: f64) -> f64 {
+    let Some(rate) = rate.map(|rate| rate.get() as f64) else {
+        return 0.0;
+    };
+    *level = (*level + elapsed * rate).min(rate);
+    (cost.min(rate) - *level).max(0.0) / rate
+}
+
+impl FromStr for Quota {
+    type Err = QuotaError;
+
+    fn from_str(quota: &str) -> Result<Self, Self::Err> {
+        let invalid = || QuotaError::Invalid(quota.to_string());
+        quota
+            .split('+')
+            .map(str::trim)
+            .try_fold(Quota::default(), |mut parsed, limit| {
+                let (name, rate) = limit.split_once(':').ok_or_else(invalid)?;
+                let rate = rate.trim().parse().map_err(|_| invalid())?;
+                match name.trim() {
+                    "proposals" => parsed.proposals = Some(rate),
+                    "bytes" => parsed.bytes = Some(rate),
+                    _ => return Err(invalid()),
+                }
+                Ok(parsed)
+            })
+    }
+}
+
+impl FromStr for Quotas {
+    type Err = QuotaError;
+
+    fn from_str(quotas: &str) -> Result<Self, Self::Err> {
+        let mut parsed = Quotas::default();
+        for entry in quotas
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            match entry.split_once('=') {
+                None => parsed.default = entry.parse()?,
+                Some((id, quota)) => {
+                    let id = id
+                        .trim()
+                        .parse()
+                        .map_err(|_| QuotaError::Invalid(entry.to_string()))?;
+                    let id = Id::new(id);
+                    parsed.identities.retain(|(known, _)| *known != id);
+                    parsed.identities.push((id, quota.parse()?));
+                }
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::MockClock;
+
+    fn limiter(quotas: &str) -> (RateLimiter, MockClock) {
+        let clock = MockClock::new();
+        let limiter = RateLimiter::new(quotas.parse().unwrap(), Arc::new(clock.clone()));
+        (limiter, clock)
+    }
+
+    #[test]
+    fn parses_the_default_quota_and_each_identitys_own() {
+        let quotas: Quotas = "proposals:100, 9=proposals:10+bytes:4096".parse().unwrap();
+        assert_eq!(quotas.default.proposals, NonZeroU64::new(100));
+        assert_eq!(quotas.default.bytes, None);
+        assert_eq!(
+            quotas.identities,
+            [(
+                Id(9),
+                Quota {
+                    proposals: NonZeroU64::new(10),
+                    bytes: NonZeroU64::new(4096),
+                }
+            )]
+        );
+        assert_eq!("".parse::<Quotas>().unwrap(), Quotas::default());
+        for invalid in ["proposals:0", "calls:5", "proposals", "nine=bytes:1"] {
+            assert!(matches!(
+                invalid.parse::<Quotas>(),
+                Err(QuotaError::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn refuses_what_overdraws_a_clients_bucket_until_it_refills() {
+        let (limiter, clock) = limiter("proposals:2+bytes:100,9=proposals:1");
+        let alice = Tenant::new(None, Some("alice"));
+        assert_eq!(limiter.admit(&alice, 10), Ok(()));
+        assert_eq!(limiter.admit(&alice, 10), Ok(()));
+        assert_eq!(limiter.admit(&alice, 10), Err(Duration::from_millis(500)));
+        // Other clients have buckets of their own.
+        assert_eq!(limiter.admit(&Tenant::new(None, Some("bob")), 10), Ok(()));
+        assert_eq!(limiter.admit(&Tenant::Anonymous, 10), Ok(()));
+        assert_eq!(limiter.admit(&Tenant::new(Some(Id(9)), None), 10), Ok(()));
+        assert_eq!(
+            limiter.admit(&Tenant::new(Some(Id(9)), Some("alice")), 10),
+            Err(Duration::from_secs(1))
+        );
+
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(limiter.admit(&alice, 10), Ok(()));
+
+        // Bytes run out before proposals do; a value larger than a second
+        // of bytes waits for a full bucket and then goes through.
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(limiter.admit(&alice, 70), Ok(()));
+        assert_eq!(limiter.admit(&alice, 150), Err(Duration::from_millis(700)));
+        clock.advance(Duration::from_millis(700));
+        assert_eq!(limiter.admit(&alice, 150), Ok(()));
+        assert_eq!(limiter.admit(&alice, 1), Err(Duration::from_millis(510)));
+    }
+
+    #[test]
+    fn unlimited_quotas_admit_everything() {
+        let (limiter, _) = limiter("");
+        for _ in 0..10_000 {
+            assert_eq!(limiter.admit(&Tenant::Anonymous, usize::MAX), Ok(()));
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use crate::alpha::Id;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use thiserror::Error;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeInfo {
+    pub addr: SocketAddr,
+    pub name: Option<String>,
+    pub metadata: BTreeMap<String, String>,
+}
+
+#[derive(Error, Debug)]
+pub enum RegistryError {
+    #[error("node {0:?} is already registered")]
+    DuplicateId(Id),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct NodeRegistry {
+    nodes: BTreeMap<Id, NodeInfo>,
+}
+
+impl NodeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, id: Id, addr: SocketAddr) -> Result<&mut NodeInfo, RegistryError> {
+        self.insert(id, addr, None)
+    }
+
+    pub fn register_named(&mut self, name: &str, addr: SocketAddr) -> Result<Id, RegistryError> {
+        let id = Id::from_name(name);
+        self.insert(id, addr, Some(name.to_string()))?;
+        Ok(id)
+    }
+
+    pub fn remove(&mut self, id: Id) -> Option<NodeInfo> {
+        self.nodes.remove(&id)
+    }
+
+    pub fn get(&self, id: Id) -> Option<&NodeInfo> {
+        self.nodes.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: Id) -> Option<&mut NodeInfo> {
+        self.nodes.get_mut(&id)
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<Id> {
+        let id = Id::from_name(name);
+        self.nodes
+            .get(&id)
+            .filter(|info| info.name.as_deref() == Some(name))
+            .map(|_| id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Id, &NodeInfo)> {
+        self.nodes.iter().map(|(id, info)| (*id, info))
+    }
+
+    pub fn members(&self) -> Vec<(Id, SocketAddr)> {
+        self.iter().map(|(id, info)| (id, info.addr)).collect()
+    }
+
+    fn insert(
+        &mut self,
+        id: Id,
+        addr: SocketAddr,
+        name: Option<String>,
+    ) -> Result<&mut NodeInfo, RegistryError> {
+        if self.nodes.contains_key(&id) {
+            return Err(RegistryError::DuplicateId(id));
+        }
+        Ok(self.nodes.entry(id).or_insert(NodeInfo {
+            addr,
+            name,
+            metadata: BTreeMap::new(),
+        }))
+    }
+}
@@ -0,0 +1,55 @@
+use crate::rng::XorShift;
+use std::time::Duration;
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: u32,
+    pub jitter: bool,
+    pub max_attempts: Option<usize>,
+    pub attempt_timeout: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+            multiplier: 2,
+            jitter: true,
+            max_attempts: None,
+            attempt_timeout: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn immediate() -> Self {
+        Self {
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+            jitter: false,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn exhausted(&self, attempts: usize) -> bool {
+        self.max_attempts.is_some_and(|max| attempts >= max)
+    }
+
+    pub(crate) fn backoff(&self, attempts: usize, rng: &mut XorShift) -> Duration {
+        let exponent = u32::try_from(attempts.saturating_sub(1)).unwrap_or(u32::MAX);
+        let backoff = self
+            .multiplier
+            .checked_pow(exponent)
+            .and_then(|factor| self.initial_backoff.checked_mul(factor))
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff);
+        if self.jitter && !backoff.is_zero() {
+            backoff.mul_f64(rng.unit())
+        } else {
+            backoff
+        }
+    }
+}
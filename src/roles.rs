@@ -0,0 +1,138 @@
+//! What authenticated identities other than the members may ask a member to
+//! do. Members take part in consensus and may do everything but admin;
+//! an identity given a role, written like `propose+read`, may only do what
+//! it names and never takes part in consensus.
+//!
+//! Forcing recovery is not among them: it rewrites a stopped node's files,
+//! so only whoever runs on its host can.
+
+use crate::alpha::Id;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Copy, Clone, PartialEq, Eq, Default, Hash)]
+pub struct Permissions(u8);
+
+impl Permissions {
+    pub const PROPOSE: Self = Self(1);
+    pub const READ: Self = Self(1 << 1);
+    /// Status, health and membership listings, as `paxos-admin watch` polls.
+    pub const WATCH: Self = Self(1 << 2);
+    /// Leadership transfers, snapshots, backups, log filters and membership
+    /// changes.
+    pub const ADMIN: Self = Self(1 << 3);
+    pub const ALL: Self = Self(0b1111);
+
+    pub fn empty() -> Self {
+        Self(0)
+    }
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+const NAMES: [(Permissions, &str); 4] = [
+    (Permissions::PROPOSE, "propose"),
+    (Permissions::READ, "read"),
+    (Permissions::WATCH, "watch"),
+    (Permissions::ADMIN, "admin"),
+];
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum RoleError {
+    #[error("unknown permission `{0}`")]
+    Unknown(String),
+    #[error("invalid role `{0}`, expected `<id>=<permission>[+<permission>...]`")]
+    Invalid(String),
+}
+
+impl FromStr for Permissions {
+    type Err = RoleError;
+
+    fn from_str(permissions: &str) -> Result<Self, Self::Err> {
+        permissions
+            .split('+')
+            .map(str::trim)
+            .try_fold(Self::empty(), |parsed, name| {
+                NAMES
+                    .iter()
+                    .find(|(_, known)| *known == name)
+                    .map(|(permission, _)| parsed.union(*permission))
+                    .ok_or_else(|| RoleError::Unknown(name.to_string()))
+            })
+    }
+}
+
+impl fmt::Display for Permissions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<_> = NAMES
+            .iter()
+            .filter(|(permission, _)| self.contains(*permission))
+            .map(|(_, name)| *name)
+            .collect();
+        match names.is_empty() {
+            true => f.write_str("none"),
+            false => f.write_str(&names.join("+")),
+        }
+    }
+}
+
+impl fmt::Debug for Permissions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Permissions({self})")
+    }
+}
+
+/// Parses `9=admin+watch,10=read`: the permissions of each identity named.
+pub fn parse_roles(roles: &str) -> Result<Vec<(Id, Permissions)>, RoleError> {
+    let mut parsed: Vec<(Id, Permissions)> = Vec::new();
+    for role in roles
+        .split(',')
+        .map(str::trim)
+        .filter(|role| !role.is_empty())
+    {
+        let invalid = || RoleError::Invalid(role.to_string());
+        let (id, permissions) = role.split_once('=').ok_or_else(invalid)?;
+        let id = Id::new(id.trim().parse().map_err(|_| invalid())?);
+        let permissions = permissions.parse()?;
+        parsed.retain(|(known, _)| *known != id);
+        parsed.push((id, permissions));
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_permissions_of_each_identity() {
+        let roles = parse_roles("9=admin+watch, 10=read+propose").unwrap();
+        assert_eq!(
+            roles,
+            [
+                (Id(9), Permissions::ADMIN.union(Permissions::WATCH)),
+                (Id(10), Permissions::READ.union(Permissions::PROPOSE)),
+            ]
+        );
+        assert_eq!(roles[1].1.to_string(), "propose+read");
+        assert_eq!(Permissions::empty().to_string(), "none");
+        assert!(Permissions::ALL.contains(roles[0].1));
+        assert!(!roles[1].1.contains(Permissions::WATCH));
+        assert_eq!(parse_roles("").unwrap(), []);
+
+        assert_eq!(
+            parse_roles("9=root"),
+            Err(RoleError::Unknown("root".to_string()))
+        );
+        for invalid in ["9", "nine=read"] {
+            assert!(matches!(parse_roles(invalid), Err(RoleError::Invalid(_))));
+        }
+    }
+}
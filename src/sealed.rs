@@ -0,0 +1,233 @@
+//! Values encrypted by the application before they are proposed, so the
+//! acceptors store and replicate ciphertext they cannot read. A [`Sealed`]
+//! value is opaque bytes plus the id of the [`ValueKey`] that sealed them;
+//! only whoever holds that key can open it.
+//!
+//! Sealing is ChaCha20 under a synthetic IV, the HMAC-SHA256 of the key id
+//! and the plaintext, which also authenticates the ciphertext. It needs no
+//! randomness and cannot reuse a nonce for different values, at the cost of
+//! sealing equal values to equal ciphertext: the acceptors learn when two
+//! proposals are the same value, and nothing else about it.
+
+use crate::bytes::BytesValue;
+use crate::codec::{from_bytes, to_bytes, Decode, Encode};
+use crate::digest::hmac_sha256;
+use std::fmt;
+use std::io;
+use thiserror::Error;
+
+pub(crate) const TAG: usize = 32;
+
+#[derive(Error, Debug)]
+pub enum SealError {
+    #[error("sealed with key {0}, not this one")]
+    OtherKey(u32),
+    #[error("the ciphertext was tampered with")]
+    Tampered,
+    #[error("the opened value does not decode")]
+    Decode(#[from] io::Error),
+}
+
+/// An application's key, named by an id stored beside what it seals.
+#[derive(Clone)]
+pub struct ValueKey {
+    id: u32,
+    cipher: [u8; 32],
+    mac: [u8; 32],
+}
+
+/// A value as the acceptors see it.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Sealed {
+    pub(crate) key: u32,
+    pub(crate) tag: [u8; TAG],
+    pub(crate) ciphertext: BytesValue,
+}
+
+impl ValueKey {
+    /// Derives separate cipher and authentication keys from `secret`.
+    pub fn new(id: u32, secret: &[u8]) -> Self {
+        Self {
+            id,
+            cipher: hmac_sha256(secret, b"paxos-classic value encryption"),
+            mac: hmac_sha256(secret, b"paxos-classic value authentication"),
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn seal<V: Encode>(&self, value: &V) -> Sealed {
+        let mut bytes = to_bytes(value);
+        let tag = self.tag(&bytes);
+        chacha20(&self.cipher, nonce(&tag), &mut bytes);
+        Sealed {
+            key: self.id,
+            tag,
+            ciphertext: bytes.into(),
+        }
+    }
+
+    pub fn open<V: Decode>(&self, sealed: &Sealed) -> Result<V, SealError> {
+        if sealed.key != self.id {
+            return Err(SealError::OtherKey(sealed.key));
+        }
+        let mut bytes = sealed.ciphertext.to_vec();
+        chacha20(&self.cipher, nonce(&sealed.tag), &mut bytes);
+        if !constant_time_eq(&self.tag(&bytes), &sealed.tag) {
+            return Err(SealError::Tampered);
+        }
+        Ok(from_bytes(&bytes)?)
+    }
+
+    fn tag(&self, plaintext: &[u8]) -> [u8; TAG] {
+        let mut message = Vec::with_capacity(4 + plaintext.len());
+        message.extend_from_slice(&self.id.to_be_bytes());
+        message.extend_from_slice(plaintext);
+        hmac_sha256(&self.mac, &message)
+    }
+}
+
+impl Sealed {
+    /// The id of the key that sealed this value.
+    pub fn key(&self) -> u32 {
+        self.key
+    }
+
+    pub fn ciphertext(&self) -> &[u8] {
+        &self.ciphertext
+    }
+}
+
+// Keys are secrets, so only their id is shown.
+impl fmt::Debug for ValueKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ValueKey({})", self.id)
+    }
+}
+
+impl fmt::Debug for Sealed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sealed")
+            .field("key", &self.key)
+            .field("len", &self.ciphertext.len())
+            .finish()
+    }
+}
+
+fn nonce(tag: &[u8; TAG]) -> [u8; 12] {
+    tag[..12].try_into().unwrap()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// XORs `bytes` with the RFC 8439 keystream, counting blocks from 1.
+fn chacha20(key: &[u8; 32], nonce: [u8; 12], bytes: &mut [u8]) {
+    for (counter, chunk) in (1u32..).zip(bytes.chunks_mut(64)) {
+        let block = chacha20_block(key, counter, &nonce);
+        for (byte, stream) in chunk.iter_mut().zip(block) {
+            *byte ^= stream;
+        }
+    }
+}
+
+fn chacha20_block(key: &[u8; 32], counter: u32, nonce: &[u8; 12]) -> [u8; 64] {
+    let words = |bytes: &[u8]| {
+        bytes
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect::<Vec<_>>()
+    };
+    let mut initial = [0u32; 16];
+    initial[..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+    initial[4..12].copy_from_slice(&words(key));
+    initial[12] = counter;
+    initial[13..].copy_from_slice(&words(nonce));
+
+    let mut state = initial;
+    for _ in 0..10 {
+        for [a, b, c, d] in [
+            [0, 4, 8, 12],
+            [1, 5, 9, 13],
+            [2, 6, 10, 14],
+            [3, 7, 11, 15],
+            [0, 5, 10, 15],
+            [1, 6, 11, 12],
+            [2, 7, 8, 13],
+            [3, 4, 9, 14],
+        ] {
+            state[a] = state[a].wrapping_add(state[b]);
+            state[d] = (state[d] ^ state[a]).rotate_left(16);
+            state[c] = state[c].wrapping_add(state[d]);
+            state[b] = (state[b] ^ state[c]).rotate_left(12);
+            state[a] = state[a].wrapping_add(state[b]);
+            state[d] = (state[d] ^ state[a]).rotate_left(8);
+            state[c] = state[c].wrapping_add(state[d]);
+            state[b] = (state[b] ^ state[c]).rotate_left(7);
+        }
+    }
+    let mut block = [0u8; 64];
+    for ((bytes, word), initial) in block.chunks_exact_mut(4).zip(state).zip(initial) {
+        bytes.copy_from_slice(&word.wrapping_add(initial).to_le_bytes());
+    }
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    #[test]
+    fn matches_the_rfc_8439_vector() {
+        let key: [u8; 32] = std::array::from_fn(|i| i as u8);
+        let nonce = [0, 0, 0, 0, 0, 0, 0, 0x4a, 0, 0, 0, 0];
+        let mut text = b"Ladies and Gentlemen of the class of '99: If I could offer you \
+            only one tip for the future, sunscreen would be it."
+            .to_vec();
+        chacha20(&key, nonce, &mut text);
+        assert_eq!(
+            hex(&text),
+            "6e2e359a2568f98041ba0728dd0d6981e97e7aec1d4360c20a27afccfd9fae0b\
+             f91b65c5524733ab8f593dabcd62b3571639d624e65152ab8f530c359f0861d8\
+             07ca0dbf500d6a6156a38e088a22b65e52bc514d16ccf806818ce91ab7793736\
+             5af90bbf74a35be6b40b8eedf2785e42874d"
+        );
+    }
+
+    #[test]
+    fn only_the_sealing_key_opens_untampered_values() {
+        let key = ValueKey::new(1, b"application secret");
+        let sealed = key.seal(&"transfer 10 to bob".to_string());
+        assert_eq!(sealed.key(), 1);
+        assert!(!sealed.ciphertext().windows(3).any(|word| word == b"bob"));
+        assert_eq!(key.open::<String>(&sealed).unwrap(), "transfer 10 to bob");
+        assert_eq!(from_bytes::<Sealed>(&to_bytes(&sealed)).unwrap(), sealed);
+        assert_eq!(key.seal(&"transfer 10 to bob".to_string()), sealed);
+        assert_ne!(key.seal(&"transfer 11 to bob".to_string()), sealed);
+        assert_eq!(format!("{key:?}"), "ValueKey(1)");
+
+        let mut tampered = sealed.clone();
+        let mut ciphertext = tampered.ciphertext.to_vec();
+        ciphertext[4] ^= 1;
+        tampered.ciphertext = ciphertext.into();
+        assert!(matches!(
+            key.open::<String>(&tampered),
+            Err(SealError::Tampered)
+        ));
+        assert!(matches!(
+            ValueKey::new(1, b"another secret").open::<String>(&sealed),
+            Err(SealError::Tampered)
+        ));
+        assert!(matches!(
+            ValueKey::new(2, b"application secret").open::<String>(&sealed),
+            Err(SealError::OtherKey(1))
+        ));
+    }
+}
@@ -0,0 +1,340 @@
+use crate::log::LogIndex;
+use crate::smr::{Snapshotting, StateMachine};
+use std::collections::{BTreeSet, HashMap};
+use thiserror::Error;
+
+#[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct ClientId(pub u64);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionRequest<C> {
+    pub client: ClientId,
+    pub sequence: u64,
+    pub command: C,
+}
+
+#[derive(Clone, Debug)]
+pub struct ClientSession {
+    client: ClientId,
+    next_sequence: u64,
+}
+
+impl ClientSession {
+    pub fn new(client: ClientId) -> Self {
+        Self {
+            client,
+            next_sequence: 0,
+        }
+    }
+
+    pub fn request<C>(&mut self, command: C) -> SessionRequest<C> {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        SessionRequest {
+            client: self.client,
+            sequence,
+            command,
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("request {sequence} from {client:?} was superseded by a newer request")]
+pub struct Superseded {
+    pub client: ClientId,
+    pub sequence: u64,
+}
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionError {
+    #[error(transparent)]
+    Superseded(#[from] Superseded),
+    /// The client's session was evicted, so a retry can no longer be told
+    /// apart from a new request. The client has to start a new session.
+    #[error("the session of {0:?} expired")]
+    Expired(ClientId),
+}
+
+const DEFAULT_MAX_SESSIONS: usize = 1 << 16;
+
+struct Session<O> {
+    sequence: u64,
+    output: O,
+    active: LogIndex,
+}
+
+/// Deduplicates requests per client. Sessions are evicted once idle for the
+/// configured number of log entries, or least recently active first past
+/// the session limit; eviction only depends on the log, so every replica
+/// must be configured alike.
+pub struct Sessions<M, O> {
+    state_machine: M,
+    sessions: HashMap<ClientId, Session<O>>,
+    recency: BTreeSet<(LogIndex, ClientId)>,
+    ttl: Option<u64>,
+    max_sessions: usize,
+}
+
+impl<M, O> Sessions<M, O> {
+    pub fn new(state_machine: M) -> Self {
+        Self {
+            state_machine,
+            sessions: HashMap::new(),
+            recency: BTreeSet::new(),
+            ttl: None,
+            max_sessions: DEFAULT_MAX_SESSIONS,
+        }
+    }
+
+    /// Evicts clients that submit nothing for `entries` log entries.
+    pub fn with_ttl(mut self, entries: u64) -> Self {
+        self.ttl = Some(entries.max(1));
+        self
+    }
+
+    pub fn with_max_sessions(mut self, max_sessions: usize) -> Self {
+        self.max_sessions = max_sessions.max(1);
+        self
+    }
+
+    pub fn state_machine(&self) -> &M {
+        &self.state_machine
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    fn evict(&mut self, now: LogIndex) {
+        while let Some(&(active, client)) = self.recency.first() {
+            let expired = self
+                .ttl
+                .is_some_and(|ttl| now.get().saturating_sub(active.get()) >= ttl);
+            if !expired && self.sessions.len() <= self.max_sessions {
+                break;
+            }
+            self.recency.pop_first();
+            self.sessions.remove(&client);
+        }
+    }
+
+    fn touch(&mut self, client: ClientId, sequence: u64, output: O, active: LogIndex) {
+        let session = Session {
+            sequence,
+            output,
+            active,
+        };
+        if let Some(previous) = self.sessions.insert(client, session) {
+            self.recency.remove(&(previous.active, client));
+        }
+        self.recency.insert((active, client));
+    }
+}
+
+impl<M, C> StateMachine<SessionRequest<C>> for Sessions<M, M::Output>
+where
+    M: StateMachine<C>,
+    M::Output: Clone,
+{
+    type Output = Result<M::Output, SessionError>;
+
+    fn apply(&mut self, index: LogIndex, request: &SessionRequest<C>) -> Self::Output {
+        self.evict(index);
+        match self.sessions.get(&request.client) {
+            Some(session) if request.sequence == session.sequence => {
+                let output = session.output.clone();
+                self.touch(request.client, request.sequence, output.clone(), index);
+                return Ok(output);
+            }
+            Some(session) if request.sequence < session.sequence => {
+                return Err(Superseded {
+                    client: request.client,
+                    sequence: request.sequence,
+                }
+                .into())
+            }
+            None if request.sequence > 0 => return Err(SessionError::Expired(request.client)),
+            _ => {}
+        }
+        let output = self.state_machine.apply(index, &request.command);
+        self.touch(request.client, request.sequence, output.clone(), index);
+        self.evict(index);
+        Ok(output)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SessionState<T, O> {
+    pub state: T,
+    /// Each client's last sequence, its output, and the index it was last
+    /// active at.
+    pub sessions: Vec<(ClientId, u64, O, LogIndex)>,
+}
+
+impl<M, C> Snapshotting<SessionRequest<C>> for Sessions<M, M::Output>
+where
+    M: Snapshotting<C>,
+    M::Output: Clone,
+{
+    type State = SessionState<M::State, M::Output>;
+
+    fn snapshot(&self) -> Self::State {
+        SessionState {
+            state: self.state_machine.snapshot(),
+            sessions: self
+                .sessions
+                .iter()
+                .map(|(client, session)| {
+                    let output = session.output.clone();
+                    (*client, session.sequence, output, session.active)
+                })
+                .collect(),
+        }
+    }
+
+    fn restore(&mut self, state: Self::State) {
+        self.state_machine.restore(state.state);
+        self.sessions.clear();
+        self.recency.clear();
+        for (client, sequence, output, active) in state.sessions {
+            self.touch(client, sequence, output, active);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Counter(u64);
+
+    impl StateMachine<u64> for Counter {
+        type Output = u64;
+
+        fn apply(&mut self, _index: LogIndex, value: &u64) -> u64 {
+            self.0 += value;
+            self.0
+        }
+    }
+
+    impl Snapshotting<u64> for Counter {
+        type State = u64;
+
+        fn snapshot(&self) -> u64 {
+            self.0
+        }
+
+        fn restore(&mut self, state: u64) {
+            self.0 = state;
+        }
+    }
+
+    #[test]
+    fn applies_each_request_once() {
+        let mut sessions = Sessions::new(Counter::default());
+        let (mut alice, mut bob) = (
+            ClientSession::new(ClientId(1)),
+            ClientSession::new(ClientId(2)),
+        );
+        let first = alice.request(5);
+        let index = LogIndex::default();
+        assert_eq!(sessions.apply(index, &first), Ok(5));
+        assert_eq!(sessions.apply(index, &bob.request(1)), Ok(6));
+        assert_eq!(sessions.apply(index, &first), Ok(5));
+        assert_eq!(sessions.state_machine().0, 6);
+
+        assert_eq!(sessions.apply(index, &alice.request(2)), Ok(8));
+        assert_eq!(
+            sessions.apply(index, &first),
+            Err(SessionError::Superseded(Superseded {
+                client: ClientId(1),
+                sequence: 0,
+            }))
+        );
+    }
+
+    #[test]
+    fn snapshots_keep_deduplicating() {
+        let mut sessions = Sessions::new(Counter::default());
+        let mut client = ClientSession::new(ClientId(1));
+        let request = client.request(3);
+        sessions.apply(LogIndex::default(), &request).unwrap();
+
+        let mut restored = Sessions::new(Counter::default());
+        restored.restore(sessions.snapshot());
+        assert_eq!(restored.apply(LogIndex::default(), &request), Ok(3));
+        assert_eq!(
+            restored.apply(LogIndex::default(), &client.request(1)),
+            Ok(4)
+        );
+    }
+
+    #[test]
+    fn forgets_clients_idle_past_the_ttl() {
+        let mut sessions = Sessions::new(Counter::default()).with_ttl(10);
+        let (mut alice, mut bob) = (
+            ClientSession::new(ClientId(1)),
+            ClientSession::new(ClientId(2)),
+        );
+        sessions.apply(LogIndex::new(1), &alice.request(1)).unwrap();
+        sessions.apply(LogIndex::new(5), &bob.request(1)).unwrap();
+        sessions.apply(LogIndex::new(10), &bob.request(1)).unwrap();
+        assert_eq!(sessions.len(), 2);
+
+        sessions.apply(LogIndex::new(11), &bob.request(1)).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(
+            sessions.apply(LogIndex::new(12), &alice.request(1)),
+            Err(SessionError::Expired(ClientId(1)))
+        );
+        assert_eq!(sessions.state_machine().0, 4);
+    }
+
+    #[test]
+    fn evicts_the_least_recently_active_past_the_limit() {
+        let mut sessions = Sessions::new(Counter::default()).with_max_sessions(2);
+        let mut clients: Vec<_> = (1..=3).map(|id| ClientSession::new(ClientId(id))).collect();
+        let first = clients[0].request(1);
+        sessions.apply(LogIndex::new(1), &first).unwrap();
+        sessions
+            .apply(LogIndex::new(2), &clients[1].request(1))
+            .unwrap();
+        // A retry counts as activity, so the second client is now the oldest.
+        assert_eq!(sessions.apply(LogIndex::new(3), &first), Ok(1));
+        sessions
+            .apply(LogIndex::new(4), &clients[2].request(1))
+            .unwrap();
+
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions.apply(LogIndex::new(5), &first), Ok(1));
+        assert_eq!(
+            sessions.apply(LogIndex::new(6), &clients[1].request(1)),
+            Err(SessionError::Expired(ClientId(2)))
+        );
+    }
+
+    #[test]
+    fn snapshots_keep_session_activity() {
+        let mut sessions = Sessions::new(Counter::default()).with_ttl(10);
+        let (mut alice, mut bob) = (
+            ClientSession::new(ClientId(1)),
+            ClientSession::new(ClientId(2)),
+        );
+        sessions.apply(LogIndex::new(1), &alice.request(1)).unwrap();
+        sessions.apply(LogIndex::new(8), &bob.request(1)).unwrap();
+
+        let mut restored = Sessions::new(Counter::default()).with_ttl(10);
+        restored.restore(sessions.snapshot());
+        restored.apply(LogIndex::new(12), &bob.request(1)).unwrap();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(
+            restored.apply(LogIndex::new(13), &alice.request(1)),
+            Err(SessionError::Expired(ClientId(1)))
+        );
+    }
+}
@@ -0,0 +1,362 @@
+use crate::acceptor::Acceptor;
+use crate::alpha::{
+    Error, Id, Quorum, ReadPeers, ReadResponse, Round, Value, WritePeers, WriteResponse,
+};
+use crate::learner::{DecisionBroadcast, DecisionPeers, Learner};
+use crate::proposer::{FailureDetector, Proposer, TickSource, Ticks};
+use crate::retry::RetryPolicy;
+use crate::rng::XorShift;
+use crate::storage::{MemoryStorage, Storage};
+use futures::executor::block_on;
+use futures::future::join_all;
+use futures::stream::{self, Stream, StreamExt};
+use std::cell::RefCell;
+use std::future::{self, ready, Future};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Faults {
+    pub request_loss: f64,
+    pub response_loss: f64,
+    pub duplication: f64,
+    pub max_delay: usize,
+}
+
+pub struct Simulation<V> {
+    network: Rc<RefCell<Network<V>>>,
+    ticks: Vec<Ticks>,
+}
+
+/// What one simulated node looks like from outside.
+#[derive(Clone, Debug)]
+pub struct NodeView<V> {
+    pub id: Id,
+    pub up: bool,
+    /// Which side of a partition the node is on, if there is one.
+    pub side: Option<usize>,
+    pub promised: Round,
+    pub accepted: Option<(Round, V)>,
+    pub learned: Option<V>,
+}
+
+impl<V> Simulation<V>
+where
+    V: Clone + PartialEq + 'static,
+{
+    pub fn new(acceptors: usize, seed: u64, faults: Faults) -> Self {
+        let network = Network {
+            acceptors: (0..acceptors)
+                .map(|i| {
+                    Acceptor::new(Id(i as u64), MemoryStorage::default())
+                        .expect("memory storage cannot fail")
+                })
+                .collect(),
+            learners: (0..acceptors).map(|_| Learner::default()).collect(),
+            down: vec![false; acceptors],
+            sides: None,
+            rng: XorShift::new(seed),
+            faults,
+        };
+        Self {
+            network: Rc::new(RefCell::new(network)),
+            ticks: (0..acceptors)
+                .map(|_| Ticks::new(TickSource::Counter))
+                .collect(),
+        }
+    }
+
+    pub fn peers(&self) -> SimPeers<V> {
+        SimPeers {
+            network: self.network.clone(),
+            from: None,
+        }
+    }
+
+    /// Peers as `node` reaches them: nodes that are down or across a
+    /// partition never answer.
+    pub fn peers_of(&self, node: usize) -> SimPeers<V> {
+        SimPeers {
+            network: self.network.clone(),
+            from: Some(node),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ticks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ticks.is_empty()
+    }
+
+    /// Stops `node` answering. What its acceptor persisted survives; what
+    /// its learner knew does not.
+    pub fn crash(&self, node: usize) {
+        self.network.borrow_mut().down[node] = true;
+    }
+
+    /// Brings `node` back from what its acceptor persisted.
+    pub fn restart(&self, node: usize) {
+        let mut network = self.network.borrow_mut();
+        if !network.down[node] {
+            return;
+        }
+        let mut storage = MemoryStorage::default();
+        let _ = storage.persist(network.acceptors[node].state());
+        network.acceptors[node] =
+            Acceptor::new(Id(node as u64), storage).expect("memory storage cannot fail");
+        network.learners[node] = Learner::default();
+        network.down[node] = false;
+    }
+
+    /// Splits the nodes into `sides` that cannot reach each other. Nodes no
+    /// side names are each cut off on their own.
+    pub fn partition(&self, sides: &[Vec<usize>]) {
+        let mut network = self.network.borrow_mut();
+        let mut assigned = vec![usize::MAX; network.acceptors.len()];
+        for (side, nodes) in sides.iter().enumerate() {
+            for node in nodes {
+                assigned[*node] = side;
+            }
+        }
+        network.sides = Some(assigned);
+    }
+
+    pub fn heal(&self) {
+        self.network.borrow_mut().sides = None;
+    }
+
+    /// Proposes `value` as `node` would, giving up after `attempts` tries
+    /// when it cannot reach a majority.
+    pub fn propose(&self, node: usize, value: V, attempts: usize) -> Result<V, Error> {
+        if self.network.borrow().down[node] {
+            return Err(Error::ProposerStopped);
+        }
+        let id = Id(node as u64);
+        let mut proposer = Proposer::builder(id, self.peers_of(node), SelfLeader(id))
+            .retry_policy(RetryPolicy {
+                max_attempts: Some(attempts),
+                ..RetryPolicy::immediate()
+            })
+            .ticks(self.ticks[node].clone())
+            .build();
+        block_on(proposer.propose(value))
+    }
+
+    pub fn nodes(&self) -> Vec<NodeView<V>> {
+        let network = self.network.borrow();
+        (0..network.acceptors.len())
+            .map(|node| {
+                let state = network.acceptors[node].state();
+                NodeView {
+                    id: Id(node as u64),
+                    up: !network.down[node],
+                    side: network
+                        .sides
+                        .as_ref()
+                        .map(|sides| sides[node])
+                        .filter(|side| *side != usize::MAX),
+                    promised: state.last_round_entered(),
+                    accepted: state.accepted_round().zip(state.accepted_value().cloned()),
+                    learned: network.learners[node].decision(),
+                }
+            })
+            .collect()
+    }
+
+    pub fn propose_all(&self, values: Vec<V>) -> Vec<V> {
+        let proposals = values.into_iter().enumerate().map(|(i, value)| {
+            let id = Id(i as u64);
+            let mut proposer = Proposer::builder(id, self.peers(), SelfLeader(id))
+                .retry_policy(RetryPolicy::immediate())
+                .build();
+            async move { proposer.propose(value).await }
+        });
+        block_on(join_all(proposals))
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .expect("proposals retry without limit")
+    }
+
+    pub fn learned(&self) -> Vec<Option<V>> {
+        self.network
+            .borrow()
+            .learners
+            .iter()
+            .map(Learner::decision)
+            .collect()
+    }
+}
+
+pub struct SimPeers<V> {
+    network: Rc<RefCell<Network<V>>>,
+    from: Option<usize>,
+}
+
+impl<V> Clone for SimPeers<V> {
+    fn clone(&self) -> Self {
+        Self {
+            network: self.network.clone(),
+            from: self.from,
+        }
+    }
+}
+
+impl<V> SimPeers<V>
+where
+    V: 'static,
+{
+    fn deliver<T, F>(&self, handler: F) -> impl Stream<Item = Result<T, Error>>
+    where
+        T: 'static,
+        F: Fn(&mut Network<V>, usize) -> Result<T, Error> + 'static,
+    {
+        let network = self.network.clone();
+        let deliveries = network.borrow_mut().schedule(self.from);
+        let handler = Rc::new(handler);
+        stream::iter(deliveries)
+            .then(move |delivery| {
+                let network = network.clone();
+                let handler = handler.clone();
+                async move {
+                    YieldNow(delivery.delay).await;
+                    let response = handler(&mut network.borrow_mut(), delivery.acceptor);
+                    (delivery.lose_response, response)
+                }
+            })
+            .filter_map(|(lost, response)| ready((!lost).then_some(response)))
+    }
+}
+
+impl<V> ReadPeers<V> for SimPeers<V>
+where
+    V: Clone + 'static,
+{
+    fn read(&self, round: Round) -> impl Stream<Item = Result<ReadResponse<V>, Error>> {
+        self.deliver(move |network, i| network.acceptors[i].handle_read(round))
+    }
+}
+
+impl<V> WritePeers<V> for SimPeers<V>
+where
+    V: Clone + PartialEq + 'static,
+{
+    fn write(&self, value: Value<V>) -> impl Stream<Item = Result<WriteResponse, Error>> {
+        self.deliver(move |network, i| network.acceptors[i].handle_write(value.clone()))
+    }
+}
+
+impl<V> DecisionPeers<V> for SimPeers<V>
+where
+    V: Clone + 'static,
+{
+    fn decide(&self, decision: DecisionBroadcast<V>) -> impl Stream<Item = Result<(), Error>> {
+        let mut network = self.network.borrow_mut();
+        let acks: Vec<_> = network
+            .schedule(self.from)
+            .into_iter()
+            .filter_map(|delivery| {
+                network.learners[delivery.acceptor].handle_decision(decision.clone());
+                (!delivery.lose_response).then_some(Ok(()))
+            })
+            .collect();
+        stream::iter(acks)
+    }
+}
+
+impl<V> Quorum for SimPeers<V> {
+    fn majority(&self) -> usize {
+        self.network.borrow().acceptors.len() / 2 + 1
+    }
+
+    fn max_failures(&self) -> usize {
+        let acceptors = self.network.borrow().acceptors.len();
+        acceptors.saturating_sub(self.majority())
+    }
+}
+
+struct Network<V> {
+    acceptors: Vec<Acceptor<V, MemoryStorage<V>>>,
+    learners: Vec<Learner<V>>,
+    down: Vec<bool>,
+    sides: Option<Vec<usize>>,
+    rng: XorShift,
+    faults: Faults,
+}
+
+struct Delivery {
+    acceptor: usize,
+    delay: usize,
+    lose_response: bool,
+}
+
+impl<V> Network<V> {
+    fn reachable(&self, from: Option<usize>, to: usize) -> bool {
+        if self.down[to] {
+            return false;
+        }
+        match (from, &self.sides) {
+            (Some(from), Some(sides)) if from != to => {
+                sides[from] == sides[to] && sides[to] != usize::MAX
+            }
+            _ => true,
+        }
+    }
+
+    fn schedule(&mut self, from: Option<usize>) -> Vec<Delivery> {
+        let mut deliveries = Vec::new();
+        for acceptor in 0..self.acceptors.len() {
+            if !self.reachable(from, acceptor) {
+                continue;
+            }
+            let copies = if self.rng.chance(self.faults.duplication) {
+                2
+            } else {
+                1
+            };
+            for _ in 0..copies {
+                if self.rng.chance(self.faults.request_loss) {
+                    continue;
+                }
+                deliveries.push(Delivery {
+                    acceptor,
+                    delay: self.rng.below(self.faults.max_delay + 1),
+                    lose_response: self.rng.chance(self.faults.response_loss),
+                });
+            }
+        }
+        for i in (1..deliveries.len()).rev() {
+            deliveries.swap(i, self.rng.below(i + 1));
+        }
+        deliveries
+    }
+}
+
+struct SelfLeader(Id);
+
+impl FailureDetector for SelfLeader {
+    fn leader(&self) -> Id {
+        self.0
+    }
+
+    fn changed(&self) -> impl Future<Output = ()> {
+        future::pending()
+    }
+}
+
+struct YieldNow(usize);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 == 0 {
+            return Poll::Ready(());
+        }
+        self.0 -= 1;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
@@ -0,0 +1,504 @@
+use crate::alpha::Error;
+use crate::log::{LogIndex, ReplicatedLog, SlotPeers};
+use crate::proposer::FailureDetector;
+use crate::time::Timestamp;
+use std::collections::BTreeMap;
+
+pub trait StateMachine<V> {
+    type Output;
+    fn apply(&mut self, index: LogIndex, value: &V) -> Self::Output;
+}
+
+pub trait Snapshotting<V>: StateMachine<V> {
+    type State: Clone;
+    fn snapshot(&self) -> Self::State;
+    fn restore(&mut self, state: Self::State);
+}
+
+#[derive(Clone, Debug)]
+pub struct Snapshot<T> {
+    pub last_included: LogIndex,
+    pub state: T,
+}
+
+/// What a client's last write made of the log: a read served by any
+/// replica that has applied everything below `applied` sees it, and the
+/// replica's clock moves past `timestamp` so what it stamps next is later.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SessionToken {
+    pub applied: LogIndex,
+    pub timestamp: Timestamp,
+}
+
+impl SessionToken {
+    /// The later of two tokens, so a client that wrote through several
+    /// handles reads what all of them wrote.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            applied: self.applied.max(other.applied),
+            timestamp: self.timestamp.max(other.timestamp),
+        }
+    }
+}
+
+pub struct Replica<M, V, S, D> {
+    log: ReplicatedLog<V, S, D>,
+    state_machine: M,
+    next_to_apply: LogIndex,
+    ack_interval: u64,
+    acked: LogIndex,
+    snapshot_every: Option<u64>,
+    snapshotted: LogIndex,
+}
+
+const ACK_INTERVAL: u64 = 64;
+
+impl<M, V, S, D> Replica<M, V, S, D>
+where
+    M: StateMachine<V>,
+    V: Clone + PartialEq,
+    S: SlotPeers<V>,
+    D: FailureDetector + Clone,
+{
+    pub fn new(log: ReplicatedLog<V, S, D>, state_machine: M) -> Self {
+        Self {
+            log,
+            state_machine,
+            next_to_apply: LogIndex::default(),
+            ack_interval: ACK_INTERVAL,
+            acked: LogIndex::default(),
+            snapshot_every: None,
+            snapshotted: LogIndex::default(),
+        }
+    }
+
+    pub fn ack_interval(mut self, ack_interval: u64) -> Self {
+        self.ack_interval = ack_interval.max(1);
+        self
+    }
+
+    /// Has [`snapshot_if_due`](Self::snapshot_if_due) take a snapshot each
+    /// time this many more entries were applied.
+    pub fn snapshot_every(mut self, entries: u64) -> Self {
+        self.snapshot_every = Some(entries.max(1));
+        self
+    }
+
+    pub fn state_machine(&self) -> &M {
+        &self.state_machine
+    }
+
+    pub fn log(&self) -> &ReplicatedLog<V, S, D> {
+        &self.log
+    }
+
+    pub fn log_mut(&mut self) -> &mut ReplicatedLog<V, S, D> {
+        &mut self.log
+    }
+
+    pub async fn propose_and_wait(&mut self, command: V) -> Result<M::Output, Error> {
+        let index = self.log.append(command).await?;
+        let mut output = None;
+        while self.next_to_apply <= index {
+            let applied = self
+                .apply_next()
+                .expect("appended entries are committed in order");
+            if self.next_to_apply > index {
+                output = Some(applied);
+            }
+        }
+        Ok(output.expect("the appended entry was applied"))
+    }
+
+    /// Appends every command, pipelined, and returns what applying each
+    /// output, in the order given.
+    pub async fn propose_all_and_wait(
+        &mut self,
+        commands: impl IntoIterator<Item = V>,
+    ) -> Result<Vec<M::Output>, Error> {
+        let indices = self.log.append_all(commands).await?;
+        let Some(last) = indices.iter().max().copied() else {
+            return Ok(Vec::new());
+        };
+        let mut outputs = BTreeMap::new();
+        while self.next_to_apply <= last {
+            let index = self.next_to_apply;
+            let applied = self
+                .apply_next()
+                .expect("appended entries are committed in order");
+            outputs.insert(index, applied);
+        }
+        Ok(indices
+            .iter()
+            .map(|index| {
+                outputs
+                    .remove(index)
+                    .expect("the appended entry was applied")
+            })
+            .collect())
+    }
+
+    /// Like [`propose_and_wait`](Self::propose_and_wait), also returning the
+    /// token a read must present to see this write.
+    pub async fn propose_with_token(
+        &mut self,
+        command: V,
+    ) -> Result<(M::Output, SessionToken), Error> {
+        let output = self.propose_and_wait(command).await?;
+        Ok((output, self.session_token()))
+    }
+
+    /// The token for everything this replica has applied.
+    pub fn session_token(&mut self) -> SessionToken {
+        let timestamp = match self.next_to_apply.get().checked_sub(1) {
+            Some(last) => self
+                .log
+                .read_stamped(LogIndex::new(last))
+                .map(|(timestamp, _)| timestamp),
+            None => None,
+        };
+        SessionToken {
+            applied: self.next_to_apply,
+            timestamp: timestamp.unwrap_or_else(|| self.log.now()),
+        }
+    }
+
+    /// Serves `query` from this replica's state once it is at least as
+    /// fresh as `token`, without a round to the leader. A replica behind
+    /// the token learns what the acceptors decided once; if that still
+    /// leaves it behind it fails with [`Error::NotCommitted`] at once
+    /// rather than wait, and the caller reads elsewhere or through
+    /// [`read_quorum`](Self::read_quorum).
+    pub async fn read_at<R>(
+        &mut self,
+        token: SessionToken,
+        query: impl FnOnce(&M) -> R,
+    ) -> Result<R, Error> {
+        self.apply_committed();
+        if self.next_to_apply < token.applied {
+            self.log.learn().await?;
+            self.apply_committed();
+        }
+        if self.next_to_apply < token.applied {
+            return Err(Error::NotCommitted(token.applied.get() - 1));
+        }
+        self.log.observe(token.timestamp);
+        Ok(query(&self.state_machine))
+    }
+
+    pub async fn read_quorum<R>(&mut self, query: impl FnOnce(&M) -> R) -> Result<R, Error> {
+        let index = self.log.read_index().await?;
+        while self.next_to_apply < index {
+            self.apply_next()
+                .ok_or(Error::NotCommitted(self.next_to_apply.get()))?;
+        }
+        Ok(query(&self.state_machine))
+    }
+
+    pub async fn catch_up(&mut self) -> Result<LogIndex, Error> {
+        let index = self.log.catch_up().await?;
+        self.apply_committed();
+        Ok(index)
+    }
+
+    /// Applies every entry the acceptors learned was decided since the
+    /// last call, for a replica whose log is an
+    /// [observer](ReplicatedLog::observer): it serves reads from
+    /// [`state_machine`](Self::state_machine) without a vote in any round.
+    pub async fn observe(&mut self) -> Result<LogIndex, Error> {
+        let index = self.log.learn().await?;
+        self.apply_committed();
+        Ok(index)
+    }
+
+    /// Applies what was learned last, then lets the replica propose; see
+    /// [`ReplicatedLog::promote`].
+    pub async fn promote(&mut self) -> Result<LogIndex, Error> {
+        let index = self.observe().await?;
+        self.log.promote();
+        Ok(index)
+    }
+
+    pub fn apply_committed(&mut self) {
+        while self.apply_next().is_some() {}
+    }
+
+    fn apply_next(&mut self) -> Option<M::Output> {
+        let index = self.next_to_apply;
+        let value = self.log.read(index)?;
+        let output = self.state_machine.apply(index, value);
+        self.next_to_apply = index.next();
+        self.acknowledge();
+        Some(output)
+    }
+
+    fn acknowledge(&mut self) {
+        if self.next_to_apply.get() - self.acked.get() >= self.ack_interval {
+            self.log.acknowledge(self.next_to_apply);
+            self.acked = self.next_to_apply;
+        }
+    }
+}
+
+impl<M, V, S, D> Replica<M, V, S, D>
+where
+    M: Snapshotting<V>,
+    V: Clone + PartialEq,
+    S: SlotPeers<V>,
+    D: FailureDetector + Clone,
+{
+    pub fn take_snapshot(&mut self, up_to: LogIndex) -> Result<Snapshot<M::State>, Error> {
+        while self.next_to_apply <= up_to {
+            if self.apply_next().is_none() {
+                return Err(Error::NotCommitted(self.next_to_apply.get()));
+            }
+        }
+        let last_included = LogIndex::new(self.next_to_apply.get() - 1);
+        self.log.truncate(last_included);
+        self.snapshotted = self.next_to_apply;
+        Ok(Snapshot {
+            last_included,
+            state: self.state_machine.snapshot(),
+        })
+    }
+
+    /// A snapshot of everything applied, once as many entries as the
+    /// [cadence](Self::snapshot_every) asks for were applied since the last
+    /// one. Proposals never take it; whoever keeps snapshots calls this
+    /// between them.
+    pub fn snapshot_if_due(&mut self) -> Option<Snapshot<M::State>> {
+        let every = self.snapshot_every?;
+        if self.next_to_apply.get() - self.snapshotted.get() < every {
+            return None;
+        }
+        let last_included = LogIndex::new(self.next_to_apply.get() - 1);
+        self.take_snapshot(last_included).ok()
+    }
+
+    pub fn install_snapshot(&mut self, snapshot: Snapshot<M::State>) {
+        if snapshot.last_included < self.next_to_apply {
+            return;
+        }
+        self.state_machine.restore(snapshot.state);
+        self.log.install(snapshot.last_included);
+        self.next_to_apply = snapshot.last_included.next();
+        self.snapshotted = self.next_to_apply;
+        self.acknowledge();
+    }
+}
+
+#[cfg(all(test, feature = "threads"))]
+mod tests {
+    use super::*;
+    use crate::alpha::Id;
+    use crate::local::{LocalCluster, LocalPeers};
+    use futures::executor::block_on;
+    use futures::Stream;
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
+    use std::future::{self, Future};
+    use std::rc::Rc;
+
+    #[derive(Clone, Default)]
+    struct Slots(Rc<RefCell<BTreeMap<LogIndex, LocalCluster<u64>>>>);
+
+    impl SlotPeers<u64> for Slots {
+        type Peers = LocalPeers<u64>;
+
+        fn slot(&self, index: LogIndex) -> Self::Peers {
+            self.0
+                .borrow_mut()
+                .entry(index)
+                .or_insert_with(|| LocalCluster::new(3))
+                .peers()
+        }
+
+        fn learned(
+            &self,
+            index: LogIndex,
+        ) -> Option<impl Stream<Item = Result<Option<u64>, Error>>> {
+            self.0.borrow().get(&index).map(LocalCluster::learned)
+        }
+    }
+
+    #[derive(Clone)]
+    struct Leader;
+
+    impl FailureDetector for Leader {
+        fn leader(&self) -> Id {
+            Id(1)
+        }
+
+        fn changed(&self) -> impl Future<Output = ()> {
+            future::pending()
+        }
+    }
+
+    #[derive(Default)]
+    struct Sum {
+        total: u64,
+        applied: Vec<LogIndex>,
+    }
+
+    impl StateMachine<u64> for Sum {
+        type Output = u64;
+
+        fn apply(&mut self, index: LogIndex, value: &u64) -> u64 {
+            self.applied.push(index);
+            self.total += value;
+            self.total
+        }
+    }
+
+    impl Snapshotting<u64> for Sum {
+        type State = u64;
+
+        fn snapshot(&self) -> u64 {
+            self.total
+        }
+
+        fn restore(&mut self, total: u64) {
+            self.total = total;
+        }
+    }
+
+    fn replica(slots: &Slots) -> Replica<Sum, u64, Slots, Leader> {
+        Replica::new(
+            ReplicatedLog::new(Id(1), slots.clone(), Leader),
+            Sum::default(),
+        )
+    }
+
+    #[test]
+    fn applies_entries_in_log_order() {
+        let mut replica = replica(&Slots::default());
+        let outputs: Vec<_> = [3, 4, 5]
+            .into_iter()
+            .map(|value| block_on(replica.propose_and_wait(value)).unwrap())
+            .collect();
+        assert_eq!(outputs, [3, 7, 12]);
+        assert_eq!(
+            replica.state_machine().applied,
+            [0, 1, 2].map(LogIndex::new)
+        );
+    }
+
+    #[test]
+    fn observers_apply_the_log_and_are_promoted_warm() {
+        let slots = Slots::default();
+        let mut leader = replica(&slots);
+        for value in [3, 4] {
+            block_on(leader.propose_and_wait(value)).unwrap();
+        }
+        let mut observer = Replica::new(
+            ReplicatedLog::new(Id(1), slots.clone(), Leader).observer(),
+            Sum::default(),
+        );
+        assert_eq!(block_on(observer.observe()).unwrap(), LogIndex::new(2));
+        assert_eq!(observer.state_machine().total, 7);
+        assert!(matches!(
+            block_on(observer.propose_and_wait(5)),
+            Err(Error::Observing)
+        ));
+        assert!(matches!(
+            block_on(observer.read_quorum(|sum| sum.total)),
+            Err(Error::Observing)
+        ));
+
+        block_on(leader.propose_and_wait(5)).unwrap();
+        assert_eq!(block_on(observer.promote()).unwrap(), LogIndex::new(3));
+        assert_eq!(observer.state_machine().total, 12);
+        assert_eq!(block_on(observer.propose_and_wait(1)).unwrap(), 13);
+    }
+
+    #[test]
+    fn replicas_serve_reads_once_they_are_as_fresh_as_the_token() {
+        let slots = Slots::default();
+        let mut leader = replica(&slots);
+        let mut observer = Replica::new(
+            ReplicatedLog::new(Id(1), slots.clone(), Leader).observer(),
+            Sum::default(),
+        );
+        let reads_before = block_on(observer.read_at(SessionToken::default(), |sum| sum.total));
+        assert_eq!(reads_before.unwrap(), 0);
+
+        block_on(leader.propose_and_wait(3)).unwrap();
+        let (total, token) = block_on(leader.propose_with_token(4)).unwrap();
+        assert_eq!(total, 7);
+        assert_eq!(token.applied, LogIndex::new(2));
+        assert_eq!(token, leader.session_token());
+
+        // The observer is behind the token until it learns the writes.
+        assert_eq!(
+            block_on(observer.read_at(token, |sum| sum.total)).unwrap(),
+            7
+        );
+        assert!(observer.log_mut().now() > token.timestamp);
+
+        let ahead = SessionToken {
+            applied: LogIndex::new(5),
+            ..token
+        };
+        assert!(matches!(
+            block_on(observer.read_at(ahead, |sum| sum.total)),
+            Err(Error::NotCommitted(4))
+        ));
+        assert_eq!(token.merge(ahead), ahead);
+        assert_eq!(SessionToken::default().merge(token), token);
+    }
+
+    #[test]
+    fn snapshots_truncate_the_log_and_restore_elsewhere() {
+        let slots = Slots::default();
+        let mut leader = replica(&slots);
+        for value in [3, 4] {
+            block_on(leader.propose_and_wait(value)).unwrap();
+        }
+        let snapshot = leader.take_snapshot(LogIndex::new(1)).unwrap();
+        assert_eq!(
+            (snapshot.last_included, snapshot.state),
+            (LogIndex::new(1), 7)
+        );
+        assert_eq!(leader.log().read(LogIndex::new(0)), None);
+        assert!(matches!(
+            leader.take_snapshot(LogIndex::new(5)),
+            Err(Error::NotCommitted(2))
+        ));
+
+        let mut restored = replica(&slots);
+        restored.install_snapshot(snapshot.clone());
+        assert_eq!(restored.state_machine().total, 7);
+        assert_eq!(block_on(restored.propose_and_wait(5)).unwrap(), 12);
+        assert_eq!(restored.state_machine().applied, [LogIndex::new(2)]);
+
+        restored.install_snapshot(snapshot);
+        assert_eq!(restored.state_machine().total, 12);
+    }
+
+    #[test]
+    fn takes_snapshots_at_the_cadence_given() {
+        let slots = Slots::default();
+        let mut cadenced = replica(&slots).snapshot_every(3);
+        let mut due = Vec::new();
+        for value in 1..=7 {
+            block_on(cadenced.propose_and_wait(value)).unwrap();
+            due.extend(
+                cadenced
+                    .snapshot_if_due()
+                    .map(|snapshot| (snapshot.last_included, snapshot.state)),
+            );
+        }
+        assert_eq!(due, [(LogIndex::new(2), 6), (LogIndex::new(5), 21)]);
+        assert_eq!(cadenced.log().read(LogIndex::new(5)), None);
+
+        // A snapshot taken by hand or installed starts the count again.
+        let mut restored = replica(&slots).snapshot_every(3);
+        restored.install_snapshot(Snapshot {
+            last_included: LogIndex::new(6),
+            state: 28,
+        });
+        block_on(restored.propose_and_wait(8)).unwrap();
+        assert!(restored.snapshot_if_due().is_none());
+        assert!(replica(&slots).snapshot_if_due().is_none());
+    }
+}
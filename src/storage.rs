@@ -0,0 +1,447 @@
+use crate::alpha::Alpha;
+use crate::codec::{from_bytes, to_bytes, Decode, Encode};
+use std::convert::Infallible;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+pub trait Storage<V> {
+    type Error: std::error::Error + Send + Sync + 'static;
+    fn persist(&mut self, state: &Alpha<V>) -> Result<(), Self::Error>;
+    fn load(&mut self) -> Result<Option<Alpha<V>>, Self::Error>;
+    fn persist_tick(&mut self, tick: u64) -> Result<(), Self::Error>;
+    fn load_tick(&mut self) -> Result<Option<u64>, Self::Error>;
+
+    fn discard(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+pub struct MemoryStorage<V> {
+    state: Option<Alpha<V>>,
+    tick: Option<u64>,
+}
+
+impl<V> Default for MemoryStorage<V> {
+    fn default() -> Self {
+        Self {
+            state: None,
+            tick: None,
+        }
+    }
+}
+
+impl<V> Storage<V> for MemoryStorage<V>
+where
+    V: Clone,
+{
+    type Error = Infallible;
+
+    fn persist(&mut self, state: &Alpha<V>) -> Result<(), Self::Error> {
+        self.state = Some(state.clone());
+        Ok(())
+    }
+
+    fn load(&mut self) -> Result<Option<Alpha<V>>, Self::Error> {
+        Ok(self.state.clone())
+    }
+
+    fn persist_tick(&mut self, tick: u64) -> Result<(), Self::Error> {
+        self.tick = Some(tick);
+        Ok(())
+    }
+
+    fn load_tick(&mut self) -> Result<Option<u64>, Self::Error> {
+        Ok(self.tick)
+    }
+}
+
+pub struct FileStorage<V> {
+    state: RecordFile,
+    tick: RecordFile,
+    _value: PhantomData<fn() -> V>,
+}
+
+impl<V> FileStorage<V> {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        Self {
+            tick: RecordFile::new(path.with_extension("tick")),
+            state: RecordFile::new(path),
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<V> Storage<V> for FileStorage<V>
+where
+    V: Encode + Decode,
+{
+    type Error = io::Error;
+
+    fn persist(&mut self, state: &Alpha<V>) -> Result<(), Self::Error> {
+        self.state.append(&to_bytes(state))
+    }
+
+    fn load(&mut self) -> Result<Option<Alpha<V>>, Self::Error> {
+        self.state
+            .recover()?
+            .map(|bytes| from_bytes(&bytes))
+            .transpose()
+    }
+
+    fn persist_tick(&mut self, tick: u64) -> Result<(), Self::Error> {
+        self.tick.append(&to_bytes(&tick))
+    }
+
+    fn load_tick(&mut self) -> Result<Option<u64>, Self::Error> {
+        self.tick
+            .recover()?
+            .map(|bytes| from_bytes(&bytes))
+            .transpose()
+    }
+
+    fn discard(&mut self) -> Result<(), Self::Error> {
+        self.state.remove()?;
+        self.tick.remove()
+    }
+}
+
+const HEADER: usize = 8;
+// Longer lengths are never written, so reading one means corruption rather
+// than a torn write.
+const MAX_RECORD: usize = 1 << 30;
+const COMPACT_AFTER: usize = 1024;
+
+pub(crate) struct RecordFile {
+    path: PathBuf,
+    records: Option<usize>,
+    torn_at: Option<u64>,
+    compact: bool,
+}
+
+impl RecordFile {
+    /// A file where only the last record matters, so it compacts itself down
+    /// to that one once it grows.
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            records: None,
+            torn_at: None,
+            compact: true,
+        }
+    }
+
+    /// A file whose records are all folded on recovery; the caller compacts it
+    /// with [`rewrite`](Self::rewrite).
+    pub(crate) fn log(path: PathBuf) -> Self {
+        Self {
+            compact: false,
+            ..Self::new(path)
+        }
+    }
+
+    pub(crate) fn records(&self) -> usize {
+        self.records.unwrap_or_default()
+    }
+
+    /// Atomically replaces every record with `payloads`.
+    pub(crate) fn rewrite<'a>(
+        &mut self,
+        payloads: impl IntoIterator<Item = &'a [u8]>,
+    ) -> io::Result<()> {
+        let mut bytes = Vec::new();
+        let mut records = 0;
+        for payload in payloads {
+            bytes.extend_from_slice(&record(payload)?);
+            records += 1;
+        }
+        write_atomic(&self.path, &bytes)?;
+        self.records = Some(records);
+        self.torn_at = None;
+        Ok(())
+    }
+
+    pub(crate) fn append(&mut self, payload: &[u8]) -> io::Result<()> {
+        self.append_all([payload])
+    }
+
+    /// Appends `payloads` with a single write and a single sync.
+    pub(crate) fn append_all<'a>(
+        &mut self,
+        payloads: impl IntoIterator<Item = &'a [u8]>,
+    ) -> io::Result<()> {
+        let mut bytes = Vec::new();
+        let mut last = 0;
+        let mut appended = 0;
+        for payload in payloads {
+            last = bytes.len();
+            bytes.extend_from_slice(&record(payload)?);
+            appended += 1;
+        }
+        if appended == 0 {
+            return Ok(());
+        }
+        let records = match self.records {
+            Some(records) => records,
+            None => {
+                self.recover()?;
+                self.records.unwrap_or_default()
+            }
+        };
+        if self.compact && records + appended > COMPACT_AFTER {
+            write_atomic(&self.path, &bytes[last..])?;
+            self.records = Some(1);
+            self.torn_at = None;
+            return Ok(());
+        }
+        if let Some(offset) = self.torn_at {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(path = %self.path.display(), offset, "discarding torn write");
+            let file = OpenOptions::new().write(true).open(&self.path)?;
+            file.set_len(offset)?;
+            file.sync_all()?;
+            self.torn_at = None;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(&bytes)?;
+        file.sync_data()?;
+        if records == 0 {
+            sync_parent(&self.path)?;
+        }
+        self.records = Some(records + appended);
+        Ok(())
+    }
+
+    /// Returns the payload of the last intact record. A damaged record is
+    /// only tolerated as a torn final write if its length is one a writer
+    /// could have produced and the record that length points to is not
+    /// intact; the torn bytes are left in place until the next append
+    /// overwrites them.
+    pub(crate) fn recover(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut last = None;
+        self.scan(|payload| last = Some(payload.to_vec()))?;
+        Ok(last)
+    }
+
+    /// Like [`recover`](Self::recover), but returns every intact record in
+    /// the order they were appended.
+    pub(crate) fn recover_all(&mut self) -> io::Result<Vec<Vec<u8>>> {
+        let mut payloads = Vec::new();
+        self.scan(|payload| payloads.push(payload.to_vec()))?;
+        Ok(payloads)
+    }
+
+    fn scan(&mut self, mut visit: impl FnMut(&[u8])) -> io::Result<()> {
+        let bytes = match fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                self.records = Some(0);
+                return Ok(());
+            }
+            Err(error) => return Err(error),
+        };
+
+        let mut offset = 0;
+        let mut records = 0;
+        let mut torn_at = None;
+        while offset < bytes.len() {
+            let Some(payload) = read_record(&bytes[offset..]) else {
+                if !torn(&bytes[offset..]) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("corrupt record at byte {offset} of {}", self.path.display()),
+                    ));
+                }
+                torn_at = Some(offset as u64);
+                break;
+            };
+            visit(payload);
+            records += 1;
+            offset += HEADER + payload.len();
+        }
+
+        self.records = Some(records);
+        self.torn_at = torn_at;
+        Ok(())
+    }
+
+    fn remove(&mut self) -> io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Ok(()) => sync_parent(&self.path)?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+        self.records = Some(0);
+        self.torn_at = None;
+        Ok(())
+    }
+}
+
+fn read_record(bytes: &[u8]) -> Option<&[u8]> {
+    let header = bytes.get(..HEADER)?;
+    let len = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+    let checksum = u32::from_be_bytes(header[4..].try_into().unwrap());
+    let payload = bytes.get(HEADER..HEADER.checked_add(len)?)?;
+    (crc32(payload) == checksum).then_some(payload)
+}
+
+// Whether a damaged record at the start of `bytes` can be the tail of an
+// interrupted append; anything after it means it was followed by more writes.
+fn torn(bytes: &[u8]) -> bool {
+    let Some(header) = bytes.get(..4) else {
+        return true;
+    };
+    let len = u32::from_be_bytes(header.try_into().unwrap()) as usize;
+    len <= MAX_RECORD && bytes.get(HEADER + len..).is_none_or(|next| !intact(next))
+}
+
+// Nothing is ever stored with an empty payload, so zero-filled space left by
+// a torn write does not count as an intact record.
+fn intact(bytes: &[u8]) -> bool {
+    read_record(bytes).is_some_and(|payload| !payload.is_empty())
+}
+
+fn record(payload: &[u8]) -> io::Result<Vec<u8>> {
+    if payload.len() > MAX_RECORD {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} byte record is over the {MAX_RECORD} byte limit",
+                payload.len()
+            ),
+        ));
+    }
+    let mut record = Vec::with_capacity(HEADER + payload.len());
+    record.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    record.extend_from_slice(&crc32(payload).to_be_bytes());
+    record.extend_from_slice(payload);
+    Ok(record)
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut file = File::create(&tmp)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    sync_parent(path)
+}
+
+pub(crate) fn sync_parent(path: &Path) -> io::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("paxos-storage-{}-{name}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn written(path: &Path, payloads: &[&[u8]]) -> Vec<u8> {
+        let mut file = RecordFile::new(path.to_owned());
+        for payload in payloads {
+            file.append(payload).unwrap();
+        }
+        fs::read(path).unwrap()
+    }
+
+    #[test]
+    fn recovers_the_last_record() {
+        let path = scratch("last");
+        written(&path, &[b"one", b"two"]);
+        assert_eq!(
+            RecordFile::new(path.clone()).recover().unwrap(),
+            Some(b"two".to_vec())
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn discards_a_torn_tail_on_the_next_append() {
+        let path = scratch("torn");
+        let mut bytes = written(&path, &[b"one", b"two"]);
+        let intact = bytes.len();
+        bytes.extend_from_slice(&record(b"three").unwrap()[..10]);
+        fs::write(&path, &bytes).unwrap();
+
+        let mut file = RecordFile::new(path.clone());
+        assert_eq!(file.recover().unwrap(), Some(b"two".to_vec()));
+        assert_eq!(fs::read(&path).unwrap().len(), bytes.len());
+        file.append(b"four").unwrap();
+        assert_eq!(fs::read(&path).unwrap().len(), intact + HEADER + 4);
+        assert_eq!(
+            RecordFile::new(path.clone()).recover().unwrap(),
+            Some(b"four".to_vec())
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_a_checksum_mismatch_before_the_tail() {
+        let path = scratch("checksum");
+        let mut bytes = written(&path, &[b"one", b"two", b"three"]);
+        bytes[HEADER] ^= 1;
+        fs::write(&path, &bytes).unwrap();
+        let error = RecordFile::new(path.clone()).recover().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_a_corrupt_length_before_the_tail() {
+        let path = scratch("length");
+        let mut bytes = written(&path, &[b"one", b"two", b"three"]);
+        bytes[..4].copy_from_slice(&u32::MAX.to_be_bytes());
+        fs::write(&path, &bytes).unwrap();
+        let error = RecordFile::new(path.clone()).recover().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn tolerates_a_checksum_mismatch_in_the_last_record() {
+        let path = scratch("last-checksum");
+        let mut bytes = written(&path, &[b"one", b"two"]);
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(
+            RecordFile::new(path.clone()).recover().unwrap(),
+            Some(b"one".to_vec())
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn refuses_records_over_the_limit() {
+        let error = record(&vec![0; MAX_RECORD + 1]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
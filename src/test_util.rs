@@ -0,0 +1,262 @@
+use crate::alpha::{
+    Alpha, Error, Id, Quorum, ReadPeers, ReadResponse, Round, Status, Value, WritePeers,
+    WriteResponse,
+};
+use crate::learner::{DecisionBroadcast, DecisionPeers};
+use crate::proposer::FailureDetector;
+use futures::channel::oneshot;
+use futures::{stream, FutureExt, Stream};
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+pub struct MockPeers<V> {
+    inner: Arc<Mutex<MockState<V>>>,
+}
+
+struct MockState<V> {
+    acceptors: Vec<Alpha<V>>,
+    scripted_reads: BTreeMap<Round, Vec<Result<ReadResponse<V>, Error>>>,
+    scripted_writes: BTreeMap<Round, Vec<Result<WriteResponse, Error>>>,
+    reads: Vec<Round>,
+    writes: Vec<Value<V>>,
+    decisions: Vec<DecisionBroadcast<V>>,
+}
+
+impl<V> Clone for MockPeers<V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<V: Clone> MockPeers<V> {
+    pub fn new(acceptors: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(MockState {
+                acceptors: (0..acceptors).map(|_| Alpha::new()).collect(),
+                scripted_reads: BTreeMap::new(),
+                scripted_writes: BTreeMap::new(),
+                reads: Vec::new(),
+                writes: Vec::new(),
+                decisions: Vec::new(),
+            })),
+        }
+    }
+
+    pub fn on_read(
+        &self,
+        round: Round,
+        responses: impl IntoIterator<Item = Result<ReadResponse<V>, Error>>,
+    ) {
+        lock(&self.inner)
+            .scripted_reads
+            .insert(round, responses.into_iter().collect());
+    }
+
+    pub fn on_write(
+        &self,
+        round: Round,
+        responses: impl IntoIterator<Item = Result<WriteResponse, Error>>,
+    ) {
+        lock(&self.inner)
+            .scripted_writes
+            .insert(round, responses.into_iter().collect());
+    }
+
+    pub fn acceptor(&self, index: usize) -> Alpha<V> {
+        lock(&self.inner).acceptors[index].clone()
+    }
+
+    pub fn reads(&self) -> Vec<Round> {
+        lock(&self.inner).reads.clone()
+    }
+
+    pub fn writes(&self) -> Vec<Value<V>> {
+        lock(&self.inner).writes.clone()
+    }
+
+    pub fn decisions(&self) -> Vec<DecisionBroadcast<V>> {
+        lock(&self.inner).decisions.clone()
+    }
+}
+
+impl<V: Clone> ReadPeers<V> for MockPeers<V> {
+    fn read(&self, round: Round) -> impl Stream<Item = Result<ReadResponse<V>, Error>> {
+        let mut state = lock(&self.inner);
+        state.reads.push(round);
+        let responses: Vec<_> = match state.scripted_reads.remove(&round) {
+            Some(scripted) => scripted,
+            None => state
+                .acceptors
+                .iter_mut()
+                .enumerate()
+                .map(|(index, alpha)| Ok(alpha.read(Id(index as u64), round)))
+                .collect(),
+        };
+        stream::iter(responses)
+    }
+}
+
+impl<V: Clone + PartialEq> WritePeers<V> for MockPeers<V> {
+    fn write(&self, value: Value<V>) -> impl Stream<Item = Result<WriteResponse, Error>> {
+        let mut state = lock(&self.inner);
+        state.writes.push(value.clone());
+        let responses: Vec<_> = match state.scripted_writes.remove(&value.last_round_with_write) {
+            Some(scripted) => scripted,
+            None => state
+                .acceptors
+                .iter_mut()
+                .enumerate()
+                .map(|(index, alpha)| Ok(alpha.write(Id(index as u64), value.clone())))
+                .collect(),
+        };
+        stream::iter(responses)
+    }
+
+    fn write_to(
+        &self,
+        acceptors: &[Id],
+        value: Value<V>,
+    ) -> impl Stream<Item = Result<WriteResponse, Error>> {
+        let mut state = lock(&self.inner);
+        state.writes.push(value.clone());
+        let responses: Vec<_> = acceptors
+            .iter()
+            .filter_map(|&acceptor| {
+                let alpha = state.acceptors.get_mut(acceptor.0 as usize)?;
+                Some(Ok(alpha.write(acceptor, value.clone())))
+            })
+            .collect();
+        stream::iter(responses)
+    }
+}
+
+impl<V: Clone> DecisionPeers<V> for MockPeers<V> {
+    fn decide(&self, decision: DecisionBroadcast<V>) -> impl Stream<Item = Result<(), Error>> {
+        lock(&self.inner).decisions.push(decision);
+        stream::iter([Ok(())])
+    }
+}
+
+impl<V> Quorum for MockPeers<V> {
+    fn majority(&self) -> usize {
+        lock(&self.inner).acceptors.len() / 2 + 1
+    }
+
+    fn max_failures(&self) -> usize {
+        let acceptors = lock(&self.inner).acceptors.len();
+        acceptors.saturating_sub(acceptors / 2 + 1)
+    }
+}
+
+pub fn read_ok<V>(acceptor: Id, round: Round, accepted: Option<Value<V>>) -> ReadResponse<V> {
+    ReadResponse {
+        acceptor,
+        round,
+        status: Status::Accepted,
+        state: Alpha::from_state(round, accepted),
+    }
+}
+
+pub fn read_rejected<V>(acceptor: Id, round: Round, conflict: Round) -> ReadResponse<V> {
+    ReadResponse {
+        acceptor,
+        round,
+        status: Status::Rejected(conflict),
+        state: Alpha::from_state(conflict, None),
+    }
+}
+
+pub fn write_ok(acceptor: Id, round: Round) -> WriteResponse {
+    WriteResponse {
+        acceptor,
+        round,
+        status: Status::Accepted,
+        last_round_entered: round,
+        signature: None,
+    }
+}
+
+pub fn write_rejected(acceptor: Id, round: Round, conflict: Round) -> WriteResponse {
+    WriteResponse {
+        acceptor,
+        round,
+        status: Status::Rejected(conflict),
+        last_round_entered: conflict,
+        signature: None,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MockFailureDetector {
+    leader: Arc<AtomicU64>,
+    watchers: Arc<Mutex<Vec<oneshot::Sender<()>>>>,
+}
+
+impl MockFailureDetector {
+    pub fn new(leader: Id) -> Self {
+        Self {
+            leader: Arc::new(AtomicU64::new(leader.0)),
+            watchers: Arc::default(),
+        }
+    }
+
+    pub fn set_leader(&self, leader: Id) {
+        self.leader.store(leader.0, Ordering::Release);
+        for watcher in lock(&self.watchers).drain(..) {
+            let _ = watcher.send(());
+        }
+    }
+}
+
+impl FailureDetector for MockFailureDetector {
+    fn leader(&self) -> Id {
+        Id(self.leader.load(Ordering::Acquire))
+    }
+
+    fn changed(&self) -> impl Future<Output = ()> {
+        let (sender, receiver) = oneshot::channel();
+        lock(&self.watchers).push(sender);
+        receiver.map(drop)
+    }
+}
+
+#[track_caller]
+pub fn assert_promised<V>(alpha: &Alpha<V>, round: Round) {
+    assert_eq!(
+        alpha.last_round_entered(),
+        round,
+        "acceptor promised the wrong round"
+    );
+}
+
+#[track_caller]
+pub fn assert_accepted<V: PartialEq + Debug>(alpha: &Alpha<V>, value: &V, round: Round) {
+    assert_eq!(
+        alpha.accepted_value(),
+        Some(value),
+        "acceptor accepted the wrong value"
+    );
+    assert_eq!(
+        alpha.accepted_round(),
+        Some(round),
+        "acceptor accepted in the wrong round"
+    );
+}
+
+#[track_caller]
+pub fn assert_nothing_accepted<V: Debug>(alpha: &Alpha<V>) {
+    assert!(
+        alpha.accepted_value().is_none(),
+        "acceptor accepted {:?}",
+        alpha.accepted_value()
+    );
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
@@ -0,0 +1,447 @@
+use futures::future::{select, BoxFuture, Either};
+use futures::FutureExt;
+#[cfg(feature = "threads")]
+use std::cmp::Ordering;
+#[cfg(feature = "threads")]
+use std::collections::BinaryHeap;
+use std::future::{poll_fn, Future};
+use std::pin::{pin, Pin};
+#[cfg(feature = "threads")]
+use std::sync::Condvar;
+use std::sync::OnceLock;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+#[cfg(feature = "threads")]
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+    fn system_time(&self) -> SystemTime;
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+
+    fn deadline(&self, duration: Duration) -> Instant {
+        self.now() + duration
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        (**self).system_time()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        (**self).sleep(duration)
+    }
+}
+
+pub trait Timeouts: Send + Sync {
+    fn stage_timeout(&self, quorum: usize) -> Option<Duration>;
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        sleep(duration).boxed()
+    }
+}
+
+#[derive(Clone)]
+pub struct MockClock {
+    inner: Arc<Mutex<MockState>>,
+}
+
+struct MockState {
+    now: Instant,
+    system_time: SystemTime,
+    sleepers: Vec<(Instant, Waker)>,
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(MockState {
+                now: Instant::now(),
+                system_time: SystemTime::now(),
+                sleepers: Vec::new(),
+            })),
+        }
+    }
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.state();
+        state.now += duration;
+        state.system_time += duration;
+        let now = state.now;
+        let (due, pending): (Vec<_>, Vec<_>) = state
+            .sleepers
+            .drain(..)
+            .partition(|(deadline, _)| *deadline <= now);
+        state.sleepers = pending;
+        drop(state);
+        for (_, waker) in due {
+            waker.wake();
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.state().now
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.state().system_time
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        let clock = self.clone();
+        let deadline = self.deadline(duration);
+        poll_fn(move |cx| {
+            let mut state = clock.state();
+            if state.now >= deadline {
+                return Poll::Ready(());
+            }
+            state.sleepers.push((deadline, cx.waker().clone()));
+            Poll::Pending
+        })
+        .boxed()
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct Timestamp(u64);
+
+impl Timestamp {
+    const LOGICAL_BITS: u32 = 16;
+
+    pub fn new(millis: u64, logical: u16) -> Self {
+        Self(millis << Self::LOGICAL_BITS | logical as u64)
+    }
+
+    pub fn physical(clock: &dyn Clock) -> Self {
+        let millis = clock
+            .system_time()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        Self::new(millis, 0)
+    }
+
+    pub fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    pub fn to_bits(self) -> u64 {
+        self.0
+    }
+
+    pub fn millis(self) -> u64 {
+        self.0 >> Self::LOGICAL_BITS
+    }
+
+    pub fn logical(self) -> u16 {
+        self.0 as u16
+    }
+
+    pub fn after(self, duration: Duration) -> Self {
+        Self::new(self.millis() + duration.as_millis() as u64, self.logical())
+    }
+}
+
+/// A hybrid logical clock: timestamps follow the wall clock but never go
+/// backwards, and stay ahead of every timestamp observed from elsewhere.
+#[derive(Clone)]
+pub struct HybridClock {
+    clock: Arc<dyn Clock>,
+    last: Timestamp,
+}
+
+impl HybridClock {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            last: Timestamp::default(),
+        }
+    }
+
+    pub fn now(&mut self) -> Timestamp {
+        self.last = Timestamp(self.last.0 + 1).max(Timestamp::physical(&*self.clock));
+        self.last
+    }
+
+    pub fn observe(&mut self, timestamp: Timestamp) {
+        self.last = self.last.max(timestamp);
+    }
+
+    pub fn last(&self) -> Timestamp {
+        self.last
+    }
+}
+
+/// Sleeps on the timer thread, or without the `threads` feature on the timer
+/// given to [`install_timer`]. With neither, the sleep never ends.
+pub fn sleep(duration: Duration) -> Sleep {
+    #[cfg(feature = "threads")]
+    return Sleep {
+        deadline: Instant::now() + duration,
+        waker: None,
+    };
+    #[cfg(not(feature = "threads"))]
+    Sleep {
+        timer: TIMER.get().map(|timer| timer.sleep(duration)),
+    }
+}
+
+#[cfg(not(feature = "threads"))]
+static TIMER: OnceLock<Arc<dyn Clock>> = OnceLock::new();
+
+/// Drives [`sleep`], and so [`SystemClock`], where there is no timer thread,
+/// e.g. from the host's event loop. Only the first timer installed is kept,
+/// and it must not be [`SystemClock`] itself.
+#[cfg(not(feature = "threads"))]
+pub fn install_timer(timer: Arc<dyn Clock>) -> Result<(), Arc<dyn Clock>> {
+    TIMER.set(timer)
+}
+
+pub async fn timeout<F: Future>(duration: Duration, future: F) -> Result<F::Output, Elapsed> {
+    timeout_with(&SystemClock, duration, future).await
+}
+
+pub async fn timeout_with<F: Future>(
+    clock: &dyn Clock,
+    duration: Duration,
+    future: F,
+) -> Result<F::Output, Elapsed> {
+    match select(pin!(future), clock.sleep(duration)).await {
+        Either::Left((output, _)) => Ok(output),
+        Either::Right(_) => Err(Elapsed),
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("deadline elapsed")]
+pub struct Elapsed;
+
+pub struct Sleep {
+    #[cfg(feature = "threads")]
+    deadline: Instant,
+    #[cfg(feature = "threads")]
+    waker: Option<Arc<Mutex<Waker>>>,
+    #[cfg(not(feature = "threads"))]
+    timer: Option<BoxFuture<'static, ()>>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    #[cfg(feature = "threads")]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+        match &self.waker {
+            Some(waker) => {
+                *waker.lock().unwrap_or_else(PoisonError::into_inner) = cx.waker().clone();
+            }
+            None => {
+                let waker = Arc::new(Mutex::new(cx.waker().clone()));
+                schedule(self.deadline, waker.clone());
+                self.waker = Some(waker);
+            }
+        }
+        Poll::Pending
+    }
+
+    #[cfg(not(feature = "threads"))]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        match &mut self.timer {
+            Some(timer) => timer.poll_unpin(cx),
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(feature = "threads")]
+fn schedule(deadline: Instant, waker: Arc<Mutex<Waker>>) {
+    let timer = Timer::get();
+    let mut sleepers = timer.lock();
+    let earliest = sleepers
+        .peek()
+        .is_none_or(|first| deadline < first.deadline);
+    sleepers.push(Sleeper { deadline, waker });
+    drop(sleepers);
+    if earliest {
+        timer.changed.notify_one();
+    }
+}
+
+/// A single background thread that wakes every pending [`Sleep`].
+#[cfg(feature = "threads")]
+struct Timer {
+    sleepers: Mutex<BinaryHeap<Sleeper>>,
+    changed: Condvar,
+}
+
+#[cfg(feature = "threads")]
+struct Sleeper {
+    deadline: Instant,
+    waker: Arc<Mutex<Waker>>,
+}
+
+#[cfg(feature = "threads")]
+impl PartialEq for Sleeper {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+#[cfg(feature = "threads")]
+impl Eq for Sleeper {}
+
+#[cfg(feature = "threads")]
+impl PartialOrd for Sleeper {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Reversed so the heap yields the earliest deadline first.
+#[cfg(feature = "threads")]
+impl Ord for Sleeper {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.deadline.cmp(&self.deadline)
+    }
+}
+
+#[cfg(feature = "threads")]
+impl Timer {
+    fn get() -> &'static Self {
+        static TIMER: OnceLock<Timer> = OnceLock::new();
+        TIMER.get_or_init(|| {
+            thread::spawn(|| Timer::get().run());
+            Timer {
+                sleepers: Mutex::new(BinaryHeap::new()),
+                changed: Condvar::new(),
+            }
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BinaryHeap<Sleeper>> {
+        self.sleepers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn run(&self) {
+        let mut sleepers = self.lock();
+        loop {
+            let now = Instant::now();
+            let next = sleepers.peek().map(|first| first.deadline);
+            sleepers = match next {
+                Some(deadline) if deadline <= now => {
+                    let due = sleepers.pop().expect("peeked");
+                    drop(sleepers);
+                    due.waker
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .wake_by_ref();
+                    self.lock()
+                }
+                Some(deadline) => {
+                    self.changed
+                        .wait_timeout(sleepers, deadline - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+                None => self
+                    .changed
+                    .wait(sleepers)
+                    .unwrap_or_else(PoisonError::into_inner),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "threads")]
+    #[test]
+    fn sleeps_wake_after_their_deadlines() {
+        use futures::executor::block_on;
+        use futures::future::join_all;
+
+        let started = Instant::now();
+        let early = block_on(join_all((0..50u64).rev().map(|i| async move {
+            let duration = Duration::from_millis(i * 2);
+            sleep(duration).await;
+            started.elapsed() < duration
+        })));
+        assert!(!early.contains(&true));
+    }
+
+    #[cfg(not(feature = "threads"))]
+    #[test]
+    fn sleeps_follow_the_installed_timer() {
+        let clock = MockClock::new();
+        assert!(install_timer(Arc::new(clock.clone())).is_ok());
+        let mut sleeping = sleep(Duration::from_secs(1));
+        assert!((&mut sleeping).now_or_never().is_none());
+        clock.advance(Duration::from_secs(1));
+        assert!(sleeping.now_or_never().is_some());
+    }
+
+    #[test]
+    fn mock_clock_advances_system_time() {
+        let clock = MockClock::new();
+        let before = clock.system_time();
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(clock.system_time(), before + Duration::from_secs(3));
+    }
+
+    #[test]
+    fn hybrid_timestamps_never_go_backwards() {
+        let clock = MockClock::new();
+        let mut hlc = HybridClock::new(Arc::new(clock.clone()));
+        let first = hlc.now();
+        assert_eq!(first, Timestamp::physical(&clock));
+        let second = hlc.now();
+        assert_eq!((second.millis(), second.logical()), (first.millis(), 1));
+
+        let remote = Timestamp::new(first.millis() + 5_000, 7);
+        hlc.observe(remote);
+        let merged = hlc.now();
+        assert!(merged > remote);
+        assert_eq!(merged.millis(), remote.millis());
+
+        clock.advance(Duration::from_secs(10));
+        let later = hlc.now();
+        assert_eq!(later, Timestamp::physical(&clock));
+        assert!(later > merged);
+    }
+}
@@ -0,0 +1,332 @@
+//! Bearer tokens clients present with their proposals and reads. A
+//! [`Server`](crate::transport::tcp::Server) given a [`TokenVerifier`]
+//! refuses client requests that carry no token it accepts, so an exposed
+//! endpoint cannot be written to by anyone who can reach it.
+//!
+//! [`JoinTokens`] are the same for new members: a node only adds itself to
+//! the cluster with a token an operator had the leader issue for it.
+
+use crate::alpha::Id;
+use crate::digest::sha256;
+use crate::time::Clock;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// What a client request does; write access includes reads.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum TokenError {
+    #[error("a token is required")]
+    Missing,
+    #[error("the token is not valid")]
+    Invalid,
+    #[error("the token does not grant {0} access")]
+    Forbidden(Access),
+    #[error("the token has expired")]
+    Expired,
+    #[error("invalid token entry `{0}`")]
+    Syntax(String),
+}
+
+pub trait TokenVerifier: fmt::Debug + Send + Sync {
+    /// Accepts `token` for a request that needs `access`.
+    fn verify(&self, token: &str, access: Access) -> Result<(), TokenError>;
+}
+
+/// A fixed set of tokens, each granting read or write access, written
+/// `write:<token>,read:<token>` in configs.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct StaticTokens {
+    tokens: Vec<(String, Access)>,
+}
+
+impl StaticTokens {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_token(mut self, token: impl Into<String>, access: Access) -> Self {
+        let token = token.into();
+        self.tokens.retain(|(known, _)| *known != token);
+        self.tokens.push((token, access));
+        self
+    }
+}
+
+impl TokenVerifier for StaticTokens {
+    // Every token is compared in full, so the time taken does not tell how
+    // much of a guess was right.
+    fn verify(&self, token: &str, access: Access) -> Result<(), TokenError> {
+        let granted = self
+            .tokens
+            .iter()
+            .filter(|(known, _)| constant_time_eq(known.as_bytes(), token.as_bytes()))
+            .map(|(_, granted)| *granted)
+            .max();
+        match granted {
+            None => Err(TokenError::Invalid),
+            Some(granted) if granted < access => Err(TokenError::Forbidden(access)),
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+// Tokens are secrets, so only their number is shown.
+impl fmt::Debug for StaticTokens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "StaticTokens({} tokens)", self.tokens.len())
+    }
+}
+
+impl FromStr for StaticTokens {
+    type Err = TokenError;
+
+    fn from_str(tokens: &str) -> Result<Self, Self::Err> {
+        tokens
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .try_fold(Self::new(), |parsed, entry| {
+                let (token, access) = match entry.split_once(':') {
+                    Some(("read", token)) if !token.is_empty() => (token, Access::Read),
+                    Some(("write", token)) if !token.is_empty() => (token, Access::Write),
+                    _ => return Err(TokenError::Syntax(entry.to_string())),
+                };
+                Ok(parsed.with_token(token, access))
+            })
+    }
+}
+
+/// Verifies with whatever verifier it last [reloaded](Self::reload), so a
+/// running server can take new tokens without a restart.
+#[derive(Debug)]
+pub struct ReloadableTokens(RwLock<Arc<dyn TokenVerifier>>);
+
+impl ReloadableTokens {
+    pub fn new(tokens: Arc<dyn TokenVerifier>) -> Self {
+        Self(RwLock::new(tokens))
+    }
+
+    pub fn reload(&self, tokens: Arc<dyn TokenVerifier>) {
+        *self.0.write().unwrap_or_else(PoisonError::into_inner) = tokens;
+    }
+}
+
+impl TokenVerifier for ReloadableTokens {
+    fn verify(&self, token: &str, access: Access) -> Result<(), TokenError> {
+        let tokens = self
+            .0
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        tokens.verify(token, access)
+    }
+}
+
+/// The join tokens a leader issued, each letting the node it names join
+/// once before it expires. Only the leader that issued a token accepts it,
+/// so a change of leader voids them.
+pub struct JoinTokens {
+    clock: Arc<dyn Clock>,
+    issued: Mutex<Vec<Issued>>,
+}
+
+/// Tokens are kept only as their digest.
+struct Issued {
+    digest: [u8; 32],
+    id: Id,
+    expires: Instant,
+}
+
+/// A join token taken out while its node joins; it is put back unless the
+/// join [succeeds](Self::spend), so a join that failed can be retried.
+pub struct Redeemed<'a> {
+    tokens: &'a JoinTokens,
+    issued: Option<Issued>,
+}
+
+impl JoinTokens {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            issued: Mutex::default(),
+        }
+    }
+
+    /// A token for node `id` to join with in the next `ttl`.
+    pub fn issue(&self, id: Id, ttl: Duration) -> String {
+        let token: String = unguessable()[..16]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        let now = self.clock.now();
+        let mut issued = self.issued();
+        issued.retain(|issued| issued.expires > now);
+        issued.push(Issued {
+            digest: sha256(token.as_bytes()),
+            id,
+            expires: now + ttl,
+        });
+        token
+    }
+
+    /// Takes out the token issued for node `id`, if it has not expired.
+    pub fn redeem(&self, token: &str, id: Id) -> Result<Redeemed<'_>, TokenError> {
+        let digest = sha256(token.as_bytes());
+        let mut issued = self.issued();
+        let position = issued
+            .iter()
+            .position(|issued| issued.digest == digest && issued.id == id)
+            .ok_or(TokenError::Invalid)?;
+        let taken = issued.swap_remove(position);
+        if taken.expires <= self.clock.now() {
+            return Err(TokenError::Expired);
+        }
+        Ok(Redeemed {
+            tokens: self,
+            issued: Some(taken),
+        })
+    }
+
+    fn issued(&self) -> MutexGuard<'_, Vec<Issued>> {
+        self.issued.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Redeemed<'_> {
+    /// Keeps the token from being used again.
+    pub fn spend(mut self) {
+        self.issued = None;
+    }
+}
+
+impl Drop for Redeemed<'_> {
+    fn drop(&mut self) {
+        if let Some(issued) = self.issued.take() {
+            self.tokens.issued().push(issued);
+        }
+    }
+}
+
+/// Bytes nobody can guess: std keys every `RandomState` from randomness
+/// the operating system hands the process.
+fn unguessable() -> [u8; 32] {
+    let mut seed = Vec::with_capacity(32);
+    for part in 0..4u64 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(part);
+        seed.extend_from_slice(&hasher.finish().to_be_bytes());
+    }
+    sha256(&seed)
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Access::Read => "read",
+            Access::Write => "write",
+        })
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::MockClock;
+
+    #[test]
+    fn write_tokens_also_read_and_read_tokens_only_read() {
+        let tokens: StaticTokens = "write:w-secret, read:r-secret".parse().unwrap();
+        assert_eq!(tokens.verify("w-secret", Access::Write), Ok(()));
+        assert_eq!(tokens.verify("w-secret", Access::Read), Ok(()));
+        assert_eq!(tokens.verify("r-secret", Access::Read), Ok(()));
+        assert_eq!(
+            tokens.verify("r-secret", Access::Write),
+            Err(TokenError::Forbidden(Access::Write))
+        );
+        assert_eq!(
+            tokens.verify("w-secre", Access::Read),
+            Err(TokenError::Invalid)
+        );
+        assert_eq!(format!("{tokens:?}"), "StaticTokens(2 tokens)");
+
+        for invalid in ["admin:x", "write:", "secret"] {
+            assert!(matches!(
+                invalid.parse::<StaticTokens>(),
+                Err(TokenError::Syntax(_))
+            ));
+        }
+        let regranted: StaticTokens = "read:x,write:x".parse().unwrap();
+        assert_eq!(
+            regranted,
+            StaticTokens::new().with_token("x", Access::Write)
+        );
+    }
+
+    #[test]
+    fn join_tokens_admit_the_node_they_name_once_before_they_expire() {
+        let clock = MockClock::new();
+        let tokens = JoinTokens::new(Arc::new(clock.clone()));
+        let token = tokens.issue(Id(4), Duration::from_secs(60));
+        assert_eq!(token.len(), 32);
+        assert_ne!(tokens.issue(Id(4), Duration::from_secs(60)), token);
+
+        assert!(matches!(
+            tokens.redeem(&token, Id(5)),
+            Err(TokenError::Invalid)
+        ));
+        // A join that fails puts the token back.
+        drop(tokens.redeem(&token, Id(4)).unwrap());
+        let redeemed = tokens.redeem(&token, Id(4)).unwrap();
+        assert!(matches!(
+            tokens.redeem(&token, Id(4)),
+            Err(TokenError::Invalid)
+        ));
+        redeemed.spend();
+        assert!(matches!(
+            tokens.redeem(&token, Id(4)),
+            Err(TokenError::Invalid)
+        ));
+
+        let expiring = tokens.issue(Id(6), Duration::from_secs(1));
+        clock.advance(Duration::from_secs(1));
+        assert!(matches!(
+            tokens.redeem(&expiring, Id(6)),
+            Err(TokenError::Expired)
+        ));
+        assert!(matches!(
+            tokens.redeem(&expiring, Id(6)),
+            Err(TokenError::Invalid)
+        ));
+    }
+
+    #[test]
+    fn reloaded_tokens_replace_the_old_ones() {
+        let tokens = ReloadableTokens::new(Arc::new(
+            StaticTokens::new().with_token("old", Access::Write),
+        ));
+        assert_eq!(tokens.verify("old", Access::Write), Ok(()));
+        tokens.reload(Arc::new(
+            StaticTokens::new().with_token("new", Access::Read),
+        ));
+        assert_eq!(tokens.verify("old", Access::Read), Err(TokenError::Invalid));
+        assert_eq!(
+            tokens.verify("new", Access::Write),
+            Err(TokenError::Forbidden(Access::Write))
+        );
+    }
+}
@@ -0,0 +1,252 @@
+use crate::alpha::{
+    Error, Id, Quorum, ReadPeers, ReadResponse, Round, Value, WritePeers, WriteResponse,
+};
+use crate::learner::{DecisionBroadcast, DecisionPeers};
+use futures::stream::{self, FuturesUnordered};
+use futures::{Stream, StreamExt};
+use std::future::Future;
+use std::task::Poll;
+
+pub trait Peer<V> {
+    fn read(&self, round: Round) -> impl Future<Output = Result<ReadResponse<V>, Error>>;
+    fn write(&self, value: Value<V>) -> impl Future<Output = Result<WriteResponse, Error>>;
+    fn decide(&self, decision: DecisionBroadcast<V>) -> impl Future<Output = Result<(), Error>>;
+
+    /// The acceptor behind this peer, if known, so targeted writes can skip
+    /// it.
+    fn id(&self) -> Option<Id> {
+        None
+    }
+}
+
+/// Fans a request out to every peer, yielding responses as they arrive.
+///
+/// The first `concurrency` requests are made when the stream is created, and
+/// each response lets one more go out, so peers past the limit are only
+/// contacted while the stream is polled. Decisions and targeted writes skip
+/// the limit, since proposers send them without waiting for the acks.
+#[derive(Clone, Debug)]
+pub struct Broadcast<P> {
+    peers: Vec<P>,
+    concurrency: usize,
+}
+
+impl<P> Broadcast<P> {
+    pub fn new(peers: Vec<P>) -> Self {
+        Self {
+            peers,
+            concurrency: usize::MAX,
+        }
+    }
+
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn peers(&self) -> &[P] {
+        &self.peers
+    }
+
+    fn fan_out<'a, F, T>(
+        &'a self,
+        request: impl FnMut(&'a P) -> F + 'a,
+    ) -> impl Stream<Item = Result<T, Error>> + 'a
+    where
+        F: Future<Output = Result<T, Error>> + 'a,
+    {
+        fan_out(self.peers.iter(), self.concurrency, request)
+    }
+
+    /// Like the trait methods, but owning the peers, for transports that
+    /// build a broadcast per request.
+    #[cfg(feature = "threads")]
+    pub(crate) fn into_fan_out<F, T>(
+        self,
+        request: impl FnMut(P) -> F,
+    ) -> impl Stream<Item = Result<T, Error>>
+    where
+        F: Future<Output = Result<T, Error>>,
+    {
+        fan_out(self.peers.into_iter(), self.concurrency, request)
+    }
+}
+
+fn fan_out<I: Iterator, F: Future>(
+    mut peers: I,
+    concurrency: usize,
+    mut request: impl FnMut(I::Item) -> F,
+) -> impl Stream<Item = F::Output> {
+    let mut pending: FuturesUnordered<F> =
+        peers.by_ref().take(concurrency).map(&mut request).collect();
+    stream::poll_fn(move |cx| {
+        let response = pending.poll_next_unpin(cx);
+        if let Poll::Ready(Some(_)) = response {
+            pending.extend(peers.next().map(&mut request));
+        }
+        response
+    })
+}
+
+impl<V: 'static, P: Peer<V>> ReadPeers<V> for Broadcast<P> {
+    fn read(&self, round: Round) -> impl Stream<Item = Result<ReadResponse<V>, Error>> {
+        self.fan_out(move |peer| peer.read(round))
+    }
+}
+
+impl<V: Clone + 'static, P: Peer<V>> WritePeers<V> for Broadcast<P> {
+    fn write(&self, value: Value<V>) -> impl Stream<Item = Result<WriteResponse, Error>> {
+        self.fan_out(move |peer| peer.write(value.clone()))
+    }
+
+    fn write_to(
+        &self,
+        acceptors: &[Id],
+        value: Value<V>,
+    ) -> impl Stream<Item = Result<WriteResponse, Error>> {
+        let targeted = self
+            .peers
+            .iter()
+            .filter(|peer| peer.id().is_none_or(|id| acceptors.contains(&id)));
+        fan_out(targeted, usize::MAX, move |peer| peer.write(value.clone()))
+    }
+}
+
+impl<V: Clone + 'static, P: Peer<V>> DecisionPeers<V> for Broadcast<P> {
+    fn decide(&self, decision: DecisionBroadcast<V>) -> impl Stream<Item = Result<(), Error>> {
+        fan_out(self.peers.iter(), usize::MAX, move |peer| {
+            peer.decide(decision.clone())
+        })
+    }
+}
+
+impl<P> Quorum for Broadcast<P> {
+    fn majority(&self) -> usize {
+        self.peers.len() / 2 + 1
+    }
+
+    fn max_failures(&self) -> usize {
+        self.peers.len().saturating_sub(self.majority())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alpha::Alpha;
+    use futures::channel::oneshot;
+    use futures::FutureExt;
+    use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+    type Started = Arc<Mutex<Vec<Id>>>;
+
+    #[derive(Clone)]
+    struct Gated {
+        id: Id,
+        started: Started,
+        gate: Arc<Mutex<Option<oneshot::Receiver<()>>>>,
+    }
+
+    impl Gated {
+        fn respond<T>(&self, response: T) -> impl Future<Output = Result<T, Error>> {
+            lock(&self.started).push(self.id);
+            let gate = lock(&self.gate).take();
+            async move {
+                if let Some(gate) = gate {
+                    let _ = gate.await;
+                }
+                Ok(response)
+            }
+        }
+    }
+
+    impl Peer<u64> for Gated {
+        fn read(&self, round: Round) -> impl Future<Output = Result<ReadResponse<u64>, Error>> {
+            self.respond(Alpha::new().read(self.id, round))
+        }
+
+        fn write(&self, value: Value<u64>) -> impl Future<Output = Result<WriteResponse, Error>> {
+            self.respond(Alpha::new().write(self.id, value))
+        }
+
+        fn decide(&self, _: DecisionBroadcast<u64>) -> impl Future<Output = Result<(), Error>> {
+            self.respond(())
+        }
+
+        fn id(&self) -> Option<Id> {
+            Some(self.id)
+        }
+    }
+
+    fn gated(n: u64) -> (Broadcast<Gated>, Vec<oneshot::Sender<()>>, Started) {
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let (peers, gates) = (0..n)
+            .map(|i| {
+                let (open, gate) = oneshot::channel();
+                let peer = Gated {
+                    id: Id(i),
+                    started: started.clone(),
+                    gate: Arc::new(Mutex::new(Some(gate))),
+                };
+                (peer, open)
+            })
+            .unzip();
+        (Broadcast::new(peers), gates, started)
+    }
+
+    fn acceptor(response: Option<Option<Result<ReadResponse<u64>, Error>>>) -> Id {
+        response.unwrap().unwrap().unwrap().acceptor
+    }
+
+    #[test]
+    fn yields_responses_as_they_arrive() {
+        let (broadcast, gates, started) = gated(3);
+        let mut gates: Vec<_> = gates.into_iter().map(Some).collect();
+        let mut responses = broadcast.read(Round::new(Id(0)));
+        assert_eq!(*lock(&started), [Id(0), Id(1), Id(2)]);
+        assert!(responses.next().now_or_never().is_none());
+        for id in [2, 0, 1] {
+            let _ = gates[id].take().unwrap().send(());
+            assert_eq!(acceptor(responses.next().now_or_never()), Id(id as u64));
+        }
+        assert!(matches!(responses.next().now_or_never(), Some(None)));
+    }
+
+    #[test]
+    fn holds_peers_past_the_limit_until_a_response_arrives() {
+        let (broadcast, mut gates, started) = gated(3);
+        let broadcast = broadcast.concurrency(2);
+        let mut responses = broadcast.read(Round::new(Id(0)));
+        assert_eq!(*lock(&started), [Id(0), Id(1)]);
+        assert!(responses.next().now_or_never().is_none());
+        let _ = gates.remove(1).send(());
+        assert_eq!(acceptor(responses.next().now_or_never()), Id(1));
+        assert_eq!(*lock(&started), [Id(0), Id(1), Id(2)]);
+    }
+
+    #[test]
+    fn targeted_writes_skip_the_limit() {
+        let (broadcast, _gates, started) = gated(3);
+        let broadcast = broadcast.concurrency(1);
+        drop(broadcast.write_to(&[Id(0), Id(2)], Value::new(7, Round::new(Id(0)))));
+        assert_eq!(*lock(&started), [Id(0), Id(2)]);
+    }
+
+    #[test]
+    fn decisions_skip_the_limit() {
+        let (broadcast, _gates, started) = gated(3);
+        let broadcast = broadcast.concurrency(1);
+        let decision = DecisionBroadcast {
+            proposer: Id(0),
+            round: Round::new(Id(0)),
+            value: 7,
+            responses: Vec::new(),
+        };
+        drop(broadcast.decide(decision));
+        assert_eq!(*lock(&started), [Id(0), Id(1), Id(2)]);
+    }
+
+    fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+        mutex.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
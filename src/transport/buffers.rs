@@ -0,0 +1,73 @@
+use std::sync::{Mutex, PoisonError};
+
+// Buffers above this are dropped rather than kept, so one huge message does
+// not pin its allocation for good.
+const MAX_CAPACITY: usize = 1 << 20;
+const MAX_BUFFERS: usize = 64;
+
+/// Buffers handed back once a frame is done with, so the next frame reuses
+/// their allocation instead of making a fresh one.
+pub(super) struct BufferPool {
+    free: Mutex<Vec<Vec<u8>>>,
+}
+
+pub(super) static BUFFERS: BufferPool = BufferPool::new();
+
+impl BufferPool {
+    const fn new() -> Self {
+        Self {
+            free: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// An empty buffer, reusing a returned one when there is any.
+    pub(super) fn take(&self) -> Vec<u8> {
+        self.free
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop()
+            .unwrap_or_default()
+    }
+
+    pub(super) fn give(&self, mut buf: Vec<u8>) {
+        if buf.capacity() == 0 || buf.capacity() > MAX_CAPACITY {
+            return;
+        }
+        buf.clear();
+        let mut free = self.free.lock().unwrap_or_else(PoisonError::into_inner);
+        if free.len() < MAX_BUFFERS {
+            free.push(buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_returned_buffers() {
+        let pool = BufferPool::new();
+        let mut buf = pool.take();
+        buf.extend_from_slice(b"frame");
+        let allocation = buf.as_ptr();
+        pool.give(buf);
+
+        let buf = pool.take();
+        assert!(buf.is_empty());
+        assert_eq!(buf.as_ptr(), allocation);
+        assert_eq!(pool.take().capacity(), 0);
+    }
+
+    #[test]
+    fn keeps_a_bounded_number_of_small_buffers() {
+        let pool = BufferPool::new();
+        pool.give(Vec::with_capacity(MAX_CAPACITY + 1));
+        assert_eq!(pool.take().capacity(), 0);
+
+        for _ in 0..MAX_BUFFERS + 1 {
+            pool.give(Vec::with_capacity(16));
+        }
+        assert_eq!(pool.free.lock().unwrap().len(), MAX_BUFFERS);
+    }
+}
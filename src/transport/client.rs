@@ -0,0 +1,359 @@
+use super::tcp::{Health, NodeStatus, Proposed, TcpPeers};
+use crate::alpha::{Error, Id};
+#[cfg(feature = "auth")]
+use crate::auth::Keyring;
+use crate::codec::{Decode, Encode};
+use crate::membership;
+use crate::retry::RetryPolicy;
+use crate::rng::XorShift;
+use crate::sealed::{SealError, Sealed, ValueKey};
+use crate::time::{Clock, SystemClock};
+use futures::{Stream, StreamExt};
+use std::io;
+use std::net::SocketAddr;
+#[cfg(any(test, feature = "auth"))]
+use std::sync::Arc;
+
+/// Talks to a running cluster one member at a time, the way an operator or
+/// application does: proposals follow the members' redirects to whoever
+/// leads, and a member that fails is retried with the next one.
+pub struct Client<V> {
+    members: Vec<Member<V>>,
+    leader: usize,
+    retry_policy: RetryPolicy,
+    clock: Box<dyn Clock>,
+    rng: XorShift,
+}
+
+struct Member<V> {
+    id: Option<Id>,
+    addr: SocketAddr,
+    peers: TcpPeers<V>,
+}
+
+impl<V> Client<V>
+where
+    V: Clone + Encode + Decode + Send + Sync + 'static,
+{
+    /// Members without an id are still reached, but redirects can only
+    /// point at members whose id is known.
+    pub fn new(members: impl IntoIterator<Item = (Option<Id>, SocketAddr)>) -> Self {
+        let members = members
+            .into_iter()
+            .map(|(id, addr)| Member {
+                id,
+                addr,
+                peers: TcpPeers::new(vec![addr]),
+            })
+            .collect();
+        Self {
+            members,
+            leader: 0,
+            retry_policy: RetryPolicy::default(),
+            clock: Box::new(SystemClock),
+            rng: XorShift::new(0),
+        }
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Presents `token` with every proposal and read.
+    pub fn with_token(mut self, token: &str) -> Self {
+        for member in &mut self.members {
+            member.peers = member.peers.clone().with_token(token);
+        }
+        self
+    }
+
+    /// Authenticates with every member whose id is known.
+    #[cfg(feature = "auth")]
+    pub fn with_auth(mut self, keyring: Arc<Keyring>) -> Self {
+        for member in &mut self.members {
+            if let Some(id) = member.id {
+                member.peers = member.peers.clone().with_auth(keyring.clone(), vec![id]);
+            }
+        }
+        self
+    }
+
+    pub fn members(&self) -> impl Iterator<Item = (Option<Id>, SocketAddr)> + '_ {
+        self.members.iter().map(|member| (member.id, member.addr))
+    }
+
+    /// Proposes `value` to the member that led last time, following its
+    /// redirect when it no longer does. The first redirect is followed at
+    /// once; members that fail, or disagree on who leads, are retried after
+    /// a backoff. A proposal over the client's quota is not retried, so the
+    /// caller decides whether to wait out [`Error::Throttled`].
+    pub async fn propose(&mut self, value: V) -> Result<V, Error> {
+        if self.members.is_empty() {
+            return Err(Error::QuorumUnreachable { errors: Vec::new() });
+        }
+        let mut attempts = 0;
+        let mut redirected = false;
+        loop {
+            attempts += 1;
+            let proposed = first(self.members[self.leader].peers.propose(value.clone())).await;
+            let (error, leader) = match proposed {
+                Ok(Proposed::Decided(decided)) => return Ok(decided),
+                Ok(Proposed::Throttled(retry_after)) => {
+                    return Err(Error::Throttled { retry_after })
+                }
+                Ok(Proposed::NotLeader(leader)) => (
+                    Error::NotLeader,
+                    leader.and_then(|leader| self.position(leader)),
+                ),
+                Err(error) => (error, None),
+            };
+            if self.retry_policy.exhausted(attempts) {
+                return Err(exhausted(attempts, error));
+            }
+            match leader.filter(|_| !redirected) {
+                Some(leader) => {
+                    self.leader = leader;
+                    redirected = true;
+                }
+                None => {
+                    self.leader = leader.unwrap_or((self.leader + 1) % self.members.len());
+                    redirected = false;
+                    let backoff = self.retry_policy.backoff(attempts, &mut self.rng);
+                    self.clock.sleep(backoff).await;
+                }
+            }
+        }
+    }
+
+    /// What the first member that knows has learned was decided, or `None`
+    /// when every member that answered is still undecided.
+    pub async fn decision(&self) -> Result<Option<V>, Error> {
+        let mut errors = Vec::new();
+        for member in &self.members {
+            match first(member.peers.decision()).await {
+                Ok(Some(decided)) => return Ok(Some(decided)),
+                Ok(None) => {}
+                Err(error) => errors.push(error),
+            }
+        }
+        if !self.members.is_empty() && errors.len() == self.members.len() {
+            return Err(Error::QuorumUnreachable { errors });
+        }
+        Ok(None)
+    }
+
+    /// Every member of the cluster, as the first member that answers knows
+    /// them.
+    pub async fn topology(&self) -> Result<Vec<membership::Member>, Error> {
+        let mut errors = Vec::new();
+        for member in &self.members {
+            match first(member.peers.topology()).await {
+                Ok(members) => return Ok(members),
+                Err(error) => errors.push(error),
+            }
+        }
+        Err(Error::QuorumUnreachable { errors })
+    }
+
+    /// Every member's status, in the order the members were given.
+    pub async fn status(&self) -> Vec<(SocketAddr, Result<NodeStatus, Error>)> {
+        let mut statuses = Vec::with_capacity(self.members.len());
+        for member in &self.members {
+            statuses.push((member.addr, first(member.peers.status()).await));
+        }
+        statuses
+    }
+
+    /// How ready every member is to serve, in the order the members were
+    /// given.
+    pub async fn health(&self) -> Vec<(SocketAddr, Result<Health, Error>)> {
+        let mut health = Vec::with_capacity(self.members.len());
+        for member in &self.members {
+            health.push((member.addr, first(member.peers.health()).await));
+        }
+        health
+    }
+
+    fn position(&self, id: Id) -> Option<usize> {
+        self.members.iter().position(|member| member.id == Some(id))
+    }
+}
+
+impl Client<Sealed> {
+    /// Proposes `value` sealed with `key`, so the members only ever hold its
+    /// ciphertext, and opens whatever was decided instead.
+    pub async fn propose_sealed<T: Encode + Decode>(
+        &mut self,
+        key: &ValueKey,
+        value: &T,
+    ) -> Result<T, Error> {
+        let decided = self.propose(key.seal(value)).await?;
+        key.open(&decided).map_err(unreadable)
+    }
+
+    /// The decided value opened with `key`.
+    pub async fn decision_opened<T: Decode>(&self, key: &ValueKey) -> Result<Option<T>, Error> {
+        self.decision()
+            .await?
+            .map(|decided| key.open(&decided).map_err(unreadable))
+            .transpose()
+    }
+}
+
+fn unreadable(error: SealError) -> Error {
+    io::Error::new(io::ErrorKind::InvalidData, error).into()
+}
+
+async fn first<T>(responses: impl Stream<Item = Result<T, Error>>) -> Result<T, Error> {
+    let mut responses = Box::pin(responses);
+    responses
+        .next()
+        .await
+        .unwrap_or_else(|| Err(io::Error::from(io::ErrorKind::BrokenPipe).into()))
+}
+
+fn exhausted(attempts: usize, last: Error) -> Error {
+    Error::RetriesExhausted {
+        attempts,
+        last: Some(Box::new(last)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NodeConfig;
+    use crate::node::Node;
+    use futures::executor::block_on;
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    fn free_addr() -> SocketAddr {
+        TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .unwrap()
+    }
+
+    fn cluster() -> Vec<Arc<Node<u64>>> {
+        let members: Vec<_> = (1..=3).map(|id| (Id(id), free_addr())).collect();
+        members
+            .iter()
+            .map(|(id, addr)| Node::start(NodeConfig::new(*id, *addr, members.clone())).unwrap())
+            .collect()
+    }
+
+    fn retries() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: Some(6),
+            ..RetryPolicy::immediate()
+        }
+    }
+
+    #[test]
+    fn proposals_follow_redirects_to_the_leader() {
+        let nodes = cluster();
+        let members = nodes
+            .iter()
+            .rev()
+            .map(|node| (Some(node.id()), node.local_addr()));
+        let mut client = Client::<u64>::new(members).with_retry_policy(retries());
+        assert_eq!(block_on(client.decision()).unwrap(), None);
+        assert_eq!(block_on(client.propose(7)).unwrap(), 7);
+        assert_eq!(client.leader, 2);
+        assert_eq!(block_on(client.propose(8)).unwrap(), 7);
+        assert_eq!(block_on(client.decision()).unwrap(), Some(7));
+        for node in nodes {
+            block_on(node.shutdown()).unwrap();
+        }
+    }
+
+    #[test]
+    fn members_without_ids_are_tried_in_turn() {
+        let nodes = cluster();
+        let members = nodes.iter().rev().map(|node| (None, node.local_addr()));
+        let mut client = Client::<u64>::new(members).with_retry_policy(retries());
+        assert_eq!(block_on(client.propose(3)).unwrap(), 3);
+        for node in nodes {
+            block_on(node.shutdown()).unwrap();
+        }
+    }
+
+    #[test]
+    fn members_hold_only_the_ciphertext_of_sealed_proposals() {
+        let members: Vec<_> = (1..=3).map(|id| (Id(id), free_addr())).collect();
+        let nodes: Vec<_> = members
+            .iter()
+            .map(|(id, addr)| {
+                Node::<Sealed>::start(NodeConfig::new(*id, *addr, members.clone())).unwrap()
+            })
+            .collect();
+        let key = ValueKey::new(1, b"application secret");
+        let mut client = Client::<Sealed>::new(members.iter().map(|(id, addr)| (Some(*id), *addr)))
+            .with_retry_policy(retries());
+        let secret = "launch code".to_string();
+        assert_eq!(
+            block_on(client.propose_sealed(&key, &secret)).unwrap(),
+            secret
+        );
+
+        let decided = block_on(client.decision()).unwrap().unwrap();
+        assert_eq!(decided.key(), 1);
+        assert!(!decided
+            .ciphertext()
+            .windows(6)
+            .any(|word| word == b"launch"));
+        assert_eq!(
+            block_on(client.decision_opened::<String>(&key)).unwrap(),
+            Some(secret)
+        );
+        assert!(matches!(
+            block_on(client.decision_opened::<String>(&ValueKey::new(2, b"other"))),
+            Err(Error::Transport(_))
+        ));
+        for node in nodes {
+            block_on(node.shutdown()).unwrap();
+        }
+    }
+
+    #[test]
+    fn proposals_over_the_quota_fail_without_retrying() {
+        let members: Vec<_> = (1..=3).map(|id| (Id(id), free_addr())).collect();
+        let nodes: Vec<_> = members
+            .iter()
+            .map(|(id, addr)| {
+                let mut config = NodeConfig::new(*id, *addr, members.clone());
+                config.client_quotas = Some("proposals:1".parse().unwrap());
+                Node::<u64>::start(config).unwrap()
+            })
+            .collect();
+        let mut client = Client::<u64>::new(members.iter().map(|(id, addr)| (Some(*id), *addr)))
+            .with_retry_policy(retries());
+        assert_eq!(block_on(client.propose(3)).unwrap(), 3);
+        match block_on(client.propose(4)) {
+            Err(Error::Throttled { retry_after }) => {
+                assert!(retry_after > Duration::ZERO && retry_after <= Duration::from_secs(1))
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        for node in nodes {
+            block_on(node.shutdown()).unwrap();
+        }
+    }
+
+    #[test]
+    fn gives_up_once_no_member_answers() {
+        let mut client =
+            Client::<u64>::new([(Some(Id(1)), free_addr())]).with_retry_policy(retries());
+        assert!(matches!(
+            block_on(client.propose(1)),
+            Err(Error::RetriesExhausted { attempts: 6, .. })
+        ));
+        assert!(block_on(client.decision()).is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use crate::codec::invalid_data;
+use std::io;
+
+const MIN_MATCH: usize = 4;
+const MAX_MATCH: usize = 0x7f + MIN_MATCH;
+const MAX_LITERALS: usize = 0x80;
+const MAX_DISTANCE: usize = u16::MAX as usize;
+const HASH_BITS: u32 = 12;
+
+/// How frames are packed for the wire. Receivers unpack whatever arrives
+/// compressed, so members need not agree on a codec.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Codec {
+    /// Frames go out as encoded.
+    Plain,
+    /// Frames longer than `above` bytes go out compressed when that makes
+    /// them smaller, which pays off on links slower than the compressor.
+    Lz { above: usize },
+}
+
+impl Default for Codec {
+    /// `benches/codec.rs` finds that on a gigabit link compressing a frame
+    /// takes longer than sending the bytes it saves, at every size.
+    fn default() -> Self {
+        Codec::Plain
+    }
+}
+
+impl Codec {
+    /// The compressed form of `frame`, if this codec sends it compressed.
+    pub fn pack(&self, frame: &[u8]) -> Option<Vec<u8>> {
+        let Codec::Lz { above } = *self else {
+            return None;
+        };
+        if frame.len() <= above {
+            return None;
+        }
+        let packed = compress(frame);
+        (packed.len() < frame.len()).then_some(packed)
+    }
+
+    pub fn unpack(packed: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+        decompress(packed, limit)
+    }
+}
+
+/// LZ77 over the frame. The output is a sequence of tokens: a control byte
+/// below `0x80` starts a run of `control + 1` literal bytes, any other is a
+/// match of `(control & 0x7f) + MIN_MATCH` bytes copied from a big-endian
+/// `u16` distance back in the output.
+pub(super) fn compress(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len() / 2);
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+    let mut literals = 0;
+    let mut at = 0;
+    while at + MIN_MATCH <= input.len() {
+        let slot = &mut table[hash(&input[at..at + MIN_MATCH])];
+        let candidate = *slot;
+        *slot = at;
+        let length = if candidate != usize::MAX && at - candidate <= MAX_DISTANCE {
+            common_prefix(&input[candidate..], &input[at..]).min(MAX_MATCH)
+        } else {
+            0
+        };
+        if length < MIN_MATCH {
+            at += 1;
+            continue;
+        }
+        flush_literals(&mut output, &input[literals..at]);
+        output.push(0x80 | (length - MIN_MATCH) as u8);
+        output.extend_from_slice(&((at - candidate) as u16).to_be_bytes());
+        at += length;
+        literals = at;
+    }
+    flush_literals(&mut output, &input[literals..]);
+    output
+}
+
+/// Refuses to produce more than `limit` bytes, so a small frame cannot
+/// expand into an arbitrarily large allocation.
+pub(super) fn decompress(mut input: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(input.len().saturating_mul(2).min(limit));
+    while let Some((&control, rest)) = input.split_first() {
+        input = rest;
+        if control < 0x80 {
+            let count = usize::from(control) + 1;
+            if count > input.len() {
+                return Err(invalid_data("truncated literal run"));
+            }
+            if output.len() + count > limit {
+                return Err(invalid_data("decompressed frame too large"));
+            }
+            output.extend_from_slice(&input[..count]);
+            input = &input[count..];
+        } else {
+            let length = usize::from(control & 0x7f) + MIN_MATCH;
+            let [high, low, rest @ ..] = input else {
+                return Err(invalid_data("truncated match"));
+            };
+            input = rest;
+            let distance = usize::from(u16::from_be_bytes([*high, *low]));
+            if distance == 0 || distance > output.len() {
+                return Err(invalid_data("match before the start of the frame"));
+            }
+            if output.len() + length > limit {
+                return Err(invalid_data("decompressed frame too large"));
+            }
+            // Copy byte by byte: a match may overlap the bytes it produces.
+            let start = output.len() - distance;
+            for i in start..start + length {
+                output.push(output[i]);
+            }
+        }
+    }
+    Ok(output)
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    (word.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+}
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+fn flush_literals(output: &mut Vec<u8>, literals: &[u8]) {
+    for chunk in literals.chunks(MAX_LITERALS) {
+        output.push((chunk.len() - 1) as u8);
+        output.extend_from_slice(chunk);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::XorShift;
+
+    fn round_trip(input: &[u8]) -> Vec<u8> {
+        let compressed = compress(input);
+        assert_eq!(decompress(&compressed, input.len()).unwrap(), input);
+        compressed
+    }
+
+    #[test]
+    fn round_trips() {
+        round_trip(b"");
+        round_trip(b"abc");
+        round_trip(b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa");
+        let mut rng = XorShift::new(7);
+        let noise: Vec<u8> = (0..10_000).map(|_| rng.next_u64() as u8).collect();
+        round_trip(&noise);
+    }
+
+    #[test]
+    fn shrinks_repetitive_payloads() {
+        let payload = b"key=value;".repeat(1_000);
+        assert!(round_trip(&payload).len() < payload.len() / 10);
+    }
+
+    #[test]
+    fn packs_only_what_the_codec_allows() {
+        let payload = b"key=value;".repeat(200);
+        assert_eq!(Codec::Plain.pack(&payload), None);
+        assert_eq!(
+            Codec::Lz {
+                above: payload.len()
+            }
+            .pack(&payload),
+            None
+        );
+        let packed = Codec::Lz { above: 1 << 10 }.pack(&payload).unwrap();
+        assert_eq!(Codec::unpack(&packed, payload.len()).unwrap(), payload);
+
+        let mut rng = XorShift::new(7);
+        let noise: Vec<u8> = (0..10_000).map(|_| rng.next_u64() as u8).collect();
+        assert_eq!(Codec::Lz { above: 0 }.pack(&noise), None);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let payload = b"key=value;".repeat(100);
+        let compressed = compress(&payload);
+        assert!(decompress(&compressed, payload.len() - 1).is_err());
+        assert!(decompress(&compressed[..compressed.len() - 1], usize::MAX).is_err());
+        assert!(decompress(&[0x80, 0, 1], usize::MAX).is_err());
+        assert!(decompress(&[0x7f, 1], usize::MAX).is_err());
+    }
+}
@@ -0,0 +1,16 @@
+pub mod broadcast;
+#[cfg(feature = "threads")]
+mod buffers;
+#[cfg(feature = "threads")]
+pub mod client;
+#[cfg(feature = "threads")]
+mod compress;
+#[cfg(feature = "threads")]
+mod pool;
+#[cfg(feature = "threads")]
+pub mod tcp;
+
+#[cfg(feature = "threads")]
+pub use compress::Codec;
+
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 << 20;
@@ -0,0 +1,328 @@
+use super::tcp::{read_frame, write_frames};
+use super::DEFAULT_MAX_MESSAGE_SIZE;
+use crate::retry::RetryPolicy;
+use crate::rng::XorShift;
+use std::io;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub(super) type Reply = Box<dyn FnOnce(io::Result<Vec<u8>>) + Send>;
+
+// Jobs queued behind the one being sent go out with it, up to this many.
+const MAX_BATCH: usize = 64;
+
+#[derive(Clone, Debug)]
+pub(super) struct PoolOptions {
+    pub(super) capacity: usize,
+    pub(super) health_check: Duration,
+    pub(super) reconnect: RetryPolicy,
+    pub(super) max_message_size: usize,
+    pub(super) connect_timeout: Duration,
+    pub(super) io_timeout: Duration,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            health_check: Duration::from_secs(1),
+            reconnect: RetryPolicy::default(),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            connect_timeout: Duration::from_secs(1),
+            io_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// An encoded request. Every member a broadcast goes to shares `body`; only
+/// what sealing adds around it is theirs alone.
+#[derive(Clone, Default)]
+pub(super) struct Frame {
+    pub(super) head: Vec<u8>,
+    pub(super) body: Arc<[u8]>,
+    pub(super) tail: Vec<u8>,
+}
+
+impl Frame {
+    pub(super) fn parts(&self) -> [&[u8]; 3] {
+        [&self.head, &self.body, &self.tail]
+    }
+}
+
+impl From<Vec<u8>> for Frame {
+    fn from(body: Vec<u8>) -> Self {
+        Self {
+            body: body.into(),
+            ..Self::default()
+        }
+    }
+}
+
+struct Job {
+    frame: Frame,
+    reply: Reply,
+}
+
+pub(super) struct Connection {
+    jobs: SyncSender<Job>,
+}
+
+impl Connection {
+    /// Health checks send whatever `ping` makes at the time, so a sealed
+    /// ping is never sent twice.
+    pub(super) fn spawn(
+        addr: SocketAddr,
+        ping: impl Fn() -> Frame + Send + 'static,
+        options: &PoolOptions,
+    ) -> Self {
+        let (jobs, queue) = mpsc::sync_channel(options.capacity);
+        let worker = Worker {
+            addr,
+            ping: Box::new(ping),
+            health_check: options.health_check,
+            reconnect: options.reconnect.clone(),
+            max_message_size: options.max_message_size,
+            connect_timeout: options.connect_timeout,
+            io_timeout: options.io_timeout,
+            stream: None,
+            failures: 0,
+            retry_at: Instant::now(),
+            rng: XorShift::new(u64::from(addr.port())),
+        };
+        thread::spawn(move || worker.run(queue));
+        Self { jobs }
+    }
+
+    /// A connection that hands each frame to `serve` on a thread of its own
+    /// instead of writing it to a socket.
+    pub(super) fn local(
+        mut serve: impl FnMut(&Frame) -> io::Result<Vec<u8>> + Send + 'static,
+        options: &PoolOptions,
+    ) -> Self {
+        let (jobs, queue) = mpsc::sync_channel::<Job>(options.capacity);
+        thread::spawn(move || {
+            for job in queue {
+                (job.reply)(serve(&job.frame));
+            }
+        });
+        Self { jobs }
+    }
+
+    pub(super) fn send(&self, frame: Frame, reply: Reply) {
+        match self.jobs.try_send(Job { frame, reply }) {
+            Ok(()) => {}
+            Err(TrySendError::Full(job)) => (job.reply)(Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "send queue is full",
+            ))),
+            Err(TrySendError::Disconnected(job)) => {
+                (job.reply)(Err(io::ErrorKind::NotConnected.into()))
+            }
+        }
+    }
+}
+
+struct Worker {
+    addr: SocketAddr,
+    ping: Box<dyn Fn() -> Frame + Send>,
+    health_check: Duration,
+    reconnect: RetryPolicy,
+    max_message_size: usize,
+    connect_timeout: Duration,
+    io_timeout: Duration,
+    stream: Option<TcpStream>,
+    failures: usize,
+    retry_at: Instant,
+    rng: XorShift,
+}
+
+impl Worker {
+    fn run(mut self, queue: Receiver<Job>) {
+        loop {
+            match queue.recv_timeout(self.health_check) {
+                Ok(job) => {
+                    let mut jobs = vec![job];
+                    jobs.extend(queue.try_iter().take(MAX_BATCH - 1));
+                    let frames: Vec<_> = jobs.iter().map(|job| &job.frame).collect();
+                    let responses = self.call(&frames);
+                    let timed_out = responses
+                        .iter()
+                        .any(|response| response.as_ref().is_err_and(is_timeout));
+                    for (job, response) in jobs.into_iter().zip(responses) {
+                        (job.reply)(response);
+                    }
+                    if timed_out {
+                        // Everything queued behind a stalled peer would wait
+                        // out the same timeout, so fail it now.
+                        for job in queue.try_iter() {
+                            (job.reply)(Err(io::ErrorKind::TimedOut.into()));
+                        }
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    let ping = (self.ping)();
+                    let _ = self.call(&[&ping]);
+                }
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    }
+
+    /// Sends `frames` in one write and reads their responses in order. A
+    /// failure fails every frame still waiting for its response.
+    fn call(&mut self, frames: &[&Frame]) -> Vec<io::Result<Vec<u8>>> {
+        let reused = self.stream.is_some();
+        let mut responses = Vec::with_capacity(frames.len());
+        let mut sent = self.exchange(frames, &mut responses);
+        if let Err(error) = &sent {
+            if reused && responses.is_empty() && !is_timeout(error) {
+                sent = self.exchange(frames, &mut responses);
+            }
+        }
+        if let Err(error) = sent {
+            let (kind, message) = (error.kind(), error.to_string());
+            responses.push(Err(error));
+            while responses.len() < frames.len() {
+                responses.push(Err(io::Error::new(kind, message.clone())));
+            }
+        }
+        responses
+    }
+
+    fn exchange(
+        &mut self,
+        frames: &[&Frame],
+        responses: &mut Vec<io::Result<Vec<u8>>>,
+    ) -> io::Result<()> {
+        let mut stream = match self.stream.take() {
+            Some(stream) => stream,
+            None => self.connect()?,
+        };
+        let parts: Vec<_> = frames.iter().map(|frame| frame.parts()).collect();
+        let parts: Vec<&[&[u8]]> = parts.iter().map(|parts| &parts[..]).collect();
+        write_frames(&mut stream, &parts)?;
+        while responses.len() < frames.len() {
+            responses.push(Ok(read_frame(&mut stream, self.max_message_size)?));
+        }
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    fn connect(&mut self) -> io::Result<TcpStream> {
+        if Instant::now() < self.retry_at {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "waiting to reconnect",
+            ));
+        }
+        let connected =
+            TcpStream::connect_timeout(&self.addr, self.connect_timeout).and_then(|stream| {
+                stream.set_nodelay(true)?;
+                stream.set_read_timeout(Some(self.io_timeout))?;
+                stream.set_write_timeout(Some(self.io_timeout))?;
+                Ok(stream)
+            });
+        match &connected {
+            Ok(_) => self.failures = 0,
+            Err(_) => {
+                self.failures += 1;
+                self.retry_at =
+                    Instant::now() + self.reconnect.backoff(self.failures, &mut self.rng);
+            }
+        }
+        connected
+    }
+}
+
+fn is_timeout(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn fails_queued_jobs_when_a_peer_stalls() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let options = PoolOptions {
+            io_timeout: Duration::from_millis(300),
+            ..PoolOptions::default()
+        };
+        let connection =
+            Connection::spawn(listener.local_addr().unwrap(), Frame::default, &options);
+        let (replies, responses) = mpsc::channel();
+        let started = Instant::now();
+        for _ in 0..3 {
+            let replies = replies.clone();
+            connection.send(
+                b"ping".to_vec().into(),
+                Box::new(move |response| replies.send(response).unwrap()),
+            );
+        }
+        for _ in 0..3 {
+            let error = responses.recv().unwrap().unwrap_err();
+            assert!(is_timeout(&error), "{error}");
+        }
+        assert!(started.elapsed() < Duration::from_millis(600));
+        drop(listener);
+    }
+
+    #[test]
+    fn answers_queued_jobs_in_order() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            while let Ok(frame) = read_frame(&mut stream, usize::MAX) {
+                write_frames(&mut stream, &[&[&frame]]).unwrap();
+            }
+        });
+        let connection = Connection::spawn(addr, Frame::default, &PoolOptions::default());
+        let (replies, responses) = mpsc::channel();
+        for i in 0..10u8 {
+            let replies = replies.clone();
+            connection.send(
+                vec![i; usize::from(i)].into(),
+                Box::new(move |response| replies.send((i, response)).unwrap()),
+            );
+        }
+        for _ in 0..10 {
+            let (i, response) = responses.recv().unwrap();
+            assert_eq!(response.unwrap(), vec![i; usize::from(i)]);
+        }
+    }
+
+    #[test]
+    fn health_checks_send_a_fresh_ping_each_time() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (pings, received) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            while let Ok(frame) = read_frame(&mut stream, usize::MAX) {
+                pings.send(frame.clone()).unwrap();
+                write_frames(&mut stream, &[&[&frame]]).unwrap();
+            }
+        });
+        let made = std::sync::atomic::AtomicU8::new(0);
+        let options = PoolOptions {
+            health_check: Duration::from_millis(10),
+            ..PoolOptions::default()
+        };
+        let _connection = Connection::spawn(
+            addr,
+            move || vec![made.fetch_add(1, std::sync::atomic::Ordering::Relaxed)].into(),
+            &options,
+        );
+        assert_eq!(received.recv().unwrap(), [0]);
+        assert_eq!(received.recv().unwrap(), [1]);
+    }
+}
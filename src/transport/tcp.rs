@@ -0,0 +1,3603 @@
+use super::broadcast::Broadcast;
+use super::buffers::BUFFERS;
+use super::compress::{decompress, Codec};
+use super::pool::{Connection, Frame, PoolOptions};
+use super::DEFAULT_MAX_MESSAGE_SIZE;
+use crate::acceptor::{Acceptor, Promised};
+use crate::alpha::{
+    Error, Id, Quorum, ReadPeers, ReadResponse, Round, Value, WritePeers, WriteResponse,
+};
+#[cfg(feature = "auth")]
+use crate::auth::Keyring;
+use crate::bootstrap::{Bootstrap, BootstrapFile};
+#[cfg(feature = "auth")]
+use crate::certificate;
+use crate::certificate::DecisionCertificate;
+use crate::codec::{from_bytes, invalid_data, to_bytes, Decode, Encode};
+use crate::failure_detector::{HeartbeatClient, OmegaDetector};
+use crate::fence::{Fence, FenceError, FenceLedger};
+use crate::instance::{InstanceAcceptors, InstanceId, InstancePeers};
+use crate::learner::{DecisionBroadcast, DecisionPeers, Learner};
+use crate::log::LogIndex;
+use crate::membership::{Applied, Member, Membership};
+use crate::metrics::ResponseTimes;
+use crate::proposer::FailureDetector;
+use crate::quorum::SharedQuorum;
+use crate::quota::{RateLimiter, Tenant};
+use crate::retry::RetryPolicy;
+#[cfg(feature = "auth")]
+use crate::roles::Permissions;
+use crate::storage::Storage;
+use crate::token::{Access, JoinTokens, TokenError, TokenVerifier};
+use futures::channel::oneshot;
+use futures::future::Either;
+use futures::{stream, FutureExt, Stream, StreamExt};
+use std::collections::HashSet;
+#[cfg(not(feature = "auth"))]
+use std::convert::Infallible;
+use std::fmt;
+use std::future::Future;
+use std::io::{self, IoSlice, Read, Write};
+use std::marker::PhantomData;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const IDLE_POLL: Duration = Duration::from_millis(100);
+const DEFAULT_FRAME_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_MAX_CONNECTIONS: usize = 256;
+
+pub const PROTOCOL_VERSION: u32 = 1;
+const READ_CHUNK: usize = 64 << 10;
+/// How many membership log entries one page of a state transfer carries.
+const TRANSFER_PAGE: usize = 64;
+const SUPPORTED_VERSIONS: RangeInclusive<u32> = 1..=PROTOCOL_VERSION;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Features(u32);
+
+impl Features {
+    pub const BATCHING: Self = Self(1);
+    pub const COMPRESSION: Self = Self(1 << 1);
+    pub const SNAPSHOTS: Self = Self(1 << 2);
+    pub const INSTANCE_GC: Self = Self(1 << 3);
+
+    pub fn empty() -> Self {
+        Self(0)
+    }
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Handshake {
+    pub version: u32,
+    pub features: Features,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeStatus {
+    pub id: Id,
+    pub leader: Option<Id>,
+    pub members: Vec<Id>,
+    pub suspected: Vec<Id>,
+    pub last_round_entered: Round,
+    pub accepted_round: Option<Round>,
+    pub decided: bool,
+    pub commit_index: Option<LogIndex>,
+    pub epoch: u64,
+}
+
+/// How ready a member is to serve clients, from least to most.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Health {
+    /// Answering, but it cannot reach a quorum.
+    Alive,
+    /// Still learning what was decided while it was down, so it would give
+    /// stale answers.
+    Recovering,
+    Ready,
+    /// Ready, and the member clients should propose through.
+    Leader,
+}
+
+impl fmt::Display for Health {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Health::Alive => "alive",
+            Health::Recovering => "recovering",
+            Health::Ready => "ready",
+            Health::Leader => "leader",
+        })
+    }
+}
+
+pub trait Admin: Send + Sync {
+    fn commit_index(&self) -> Option<LogIndex>;
+    fn snapshot(&self) -> io::Result<()>;
+    /// Copies the node's state into a new data directory at `to`, on the
+    /// node's own host.
+    fn backup(&self, to: &Path) -> io::Result<()>;
+    /// Replaces what the node logs; see [`log_filter`](crate::log_filter).
+    fn set_log_filter(&self, filter: &str) -> io::Result<()>;
+    /// Rereads the keys and client tokens the node authenticates with.
+    fn reload_credentials(&self) -> io::Result<()>;
+}
+
+/// Runs the proposals clients send a [`Server`] while its node leads.
+pub trait Proposals<V>: Send + Sync {
+    fn propose(&self, value: V) -> Result<V, Error>;
+}
+
+/// Changes the membership as operators ask a [`Server`] while its node
+/// leads, and applies what the membership log decides.
+pub trait Reconfigure: Send + Sync {
+    fn add_member(&self, id: Id, addr: SocketAddr) -> Result<Membership, Error>;
+    fn remove_member(&self, id: Id) -> Result<Membership, Error>;
+    /// Where a member about to be added listens, told by the leader before
+    /// it proposes adding it.
+    fn introduce(&self, id: Id, addr: SocketAddr);
+    /// Called with each decision the membership log learns.
+    fn learned(&self, instance: InstanceId, membership: Membership) -> Result<(), Error>;
+    /// Every member and its role, as this node last heard from them.
+    fn members(&self) -> Result<Vec<Member>, Error>;
+    /// The membership this node last applied from the membership log.
+    fn applied(&self) -> Result<Applied, Error>;
+}
+
+type ServedMembership = (Arc<InstanceAcceptors<Membership>>, Arc<dyn Reconfigure>);
+
+/// How a member answers a client's proposal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Proposed<V> {
+    Decided(V),
+    /// Someone else leads, named when the member knows who.
+    NotLeader(Option<Id>),
+    /// The client proposed more than its quota; the proposal fits again
+    /// after this long.
+    Throttled(Duration),
+}
+
+/// One page of the state a member hands a node that joins, so it can vote
+/// safely: the membership the member applied, if the joiner is behind it,
+/// the decisions of its membership log after that, the highest round its
+/// acceptor entered, and what it learned was decided.
+#[derive(Clone, Debug)]
+pub(crate) struct StateTransfer<V> {
+    pub(crate) applied: Option<Applied>,
+    pub(crate) entries: Vec<(LogIndex, Membership)>,
+    /// The first slot of the membership log the member knows no decision
+    /// for, so the joiner can tell how far along it is.
+    pub(crate) end: LogIndex,
+    pub(crate) last_round_entered: Round,
+    pub(crate) certificate: Option<DecisionCertificate<V>>,
+}
+
+pub struct TcpPeers<V> {
+    members: Arc<Mutex<Arc<Members>>>,
+    features: Features,
+    negotiated: Arc<Mutex<Features>>,
+    instance: Option<InstanceId>,
+    options: PoolOptions,
+    concurrency: usize,
+    fastest_quorum: bool,
+    codec: Codec,
+    loopback: Option<Loopback>,
+    response_times: Option<Arc<ResponseTimes<SocketAddr>>>,
+    membership_log: bool,
+    epoch: Arc<AtomicU64>,
+    token: Option<Arc<str>>,
+    #[cfg(feature = "auth")]
+    auth: Option<Arc<Keyring>>,
+    _value: PhantomData<fn() -> V>,
+}
+
+/// Who a [`TcpPeers`] and all of its clones currently talk to. `ids` is only
+/// known once the peers are authenticated or reconfigured.
+struct Members {
+    addrs: Vec<SocketAddr>,
+    ids: Vec<Id>,
+    connections: OnceLock<Vec<Arc<Connection>>>,
+}
+
+impl Members {
+    fn new(addrs: Vec<SocketAddr>, ids: Vec<Id>) -> Arc<Self> {
+        Arc::new(Self {
+            addrs,
+            ids,
+            connections: OnceLock::new(),
+        })
+    }
+}
+
+impl<V> Clone for TcpPeers<V> {
+    fn clone(&self) -> Self {
+        Self {
+            members: self.members.clone(),
+            features: self.features,
+            negotiated: self.negotiated.clone(),
+            instance: self.instance,
+            options: self.options.clone(),
+            concurrency: self.concurrency,
+            fastest_quorum: self.fastest_quorum,
+            codec: self.codec,
+            loopback: self.loopback.clone(),
+            response_times: self.response_times.clone(),
+            membership_log: self.membership_log,
+            epoch: self.epoch.clone(),
+            token: self.token.clone(),
+            #[cfg(feature = "auth")]
+            auth: self.auth.clone(),
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<V> TcpPeers<V>
+where
+    V: Encode + Decode + Send + Sync + 'static,
+{
+    pub fn new(addrs: Vec<SocketAddr>) -> Self {
+        Self {
+            members: Arc::new(Mutex::new(Members::new(addrs, Vec::new()))),
+            features: Features::empty(),
+            negotiated: Arc::new(Mutex::new(Features::empty())),
+            instance: None,
+            options: PoolOptions::default(),
+            concurrency: usize::MAX,
+            fastest_quorum: false,
+            codec: Codec::default(),
+            loopback: None,
+            response_times: None,
+            membership_log: false,
+            epoch: Arc::new(AtomicU64::new(0)),
+            token: None,
+            #[cfg(feature = "auth")]
+            auth: None,
+            _value: PhantomData,
+        }
+    }
+
+    pub fn with_queue_capacity(mut self, capacity: usize) -> Self {
+        self.options.capacity = capacity;
+        self
+    }
+
+    /// Bounds how many members a request is in flight to at once. Decisions,
+    /// heartbeats and other messages nobody waits on go to everyone.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Sends to the majority that answered fastest so far first, and to the
+    /// rest only as their answers come back. Needs
+    /// [`with_response_times`](Self::with_response_times) to tell who that
+    /// is.
+    pub fn with_fastest_quorum(mut self) -> Self {
+        self.fastest_quorum = true;
+        self
+    }
+
+    pub fn with_health_check(mut self, interval: Duration) -> Self {
+        self.options.health_check = interval;
+        self
+    }
+
+    pub fn with_response_times(mut self, response_times: Arc<ResponseTimes<SocketAddr>>) -> Self {
+        self.response_times = Some(response_times);
+        self
+    }
+
+    pub fn with_reconnect_backoff(mut self, backoff: RetryPolicy) -> Self {
+        self.options.reconnect = backoff;
+        self
+    }
+
+    /// Presents `token` with every proposal and read, for servers that
+    /// [verify tokens](Server::with_tokens).
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(Arc::from(token));
+        self
+    }
+
+    pub fn with_max_message_size(mut self, limit: usize) -> Self {
+        self.options.max_message_size = limit;
+        self
+    }
+
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Reaches the member at the loopback's address in process.
+    pub fn with_loopback(mut self, loopback: Loopback) -> Self {
+        self.loopback = Some(loopback);
+        self
+    }
+
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.options.connect_timeout = timeout;
+        self
+    }
+
+    pub fn with_io_timeout(mut self, timeout: Duration) -> Self {
+        self.options.io_timeout = timeout;
+        self
+    }
+
+    pub fn with_features(mut self, features: Features) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// Asks every member which features it supports. Requests that need a
+    /// feature are only sent once every member has agreed to it; a member
+    /// that does not answer agrees to nothing.
+    pub async fn handshake(&self) -> Result<Handshake, Error> {
+        let local = Handshake {
+            version: PROTOCOL_VERSION,
+            features: self.features,
+        };
+        let mut responses = self.broadcast(Request::Hello(local), |response| match response {
+            Response::Hello(handshake) => Ok(handshake),
+            _ => Err(invalid_data("unexpected response")),
+        });
+        let mut negotiated = local;
+        let mut answered = 0;
+        while let Some(response) = responses.next().await {
+            match response {
+                Ok(remote) => {
+                    answered += 1;
+                    negotiated.version = negotiated.version.min(remote.version);
+                    negotiated.features = negotiated.features.intersection(remote.features);
+                }
+                Err(error @ Error::IncompatibleVersion { .. }) => return Err(error),
+                Err(_) => {}
+            }
+        }
+        if answered < self.members().addrs.len() {
+            negotiated.features = Features::empty();
+        }
+        *lock(&self.negotiated) = negotiated.features;
+        Ok(negotiated)
+    }
+
+    pub fn negotiated(&self) -> Features {
+        *lock(&self.negotiated)
+    }
+
+    /// How ready each member is; cheaper than [`status`](Self::status), for
+    /// load balancers to poll.
+    pub fn health(&self) -> impl Stream<Item = Result<Health, Error>> {
+        self.broadcast(Request::Health, |response| match response {
+            Response::Health(health) => Ok(health),
+            response => Err(failure(response)),
+        })
+    }
+
+    /// Asks each member for the certificate of what it has learned was
+    /// decided, with the id of the member answering.
+    pub(crate) fn certificate(
+        &self,
+    ) -> impl Stream<Item = Result<(Id, Option<DecisionCertificate<V>>), Error>> {
+        self.broadcast(Request::Certificate, |response| match response {
+            Response::Certificate(id, certificate) => Ok((id, certificate)),
+            response => Err(failure(response)),
+        })
+    }
+
+    pub fn status(&self) -> impl Stream<Item = Result<NodeStatus, Error>> {
+        self.broadcast(Request::Status, |response| match response {
+            Response::Status(status) => Ok(status),
+            response => Err(failure(response)),
+        })
+    }
+
+    /// Asks each member to decide `value`; only the leader does, the others
+    /// answer who that is.
+    pub fn propose(&self, value: V) -> impl Stream<Item = Result<Proposed<V>, Error>> {
+        self.broadcast(Request::Propose(value), |response| match response {
+            Response::Decided(Some(decided)) => Ok(Proposed::Decided(decided)),
+            Response::NotLeader(leader) => Ok(Proposed::NotLeader(leader)),
+            Response::Throttled(millis) => Ok(Proposed::Throttled(Duration::from_millis(millis))),
+            response => Err(failure(response)),
+        })
+    }
+
+    /// What each member has learned was decided, if anything.
+    pub fn decision(&self) -> impl Stream<Item = Result<Option<V>, Error>> {
+        self.broadcast(Request::Decided, |response| match response {
+            Response::Decided(decided) => Ok(decided),
+            response => Err(failure(response)),
+        })
+    }
+
+    /// Offers `bootstrap` to each member, or with `None` only asks, and
+    /// yields which bootstrap each member holds afterwards.
+    pub(crate) fn bootstrap(
+        &self,
+        bootstrap: Option<Bootstrap>,
+    ) -> impl Stream<Item = Result<(Id, Option<Bootstrap>), Error>> {
+        self.broadcast(Request::Bootstrap(bootstrap), |response| match response {
+            Response::Bootstrapped(id, held) => Ok((id, held)),
+            response => Err(failure(response)),
+        })
+    }
+
+    /// Shares `epoch` with the server of the node these peers belong to.
+    /// Once it is past zero every consensus request carries it, and members
+    /// of any other epoch refuse them.
+    pub(crate) fn with_epoch(mut self, epoch: Arc<AtomicU64>) -> Self {
+        self.epoch = epoch;
+        self
+    }
+
+    /// The same members, over the same connections, deciding the
+    /// membership log instead of values.
+    pub(crate) fn membership_log(&self) -> TcpPeers<Membership> {
+        TcpPeers {
+            members: self.members.clone(),
+            features: self.features,
+            negotiated: self.negotiated.clone(),
+            instance: None,
+            options: self.options.clone(),
+            concurrency: self.concurrency,
+            fastest_quorum: self.fastest_quorum,
+            codec: self.codec,
+            loopback: self.loopback.clone(),
+            response_times: self.response_times.clone(),
+            membership_log: true,
+            epoch: self.epoch.clone(),
+            token: self.token.clone(),
+            #[cfg(feature = "auth")]
+            auth: self.auth.clone(),
+            _value: PhantomData,
+        }
+    }
+
+    /// Asks each member to add `id`, listening at `addr`, as a voter; only
+    /// the leader does, the others answer who that is.
+    pub fn add_member(
+        &self,
+        id: Id,
+        addr: SocketAddr,
+    ) -> impl Stream<Item = Result<Proposed<Membership>, Error>> {
+        self.broadcast(Request::AddMember(id, addr), reconfigured)
+    }
+
+    /// Asks each member for a token node `id` can join with in the next
+    /// `ttl`; only the leader issues one, the others answer who that is.
+    pub fn issue_join_token(
+        &self,
+        id: Id,
+        ttl: Duration,
+    ) -> impl Stream<Item = Result<Proposed<String>, Error>> {
+        let request = Request::IssueJoinToken(id, ttl.as_millis() as u64);
+        self.broadcast(request, |response| match response {
+            Response::JoinToken(token) => Ok(Proposed::Decided(token)),
+            Response::NotLeader(leader) => Ok(Proposed::NotLeader(leader)),
+            response => Err(failure(response)),
+        })
+    }
+
+    /// Asks each member to add node `id`, listening at `addr`, as a voter
+    /// with the join token the leader issued for it.
+    pub fn join(
+        &self,
+        token: &str,
+        id: Id,
+        addr: SocketAddr,
+    ) -> impl Stream<Item = Result<Proposed<Membership>, Error>> {
+        self.broadcast(Request::Join(token.to_string(), id, addr), reconfigured)
+    }
+
+    pub fn remove_member(&self, id: Id) -> impl Stream<Item = Result<Proposed<Membership>, Error>> {
+        self.broadcast(Request::RemoveMember(id), reconfigured)
+    }
+
+    /// Asks each member for every member of the cluster it knows of.
+    pub fn topology(&self) -> impl Stream<Item = Result<Vec<Member>, Error>> {
+        self.broadcast(Request::Members, |response| match response {
+            Response::Members(members) => Ok(members),
+            response => Err(failure(response)),
+        })
+    }
+
+    pub(crate) fn introduce(
+        &self,
+        id: Id,
+        addr: SocketAddr,
+    ) -> impl Stream<Item = Result<(), Error>> {
+        self.broadcast(Request::Introduce(id, addr), acknowledged)
+    }
+
+    /// Asks each member for the page of its state from slot `from` of the
+    /// membership log on.
+    pub(crate) fn state_transfer(
+        &self,
+        from: LogIndex,
+    ) -> impl Stream<Item = Result<StateTransfer<V>, Error>> {
+        self.broadcast(Request::StateTransfer(from), |response| match response {
+            Response::StateTransfer(page) => Ok(page),
+            response => Err(failure(response)),
+        })
+    }
+
+    /// Tells each member node `id` now runs at `fence`. A member that heard
+    /// of as high a fence from it before answers with that one.
+    pub(crate) fn announce_fence(
+        &self,
+        id: Id,
+        fence: Fence,
+    ) -> impl Stream<Item = Result<Option<Fence>, Error>> {
+        self.broadcast(Request::Fence(id, fence), |response| match response {
+            Response::Ack => Ok(None),
+            Response::StaleStorage(seen) => Ok(Some(seen)),
+            response => Err(failure(response)),
+        })
+    }
+
+    pub fn transfer_leadership(&self, to: Id) -> impl Stream<Item = Result<(), Error>> {
+        self.broadcast(Request::Transfer(to), acknowledged)
+    }
+
+    pub fn snapshot(&self) -> impl Stream<Item = Result<(), Error>> {
+        self.broadcast(Request::Snapshot, acknowledged)
+    }
+
+    /// Asks each member to back itself up into `to`, a path on its own host.
+    pub fn backup(&self, to: &str) -> impl Stream<Item = Result<(), Error>> {
+        self.broadcast(Request::Backup(to.to_string()), acknowledged)
+    }
+
+    pub fn set_log_filter(&self, filter: &str) -> impl Stream<Item = Result<(), Error>> {
+        self.broadcast(Request::LogFilter(filter.to_string()), acknowledged)
+    }
+
+    pub fn reload_credentials(&self) -> impl Stream<Item = Result<(), Error>> {
+        self.broadcast(Request::ReloadCredentials, acknowledged)
+    }
+
+    #[cfg(feature = "auth")]
+    pub fn with_auth(mut self, keyring: Arc<Keyring>, peers: Vec<Id>) -> Self {
+        let addrs = self.members().addrs.clone();
+        self.members = Arc::new(Mutex::new(Members::new(addrs, peers)));
+        self.auth = Some(keyring);
+        self
+    }
+
+    /// Replaces the members every clone of these peers talks to, keeping the
+    /// connections to members that stay. Features are renegotiated, since the
+    /// new members have not agreed to any yet.
+    pub fn reconfigure(&self, members: impl IntoIterator<Item = (Id, SocketAddr)>) {
+        let (ids, addrs): (Vec<_>, Vec<_>) = members.into_iter().unzip();
+        let next = Members::new(addrs, ids);
+        let mut members = lock(&self.members);
+        if let Some(connections) = members.connections.get() {
+            let kept = (0..next.addrs.len())
+                .map(|index| {
+                    let same = |old: &usize| {
+                        members.addrs[*old] == next.addrs[index]
+                            && members.ids.get(*old) == next.ids.get(index)
+                    };
+                    match (0..members.addrs.len()).find(same) {
+                        Some(old) => connections[old].clone(),
+                        None => self.connect(&next, index),
+                    }
+                })
+                .collect();
+            let _ = next.connections.set(kept);
+        }
+        *members = next;
+        *lock(&self.negotiated) = Features::empty();
+    }
+
+    fn members(&self) -> Arc<Members> {
+        lock(&self.members).clone()
+    }
+
+    #[cfg(feature = "auth")]
+    fn seal(&self, members: &Members, index: usize) -> Option<(Arc<Keyring>, Id)> {
+        if self.local(members, index).is_some() {
+            return None;
+        }
+        let keyring = self.auth.as_ref()?;
+        Some((keyring.clone(), *members.ids.get(index)?))
+    }
+
+    #[cfg(not(feature = "auth"))]
+    fn seal(&self, _members: &Members, _index: usize) -> Option<Infallible> {
+        None
+    }
+
+    fn connections<'a>(&self, members: &'a Members) -> &'a [Arc<Connection>] {
+        members.connections.get_or_init(|| {
+            (0..members.addrs.len())
+                .map(|index| self.connect(members, index))
+                .collect()
+        })
+    }
+
+    fn connect(&self, members: &Members, index: usize) -> Arc<Connection> {
+        if let Some(loopback) = self.local(members, index) {
+            return loopback.connection.clone();
+        }
+        let ping: Arc<[u8]> = versioned(&Request::<V>::Hello(Handshake {
+            version: PROTOCOL_VERSION,
+            features: self.features,
+        }))
+        .into();
+        let seal = self.seal(members, index);
+        Arc::new(Connection::spawn(
+            members.addrs[index],
+            move || seal_request(&ping, &seal).unwrap_or_default(),
+            &self.options,
+        ))
+    }
+
+    fn local(&self, members: &Members, index: usize) -> Option<&Loopback> {
+        let loopback = self.loopback.as_ref()?;
+        (loopback.addr == members.addrs[index]).then_some(loopback)
+    }
+
+    /// Frames travel as the codec packs them once every member has
+    /// negotiated [`Features::COMPRESSION`]; until then they go out as they
+    /// are.
+    fn compress(&self, frame: Vec<u8>) -> Vec<u8> {
+        if !self.negotiated().contains(Features::COMPRESSION) {
+            return frame;
+        }
+        match self.codec.pack(&frame[4..]) {
+            Some(packed) => {
+                BUFFERS.give(frame);
+                versioned(&Request::<V>::Compressed(packed))
+            }
+            None => frame,
+        }
+    }
+
+    fn broadcast<T, F>(
+        &self,
+        request: Request<V>,
+        extract: F,
+    ) -> impl Stream<Item = Result<T, Error>> + Unpin
+    where
+        T: Send + 'static,
+        F: Fn(Response<V>) -> io::Result<T> + Copy + Send + 'static,
+    {
+        let concurrency = match self.fastest_quorum {
+            true => self.concurrency.min(self.majority()),
+            false => self.concurrency,
+        };
+        self.send(request, None, concurrency, extract)
+    }
+
+    /// Puts the fastest members first. Our own acceptor joins the first
+    /// `concurrency` of them, after the others, so its fsync overlaps with
+    /// their round trips rather than delaying them.
+    fn prioritize<T>(
+        &self,
+        targets: &mut Vec<T>,
+        addr: impl Fn(&T) -> SocketAddr,
+        concurrency: usize,
+    ) {
+        targets.sort_by_cached_key(|target| self.typical_response(addr(target)));
+        let local = targets.iter().position(|target| {
+            self.loopback
+                .as_ref()
+                .is_some_and(|l| l.addr == addr(target))
+        });
+        if let Some(local) = local.map(|local| targets.remove(local)) {
+            let at = concurrency.saturating_sub(1).min(targets.len());
+            targets.insert(at, local);
+        }
+    }
+
+    /// How long `addr` usually takes to answer; members we have not heard
+    /// from yet come first, so they get measured.
+    fn typical_response(&self, addr: SocketAddr) -> Duration {
+        self.response_times
+            .as_ref()
+            .and_then(|response_times| response_times.histogram(addr))
+            .and_then(|histogram| histogram.quantile(0.5))
+            .unwrap_or_default()
+    }
+
+    /// Sends to `only` when the member ids are known, and to everyone
+    /// otherwise, through a [`Broadcast`] bounded by `concurrency`.
+    fn send<T, F>(
+        &self,
+        request: Request<V>,
+        only: Option<&[Id]>,
+        concurrency: usize,
+        extract: F,
+    ) -> impl Stream<Item = Result<T, Error>> + Unpin
+    where
+        T: Send + 'static,
+        F: Fn(Response<V>) -> io::Result<T> + Copy + Send + 'static,
+    {
+        let request = match self.instance {
+            Some(instance) => Request::Instance(instance, Box::new(request)),
+            None => request,
+        };
+        let request = match self.membership_log {
+            true => Request::MembershipLog(to_bytes(&request)),
+            false => request,
+        };
+        let request = match &self.token {
+            Some(token) if request.access().is_some() => {
+                Request::Authorized(token.to_string(), Box::new(request))
+            }
+            _ => request,
+        };
+        let request = match self.epoch.load(Ordering::Acquire) {
+            epoch if epoch > 0 && request.fenced() => Request::Fenced(epoch, Box::new(request)),
+            _ => request,
+        };
+        let required = request.required();
+        if !self.negotiated().contains(required) {
+            return Either::Left(stream::iter([Err(not_negotiated(required).into())]));
+        }
+        let request = versioned(&request);
+        let limit = self.options.max_message_size;
+        if request.len() > limit {
+            return Either::Left(stream::iter([Err(Error::ValueTooLarge {
+                size: request.len(),
+                limit,
+            })]));
+        }
+        // Encoded once; every member's frame shares these bytes.
+        let frame = self.compress(request);
+        let request: Arc<[u8]> = Arc::from(&frame[..]);
+        BUFFERS.give(frame);
+        let members = self.members();
+        let connections = self.connections(&members).iter().zip(&members.addrs);
+        let mut targets: Vec<_> = connections
+            .enumerate()
+            .filter(|(index, _)| {
+                let skipped = members.ids.get(*index).zip(only);
+                skipped.is_none_or(|(id, only)| only.contains(id))
+            })
+            .map(|(index, (connection, addr))| {
+                (connection.clone(), *addr, self.seal(&members, index))
+            })
+            .collect();
+        self.prioritize(&mut targets, |(_, addr, _)| *addr, concurrency);
+        let response_times = self.response_times.clone();
+        let responses = Broadcast::new(targets)
+            .concurrency(concurrency)
+            .into_fan_out(move |(connection, addr, seal)| {
+                let (reply, response) = oneshot::channel();
+                match seal_request(&request, &seal) {
+                    Ok(frame) => {
+                        let (response_times, sent) = (response_times.clone(), Instant::now());
+                        connection.send(
+                            frame,
+                            Box::new(move |frame| {
+                                if let (Ok(_), Some(response_times)) = (&frame, response_times) {
+                                    response_times.record(addr, sent.elapsed());
+                                }
+                                let response = frame
+                                    .map_err(Error::from)
+                                    .and_then(|frame| {
+                                        let response = open_response::<V>(&frame, seal, limit);
+                                        BUFFERS.give(frame);
+                                        response
+                                    })
+                                    .and_then(|response| extract(response).map_err(Error::from));
+                                let _ = reply.send(response);
+                            }),
+                        );
+                    }
+                    Err(error) => {
+                        let _ = reply.send(Err(error.into()));
+                    }
+                }
+                response.map(|response| {
+                    response
+                        .unwrap_or_else(|_| Err(io::Error::from(io::ErrorKind::BrokenPipe).into()))
+                })
+            });
+        Either::Right(responses)
+    }
+
+    /// Messages nobody waits on skip the concurrency limit, since the rest of
+    /// the broadcast would only go out while someone polls it.
+    fn notify(&self, request: Request<V>, only: Option<&[Id]>) {
+        drop(self.send(request, only, usize::MAX, |_| Ok(())));
+    }
+}
+
+impl<V> ReadPeers<V> for TcpPeers<V>
+where
+    V: Encode + Decode + Send + Sync + 'static,
+{
+    fn read(&self, round: Round) -> impl Stream<Item = Result<ReadResponse<V>, Error>> {
+        self.broadcast(Request::Read(round), |response| match response {
+            Response::Read(response) => Ok(response),
+            _ => Err(invalid_data("unexpected response")),
+        })
+    }
+}
+
+impl<V> WritePeers<V> for TcpPeers<V>
+where
+    V: Encode + Decode + Send + Sync + 'static,
+{
+    fn write(&self, value: Value<V>) -> impl Stream<Item = Result<WriteResponse, Error>> {
+        self.broadcast(Request::Write(value), |response| match response {
+            Response::Write(response) => Ok(response),
+            _ => Err(invalid_data("unexpected response")),
+        })
+    }
+
+    fn write_to(
+        &self,
+        acceptors: &[Id],
+        value: Value<V>,
+    ) -> impl Stream<Item = Result<WriteResponse, Error>> {
+        let request = Request::Write(value);
+        self.send(
+            request,
+            Some(acceptors),
+            usize::MAX,
+            |response| match response {
+                Response::Write(response) => Ok(response),
+                _ => Err(invalid_data("unexpected response")),
+            },
+        )
+    }
+}
+
+impl<V> DecisionPeers<V> for TcpPeers<V>
+where
+    V: Encode + Decode + Send + Sync + 'static,
+{
+    fn decide(&self, decision: DecisionBroadcast<V>) -> impl Stream<Item = Result<(), Error>> {
+        let request = Request::Decision(decision);
+        self.send(request, None, usize::MAX, |response| match response {
+            Response::Ack => Ok(()),
+            _ => Err(invalid_data("unexpected response")),
+        })
+    }
+}
+
+impl<V> HeartbeatClient for TcpPeers<V>
+where
+    V: Encode + Decode + Send + Sync + 'static,
+{
+    fn broadcast_heartbeat(&self, from: Id) {
+        self.notify(Request::Heartbeat(from), None);
+    }
+
+    fn broadcast_leave(&self, from: Id) -> impl Future<Output = ()> + Send {
+        self.broadcast(Request::Leave(from), |_| Ok(()))
+            .count()
+            .map(drop)
+    }
+}
+
+impl<V> InstancePeers<V> for TcpPeers<V>
+where
+    V: Encode + Decode + Send + Sync + 'static,
+{
+    type Peers = TcpPeers<V>;
+
+    fn instance(&self, instance: InstanceId) -> Self::Peers {
+        Self {
+            instance: Some(instance),
+            ..self.clone()
+        }
+    }
+
+    fn acknowledge(&self, learner: Id, below: InstanceId) {
+        self.notify(Request::Applied(learner, below), None);
+    }
+}
+
+impl<V> Quorum for TcpPeers<V> {
+    fn majority(&self) -> usize {
+        lock(&self.members).addrs.len() / 2 + 1
+    }
+
+    fn max_failures(&self) -> usize {
+        let members = lock(&self.members).addrs.len();
+        members.saturating_sub(members / 2 + 1)
+    }
+}
+
+/// A node's own [`Server`], reached in process through
+/// [`TcpPeers::with_loopback`] instead of over a socket. Built by
+/// [`Server::loopback`].
+#[derive(Clone)]
+pub struct Loopback {
+    addr: SocketAddr,
+    connection: Arc<Connection>,
+}
+
+pub struct Server<V, S> {
+    id: Id,
+    acceptor: Arc<Mutex<Acceptor<V, S>>>,
+    promised: Arc<Promised>,
+    detector: Option<Arc<OmegaDetector>>,
+    learner: Option<Learner<V>>,
+    instances: Option<Arc<InstanceAcceptors<V>>>,
+    admin: Option<Arc<dyn Admin>>,
+    proposals: Option<Arc<dyn Proposals<V>>>,
+    membership: Option<ServedMembership>,
+    bootstrap: Option<Arc<Mutex<BootstrapFile>>>,
+    epoch: Arc<AtomicU64>,
+    ready: Option<Arc<AtomicBool>>,
+    quorum: Option<SharedQuorum>,
+    max_message_size: usize,
+    codec: Codec,
+    frame_timeout: Duration,
+    max_connections: usize,
+    features: Features,
+    stopped: Arc<AtomicBool>,
+    tokens: Option<Arc<dyn TokenVerifier>>,
+    limiter: Option<Arc<RateLimiter>>,
+    join_tokens: Option<Arc<JoinTokens>>,
+    fences: Option<Arc<FenceLedger>>,
+    #[cfg(feature = "auth")]
+    keyring: Option<Arc<Keyring>>,
+}
+
+impl<V, S> Clone for Server<V, S> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            acceptor: self.acceptor.clone(),
+            promised: self.promised.clone(),
+            detector: self.detector.clone(),
+            learner: self.learner.clone(),
+            instances: self.instances.clone(),
+            admin: self.admin.clone(),
+            proposals: self.proposals.clone(),
+            membership: self.membership.clone(),
+            bootstrap: self.bootstrap.clone(),
+            epoch: self.epoch.clone(),
+            ready: self.ready.clone(),
+            quorum: self.quorum.clone(),
+            max_message_size: self.max_message_size,
+            codec: self.codec,
+            frame_timeout: self.frame_timeout,
+            max_connections: self.max_connections,
+            features: self.features,
+            stopped: self.stopped.clone(),
+            tokens: self.tokens.clone(),
+            limiter: self.limiter.clone(),
+            join_tokens: self.join_tokens.clone(),
+            fences: self.fences.clone(),
+            #[cfg(feature = "auth")]
+            keyring: self.keyring.clone(),
+        }
+    }
+}
+
+impl<V, S> Server<V, S>
+where
+    V: Clone + PartialEq + Encode + Decode + Send + Sync + 'static,
+    S: Storage<V> + Send + 'static,
+{
+    pub fn new(acceptor: Arc<Mutex<Acceptor<V, S>>>) -> Self {
+        let (id, promised) = {
+            let acceptor = lock(&acceptor);
+            (acceptor.id(), acceptor.promised())
+        };
+        Self {
+            id,
+            acceptor,
+            promised,
+            detector: None,
+            learner: None,
+            instances: None,
+            admin: None,
+            proposals: None,
+            membership: None,
+            bootstrap: None,
+            epoch: Arc::new(AtomicU64::new(0)),
+            ready: None,
+            quorum: None,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            codec: Codec::default(),
+            frame_timeout: DEFAULT_FRAME_TIMEOUT,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            features: Features::empty(),
+            stopped: Arc::new(AtomicBool::new(false)),
+            tokens: None,
+            limiter: None,
+            join_tokens: None,
+            fences: None,
+            #[cfg(feature = "auth")]
+            keyring: None,
+        }
+    }
+
+    pub fn with_detector(mut self, detector: Arc<OmegaDetector>) -> Self {
+        self.detector = Some(detector);
+        self
+    }
+
+    pub fn with_learner(mut self, learner: Learner<V>) -> Self {
+        self.learner = Some(learner);
+        self
+    }
+
+    pub fn with_instances(mut self, instances: Arc<InstanceAcceptors<V>>) -> Self {
+        self.instances = Some(instances);
+        self
+    }
+
+    pub fn with_admin(mut self, admin: Arc<dyn Admin>) -> Self {
+        self.admin = Some(admin);
+        self
+    }
+
+    /// Lets clients propose through this server. Members that do not lead
+    /// point them at the one that does.
+    pub fn with_proposals(mut self, proposals: Arc<dyn Proposals<V>>) -> Self {
+        self.proposals = Some(proposals);
+        self
+    }
+
+    /// Refuses proposals and reads from clients unless `tokens` accepts the
+    /// token they present.
+    pub fn with_tokens(mut self, tokens: Arc<dyn TokenVerifier>) -> Self {
+        self.tokens = Some(tokens);
+        self
+    }
+
+    /// Refuses proposals from clients over their quota in `limiter`.
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// Issues join tokens from `tokens` while this node leads, and adds the
+    /// nodes that join with one.
+    pub fn with_join_tokens(mut self, tokens: Arc<JoinTokens>) -> Self {
+        self.join_tokens = Some(tokens);
+        self
+    }
+
+    /// Serves the membership log from `log` and lets operators add and
+    /// remove members through `reconfigure`.
+    pub fn with_membership(
+        mut self,
+        log: Arc<InstanceAcceptors<Membership>>,
+        reconfigure: Arc<dyn Reconfigure>,
+    ) -> Self {
+        self.membership = Some((log, reconfigure));
+        self
+    }
+
+    /// Keeps the fences members announce in `fences`, refusing those no
+    /// higher than one announced before.
+    pub fn with_fences(mut self, fences: Arc<FenceLedger>) -> Self {
+        self.fences = Some(fences);
+        self
+    }
+
+    /// Reports the member as recovering, and refuses client requests, until
+    /// `ready` is set.
+    pub(crate) fn with_readiness(mut self, ready: Arc<AtomicBool>) -> Self {
+        self.ready = Some(ready);
+        self
+    }
+
+    /// Refuses consensus requests from members of any other epoch than the
+    /// one `epoch` holds.
+    pub(crate) fn with_epoch(mut self, epoch: Arc<AtomicU64>) -> Self {
+        self.epoch = epoch;
+        self
+    }
+
+    /// Where this member keeps the bootstrap it adopted, which peers
+    /// bootstrapping or joining the cluster ask about.
+    pub(crate) fn with_bootstrap(mut self, bootstrap: Arc<Mutex<BootstrapFile>>) -> Self {
+        self.bootstrap = Some(bootstrap);
+        self
+    }
+
+    /// The quorum decisions must be certified by before the learner or the
+    /// instances adopt them. Without one every decision is refused.
+    pub fn with_quorum(mut self, quorum: SharedQuorum) -> Self {
+        self.quorum = Some(quorum);
+        self
+    }
+
+    pub fn with_max_message_size(mut self, limit: usize) -> Self {
+        self.max_message_size = limit;
+        self
+    }
+
+    /// How responses to compressed requests are packed.
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// How long a peer may take to send a whole request frame, or to read a
+    /// whole response, before its connection is dropped.
+    pub fn with_frame_timeout(mut self, timeout: Duration) -> Self {
+        self.frame_timeout = timeout;
+        self
+    }
+
+    /// Connections accepted beyond this many open ones are closed at once.
+    pub fn with_max_connections(mut self, limit: usize) -> Self {
+        self.max_connections = limit;
+        self
+    }
+
+    /// Features to advertise on top of the ones the server's configuration
+    /// implies, such as [`Features::SNAPSHOTS`] when it has an admin handle.
+    pub fn with_features(mut self, features: Features) -> Self {
+        self.features = features;
+        self
+    }
+
+    fn features(&self) -> Features {
+        let mut features = self.features;
+        if self.admin.is_some() {
+            features = features.union(Features::SNAPSHOTS);
+        }
+        if self.instances.is_some() {
+            features = features.union(Features::INSTANCE_GC);
+        }
+        features
+    }
+
+    #[cfg(feature = "auth")]
+    pub fn with_auth(mut self, keyring: Arc<Keyring>) -> Self {
+        self.keyring = Some(keyring);
+        self
+    }
+
+    pub fn with_shutdown(mut self, stopped: Arc<AtomicBool>) -> Self {
+        self.stopped = stopped;
+        self
+    }
+
+    /// Serves requests for `addr`, the address this server listens on, from
+    /// a thread of its own. Requests arrive as if sent by this node itself.
+    pub fn loopback(&self, addr: SocketAddr) -> Loopback {
+        let server = self.clone();
+        let own = Some(lock(&self.acceptor).id());
+        let serve = move |frame: &Frame| {
+            server
+                .respond(&frame.body, own)
+                .map_err(|error| io::Error::other(error.to_string()))
+        };
+        Loopback {
+            addr,
+            connection: Arc::new(Connection::local(serve, &PoolOptions::default())),
+        }
+    }
+
+    pub fn serve(self, listener: TcpListener) -> io::Result<()> {
+        let server = Arc::new(self);
+        let mut connections: Vec<thread::JoinHandle<_>> = Vec::new();
+        for stream in listener.incoming() {
+            if server.stopped.load(Ordering::Acquire) {
+                break;
+            }
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_error) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(error = %_error, "accept failed");
+                    // Back off so a persistent condition such as running out
+                    // of descriptors does not spin the accept loop.
+                    thread::sleep(IDLE_POLL);
+                    continue;
+                }
+            };
+            connections.retain(|connection| !connection.is_finished());
+            if connections.len() >= server.max_connections {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(limit = server.max_connections, "too many connections");
+                continue;
+            }
+            let server = server.clone();
+            connections.push(thread::spawn(move || server.handle_connection(stream)));
+        }
+        for connection in connections {
+            let _ = connection.join();
+        }
+        Ok(())
+    }
+
+    fn handle_connection(&self, stream: TcpStream) -> Result<(), Error> {
+        loop {
+            stream.set_read_timeout(Some(IDLE_POLL))?;
+            while !self.stopped.load(Ordering::Acquire) {
+                match stream.peek(&mut [0]) {
+                    Ok(0) => return Ok(()),
+                    Ok(_) => break,
+                    Err(error)
+                        if matches!(
+                            error.kind(),
+                            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                        ) => {}
+                    Err(error) => return Err(error.into()),
+                }
+            }
+            if self.stopped.load(Ordering::Acquire) {
+                return Ok(());
+            }
+            let mut reading = Deadline::after(&stream, self.frame_timeout);
+            let frame = match read_frame(&mut reading, self.max_message_size) {
+                Ok(frame) => frame,
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(error) => return Err(error.into()),
+            };
+            #[cfg(feature = "auth")]
+            if let Some(keyring) = &self.keyring {
+                let (from, payload) = keyring.open(&frame)?;
+                let response = self.respond(payload, Some(from))?;
+                BUFFERS.give(frame);
+                let sealed = keyring.seal(from, &response)?;
+                BUFFERS.give(response);
+                write_frame(
+                    &mut Deadline::after(&stream, self.frame_timeout),
+                    &[&sealed],
+                )?;
+                continue;
+            }
+            let response = self.respond(&frame, None)?;
+            BUFFERS.give(frame);
+            write_frame(
+                &mut Deadline::after(&stream, self.frame_timeout),
+                &[&response],
+            )?;
+            BUFFERS.give(response);
+        }
+    }
+
+    fn respond(&self, payload: &[u8], from: Option<Id>) -> Result<Vec<u8>, Error> {
+        let response = match unversioned(payload) {
+            Ok(request) => self.handle(request, from)?,
+            Err(Error::IncompatibleVersion { .. }) => Response::Incompatible(PROTOCOL_VERSION),
+            Err(error) => return Err(error),
+        };
+        Ok(versioned(&response))
+    }
+
+    fn handle(&self, request: Request<V>, from: Option<Id>) -> Result<Response<V>, Error> {
+        let (request, token) = match request {
+            Request::Authorized(token, request) => (*request, Some(token)),
+            request => (request, None),
+        };
+        let epoch = self.epoch.load(Ordering::Acquire);
+        let request = match request {
+            Request::Fenced(theirs, request) if theirs == epoch && request.fenced() => *request,
+            Request::Fenced(..) => return Ok(Response::Fenced(epoch)),
+            request if epoch > 0 && request.fenced() => return Ok(Response::Fenced(epoch)),
+            request => request,
+        };
+        let required = request.required();
+        if !self.features().contains(required) {
+            return Ok(Response::Failed(not_negotiated(required).to_string()));
+        }
+        if let Some(refused) = self.forbidden(&request, from) {
+            return Ok(refused);
+        }
+        if let Some(refused) = self.unauthorized(&request, token.as_deref()) {
+            return Ok(refused);
+        }
+        match request {
+            Request::Read(round) => Ok(Response::Read(self.acceptor().handle_read(round)?)),
+            Request::Write(value) => {
+                // A stale write is refused without waiting on the acceptor,
+                // which may be busy persisting a newer round.
+                let response = match self.promised.refuse(value.last_round_with_write) {
+                    Some(refused) => refused,
+                    None => self.acceptor().handle_write(value.clone())?,
+                };
+                Ok(Response::Write(self.sign(
+                    response,
+                    value.value.as_ref(),
+                    from,
+                )?))
+            }
+            Request::Heartbeat(id) => {
+                if let Some(refused) = impersonated(id, from) {
+                    return Ok(refused);
+                }
+                if let Some(detector) = &self.detector {
+                    detector.heartbeat(id);
+                }
+                Ok(Response::Ack)
+            }
+            Request::Decision(decision) => {
+                if let Some(refused) = self.uncertified(&decision) {
+                    return Ok(refused);
+                }
+                if let Some(learner) = &self.learner {
+                    learner.handle_decision(decision);
+                }
+                Ok(Response::Ack)
+            }
+            Request::Leave(id) => {
+                if let Some(refused) = impersonated(id, from) {
+                    return Ok(refused);
+                }
+                if let Some(detector) = &self.detector {
+                    detector.leave(id);
+                }
+                Ok(Response::Ack)
+            }
+            Request::Fence(id, fence) => {
+                if let Some(refused) = impersonated(id, from) {
+                    return Ok(refused);
+                }
+                let Some(fences) = &self.fences else {
+                    return Ok(Response::Failed("fences are not kept here".to_string()));
+                };
+                match fences.announce(id, fence) {
+                    Ok(()) => Ok(Response::Ack),
+                    Err(FenceError::Stale { seen }) => Ok(Response::StaleStorage(seen)),
+                    Err(FenceError::Io(error)) => Err(error.into()),
+                }
+            }
+            Request::Hello(remote) => Ok(Response::Hello(Handshake {
+                version: PROTOCOL_VERSION.min(remote.version),
+                features: self.features().intersection(remote.features),
+            })),
+            Request::Status => Ok(Response::Status(self.status())),
+            Request::Health => Ok(Response::Health(self.health())),
+            Request::Certificate => Ok(Response::Certificate(
+                self.id,
+                self.learner.as_ref().and_then(Learner::certificate),
+            )),
+            Request::Propose(_) | Request::Decided | Request::StateTransfer(_)
+                if self.health() < Health::Ready =>
+            {
+                Ok(Response::Failed(format!(
+                    "not ready to serve: {}",
+                    self.health()
+                )))
+            }
+            Request::Propose(value) => {
+                if let Some(refused) = self.throttled(&value, from, token.as_deref()) {
+                    return Ok(refused);
+                }
+                Ok(self.propose(value))
+            }
+            Request::Decided => Ok(Response::Decided(
+                self.learner.as_ref().and_then(Learner::decision),
+            )),
+            Request::StateTransfer(from) => Ok(match &self.membership {
+                Some((log, reconfigure)) => match reconfigure.applied() {
+                    Ok(applied) => Response::StateTransfer(self.state_transfer(log, applied, from)),
+                    Err(error) => Response::Failed(error.to_string()),
+                },
+                None => Response::Failed("state transfer is not served here".to_string()),
+            }),
+            Request::Bootstrap(proposed) => {
+                let Some(bootstrap) = &self.bootstrap else {
+                    return Ok(Response::Failed("bootstrap is not served here".to_string()));
+                };
+                let mut bootstrap = lock(bootstrap);
+                let held = match proposed {
+                    Some(proposed) => Some(bootstrap.adopt(proposed)?.clone()),
+                    None => bootstrap.adopted().cloned(),
+                };
+                Ok(Response::Bootstrapped(self.acceptor().id(), held))
+            }
+            Request::AddMember(id, addr) => {
+                Ok(self.reconfigure(|membership| membership.add_member(id, addr)))
+            }
+            Request::RemoveMember(id) => {
+                Ok(self.reconfigure(|membership| membership.remove_member(id)))
+            }
+            Request::IssueJoinToken(id, ttl) => {
+                let Some(tokens) = &self.join_tokens else {
+                    return Ok(Response::Failed(
+                        "join tokens are not issued here".to_string(),
+                    ));
+                };
+                if let Some(redirect) = self.redirect() {
+                    return Ok(redirect);
+                }
+                Ok(Response::JoinToken(
+                    tokens.issue(id, Duration::from_millis(ttl)),
+                ))
+            }
+            Request::Join(token, id, addr) => {
+                let Some(tokens) = &self.join_tokens else {
+                    return Ok(Response::Failed("joining is not served here".to_string()));
+                };
+                if let Some(redirect) = self.redirect() {
+                    return Ok(redirect);
+                }
+                let redeemed = match tokens.redeem(&token, id) {
+                    Ok(redeemed) => redeemed,
+                    Err(error) => return Ok(Response::Failed(format!("unauthorized: {error}"))),
+                };
+                let response = self.reconfigure(|membership| membership.add_member(id, addr));
+                if matches!(response, Response::Membership(_)) {
+                    redeemed.spend();
+                }
+                Ok(response)
+            }
+            Request::Members => Ok(match &self.membership {
+                Some((_, reconfigure)) => match reconfigure.members() {
+                    Ok(members) => Response::Members(members),
+                    Err(error) => Response::Failed(error.to_string()),
+                },
+                None => Response::Failed("the membership is not served here".to_string()),
+            }),
+            Request::Introduce(id, addr) => match &self.membership {
+                Some((_, reconfigure)) => {
+                    reconfigure.introduce(id, addr);
+                    Ok(Response::Ack)
+                }
+                None => Ok(Response::Failed(
+                    "membership changes are not served here".to_string(),
+                )),
+            },
+            Request::MembershipLog(request) => {
+                let response = self.membership_log(&request, from)?;
+                Ok(Response::MembershipLog(to_bytes(&response)))
+            }
+            Request::Fenced(..) => Err(invalid_data("nested fenced request").into()),
+            Request::Authorized(..) => Err(invalid_data("nested authorized request").into()),
+            Request::Transfer(to) => match &self.detector {
+                Some(detector) => {
+                    detector.transfer(to);
+                    Ok(Response::Ack)
+                }
+                None => Ok(Response::Failed("no failure detector".to_string())),
+            },
+            Request::Snapshot => match self.admin.as_ref().map(|admin| admin.snapshot()) {
+                Some(Ok(())) => Ok(Response::Ack),
+                Some(Err(error)) => Ok(Response::Failed(error.to_string())),
+                None => Ok(Response::Failed("snapshots are not supported".to_string())),
+            },
+            Request::Backup(to) => {
+                match self
+                    .admin
+                    .as_ref()
+                    .map(|admin| admin.backup(Path::new(&to)))
+                {
+                    Some(Ok(())) => Ok(Response::Ack),
+                    Some(Err(error)) => Ok(Response::Failed(error.to_string())),
+                    None => Ok(Response::Failed("backups are not supported".to_string())),
+                }
+            }
+            Request::LogFilter(filter) => {
+                match self
+                    .admin
+                    .as_ref()
+                    .map(|admin| admin.set_log_filter(&filter))
+                {
+                    Some(Ok(())) => Ok(Response::Ack),
+                    Some(Err(error)) => Ok(Response::Failed(error.to_string())),
+                    None => Ok(Response::Failed(
+                        "log filters are not supported".to_string(),
+                    )),
+                }
+            }
+            Request::ReloadCredentials => {
+                match self.admin.as_ref().map(|admin| admin.reload_credentials()) {
+                    Some(Ok(())) => Ok(Response::Ack),
+                    Some(Err(error)) => Ok(Response::Failed(error.to_string())),
+                    None => Ok(Response::Failed(
+                        "reloading credentials is not supported".to_string(),
+                    )),
+                }
+            }
+            Request::Compressed(packed) => {
+                let request = from_bytes(&decompress(&packed, self.max_message_size)?)?;
+                if matches!(request, Request::Compressed(_)) {
+                    return Err(invalid_data("nested compressed request").into());
+                }
+                let response = self.handle(request, from)?;
+                match self.codec.pack(&to_bytes(&response)) {
+                    Some(packed) => Ok(Response::Compressed(packed)),
+                    None => Ok(response),
+                }
+            }
+            Request::Applied(learner, below) => {
+                if let Some(refused) = impersonated(learner, from) {
+                    return Ok(refused);
+                }
+                match &self.instances {
+                    Some(instances) => {
+                        instances.acknowledge(learner, below)?;
+                        Ok(Response::Ack)
+                    }
+                    None => Ok(Response::Failed(
+                        "instances are not served here".to_string(),
+                    )),
+                }
+            }
+            Request::Instance(instance, request) => {
+                let instances = self
+                    .instances
+                    .as_ref()
+                    .ok_or_else(|| invalid_data("instances are not served here"))?;
+                match *request {
+                    Request::Read(round) => {
+                        Ok(Response::Read(instances.handle_read(instance, round)?))
+                    }
+                    Request::Write(value) => {
+                        let response = instances.handle_write(instance, value.clone())?;
+                        Ok(Response::Write(self.sign(
+                            response,
+                            value.value.as_ref(),
+                            from,
+                        )?))
+                    }
+                    Request::Decision(decision) => {
+                        if let Some(refused) = self.uncertified(&decision) {
+                            return Ok(refused);
+                        }
+                        instances.handle_decision(instance, decision);
+                        Ok(Response::Ack)
+                    }
+                    Request::Decided => Ok(Response::Decided(instances.decision(instance))),
+                    _ => Err(invalid_data("unexpected instance request").into()),
+                }
+            }
+        }
+    }
+
+    fn membership_log(
+        &self,
+        request: &[u8],
+        from: Option<Id>,
+    ) -> Result<Response<Membership>, Error> {
+        let Some((log, reconfigure)) = &self.membership else {
+            return Ok(Response::Failed(
+                "the membership log is not served here".to_string(),
+            ));
+        };
+        let Request::Instance(index, request) = from_bytes::<Request<Membership>>(request)? else {
+            return Err(invalid_data("unexpected membership log request").into());
+        };
+        match *request {
+            Request::Read(round) => Ok(Response::Read(log.handle_read(index, round)?)),
+            Request::Write(value) => {
+                let response = log.handle_write(index, value.clone())?;
+                Ok(Response::Write(self.sign(
+                    response,
+                    value.value.as_ref(),
+                    from,
+                )?))
+            }
+            Request::Decision(decision) => {
+                if let Some(refused) = self.uncertified(&decision) {
+                    return Ok(refused);
+                }
+                let membership = decision.value.clone();
+                log.handle_decision(index, decision);
+                reconfigure.learned(index, membership)?;
+                Ok(Response::Ack)
+            }
+            Request::Decided => Ok(Response::Decided(log.decision(index))),
+            _ => Err(invalid_data("unexpected membership log request").into()),
+        }
+    }
+
+    /// Refuses decisions whose certificate is not a write quorum of the
+    /// current configuration or, with authentication, is not signed by it.
+    fn uncertified<T: Encode, R>(&self, decision: &DecisionBroadcast<T>) -> Option<Response<R>> {
+        let Some(quorum) = &self.quorum else {
+            return Some(Response::Failed(
+                "no quorum to verify decisions".to_string(),
+            ));
+        };
+        let verified = decision.verify(quorum);
+        #[cfg(feature = "auth")]
+        let verified = verified.and_then(|()| match &self.keyring {
+            Some(keyring) => decision.verify_signatures(keyring),
+            None => Ok(()),
+        });
+        verified
+            .err()
+            .map(|error| Response::Failed(format!("uncertified decision: {error}")))
+    }
+
+    fn propose(&self, value: V) -> Response<V> {
+        let Some(proposals) = &self.proposals else {
+            return Response::Failed("proposals are not served here".to_string());
+        };
+        if let Some(redirect) = self.redirect() {
+            return redirect;
+        }
+        match proposals.propose(value) {
+            Ok(decided) => Response::Decided(Some(decided)),
+            Err(error) => Response::Failed(error.to_string()),
+        }
+    }
+
+    fn reconfigure(
+        &self,
+        change: impl FnOnce(&dyn Reconfigure) -> Result<Membership, Error>,
+    ) -> Response<V> {
+        let Some((_, reconfigure)) = &self.membership else {
+            return Response::Failed("membership changes are not served here".to_string());
+        };
+        if let Some(redirect) = self.redirect() {
+            return redirect;
+        }
+        match change(reconfigure.as_ref()) {
+            Ok(membership) => Response::Membership(membership),
+            Err(error) => Response::Failed(error.to_string()),
+        }
+    }
+
+    /// Points the sender at the leader, unless this member is the one.
+    fn redirect(&self) -> Option<Response<V>> {
+        let leader = self.detector.as_ref().map(|detector| detector.leader());
+        leader
+            .filter(|leader| *leader != self.acceptor().id())
+            .map(|leader| Response::NotLeader(Some(leader)))
+    }
+
+    fn health(&self) -> Health {
+        if let (Some(detector), Some(quorum)) = (&self.detector, &self.quorum) {
+            let suspected = detector.suspected();
+            let live: HashSet<Id> = detector
+                .members()
+                .into_iter()
+                .filter(|member| !suspected.contains(member))
+                .collect();
+            if !quorum.is_write_quorum(&live) {
+                return Health::Alive;
+            }
+        }
+        if self
+            .ready
+            .as_ref()
+            .is_some_and(|ready| !ready.load(Ordering::Acquire))
+        {
+            return Health::Recovering;
+        }
+        match &self.detector {
+            Some(detector) if detector.leader() == self.id => Health::Leader,
+            _ => Health::Ready,
+        }
+    }
+
+    // Without authentication every sender is trusted. With it, members may
+    // send anything but admin requests, and everyone else only what their
+    // role grants.
+    fn forbidden(&self, request: &Request<V>, from: Option<Id>) -> Option<Response<V>> {
+        #[cfg(feature = "auth")]
+        if let (Some(keyring), Some(from)) = (&self.keyring, from) {
+            let needed = request.permission();
+            let allowed = match (keyring.role(from), needed) {
+                (None, needed) => needed.is_none_or(|needed| !needed.contains(Permissions::ADMIN)),
+                (Some(granted), Some(needed)) => granted.contains(needed),
+                (Some(_), None) => false,
+            };
+            return (!allowed).then(|| {
+                let what = needed.map_or("consensus".to_string(), |needed| needed.to_string());
+                Response::Failed(format!("{from:?} may not send {what} requests"))
+            });
+        }
+        let _ = (request, from);
+        None
+    }
+
+    fn unauthorized(&self, request: &Request<V>, token: Option<&str>) -> Option<Response<V>> {
+        let (tokens, access) = (self.tokens.as_ref()?, request.access()?);
+        let verified = match token {
+            Some(token) => tokens.verify(token, access),
+            None => Err(TokenError::Missing),
+        };
+        verified
+            .err()
+            .map(|error| Response::Failed(format!("unauthorized: {error}")))
+    }
+
+    fn throttled(&self, value: &V, from: Option<Id>, token: Option<&str>) -> Option<Response<V>> {
+        let limiter = self.limiter.as_ref()?;
+        let retry_after = limiter
+            .admit(&Tenant::new(from, token), to_bytes(value).len())
+            .err()?;
+        Some(Response::Throttled(retry_after.as_millis() as u64))
+    }
+
+    fn status(&self) -> NodeStatus {
+        let (id, last_round_entered, accepted_round) = {
+            let acceptor = self.acceptor();
+            let state = acceptor.state();
+            (
+                acceptor.id(),
+                state.last_round_entered(),
+                state.accepted_round(),
+            )
+        };
+        NodeStatus {
+            id,
+            leader: self.detector.as_ref().map(|detector| detector.leader()),
+            members: self
+                .detector
+                .as_ref()
+                .map_or_else(Vec::new, |detector| detector.members()),
+            suspected: self
+                .detector
+                .as_ref()
+                .map_or_else(Vec::new, |detector| detector.suspected()),
+            last_round_entered,
+            accepted_round,
+            decided: self
+                .learner
+                .as_ref()
+                .is_some_and(|learner| learner.decision().is_some()),
+            commit_index: self.admin.as_ref().and_then(|admin| admin.commit_index()),
+            epoch: self.epoch.load(Ordering::Acquire),
+        }
+    }
+
+    #[cfg(not(feature = "auth"))]
+    fn sign(
+        &self,
+        response: WriteResponse,
+        _value: &impl Encode,
+        _from: Option<Id>,
+    ) -> io::Result<WriteResponse> {
+        Ok(response)
+    }
+
+    #[cfg(feature = "auth")]
+    fn sign(
+        &self,
+        response: WriteResponse,
+        value: &impl Encode,
+        from: Option<Id>,
+    ) -> io::Result<WriteResponse> {
+        match (&self.keyring, from) {
+            (Some(keyring), Some(proposer)) => {
+                certificate::sign(response, value, keyring, proposer)
+            }
+            _ => Ok(response),
+        }
+    }
+
+    fn state_transfer(
+        &self,
+        log: &InstanceAcceptors<Membership>,
+        applied: Applied,
+        from: LogIndex,
+    ) -> StateTransfer<V> {
+        let decided = |index: u64| {
+            log.decision(InstanceId(index))
+                .map(|membership| (LogIndex::new(index), membership))
+        };
+        let start = from.max(applied.next).get();
+        let entries: Vec<_> = (start..).map_while(decided).take(TRANSFER_PAGE).collect();
+        let next = start + entries.len() as u64;
+        let end = next + (next..).map_while(decided).count() as u64;
+        StateTransfer {
+            applied: (from < applied.next).then_some(applied),
+            entries,
+            end: LogIndex::new(end),
+            last_round_entered: self.acceptor().state().last_round_entered,
+            certificate: self.learner.as_ref().and_then(Learner::certificate),
+        }
+    }
+
+    fn acceptor(&self) -> MutexGuard<'_, Acceptor<V, S>> {
+        self.acceptor.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(not(feature = "auth"))]
+fn seal_request(request: &Arc<[u8]>, _seal: &Option<Infallible>) -> io::Result<Frame> {
+    Ok(Frame {
+        body: request.clone(),
+        ..Frame::default()
+    })
+}
+
+#[cfg(feature = "auth")]
+fn seal_request(request: &Arc<[u8]>, seal: &Option<(Arc<Keyring>, Id)>) -> io::Result<Frame> {
+    let (head, tail) = match seal {
+        Some((keyring, peer)) => {
+            let (head, tail) = keyring.seal_around(*peer, request)?;
+            (head.to_vec(), tail.to_vec())
+        }
+        None => Default::default(),
+    };
+    Ok(Frame {
+        head,
+        body: request.clone(),
+        tail,
+    })
+}
+
+#[cfg(not(feature = "auth"))]
+fn open_response<V: Decode>(
+    frame: &[u8],
+    _seal: Option<Infallible>,
+    limit: usize,
+) -> Result<Response<V>, Error> {
+    response(frame, limit)
+}
+
+#[cfg(feature = "auth")]
+fn open_response<V: Decode>(
+    frame: &[u8],
+    seal: Option<(Arc<Keyring>, Id)>,
+    limit: usize,
+) -> Result<Response<V>, Error> {
+    let Some((keyring, peer)) = seal else {
+        return response(frame, limit);
+    };
+    let (from, payload) = keyring.open(frame)?;
+    let response: Response<V> = response(payload, limit)?;
+    if from != peer || response.acceptor().is_some_and(|acceptor| acceptor != peer) {
+        return Err(invalid_data("response from unexpected peer").into());
+    }
+    Ok(response)
+}
+
+fn response<V: Decode>(frame: &[u8], limit: usize) -> Result<Response<V>, Error> {
+    let response = match unversioned(frame)? {
+        Response::Incompatible(remote) => {
+            return Err(Error::IncompatibleVersion {
+                local: PROTOCOL_VERSION,
+                remote,
+            })
+        }
+        Response::Compressed(packed) => match from_bytes(&decompress(&packed, limit)?)? {
+            Response::Compressed(_) => {
+                return Err(invalid_data("nested compressed response").into())
+            }
+            response => response,
+        },
+        response => response,
+    };
+    // Only peers speaking the membership log send these, and for them `V`
+    // is the membership.
+    match response {
+        Response::MembershipLog(inner) => match from_bytes(&inner)? {
+            Response::MembershipLog(_) | Response::Compressed(_) => {
+                Err(invalid_data("nested membership log response").into())
+            }
+            response => Ok(response),
+        },
+        response => Ok(response),
+    }
+}
+
+/// Refuses messages that speak for another node than the one that
+/// authenticated them.
+fn impersonated<V>(id: Id, from: Option<Id>) -> Option<Response<V>> {
+    let from = from.filter(|from| *from != id)?;
+    Some(Response::Failed(format!(
+        "{from:?} may not speak for {id:?}"
+    )))
+}
+
+fn reconfigured<V>(response: Response<V>) -> io::Result<Proposed<Membership>> {
+    match response {
+        Response::Membership(membership) => Ok(Proposed::Decided(membership)),
+        Response::NotLeader(leader) => Ok(Proposed::NotLeader(leader)),
+        response => Err(failure(response)),
+    }
+}
+
+fn acknowledged<V>(response: Response<V>) -> io::Result<()> {
+    match response {
+        Response::Ack => Ok(()),
+        response => Err(failure(response)),
+    }
+}
+
+fn failure<V>(response: Response<V>) -> io::Error {
+    match response {
+        Response::Failed(message) => io::Error::other(message),
+        Response::Fenced(epoch) => io::Error::other(format!("fenced off by epoch {epoch}")),
+        _ => invalid_data("unexpected response"),
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn not_negotiated(features: Features) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("features {:#x} were not negotiated", features.0),
+    )
+}
+
+fn versioned<T: Encode>(message: &T) -> Vec<u8> {
+    let mut buf = BUFFERS.take();
+    PROTOCOL_VERSION.encode(&mut buf);
+    message.encode(&mut buf);
+    buf
+}
+
+fn unversioned<T: Decode>(mut frame: &[u8]) -> Result<T, Error> {
+    let version = u32::decode(&mut frame)?;
+    if !SUPPORTED_VERSIONS.contains(&version) {
+        return Err(Error::IncompatibleVersion {
+            local: PROTOCOL_VERSION,
+            remote: version,
+        });
+    }
+    Ok(from_bytes(frame)?)
+}
+
+/// Writes `parts` back to back as the payload of one frame.
+pub(super) fn write_frame(stream: &mut impl Write, parts: &[&[u8]]) -> io::Result<()> {
+    write_frames(stream, &[parts])
+}
+
+/// Writes each entry of `frames` as one frame, all with as few system calls
+/// as the stream allows and without copying them together first.
+pub(super) fn write_frames(stream: &mut impl Write, frames: &[&[&[u8]]]) -> io::Result<()> {
+    let headers = frames
+        .iter()
+        .map(|parts| {
+            let size = parts.iter().map(|part| part.len()).sum::<usize>();
+            let len = u32::try_from(size).map_err(|_| invalid_data("frame too large"))?;
+            Ok(len.to_be_bytes())
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut slices: Vec<_> = headers
+        .iter()
+        .zip(frames)
+        .flat_map(|(header, parts)| {
+            std::iter::once(IoSlice::new(header)).chain(parts.iter().map(|part| IoSlice::new(part)))
+        })
+        .collect();
+    let mut slices = &mut slices[..];
+    IoSlice::advance_slices(&mut slices, 0);
+    while !slices.is_empty() {
+        match stream.write_vectored(slices) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(written) => IoSlice::advance_slices(&mut slices, written),
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(())
+}
+
+pub(super) fn read_frame(stream: &mut impl Read, limit: usize) -> io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > limit {
+        return Err(invalid_data("frame too large"));
+    }
+    // Grow the buffer as bytes arrive so a peer cannot make us allocate a
+    // whole frame up front by lying about its length.
+    let mut payload = BUFFERS.take();
+    payload.reserve(len.min(READ_CHUNK));
+    stream.take(len as u64).read_to_end(&mut payload)?;
+    if payload.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(payload)
+}
+
+/// Bounds a whole frame rather than each read or write of it, so a peer
+/// cannot hold a connection by trickling bytes.
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl<'a> Deadline<'a> {
+    fn after(stream: &'a TcpStream, timeout: Duration) -> Self {
+        Self {
+            stream,
+            deadline: Instant::now() + timeout,
+        }
+    }
+
+    fn remaining(&self) -> io::Result<Duration> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        Ok(remaining)
+    }
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.set_read_timeout(Some(self.remaining()?))?;
+        self.stream.read(buf)
+    }
+}
+
+impl Write for Deadline<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.set_write_timeout(Some(self.remaining()?))?;
+        self.stream.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.stream.set_write_timeout(Some(self.remaining()?))?;
+        self.stream.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+enum Request<V> {
+    Read(Round),
+    Write(Value<V>),
+    Heartbeat(Id),
+    Decision(DecisionBroadcast<V>),
+    Leave(Id),
+    Hello(Handshake),
+    Instance(InstanceId, Box<Request<V>>),
+    Status,
+    Transfer(Id),
+    Snapshot,
+    Compressed(Vec<u8>),
+    Applied(Id, InstanceId),
+    Propose(V),
+    Decided,
+    /// Adopts the bootstrap if given and none was adopted yet; either way
+    /// the member answers with the one it holds.
+    Bootstrap(Option<Bootstrap>),
+    /// A request for the membership log, kept encoded since it decides
+    /// memberships rather than `V`.
+    MembershipLog(Vec<u8>),
+    AddMember(Id, SocketAddr),
+    RemoveMember(Id),
+    Introduce(Id, SocketAddr),
+    /// A consensus request from a member of the given epoch.
+    Fenced(u64, Box<Request<V>>),
+    Health,
+    Certificate,
+    Members,
+    /// Backs the member up into the directory at this path on its host.
+    Backup(String),
+    LogFilter(String),
+    ReloadCredentials,
+    /// A client request with the bearer token it presents.
+    Authorized(String, Box<Request<V>>),
+    /// Issues a token for this node to join with in the next this many
+    /// milliseconds.
+    IssueJoinToken(Id, u64),
+    /// Adds the node sending it, listening at this address, with the join
+    /// token the leader issued for it.
+    Join(String, Id, SocketAddr),
+    /// Asks for the page of this member's state a joining node needs, from
+    /// this slot of the membership log on.
+    StateTransfer(LogIndex),
+    /// Tells a member the node named now runs on storage at this fence.
+    Fence(Id, Fence),
+}
+
+enum Response<V> {
+    Read(ReadResponse<V>),
+    Write(WriteResponse),
+    Ack,
+    Hello(Handshake),
+    Incompatible(u32),
+    Status(NodeStatus),
+    Failed(String),
+    Compressed(Vec<u8>),
+    Decided(Option<V>),
+    NotLeader(Option<Id>),
+    Bootstrapped(Id, Option<Bootstrap>),
+    MembershipLog(Vec<u8>),
+    Membership(Membership),
+    /// Refused since the sender is not in the epoch given.
+    Fenced(u64),
+    Health(Health),
+    Certificate(Id, Option<DecisionCertificate<V>>),
+    Members(Vec<Member>),
+    /// Refused since the client is over its quota for this many more
+    /// milliseconds.
+    Throttled(u64),
+    JoinToken(String),
+    StateTransfer(StateTransfer<V>),
+    /// Refused since the member heard of this fence, no lower, before.
+    StaleStorage(Fence),
+}
+
+impl<V> Request<V> {
+    fn required(&self) -> Features {
+        match self {
+            Request::Snapshot => Features::SNAPSHOTS,
+            Request::Compressed(_) => Features::COMPRESSION,
+            Request::Applied(..) => Features::INSTANCE_GC,
+            Request::Instance(_, request)
+            | Request::Fenced(_, request)
+            | Request::Authorized(_, request) => request.required(),
+            _ => Features::empty(),
+        }
+    }
+
+    /// What an authenticated sender's role must grant for it to make the
+    /// request; `None` for the requests only members make among themselves.
+    #[cfg(feature = "auth")]
+    fn permission(&self) -> Option<Permissions> {
+        match self {
+            Request::Hello(_) | Request::Compressed(_) => Some(Permissions::empty()),
+            Request::Propose(_) => Some(Permissions::PROPOSE),
+            Request::Decided => Some(Permissions::READ),
+            Request::Status | Request::Health | Request::Members => Some(Permissions::WATCH),
+            Request::Transfer(_)
+            | Request::Snapshot
+            | Request::Backup(_)
+            | Request::LogFilter(_)
+            | Request::ReloadCredentials
+            | Request::IssueJoinToken(..)
+            | Request::AddMember(..)
+            | Request::RemoveMember(_) => Some(Permissions::ADMIN),
+            _ => None,
+        }
+    }
+
+    /// What a token must grant for a client to make the request.
+    fn access(&self) -> Option<Access> {
+        match self {
+            Request::Propose(_) => Some(Access::Write),
+            Request::Decided => Some(Access::Read),
+            _ => None,
+        }
+    }
+
+    /// Whether the request takes part in consensus, so only members of the
+    /// same epoch may make it.
+    fn fenced(&self) -> bool {
+        matches!(
+            self,
+            Request::Read(_)
+                | Request::Write(_)
+                | Request::Heartbeat(_)
+                | Request::Decision(_)
+                | Request::Leave(_)
+                | Request::Instance(..)
+                | Request::Applied(..)
+                | Request::MembershipLog(_)
+                | Request::Introduce(..)
+                | Request::Certificate
+        )
+    }
+}
+
+#[cfg(feature = "auth")]
+impl<V> Response<V> {
+    fn acceptor(&self) -> Option<Id> {
+        match self {
+            Response::Read(response) => Some(response.acceptor),
+            Response::Write(response) => Some(response.acceptor),
+            Response::Status(status) => Some(status.id),
+            Response::Bootstrapped(id, _) | Response::Certificate(id, _) => Some(*id),
+            Response::Ack
+            | Response::MembershipLog(_)
+            | Response::Membership(_)
+            | Response::Fenced(_)
+            | Response::Health(_)
+            | Response::Members(_)
+            | Response::Throttled(_)
+            | Response::JoinToken(_)
+            | Response::StateTransfer(_)
+            | Response::StaleStorage(_)
+            | Response::Hello(_)
+            | Response::Incompatible(_)
+            | Response::Failed(_)
+            | Response::Compressed(_)
+            | Response::Decided(_)
+            | Response::NotLeader(_) => None,
+        }
+    }
+}
+
+impl<V: Encode> Encode for Request<V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Request::Read(round) => {
+                0u8.encode(buf);
+                round.encode(buf);
+            }
+            Request::Write(value) => {
+                1u8.encode(buf);
+                value.encode(buf);
+            }
+            Request::Heartbeat(from) => {
+                2u8.encode(buf);
+                from.encode(buf);
+            }
+            Request::Decision(decision) => {
+                3u8.encode(buf);
+                decision.encode(buf);
+            }
+            Request::Leave(from) => {
+                4u8.encode(buf);
+                from.encode(buf);
+            }
+            Request::Hello(handshake) => {
+                5u8.encode(buf);
+                handshake.encode(buf);
+            }
+            Request::Instance(instance, request) => {
+                6u8.encode(buf);
+                instance.encode(buf);
+                request.encode(buf);
+            }
+            Request::Status => 7u8.encode(buf),
+            Request::Transfer(to) => {
+                8u8.encode(buf);
+                to.encode(buf);
+            }
+            Request::Snapshot => 9u8.encode(buf),
+            Request::Compressed(packed) => {
+                10u8.encode(buf);
+                packed.encode(buf);
+            }
+            Request::Applied(learner, below) => {
+                11u8.encode(buf);
+                learner.encode(buf);
+                below.encode(buf);
+            }
+            Request::Propose(value) => {
+                12u8.encode(buf);
+                value.encode(buf);
+            }
+            Request::Decided => 13u8.encode(buf),
+            Request::Bootstrap(bootstrap) => {
+                14u8.encode(buf);
+                bootstrap.encode(buf);
+            }
+            Request::MembershipLog(request) => {
+                15u8.encode(buf);
+                request.encode(buf);
+            }
+            Request::AddMember(id, addr) => {
+                16u8.encode(buf);
+                id.encode(buf);
+                addr.encode(buf);
+            }
+            Request::RemoveMember(id) => {
+                17u8.encode(buf);
+                id.encode(buf);
+            }
+            Request::Introduce(id, addr) => {
+                18u8.encode(buf);
+                id.encode(buf);
+                addr.encode(buf);
+            }
+            Request::Fenced(epoch, request) => {
+                19u8.encode(buf);
+                epoch.encode(buf);
+                request.encode(buf);
+            }
+            Request::Health => 20u8.encode(buf),
+            Request::Certificate => 21u8.encode(buf),
+            Request::Members => 22u8.encode(buf),
+            Request::Backup(to) => {
+                23u8.encode(buf);
+                to.encode(buf);
+            }
+            Request::LogFilter(filter) => {
+                24u8.encode(buf);
+                filter.encode(buf);
+            }
+            Request::Authorized(token, request) => {
+                25u8.encode(buf);
+                token.encode(buf);
+                request.encode(buf);
+            }
+            Request::ReloadCredentials => 26u8.encode(buf),
+            Request::IssueJoinToken(id, ttl) => {
+                27u8.encode(buf);
+                id.encode(buf);
+                ttl.encode(buf);
+            }
+            Request::Join(token, id, addr) => {
+                28u8.encode(buf);
+                token.encode(buf);
+                id.encode(buf);
+                addr.encode(buf);
+            }
+            Request::StateTransfer(from) => {
+                29u8.encode(buf);
+                from.encode(buf);
+            }
+            Request::Fence(id, fence) => {
+                30u8.encode(buf);
+                id.encode(buf);
+                fence.encode(buf);
+            }
+        }
+    }
+}
+
+impl<V: Decode> Decode for Request<V> {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        match u8::decode(buf)? {
+            0 => Ok(Request::Read(Round::decode(buf)?)),
+            1 => Ok(Request::Write(Value::decode(buf)?)),
+            2 => Ok(Request::Heartbeat(Id::decode(buf)?)),
+            3 => Ok(Request::Decision(DecisionBroadcast::decode(buf)?)),
+            4 => Ok(Request::Leave(Id::decode(buf)?)),
+            5 => Ok(Request::Hello(Handshake::decode(buf)?)),
+            6 => {
+                let instance = InstanceId::decode(buf)?;
+                if buf.first() == Some(&6) {
+                    return Err(invalid_data("nested instance request"));
+                }
+                Ok(Request::Instance(instance, Box::new(Request::decode(buf)?)))
+            }
+            7 => Ok(Request::Status),
+            8 => Ok(Request::Transfer(Id::decode(buf)?)),
+            9 => Ok(Request::Snapshot),
+            10 => Ok(Request::Compressed(Vec::decode(buf)?)),
+            11 => Ok(Request::Applied(Id::decode(buf)?, InstanceId::decode(buf)?)),
+            12 => Ok(Request::Propose(V::decode(buf)?)),
+            13 => Ok(Request::Decided),
+            14 => Ok(Request::Bootstrap(Option::decode(buf)?)),
+            15 => Ok(Request::MembershipLog(Vec::decode(buf)?)),
+            16 => Ok(Request::AddMember(
+                Id::decode(buf)?,
+                SocketAddr::decode(buf)?,
+            )),
+            17 => Ok(Request::RemoveMember(Id::decode(buf)?)),
+            18 => Ok(Request::Introduce(
+                Id::decode(buf)?,
+                SocketAddr::decode(buf)?,
+            )),
+            19 => Ok(Request::Fenced(
+                u64::decode(buf)?,
+                Box::new(Request::decode(buf)?),
+            )),
+            20 => Ok(Request::Health),
+            21 => Ok(Request::Certificate),
+            22 => Ok(Request::Members),
+            23 => Ok(Request::Backup(String::decode(buf)?)),
+            24 => Ok(Request::LogFilter(String::decode(buf)?)),
+            25 => {
+                let token = String::decode(buf)?;
+                if buf.first() == Some(&25) {
+                    return Err(invalid_data("nested authorized request"));
+                }
+                Ok(Request::Authorized(token, Box::new(Request::decode(buf)?)))
+            }
+            26 => Ok(Request::ReloadCredentials),
+            27 => Ok(Request::IssueJoinToken(Id::decode(buf)?, u64::decode(buf)?)),
+            28 => Ok(Request::Join(
+                String::decode(buf)?,
+                Id::decode(buf)?,
+                SocketAddr::decode(buf)?,
+            )),
+            29 => Ok(Request::StateTransfer(LogIndex::decode(buf)?)),
+            30 => Ok(Request::Fence(Id::decode(buf)?, Fence::decode(buf)?)),
+            _ => Err(invalid_data("unknown request")),
+        }
+    }
+}
+
+impl<V: Encode> Encode for Response<V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Response::Read(response) => {
+                0u8.encode(buf);
+                response.encode(buf);
+            }
+            Response::Write(response) => {
+                1u8.encode(buf);
+                response.encode(buf);
+            }
+            Response::Ack => 2u8.encode(buf),
+            Response::Hello(handshake) => {
+                3u8.encode(buf);
+                handshake.encode(buf);
+            }
+            Response::Incompatible(version) => {
+                4u8.encode(buf);
+                version.encode(buf);
+            }
+            Response::Status(status) => {
+                5u8.encode(buf);
+                status.encode(buf);
+            }
+            Response::Failed(message) => {
+                6u8.encode(buf);
+                message.encode(buf);
+            }
+            Response::Compressed(packed) => {
+                7u8.encode(buf);
+                packed.encode(buf);
+            }
+            Response::Decided(decided) => {
+                8u8.encode(buf);
+                decided.encode(buf);
+            }
+            Response::NotLeader(leader) => {
+                9u8.encode(buf);
+                leader.encode(buf);
+            }
+            Response::Bootstrapped(id, bootstrap) => {
+                10u8.encode(buf);
+                id.encode(buf);
+                bootstrap.encode(buf);
+            }
+            Response::MembershipLog(response) => {
+                11u8.encode(buf);
+                response.encode(buf);
+            }
+            Response::Membership(membership) => {
+                12u8.encode(buf);
+                membership.encode(buf);
+            }
+            Response::Fenced(epoch) => {
+                13u8.encode(buf);
+                epoch.encode(buf);
+            }
+            Response::Health(health) => {
+                14u8.encode(buf);
+                health.encode(buf);
+            }
+            Response::Certificate(id, certificate) => {
+                15u8.encode(buf);
+                id.encode(buf);
+                certificate.encode(buf);
+            }
+            Response::Members(members) => {
+                16u8.encode(buf);
+                members.encode(buf);
+            }
+            Response::Throttled(millis) => {
+                17u8.encode(buf);
+                millis.encode(buf);
+            }
+            Response::JoinToken(token) => {
+                18u8.encode(buf);
+                token.encode(buf);
+            }
+            Response::StateTransfer(page) => {
+                19u8.encode(buf);
+                page.encode(buf);
+            }
+            Response::StaleStorage(seen) => {
+                20u8.encode(buf);
+                seen.encode(buf);
+            }
+        }
+    }
+}
+
+impl<V: Decode> Decode for Response<V> {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        match u8::decode(buf)? {
+            0 => Ok(Response::Read(ReadResponse::decode(buf)?)),
+            1 => Ok(Response::Write(WriteResponse::decode(buf)?)),
+            2 => Ok(Response::Ack),
+            3 => Ok(Response::Hello(Handshake::decode(buf)?)),
+            4 => Ok(Response::Incompatible(u32::decode(buf)?)),
+            5 => Ok(Response::Status(NodeStatus::decode(buf)?)),
+            6 => Ok(Response::Failed(String::decode(buf)?)),
+            7 => Ok(Response::Compressed(Vec::decode(buf)?)),
+            8 => Ok(Response::Decided(Option::decode(buf)?)),
+            9 => Ok(Response::NotLeader(Option::decode(buf)?)),
+            10 => Ok(Response::Bootstrapped(
+                Id::decode(buf)?,
+                Option::decode(buf)?,
+            )),
+            11 => Ok(Response::MembershipLog(Vec::decode(buf)?)),
+            12 => Ok(Response::Membership(Membership::decode(buf)?)),
+            13 => Ok(Response::Fenced(u64::decode(buf)?)),
+            14 => Ok(Response::Health(Health::decode(buf)?)),
+            15 => Ok(Response::Certificate(
+                Id::decode(buf)?,
+                Option::decode(buf)?,
+            )),
+            16 => Ok(Response::Members(Vec::decode(buf)?)),
+            17 => Ok(Response::Throttled(u64::decode(buf)?)),
+            18 => Ok(Response::JoinToken(String::decode(buf)?)),
+            19 => Ok(Response::StateTransfer(StateTransfer::decode(buf)?)),
+            20 => Ok(Response::StaleStorage(Fence::decode(buf)?)),
+            _ => Err(invalid_data("unknown response")),
+        }
+    }
+}
+
+impl Encode for Handshake {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.version.encode(buf);
+        self.features.0.encode(buf);
+    }
+}
+
+impl Decode for Handshake {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            version: u32::decode(buf)?,
+            features: Features(u32::decode(buf)?),
+        })
+    }
+}
+
+impl<V: Encode> Encode for StateTransfer<V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.applied.encode(buf);
+        (self.entries.len() as u64).encode(buf);
+        for (index, membership) in &self.entries {
+            index.encode(buf);
+            membership.encode(buf);
+        }
+        self.end.encode(buf);
+        self.last_round_entered.encode(buf);
+        self.certificate.encode(buf);
+    }
+}
+
+impl<V: Decode> Decode for StateTransfer<V> {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        let applied = Option::decode(buf)?;
+        let len = u64::decode(buf)?;
+        let mut entries = Vec::new();
+        for _ in 0..len {
+            entries.push((LogIndex::decode(buf)?, Membership::decode(buf)?));
+        }
+        Ok(Self {
+            applied,
+            entries,
+            end: LogIndex::decode(buf)?,
+            last_round_entered: Round::decode(buf)?,
+            certificate: Option::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for NodeStatus {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.id.encode(buf);
+        self.leader.encode(buf);
+        self.members.encode(buf);
+        self.suspected.encode(buf);
+        self.last_round_entered.encode(buf);
+        self.accepted_round.encode(buf);
+        self.decided.encode(buf);
+        self.commit_index.encode(buf);
+        self.epoch.encode(buf);
+    }
+}
+
+impl Encode for Health {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            Health::Alive => 0,
+            Health::Recovering => 1,
+            Health::Ready => 2,
+            Health::Leader => 3,
+        };
+        tag.encode(buf);
+    }
+}
+
+impl Decode for Health {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        match u8::decode(buf)? {
+            0 => Ok(Health::Alive),
+            1 => Ok(Health::Recovering),
+            2 => Ok(Health::Ready),
+            3 => Ok(Health::Leader),
+            _ => Err(invalid_data("unknown health")),
+        }
+    }
+}
+
+impl Decode for NodeStatus {
+    fn decode(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            id: Id::decode(buf)?,
+            leader: Option::decode(buf)?,
+            members: Vec::decode(buf)?,
+            suspected: Vec::decode(buf)?,
+            last_round_entered: Round::decode(buf)?,
+            accepted_round: Option::decode(buf)?,
+            decided: bool::decode(buf)?,
+            commit_index: Option::decode(buf)?,
+            epoch: u64::decode(buf)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alpha::{Alpha, Status};
+    use crate::bytes::BytesValue;
+    use crate::codec::{from_bytes, to_bytes};
+    use crate::instance::InstanceStore;
+    use crate::quorum::QuorumSpec;
+    use crate::storage::MemoryStorage;
+    use crate::time::MockClock;
+    use crate::token::StaticTokens;
+    use futures::executor::block_on;
+
+    struct Snapshots;
+
+    impl Admin for Snapshots {
+        fn commit_index(&self) -> Option<LogIndex> {
+            None
+        }
+
+        fn snapshot(&self) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn backup(&self, to: &Path) -> io::Result<()> {
+            match to.is_relative() {
+                true => Ok(()),
+                false => Err(io::Error::other("backups go elsewhere")),
+            }
+        }
+
+        fn set_log_filter(&self, filter: &str) -> io::Result<()> {
+            filter
+                .parse::<crate::log_filter::Filter>()
+                .map(drop)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))
+        }
+
+        fn reload_credentials(&self) -> io::Result<()> {
+            Err(io::Error::other("no credentials here"))
+        }
+    }
+
+    fn server<V>() -> Server<V, MemoryStorage<V>>
+    where
+        V: Clone + PartialEq + Encode + Decode + Send + Sync + 'static,
+    {
+        let acceptor = Acceptor::new(Id(1), MemoryStorage::default()).unwrap();
+        Server::new(Arc::new(Mutex::new(acceptor)))
+    }
+
+    fn serve<V>(server: Server<V, MemoryStorage<V>>) -> SocketAddr
+    where
+        V: Clone + PartialEq + Encode + Decode + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || server.serve(listener));
+        addr
+    }
+
+    fn first<T>(responses: impl Stream<Item = Result<T, Error>>) -> Result<T, Error> {
+        block_on(Box::pin(responses).next()).unwrap()
+    }
+
+    fn stream_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (client, server)
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let (mut client, mut server) = stream_pair();
+        write_frame(&mut client, &[b"hel", b"lo"]).unwrap();
+        assert_eq!(read_frame(&mut server, 5).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn membership_requests_round_trip() {
+        let addr: SocketAddr = "127.0.0.1:7003".parse().unwrap();
+        let log = Request::<Membership>::Instance(
+            InstanceId(2),
+            Box::new(Request::Read(Round::new(Id(1)))),
+        );
+        for request in [
+            Request::<u64>::AddMember(Id(3), addr),
+            Request::RemoveMember(Id(3)),
+            Request::Introduce(Id(3), addr),
+            Request::MembershipLog(to_bytes(&log)),
+            Request::Members,
+            Request::Backup("/var/backups/paxos".to_string()),
+            Request::LogFilter("info,paxos_classic::node=debug".to_string()),
+            Request::ReloadCredentials,
+            Request::Authorized("secret".to_string(), Box::new(Request::Propose(7))),
+            Request::IssueJoinToken(Id(3), 60_000),
+            Request::Join("0123abcd".to_string(), Id(3), addr),
+            Request::StateTransfer(LogIndex::new(5)),
+            Request::Fence(Id(3), Fence { epoch: 1, count: 4 }),
+        ] {
+            let bytes = to_bytes(&request);
+            assert_eq!(
+                to_bytes(&from_bytes::<Request<u64>>(&bytes).unwrap()),
+                bytes
+            );
+        }
+        let membership = Membership::Stable(crate::membership::Configuration::new([Id(1), Id(3)]));
+        let bytes = to_bytes(&Response::<u64>::Membership(membership.clone()));
+        assert!(matches!(
+            from_bytes::<Response<u64>>(&bytes).unwrap(),
+            Response::Membership(decoded) if decoded == membership
+        ));
+    }
+
+    struct Transferred(Applied);
+
+    impl Reconfigure for Transferred {
+        fn add_member(&self, _: Id, _: SocketAddr) -> Result<Membership, Error> {
+            Err(Error::NotLeader)
+        }
+
+        fn remove_member(&self, _: Id) -> Result<Membership, Error> {
+            Err(Error::NotLeader)
+        }
+
+        fn introduce(&self, _: Id, _: SocketAddr) {}
+
+        fn learned(&self, _: InstanceId, _: Membership) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn members(&self) -> Result<Vec<Member>, Error> {
+            Ok(Vec::new())
+        }
+
+        fn applied(&self) -> Result<Applied, Error> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn transfers_the_membership_log_a_page_at_a_time() {
+        let membership = |voters: &[u64]| {
+            Membership::Stable(crate::membership::Configuration::new(
+                voters.iter().copied().map(Id),
+            ))
+        };
+        let log = Arc::new(InstanceAcceptors::new(Id(1), InstanceStore::in_memory()));
+        let decided = 2 + TRANSFER_PAGE as u64 + 10;
+        for index in 0..decided {
+            log.handle_decision(
+                InstanceId(index),
+                DecisionCertificate {
+                    proposer: Id(1),
+                    round: Round::new(Id(1)),
+                    value: membership(&[1, index + 2]),
+                    responses: Vec::new(),
+                },
+            );
+        }
+        let applied = Applied {
+            next: LogIndex::new(2),
+            membership: membership(&[1, 3]),
+            members: vec![(Id(1), "127.0.0.1:7001".parse().unwrap())],
+            epoch: 4,
+        };
+        let learner = Learner::default();
+        learner.handle_decision(certificate(8, &[1, 2]));
+        assert!(failed(
+            server::<u64>()
+                .handle(Request::StateTransfer(LogIndex::new(0)), None)
+                .unwrap()
+        )
+        .is_some());
+        let server = server::<u64>()
+            .with_learner(learner)
+            .with_membership(log, Arc::new(Transferred(applied.clone())));
+        server
+            .handle(Request::Read(Round::new(Id(2)).next()), None)
+            .unwrap();
+        let transfer = |from| match server
+            .handle(Request::StateTransfer(LogIndex::new(from)), None)
+            .unwrap()
+        {
+            Response::StateTransfer(page) => page,
+            _ => panic!("not a state transfer"),
+        };
+
+        let first = transfer(0);
+        assert_eq!(first.applied, Some(applied.clone()));
+        assert_eq!(first.entries.len(), TRANSFER_PAGE);
+        assert_eq!(first.entries[0], (LogIndex::new(2), membership(&[1, 4])));
+        assert_eq!(first.end, LogIndex::new(decided));
+        assert_eq!(first.last_round_entered, Round::new(Id(2)).next());
+        assert_eq!(
+            first.certificate.map(|certificate| certificate.value),
+            Some(8)
+        );
+
+        let rest = transfer(2 + TRANSFER_PAGE as u64);
+        assert_eq!(rest.applied, None);
+        assert_eq!(rest.entries.len(), 10);
+        assert_eq!(rest.end, LogIndex::new(decided));
+        assert!(transfer(decided).entries.is_empty());
+
+        let bytes = to_bytes(&Response::<u64>::StateTransfer(rest));
+        assert!(matches!(
+            from_bytes::<Response<u64>>(&bytes).unwrap(),
+            Response::StateTransfer(page) if page.entries.len() == 10 && page.applied.is_none()
+        ));
+    }
+
+    #[test]
+    fn refuses_fences_no_higher_than_one_announced_before() {
+        let fence = |count| Fence { epoch: 0, count };
+        let member = server::<u64>().with_fences(Arc::new(FenceLedger::in_memory()));
+        let announce = |id, fence, from| member.handle(Request::Fence(id, fence), from).unwrap();
+        assert!(matches!(announce(Id(2), fence(2), None), Response::Ack));
+        assert!(matches!(
+            announce(Id(2), fence(1), Some(Id(2))),
+            Response::StaleStorage(seen) if seen == fence(2)
+        ));
+        assert!(failed(announce(Id(2), fence(3), Some(Id(3)))).is_some());
+        assert!(matches!(announce(Id(2), fence(3), None), Response::Ack));
+        assert!(failed(
+            server::<u64>()
+                .handle(Request::Fence(Id(2), fence(1)), None)
+                .unwrap()
+        )
+        .is_some());
+
+        let bytes = to_bytes(&Response::<u64>::StaleStorage(fence(3)));
+        assert!(matches!(
+            from_bytes::<Response<u64>>(&bytes).unwrap(),
+            Response::StaleStorage(seen) if seen == fence(3)
+        ));
+    }
+
+    #[test]
+    fn membership_changes_need_a_node_behind_the_server() {
+        let peers = TcpPeers::<u64>::new(vec![serve(server::<u64>())]);
+        let added =
+            block_on(Box::pin(peers.add_member(Id(2), "127.0.0.1:7002".parse().unwrap())).next());
+        assert!(matches!(added, Some(Err(_))));
+        let removed = block_on(Box::pin(peers.remove_member(Id(2))).next());
+        assert!(matches!(removed, Some(Err(_))));
+        let members = block_on(Box::pin(peers.topology()).next());
+        assert!(matches!(members, Some(Err(_))));
+    }
+
+    #[test]
+    fn a_recovering_server_refuses_clients_until_it_is_ready() {
+        let ready = Arc::new(AtomicBool::new(false));
+        let peers = TcpPeers::<u64>::new(vec![serve(
+            server::<u64>()
+                .with_learner(Learner::default())
+                .with_readiness(ready.clone()),
+        )]);
+        let health = block_on(Box::pin(peers.health()).next());
+        assert!(matches!(health, Some(Ok(Health::Recovering))));
+        let decided = block_on(Box::pin(peers.decision()).next());
+        assert!(matches!(decided, Some(Err(_))));
+        let certified = block_on(Box::pin(peers.certificate()).next());
+        assert!(matches!(certified, Some(Ok((Id(1), None)))));
+
+        ready.store(true, Ordering::Release);
+        let health = block_on(Box::pin(peers.health()).next());
+        assert!(matches!(health, Some(Ok(Health::Ready))));
+        let decided = block_on(Box::pin(peers.decision()).next());
+        assert!(matches!(decided, Some(Ok(None))));
+
+        for health in [
+            Health::Alive,
+            Health::Recovering,
+            Health::Ready,
+            Health::Leader,
+        ] {
+            let bytes = to_bytes(&Response::<u64>::Health(health));
+            assert!(matches!(
+                from_bytes::<Response<u64>>(&bytes).unwrap(),
+                Response::Health(decoded) if decoded == health
+            ));
+        }
+        assert!(Health::Recovering < Health::Ready);
+    }
+
+    /// Storage whose writes wait until the test lets each one through.
+    struct Gated(std::sync::mpsc::Receiver<()>);
+
+    impl Storage<u64> for Gated {
+        type Error = std::convert::Infallible;
+
+        fn persist(&mut self, _state: &Alpha<u64>) -> Result<(), Self::Error> {
+            let _ = self.0.recv();
+            Ok(())
+        }
+
+        fn load(&mut self) -> Result<Option<Alpha<u64>>, Self::Error> {
+            Ok(None)
+        }
+
+        fn persist_tick(&mut self, _tick: u64) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn load_tick(&mut self) -> Result<Option<u64>, Self::Error> {
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn others_hear_a_write_while_our_own_fsync_runs() {
+        let (release, gate) = std::sync::mpsc::channel();
+        let own = Server::new(Arc::new(Mutex::new(
+            Acceptor::new(Id(9), Gated(gate)).unwrap(),
+        )));
+        let own_addr = "127.0.0.1:1".parse().unwrap();
+        let other = serve(server::<u64>());
+        let peers =
+            TcpPeers::<u64>::new(vec![own_addr, other]).with_loopback(own.loopback(own_addr));
+
+        let mut written = Box::pin(peers.write(Value::new(7, Round::new(Id(1)))));
+        let first = block_on(written.next()).unwrap().unwrap();
+        assert_eq!(first.acceptor, Id(1));
+        release.send(()).unwrap();
+        let second = block_on(written.next()).unwrap().unwrap();
+        assert_eq!(second.acceptor, Id(9));
+        assert!(matches!(second.status, Status::Accepted));
+    }
+
+    #[test]
+    fn refuses_stale_writes_while_the_acceptor_is_busy() {
+        let server = server::<u64>();
+        let (old, new) = (Round::new(Id(2)), Round::new(Id(2)).next());
+        server.handle(Request::Read(new), None).unwrap();
+
+        let _busy = server.acceptor();
+        let response = server
+            .handle(Request::Write(Value::new(1, old)), None)
+            .unwrap();
+        let Response::Write(refused) = response else {
+            panic!("expected a write response");
+        };
+        assert!(matches!(refused.status, Status::Rejected(round) if round == new));
+    }
+
+    #[test]
+    fn members_share_one_encoded_request() {
+        let request: Arc<[u8]> = versioned(&Request::<u64>::Status).into();
+        let (a, b) = (
+            seal_request(&request, &None).unwrap(),
+            seal_request(&request, &None).unwrap(),
+        );
+        assert!(Arc::ptr_eq(&a.body, &request) && Arc::ptr_eq(&b.body, &request));
+        assert_eq!(a.parts().concat(), &*request);
+    }
+
+    #[cfg(feature = "auth")]
+    #[test]
+    fn sealing_wraps_the_shared_request() {
+        let keyring = Arc::new(Keyring::new(Id(1)).with_key(Id(2), "secret"));
+        let request: Arc<[u8]> = versioned(&Request::<u64>::Status).into();
+        let frame = seal_request(&request, &Some((keyring.clone(), Id(2)))).unwrap();
+        assert!(Arc::ptr_eq(&frame.body, &request));
+        let peer = Keyring::new(Id(2)).with_key(Id(1), "secret");
+        let sealed = frame.parts().concat();
+        assert_eq!(peer.open(&sealed).unwrap(), (Id(1), &request[..]));
+        assert!(peer.open(&sealed).is_err());
+    }
+
+    #[test]
+    fn writes_every_frame_in_one_vectored_write() {
+        #[derive(Default)]
+        struct Recording {
+            bytes: Vec<u8>,
+            writes: usize,
+        }
+
+        impl Write for Recording {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.write_vectored(&[IoSlice::new(buf)])
+            }
+
+            fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+                self.writes += 1;
+                bufs.iter()
+                    .for_each(|buf| self.bytes.extend_from_slice(buf));
+                Ok(bufs.iter().map(|buf| buf.len()).sum())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut recording = Recording::default();
+        write_frames(&mut recording, &[&[b"head", b"body"], &[], &[b"tail"]]).unwrap();
+        assert_eq!(recording.writes, 1);
+        let mut frames = &recording.bytes[..];
+        assert_eq!(read_frame(&mut frames, 8).unwrap(), b"headbody");
+        assert_eq!(read_frame(&mut frames, 8).unwrap(), b"");
+        assert_eq!(read_frame(&mut frames, 8).unwrap(), b"tail");
+        assert!(frames.is_empty());
+    }
+
+    #[test]
+    fn rejects_frames_over_the_limit() {
+        let (mut client, mut server) = stream_pair();
+        write_frame(&mut client, &[&[0; 6]]).unwrap();
+        let error = read_frame(&mut server, 5).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated_frames_do_not_allocate_the_claimed_length() {
+        let (mut client, mut server) = stream_pair();
+        client.write_all(&u32::MAX.to_be_bytes()).unwrap();
+        client.write_all(b"short").unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+        let error = read_frame(&mut server, usize::MAX).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn drops_peers_that_trickle_a_frame() {
+        let addr = serve(server::<u64>().with_frame_timeout(Duration::from_millis(200)));
+        let mut client = TcpStream::connect(addr).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let started = Instant::now();
+        for byte in 1u32.to_be_bytes() {
+            if client.write_all(&[byte]).is_err() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        assert_eq!(client.read(&mut [0; 16]).unwrap_or(0), 0);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn closes_connections_over_the_limit() {
+        let open = |addr| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.set_read_timeout(Some(IDLE_POLL * 3)).unwrap();
+            let closed = matches!(stream.read(&mut [0]), Ok(0));
+            (stream, closed)
+        };
+        let addr = serve(server::<u64>().with_max_connections(1));
+        let (held, closed) = open(addr);
+        assert!(!closed);
+        assert!(open(addr).1);
+
+        drop(held);
+        thread::sleep(IDLE_POLL * 2);
+        assert!(!open(addr).1);
+    }
+
+    #[test]
+    fn decodes_an_instance_request() {
+        let request = Request::<u64>::Instance(InstanceId(3), Box::new(Request::Status));
+        let decoded = from_bytes::<Request<u64>>(&to_bytes(&request)).unwrap();
+        assert!(matches!(
+            decoded,
+            Request::Instance(InstanceId(3), inner) if matches!(*inner, Request::Status)
+        ));
+    }
+
+    #[test]
+    fn rejects_nested_instance_requests() {
+        let nested = Request::<u64>::Instance(
+            InstanceId(1),
+            Box::new(Request::Instance(InstanceId(2), Box::new(Request::Status))),
+        );
+        assert!(from_bytes::<Request<u64>>(&to_bytes(&nested)).is_err());
+
+        let mut deep = Vec::new();
+        for instance in 0..1_000_000u64 {
+            6u8.encode(&mut deep);
+            instance.encode(&mut deep);
+        }
+        assert!(from_bytes::<Request<u64>>(&deep).is_err());
+    }
+
+    #[test]
+    fn sends_only_negotiated_requests() {
+        let plain =
+            TcpPeers::<u64>::new(vec![serve(server::<u64>())]).with_features(Features::SNAPSHOTS);
+        assert!(first(plain.snapshot()).is_err());
+        let negotiated = block_on(plain.handshake()).unwrap();
+        assert_eq!(negotiated.features, Features::empty());
+        assert!(first(plain.snapshot()).is_err());
+
+        let admin = serve(server::<u64>().with_admin(Arc::new(Snapshots)));
+        let peers = TcpPeers::<u64>::new(vec![admin]).with_features(Features::SNAPSHOTS);
+        assert!(first(peers.snapshot()).is_err());
+        block_on(peers.handshake()).unwrap();
+        assert_eq!(peers.negotiated(), Features::SNAPSHOTS);
+        first(peers.snapshot()).unwrap();
+    }
+
+    #[test]
+    fn negotiates_nothing_while_a_member_is_unreachable() {
+        let down = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .unwrap();
+        let admin = serve(server::<u64>().with_admin(Arc::new(Snapshots)));
+        let peers = TcpPeers::<u64>::new(vec![admin, down]).with_features(Features::SNAPSHOTS);
+        assert_eq!(
+            block_on(peers.handshake()).unwrap().features,
+            Features::empty()
+        );
+    }
+
+    #[test]
+    fn refuses_requests_for_unsupported_features() {
+        let response = server::<u64>().handle(Request::Snapshot, None).unwrap();
+        assert!(matches!(response, Response::Failed(_)));
+        let response = server::<u64>()
+            .with_admin(Arc::new(Snapshots))
+            .handle(Request::Snapshot, None)
+            .unwrap();
+        assert!(matches!(response, Response::Ack));
+    }
+
+    #[test]
+    fn backs_up_through_the_admin_only() {
+        let backup = |to: &str| Request::Backup(to.to_string());
+        let response = server::<u64>().handle(backup("backup"), None).unwrap();
+        assert!(matches!(response, Response::Failed(error) if error.contains("not supported")));
+        let server = server::<u64>().with_admin(Arc::new(Snapshots));
+        assert!(matches!(
+            server.handle(backup("backup"), None).unwrap(),
+            Response::Ack
+        ));
+        assert!(matches!(
+            server.handle(backup("/backup"), None).unwrap(),
+            Response::Failed(error) if error == "backups go elsewhere"
+        ));
+    }
+
+    #[test]
+    fn sets_the_log_filter_through_the_admin_only() {
+        let filter = |filter: &str| Request::LogFilter(filter.to_string());
+        let response = server::<u64>().handle(filter("debug"), None).unwrap();
+        assert!(matches!(response, Response::Failed(error) if error.contains("not supported")));
+        let server = server::<u64>().with_admin(Arc::new(Snapshots));
+        assert!(matches!(
+            server.handle(filter("debug"), None).unwrap(),
+            Response::Ack
+        ));
+        assert!(matches!(
+            server.handle(filter("loud"), None).unwrap(),
+            Response::Failed(error) if error.contains("loud")
+        ));
+    }
+
+    #[test]
+    fn reloads_credentials_through_the_admin_only() {
+        let reload = |server: Server<u64, _>| server.handle(Request::ReloadCredentials, None);
+        assert!(matches!(
+            reload(server()).unwrap(),
+            Response::Failed(error) if error.contains("not supported")
+        ));
+        assert!(matches!(
+            reload(server().with_admin(Arc::new(Snapshots))).unwrap(),
+            Response::Failed(error) if error == "no credentials here"
+        ));
+    }
+
+    #[test]
+    fn serves_clients_only_the_access_their_token_grants() {
+        let tokens = StaticTokens::new()
+            .with_token("reader", Access::Read)
+            .with_token("writer", Access::Write);
+        let server = server::<u64>().with_tokens(Arc::new(tokens));
+        let authorized =
+            |token: &str, request| Request::Authorized(token.to_string(), Box::new(request));
+        let refusal = |request| match server.handle(request, None).unwrap() {
+            Response::Failed(error) => error.strip_prefix("unauthorized: ").map(str::to_string),
+            _ => None,
+        };
+        assert_eq!(
+            refusal(Request::Decided).as_deref(),
+            Some("a token is required")
+        );
+        assert_eq!(
+            refusal(authorized("guess", Request::Decided)).as_deref(),
+            Some("the token is not valid")
+        );
+        assert_eq!(
+            refusal(authorized("reader", Request::Propose(7))).as_deref(),
+            Some("the token does not grant write access")
+        );
+        assert_eq!(refusal(authorized("writer", Request::Propose(7))), None);
+        assert!(matches!(
+            server.handle(authorized("reader", Request::Decided), None),
+            Ok(Response::Decided(None))
+        ));
+        // Members talk among themselves without tokens.
+        assert!(matches!(
+            server.handle(Request::Status, None),
+            Ok(Response::Status(_))
+        ));
+        assert!(server
+            .handle(
+                authorized("reader", authorized("reader", Request::Decided)),
+                None
+            )
+            .is_err());
+
+        let addr = serve(server);
+        assert!(first(TcpPeers::<u64>::new(vec![addr]).decision()).is_err());
+        let reader = TcpPeers::<u64>::new(vec![addr]).with_token("reader");
+        assert_eq!(first(reader.decision()).unwrap(), None);
+    }
+
+    #[test]
+    fn joins_only_with_a_token_issued_for_the_joiner() {
+        let addr: SocketAddr = "127.0.0.1:7004".parse().unwrap();
+        let tokens = Arc::new(JoinTokens::new(Arc::new(MockClock::new())));
+        let leader = server::<u64>().with_join_tokens(tokens.clone());
+        let issued = match leader.handle(Request::IssueJoinToken(Id(4), 60_000), None) {
+            Ok(Response::JoinToken(token)) => token,
+            _ => panic!("no token issued"),
+        };
+        let join = |token: &str, id| match leader
+            .handle(Request::Join(token.to_string(), id, addr), None)
+        {
+            Ok(Response::Failed(error)) => error,
+            _ => panic!("joined without a node behind the server"),
+        };
+        assert_eq!(join("guess", Id(4)), "unauthorized: the token is not valid");
+        assert_eq!(join(&issued, Id(5)), "unauthorized: the token is not valid");
+        // The join itself failed, so the token may be used again.
+        assert_eq!(
+            join(&issued, Id(4)),
+            "membership changes are not served here"
+        );
+        assert!(tokens.redeem(&issued, Id(4)).is_ok());
+
+        assert!(matches!(
+            server::<u64>().handle(Request::Join(issued, Id(4), addr), None),
+            Ok(Response::Failed(error)) if error == "joining is not served here"
+        ));
+    }
+
+    #[test]
+    fn throttles_each_client_over_its_quota() {
+        let clock = MockClock::new();
+        let limiter = RateLimiter::new("proposals:1".parse().unwrap(), Arc::new(clock.clone()));
+        let server = server::<u64>().with_rate_limiter(Arc::new(limiter));
+        let propose = |token: &str| {
+            let request = Request::Authorized(token.to_string(), Box::new(Request::Propose(7)));
+            match server.handle(request, None).unwrap() {
+                Response::Throttled(millis) => Some(millis),
+                _ => None,
+            }
+        };
+        assert_eq!(propose("alice"), None);
+        assert_eq!(propose("alice"), Some(1000));
+        assert_eq!(propose("bob"), None);
+        clock.advance(Duration::from_millis(400));
+        assert_eq!(propose("alice"), Some(600));
+        // Only proposals are counted.
+        assert!(matches!(
+            server.handle(Request::Status, None),
+            Ok(Response::Status(_))
+        ));
+
+        let peers = TcpPeers::<u64>::new(vec![serve(server)]).with_token("alice");
+        assert!(matches!(
+            first(peers.propose(7)),
+            Ok(Proposed::Throttled(retry_after)) if retry_after == Duration::from_millis(600)
+        ));
+    }
+
+    #[test]
+    fn compresses_large_frames_once_negotiated() {
+        let lz = Codec::Lz { above: 1 << 10 };
+        let addr = serve(
+            server::<BytesValue>()
+                .with_features(Features::COMPRESSION)
+                .with_codec(lz),
+        );
+        let peers = TcpPeers::<BytesValue>::new(vec![addr])
+            .with_features(Features::COMPRESSION)
+            .with_codec(lz);
+        let frame = versioned(&Request::<BytesValue>::Status).repeat(1_000);
+        assert_eq!(peers.compress(frame.clone()), frame);
+
+        block_on(peers.handshake()).unwrap();
+        assert!(peers.compress(frame.clone()).len() < frame.len() / 10);
+        let plain = peers.clone().with_codec(Codec::Plain);
+        assert_eq!(plain.compress(frame.clone()), frame);
+
+        let value = BytesValue::from(b"key=value;".repeat(10_000));
+        let round = Round::new(Id(7));
+        let promised = first(peers.read(round)).unwrap();
+        assert!(matches!(promised.status, Status::Accepted));
+        let written = first(peers.write(Value::new(value.clone(), round))).unwrap();
+        assert!(matches!(written.status, Status::Accepted));
+        let read = first(peers.read(round.next())).unwrap();
+        assert_eq!(read.state.accepted_value(), Some(&value));
+    }
+
+    fn failed<V>(response: Response<V>) -> Option<String> {
+        match response {
+            Response::Failed(message) => Some(message),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn refuses_messages_that_speak_for_another_node() {
+        let detector = Arc::new(OmegaDetector::new(
+            Id(1),
+            [Id(1), Id(2), Id(3)],
+            Duration::from_secs(60),
+        ));
+        let server = server::<u64>().with_detector(detector.clone());
+
+        let forged = server.handle(Request::Leave(Id(2)), Some(Id(3))).unwrap();
+        assert_eq!(failed(forged).unwrap(), "Id(3) may not speak for Id(2)");
+        let forged = server
+            .handle(Request::Heartbeat(Id(2)), Some(Id(3)))
+            .unwrap();
+        assert!(failed(forged).is_some());
+        let own = server
+            .handle(Request::Heartbeat(Id(3)), Some(Id(3)))
+            .unwrap();
+        assert!(matches!(own, Response::Ack));
+    }
+
+    #[cfg(feature = "auth")]
+    #[test]
+    fn only_operators_send_admin_requests() {
+        let keyring = Keyring::new(Id(1))
+            .with_key(Id(2), "secret")
+            .with_operator(Id(9), "secret");
+        let server = server::<u64>()
+            .with_admin(Arc::new(Snapshots))
+            .with_auth(Arc::new(keyring));
+
+        let member = server.handle(Request::Snapshot, Some(Id(2))).unwrap();
+        assert_eq!(failed(member).unwrap(), "Id(2) may not send admin requests");
+        let member = server
+            .handle(Request::Transfer(Id(2)), Some(Id(2)))
+            .unwrap();
+        assert!(failed(member).is_some());
+        let operator = server.handle(Request::Snapshot, Some(Id(9))).unwrap();
+        assert!(matches!(operator, Response::Ack));
+    }
+
+    #[cfg(feature = "auth")]
+    #[test]
+    fn identities_with_a_role_send_only_what_it_grants() {
+        let keyring = Keyring::new(Id(1))
+            .with_key(Id(2), "secret")
+            .with_role(Id(10), "secret", Permissions::READ)
+            .with_role(Id(11), "secret", Permissions::WATCH);
+        let server = server::<u64>().with_auth(Arc::new(keyring));
+        let refusal = |request, from| failed(server.handle(request, Some(Id(from))).unwrap());
+
+        assert_eq!(refusal(Request::Decided, 10), None);
+        assert_eq!(
+            refusal(Request::Propose(7), 10).as_deref(),
+            Some("Id(10) may not send propose requests")
+        );
+        assert_eq!(
+            refusal(Request::Status, 10).as_deref(),
+            Some("Id(10) may not send watch requests")
+        );
+        assert_eq!(
+            refusal(Request::Read(Round::new(Id(10))), 10).as_deref(),
+            Some("Id(10) may not send consensus requests")
+        );
+        assert_eq!(refusal(Request::Status, 11), None);
+        assert_eq!(refusal(Request::Health, 11), None);
+        assert!(refusal(Request::Decided, 11).is_some());
+        assert!(refusal(Request::Snapshot, 11).is_some());
+
+        // Members keep everything but admin.
+        assert_eq!(refusal(Request::Status, 2), None);
+        assert_eq!(refusal(Request::Read(Round::new(Id(2))), 2), None);
+    }
+
+    fn certificate(value: u64, acceptors: &[u64]) -> DecisionCertificate<u64> {
+        let round = Round::new(Id(1));
+        DecisionCertificate {
+            proposer: Id(1),
+            round,
+            value,
+            responses: acceptors
+                .iter()
+                .map(|acceptor| WriteResponse {
+                    acceptor: Id(*acceptor),
+                    round,
+                    status: Status::Accepted,
+                    last_round_entered: round,
+                    signature: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn adopts_only_certified_decisions() {
+        let members = QuorumSpec::Majority {
+            members: vec![Id(1), Id(2), Id(3)],
+        };
+        let learner = Learner::default();
+        let server = server::<u64>()
+            .with_learner(learner.clone())
+            .with_quorum(SharedQuorum::new(members));
+
+        let minority = server
+            .handle(Request::Decision(certificate(7, &[2])), Some(Id(2)))
+            .unwrap();
+        assert!(failed(minority)
+            .unwrap()
+            .starts_with("uncertified decision"));
+        let outsiders = server
+            .handle(Request::Decision(certificate(7, &[4, 5])), None)
+            .unwrap();
+        assert!(failed(outsiders).is_some());
+        assert_eq!(learner.decision(), None);
+
+        let quorum = server
+            .handle(Request::Decision(certificate(8, &[1, 3])), None)
+            .unwrap();
+        assert!(matches!(quorum, Response::Ack));
+        assert_eq!(learner.decision(), Some(8));
+    }
+
+    #[test]
+    fn tells_observers_what_an_instance_decided() {
+        let instance =
+            |instance, request| Request::Instance(InstanceId(instance), Box::new(request));
+        let server = server::<u64>()
+            .with_instances(Arc::new(InstanceAcceptors::new(
+                Id(1),
+                InstanceStore::in_memory(),
+            )))
+            .with_quorum(SharedQuorum::new(QuorumSpec::Majority {
+                members: vec![Id(1), Id(2), Id(3)],
+            }));
+        let decision = Request::Decision(certificate(8, &[1, 3]));
+        assert!(matches!(
+            server.handle(instance(2, decision), None).unwrap(),
+            Response::Ack
+        ));
+        assert!(matches!(
+            server.handle(instance(2, Request::Decided), None).unwrap(),
+            Response::Decided(Some(8))
+        ));
+        assert!(matches!(
+            server.handle(instance(3, Request::Decided), None).unwrap(),
+            Response::Decided(None)
+        ));
+    }
+
+    #[test]
+    fn refuses_decisions_without_a_quorum_to_check() {
+        let learner = Learner::default();
+        let server = server::<u64>().with_learner(learner.clone());
+        let response = server
+            .handle(Request::Decision(certificate(7, &[1, 2, 3])), None)
+            .unwrap();
+        assert!(failed(response).is_some());
+        assert_eq!(learner.decision(), None);
+    }
+
+    #[cfg(feature = "auth")]
+    #[test]
+    fn refuses_unsigned_decisions_under_auth() {
+        let keyring = Keyring::new(Id(1))
+            .with_key(Id(1), "secret")
+            .with_key(Id(2), "secret");
+        let learner = Learner::default();
+        let server = server::<u64>()
+            .with_learner(learner.clone())
+            .with_quorum(SharedQuorum::new(QuorumSpec::Majority {
+                members: vec![Id(1), Id(2)],
+            }))
+            .with_auth(Arc::new(keyring));
+
+        let mut decision = certificate(7, &[1, 2]);
+        let unsigned = server
+            .handle(Request::Decision(decision.clone()), Some(Id(2)))
+            .unwrap();
+        assert!(failed(unsigned).is_some());
+        assert_eq!(learner.decision(), None);
+
+        for response in &mut decision.responses {
+            let acceptor = Keyring::new(response.acceptor).with_key(Id(1), "secret");
+            *response = certificate::sign(*response, &7u64, &acceptor, Id(1)).unwrap();
+        }
+        let signed = server
+            .handle(Request::Decision(decision), Some(Id(2)))
+            .unwrap();
+        assert!(matches!(signed, Response::Ack));
+        assert_eq!(learner.decision(), Some(7));
+    }
+
+    #[test]
+    fn reconfiguring_reaches_every_clone() {
+        let (a, b) = (serve(server::<u64>()), serve(server::<u64>()));
+        let peers = TcpPeers::<u64>::new(vec![a]);
+        let heartbeats = peers.clone();
+        assert_eq!(block_on(heartbeats.status().count()), 1);
+
+        peers.reconfigure([(Id(1), a), (Id(2), b)]);
+        assert_eq!(block_on(heartbeats.status().count()), 2);
+        assert_eq!(heartbeats.majority(), 2);
+
+        peers.reconfigure([(Id(2), b)]);
+        assert_eq!(block_on(heartbeats.status().count()), 1);
+        assert_eq!(heartbeats.max_failures(), 0);
+    }
+
+    #[test]
+    fn limited_peers_still_hear_every_member() {
+        let members = (0..3).map(|_| serve(server::<u64>())).collect();
+        let peers = TcpPeers::<u64>::new(members).with_concurrency(1);
+        assert_eq!(block_on(peers.status().count()), 3);
+    }
+
+    #[test]
+    fn fastest_members_hear_a_request_first() {
+        let addrs: Vec<SocketAddr> = (1..=4)
+            .map(|port| SocketAddr::from(([127, 0, 0, 1], port)))
+            .collect();
+        let response_times = Arc::new(ResponseTimes::new(Default::default()));
+        for (addr, millis) in addrs.iter().zip([40, 10, 30]) {
+            response_times.record(*addr, Duration::from_millis(millis));
+        }
+        let peers = TcpPeers::<u64>::new(addrs.clone()).with_response_times(response_times);
+        let mut targets = addrs.clone();
+        peers.prioritize(&mut targets, |addr| *addr, usize::MAX);
+        // Nobody has measured the last member yet, so it goes first.
+        assert_eq!(targets, [addrs[3], addrs[1], addrs[2], addrs[0]]);
+    }
+
+    #[test]
+    fn fastest_quorum_counts_our_own_acceptor() {
+        let (near, far, ours) = (
+            serve(server::<u64>()),
+            serve(server::<u64>()),
+            server::<u64>(),
+        );
+        let (here, elsewhere) = (serve(ours.clone()), serve(server::<u64>()));
+        let response_times = Arc::new(ResponseTimes::new(Default::default()));
+        response_times.record(near, Duration::from_millis(1));
+        response_times.record(far, Duration::from_millis(50));
+        response_times.record(elsewhere, Duration::from_millis(50));
+        let peers = TcpPeers::<u64>::new(vec![far, here, near, elsewhere])
+            .with_response_times(response_times)
+            .with_loopback(ours.loopback(here))
+            .with_fastest_quorum();
+        let mut targets = vec![far, here, near, elsewhere];
+        peers.prioritize(&mut targets, |addr| *addr, peers.majority());
+        assert_eq!(&targets[..3], [near, far, here]);
+        assert_eq!(block_on(peers.status().count()), 4);
+    }
+
+    #[test]
+    fn targeted_writes_reach_only_the_named_members() {
+        let (a, b) = (serve(server::<u64>()), serve(server::<u64>()));
+        let peers = TcpPeers::<u64>::new(vec![a, b]);
+        peers.reconfigure([(Id(1), a), (Id(2), b)]);
+        let value = Value::new(7, Round::new(Id(0)));
+        let written: Vec<_> = block_on(peers.write_to(&[Id(2)], value).collect());
+        assert_eq!(written.len(), 1);
+    }
+}
@@ -0,0 +1,29 @@
+use futures::executor::block_on;
+use paxos_classic::alpha::Id;
+use paxos_classic::local::LocalCluster;
+use paxos_classic::proposer::{FailureDetector, Proposer};
+use std::future::{self, Future};
+
+struct Leader(Id);
+
+impl FailureDetector for Leader {
+    fn leader(&self) -> Id {
+        self.0
+    }
+
+    fn changed(&self) -> impl Future<Output = ()> {
+        future::pending()
+    }
+}
+
+#[test]
+fn three_nodes_reach_consensus() {
+    let cluster = LocalCluster::new(3);
+    let id = Id::default();
+    let mut proposer = Proposer::builder(id, cluster.peers(), Leader(id)).build();
+
+    assert_eq!(block_on(proposer.propose("value")).unwrap(), "value");
+    for learner in cluster.learners() {
+        assert_eq!(block_on(learner.await_decision()), "value");
+    }
+}
@@ -0,0 +1,53 @@
+use paxos_classic::sim::{Faults, Simulation};
+
+struct Cases(u64);
+
+impl Cases {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn probability(&mut self, max: f64) -> f64 {
+        (self.next() % 1000) as f64 / 1000.0 * max
+    }
+
+    fn faults(&mut self) -> Faults {
+        Faults {
+            request_loss: self.probability(0.3),
+            response_loss: self.probability(0.3),
+            duplication: self.probability(0.5),
+            max_delay: (self.next() % 8) as usize,
+        }
+    }
+}
+
+#[test]
+fn only_one_value_is_chosen() {
+    let mut cases = Cases(0x5eed);
+    for seed in 0..200 {
+        let acceptors = 3 + 2 * (cases.next() % 2) as usize;
+        let faults = cases.faults();
+        let simulation = Simulation::new(acceptors, seed, faults);
+
+        let proposals: Vec<u64> = (0..acceptors as u64).map(|i| 100 + i).collect();
+        let decided = simulation.propose_all(proposals.clone());
+        let chosen = decided[0];
+        assert!(proposals.contains(&chosen), "seed {seed}: {decided:?}");
+        assert!(
+            decided.iter().all(|value| *value == chosen),
+            "seed {seed}, {faults:?}: proposers disagree on {decided:?}"
+        );
+        for learned in simulation.learned().into_iter().flatten() {
+            assert_eq!(learned, chosen, "seed {seed}, {faults:?}");
+        }
+
+        let later = simulation.propose_all((0..acceptors as u64).map(|i| 200 + i).collect());
+        assert!(
+            later.iter().all(|value| *value == chosen),
+            "seed {seed}, {faults:?}: chosen value {chosen} forgotten, later rounds decided {later:?}"
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use paxos_classic::sim::{Faults, Simulation};
+
+const SEEDS: u64 = 200;
+
+fn assert_safe(simulation: &Simulation<u64>, proposed: &[u64], decided: &[u64]) {
+    let chosen = decided[0];
+    assert!(decided.iter().all(|value| *value == chosen), "{decided:?}");
+    assert!(proposed.contains(&chosen));
+    assert!(simulation
+        .learned()
+        .iter()
+        .flatten()
+        .all(|value| *value == chosen));
+}
+
+#[test]
+fn single_proposer_decides_its_value() {
+    for seed in 0..SEEDS {
+        let simulation = Simulation::new(3, seed, Faults::default());
+        assert_eq!(simulation.propose_all(vec![7]), vec![7]);
+    }
+}
+
+#[test]
+fn agreement_under_reordering() {
+    let faults = Faults {
+        max_delay: 4,
+        ..Faults::default()
+    };
+    for seed in 0..SEEDS {
+        let simulation = Simulation::new(5, seed, faults);
+        let proposed = vec![1, 2, 3];
+        let decided = simulation.propose_all(proposed.clone());
+        assert_safe(&simulation, &proposed, &decided);
+    }
+}
+
+#[test]
+fn agreement_under_loss_and_duplication() {
+    let faults = Faults {
+        request_loss: 0.2,
+        response_loss: 0.2,
+        duplication: 0.3,
+        max_delay: 4,
+    };
+    for seed in 0..SEEDS {
+        let simulation = Simulation::new(5, seed, faults);
+        let proposed = vec![1, 2, 3];
+        let decided = simulation.propose_all(proposed.clone());
+        assert_safe(&simulation, &proposed, &decided);
+    }
+}
+
+#[test]
+fn only_the_majority_side_of_a_partition_decides() {
+    let simulation = Simulation::new(5, 0, Faults::default());
+    simulation.partition(&[vec![0, 1], vec![2, 3, 4]]);
+    assert!(simulation.propose(0, 1, 3).is_err());
+    assert_eq!(simulation.propose(4, 2, 3).unwrap(), 2);
+
+    simulation.heal();
+    assert_eq!(simulation.propose(0, 1, 3).unwrap(), 2);
+    let nodes = simulation.nodes();
+    assert!(nodes.iter().all(|node| node.side.is_none()));
+    assert!(nodes.iter().all(|node| node.learned == Some(2)));
+}
+
+#[test]
+fn restarted_nodes_keep_what_they_accepted() {
+    let simulation = Simulation::new(3, 0, Faults::default());
+    simulation.crash(1);
+    simulation.crash(2);
+    assert!(simulation.propose(0, 1, 3).is_err());
+    assert!(simulation.propose(1, 1, 3).is_err());
+
+    simulation.restart(1);
+    assert_eq!(simulation.propose(0, 5, 3).unwrap(), 5);
+    simulation.crash(1);
+    simulation.restart(1);
+    let restarted = &simulation.nodes()[1];
+    assert!(restarted.up);
+    assert_eq!(restarted.learned, None);
+    assert_eq!(
+        restarted.accepted.as_ref().map(|(_, value)| *value),
+        Some(5)
+    );
+
+    simulation.crash(0);
+    simulation.restart(2);
+    assert_eq!(simulation.propose(2, 9, 3).unwrap(), 5);
+}